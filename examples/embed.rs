@@ -0,0 +1,40 @@
+//! Timing Lines from Your Own Code with the timeln Library
+//!
+//! Feeds an in-memory list of lines through a `Pipeline`, keeping only the lines that match a
+//! pattern, and collects their snapshots instead of printing a summary.
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use timeln::{IterReadData, Pipeline, SecondsFormat, SimpleAnnotator, TimelnError};
+
+fn main() -> Result<(), TimelnError> {
+    let annotator = SimpleAnnotator {
+        color: false,
+        time_format: Arc::new(Box::new(SecondsFormat)),
+        widths: None,
+    };
+    let mut pipeline = Pipeline::new(Box::new(annotator)).regex(r"\.\.\. ok")?;
+
+    let lines = (1..=5).map(|i| {
+        thread::sleep(Duration::from_millis(20 * i));
+        if i % 2 == 0 {
+            format!("test case_{} ... FAILED", i)
+        } else {
+            format!("test case_{} ... ok", i)
+        }
+    });
+
+    let mut annotated = Vec::new();
+    let snapshots = pipeline.run(&mut IterReadData::new(lines), &mut annotated)?;
+
+    print!("{}", String::from_utf8_lossy(&annotated));
+    for (i, snapshot) in snapshots.iter().enumerate() {
+        println!(
+            "passing test {}: {:.3} s after the previous one",
+            i + 1,
+            snapshot.delta.as_secs_f64()
+        );
+    }
+    Ok(())
+}
@@ -2,7 +2,6 @@
 use std::thread;
 use std::time::Duration;
 
-
 fn main() {
     println!("Printing patterns...");
     for i in 0..=10 {
@@ -10,4 +9,4 @@ fn main() {
         println!("Iteration {}", i);
     }
     println!("Finished.");
-}
\ No newline at end of file
+}
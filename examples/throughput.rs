@@ -0,0 +1,43 @@
+//! Measures How Many Lines per Second timeln Can Process
+//!
+//! Pipes `yes | head -n N` style input into a timeln binary and reports the throughput.
+//! Build timeln in release mode first, then run:
+//!
+//!     cargo build --release
+//!     cargo run --release --example throughput -- 5000000 target/release/timeln
+use std::io::{BufWriter, Write};
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let lines: usize = args
+        .next()
+        .map(|n| n.parse().expect("the number of lines must be an integer"))
+        .unwrap_or(5_000_000);
+    let binary = args
+        .next()
+        .unwrap_or_else(|| "target/release/timeln".to_string());
+
+    let start = Instant::now();
+    let mut child = Command::new(&binary)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap_or_else(|err| panic!("could not start {}: {}", binary, err));
+    {
+        let mut stdin = BufWriter::new(child.stdin.take().unwrap());
+        for _ in 0..lines {
+            stdin.write_all(b"y\n").unwrap();
+        }
+    }
+    child.wait().unwrap();
+    let secs = start.elapsed().as_secs_f64();
+
+    println!(
+        "{} lines in {:.2} s: {:.0} lines/sec",
+        lines,
+        secs,
+        lines as f64 / secs
+    );
+}
@@ -0,0 +1,164 @@
+//! This module counts the lines and matches of a run per bucket of time, such as per minute, to
+//! show when a long run was busy and when it was idle.
+//!
+//! `Activity` keeps one bucket per `width` of elapsed time, indexed by `elapsed / width`. When a
+//! line arrives past the last of its `max_buckets`, pairs of neighbouring buckets are merged and
+//! the width doubles, as many times as needed, so that memory stays bounded however long the run
+//! lasts, at the cost of resolution.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use timeln::activity::Activity;
+//!
+//! let mut activity = Activity::new(Duration::from_secs(60), 4);
+//! activity.push(Duration::from_secs(30), false);
+//! activity.push(Duration::from_secs(150), true);
+//! assert_eq!(activity.lines(), [1, 0, 1]);
+//! // A line in the sixth minute doubles the width to two minutes.
+//! activity.push(Duration::from_secs(330), false);
+//! assert_eq!(activity.width(), Duration::from_secs(120));
+//! assert_eq!(activity.lines(), [1, 1, 1]);
+//! assert_eq!(activity.matches(), [0, 1, 0]);
+//! ```
+use std::time::Duration;
+
+/// Width of the buckets of the activity, unless given.
+pub const DEFAULT_ACTIVITY_BUCKET: Duration = Duration::from_secs(60);
+
+/// Largest number of buckets of the activity before their width doubles.
+pub const MAX_ACTIVITY_BUCKETS: usize = 60;
+
+/// Glyphs of the bars of the activity, from the least busy bucket to the busiest.
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// ASCII glyphs of the bars of the activity, from the least busy bucket to the busiest.
+const ASCII_BARS: [char; 8] = ['.', ':', '-', '=', '+', '*', '%', '#'];
+
+/// The lines and matches of a run counted per bucket of elapsed time.
+#[derive(Debug, Clone)]
+pub struct Activity {
+    width: Duration,
+    max_buckets: usize,
+    /// Lines and matches of each bucket.
+    buckets: Vec<(u64, u64)>,
+}
+
+impl Activity {
+    /// Counts per bucket of `width` (at least 1 ms), doubling it to keep at most `max_buckets`
+    /// (at least 2) buckets.
+    pub fn new(width: Duration, max_buckets: usize) -> Self {
+        Self {
+            width: width.max(Duration::from_millis(1)),
+            max_buckets: max_buckets.max(2),
+            buckets: Vec::new(),
+        }
+    }
+
+    /// Width of the buckets, which doubles as the run grows.
+    pub fn width(&self) -> Duration {
+        self.width
+    }
+
+    /// Counts a line that arrived `elapsed` after the start of the run, and whether it matched.
+    pub fn push(&mut self, elapsed: Duration, matched: bool) {
+        let index = self.extend_to(elapsed);
+        let (lines, matches) = &mut self.buckets[index];
+        *lines += 1;
+        *matches += u64::from(matched);
+    }
+
+    /// Adds the empty buckets up to the one of `elapsed`, such as the end of the run, doubling the
+    /// width as needed. Returns the index of the bucket of `elapsed`.
+    pub fn extend_to(&mut self, elapsed: Duration) -> usize {
+        let mut index = self.index(elapsed);
+        while index >= self.max_buckets {
+            self.buckets = self
+                .buckets
+                .chunks(2)
+                .map(|pair| pair.iter().fold((0, 0), |a, b| (a.0 + b.0, a.1 + b.1)))
+                .collect();
+            self.width *= 2;
+            index = self.index(elapsed);
+        }
+        if self.buckets.len() <= index {
+            self.buckets.resize(index + 1, (0, 0));
+        }
+        index
+    }
+
+    fn index(&self, elapsed: Duration) -> usize {
+        (elapsed.as_nanos() / self.width.as_nanos()) as usize
+    }
+
+    /// Number of lines in each bucket.
+    pub fn lines(&self) -> Vec<u64> {
+        self.buckets.iter().map(|(lines, _)| *lines).collect()
+    }
+
+    /// Number of matches in each bucket.
+    pub fn matches(&self) -> Vec<u64> {
+        self.buckets.iter().map(|(_, matches)| *matches).collect()
+    }
+}
+
+/// Draws `counts` as a row of bars scaled to the largest count, an empty bucket as a space, in
+/// ASCII if `ascii`.
+pub fn bars(counts: &[u64], ascii: bool) -> String {
+    let glyphs = if ascii { &ASCII_BARS } else { &BARS };
+    let max = counts.iter().copied().max().unwrap_or(0);
+    counts
+        .iter()
+        .map(|count| match count {
+            0 => ' ',
+            count => glyphs[((count * glyphs.len() as u64 - 1) / max) as usize],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_assignment() {
+        let mut activity = Activity::new(Duration::from_secs(60), MAX_ACTIVITY_BUCKETS);
+        assert!(activity.lines().is_empty());
+        for (secs, matched) in [(0, false), (59, true), (60, false), (185, false)] {
+            activity.push(Duration::from_secs(secs), matched);
+        }
+        assert_eq!(activity.lines(), [2, 1, 0, 1]);
+        assert_eq!(activity.matches(), [1, 0, 0, 0]);
+        assert_eq!(activity.extend_to(Duration::from_secs(300)), 5);
+        assert_eq!(activity.lines(), [2, 1, 0, 1, 0, 0]);
+        assert_eq!(activity.width(), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_width_doubles() {
+        let mut activity = Activity::new(Duration::from_secs(1), 4);
+        for secs in 0..4 {
+            activity.push(Duration::from_secs(secs), secs == 3);
+        }
+        assert_eq!(activity.lines(), [1, 1, 1, 1]);
+        // Past the fourth bucket, pairs merge into buckets of 2 s.
+        activity.push(Duration::from_millis(4500), false);
+        assert_eq!(activity.width(), Duration::from_secs(2));
+        assert_eq!(activity.lines(), [2, 2, 1]);
+        assert_eq!(activity.matches(), [0, 1, 0]);
+        // Far past them, the width doubles as many times as needed.
+        activity.push(Duration::from_secs(30), false);
+        assert_eq!(activity.width(), Duration::from_secs(8));
+        assert_eq!(activity.lines(), [5, 0, 0, 1]);
+        assert_eq!(activity.lines().iter().sum::<u64>(), 6);
+    }
+
+    #[test]
+    fn test_bars() {
+        assert_eq!(bars(&[1, 0, 8, 4], false), "▁ █▄");
+        assert_eq!(bars(&[1, 0, 8, 4], true), ". #=");
+        assert_eq!(bars(&[0, 0], false), "  ");
+        assert_eq!(bars(&[], false), "");
+    }
+}
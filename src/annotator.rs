@@ -1,7 +1,15 @@
-use crate::formatter::TimeFormat;
-use std::{sync::Arc, time::Duration};
+use crate::error::TimelnError;
+use crate::formatter::{RateFormat, TimeFormat};
+use crate::stats::window_label;
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use colored::Colorize;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, Utc};
+use colored::{Color, Colorize};
 
 /// The `TimelnAnnotation` trait provides an abstraction over line annotation.
 /// Implementations of `TimelnAnnotation` provide methods to format lines with timestamps and time deltas.
@@ -17,26 +25,158 @@ pub trait TimelnAnnotation {
     /// # Returns
     ///
     /// * `String` - A string with the line text and its annotated information.
-    fn format_line(&self, line: &String, now: &Duration, delta: &Duration) -> String;
+    fn format_line(&self, line: &str, now: &Duration, delta: &Duration) -> String;
+
+    /// Like `format_line`, with everything known about the timing of the line in `context`. Annotators that do not
+    /// show more than the time and delta need not implement it.
+    fn format_line_with(&self, line: &str, context: &AnnotationContext) -> String {
+        self.format_line(line, &context.now, &context.delta)
+    }
+
+    /// Like `format_line_with`, without the line: only the annotation. By default, that of an empty line without the
+    /// space that separates it from the line.
+    fn format_annotation(&self, context: &AnnotationContext) -> String {
+        let line = self.format_line_with("", context);
+        match line.strip_suffix(' ') {
+            Some(annotation) => annotation.to_string(),
+            None => line,
+        }
+    }
+}
+
+/// The timing of a line, as given to `TimelnAnnotation::format_line_with`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AnnotationContext {
+    /// The time since the start.
+    pub now: Duration,
+    /// The time since the previous line.
+    pub delta: Duration,
+    /// The mean of the deltas up to this line, if it is shown and there are deltas to average.
+    pub mean: Option<Duration>,
+    /// The moving average of the deltas up to this line, if it is computed.
+    pub ewma: Option<Duration>,
+    /// The length of the sliding window and the lines per second that arrived in it, if it is shown.
+    pub rate: Option<(Duration, f64)>,
+    /// The color of the delta on the heat scale of the deltas, if they are colored by it.
+    pub delta_color: Option<Color>,
+    /// Whether the delta and its averages are shown as the rates they stand for, in Hz.
+    pub as_rate: bool,
+}
+
+/// A column of the annotations of `SimpleAnnotator` and `UnicodeAnnotator`.
+#[derive(Debug, Clone, Copy)]
+enum Column {
+    Time,
+    Delta,
+    Mean,
+    Ewma,
+    Rate,
+}
+
+/// The widest text seen so far in each column of the annotations, to which the later annotations are padded, so
+/// that the columns line up and only ever grow: an annotation wider than those before it widens its column once, for
+/// itself and every annotation after it.
+#[derive(Debug, Default)]
+pub struct ColumnWidths {
+    widths: [AtomicUsize; 5],
+}
+
+impl ColumnWidths {
+    /// Right-aligns `text` to the width of `column`, first widening the column to `text` if it is wider.
+    fn pad(&self, column: Column, text: String) -> String {
+        let len = text.chars().count();
+        let width = self.widths[column as usize]
+            .fetch_max(len, Ordering::Relaxed)
+            .max(len);
+        format!("{:>width$}", text, width = width)
+    }
+}
+
+/// Formats the times of `context` as `time_label: 5.50 s, delta_label: 1.50 s`, followed by `avg: 1.10 s` if the
+/// mean of the deltas is known, by `~1.20 s` if the moving average of the deltas is and by `rate_10s: 8.0/s` if the rate of lines over a sliding window is. The
+/// delta is in its heat color, if it has one, and the delta and averages are formatted as rates if `as_rate`. Each
+/// time is padded to the width of its column, if `widths` are kept.
+fn annotation(
+    time_format: &dyn TimeFormat,
+    context: &AnnotationContext,
+    widths: Option<&ColumnWidths>,
+    time_label: &str,
+    delta_label: &str,
+) -> String {
+    let pad = |column, text| match widths {
+        Some(widths) => widths.pad(column, text),
+        None => text,
+    };
+    let delta_format = match context.as_rate {
+        true => &RateFormat,
+        false => time_format,
+    };
+    let delta = pad(Column::Delta, delta_format.format_duration(&context.delta));
+    let delta = match context.delta_color {
+        Some(color) => delta.color(color).to_string(),
+        None => delta,
+    };
+    let mut annotation = format!(
+        "{}: {}, {}: {}",
+        time_label,
+        pad(Column::Time, time_format.format_duration(&context.now)),
+        delta_label,
+        delta
+    );
+    if let Some(mean) = &context.mean {
+        let mean = pad(Column::Mean, delta_format.format_duration(mean));
+        annotation.push_str(&format!(", avg: {}", mean));
+    }
+    if let Some(ewma) = &context.ewma {
+        let ewma = pad(
+            Column::Ewma,
+            format!("~{}", delta_format.format_duration(ewma)),
+        );
+        annotation.push_str(&format!(", {}", ewma));
+    }
+    if let Some((window, rate)) = &context.rate {
+        let rate = pad(Column::Rate, format!("{:.1}/s", rate));
+        annotation.push_str(&format!(", rate_{}: {}", window_label(window), rate));
+    }
+    annotation
 }
 
 /// The `SimpleAnnotator` struct is an implementation of the `TimelnAnnotation` trait that annotates lines with simple time and delta information.
 pub struct SimpleAnnotator {
     pub color: bool,
     pub time_format: Arc<Box<dyn TimeFormat>>,
+    /// The widths of the columns the times are aligned to, if they are aligned.
+    pub widths: Option<ColumnWidths>,
 }
 
 impl TimelnAnnotation for SimpleAnnotator {
     /// Annotates the given line with the current time and delta in a simple format.
-    fn format_line(&self, line: &String, now: &Duration, delta: &Duration) -> String {
-        let time_str = self.time_format.format_duration(now);
-        let delta_str = self.time_format.format_duration(delta);
+    fn format_line(&self, line: &str, now: &Duration, delta: &Duration) -> String {
+        let context = AnnotationContext {
+            now: *now,
+            delta: *delta,
+            ..AnnotationContext::default()
+        };
+        self.format_line_with(line, &context)
+    }
 
+    /// Annotates the given line with the current time, delta, moving average of the deltas and rate of lines, if
+    /// known.
+    fn format_line_with(&self, line: &str, context: &AnnotationContext) -> String {
+        let annotation = format!(
+            "[{}]",
+            annotation(
+                &**self.time_format,
+                context,
+                self.widths.as_ref(),
+                "time",
+                "delta"
+            )
+        );
         if self.color {
-            let color_annotation = format!("[time: {}, delta: {}]", time_str, delta_str);
-            format!("{} {}", color_annotation.green(), line)
+            format!("{} {}", annotation.green(), line)
         } else {
-            format!("[time: {}, delta: {}] {}", time_str, delta_str, line)
+            format!("{} {}", annotation, line)
         }
     }
 }
@@ -45,19 +185,119 @@ impl TimelnAnnotation for SimpleAnnotator {
 pub struct UnicodeAnnotator {
     pub color: bool,
     pub time_format: Arc<Box<dyn TimeFormat>>,
+    /// The widths of the columns the times are aligned to, if they are aligned.
+    pub widths: Option<ColumnWidths>,
 }
 
 impl TimelnAnnotation for UnicodeAnnotator {
     /// Annotates the given line with the current time and delta in a Unicode format.
-    fn format_line(&self, line: &String, now: &Duration, delta: &Duration) -> String {
-        let time_str = self.time_format.format_duration(now);
-        let delta_str = self.time_format.format_duration(delta);
+    fn format_line(&self, line: &str, now: &Duration, delta: &Duration) -> String {
+        let context = AnnotationContext {
+            now: *now,
+            delta: *delta,
+            ..AnnotationContext::default()
+        };
+        self.format_line_with(line, &context)
+    }
 
+    /// Annotates the given line with the current time, delta, moving average of the deltas and rate of lines, if
+    /// known, in a Unicode format.
+    fn format_line_with(&self, line: &str, context: &AnnotationContext) -> String {
+        let annotation = format!(
+            "[{}]",
+            annotation(&**self.time_format, context, self.widths.as_ref(), "Τ", "Δ")
+        );
         if self.color {
-            let color_annotation = format!("[Τ: {}, Δ: {}]", time_str, delta_str);
-            format!("{} {}", color_annotation.green(), line)
+            format!("{} {}", annotation.green(), line)
         } else {
-            format!("[Τ: {}, Δ: {}] {}", time_str, delta_str, line)
+            format!("{} {}", annotation, line)
+        }
+    }
+}
+
+/// The timestamps of `TsAnnotator`, as in the modes of moreutils `ts`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TsMode {
+    /// The local wall-clock time of each line, like `ts`.
+    #[default]
+    Absolute,
+    /// The time since the previous line, like `ts -i`.
+    Incremental,
+    /// The time since the start, like `ts -s`.
+    SinceStart,
+}
+
+impl TsMode {
+    /// The strftime format of the timestamps when none is given, the same as that of `ts`.
+    pub fn default_format(self) -> &'static str {
+        match self {
+            TsMode::Absolute => "%b %d %H:%M:%S",
+            TsMode::Incremental | TsMode::SinceStart => "%H:%M:%S",
+        }
+    }
+}
+
+/// The `TsAnnotator` struct is an implementation of the `TimelnAnnotation` trait that prefixes lines with a
+/// timestamp and a space, without brackets, like moreutils `ts`, so that timeln can replace it in scripts.
+///
+/// The timestamps are formatted with a strftime format, which also accepts the `%.S`, `%.s` and `%.T` of `ts`: the
+/// seconds, the seconds since the epoch and the time with microseconds. The durations of `TsMode::Incremental` and
+/// `TsMode::SinceStart` are formatted as times of day in UTC, so that `%H:%M:%S` reads as hours, minutes and seconds.
+pub struct TsAnnotator {
+    pub color: bool,
+    mode: TsMode,
+    format: String,
+    /// Wall-clock time of the start, from which `TsMode::Absolute` stamps the lines.
+    started_at: SystemTime,
+}
+
+impl TsAnnotator {
+    /// Creates an annotator stamping lines in `mode` with the strftime `format`, or the default format of the mode.
+    /// Fails if the format is invalid.
+    pub fn new(
+        mode: TsMode,
+        format: Option<&str>,
+        started_at: SystemTime,
+        color: bool,
+    ) -> Result<Self, TimelnError> {
+        let format = format.unwrap_or(mode.default_format());
+        let expanded = format
+            .replace("%.S", "%S%.6f")
+            .replace("%.s", "%s%.6f")
+            .replace("%.T", "%T%.6f");
+        if StrftimeItems::new(&expanded).any(|item| item == Item::Error) {
+            return Err(TimelnError::Usage(format!(
+                "invalid ts format '{}', expected e.g. %b %d %H:%M:%S",
+                format
+            )));
+        }
+        Ok(Self {
+            color,
+            mode,
+            format: expanded,
+            started_at,
+        })
+    }
+}
+
+impl TimelnAnnotation for TsAnnotator {
+    /// Prefixes the given line with its timestamp in the mode of the annotator.
+    fn format_line(&self, line: &str, now: &Duration, delta: &Duration) -> String {
+        let stamp = match self.mode {
+            TsMode::Absolute => DateTime::<Local>::from(self.started_at + *now)
+                .format(&self.format)
+                .to_string(),
+            TsMode::Incremental => DateTime::<Utc>::from(UNIX_EPOCH + *delta)
+                .format(&self.format)
+                .to_string(),
+            TsMode::SinceStart => DateTime::<Utc>::from(UNIX_EPOCH + *now)
+                .format(&self.format)
+                .to_string(),
+        };
+        if self.color {
+            format!("{} {}", stamp.green(), line)
+        } else {
+            format!("{} {}", stamp, line)
         }
     }
 }
@@ -73,6 +313,7 @@ mod tests {
         let annotator = SimpleAnnotator {
             color: false,
             time_format: Arc::new(Box::new(SecondsFormat)),
+            widths: None,
         };
         let now = Duration::new(5, 500_000_000); // 5.5 seconds
         let delta = Duration::new(1, 500_000_000); // 1.5 seconds
@@ -88,6 +329,7 @@ mod tests {
         let annotator = UnicodeAnnotator {
             color: false,
             time_format: Arc::new(Box::new(SecondsFormat)),
+            widths: None,
         };
         let now = Duration::new(5, 500_000_000); // 5.5 seconds
         let delta = Duration::new(1, 500_000_000); // 1.5 seconds
@@ -97,4 +339,257 @@ mod tests {
             "[Τ: 5.50 s, Δ: 1.50 s] Sample line"
         );
     }
+
+    #[test]
+    fn test_annotations_with_ewma() {
+        let context = AnnotationContext {
+            now: Duration::new(5, 500_000_000),
+            delta: Duration::from_millis(420),
+            mean: None,
+            ewma: Some(Duration::from_millis(510)),
+            rate: None,
+            delta_color: None,
+            as_rate: false,
+        };
+        let simple = SimpleAnnotator {
+            color: false,
+            time_format: Arc::new(Box::new(SecondsFormat)),
+            widths: None,
+        };
+        assert_eq!(
+            simple.format_line_with("Sample line", &context),
+            "[time: 5.50 s, delta: 0.42 s, ~0.51 s] Sample line"
+        );
+        let unicode = UnicodeAnnotator {
+            color: false,
+            time_format: Arc::new(Box::new(SecondsFormat)),
+            widths: None,
+        };
+        assert_eq!(
+            unicode.format_line_with("Sample line", &context),
+            "[Τ: 5.50 s, Δ: 0.42 s, ~0.51 s] Sample line"
+        );
+        // Annotators that do not show the average fall back to `format_line`.
+        let ts = ts(TsMode::SinceStart, None);
+        assert_eq!(
+            ts.format_line_with("Sample line", &context),
+            "00:00:05 Sample line"
+        );
+    }
+
+    #[test]
+    fn test_annotations_with_rate() {
+        let context = AnnotationContext {
+            now: Duration::new(5, 500_000_000),
+            delta: Duration::from_millis(420),
+            mean: None,
+            ewma: None,
+            rate: Some((Duration::from_secs(10), 8.0)),
+            delta_color: None,
+            as_rate: false,
+        };
+        let simple = SimpleAnnotator {
+            color: false,
+            time_format: Arc::new(Box::new(SecondsFormat)),
+            widths: None,
+        };
+        assert_eq!(
+            simple.format_line_with("Sample line", &context),
+            "[time: 5.50 s, delta: 0.42 s, rate_10s: 8.0/s] Sample line"
+        );
+    }
+
+    #[test]
+    fn test_annotations_with_mean() {
+        let context = AnnotationContext {
+            now: Duration::new(5, 500_000_000),
+            delta: Duration::from_millis(420),
+            mean: Some(Duration::from_millis(550)),
+            ..AnnotationContext::default()
+        };
+        let unicode = UnicodeAnnotator {
+            color: false,
+            time_format: Arc::new(Box::new(SecondsFormat)),
+            widths: None,
+        };
+        assert_eq!(
+            unicode.format_line_with("Sample line", &context),
+            "[Τ: 5.50 s, Δ: 0.42 s, avg: 0.55 s] Sample line"
+        );
+    }
+
+    #[test]
+    fn test_annotations_with_heat() {
+        let red = Color::TrueColor { r: 255, g: 0, b: 0 };
+        let context = AnnotationContext {
+            now: Duration::new(5, 500_000_000),
+            delta: Duration::from_millis(420),
+            delta_color: Some(red),
+            ..AnnotationContext::default()
+        };
+        let simple = SimpleAnnotator {
+            color: false,
+            time_format: Arc::new(Box::new(SecondsFormat)),
+            widths: None,
+        };
+        assert_eq!(
+            simple.format_line_with("Sample line", &context),
+            format!("[time: 5.50 s, delta: {}] Sample line", "0.42 s".color(red))
+        );
+    }
+
+    #[test]
+    fn test_annotations_as_rate() {
+        let context = AnnotationContext {
+            now: Duration::new(5, 500_000_000),
+            delta: Duration::from_millis(250),
+            ewma: Some(Duration::from_micros(500)),
+            as_rate: true,
+            ..AnnotationContext::default()
+        };
+        let simple = SimpleAnnotator {
+            color: false,
+            time_format: Arc::new(Box::new(SecondsFormat)),
+            widths: None,
+        };
+        assert_eq!(
+            simple.format_line_with("Sample line", &context),
+            "[time: 5.50 s, delta: 4.00 Hz, ~2.00 kHz] Sample line"
+        );
+        let burst = AnnotationContext {
+            delta: Duration::ZERO,
+            ewma: None,
+            ..context
+        };
+        assert_eq!(
+            simple.format_line_with("Sample line", &burst),
+            "[time: 5.50 s, delta: 1000.00 kHz] Sample line"
+        );
+    }
+
+    #[test]
+    fn test_aligned_columns_only_grow() {
+        let simple = SimpleAnnotator {
+            color: false,
+            time_format: Arc::new(Box::new(SecondsFormat)),
+            widths: Some(ColumnWidths::default()),
+        };
+        let lines: Vec<String> = [(9_500, 420), (21_500, 12_000), (22_000, 500)]
+            .iter()
+            .map(|(now, delta)| {
+                let now = Duration::from_millis(*now);
+                simple.format_line("Sample line", &now, &Duration::from_millis(*delta))
+            })
+            .collect();
+        // The columns widen once, at the first wider time and delta, and stay wide.
+        assert_eq!(lines[0], "[time: 9.50 s, delta: 0.42 s] Sample line");
+        assert_eq!(lines[1], "[time: 21.50 s, delta: 12.00 s] Sample line");
+        assert_eq!(lines[2], "[time: 22.00 s, delta:  0.50 s] Sample line");
+
+        let unicode = UnicodeAnnotator {
+            color: false,
+            time_format: Arc::new(Box::new(SecondsFormat)),
+            widths: Some(ColumnWidths::default()),
+        };
+        let context = AnnotationContext {
+            now: Duration::from_secs(100),
+            delta: Duration::from_millis(10),
+            ewma: Some(Duration::from_secs(10)),
+            rate: Some((Duration::from_secs(10), 12.5)),
+            ..AnnotationContext::default()
+        };
+        unicode.format_line_with("a", &context);
+        let later = AnnotationContext {
+            now: Duration::from_secs(101),
+            delta: Duration::from_secs(1),
+            ewma: Some(Duration::from_secs(1)),
+            rate: Some((Duration::from_secs(10), 1.0)),
+            ..AnnotationContext::default()
+        };
+        assert_eq!(
+            unicode.format_line_with("b", &later),
+            "[Τ: 101.00 s, Δ: 1.00 s,  ~1.00 s, rate_10s:  1.0/s] b"
+        );
+    }
+
+    fn ts(mode: TsMode, format: Option<&str>) -> TsAnnotator {
+        TsAnnotator::new(
+            mode,
+            format,
+            UNIX_EPOCH + Duration::from_secs(1_709_553_600),
+            false,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_annotation_without_line() {
+        let context = AnnotationContext {
+            now: Duration::new(5, 500_000_000),
+            delta: Duration::from_millis(420),
+            ..AnnotationContext::default()
+        };
+        let simple = SimpleAnnotator {
+            color: false,
+            time_format: Arc::new(Box::new(SecondsFormat)),
+            widths: None,
+        };
+        assert_eq!(
+            simple.format_annotation(&context),
+            "[time: 5.50 s, delta: 0.42 s]"
+        );
+        let ts = ts(TsMode::Incremental, Some("%.S"));
+        assert_eq!(ts.format_annotation(&context), "00.420000");
+    }
+
+    #[test]
+    fn test_ts_absolute() {
+        // `ts` prints e.g. "Mar 04 12:00:05 Sample line", in local time.
+        let shape =
+            regex::Regex::new(r"^[A-Z][a-z]{2} \d{2} \d{2}:\d{2}:\d{2} Sample line$").unwrap();
+        let now = Duration::new(5, 500_000_000);
+        let line = ts(TsMode::Absolute, None).format_line("Sample line", &now, &now);
+        assert!(shape.is_match(&line), "{}", line);
+        let epoch = ts(TsMode::Absolute, Some("%.s")).format_line("Sample line", &now, &now);
+        assert_eq!(epoch, "1709553605.500000 Sample line");
+    }
+
+    #[test]
+    fn test_ts_incremental() {
+        // `ts -i` prints e.g. "00:00:01 Sample line".
+        let annotator = ts(TsMode::Incremental, None);
+        let now = Duration::new(3725, 0);
+        let delta = Duration::new(1, 500_000_000);
+        assert_eq!(
+            annotator.format_line("Sample line", &now, &delta),
+            "00:00:01 Sample line"
+        );
+        assert_eq!(
+            ts(TsMode::Incremental, Some("%.S")).format_line("Sample line", &now, &delta),
+            "01.500000 Sample line"
+        );
+    }
+
+    #[test]
+    fn test_ts_since_start() {
+        // `ts -s` prints e.g. "01:02:05 Sample line".
+        let now = Duration::new(3725, 250_000_000);
+        let delta = Duration::new(1, 0);
+        assert_eq!(
+            ts(TsMode::SinceStart, None).format_line("Sample line", &now, &delta),
+            "01:02:05 Sample line"
+        );
+        assert_eq!(
+            ts(TsMode::SinceStart, Some("%.T")).format_line("Sample line", &now, &delta),
+            "01:02:05.250000 Sample line"
+        );
+    }
+
+    #[test]
+    fn test_ts_invalid_format() {
+        assert!(matches!(
+            TsAnnotator::new(TsMode::Incremental, Some("%Q"), UNIX_EPOCH, false),
+            Err(TimelnError::Usage(message)) if message.contains("%Q")
+        ));
+    }
 }
@@ -1,15 +1,1262 @@
-use structopt::StructOpt;
+use std::path::PathBuf;
+use std::time::Duration;
 
-#[derive(Debug, StructOpt)]
-#[structopt(
+use clap::builder::FalseyValueParser;
+use clap::{ArgGroup, Parser};
+use colored::Color;
+
+use crate::build_info::long_version;
+use crate::burst::BurstThreshold;
+use crate::csv::CsvColumns;
+use crate::formatter::parse_duration;
+use crate::journal::JournalPriority;
+use crate::pipeline::CaptureGroup;
+use crate::plot::{PlotSize, PlotX};
+use crate::reader::FollowMode;
+use crate::summarizer::SummaryKind;
+
+/// Parses a duration that must be longer than zero, such as the time between two exports or the
+/// time without input after which the run ends.
+pub fn parse_interval(s: &str) -> Result<Duration, String> {
+    let interval = parse_duration(s).map_err(|err| err.to_string())?;
+    if interval.is_zero() {
+        return Err(format!(
+            "invalid duration '{}': must be longer than zero",
+            s
+        ));
+    }
+    Ok(interval)
+}
+
+/// Parses a number of bytes such as `500000`, `64K`, `512M` or `2G`, in powers of 1024.
+pub fn parse_bytes(s: &str) -> Result<usize, String> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let value: usize = value
+        .parse()
+        .map_err(|_| format!("invalid size '{}', expected e.g. 500000, 64K or 512M", s))?;
+    let multiplier: usize = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        _ => {
+            return Err(format!(
+                "invalid size unit '{}', expected 'K', 'M' or 'G'",
+                unit
+            ))
+        }
+    };
+    value
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size '{}' is too large", s))
+}
+
+/// Parses the number of standard deviations above the mean delta from which a line is an outlier,
+/// such as `3` or `2.5`, which must be positive.
+pub fn parse_sigma(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(sigma) if sigma > 0.0 && sigma.is_finite() => Ok(sigma),
+        _ => Err(format!(
+            "invalid sigma '{}', expected a positive number of standard deviations such as 3",
+            s
+        )),
+    }
+}
+
+/// Parses how many times faster or slower than their phase the latest deltas must be to start a
+/// new phase, such as `3`, which must be above 1.
+pub fn parse_ratio(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(ratio) if ratio > 1.0 && ratio.is_finite() => Ok(ratio),
+        _ => Err(format!(
+            "invalid ratio '{}', expected a factor above 1 such as 3",
+            s
+        )),
+    }
+}
+
+/// Parses the weight of the latest delta in the moving average of the deltas, such as `0.1`,
+/// which must be above 0 and at most 1.
+pub fn parse_alpha(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(alpha) if alpha > 0.0 && alpha <= 1.0 => Ok(alpha),
+        _ => Err(format!(
+            "invalid alpha '{}', expected a weight above 0 and at most 1 such as 0.1",
+            s
+        )),
+    }
+}
+
+/// Parses the relative difference from the moving average within which a delta is steady, as a
+/// fraction such as `0.1` or a percentage such as `10%`, which must not be negative.
+pub fn parse_tolerance(s: &str) -> Result<f64, String> {
+    let tolerance = match s.strip_suffix('%') {
+        Some(percent) => percent.parse::<f64>().map(|percent| percent / 100.0),
+        None => s.parse::<f64>(),
+    };
+    match tolerance {
+        Ok(tolerance) if tolerance >= 0.0 && tolerance.is_finite() => Ok(tolerance),
+        _ => Err(format!(
+            "invalid tolerance '{}', expected a fraction such as 0.1 or a percentage such as 10%",
+            s
+        )),
+    }
+}
+
+/// Parses a burst threshold such as `100/1s`: more than 100 lines within a second. Both must be
+/// positive.
+pub fn parse_burst(s: &str) -> Result<BurstThreshold, String> {
+    let invalid = || {
+        format!(
+            "invalid burst '{}', expected lines/window such as 100/1s",
+            s
+        )
+    };
+    let (lines, window) = s.split_once('/').ok_or_else(invalid)?;
+    let lines = lines.parse::<usize>().map_err(|_| invalid())?;
+    let window = parse_duration(window).map_err(|err| err.to_string())?;
+    if lines == 0 || window.is_zero() {
+        return Err(invalid());
+    }
+    Ok(BurstThreshold { lines, window })
+}
+
+/// Parses a replay speed factor such as `2`, `10` or `0.5`, which must be positive.
+pub fn parse_speed(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(speed),
+        _ => Err(format!(
+            "invalid speed '{}', expected a positive factor such as 2 or 0.5",
+            s
+        )),
+    }
+}
+
+/// Parses a capture group of the regex patterns: its index, such as `1`, or its name.
+pub fn parse_group(s: &str) -> Result<CaptureGroup, String> {
+    if s.is_empty() {
+        return Err("invalid group '', expected an index such as 1 or a name".to_string());
+    }
+    Ok(match s.parse::<usize>() {
+        Ok(index) => CaptureGroup::Index(index),
+        Err(_) => CaptureGroup::Name(s.to_string()),
+    })
+}
+
+/// Parses the name of a terminal color, such as `red`, `bright blue` or `bright_blue`.
+pub fn parse_color(s: &str) -> Result<Color, String> {
+    s.replace('_', " ").parse::<Color>().map_err(|_| {
+        format!(
+            "unknown color '{}', expected a color such as red, yellow, cyan or bright_blue",
+            s
+        )
+    })
+}
+
+#[derive(Debug, Default, Parser)]
+#[command(
     name = "timeln",
-    about = "A utility that times lines/regex from stdin."
+    version,
+    long_version = long_version(),
+    about = "A utility that times lines/regex from stdin.",
+    group = ArgGroup::new("input").args(["file", "replay", "journal", "listen", "connect", "listen_tcp"]),
+    group = ArgGroup::new("tcp").args(["connect", "listen_tcp"]),
+    group = ArgGroup::new("timestamps").args(["parse_time", "json_input", "logfmt"])
 )]
 pub struct TimelnOpt {
-    #[structopt(short = "c", long = "color")]
+    /// Colors the times green, the matches red and the stderr lines of a command yellow.
+    #[arg(short = 'c', long = "color", env = "TIMELN_COLOR", value_parser = FalseyValueParser::new())]
     pub color: bool,
-    #[structopt(short = "r", long = "regex")]
-    pub regex: Option<String>,
-    #[structopt(short = "p", long = "plot")]
+    /// Times and prints only the lines matching the regex pattern.
+    ///
+    /// Repeat it to match several patterns; the delta plot then shows one series per pattern.
+    #[arg(short = 'r', long = "regex")]
+    pub regex: Vec<String>,
+    /// Names the regex patterns, one per -r in the same order.
+    ///
+    /// Each matching line is prefixed with the label of its pattern, which also names the pattern
+    /// in the match counts, the plot legend and the report. Unlabelled patterns are named p0, p1
+    /// and so on.
+    #[arg(long = "label", requires = "regex")]
+    pub label: Vec<String>,
+    /// Highlights the matches of each regex pattern in a color of its own with --color, one per -r
+    /// in the same order, such as red,yellow,cyan.
+    ///
+    /// The colors are cycled through if there are fewer than patterns. They are black, red, green,
+    /// yellow, blue, magenta, cyan and white, or bright_ followed by one of them.
+    #[arg(long = "match-colors", requires = "regex", value_delimiter = ',', value_parser = parse_color)]
+    pub match_colors: Vec<Color>,
+    /// Prints only the matches of the regex patterns, each on a line of its own, like `grep -o`.
+    #[arg(
+        short = 'o',
+        long = "only-matching",
+        requires = "regex",
+        conflicts_with = "passthrough"
+    )]
+    pub only_matching: bool,
+    /// Prints this capture group of the matches of --only-matching instead, by index or by name.
+    ///
+    /// Matches without the group are left out.
+    #[arg(long = "group", requires = "only_matching", value_parser = parse_group)]
+    pub group: Option<CaptureGroup>,
+    /// Also prints the N lines before each match, like `grep -B`.
+    ///
+    /// The context lines are annotated with their arrival times, prefixed with `-` and not timed,
+    /// so the deltas of the matches are unchanged.
+    #[arg(
+        short = 'B',
+        long = "before-context",
+        requires = "regex",
+        conflicts_with = "passthrough"
+    )]
+    pub before_context: Option<usize>,
+    /// Also prints the N lines after each match, like `grep -A`.
+    #[arg(
+        short = 'A',
+        long = "after-context",
+        requires = "regex",
+        conflicts_with = "passthrough"
+    )]
+    pub after_context: Option<usize>,
+    /// Exits with status 1 when the regex patterns matched no line, like `grep`.
+    #[arg(long = "exit-code", requires = "regex")]
+    pub exit_code: bool,
+    /// Compares the durations the lines report, such as `took (\S+)`, with the measured deltas.
+    ///
+    /// The first capture group, or the whole match, is parsed as a duration with an optional ms, s,
+    /// m or h suffix. Each line is annotated with the reported duration and the measured delta
+    /// minus it, and the summary reports the mean and largest difference.
+    #[arg(long = "self-reported")]
+    pub self_reported: Option<String>,
+    /// Times the lines of each value of this named capture group of a regex pattern separately,
+    /// such as `-r 'worker-(?P<id>\d+)' --group-by id`.
+    ///
+    /// A table of the count and mean and maximum interval of each value is printed after the
+    /// summary. Only the first 50 distinct values are timed; the lines of any later values are
+    /// counted in an "other" row.
+    #[arg(long = "group-by")]
+    pub group_by: Option<String>,
+    /// Pulls numbers out of the lines, such as `--extract 'loss=(?P<loss>[0-9.]+)'`, and summarizes
+    /// each named capture group.
+    ///
+    /// The summary reports the minimum, maximum, mean and last value of each group; with --plot,
+    /// each group is also plotted against the time elapsed to values-<name>.svg. Can be repeated.
+    #[arg(long = "extract")]
+    pub extract: Vec<String>,
+    /// Times the spans from each line matching START_RE to the next one matching END_RE, such as
+    /// `--between '^\s*Compiling' '^\s*Finished'`.
+    ///
+    /// Nested spans pair like brackets, and an END_RE line with no open span is ignored. The
+    /// summary reports the number, mean and maximum of the spans and lists those still open as
+    /// incomplete.
+    #[arg(long = "between", num_args = 2, value_names = ["START_RE", "END_RE"])]
+    pub between: Vec<String>,
+    /// Starts a new lap at each line matching the regex, measuring the elapsed times from it again.
+    ///
+    /// The summary reports the number of laps and their mean duration.
+    #[arg(long = "reset-on")]
+    pub reset_on: Option<String>,
+    /// Prints the lines, matches and duration of each lap of --reset-on when it ends.
+    #[arg(long = "lap-summary", requires = "reset_on")]
+    pub lap_summary: bool,
+    /// Sets what the summary at the end of the run reports.
+    ///
+    /// `detailed` adds the average time per line, the overhead of timeln and the bytes read;
+    /// `stats` adds the mean, spread and percentiles of the deltas, streamed so that they work on
+    /// unbounded input.
+    #[arg(
+        long = "summary",
+        env = "TIMELN_SUMMARY",
+        default_value = "simple",
+        value_enum
+    )]
+    pub summary: SummaryKind,
+    /// Lists the N largest silences of the run in the summary, each with the time it ended and the
+    /// lines before and after it.
+    #[arg(long = "gaps")]
+    pub gaps: Option<usize>,
+    /// Shows an exponentially weighted moving average of the deltas after each delta, and its final
+    /// value after the summary.
+    #[arg(long = "ewma")]
+    pub ewma: bool,
+    /// Sets the weight of each new delta in the average of --ewma, above 0 and at most 1 [default:
+    /// 0.1].
+    #[arg(long = "ewma-alpha", requires = "ewma", value_parser = parse_alpha)]
+    pub ewma_alpha: Option<f64>,
+    /// Prints a notice when more than LINES lines arrive within WINDOW, such as 100/1s, and lists
+    /// the bursts after the summary.
+    ///
+    /// A burst lasts until at most half as many lines are left in the window, so that a pace
+    /// hovering around the threshold is reported once.
+    #[arg(long = "detect-bursts", value_parser = parse_burst)]
+    pub detect_bursts: Option<BurstThreshold>,
+    /// Draws the lines, and matches, per minute of the run after the summary.
+    #[arg(long = "activity")]
+    pub activity: bool,
+    /// Sets the width of the buckets of --activity [default: 60s].
+    ///
+    /// The width doubles whenever the run outgrows 60 buckets.
+    #[arg(long = "activity-bucket", requires = "activity", value_parser = parse_duration)]
+    pub activity_bucket: Option<Duration>,
+    /// Reports which fraction of the deltas were at most a few thresholds after the summary.
+    ///
+    /// The thresholds are powers of ten spanning the deltas unless --cdf-points gives them. The
+    /// fractions come from the streaming statistics, so a delta within 1% above a threshold may
+    /// count as within it.
+    #[arg(long = "cdf")]
+    pub cdf: bool,
+    /// Sets the thresholds of --cdf, such as 10ms,100ms,1s.
+    #[arg(long = "cdf-points", requires = "cdf", value_delimiter = ',', value_parser = parse_duration)]
+    pub cdf_points: Vec<Duration>,
+    /// Prefixes each line with whether its delta is slower (↑), about the same (→) or faster (↓)
+    /// than the average of --ewma before it.
+    #[arg(long = "trend", requires = "ewma")]
+    pub trend: bool,
+    /// Sets how close to the average of --ewma a delta is about the same, as a fraction such as 0.2
+    /// or a percentage such as 20% [default: 10%].
+    #[arg(long = "trend-tolerance", requires = "trend", value_parser = parse_tolerance)]
+    pub trend_tolerance: Option<f64>,
+    /// Draws the arrows of --trend and the bars of --activity in ASCII.
+    #[arg(long = "ascii")]
+    pub ascii: bool,
+    /// Sets the window of the rate of lines shown by --annotate-rate, --status, --dashboard and the
+    /// events [default: 10s].
+    #[arg(long = "rate-window", value_parser = parse_interval)]
+    pub rate_window: Option<Duration>,
+    /// Shows the lines per second that arrived within --rate-window after each delta.
+    #[arg(long = "annotate-rate")]
+    pub annotate_rate: bool,
+    /// Colors each delta from blue for the fastest lines to red for the slowest, scaled between the
+    /// 5th and 95th percentiles of the deltas so far.
+    ///
+    /// The gradient is drawn in 24-bit color when COLORTERM is `truecolor` or `24bit`, and in three
+    /// levels otherwise.
+    #[arg(long = "heat")]
+    pub heat: bool,
+    /// Shows the mean of the deltas so far after each delta.
+    #[arg(long = "show-avg")]
+    pub show_avg: bool,
+    /// Leaves the delta of the first line, from the start to it, out of the mean of --show-avg and
+    /// the statistics of the summary.
+    #[arg(long = "skip-first-delta")]
+    pub skip_first_delta: bool,
+    /// Shows each delta as the rate it stands for, 1/delta, in Hz or kHz, and reports the mean,
+    /// lowest and highest rates after the summary.
+    #[arg(long = "as-rate")]
+    pub as_rate: bool,
+    /// Marks each line whose delta is more than SIGMA standard deviations above the mean of the
+    /// deltas before it with ⚠, and counts them after the summary.
+    #[arg(long = "flag-outliers", value_parser = parse_sigma)]
+    pub flag_outliers: Option<f64>,
+    /// Sets how many lines are timed before --flag-outliers flags any [default: 10].
+    #[arg(long = "outlier-warmup", requires = "flag_outliers")]
+    pub outlier_warmup: Option<u64>,
+    /// Splits the run into phases of distinct pace, such as the download, compile and link steps of
+    /// a build.
+    ///
+    /// A new phase starts when the mean of the latest --phase-window deltas is more than
+    /// --phase-ratio times slower or faster than that of the current phase. Each phase is announced
+    /// when it is detected and summarized at the end.
+    #[arg(long = "detect-phases")]
+    pub detect_phases: bool,
+    /// Sets how many of the latest deltas --detect-phases compares with the current phase [default:
+    /// 20].
+    #[arg(long = "phase-window", requires = "detect_phases")]
+    pub phase_window: Option<usize>,
+    /// Sets how many times slower or faster the latest deltas must be to start a new phase, above 1
+    /// [default: 3].
+    #[arg(long = "phase-ratio", requires = "detect_phases", value_parser = parse_ratio)]
+    pub phase_ratio: Option<f64>,
+    /// Replaces each run of consecutive lines faster than the duration, such as 50ms, with a single
+    /// `[… N lines in T]` placeholder.
+    ///
+    /// Collapsed lines still count in the summary and plots.
+    #[arg(long = "collapse-faster-than", value_parser = parse_duration)]
+    pub collapse_faster_than: Option<Duration>,
+    /// Stamps the lines like moreutils `ts`: each line is prefixed with a timestamp and a space,
+    /// without the annotation.
+    ///
+    /// Without -i or -s, the timestamp is the local time. The optional strftime format replaces the
+    /// defaults, and also accepts the `%.S`, `%.s` and `%.T` of `ts`.
+    #[arg(long = "ts")]
+    pub ts: Option<Option<String>>,
+    /// Stamps the lines of --ts with the time since the previous line, like `ts -i`.
+    #[arg(short = 'i', long = "ts-incremental", requires = "ts")]
+    pub ts_incremental: bool,
+    /// Stamps the lines of --ts with the time since the start, like `ts -s`.
+    #[arg(
+        short = 's',
+        long = "ts-since-start",
+        requires = "ts",
+        conflicts_with = "ts_incremental"
+    )]
+    pub ts_since_start: bool,
+    /// Pads each time of the annotations to the widest seen so far in its column.
+    #[arg(long = "align", conflicts_with = "ts")]
+    pub align: bool,
+    /// Removes the leading and trailing whitespace of the lines.
+    #[arg(long = "trim")]
+    pub trim: bool,
+    /// Prints only the annotation of each timed line, without the line.
+    #[arg(long = "no-line")]
+    pub no_line: bool,
+    /// Prints and times every line while still highlighting and counting the regex matches.
+    #[arg(long = "passthrough")]
+    pub passthrough: bool,
+    /// Writes SVG plots of the deltas and of the time elapsed, to deltas.svg and times.svg.
+    #[arg(short = 'p', long = "plot")]
     pub plot: bool,
+    /// Draws the delta plot on a logarithmic y-axis.
+    #[arg(long = "plot-log-y")]
+    pub plot_log_y: bool,
+    /// Sets what the deltas are plotted against.
+    #[arg(long = "plot-x", default_value = "index", value_enum)]
+    pub plot_x: PlotX,
+    /// Prints character-based plots of the deltas and elapsed times at the end of the run.
+    #[arg(long = "plot-term")]
+    pub plot_term: bool,
+    /// Sets the width and height of the plots, as WxH.
+    #[arg(long = "plot-size", default_value = "640x480")]
+    pub plot_size: PlotSize,
+    /// Sets the caption of the plots.
+    #[arg(long = "plot-title")]
+    pub plot_title: Option<String>,
+    /// Downsamples the series longer than this before plotting, keeping the highest and lowest
+    /// point of each bucket, 0 to disable [default: 10000].
+    #[arg(long = "plot-max-points")]
+    pub plot_max_points: Option<usize>,
+    /// Keeps at most this many snapshots for the plots, as a uniform random sample, 0 for no limit
+    /// [default: 100000].
+    ///
+    /// The summary statistics still cover every line.
+    #[arg(long = "plot-max-samples")]
+    pub plot_max_samples: Option<usize>,
+    /// Draws the p50, p95 and p99 deltas as dashed lines on the delta plot.
+    #[arg(long = "plot-percentiles")]
+    pub plot_percentiles: bool,
+    /// Plots the rate of each delta in Hz instead of the delta.
+    #[arg(long = "plot-rate")]
+    pub plot_rate: bool,
+    /// Draws the boundaries of the phases of --detect-phases on the delta plot.
+    #[arg(long = "plot-phases", requires = "detect_phases")]
+    pub plot_phases: bool,
+    /// Writes the plotted points to a gnuplot data file, or CSV if the path ends in .csv.
+    #[arg(long = "plot-data")]
+    pub plot_data: Option<String>,
+    /// Writes a self-contained HTML report with charts and the slowest lines.
+    #[arg(long = "report")]
+    pub report: Option<String>,
+    /// Exports the counters as Prometheus metrics to this file, for the textfile collector of
+    /// node_exporter.
+    ///
+    /// The file is atomically rewritten every --prom-interval and once more at the end of the run.
+    #[arg(long = "prom-textfile")]
+    pub prom_textfile: Option<String>,
+    /// Sets how often --prom-textfile is rewritten [default: 5s].
+    #[arg(long = "prom-interval", requires = "prom_textfile", value_parser = parse_interval)]
+    pub prom_interval: Option<Duration>,
+    /// Serves the Prometheus metrics at `/metrics` on this address, such as 127.0.0.1:9464.
+    ///
+    /// Port 0 picks a free port, which is printed to stderr.
+    #[arg(long = "prom-listen")]
+    pub prom_listen: Option<String>,
+    /// Serves a page following the run at `/`, and its stats as JSON at `/stats`, on this address,
+    /// such as 127.0.0.1:8080.
+    ///
+    /// Port 0 picks a free port, which is printed to stderr. The server stops at the end of the
+    /// run.
+    #[arg(long = "serve")]
+    pub serve: Option<String>,
+    /// Shows a status line on stderr during the run, like `pv`, when stderr is a terminal.
+    #[arg(long = "status")]
+    pub status: bool,
+    /// Shows the progress of the run in the title of the terminal, and restores the title at the
+    /// end.
+    #[arg(long = "title")]
+    pub title: bool,
+    /// Controls the run from the keyboard: `s` prints the summary so far, `p` pauses the display of
+    /// the lines, `q` finishes the run and `c` toggles the colors.
+    ///
+    /// The keys are read from the terminal, so this does nothing when stdin is the terminal or
+    /// there is no terminal.
+    #[arg(long = "keys")]
+    pub keys: bool,
+    /// Keeps a panel of the elapsed time, rate, last deltas and matches in the bottom rows of the
+    /// terminal while the lines scroll above it.
+    #[arg(long = "dashboard")]
+    pub dashboard: bool,
+    /// Redraws the summary so far, a histogram of the deltas, the slowest lines and a sparkline on
+    /// the whole terminal every second, instead of printing the lines.
+    #[arg(long = "watch-summary", conflicts_with_all = ["dashboard", "status"])]
+    pub watch_summary: bool,
+    /// Sets how many matches to expect, to show the progress and estimated time left in --status
+    /// and --dashboard.
+    ///
+    /// The summary is followed by the total time estimated at the last match, and how far it was
+    /// from the time the run took.
+    #[arg(long = "total-matches", requires = "regex")]
+    pub total_matches: Option<u64>,
+    /// Sends a StatsD timing for each timed line, and the counters as gauges, to this UDP address.
+    #[arg(long = "statsd", env = "TIMELN_STATSD")]
+    pub statsd: Option<String>,
+    /// Replaces the `timeln` prefix of the names of the StatsD metrics.
+    #[arg(long = "statsd-prefix", requires = "statsd")]
+    pub statsd_prefix: Option<String>,
+    /// Sets how often the StatsD gauges are sent [default: 10s].
+    #[arg(long = "statsd-interval", requires = "statsd", value_parser = parse_interval)]
+    pub statsd_interval: Option<Duration>,
+    /// Writes machine-readable events as newline-delimited JSON to this open file descriptor, on
+    /// Unix, such as `--event-fd 3 3>events.ndjson`.
+    ///
+    /// A `started` event, a `line` event per input line, an `interim-summary` event at the end of
+    /// each lap of --reset-on and a `finished` event are written.
+    #[arg(long = "event-fd")]
+    pub event_fd: Option<i32>,
+    /// Sets how many events are queued for a slow reader of --event-fd before timeln waits for it
+    /// [default: 1024].
+    #[arg(long = "event-buffer", requires = "event_fd")]
+    pub event_buffer: Option<usize>,
+    /// Drops the oldest queued events when the queue of --event-fd is full instead of waiting, and
+    /// reports how many at the end.
+    #[arg(long = "event-drop-oldest", requires = "event_fd")]
+    pub event_drop_oldest: bool,
+    /// Adds the run and a row per timed line to this SQLite database, which accumulates runs. Needs
+    /// the sqlite feature.
+    #[arg(long = "sqlite")]
+    pub sqlite: Option<String>,
+    /// Writes the snapshots of the run to this Parquet file. Needs the parquet feature.
+    #[arg(long = "parquet")]
+    pub parquet: Option<String>,
+    /// Sets how many rows are written per record batch of --parquet [default: 8192].
+    #[arg(long = "parquet-batch", requires = "parquet")]
+    pub parquet_batch: Option<usize>,
+    /// Shows a desktop notification when the run finishes. Needs the desktop feature.
+    #[arg(long = "notify")]
+    pub notify: bool,
+    /// Also shows a desktop notification for the first line matching this regex.
+    #[arg(long = "notify-on-match", requires = "notify")]
+    pub notify_on_match: Option<String>,
+    /// POSTs a JSON notification to this webhook when the run ends. Needs the notify feature.
+    #[arg(long = "notify-url", env = "TIMELN_NOTIFY_URL")]
+    pub notify_url: Option<String>,
+    /// Names the run in the notifications of --notify-url.
+    #[arg(long = "notify-label", requires = "notify_url")]
+    pub notify_label: Option<String>,
+    /// Also notifies --notify-url of the lines slower than this duration.
+    #[arg(long = "notify-slower-than", requires = "notify_url", value_parser = parse_duration)]
+    pub notify_slower_than: Option<Duration>,
+    /// Sets the least time between two notifications of --notify-slower-than [default: 1m].
+    #[arg(long = "notify-interval", requires = "notify_slower_than", value_parser = parse_interval)]
+    pub notify_interval: Option<Duration>,
+    /// Runs --on-slow-cmd when a line is slower than this duration.
+    #[arg(long = "on-slow", requires = "on_slow_cmd", value_parser = parse_duration)]
+    pub on_slow: Option<Duration>,
+    /// Sets the shell command run by --on-slow.
+    ///
+    /// The command gets the line in TIMELN_LINE, its delta in TIMELN_DELTA and the time since the
+    /// start in TIMELN_ELAPSED, both in seconds. It runs in the background, and one that fails is
+    /// only logged to stderr.
+    #[arg(long = "on-slow-cmd", requires = "on_slow")]
+    pub on_slow_cmd: Option<String>,
+    /// Sets the least time between two runs of --on-slow-cmd [default: 1m].
+    #[arg(long = "on-slow-interval", requires = "on_slow", value_parser = parse_interval)]
+    pub on_slow_interval: Option<Duration>,
+    /// Runs this shell command for each line matching a regex pattern.
+    ///
+    /// The command gets the same variables as --on-slow-cmd, the text of the match in TIMELN_MATCH
+    /// and that of its capture groups in TIMELN_GROUP_1, TIMELN_GROUP_2 and so on.
+    #[arg(long = "on-match-cmd", requires = "regex")]
+    pub on_match_cmd: Option<String>,
+    /// Runs --on-match-cmd for the first N matches only.
+    #[arg(long = "on-match-limit", requires = "on_match_cmd")]
+    pub on_match_limit: Option<usize>,
+    /// Caps the memory kept for the plots and report, such as 64M.
+    ///
+    /// Once it is reached, timeln warns once, plots a smaller sample and keeps only the numbers of
+    /// the slowest lines, not their text.
+    #[arg(long = "max-memory", env = "TIMELN_MAX_MEMORY", value_parser = parse_bytes)]
+    pub max_memory: Option<usize>,
+    /// Reads this file instead of stdin. Repeat it to read several files one after the other.
+    ///
+    /// A gzip- or zstd-compressed file is decompressed as it is read, which needs the gzip or zstd
+    /// feature. With several files, each starts with a `==> name <==` header and has a row in the
+    /// summary.
+    #[arg(long = "file")]
+    pub file: Vec<String>,
+    /// Measures the elapsed times of each --file from its first line.
+    #[arg(long = "reset-per-file", requires = "file")]
+    pub reset_per_file: bool,
+    /// Keeps reading the --file as it grows, like `tail -f`; `name` also reopens it when it is
+    /// rotated, like `tail -F`.
+    #[arg(long = "follow", requires = "file", value_enum)]
+    pub follow: Option<FollowMode>,
+    /// Sets how often the followed file is checked [default: 1s].
+    #[arg(long = "follow-interval", value_parser = parse_interval)]
+    pub follow_interval: Option<Duration>,
+    /// Ends the input when no line arrives for this long, as if it were closed, and exits with
+    /// status 3.
+    #[arg(long = "exit-idle", value_parser = parse_interval)]
+    pub exit_idle: Option<Duration>,
+    /// Joins the input lines until and including a line matching this regex, and times the joined
+    /// line.
+    #[arg(long = "join-until")]
+    pub join_until: Option<String>,
+    /// Joins the input lines up to each blank line, and times the joined lines.
+    #[arg(long = "paragraph", conflicts_with = "join_until")]
+    pub paragraph: bool,
+    /// Also saves the input, unannotated and with its original line endings, to this file.
+    #[arg(long = "tee-raw")]
+    pub tee_raw: Option<String>,
+    /// Saves every input line with its arrival time to this record file as the run goes.
+    #[arg(long = "record")]
+    pub record: Option<String>,
+    /// Syncs each line of --record to disk.
+    #[arg(long = "record-sync", requires = "record")]
+    pub record_sync: bool,
+    /// Feeds this record file, or CSV file of timings, back through timeln at its recorded pace.
+    #[arg(long = "replay", conflicts_with_all = ["command", "timestamps"])]
+    pub replay: Option<String>,
+    /// Replays this many times faster, such as 10, or slower, such as 0.5.
+    #[arg(long = "speed", requires = "replay", value_parser = parse_speed)]
+    pub speed: Option<f64>,
+    /// Waits at most this long between two replayed lines.
+    #[arg(long = "replay-clamp", requires = "replay", value_parser = parse_duration)]
+    pub replay_clamp: Option<Duration>,
+    /// Sets the columns of the CSV file replayed, such as elapsed=2,delta=3, or delta=3 alone to
+    /// add up the deltas [default: elapsed=1,delta=2].
+    #[arg(long = "csv-columns")]
+    pub csv_columns: Option<CsvColumns>,
+    /// Times the entries of the systemd journal, read through journalctl, on Linux. Needs the
+    /// journald feature.
+    ///
+    /// The entries logged before timeln started are timed by their journal timestamps; the journal
+    /// is then followed.
+    #[arg(long = "journal", conflicts_with = "command")]
+    pub journal: bool,
+    /// Reads the lines written to a Unix domain socket created at this path, or to the named pipe
+    /// already there, on Unix.
+    ///
+    /// The first connection is read until it closes. The socket file is removed at the end.
+    #[arg(long = "listen", conflicts_with = "command")]
+    pub listen: Option<String>,
+    /// Also reads the connections after the first one to --listen, each announced by a
+    /// `[connection N]` notice.
+    #[arg(long = "listen-keep", requires = "listen")]
+    pub listen_keep: bool,
+    /// Reads the lines of a TCP connection made to this address, like `nc host port`.
+    #[arg(long = "connect", conflicts_with = "command")]
+    pub connect: Option<String>,
+    /// Reads the lines of the first client to connect to this address, like `nc -l port`.
+    #[arg(long = "listen-tcp", conflicts_with = "command")]
+    pub listen_tcp: Option<String>,
+    /// Connects again, retrying every second, or accepts the next client, when the TCP connection
+    /// closes or drops.
+    #[arg(long = "reconnect", requires = "tcp")]
+    pub reconnect: bool,
+    /// Waits at most this long for the TCP connection of --connect to be made [default: 10s].
+    #[arg(
+        long = "connect-timeout",
+        requires = "connect",
+        conflicts_with = "listen_tcp",
+        value_parser = parse_duration
+    )]
+    pub connect_timeout: Option<Duration>,
+    /// Reads the journal entries of this systemd unit only. Can be repeated.
+    #[arg(short = 'u', long = "unit", requires = "journal")]
+    pub unit: Vec<String>,
+    /// Reads the journal entries of this priority only, as a level or a range, such as err, 3 or
+    /// crit..warning.
+    #[arg(long = "priority", requires = "journal")]
+    pub priority: Option<JournalPriority>,
+    /// Times the lines by the timestamps they contain, found with this regex: its first capture
+    /// group, or else the whole match.
+    ///
+    /// A line without a timestamp takes the time of the line before it, and a timestamp earlier
+    /// than a previous one gives a delta of zero.
+    #[arg(long = "parse-time")]
+    pub parse_time: Option<String>,
+    /// Parses the timestamps of --parse-time with this chrono layout, such as '%b %e %H:%M:%S'
+    /// [default: %Y-%m-%dT%H:%M:%S%.f].
+    #[arg(long = "time-layout", requires = "parse_time")]
+    pub time_layout: Option<String>,
+    /// Reads one JSON object per line, timed by its ts field.
+    ///
+    /// The field holds seconds or milliseconds since the epoch or an RFC 3339 timestamp. Nested
+    /// fields are named with dots, as in event.ts.
+    #[arg(long = "json-input")]
+    pub json_input: bool,
+    /// Times the lines of --json-input or --logfmt by this field instead of ts.
+    #[arg(long = "time-field")]
+    pub time_field: Option<String>,
+    /// Shows the lines of --json-input as this field, such as msg, which the regex patterns then
+    /// match.
+    #[arg(long = "line-field", requires = "json_input")]
+    pub line_field: Option<String>,
+    /// Reads key=value lines, timed by their ts key.
+    ///
+    /// --group-by and --extract then name keys rather than capture groups of regex patterns.
+    #[arg(long = "logfmt")]
+    pub logfmt: bool,
+    /// Shows only these keys of the lines of --logfmt, such as level,msg.
+    #[arg(long = "fields", requires = "logfmt", value_delimiter = ',')]
+    pub fields: Vec<String>,
+    /// Prints the build of timeln, the operating system and the TIMELN_* environment variables, to
+    /// paste into a bug report.
+    #[arg(long = "bugreport")]
+    pub bugreport: bool,
+    /// Runs this command and times both its stdout and stderr, tagged [out] and [err].
+    #[arg(last = true)]
+    pub command: Vec<String>,
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "timeln plot")]
+pub struct PlotOpt {
+    /// The record file, or CSV file of timings, to plot.
+    pub path: String,
+    #[command(flatten)]
+    pub opt: TimelnOpt,
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "timeln watch")]
+pub struct WatchOpt {
+    /// Runs the command again this long after each run [default: 2s].
+    #[arg(long = "interval", value_parser = parse_interval)]
+    pub interval: Option<Duration>,
+    /// Runs the command again each time this file or directory changes. Can be repeated.
+    #[arg(long = "watch-path")]
+    pub watch_path: Vec<PathBuf>,
+    /// Stops after this many runs and prints the table of the runs.
+    #[arg(long = "runs")]
+    pub runs: Option<usize>,
+    #[command(flatten)]
+    pub opt: TimelnOpt,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_option_has_help() {
+        use clap::CommandFactory;
+        for command in [
+            TimelnOpt::command(),
+            PlotOpt::command(),
+            WatchOpt::command(),
+        ] {
+            command.clone().debug_assert();
+            for arg in command.get_arguments() {
+                let name = arg.get_id();
+                assert!(arg.get_help().is_some(), "{} has no help", name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        let opt = TimelnOpt::parse_from(["timeln", "--exit-idle", "1m30s"]);
+        assert_eq!(opt.exit_idle, Some(Duration::from_secs(90)));
+        // An invalid duration fails the parsing of the arguments, naming the invalid part.
+        let err = TimelnOpt::try_parse_from(["timeln", "--exit-idle", "5 days"]).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("unknown unit ' days' at position 2"),
+            "{}",
+            err
+        );
+        assert!(TimelnOpt::try_parse_from(["timeln", "--rate-window", "-1s"]).is_err());
+    }
+
+    #[test]
+    fn test_intervals_reject_zero() {
+        let err = TimelnOpt::try_parse_from(["timeln", "--exit-idle", "0"]).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("invalid duration '0': must be longer than zero"),
+            "{}",
+            err
+        );
+        assert!(TimelnOpt::try_parse_from(["timeln", "--rate-window", "0s"]).is_err());
+        let prom = [
+            "timeln",
+            "--prom-textfile",
+            "t.prom",
+            "--prom-interval",
+            "0ms",
+        ];
+        assert!(TimelnOpt::try_parse_from(prom).is_err());
+        assert!(
+            TimelnOpt::try_parse_from(["timeln", "--statsd", "h:1", "--statsd-interval", "0"])
+                .is_err()
+        );
+        assert!(WatchOpt::try_parse_from(["watch", "--interval", "0", "--", "make"]).is_err());
+        assert!(WatchOpt::try_parse_from(["watch", "--interval", "1s", "--", "make"]).is_ok());
+    }
+
+    #[test]
+    fn test_parse_bytes() {
+        assert_eq!(parse_bytes("500000"), Ok(500_000));
+        assert_eq!(parse_bytes("64K"), Ok(64 * 1024));
+        assert_eq!(parse_bytes("512m"), Ok(512 * 1024 * 1024));
+        assert_eq!(parse_bytes("2GB"), Ok(2 << 30));
+        assert!(parse_bytes("1.5G").is_err());
+        assert!(parse_bytes("10T").is_err());
+        assert!(parse_bytes("M").is_err());
+    }
+
+    #[test]
+    fn test_parse_speed() {
+        assert_eq!(parse_speed("2"), Ok(2.0));
+        assert_eq!(parse_speed("0.5"), Ok(0.5));
+        assert!(parse_speed("0").is_err());
+        assert!(parse_speed("-1").is_err());
+        assert!(parse_speed("inf").is_err());
+        assert!(parse_speed("fast").is_err());
+    }
+
+    #[test]
+    fn test_labels_pair_with_patterns() {
+        let opt = TimelnOpt::parse_from([
+            "timeln", "-r", "ERROR", "--label", "err", "-r", "WARN", "--label", "warn",
+        ]);
+        assert_eq!(opt.regex, vec!["ERROR", "WARN"]);
+        assert_eq!(opt.label, vec!["err", "warn"]);
+    }
+
+    #[test]
+    fn test_match_colors() {
+        let opt = TimelnOpt::parse_from([
+            "timeln",
+            "-r",
+            "ERROR",
+            "-r",
+            "WARN",
+            "--match-colors",
+            "red,Yellow,bright_cyan",
+        ]);
+        assert_eq!(
+            opt.match_colors,
+            vec![Color::Red, Color::Yellow, Color::BrightCyan]
+        );
+        let unknown =
+            TimelnOpt::try_parse_from(["timeln", "-r", "ERROR", "--match-colors", "red,mauve"]);
+        assert!(unknown
+            .unwrap_err()
+            .to_string()
+            .contains("unknown color 'mauve'"));
+    }
+
+    #[test]
+    fn test_only_matching() {
+        let opt = TimelnOpt::parse_from(["timeln", "-r", "(\\d+)ms", "-o", "--group", "1"]);
+        assert!(opt.only_matching);
+        assert_eq!(opt.group, Some(CaptureGroup::Index(1)));
+        let opt = TimelnOpt::parse_from(["timeln", "-r", "x", "--only-matching", "--group", "ms"]);
+        assert_eq!(opt.group, Some(CaptureGroup::Name("ms".to_string())));
+        assert!(TimelnOpt::try_parse_from(["timeln", "-o"]).is_err());
+        assert!(TimelnOpt::try_parse_from(["timeln", "-r", "x", "--group", "1"]).is_err());
+        assert!(TimelnOpt::try_parse_from(["timeln", "-r", "x", "-o", "--passthrough"]).is_err());
+    }
+
+    #[test]
+    fn test_context_lines() {
+        let opt = TimelnOpt::parse_from(["timeln", "-r", "ERROR", "-B", "2", "-A", "3"]);
+        assert_eq!(opt.before_context, Some(2));
+        assert_eq!(opt.after_context, Some(3));
+        assert!(TimelnOpt::try_parse_from(["timeln", "-B", "2"]).is_err());
+        assert!(
+            TimelnOpt::try_parse_from(["timeln", "-r", "x", "-A", "1", "--passthrough"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_group_by_requires_regex() {
+        let opt =
+            TimelnOpt::parse_from(["timeln", "-r", "worker-(?P<id>\\d+)", "--group-by", "id"]);
+        assert_eq!(opt.group_by.as_deref(), Some("id"));
+        let opt = TimelnOpt::parse_from(["timeln", "--logfmt", "--group-by", "worker"]);
+        assert_eq!(opt.group_by.as_deref(), Some("worker"));
+    }
+
+    #[test]
+    fn test_logfmt_fields() {
+        let opt = TimelnOpt::parse_from(["timeln", "--logfmt", "--fields", "level,msg"]);
+        assert_eq!(opt.fields, vec!["level", "msg"]);
+        assert!(TimelnOpt::try_parse_from(["timeln", "--fields", "msg"]).is_err());
+        assert!(TimelnOpt::try_parse_from(["timeln", "--logfmt", "--json-input"]).is_err());
+    }
+
+    #[test]
+    fn test_extract_repeats() {
+        let opt = TimelnOpt::parse_from([
+            "timeln",
+            "--extract",
+            "loss=(?P<loss>\\S+)",
+            "--extract",
+            "acc=(\\S+)",
+        ]);
+        assert_eq!(opt.extract, vec!["loss=(?P<loss>\\S+)", "acc=(\\S+)"]);
+    }
+
+    #[test]
+    fn test_between_takes_two_patterns() {
+        let opt = TimelnOpt::parse_from(["timeln", "--between", "^Compiling", "^Finished"]);
+        assert_eq!(opt.between, vec!["^Compiling", "^Finished"]);
+        assert!(TimelnOpt::try_parse_from(["timeln", "--between", "^Compiling"]).is_err());
+    }
+
+    #[test]
+    fn test_plot_opt_takes_timeln_options() {
+        let plot = PlotOpt::parse_from(["plot", "run.rec", "--plot-log-y", "-r", "done"]);
+        assert_eq!(plot.path, "run.rec");
+        assert!(plot.opt.plot_log_y);
+        assert_eq!(plot.opt.regex, vec!["done"]);
+        let plot = PlotOpt::parse_from(["plot", "times.csv", "--csv-columns", "delta=3"]);
+        assert_eq!(
+            plot.opt.csv_columns.and_then(|columns| columns.delta),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn test_journal_units_and_priority() {
+        let opt = TimelnOpt::parse_from([
+            "timeln",
+            "--journal",
+            "-u",
+            "a.service",
+            "--unit",
+            "b.service",
+            "--priority",
+            "warning",
+        ]);
+        assert_eq!(opt.unit, vec!["a.service", "b.service"]);
+        assert_eq!(opt.priority.map(|priority| priority.to), Some(4));
+        assert!(TimelnOpt::try_parse_from(["timeln", "-u", "a.service"]).is_err());
+        assert!(TimelnOpt::try_parse_from(["timeln", "--journal", "--priority", "loud"]).is_err());
+    }
+
+    #[test]
+    fn test_ewma_alpha() {
+        let opt = TimelnOpt::parse_from(["timeln", "--ewma", "--ewma-alpha", "0.25"]);
+        assert!(opt.ewma);
+        assert_eq!(opt.ewma_alpha, Some(0.25));
+        assert_eq!(parse_alpha("1"), Ok(1.0));
+        assert!(parse_alpha("0").is_err());
+        assert!(parse_alpha("1.5").is_err());
+        assert!(TimelnOpt::try_parse_from(["timeln", "--ewma-alpha", "0.5"]).is_err());
+    }
+
+    #[test]
+    fn test_gaps() {
+        let opt = TimelnOpt::parse_from(["timeln", "--summary", "detailed", "--gaps", "5"]);
+        assert_eq!(opt.gaps, Some(5));
+        assert!(TimelnOpt::try_parse_from(["timeln", "--gaps", "many"]).is_err());
+    }
+
+    #[test]
+    fn test_detect_bursts() {
+        let opt = TimelnOpt::parse_from(["timeln", "--detect-bursts", "100/1s"]);
+        assert_eq!(
+            opt.detect_bursts,
+            Some(BurstThreshold {
+                lines: 100,
+                window: Duration::from_secs(1)
+            })
+        );
+        assert_eq!(
+            parse_burst("5/250ms").map(|threshold| threshold.window),
+            Ok(Duration::from_millis(250))
+        );
+        for invalid in ["100", "0/1s", "100/0s", "x/1s", "100/soon"] {
+            assert!(parse_burst(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_on_slow() {
+        let opt = TimelnOpt::parse_from([
+            "timeln",
+            "--on-slow",
+            "2m",
+            "--on-slow-cmd",
+            "notify-send 'build stalled'",
+        ]);
+        assert_eq!(opt.on_slow, Some(Duration::from_secs(120)));
+        assert_eq!(
+            opt.on_slow_cmd.as_deref(),
+            Some("notify-send 'build stalled'")
+        );
+        assert_eq!(opt.on_slow_interval, None);
+        // The threshold and the command go together.
+        assert!(TimelnOpt::try_parse_from(["timeln", "--on-slow", "2m"]).is_err());
+        assert!(TimelnOpt::try_parse_from(["timeln", "--on-slow-cmd", "true"]).is_err());
+    }
+
+    #[test]
+    fn test_on_match_cmd() {
+        let opt = TimelnOpt::parse_from([
+            "timeln",
+            "-r",
+            "epoch",
+            "--on-match-cmd",
+            "echo $TIMELN_MATCH",
+            "--on-match-limit",
+            "3",
+        ]);
+        assert_eq!(opt.on_match_cmd.as_deref(), Some("echo $TIMELN_MATCH"));
+        assert_eq!(opt.on_match_limit, Some(3));
+        // Only matches of a regex pattern run it.
+        assert!(TimelnOpt::try_parse_from(["timeln", "--on-match-cmd", "true"]).is_err());
+        assert!(TimelnOpt::try_parse_from(["timeln", "-r", "a", "--on-match-limit", "3"]).is_err());
+    }
+
+    #[test]
+    fn test_activity_bucket() {
+        let opt = TimelnOpt::parse_from(["timeln", "--activity", "--activity-bucket", "5m"]);
+        assert!(opt.activity);
+        assert_eq!(opt.activity_bucket, Some(Duration::from_secs(300)));
+        assert!(TimelnOpt::try_parse_from(["timeln", "--activity-bucket", "1m"]).is_err());
+    }
+
+    #[test]
+    fn test_cdf_points() {
+        let opt = TimelnOpt::parse_from(["timeln", "--cdf", "--cdf-points", "10ms,100ms,1s"]);
+        assert!(opt.cdf);
+        assert_eq!(
+            opt.cdf_points,
+            [10, 100, 1000].map(Duration::from_millis).to_vec()
+        );
+        assert!(TimelnOpt::try_parse_from(["timeln", "--cdf-points", "1s"]).is_err());
+        assert!(TimelnOpt::try_parse_from(["timeln", "--cdf", "--cdf-points", "1s,soon"]).is_err());
+    }
+
+    #[test]
+    fn test_trend_tolerance() {
+        let opt = TimelnOpt::parse_from([
+            "timeln",
+            "--ewma",
+            "--trend",
+            "--trend-tolerance",
+            "20%",
+            "--ascii",
+        ]);
+        assert!(opt.trend && opt.ascii);
+        assert_eq!(opt.trend_tolerance, Some(0.2));
+        assert_eq!(parse_tolerance("0.05"), Ok(0.05));
+        assert_eq!(parse_tolerance("0"), Ok(0.0));
+        assert!(parse_tolerance("-0.1").is_err());
+        assert!(parse_tolerance("ten%").is_err());
+        assert!(TimelnOpt::try_parse_from(["timeln", "--trend"]).is_err());
+        assert!(
+            TimelnOpt::try_parse_from(["timeln", "--ewma", "--trend-tolerance", "0.1"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_rate_window() {
+        let opt = TimelnOpt::parse_from(["timeln", "--rate-window", "30s", "--annotate-rate"]);
+        assert_eq!(opt.rate_window, Some(Duration::from_secs(30)));
+        assert!(opt.annotate_rate);
+        assert!(TimelnOpt::try_parse_from(["timeln", "--rate-window", "soon"]).is_err());
+    }
+
+    #[test]
+    fn test_align() {
+        assert!(TimelnOpt::parse_from(["timeln", "--align"]).align);
+        assert!(!TimelnOpt::parse_from(["timeln"]).align);
+        assert!(TimelnOpt::try_parse_from(["timeln", "--align", "--ts"]).is_err());
+    }
+
+    #[test]
+    fn test_no_line() {
+        assert!(TimelnOpt::parse_from(["timeln", "--no-line"]).no_line);
+        assert!(!TimelnOpt::parse_from(["timeln"]).no_line);
+    }
+
+    #[test]
+    fn test_heat() {
+        assert!(TimelnOpt::parse_from(["timeln", "--heat"]).heat);
+        assert!(!TimelnOpt::parse_from(["timeln"]).heat);
+    }
+
+    #[test]
+    fn test_show_avg() {
+        let opt = TimelnOpt::parse_from(["timeln", "--show-avg", "--skip-first-delta"]);
+        assert!(opt.show_avg);
+        assert!(opt.skip_first_delta);
+        let opt = TimelnOpt::parse_from(["timeln"]);
+        assert!(!opt.show_avg);
+        assert!(!opt.skip_first_delta);
+    }
+
+    #[test]
+    fn test_as_rate() {
+        let opt = TimelnOpt::parse_from(["timeln", "--as-rate", "--plot-rate"]);
+        assert!(opt.as_rate);
+        assert!(opt.plot_rate);
+        let opt = TimelnOpt::parse_from(["timeln"]);
+        assert!(!opt.as_rate);
+        assert!(!opt.plot_rate);
+    }
+
+    #[test]
+    fn test_detect_phases() {
+        let opt = TimelnOpt::parse_from([
+            "timeln",
+            "--detect-phases",
+            "--phase-window",
+            "10",
+            "--phase-ratio",
+            "2.5",
+            "--plot-phases",
+        ]);
+        assert!(opt.detect_phases && opt.plot_phases);
+        assert_eq!(opt.phase_window, Some(10));
+        assert_eq!(opt.phase_ratio, Some(2.5));
+        assert!(parse_ratio("1").is_err());
+        assert!(TimelnOpt::try_parse_from(["timeln", "--phase-window", "10"]).is_err());
+        assert!(TimelnOpt::try_parse_from(["timeln", "--plot-phases"]).is_err());
+    }
+
+    #[test]
+    fn test_flag_outliers() {
+        let opt =
+            TimelnOpt::parse_from(["timeln", "--flag-outliers", "2.5", "--outlier-warmup", "5"]);
+        assert_eq!(opt.flag_outliers, Some(2.5));
+        assert_eq!(opt.outlier_warmup, Some(5));
+        assert!(parse_sigma("0").is_err());
+        assert!(parse_sigma("-1").is_err());
+        assert!(TimelnOpt::try_parse_from(["timeln", "--outlier-warmup", "5"]).is_err());
+    }
+
+    #[test]
+    fn test_ts_modes() {
+        let ts = TimelnOpt::parse_from(["timeln", "--ts"]);
+        assert_eq!(ts.ts, Some(None));
+        let ts = TimelnOpt::parse_from(["timeln", "--ts", "%.S", "-i"]);
+        assert_eq!(ts.ts, Some(Some("%.S".to_string())));
+        assert!(ts.ts_incremental && !ts.ts_since_start);
+        assert!(TimelnOpt::parse_from(["timeln", "--ts", "-s"]).ts_since_start);
+        assert!(TimelnOpt::try_parse_from(["timeln", "-i"]).is_err());
+        assert!(TimelnOpt::try_parse_from(["timeln", "--ts", "-i", "-s"]).is_err());
+    }
+
+    #[test]
+    fn test_listen_conflicts_with_other_inputs() {
+        let opt =
+            TimelnOpt::parse_from(["timeln", "--listen", "/tmp/timeln.sock", "--listen-keep"]);
+        assert_eq!(opt.listen.as_deref(), Some("/tmp/timeln.sock"));
+        assert!(opt.listen_keep);
+        assert!(TimelnOpt::try_parse_from(["timeln", "--listen-keep"]).is_err());
+        assert!(
+            TimelnOpt::try_parse_from(["timeln", "--listen", "a.sock", "--file", "a.log"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_tcp_inputs() {
+        let opt = TimelnOpt::parse_from([
+            "timeln",
+            "--connect",
+            "localhost:9000",
+            "--connect-timeout",
+            "3s",
+            "--reconnect",
+        ]);
+        assert_eq!(opt.connect.as_deref(), Some("localhost:9000"));
+        assert_eq!(opt.connect_timeout, Some(Duration::from_secs(3)));
+        assert!(opt.reconnect);
+        let opt = TimelnOpt::parse_from(["timeln", "--listen-tcp", "0.0.0.0:9000"]);
+        assert_eq!(opt.listen_tcp.as_deref(), Some("0.0.0.0:9000"));
+        assert!(!opt.reconnect);
+        assert!(TimelnOpt::try_parse_from(["timeln", "--reconnect"]).is_err());
+        assert!(TimelnOpt::try_parse_from([
+            "timeln",
+            "--listen-tcp",
+            ":9000",
+            "--connect-timeout",
+            "3s"
+        ])
+        .is_err());
+        assert!(TimelnOpt::try_parse_from([
+            "timeln",
+            "--connect",
+            "localhost:9000",
+            "--listen-tcp",
+            ":9000"
+        ])
+        .is_err());
+        assert!(
+            TimelnOpt::try_parse_from(["timeln", "--connect", "localhost:9000", "--", "make"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_several_files() {
+        let opt = TimelnOpt::parse_from([
+            "timeln",
+            "--file",
+            "build1.log",
+            "--file",
+            "build2.log",
+            "--reset-per-file",
+        ]);
+        assert_eq!(opt.file, ["build1.log", "build2.log"]);
+        assert!(opt.reset_per_file);
+        assert!(TimelnOpt::parse_from(["timeln"]).file.is_empty());
+        assert!(TimelnOpt::try_parse_from(["timeln", "--reset-per-file"]).is_err());
+    }
+
+    #[test]
+    fn test_keys() {
+        assert!(TimelnOpt::parse_from(["timeln", "--keys"]).keys);
+        assert!(!TimelnOpt::parse_from(["timeln"]).keys);
+    }
+
+    #[test]
+    fn test_dashboard() {
+        assert!(TimelnOpt::parse_from(["timeln", "--dashboard"]).dashboard);
+        assert!(!TimelnOpt::parse_from(["timeln"]).dashboard);
+    }
+
+    #[test]
+    fn test_watch_summary() {
+        assert!(TimelnOpt::parse_from(["timeln", "--watch-summary"]).watch_summary);
+        // Both would draw on the same terminal.
+        assert!(TimelnOpt::try_parse_from(["timeln", "--watch-summary", "--dashboard"]).is_err());
+        assert!(TimelnOpt::try_parse_from(["timeln", "--watch-summary", "--status"]).is_err());
+    }
+
+    #[test]
+    fn test_total_matches() {
+        let opt = TimelnOpt::parse_from(["timeln", "-r", "epoch", "--total-matches", "100"]);
+        assert_eq!(opt.total_matches, Some(100));
+        // Only matches of a regex pattern are counted.
+        assert!(TimelnOpt::try_parse_from(["timeln", "--total-matches", "100"]).is_err());
+    }
+
+    #[test]
+    fn test_watch_opt_takes_command() {
+        let watch = WatchOpt::parse_from([
+            "watch",
+            "--watch-path",
+            "src",
+            "--runs",
+            "3",
+            "-r",
+            "ok",
+            "--",
+            "make",
+            "-j4",
+        ]);
+        assert_eq!(watch.watch_path, [PathBuf::from("src")]);
+        assert_eq!(watch.runs, Some(3));
+        assert_eq!(watch.opt.regex, vec!["ok"]);
+        assert_eq!(watch.opt.command, vec!["make", "-j4"]);
+    }
 }
@@ -0,0 +1,124 @@
+//! This module describes the build of timeln: its version, the commit it was built from, the date
+//! of the build, the target it was built for and its enabled cargo features, as captured by the
+//! build script. `timeln --version` prints it, and record files and events include it, so that a
+//! bug report or a recording can be traced back to a build.
+//!
+//! # Example
+//!
+//! ```
+//! use timeln::build_info::BuildInfo;
+//!
+//! let build = BuildInfo::current();
+//! assert_eq!(build.version, env!("CARGO_PKG_VERSION"));
+//! assert!(build.to_string().starts_with("timeln "));
+//! ```
+use std::fmt;
+use std::sync::OnceLock;
+
+use crate::report::escape_json;
+
+/// The version of timeln and the build metadata that `--version` prints after it.
+pub fn long_version() -> &'static str {
+    static LONG_VERSION: OnceLock<String> = OnceLock::new();
+    LONG_VERSION.get_or_init(|| {
+        let build = BuildInfo::current().to_string();
+        build.trim_start_matches("timeln ").to_string()
+    })
+}
+
+/// Metadata of a build of timeln.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildInfo {
+    pub version: String,
+    /// Short hash of the commit, or `unknown` if built outside of a git checkout.
+    pub commit: String,
+    /// UTC date of the build, as `YYYY-MM-DD`.
+    pub date: String,
+    /// Target triple, such as `x86_64-unknown-linux-gnu`.
+    pub target: String,
+    /// Enabled cargo features, sorted.
+    pub features: Vec<String>,
+}
+
+impl BuildInfo {
+    /// The build of this timeln.
+    pub fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            commit: env!("TIMELN_GIT_COMMIT").to_string(),
+            date: env!("TIMELN_BUILD_DATE").to_string(),
+            target: env!("TIMELN_TARGET").to_string(),
+            features: split_features(env!("TIMELN_FEATURES")),
+        }
+    }
+
+    /// Formats the build as a JSON object.
+    pub fn to_json(&self) -> String {
+        let features: Vec<String> = self
+            .features
+            .iter()
+            .map(|feature| format!("\"{}\"", escape_json(feature)))
+            .collect();
+        format!(
+            r#"{{"version":"{}","commit":"{}","date":"{}","target":"{}","features":[{}]}}"#,
+            escape_json(&self.version),
+            escape_json(&self.commit),
+            escape_json(&self.date),
+            escape_json(&self.target),
+            features.join(",")
+        )
+    }
+}
+
+/// Prints the build as `--version` does, one field per line after the version, and `none` without
+/// features.
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "timeln {}", self.version)?;
+        writeln!(f, "commit: {}", self.commit)?;
+        writeln!(f, "build date: {}", self.date)?;
+        writeln!(f, "target: {}", self.target)?;
+        if self.features.is_empty() {
+            write!(f, "features: none")
+        } else {
+            write!(f, "features: {}", self.features.join(","))
+        }
+    }
+}
+
+/// Splits a comma-separated list of features, which is empty without features.
+pub(crate) fn split_features(features: &str) -> Vec<String> {
+    features
+        .split(',')
+        .filter(|feature| !feature.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_current_build_is_described() {
+        let build = BuildInfo::current();
+        for field in [&build.version, &build.commit, &build.date, &build.target] {
+            assert!(!field.is_empty());
+        }
+        assert_eq!(build.date.len(), "2024-01-01".len());
+        assert_eq!(build.to_string(), format!("timeln {}", long_version()));
+        let json: serde_json::Value = serde_json::from_str(&build.to_json()).unwrap();
+        assert_eq!(json["commit"], build.commit.as_str());
+        assert_eq!(json["target"], build.target.as_str());
+        assert_eq!(
+            json["features"].as_array().unwrap().len(),
+            build.features.len()
+        );
+    }
+
+    #[test]
+    fn test_split_features() {
+        assert!(split_features("").is_empty());
+        assert_eq!(split_features("notify,sqlite"), ["notify", "sqlite"]);
+    }
+}
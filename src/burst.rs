@@ -0,0 +1,200 @@
+//! This module detects bursts of lines: moments when more than a number of lines arrived within a
+//! window of time, such as a flood of retries or of errors.
+//!
+//! `BurstDetector` counts the lines in the window with the `SlidingWindow` of the rate of lines. A
+//! burst starts when a line brings the count above the threshold, and only ends when a line
+//! arrives with at most half the threshold left in the window. This hysteresis keeps a pace that
+//! hovers around the threshold from being reported as a string of bursts. A burst starts at the
+//! oldest line of the window when it was detected, and lasts until its last line.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::{Duration, Instant};
+//! use timeln::burst::{BurstDetector, BurstThreshold};
+//!
+//! let start = Instant::now();
+//! let threshold = BurstThreshold { lines: 3, window: Duration::from_secs(1) };
+//! let mut detector = BurstDetector::new(threshold, start);
+//! let mut started = Vec::new();
+//! for millis in [0, 100, 200, 300, 400, 5000] {
+//!     started.extend(detector.push(start + Duration::from_millis(millis)));
+//! }
+//! assert_eq!(started.len(), 1);
+//! let bursts = detector.bursts();
+//! assert_eq!(bursts[0].lines, 5);
+//! assert_eq!(bursts[0].duration, Duration::from_millis(400));
+//! ```
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::stats::{window_label, SlidingWindow};
+
+/// More than `lines` lines within `window` make a burst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BurstThreshold {
+    pub lines: usize,
+    pub window: Duration,
+}
+
+impl fmt::Display for BurstThreshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.lines, window_label(&self.window))
+    }
+}
+
+/// A burst of lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Burst {
+    /// Position of the burst in the run, from 1.
+    pub index: usize,
+    /// Time since the start of the run when the first line of the burst arrived.
+    pub start: Duration,
+    /// Time from the first line of the burst to its last line so far.
+    pub duration: Duration,
+    /// Number of lines in the burst so far.
+    pub lines: usize,
+}
+
+/// Detects the bursts of a stream of lines.
+#[derive(Debug)]
+pub struct BurstDetector {
+    threshold: BurstThreshold,
+    window: SlidingWindow,
+    /// Start of the run, from which the bursts are timed.
+    origin: Instant,
+    current: Option<Burst>,
+    ended: Vec<Burst>,
+}
+
+impl BurstDetector {
+    /// Detects the bursts above `threshold` of a run that started at `origin`.
+    pub fn new(threshold: BurstThreshold, origin: Instant) -> Self {
+        Self {
+            threshold,
+            window: SlidingWindow::new(threshold.window),
+            origin,
+            current: None,
+            ended: Vec::new(),
+        }
+    }
+
+    pub fn threshold(&self) -> BurstThreshold {
+        self.threshold
+    }
+
+    /// Adds a line arriving `at`, no earlier than the previous one. Returns the burst it started,
+    /// if any.
+    pub fn push(&mut self, at: Instant) -> Option<Burst> {
+        let count = self.window.push(at);
+        let elapsed = at.saturating_duration_since(self.origin);
+        if let Some(burst) = &mut self.current {
+            if count > (self.threshold.lines / 2).max(1) {
+                burst.lines += 1;
+                burst.duration = elapsed.saturating_sub(burst.start);
+                return None;
+            }
+            self.ended.extend(self.current.take());
+        }
+        if count <= self.threshold.lines {
+            return None;
+        }
+        let start = self.window.oldest().map_or(elapsed, |oldest| {
+            oldest.saturating_duration_since(self.origin)
+        });
+        let burst = Burst {
+            index: self.ended.len() + 1,
+            start,
+            duration: elapsed.saturating_sub(start),
+            lines: count,
+        };
+        self.current = Some(burst.clone());
+        Some(burst)
+    }
+
+    /// The bursts so far, the last of which may still be going on.
+    pub fn bursts(&self) -> Vec<Burst> {
+        self.ended.iter().chain(&self.current).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds lines arriving the given milliseconds after `start` to `detector`, and returns the
+    /// indices of the lines that started a burst.
+    fn feed(detector: &mut BurstDetector, start: Instant, arrivals_ms: &[u64]) -> Vec<usize> {
+        arrivals_ms
+            .iter()
+            .enumerate()
+            .filter_map(|(i, millis)| {
+                detector
+                    .push(start + Duration::from_millis(*millis))
+                    .map(|_| i)
+            })
+            .collect()
+    }
+
+    /// Arrivals `every` milliseconds from `from`, `count` of them.
+    fn paced(from: u64, every: u64, count: u64) -> Vec<u64> {
+        (0..count).map(|i| from + i * every).collect()
+    }
+
+    fn threshold() -> BurstThreshold {
+        BurstThreshold {
+            lines: 5,
+            window: Duration::from_secs(1),
+        }
+    }
+
+    #[test]
+    fn test_threshold_is_exclusive() {
+        let start = Instant::now();
+        // Every 200 ms, exactly 5 lines are within any second: not a burst.
+        let mut detector = BurstDetector::new(threshold(), start);
+        assert!(feed(&mut detector, start, &paced(0, 200, 100)).is_empty());
+        assert!(detector.bursts().is_empty());
+        // Every 190 ms, 6 are: a single burst from the sixth line on, however long it lasts.
+        let mut detector = BurstDetector::new(threshold(), start);
+        assert_eq!(feed(&mut detector, start, &paced(0, 190, 100)), [5]);
+        let bursts = detector.bursts();
+        assert_eq!(bursts.len(), 1);
+        assert_eq!(bursts[0].start, Duration::ZERO);
+        assert_eq!(bursts[0].lines, 100);
+        assert_eq!(bursts[0].duration, Duration::from_millis(99 * 190));
+    }
+
+    #[test]
+    fn test_hysteresis() {
+        let start = Instant::now();
+        let mut detector = BurstDetector::new(threshold(), start);
+        // A burst at 10 lines per second, which slows down to 4 lines per second, below the
+        // threshold but above half of it, then to 2, which ends it once the window only holds
+        // lines at that pace.
+        let mut arrivals = paced(0, 100, 10);
+        arrivals.extend(paced(1150, 250, 8));
+        arrivals.extend(paced(3500, 500, 4));
+        // Then a second burst.
+        arrivals.extend(paced(6000, 100, 10));
+        assert_eq!(feed(&mut detector, start, &arrivals), [5, 27]);
+        let bursts = detector.bursts();
+        assert_eq!(bursts.len(), 2);
+        assert_eq!(bursts[0].index, 1);
+        assert_eq!(bursts[0].lines, 19);
+        assert_eq!(bursts[0].duration, Duration::from_millis(3500));
+        assert_eq!(bursts[1].index, 2);
+        assert_eq!(bursts[1].start, Duration::from_millis(6000));
+        assert_eq!(bursts[1].lines, 10);
+    }
+
+    #[test]
+    fn test_display_threshold() {
+        assert_eq!(threshold().to_string(), "5/1s");
+        let threshold = BurstThreshold {
+            lines: 100,
+            window: Duration::from_millis(500),
+        };
+        assert_eq!(threshold.to_string(), "100/500ms");
+    }
+}
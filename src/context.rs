@@ -0,0 +1,129 @@
+//! This module picks the lines printed around the matches of the regex patterns with `-B` and
+//! `-A`, like `grep`: the lines before each match, kept in a ring buffer as they arrive, and the
+//! lines after it.
+//!
+//! Each line is printed at most once, so that the context of nearby matches is not repeated: a
+//! line after one match and before the next is printed as context of the first, and the buffer
+//! is emptied at each match. The context lines are not timed; they are annotated with their own
+//! arrival times by the caller, which formats them lazily so that lines that are never printed
+//! cost nothing.
+//!
+//! # Example
+//!
+//! ```
+//! use timeln::context::ContextLines;
+//!
+//! let mut context = ContextLines::new(1, 1);
+//! assert_eq!(context.other(|| "a".to_string()), None);
+//! assert_eq!(context.other(|| "b".to_string()), None);
+//! assert_eq!(context.matched(), vec!["b".to_string()]);
+//! assert_eq!(context.other(|| "c".to_string()), Some("c".to_string()));
+//! assert_eq!(context.other(|| "d".to_string()), None);
+//! ```
+use std::collections::VecDeque;
+
+/// Prefix of the matched lines when context is printed.
+pub const MATCH_PREFIX: &str = ":";
+
+/// Prefix of the context lines.
+pub const CONTEXT_PREFIX: &str = "-";
+
+/// The context of the matches still to print.
+#[derive(Debug, Clone, Default)]
+pub struct ContextLines {
+    before: usize,
+    after: usize,
+    /// The latest lines not printed, oldest first, at most `before` of them.
+    buffer: VecDeque<String>,
+    /// Lines still to print after the latest match.
+    after_left: usize,
+}
+
+impl ContextLines {
+    /// Prints `before` lines before each match and `after` lines after it.
+    pub fn new(before: usize, after: usize) -> Self {
+        Self {
+            before,
+            after,
+            buffer: VecDeque::with_capacity(before),
+            after_left: 0,
+        }
+    }
+
+    /// A line that did not match, formatted by `output` if needed: returns it if it is printed now,
+    /// after a match, or else keeps it in case a match follows.
+    pub fn other(&mut self, output: impl FnOnce() -> String) -> Option<String> {
+        if self.after_left > 0 {
+            self.after_left -= 1;
+            return Some(output());
+        }
+        if self.before > 0 {
+            if self.buffer.len() == self.before {
+                self.buffer.pop_front();
+            }
+            self.buffer.push_back(output());
+        }
+        None
+    }
+
+    /// A line that matched: returns the lines before it to print first, oldest first, and starts
+    /// counting the lines after it.
+    pub fn matched(&mut self) -> Vec<String> {
+        self.after_left = self.after;
+        self.buffer.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds the numbered lines to `context`, the matching ones as given, and returns the numbers
+    /// of the lines printed, in order.
+    fn printed(context: &mut ContextLines, lines: usize, matches: &[usize]) -> Vec<usize> {
+        let mut printed = Vec::new();
+        for line in 1..=lines {
+            if matches.contains(&line) {
+                printed.extend(
+                    context
+                        .matched()
+                        .iter()
+                        .map(|text| text.parse::<usize>().unwrap()),
+                );
+                printed.push(line);
+            } else if let Some(text) = context.other(|| line.to_string()) {
+                printed.push(text.parse().unwrap());
+            }
+        }
+        printed
+    }
+
+    #[test]
+    fn test_context_around_match() {
+        let mut context = ContextLines::new(2, 1);
+        assert_eq!(printed(&mut context, 10, &[5]), [3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_overlapping_context_is_printed_once() {
+        let mut context = ContextLines::new(2, 2);
+        // The windows of 3 (1-5) and 6 (4-8) overlap.
+        assert_eq!(printed(&mut context, 10, &[3, 6]), [1, 2, 3, 4, 5, 6, 7, 8]);
+        // Adjacent matches.
+        let mut context = ContextLines::new(1, 1);
+        assert_eq!(printed(&mut context, 6, &[2, 3, 4]), [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_match_near_start() {
+        let mut context = ContextLines::new(3, 0);
+        assert_eq!(printed(&mut context, 4, &[1, 3]), [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_lines_without_context_are_not_formatted() {
+        let mut context = ContextLines::new(0, 0);
+        assert_eq!(context.other(|| unreachable!()), None);
+        assert!(context.matched().is_empty());
+    }
+}
@@ -0,0 +1,269 @@
+//! This module reads timing data written by other tools as CSV, so that it can be replayed,
+//! summarized and plotted like a record file.
+//!
+//! Each row is one line, arriving at the time in its elapsed column, in seconds since the start.
+//! Without an elapsed column, the times are the running sum of the delta column instead. The
+//! columns are numbered from 1, `elapsed=1,delta=2` unless mapped otherwise with `CsvColumns`.
+//! A first row whose columns are not numbers is a header and skipped; any later such row is an
+//! `InvalidData` error naming its line.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use timeln::csv::{CsvColumns, CsvReader};
+//! use timeln::record::Record;
+//!
+//! let data = "step,time\nbuild,1.5\ntest,4\n";
+//! let columns: CsvColumns = "elapsed=2".parse()?;
+//! let records: Vec<Record> = CsvReader::new(data.as_bytes(), columns).collect::<Result<_, _>>()?;
+//! assert_eq!(
+//!     records[1],
+//!     Record::Line {
+//!         elapsed: Duration::from_secs(4),
+//!         stream: None,
+//!         text: "test,4".to_string(),
+//!     }
+//! );
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+use std::io::{self, BufRead};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::record::Record;
+
+/// The columns of a CSV file holding the elapsed and delta times, numbered from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvColumns {
+    pub elapsed: Option<usize>,
+    pub delta: Option<usize>,
+}
+
+impl Default for CsvColumns {
+    fn default() -> Self {
+        Self {
+            elapsed: Some(1),
+            delta: Some(2),
+        }
+    }
+}
+
+impl FromStr for CsvColumns {
+    type Err = String;
+
+    /// Parses a mapping such as `elapsed=2,delta=3`; either column may be left out, not both.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut columns = CsvColumns {
+            elapsed: None,
+            delta: None,
+        };
+        for mapping in s.split(',') {
+            let (name, column) = mapping.split_once('=').ok_or_else(|| {
+                format!("invalid column mapping '{}', expected NAME=COLUMN", mapping)
+            })?;
+            let column = match column.trim().parse() {
+                Ok(column) if column > 0 => column,
+                _ => {
+                    return Err(format!(
+                        "invalid column '{}', expected a number from 1",
+                        column
+                    ))
+                }
+            };
+            match name.trim() {
+                "elapsed" => columns.elapsed = Some(column),
+                "delta" => columns.delta = Some(column),
+                other => {
+                    return Err(format!(
+                        "unknown column '{}', expected elapsed or delta",
+                        other
+                    ))
+                }
+            }
+        }
+        if columns.elapsed.is_none() && columns.delta.is_none() {
+            return Err("map at least one of the elapsed and delta columns".to_string());
+        }
+        Ok(columns)
+    }
+}
+
+/// Reads the rows of a CSV file as the lines of a record file.
+pub struct CsvReader<R> {
+    reader: R,
+    columns: CsvColumns,
+    line_number: usize,
+    /// Elapsed time of the previous row, to which the deltas are added without an elapsed column.
+    elapsed: Duration,
+    buffer: Vec<u8>,
+}
+
+impl<R: BufRead> CsvReader<R> {
+    pub fn new(reader: R, columns: CsvColumns) -> Self {
+        Self {
+            reader,
+            columns,
+            line_number: 0,
+            elapsed: Duration::ZERO,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Number of the last line read, counting from 1.
+    pub fn line_number(&self) -> usize {
+        self.line_number
+    }
+
+    /// The time in seconds in `column` of `fields`.
+    fn seconds(fields: &[&str], column: usize) -> Result<Duration, String> {
+        let field = fields
+            .get(column - 1)
+            .ok_or_else(|| format!("no column {}", column))?;
+        let value = field.trim().trim_matches('"');
+        match value.parse::<f64>() {
+            Ok(seconds) if seconds >= 0.0 && seconds.is_finite() => {
+                Ok(Duration::from_secs_f64(seconds))
+            }
+            _ => Err(format!(
+                "column {} '{}' is not a number of seconds",
+                column, value
+            )),
+        }
+    }
+
+    /// The arrival time of the row with `fields`.
+    fn elapsed(&self, fields: &[&str]) -> Result<Duration, String> {
+        match self.columns {
+            CsvColumns {
+                elapsed: Some(column),
+                ..
+            } => Self::seconds(fields, column),
+            CsvColumns {
+                delta: Some(column),
+                ..
+            } => Ok(self.elapsed + Self::seconds(fields, column)?),
+            _ => unreachable!("CsvColumns maps at least one column"),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for CsvReader<R> {
+    type Item = io::Result<Record>;
+
+    /// Returns the next row as a line, skipping blank lines and a header, or an `InvalidData`
+    /// error naming the line of a malformed row.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.buffer.clear();
+            match self.reader.read_until(b'\n', &mut self.buffer) {
+                Err(err) => return Some(Err(err)),
+                Ok(0) => return None,
+                Ok(_) => {}
+            }
+            self.line_number += 1;
+            let line = String::from_utf8_lossy(&self.buffer);
+            let text = line.trim_end_matches(['\n', '\r']);
+            if text.trim().is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = text.split(',').collect();
+            match self.elapsed(&fields) {
+                Ok(elapsed) => {
+                    self.elapsed = elapsed;
+                    return Some(Ok(Record::Line {
+                        elapsed,
+                        stream: None,
+                        text: text.to_string(),
+                    }));
+                }
+                // Only the first row may be a header.
+                Err(_) if self.line_number == 1 => continue,
+                Err(err) => {
+                    return Some(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("CSV line {}: {}", self.line_number, err),
+                    )))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(data: &str, columns: CsvColumns) -> io::Result<Vec<(Duration, String)>> {
+        CsvReader::new(data.as_bytes(), columns)
+            .map(|record| match record? {
+                Record::Line { elapsed, text, .. } => Ok((elapsed, text)),
+                other => panic!("unexpected record {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_columns() {
+        assert_eq!(
+            "elapsed=2,delta=3".parse(),
+            Ok(CsvColumns {
+                elapsed: Some(2),
+                delta: Some(3)
+            })
+        );
+        assert_eq!(
+            "delta=1".parse(),
+            Ok(CsvColumns {
+                elapsed: None,
+                delta: Some(1)
+            })
+        );
+        assert!("elapsed=0".parse::<CsvColumns>().is_err());
+        assert!("time=1".parse::<CsvColumns>().is_err());
+        assert!("elapsed".parse::<CsvColumns>().is_err());
+    }
+
+    #[test]
+    fn test_header_is_skipped() {
+        let rows = read("elapsed,delta\n0.5,0.5\n1.25,0.75\n", CsvColumns::default()).unwrap();
+        assert_eq!(
+            rows,
+            [
+                (Duration::from_millis(500), "0.5,0.5".to_string()),
+                (Duration::from_millis(1250), "1.25,0.75".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_without_header() {
+        let rows = read("a,1\r\n\r\nb,3\n", "elapsed=2".parse().unwrap()).unwrap();
+        assert_eq!(
+            rows,
+            [
+                (Duration::from_secs(1), "a,1".to_string()),
+                (Duration::from_secs(3), "b,3".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deltas_are_summed_without_elapsed() {
+        let rows = read("x,0.5\ny,\"1.5\"\n", "delta=2".parse().unwrap()).unwrap();
+        let times: Vec<Duration> = rows.into_iter().map(|(elapsed, _)| elapsed).collect();
+        assert_eq!(times, [Duration::from_millis(500), Duration::from_secs(2)]);
+    }
+
+    #[test]
+    fn test_bad_row_names_its_line() {
+        let err = read("elapsed\n1\n2\noops\n3\n", "elapsed=1".parse().unwrap()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "CSV line 4: column 1 'oops' is not a number of seconds"
+        );
+        let err = read("1,2\n3\n", "delta=2".parse().unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "CSV line 2: no column 2");
+    }
+}
@@ -0,0 +1,391 @@
+//! This module draws a live panel at the bottom of the terminal with `--dashboard`, while the
+//! annotated lines scroll above it:
+//!
+//! ```text
+//! [time: 12.20 s, delta: 0.42 s] Compiling foo
+//! ────────────────────────────────────────────────────────────
+//! Elapsed: 12.30 s │ Rate 10s: 8.0 lines/s │ Matches: 3
+//! Last deltas: 0.42 s, 0.10 s, 0.05 s
+//! ```
+//!
+//! The bottom `DASHBOARD_ROWS` rows of the terminal are taken out of its scroll region, so that
+//! the lines printed to stdout scroll above them, and the panel is redrawn in them every
+//! `STATUS_INTERVAL` with the cursor saved and restored around it. When the run ends, before its
+//! summary, the panel is cleared and the scroll region reset to the whole terminal.
+//!
+//! The escape sequences of each step are built by pure functions (`setup`, `draw` and
+//! `teardown`), and the rows of the panel by `layout`, which returns `None` for a terminal too
+//! small to keep enough rows for the lines.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use timeln::dashboard::{layout, DashboardStats};
+//! use timeln::SecondsFormat;
+//!
+//! let stats = DashboardStats {
+//!     elapsed: Duration::from_secs(12),
+//!     matches: 3,
+//!     ..DashboardStats::default()
+//! };
+//! let rows = layout(60, 24, &stats, &SecondsFormat).unwrap();
+//! assert_eq!(rows[1], "Elapsed: 12.00 s │ Rate: - │ Matches: 3");
+//! assert!(layout(60, 6, &stats, &SecondsFormat).is_none());
+//! ```
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::formatter::TimeFormat;
+use crate::progress::Progress;
+use crate::stats::window_label;
+
+/// Rows of the terminal taken by the panel.
+pub const DASHBOARD_ROWS: usize = 3;
+
+/// Fewest rows left above the panel for the lines; on a shorter terminal there is no panel.
+pub const MIN_SCROLL_ROWS: usize = 5;
+
+/// Fewest columns the panel is drawn in.
+pub const MIN_WIDTH: usize = 20;
+
+/// Number of latest deltas shown in the panel.
+pub const DASHBOARD_DELTAS: usize = 3;
+
+/// What the panel shows.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DashboardStats {
+    /// Time since the start of the run.
+    pub elapsed: Duration,
+    /// Length of the sliding window of the rate and the lines per second that arrived in it, if
+    /// known.
+    pub rate: Option<(Duration, f64)>,
+    /// The latest deltas, newest first.
+    pub recent_deltas: Vec<Duration>,
+    /// Lines that matched a regex pattern.
+    pub matches: u64,
+    /// The progress towards the matches expected with `--total-matches`, if given.
+    pub progress: Option<Progress>,
+}
+
+/// Cuts `row` to at most `width` characters.
+pub(crate) fn fit(row: String, width: usize) -> String {
+    match row.char_indices().nth(width) {
+        Some((end, _)) => row[..end].to_string(),
+        None => row,
+    }
+}
+
+/// The rows of the panel showing `stats` on a terminal of `width` columns and `height` rows, top
+/// first, each cut to the width; or `None` if the terminal is too small for the panel.
+pub fn layout(
+    width: usize,
+    height: usize,
+    stats: &DashboardStats,
+    time_format: &dyn TimeFormat,
+) -> Option<[String; DASHBOARD_ROWS]> {
+    if width < MIN_WIDTH || height < DASHBOARD_ROWS + MIN_SCROLL_ROWS {
+        return None;
+    }
+    let rate = match stats.rate {
+        Some((window, rate)) => format!("Rate {}: {:.1} lines/s", window_label(&window), rate),
+        None => "Rate: -".to_string(),
+    };
+    let matches = match stats.progress {
+        Some(progress) => progress.label(time_format),
+        None => stats.matches.to_string(),
+    };
+    let deltas = if stats.recent_deltas.is_empty() {
+        "-".to_string()
+    } else {
+        stats
+            .recent_deltas
+            .iter()
+            .map(|delta| time_format.format_duration(delta))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    Some([
+        "─".repeat(width),
+        fit(
+            format!(
+                "Elapsed: {} │ {} │ Matches: {}",
+                time_format.format_duration(&stats.elapsed),
+                rate,
+                matches
+            ),
+            width,
+        ),
+        fit(format!("Last deltas: {}", deltas), width),
+    ])
+}
+
+/// Makes room for the panel at the bottom of a terminal of `height` rows and keeps the lines out
+/// of it, leaving the cursor where the next line is printed.
+pub fn setup(height: usize) -> String {
+    format!(
+        "{}\x1b[{}A\x1b7\x1b[1;{}r\x1b8",
+        "\n".repeat(DASHBOARD_ROWS),
+        DASHBOARD_ROWS,
+        height - DASHBOARD_ROWS
+    )
+}
+
+/// Draws the `rows` of the panel at the bottom of a terminal of `height` rows, putting the cursor
+/// back where it was.
+pub fn draw(height: usize, rows: &[String]) -> String {
+    let first = height - DASHBOARD_ROWS + 1;
+    let mut frame = "\x1b7".to_string();
+    for (i, row) in rows.iter().enumerate() {
+        frame.push_str(&format!("\x1b[{};1H\x1b[2K{}", first + i, row));
+    }
+    frame.push_str("\x1b8");
+    frame
+}
+
+/// Clears the panel at the bottom of a terminal of `height` rows and gives the whole terminal
+/// back to the lines, putting the cursor back where it was.
+pub fn teardown(height: usize) -> String {
+    let first = height - DASHBOARD_ROWS + 1;
+    let mut frame = "\x1b7".to_string();
+    for row in first..=height {
+        frame.push_str(&format!("\x1b[{};1H\x1b[2K", row));
+    }
+    frame.push_str("\x1b[r\x1b8");
+    frame
+}
+
+/// The size of the terminal of stdout, in columns and rows, if stdout is a terminal.
+pub fn terminal_size() -> Option<(usize, usize)> {
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    #[cfg(unix)]
+    {
+        // SAFETY: winsize is a plain C struct, fully written by the ioctl before it is read.
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        // SAFETY: TIOCGWINSZ writes a winsize to the valid pointer given.
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0
+            && size.ws_col > 0
+            && size.ws_row > 0
+        {
+            return Some((size.ws_col as usize, size.ws_row as usize));
+        }
+    }
+    let var = |name: &str| std::env::var(name).ok()?.parse::<usize>().ok();
+    Some((var("COLUMNS")?, var("LINES")?))
+}
+
+/// Redraws the panel from a background thread, until it is cleared.
+#[derive(Debug, Default)]
+pub struct Dashboard {
+    /// Rows of the terminal the panel was set up for.
+    height: usize,
+    stop: Arc<AtomicBool>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Dashboard {
+    /// A panel for a terminal of `width` columns and `height` rows, or `None` if the terminal is
+    /// too small for it.
+    pub fn new(width: usize, height: usize) -> Option<Self> {
+        layout(width, height, &DashboardStats::default(), &NoFormat)?;
+        Some(Self {
+            height,
+            ..Self::default()
+        })
+    }
+
+    /// Sets up the panel on `out`, which the lines are also written to, and draws it every
+    /// `interval` with the stats returned by `scrape`, cut to the `width` of the terminal, until
+    /// `clear` is called or `scrape` returns `None`. A frame is skipped while `out` is in use.
+    pub fn start(
+        &self,
+        out: Arc<Mutex<Box<dyn Write + Send>>>,
+        width: usize,
+        interval: Duration,
+        time_format: Arc<Box<dyn TimeFormat>>,
+        scrape: impl Fn() -> Option<DashboardStats> + Send + 'static,
+    ) {
+        let stop = Arc::clone(&self.stop);
+        let height = self.height;
+        if let Ok(mut out) = out.lock() {
+            // The panel shares the output with the lines, whose own writes report it if it breaks.
+            let _ = write!(out, "{}", setup(height));
+            let _ = out.flush();
+        }
+        let handle = thread::spawn(move || {
+            while !stop.load(Ordering::Acquire) {
+                let Some(stats) = scrape() else {
+                    break;
+                };
+                // Waiting for the output could deadlock with the finalizer clearing the panel.
+                if let (Some(rows), Ok(mut out)) = (
+                    layout(width, height, &stats, &**time_format),
+                    out.try_lock(),
+                ) {
+                    let _ = write!(out, "{}", draw(height, &rows));
+                    let _ = out.flush();
+                }
+                thread::park_timeout(interval);
+            }
+        });
+        if let Ok(mut thread) = self.thread.lock() {
+            *thread = Some(handle);
+        }
+    }
+
+    /// Stops drawing, waiting for the thread drawing the panel, and clears it on `out`, giving
+    /// the whole terminal back to the lines. Does nothing if the panel was not started or is
+    /// already cleared.
+    pub fn clear(&self, out: &mut dyn Write) {
+        self.stop.store(true, Ordering::Release);
+        let handle = match self.thread.lock() {
+            Ok(mut thread) => thread.take(),
+            Err(_) => None,
+        };
+        if let Some(handle) = handle {
+            handle.thread().unpark();
+            let _ = handle.join();
+            let _ = write!(out, "{}", teardown(self.height));
+            let _ = out.flush();
+        }
+    }
+}
+
+/// Formats no duration, to check the size of the terminal before there are stats.
+struct NoFormat;
+
+impl TimeFormat for NoFormat {
+    fn format_duration(&self, _duration: &Duration) -> String {
+        String::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatter::SecondsFormat;
+
+    fn stats() -> DashboardStats {
+        DashboardStats {
+            elapsed: Duration::from_millis(12_300),
+            rate: Some((Duration::from_secs(10), 8.0)),
+            recent_deltas: [420, 100, 50].map(Duration::from_millis).to_vec(),
+            matches: 3,
+            progress: None,
+        }
+    }
+
+    #[test]
+    fn test_layout() {
+        let rows = layout(60, 24, &stats(), &SecondsFormat).unwrap();
+        assert_eq!(
+            rows,
+            [
+                "─".repeat(60),
+                "Elapsed: 12.30 s │ Rate 10s: 8.0 lines/s │ Matches: 3".to_string(),
+                "Last deltas: 0.42 s, 0.10 s, 0.05 s".to_string(),
+            ]
+        );
+        let empty = layout(60, 24, &DashboardStats::default(), &SecondsFormat).unwrap();
+        assert_eq!(empty[1], "Elapsed: 0.00 s │ Rate: - │ Matches: 0");
+        assert_eq!(empty[2], "Last deltas: -");
+        let progress = DashboardStats {
+            progress: Some(Progress {
+                matches: 3,
+                total: 10,
+                eta: Some(Duration::from_secs(28)),
+            }),
+            ..stats()
+        };
+        let rows = layout(100, 24, &progress, &SecondsFormat).unwrap();
+        assert_eq!(
+            rows[1],
+            "Elapsed: 12.30 s │ Rate 10s: 8.0 lines/s │ Matches: [######--------------] 3/10, ETA: 28.00 s"
+        );
+    }
+
+    #[test]
+    fn test_layout_cuts_rows_to_width() {
+        let rows = layout(24, 24, &stats(), &SecondsFormat).unwrap();
+        assert_eq!(rows[0], "─".repeat(24));
+        assert_eq!(rows[1], "Elapsed: 12.30 s │ Rate ");
+        assert_eq!(rows[2], "Last deltas: 0.42 s, 0.1");
+    }
+
+    #[test]
+    fn test_layout_needs_room() {
+        assert!(layout(MIN_WIDTH - 1, 24, &stats(), &SecondsFormat).is_none());
+        assert!(layout(
+            80,
+            DASHBOARD_ROWS + MIN_SCROLL_ROWS - 1,
+            &stats(),
+            &SecondsFormat
+        )
+        .is_none());
+        assert!(layout(
+            MIN_WIDTH,
+            DASHBOARD_ROWS + MIN_SCROLL_ROWS,
+            &stats(),
+            &SecondsFormat
+        )
+        .is_some());
+        assert!(Dashboard::new(80, 4).is_none());
+    }
+
+    #[test]
+    fn test_escape_sequences() {
+        assert_eq!(setup(24), "\n\n\n\x1b[3A\x1b7\x1b[1;21r\x1b8");
+        let rows = ["-".to_string(), "a".to_string(), "b".to_string()];
+        assert_eq!(
+            draw(24, &rows),
+            "\x1b7\x1b[22;1H\x1b[2K-\x1b[23;1H\x1b[2Ka\x1b[24;1H\x1b[2Kb\x1b8"
+        );
+        assert_eq!(
+            teardown(24),
+            "\x1b7\x1b[22;1H\x1b[2K\x1b[23;1H\x1b[2K\x1b[24;1H\x1b[2K\x1b[r\x1b8"
+        );
+    }
+
+    /// A writer whose contents can be read while it is shared with the dashboard.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_dashboard_draws_then_tears_down() {
+        let written = Shared::default();
+        let out: Arc<Mutex<Box<dyn Write + Send>>> =
+            Arc::new(Mutex::new(Box::new(written.clone())));
+        let dashboard = Dashboard::new(60, 24).unwrap();
+        dashboard.start(
+            Arc::clone(&out),
+            60,
+            Duration::from_millis(5),
+            Arc::new(Box::new(SecondsFormat)),
+            || Some(stats()),
+        );
+        thread::sleep(Duration::from_millis(30));
+        dashboard.clear(&mut **out.lock().unwrap());
+        let text = String::from_utf8(written.0.lock().unwrap().clone()).unwrap();
+        assert!(text.starts_with(&setup(24)), "{:?}", text);
+        assert!(text.contains("Last deltas: 0.42 s, 0.10 s, 0.05 s"));
+        assert!(text.ends_with(&teardown(24)), "{:?}", text);
+        // Clearing again does nothing.
+        dashboard.clear(&mut **out.lock().unwrap());
+        assert_eq!(written.0.lock().unwrap().len(), text.len());
+    }
+}
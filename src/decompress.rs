@@ -0,0 +1,352 @@
+//! This module decompresses the input on the fly when it is gzip- or zstd-compressed, such as a
+//! rotated log (`app.log.3.gz`) read with `--file` or compressed data piped into timeln.
+//!
+//! The format is told by the magic bytes at the start of the input, not by the name of the file,
+//! and only once the first line is read, so that waiting for the input does not delay the start of
+//! the run. Anything else is read as it is. Decompressing gzip needs the `gzip` cargo feature, and
+//! zstd the `zstd` feature; without them, compressed input fails with an error naming the feature.
+//!
+//! The decompressed stream is a `BufRead` like any other, read by `BufReadData` as a
+//! `DecompressReadData`. Corrupt data ends
+//! the run with an error naming the input and how far into the compressed data it was found.
+//!
+//! # Example
+//!
+//! ```
+//! use std::io::BufRead;
+//! use timeln::decompress::{Compression, Decompress};
+//!
+//! assert_eq!(Compression::detect(b"\x1f\x8b\x08\x00"), Some(Compression::Gzip));
+//! assert_eq!(Compression::detect(b"plain text\n"), None);
+//!
+//! let mut plain = Decompress::new(&b"plain text\n"[..], "stdin");
+//! let mut line = String::new();
+//! plain.read_line(&mut line).unwrap();
+//! assert_eq!(line, "plain text\n");
+//! ```
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::error::TimelnError;
+use crate::reader::BufReadData;
+
+/// The compression formats read by `Decompress`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// The format of data starting with `magic`, if it is compressed. `magic` may be shorter than
+    /// the magic bytes only at the end of the input.
+    pub fn detect(magic: &[u8]) -> Option<Self> {
+        if magic.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// The name of the format, which is also that of the cargo feature that reads it.
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+}
+
+/// Reads a stream, decompressing it if it starts with the magic bytes of a compression format.
+pub struct Decompress {
+    /// The stream as given, until its format is detected.
+    source: Option<Box<dyn BufRead + Send>>,
+    /// The stream as read, once its format is detected.
+    reader: Option<Box<dyn BufRead + Send>>,
+    /// What the stream is read from, named in the errors.
+    name: String,
+}
+
+impl Decompress {
+    /// Reads `source`, named `name` in the errors, decompressing it if needed.
+    pub fn new(source: impl BufRead + Send + 'static, name: impl Into<String>) -> Self {
+        Self {
+            source: Some(Box::new(source)),
+            reader: None,
+            name: name.into(),
+        }
+    }
+
+    /// The stream as read, detecting its format first if it is not yet known.
+    fn reader(&mut self) -> io::Result<&mut Box<dyn BufRead + Send>> {
+        if self.reader.is_none() {
+            let Some(mut source) = self.source.take() else {
+                return Err(io::Error::other("the input could not be decompressed"));
+            };
+            let reader = match Compression::detect(source.fill_buf()?) {
+                Some(compression) => decoder(compression, source, &self.name)?,
+                None => source,
+            };
+            self.reader = Some(reader);
+        }
+        Ok(self.reader.as_mut().expect("the reader was just set"))
+    }
+}
+
+impl Read for Decompress {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader()?.read(buf)
+    }
+}
+
+impl BufRead for Decompress {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.reader()?.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        if let Some(reader) = &mut self.reader {
+            reader.consume(amount);
+        }
+    }
+}
+
+/// Reads the lines of a stream that may be compressed.
+pub type DecompressReadData = BufReadData<Decompress>;
+
+impl DecompressReadData {
+    /// Creates a reader of the lines of standard input, decompressing it if needed.
+    pub fn stdin() -> Self {
+        BufReadData::new(
+            Decompress::new(BufReader::new(io::stdin()), "stdin"),
+            "stdin",
+        )
+    }
+
+    /// Opens the file at `path` to read its lines, decompressing it if needed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, TimelnError> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|err| TimelnError::input(path, err))?;
+        let name = path.display().to_string();
+        Ok(BufReadData::new(
+            Decompress::new(BufReader::new(file), name.clone()),
+            name,
+        ))
+    }
+}
+
+/// Counts the bytes of a stream that were consumed, to tell where its decompression failed.
+struct Counted<R> {
+    inner: R,
+    consumed: Arc<AtomicU64>,
+}
+
+impl<R: BufRead> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.consumed.fetch_add(read as u64, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for Counted<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.consumed.fetch_add(amount as u64, Ordering::Relaxed);
+        self.inner.consume(amount);
+    }
+}
+
+/// Decompressed data, whose errors tell how far into the compressed data they happened.
+struct Decoded<D> {
+    decoder: D,
+    compression: Compression,
+    consumed: Arc<AtomicU64>,
+}
+
+impl<D: Read> Read for Decoded<D> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.decoder.read(buf).map_err(|err| {
+            let offset = self.consumed.load(Ordering::Relaxed);
+            io::Error::new(
+                err.kind(),
+                format!(
+                    "corrupt {} data after byte {}: {}",
+                    self.compression.name(),
+                    offset,
+                    err
+                ),
+            )
+        })
+    }
+}
+
+/// The decoder of `source`, compressed in `compression`, or `None` if timeln was built without
+/// the feature that reads it.
+fn open_decoder(
+    compression: Compression,
+    source: Counted<Box<dyn BufRead + Send>>,
+) -> io::Result<Option<Box<dyn Read + Send>>> {
+    match compression {
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => Ok(Some(Box::new(flate2::bufread::MultiGzDecoder::new(source)))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Ok(Some(Box::new(zstd::stream::read::Decoder::with_buffer(
+            source,
+        )?))),
+        #[allow(unreachable_patterns)]
+        _ => {
+            drop(source);
+            Ok(None)
+        }
+    }
+}
+
+/// Wraps `source`, compressed in `compression`, in its decoder.
+fn decoder(
+    compression: Compression,
+    source: Box<dyn BufRead + Send>,
+    name: &str,
+) -> io::Result<Box<dyn BufRead + Send>> {
+    let consumed = Arc::new(AtomicU64::new(0));
+    let counted = Counted {
+        inner: source,
+        consumed: Arc::clone(&consumed),
+    };
+    let Some(decoder) = open_decoder(compression, counted)? else {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "{} is {}-compressed, but timeln was built without the {} feature",
+                name,
+                compression.name(),
+                compression.name()
+            ),
+        ));
+    };
+    Ok(Box::new(BufReader::new(Decoded {
+        decoder,
+        compression,
+        consumed,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::ReadData;
+
+    /// A log of a few lines, as read after decompression.
+    const LOG: &str = "starting\nfetching deps\nbuilding\nlast line without newline";
+
+    /// The lines read from `reader`.
+    fn read_lines(reader: &mut dyn ReadData) -> Result<Vec<String>, crate::TimelnError> {
+        let mut lines = Vec::new();
+        let mut buf = String::new();
+        while reader.read_line(&mut buf)? > 0 {
+            lines.push(std::mem::take(&mut buf));
+        }
+        Ok(lines)
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Compression::detect(b"\x1f\x8b"), Some(Compression::Gzip));
+        assert_eq!(
+            Compression::detect(b"\x28\xb5\x2f\xfd\x00"),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(Compression::detect(b"\x1f"), None);
+        assert_eq!(Compression::detect(b""), None);
+    }
+
+    #[test]
+    fn test_plain_input_is_read_as_is() {
+        let mut reader = BufReadData::new(Decompress::new(LOG.as_bytes(), "app.log"), "app.log");
+        let lines = read_lines(&mut reader).unwrap();
+        assert_eq!(lines.concat(), LOG);
+        assert_eq!(lines.len(), LOG.lines().count());
+
+        let mut empty = BufReadData::new(Decompress::new(&b""[..], "empty.log"), "empty.log");
+        assert!(read_lines(&mut empty).unwrap().is_empty());
+    }
+
+    #[cfg(not(feature = "gzip"))]
+    #[test]
+    fn test_gzip_needs_feature() {
+        let gzip = &b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x00\x03"[..];
+        let mut reader = BufReadData::new(Decompress::new(gzip, "app.log.gz"), "app.log.gz");
+        let err = read_lines(&mut reader).unwrap_err().to_string();
+        assert!(err.starts_with("could not read app.log.gz: "), "{}", err);
+        assert!(err.contains("without the gzip feature"), "{}", err);
+    }
+
+    #[cfg(feature = "gzip")]
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip_lines_match_original() {
+        let compressed = gzip(LOG.as_bytes());
+        let mut reader = BufReadData::new(
+            Decompress::new(io::Cursor::new(compressed), "app.log.gz"),
+            "app.log.gz",
+        );
+        let lines = read_lines(&mut reader).unwrap();
+        assert_eq!(lines.len(), LOG.lines().count());
+        assert_eq!(lines.concat(), LOG);
+
+        // Concatenated members, as written by `cat a.gz b.gz`, are read one after the other.
+        let mut twice = gzip(b"a\n");
+        twice.extend(gzip(b"b\n"));
+        let mut reader =
+            BufReadData::new(Decompress::new(io::Cursor::new(twice), "ab.gz"), "ab.gz");
+        assert_eq!(read_lines(&mut reader).unwrap(), ["a\n", "b\n"]);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_corrupt_gzip_names_file_and_offset() {
+        let mut compressed = gzip(LOG.repeat(100).as_bytes());
+        let middle = compressed.len() / 2;
+        for byte in &mut compressed[middle..middle + 16] {
+            *byte = !*byte;
+        }
+        let mut reader = BufReadData::new(
+            Decompress::new(io::Cursor::new(compressed), "app.log.gz"),
+            "app.log.gz",
+        );
+        let err = read_lines(&mut reader).unwrap_err().to_string();
+        assert!(
+            err.starts_with("could not read app.log.gz: corrupt gzip data after byte "),
+            "{}",
+            err
+        );
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_lines_match_original() {
+        let compressed = zstd::stream::encode_all(LOG.as_bytes(), 0).unwrap();
+        let mut reader = BufReadData::new(
+            Decompress::new(io::Cursor::new(compressed), "app.log.zst"),
+            "app.log.zst",
+        );
+        let lines = read_lines(&mut reader).unwrap();
+        assert_eq!(lines.len(), LOG.lines().count());
+        assert_eq!(lines.concat(), LOG);
+    }
+}
@@ -0,0 +1,111 @@
+//! This module shows desktop notifications, when a run finishes and on the first line matching a
+//! pattern, for long runs left in the background. Showing them needs the `desktop` cargo feature.
+//!
+//! A notification that cannot be shown, as on a system without a notification server, is only
+//! logged to stderr: it never affects the run. The notification of a match is shown from a
+//! background thread, so as not to slow the run down.
+//!
+//! # Example
+//!
+//! ```
+//! use regex::Regex;
+//! use timeln::desktop::DesktopNotifier;
+//!
+//! let notifier = DesktopNotifier::new(Some(Regex::new("ERROR").unwrap()));
+//! assert!(!notifier.first_match("INFO starting"));
+//! assert!(notifier.first_match("ERROR disk full"));
+//! // Only the first match is notified.
+//! assert!(!notifier.first_match("ERROR disk still full"));
+//! ```
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use regex::Regex;
+
+/// Shows the desktop notifications of a run.
+#[derive(Debug)]
+pub struct DesktopNotifier {
+    on_match: Option<Regex>,
+    matched: AtomicBool,
+}
+
+impl DesktopNotifier {
+    /// Creates a notifier also notifying of the first line matching `on_match`, if given.
+    pub fn new(on_match: Option<Regex>) -> Self {
+        Self {
+            on_match,
+            matched: AtomicBool::new(false),
+        }
+    }
+
+    /// The pattern whose first match is notified, if any.
+    pub fn pattern(&self) -> Option<&str> {
+        self.on_match.as_ref().map(Regex::as_str)
+    }
+
+    /// Whether `line` is the first line matching the pattern, to be notified.
+    pub fn first_match(&self, line: &str) -> bool {
+        match &self.on_match {
+            Some(regex) if !self.matched.load(Ordering::Relaxed) && regex.is_match(line) => {
+                !self.matched.swap(true, Ordering::AcqRel)
+            }
+            _ => false,
+        }
+    }
+
+    /// Shows `message`, waiting for it to be shown.
+    pub fn finished(&self, message: &str) {
+        if let Err(err) = show(message) {
+            eprintln!("timeln: could not show a desktop notification: {}", err);
+        }
+    }
+
+    /// Shows `message` in the background.
+    pub fn matched(&self, message: String) {
+        thread::spawn(move || {
+            if let Err(err) = show(&message) {
+                eprintln!("timeln: could not show a desktop notification: {}", err);
+            }
+        });
+    }
+}
+
+/// Shows the desktop notification `message`.
+#[cfg(feature = "desktop")]
+fn show(message: &str) -> Result<(), String> {
+    notify_rust::Notification::new()
+        .appname("timeln")
+        .summary(message)
+        .show()
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+/// Fails, as timeln was built without the `desktop` feature.
+#[cfg(not(feature = "desktop"))]
+fn show(_: &str) -> Result<(), String> {
+    Err("timeln was built without the desktop feature".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_without_pattern() {
+        let notifier = DesktopNotifier::new(None);
+        assert_eq!(notifier.pattern(), None);
+        assert!(!notifier.first_match("ERROR"));
+    }
+
+    #[test]
+    fn test_first_match_only() {
+        let notifier = DesktopNotifier::new(Some(Regex::new("fail(ed)?").unwrap()));
+        assert_eq!(notifier.pattern(), Some("fail(ed)?"));
+        let matches: Vec<bool> = ["ok", "failed", "ok", "fail"]
+            .iter()
+            .map(|line| notifier.first_match(line))
+            .collect();
+        assert_eq!(matches, [false, true, false, false]);
+    }
+}
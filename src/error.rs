@@ -1,15 +1,15 @@
 //! This module defines the `TimelnError` enum and its associated conversions, which represent the various types of errors that can occur within the timeln module.
 //!
-//! The `TimelnError` enum encapsulates different error types, including `std::io::Error`, `regex::Error`, `SendError<TimeSnapshot>`, `PoisonError<MutexGuard<'_, T>>`, and `Box<dyn std::error::Error>`. These error types cover common scenarios encountered in the timeln module, such as I/O errors, regular expression errors, channel send errors, mutex poisoning errors, and generic boxed errors.
+//! The `TimelnError` enum encapsulates different error types, including `std::io::Error`, `regex::Error`, `PoisonError<MutexGuard<'_, T>>`, and `Box<dyn std::error::Error + Send + Sync>`, so that it can be sent between threads. The errors about a regex pattern, an input or output file, the timed command, a plot, the plot data, the report or a record file carry the pattern, path or command that failed, so that the message names it and the binary can exit with a status specific to the kind of failure.
 //!
-//! The `From` trait is implemented for each error type, allowing easy conversion of these errors into the `TimelnError` enum. This enables consistent error handling and propagation within the timeln module, simplifying error management for the caller.
+//! The `From` trait is implemented for `std::io::Error`, mutex poisoning errors and boxed errors, allowing easy conversion of these errors into the `TimelnError` enum. The other variants are built with their context where the error happens, for example with `TimelnError::regex` or `TimelnError::output`.
 //!
 //! # Examples
 //!
 //! Converting a `std::io::Error` into a `TimelnError`:
 //!
 //! ```
-//! use crate::TimelnError;
+//! use timeln::TimelnError;
 //!
 //! let io_error = std::io::Error::new(std::io::ErrorKind::Other, "Custom I/O Error");
 //! let timeln_error: TimelnError = io_error.into();
@@ -17,23 +17,6 @@
 //! println!("Converted TimelnError: {:?}", timeln_error);
 //! ```
 //!
-//! Handling a `SendError<TimeSnapshot>` and converting it to `TimelnError`:
-//!
-//! ```
-//! use crate::{TimelnError, timeln::TimeSnapshot};
-//! use std::sync::mpsc::channel;
-//!
-//! let (sender, receiver) = channel::<TimeSnapshot>();
-//!
-//! // Attempt to send a TimeSnapshot
-//! let snapshot = TimeSnapshot::new();
-//! let send_result = sender.send(snapshot);
-//!
-//! let timeln_error: TimelnError = send_result.err().unwrap().into();
-//!
-//! println!("Converted TimelnError: {:?}", timeln_error);
-//! ```
-//!
 //! # Error Handling
 //!
 //! When interacting with the timeln module, it is important to handle errors appropriately. The `TimelnError` enum provides a comprehensive set of error types that can occur within the module, allowing for granular error handling based on the specific error scenario.
@@ -42,46 +25,225 @@
 //!
 //! The `TimelnError` enum and its conversions are specific to the timeln module and may require additional error handling and customization for your application's specific needs.
 //!
-use std::sync::{mpsc::SendError, MutexGuard, PoisonError};
-
-use crate::timeln::TimeSnapshot;
+use std::path::Path;
+use std::sync::{MutexGuard, PoisonError};
 
 /// This enum defines the various types of errors that could occur within the timeln module.
+///
+/// The errors about a file, a pattern or a command name it, so that the message says which one
+/// failed; `Io` is left for reading stdin and writing stdout.
 #[derive(Debug)]
 pub enum TimelnError {
     Io(std::io::Error),
-    Regex(regex::Error),
-    SendError(SendError<TimeSnapshot>),
+    /// Options that cannot be used together, or that do not fit each other.
+    Usage(String),
+    RegexCompile {
+        pattern: String,
+        source: regex::Error,
+    },
+    /// A duration that cannot be parsed, with why, naming the invalid part.
+    InvalidDuration {
+        input: String,
+        reason: String,
+    },
+    /// Every invalid pattern of a run with more than one, so that they can be fixed at once.
+    MultiplePatternErrors(Vec<PatternError>),
+    /// Opening or reading an input file.
+    InputIo {
+        path: String,
+        source: std::io::Error,
+    },
+    /// Creating or writing an output file other than a plot.
+    OutputIo {
+        path: String,
+        source: std::io::Error,
+    },
+    /// Spawning or waiting for the command whose output is timed.
+    Command {
+        command: String,
+        source: std::io::Error,
+    },
+    /// Drawing or writing a plot.
+    PlotRender {
+        path: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// Writing the data of the plots, with `--plot-data`.
+    PlotData {
+        path: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// Writing the HTML report, with `--report`.
+    Report {
+        path: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// A malformed line of a record file.
+    RecordParse {
+        path: String,
+        line: usize,
+        source: std::io::Error,
+    },
     MutexPoisonedError(String),
-    BoxError(Box<dyn std::error::Error>),
+    BoxError(Box<dyn std::error::Error + Send + Sync>),
 }
 
-/// Implementations of From trait for TimelnError.
+/// A pattern that failed to compile, with the option it was given to.
+#[derive(Debug)]
+pub struct PatternError {
+    /// The option the pattern was given to, such as `--regex`.
+    pub flag: String,
+    pub pattern: String,
+    pub source: regex::Error,
+}
 
-impl From<std::io::Error> for TimelnError {
-    /// Converts a `std::io::Error` into a `TimelnError`.
-    fn from(err: std::io::Error) -> Self {
-        TimelnError::Io(err)
+impl std::fmt::Display for PatternError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} '{}': ", self.flag, self.pattern)?;
+        // The regex crate points at the error under the pattern, on lines of their own.
+        let source = self.source.to_string();
+        for (i, line) in source.lines().enumerate() {
+            if i > 0 {
+                write!(f, "\n    ")?;
+            }
+            write!(f, "{}", line)?;
+        }
+        Ok(())
     }
 }
 
-impl From<regex::Error> for TimelnError {
-    /// Converts a `regex::Error` into a TimelnError.
-    fn from(err: regex::Error) -> Self {
-        TimelnError::Regex(err)
+impl TimelnError {
+    /// Wraps the error of compiling `pattern`.
+    pub fn regex(pattern: &str, source: regex::Error) -> Self {
+        TimelnError::RegexCompile {
+            pattern: pattern.to_string(),
+            source,
+        }
+    }
+
+    /// Wraps an I/O error on the input file `path`.
+    pub fn input(path: impl AsRef<Path>, source: std::io::Error) -> Self {
+        TimelnError::InputIo {
+            path: path.as_ref().display().to_string(),
+            source,
+        }
+    }
+
+    /// Wraps an I/O error on the output file `path`.
+    pub fn output(path: impl AsRef<Path>, source: std::io::Error) -> Self {
+        TimelnError::OutputIo {
+            path: path.as_ref().display().to_string(),
+            source,
+        }
+    }
+
+    /// Wraps an error drawing or writing the plot file `path`.
+    pub fn plot(path: &str, source: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        TimelnError::PlotRender {
+            path: path.to_string(),
+            source: source.into(),
+        }
+    }
+
+    /// Wraps an error writing the plot data file `path`.
+    pub fn plot_data(
+        path: &str,
+        source: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+    ) -> Self {
+        TimelnError::PlotData {
+            path: path.to_string(),
+            source: source.into(),
+        }
+    }
+
+    /// Wraps an error writing the report file `path`.
+    pub fn report(path: &str, source: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        TimelnError::Report {
+            path: path.to_string(),
+            source: source.into(),
+        }
     }
 }
 
-impl From<SendError<TimeSnapshot>> for TimelnError {
-    /// Converts a `SendError<TimeSnapshot>` into a `TimelnError`.
-    fn from(err: SendError<TimeSnapshot>) -> Self {
-        TimelnError::SendError(err)
+impl std::fmt::Display for TimelnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimelnError::Io(err) => write!(f, "I/O error: {}", err),
+            TimelnError::Usage(msg) => write!(f, "{}", msg),
+            TimelnError::RegexCompile { pattern, source } => {
+                write!(f, "invalid regex '{}': {}", pattern, source)
+            }
+            TimelnError::InvalidDuration { input, reason } => {
+                write!(f, "invalid duration '{}': {}", input, reason)
+            }
+            TimelnError::MultiplePatternErrors(errors) => {
+                write!(f, "{} invalid regex patterns:", errors.len())?;
+                for error in errors {
+                    write!(f, "\n  {}", error)?;
+                }
+                Ok(())
+            }
+            TimelnError::InputIo { path, source } => {
+                write!(f, "could not read {}: {}", path, source)
+            }
+            TimelnError::OutputIo { path, source } => {
+                write!(f, "could not write {}: {}", path, source)
+            }
+            TimelnError::Command { command, source } => {
+                write!(f, "could not run {}: {}", command, source)
+            }
+            TimelnError::PlotRender { path, source } => {
+                write!(f, "could not write plot {}: {}", path, source)
+            }
+            TimelnError::PlotData { path, source } => {
+                write!(f, "could not write plot data {}: {}", path, source)
+            }
+            TimelnError::Report { path, source } => {
+                write!(f, "could not write report {}: {}", path, source)
+            }
+            // The error of a malformed record already names its line.
+            TimelnError::RecordParse { path, source, .. } => {
+                write!(f, "invalid record file {}: {}", path, source)
+            }
+            TimelnError::MutexPoisonedError(msg) => write!(f, "{}", msg),
+            TimelnError::BoxError(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for TimelnError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TimelnError::Io(source)
+            | TimelnError::InputIo { source, .. }
+            | TimelnError::OutputIo { source, .. }
+            | TimelnError::Command { source, .. }
+            | TimelnError::RecordParse { source, .. } => Some(source),
+            TimelnError::RegexCompile { source, .. } => Some(source),
+            TimelnError::PlotRender { source, .. }
+            | TimelnError::PlotData { source, .. }
+            | TimelnError::Report { source, .. }
+            | TimelnError::BoxError(source) => Some(source.as_ref()),
+            TimelnError::Usage(_)
+            | TimelnError::InvalidDuration { .. }
+            | TimelnError::MultiplePatternErrors(_)
+            | TimelnError::MutexPoisonedError(_) => None,
+        }
     }
 }
 
-impl From<Box<dyn std::error::Error>> for TimelnError {
-    /// Converts a `Box<dyn std::error::Error>` into a `TimelnError`.
-    fn from(err: Box<dyn std::error::Error>) -> Self {
+// Implementations of From trait for TimelnError.
+
+impl From<std::io::Error> for TimelnError {
+    /// Converts a `std::io::Error` into a `TimelnError`.
+    fn from(err: std::io::Error) -> Self {
+        TimelnError::Io(err)
+    }
+}
+
+impl From<Box<dyn std::error::Error + Send + Sync>> for TimelnError {
+    /// Converts a `Box<dyn std::error::Error + Send + Sync>` into a `TimelnError`.
+    fn from(err: Box<dyn std::error::Error + Send + Sync>) -> Self {
         TimelnError::BoxError(err)
     }
 }
@@ -101,7 +263,7 @@ mod tests {
     #[test]
     fn test_conversion_from_io_error() {
         // Arrange
-        let io_error = io::Error::new(io::ErrorKind::Other, "Custom I/O Error");
+        let io_error = io::Error::other("Custom I/O Error");
 
         // Act
         let timeln_error: TimelnError = io_error.into();
@@ -114,27 +276,34 @@ mod tests {
     }
 
     #[test]
-    fn test_conversion_from_send_error() {
-        // Arrange
-        let (sender, receiver) = std::sync::mpsc::channel::<TimeSnapshot>();
-        let snapshot = TimeSnapshot::default();
+    fn test_contextual_errors_name_what_failed() {
+        let err = TimelnError::plot(
+            "missing/deltas.svg",
+            io::Error::from(io::ErrorKind::NotFound),
+        );
+        assert!(err
+            .to_string()
+            .starts_with("could not write plot missing/deltas.svg: "));
+        assert!(std::error::Error::source(&err).is_some());
 
-        // Close the receiver end of the channel
-        drop(receiver);
+        let err = TimelnError::report(
+            "missing/report.html",
+            io::Error::from(io::ErrorKind::NotFound),
+        );
+        assert!(err
+            .to_string()
+            .starts_with("could not write report missing/report.html: "));
 
-        // Attempt to send a TimeSnapshot (receiver dropped intentionally)
-        let send_result = sender.send(snapshot);
+        let err =
+            TimelnError::plot_data("missing/data.csv", io::Error::from(io::ErrorKind::NotFound));
+        assert!(err
+            .to_string()
+            .starts_with("could not write plot data missing/data.csv: "));
 
-        // Act
-        let timeln_error: TimelnError = match send_result {
-            Err(err) => err.into(),
-            Ok(_) => panic!("Expected SendError, but send operation succeeded."),
-        };
+        let err = TimelnError::input("app.log", io::Error::from(io::ErrorKind::NotFound));
+        assert!(err.to_string().starts_with("could not read app.log: "));
 
-        // Assert
-        match timeln_error {
-            TimelnError::SendError(_) => assert!(true),
-            _ => panic!("Expected TimelnError::SendError, but got a different variant."),
-        }
+        let err = TimelnError::regex("(", regex::Error::Syntax("unclosed group".to_string()));
+        assert_eq!(err.to_string(), "invalid regex '(': unclosed group");
     }
 }
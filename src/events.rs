@@ -0,0 +1,415 @@
+//! This module writes the events of a run as newline-delimited JSON, for supervisors and wrapper
+//! scripts that want a machine-readable channel separate from the human output.
+//!
+//! The events mirror the records of a record file, with the same fields, so that one parser can
+//! handle both:
+//!
+//! - `{"event": "started", "version": 2, "start_unix_ms": .., "build": {..}, "argv": [..]}`,
+//!   first. The build has the `version`, `commit`, `date`, `target` and `features` of timeln.
+//! - `{"event": "line", "elapsed_ns": .., "stream": "o" | "e" | null, "text": ".."}` for each
+//!   input line. When the rate of lines is measured, the event also has the lines per second that
+//!   arrived in the sliding window ending with it, as `"rate_10s": ..` for a window of 10 s.
+//! - `{"event": "interim-summary", "lap": .., "lines": .., "matches": .., "duration_ns": ..}`
+//!   at the end of each lap of `--reset-on`.
+//! - `{"event": "finished", "lines": .., "total_ns": ..}`, last, once the run finished.
+//!
+//! The events are queued and written by a background thread, so that a slow consumer does not
+//! slow the run down until the queue is full. Then the run waits for the consumer, or with
+//! `drop_oldest` the oldest queued events are dropped to make room and counted.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use timeln::events::{record_event, EventWriter};
+//! use timeln::record::Record;
+//!
+//! let line = Record::Line {
+//!     elapsed: Duration::from_millis(1500),
+//!     stream: None,
+//!     text: "done".to_string(),
+//! };
+//! let event = record_event(&line);
+//! assert_eq!(
+//!     event,
+//!     r#"{"event":"line","elapsed_ns":1500000000,"stream":null,"text":"done"}"#
+//! );
+//! let writer = EventWriter::new(std::io::sink(), 1024, false);
+//! writer.send(event);
+//! assert_eq!(writer.finish(), 0);
+//! ```
+use std::collections::VecDeque;
+use std::io::{self, BufWriter, Write};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::build_info::BuildInfo;
+use crate::reader::Stream;
+use crate::record::Record;
+use crate::report::escape_json;
+use crate::stats::window_label;
+use crate::summarizer::Lap;
+
+/// Number of events queued for a slow consumer, unless changed.
+pub const DEFAULT_EVENT_BUFFER: usize = 1024;
+
+/// Formats a record as an event.
+pub fn record_event(record: &Record) -> String {
+    match record {
+        Record::Header {
+            version,
+            start_unix_ms,
+            build,
+            argv,
+        } => {
+            let build = build
+                .as_ref()
+                .map_or_else(|| "null".to_string(), BuildInfo::to_json);
+            let argv: Vec<String> = argv
+                .iter()
+                .map(|arg| format!("\"{}\"", escape_json(arg)))
+                .collect();
+            format!(
+                r#"{{"event":"started","version":{},"start_unix_ms":{},"build":{},"argv":[{}]}}"#,
+                version,
+                start_unix_ms,
+                build,
+                argv.join(",")
+            )
+        }
+        Record::Line {
+            elapsed,
+            stream,
+            text,
+        } => {
+            let stream = match stream {
+                Some(Stream::Stdout) => "\"o\"",
+                Some(Stream::Stderr) => "\"e\"",
+                None => "null",
+            };
+            format!(
+                r#"{{"event":"line","elapsed_ns":{},"stream":{},"text":"{}"}}"#,
+                elapsed.as_nanos(),
+                stream,
+                escape_json(text)
+            )
+        }
+        Record::Footer { lines, total } => format!(
+            r#"{{"event":"finished","lines":{},"total_ns":{}}}"#,
+            lines,
+            total.as_nanos()
+        ),
+    }
+}
+
+/// Formats a record as an event, with the lines per second in the sliding `window` ending with it
+/// as one more field named after the window.
+pub fn record_event_with_rate(record: &Record, window: &Duration, rate: f64) -> String {
+    let event = record_event(record);
+    let fields = event.strip_suffix('}').unwrap_or(&event);
+    format!("{},\"rate_{}\":{}}}", fields, window_label(window), rate)
+}
+
+/// Formats the summary of a lap that ended as an event.
+pub fn lap_event(lap: &Lap) -> String {
+    format!(
+        r#"{{"event":"interim-summary","lap":{},"lines":{},"matches":{},"duration_ns":{}}}"#,
+        lap.number,
+        lap.lines,
+        lap.matches,
+        lap.duration.as_nanos()
+    )
+}
+
+/// Events waiting for the writer thread.
+#[derive(Debug, Default)]
+struct Queue {
+    events: VecDeque<String>,
+    /// Whether no more events will be sent.
+    closed: bool,
+    /// Whether writing failed, after which events are discarded.
+    failed: bool,
+    /// Number of events dropped to make room for newer ones.
+    dropped: u64,
+}
+
+/// Writes events, one per line, to an output from a background thread.
+#[derive(Debug)]
+pub struct EventWriter {
+    queue: Mutex<Queue>,
+    /// Signalled when an event is queued or the queue is closed.
+    ready: Condvar,
+    /// Signalled when the writer thread makes room in the queue.
+    room: Condvar,
+    capacity: usize,
+    drop_oldest: bool,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl EventWriter {
+    /// Starts writing the events sent to `out`, queueing at most `capacity` of them; once the queue
+    /// is full, `send` waits for room, or with `drop_oldest` drops the oldest queued event.
+    pub fn new(out: impl Write + Send + 'static, capacity: usize, drop_oldest: bool) -> Arc<Self> {
+        let writer = Arc::new(Self {
+            queue: Mutex::new(Queue::default()),
+            ready: Condvar::new(),
+            room: Condvar::new(),
+            capacity: capacity.max(1),
+            drop_oldest,
+            thread: Mutex::new(None),
+        });
+        let thread = {
+            let writer = Arc::clone(&writer);
+            thread::spawn(move || writer.write_all(BufWriter::new(out)))
+        };
+        *writer.lock_thread() = Some(thread);
+        writer
+    }
+
+    /// Starts writing the events sent to the open file descriptor `fd`, which is duplicated so
+    /// that it stays open for the rest of the process. Fails if `fd` is not open.
+    #[cfg(unix)]
+    pub fn open_fd(fd: i32, capacity: usize, drop_oldest: bool) -> io::Result<Arc<Self>> {
+        use std::fs::File;
+        use std::os::unix::io::FromRawFd;
+
+        // SAFETY: fcntl has no memory safety preconditions; it fails with EBADF if `fd` is not
+        // open. The duplicate is owned by the returned file alone.
+        let duplicate = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 3) };
+        if duplicate < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let file = unsafe { File::from_raw_fd(duplicate) };
+        Ok(Self::new(file, capacity, drop_oldest))
+    }
+
+    /// Queues an event, waiting for room if the queue is full unless dropping the oldest events.
+    pub fn send(&self, event: String) {
+        let mut queue = self.lock_queue();
+        while queue.events.len() >= self.capacity && !queue.failed && !self.drop_oldest {
+            queue = self.room.wait(queue).unwrap_or_else(|err| err.into_inner());
+        }
+        if queue.failed || queue.closed {
+            return;
+        }
+        if queue.events.len() >= self.capacity {
+            queue.events.pop_front();
+            queue.dropped += 1;
+        }
+        queue.events.push_back(event);
+        self.ready.notify_one();
+    }
+
+    /// Writes the queued events and stops the writer thread. Returns the number of events dropped
+    /// to make room for newer ones.
+    pub fn finish(&self) -> u64 {
+        self.lock_queue().closed = true;
+        self.ready.notify_one();
+        if let Some(thread) = self.lock_thread().take() {
+            // The writer thread only panics if the output does.
+            let _ = thread.join();
+        }
+        self.lock_queue().dropped
+    }
+
+    // A panic while holding the locks leaves the queue consistent, so poisoning is ignored.
+    fn lock_queue(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn lock_thread(&self) -> MutexGuard<'_, Option<JoinHandle<()>>> {
+        self.thread.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Writes the queued events to `out` until the queue is closed and empty, flushing whenever
+    /// the queue runs empty. A failed write is reported once and discards the later events.
+    fn write_all(&self, mut out: impl Write) {
+        loop {
+            let mut queue = self.lock_queue();
+            while queue.events.is_empty() && !queue.closed {
+                queue = self
+                    .ready
+                    .wait(queue)
+                    .unwrap_or_else(|err| err.into_inner());
+            }
+            if queue.events.is_empty() {
+                if let Err(err) = out.flush() {
+                    eprintln!("timeln: could not write the events: {}", err);
+                }
+                return;
+            }
+            let events: Vec<String> = queue.events.drain(..).collect();
+            drop(queue);
+            self.room.notify_all();
+            let result = events
+                .iter()
+                .try_for_each(|event| writeln!(out, "{}", event))
+                .and_then(|_| {
+                    if self.lock_queue().events.is_empty() {
+                        out.flush()
+                    } else {
+                        Ok(())
+                    }
+                });
+            if let Err(err) = result {
+                eprintln!(
+                    "timeln: could not write the events, no longer writing them: {}",
+                    err
+                );
+                let mut queue = self.lock_queue();
+                queue.failed = true;
+                queue.events.clear();
+                drop(queue);
+                self.room.notify_all();
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    /// An output shared with the test, that can be held up to simulate a slow consumer.
+    #[derive(Clone, Default)]
+    struct SlowOutput {
+        written: Arc<Mutex<Vec<u8>>>,
+        gate: Arc<Mutex<()>>,
+        /// Whether a write was attempted.
+        writing: Arc<AtomicBool>,
+    }
+
+    impl Write for SlowOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.writing.store(true, Ordering::SeqCst);
+            let _gate = self.gate.lock().unwrap();
+            self.written.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SlowOutput {
+        fn lines(&self) -> Vec<String> {
+            let written = self.written.lock().unwrap();
+            String::from_utf8_lossy(&written)
+                .lines()
+                .map(str::to_string)
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_events_are_json() {
+        let header = Record::Header {
+            version: 2,
+            start_unix_ms: 42,
+            build: Some(BuildInfo::current()),
+            argv: vec!["timeln".to_string(), "-r".to_string(), "a\"b".to_string()],
+        };
+        let line = Record::Line {
+            elapsed: Duration::from_nanos(7),
+            stream: Some(Stream::Stderr),
+            text: "tab\there".to_string(),
+        };
+        let footer = Record::Footer {
+            lines: 3,
+            total: Duration::from_secs(2),
+        };
+        let lap = Lap {
+            number: 2,
+            lines: 5,
+            matches: 1,
+            duration: Duration::from_millis(3),
+        };
+        let events: Vec<serde_json::Value> = [
+            record_event(&header),
+            record_event(&line),
+            lap_event(&lap),
+            record_event_with_rate(&line, &Duration::from_secs(10), 0.8),
+            record_event(&footer),
+        ]
+        .iter()
+        .map(|event| serde_json::from_str(event).unwrap())
+        .collect();
+        assert_eq!(events[0]["event"], "started");
+        assert_eq!(events[0]["argv"][2], "a\"b");
+        assert_eq!(events[0]["build"]["version"], env!("CARGO_PKG_VERSION"));
+        assert!(!events[0]["build"]["commit"].as_str().unwrap().is_empty());
+        assert_eq!(events[1]["event"], "line");
+        assert_eq!(events[1]["elapsed_ns"], 7);
+        assert_eq!(events[1]["stream"], "e");
+        assert_eq!(events[1]["text"], "tab\there");
+        assert_eq!(events[2]["event"], "interim-summary");
+        assert_eq!(events[2]["duration_ns"], 3_000_000);
+        assert_eq!(events[3]["event"], "line");
+        assert_eq!(events[3]["text"], "tab\there");
+        assert_eq!(events[3]["rate_10s"], 0.8);
+        assert_eq!(events[4]["event"], "finished");
+        assert_eq!(events[4]["total_ns"], 2_000_000_000u64);
+    }
+
+    #[test]
+    fn test_slow_consumer_waits_by_default() {
+        let out = SlowOutput::default();
+        let writer = EventWriter::new(out.clone(), 2, false);
+        let gate = out.gate.lock().unwrap();
+        let sender = {
+            let writer = Arc::clone(&writer);
+            thread::spawn(move || (0..10).for_each(|i| writer.send(i.to_string())))
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert!(!sender.is_finished());
+        drop(gate);
+        sender.join().unwrap();
+        assert_eq!(writer.finish(), 0);
+        let expected: Vec<String> = (0..10).map(|i| i.to_string()).collect();
+        assert_eq!(out.lines(), expected);
+    }
+
+    #[test]
+    fn test_slow_consumer_drops_oldest() {
+        let out = SlowOutput::default();
+        let writer = EventWriter::new(out.clone(), 2, true);
+        let gate = out.gate.lock().unwrap();
+        writer.send("first".to_string());
+        // Wait for the writer thread to be held at the gate with the first event.
+        while !out.writing.load(Ordering::SeqCst) {
+            thread::yield_now();
+        }
+        (0..10).for_each(|i| writer.send(i.to_string()));
+        drop(gate);
+        assert_eq!(writer.finish(), 8);
+        assert_eq!(out.lines(), ["first", "8", "9"]);
+    }
+
+    #[test]
+    fn test_failed_output_discards_events() {
+        struct Closed;
+        impl Write for Closed {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+        }
+        let writer = EventWriter::new(Closed, 1, false);
+        // Sending must not wait for room forever once the writer has given up.
+        (0..100).for_each(|i| writer.send(i.to_string()));
+        assert_eq!(writer.finish(), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_closed_fd_fails() {
+        assert!(EventWriter::open_fd(9999, 1, false).is_err());
+    }
+}
@@ -0,0 +1,220 @@
+//! This module extracts numeric values, such as a training loss, from the text of the lines.
+//!
+//! An `Extractor` is a regex whose named capture groups are parsed as floats on every line it
+//! matches, each group accumulating a `ValueSeries`. A pattern without named groups captures its
+//! first group, or the whole match if it has none, under the name of the pattern. A capture that is
+//! not a number is counted as unparsed and otherwise ignored. `Extractor::key` extracts the value
+//! of a key of a logfmt log instead.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use timeln::extract::Extractor;
+//!
+//! let mut extractor = Extractor::new(r"loss=(?P<loss>[0-9.]+)")?;
+//! extractor.line("step 1 loss=0.5", Duration::from_secs(1));
+//! extractor.line("step 2 loss=0.25", Duration::from_secs(2));
+//! let loss = &extractor.series()[0];
+//! assert_eq!(loss.name, "loss");
+//! assert_eq!(loss.last, Some(0.25));
+//! assert_eq!(loss.stats.max(), Some(0.5));
+//! # Ok::<(), regex::Error>(())
+//! ```
+use std::time::Duration;
+
+use regex::Regex;
+
+use crate::logfmt::{self, Pair};
+use crate::stats::RunningStats;
+
+/// The values of a capture group over the run.
+#[derive(Debug, Clone, Default)]
+pub struct ValueSeries {
+    /// Name of the capture group.
+    pub name: String,
+    /// Each value, with the time of its line since the start of the run in seconds.
+    pub points: Vec<(f64, f64)>,
+    /// Statistics of the values.
+    pub stats: RunningStats,
+    /// The most recent value.
+    pub last: Option<f64>,
+    /// Number of captures that could not be parsed as a number.
+    pub unparsed: usize,
+}
+
+impl ValueSeries {
+    /// Creates an empty series named `name`.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Parses `text`, captured from a line that arrived `at` since the start of the run, and adds
+    /// its value to the series.
+    pub fn push(&mut self, text: &str, at: Duration) {
+        match text.trim().parse::<f64>() {
+            Ok(value) if value.is_finite() => {
+                self.points.push((at.as_secs_f64(), value));
+                self.stats.push(value);
+                self.last = Some(value);
+            }
+            _ => self.unparsed += 1,
+        }
+    }
+}
+
+/// Extracts the values captured by a regex from the lines.
+#[derive(Debug)]
+pub struct Extractor {
+    /// `None` for an extractor of a logfmt key, the name of its only series.
+    regex: Option<Regex>,
+    /// The index of each extracted capture group, parallel to `series`.
+    groups: Vec<usize>,
+    series: Vec<ValueSeries>,
+}
+
+impl Extractor {
+    /// Creates an extractor of the named capture groups of `pattern`.
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        let regex = Regex::new(pattern)?;
+        let (groups, series) = regex
+            .capture_names()
+            .enumerate()
+            .filter_map(|(index, name)| Some((index, ValueSeries::new(name?))))
+            .unzip::<_, _, Vec<_>, Vec<_>>();
+        let (groups, series) = if groups.is_empty() {
+            let group = if regex.captures_len() > 1 { 1 } else { 0 };
+            (vec![group], vec![ValueSeries::new(pattern)])
+        } else {
+            (groups, series)
+        };
+        Ok(Self {
+            regex: Some(regex),
+            groups,
+            series,
+        })
+    }
+
+    /// Creates an extractor of the values of the key `name` of a logfmt log.
+    pub fn key(name: &str) -> Self {
+        Self {
+            regex: None,
+            groups: Vec::new(),
+            series: vec![ValueSeries::new(name)],
+        }
+    }
+
+    /// Adds the values captured from `line`, which arrived `at` since the start of the run.
+    /// Groups that did not participate in the match are skipped.
+    pub fn line(&mut self, line: &str, at: Duration) {
+        let Some(captures) = self.regex.as_ref().and_then(|regex| regex.captures(line)) else {
+            return;
+        };
+        for (group, series) in self.groups.iter().zip(&mut self.series) {
+            if let Some(capture) = captures.get(*group) {
+                series.push(capture.as_str(), at);
+            }
+        }
+    }
+
+    /// Adds the value of the key from a line of a logfmt log with `pairs`, which arrived `at` since
+    /// the start of the run, if extracting a key and the line has it.
+    pub fn pairs(&mut self, pairs: &[Pair], at: Duration) {
+        if self.regex.is_some() {
+            return;
+        }
+        let series = &mut self.series[0];
+        if let Some(value) = logfmt::get(pairs, &series.name) {
+            series.push(value, at);
+        }
+    }
+
+    /// The series of each extracted capture group, in the order of the groups.
+    pub fn series(&self) -> &[ValueSeries] {
+        &self.series
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extract(pattern: &str, lines: &[&str]) -> Extractor {
+        let mut extractor = Extractor::new(pattern).unwrap();
+        for (i, line) in lines.iter().enumerate() {
+            extractor.line(line, Duration::from_secs(i as u64));
+        }
+        extractor
+    }
+
+    #[test]
+    fn test_extracts_well_formed_values() {
+        let extractor = extract(
+            r"loss=(?P<loss>\S+)",
+            &["loss=0.5", "no value", "loss=1e-2", "loss=0.25"],
+        );
+        let loss = &extractor.series()[0];
+        assert_eq!(loss.points, vec![(0.0, 0.5), (2.0, 0.01), (3.0, 0.25)]);
+        assert_eq!(loss.stats.min(), Some(0.01));
+        assert_eq!(loss.stats.max(), Some(0.5));
+        assert_eq!(loss.last, Some(0.25));
+        assert_eq!(loss.unparsed, 0);
+    }
+
+    #[test]
+    fn test_counts_malformed_values() {
+        let extractor = extract(
+            r"loss=(?P<loss>\S+)",
+            &["loss=0.5", "loss=nan", "loss=abc", "loss=0.1.2"],
+        );
+        let loss = &extractor.series()[0];
+        assert_eq!(loss.stats.count(), 1);
+        assert_eq!(loss.unparsed, 3);
+    }
+
+    #[test]
+    fn test_extracts_each_named_group() {
+        let extractor = extract(
+            r"loss=(?P<loss>[0-9.]+)(?: acc=(?P<acc>[0-9.]+))?",
+            &["loss=0.5 acc=0.75", "loss=0.25"],
+        );
+        let names: Vec<&str> = extractor.series().iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["loss", "acc"]);
+        assert_eq!(extractor.series()[0].stats.count(), 2);
+        assert_eq!(extractor.series()[1].points, vec![(0.0, 0.75)]);
+    }
+
+    #[test]
+    fn test_extracts_key() {
+        let mut extractor = Extractor::key("loss");
+        for (i, line) in [
+            "step=1 loss=0.5",
+            "step=2",
+            "step=3 loss=\"0.25\"",
+            "loss=high",
+        ]
+        .iter()
+        .enumerate()
+        {
+            extractor.line(line, Duration::from_secs(i as u64));
+            extractor.pairs(&logfmt::parse(line), Duration::from_secs(i as u64));
+        }
+        let loss = &extractor.series()[0];
+        assert_eq!(loss.name, "loss");
+        assert_eq!(loss.points, vec![(0.0, 0.5), (2.0, 0.25)]);
+        assert_eq!(loss.unparsed, 1);
+    }
+
+    #[test]
+    fn test_unnamed_pattern_uses_first_group() {
+        let extractor = extract(r"took (\d+)ms", &["took 12ms"]);
+        assert_eq!(extractor.series()[0].name, r"took (\d+)ms");
+        assert_eq!(extractor.series()[0].last, Some(12.0));
+
+        let extractor = extract(r"\d+", &["42 lines"]);
+        assert_eq!(extractor.series()[0].last, Some(42.0));
+    }
+}
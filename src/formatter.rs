@@ -1,5 +1,17 @@
 use std::time::Duration;
 
+use crate::error::TimelnError;
+
+/// The units of the durations parsed by `parse_duration`, with their lengths in nanoseconds.
+const DURATION_UNITS: [(&str, u128); 6] = [
+    ("ns", 1),
+    ("us", 1_000),
+    ("ms", 1_000_000),
+    ("s", 1_000_000_000),
+    ("m", 60_000_000_000),
+    ("h", 3_600_000_000_000),
+];
+
 /// The `TimeFormat` trait provides an abstraction over formatting of `Duration`s.
 /// Implementations of `TimeFormat` provide methods to convert `Duration` into a human-readable string format.
 pub trait TimeFormat: Send + Sync {
@@ -54,10 +66,204 @@ impl TimeFormat for MinutesSecondsFormat {
     }
 }
 
+/// The highest rate shown by `RateFormat`, in Hz, to which the rates of deltas that are zero or
+/// too short to tell apart are clamped.
+pub const MAX_RATE_HZ: f64 = 1e6;
+
+/// The `RateFormat` struct is an implementation of the `TimeFormat` trait that formats a delta as
+/// the rate it stands for, `1/delta`, in Hz below 1000 Hz and in kHz above. Deltas shorter than a
+/// microsecond, including zero, are shown as `MAX_RATE_HZ` rather than as an infinite rate.
+#[derive(Debug, Clone, Copy)]
+pub struct RateFormat;
+
+impl RateFormat {
+    /// The rate of `duration`, in Hz, clamped to `MAX_RATE_HZ`.
+    pub fn hz(duration: &Duration) -> f64 {
+        let seconds = duration.as_secs_f64();
+        if seconds * MAX_RATE_HZ <= 1.0 {
+            MAX_RATE_HZ
+        } else {
+            1.0 / seconds
+        }
+    }
+
+    /// Formats a rate of `hz`, as `RateFormat` does that of a delta.
+    pub fn format_hz(hz: f64) -> String {
+        if hz < 1000.0 {
+            format!("{:.2} Hz", hz)
+        } else {
+            format!("{:.2} kHz", hz / 1000.0)
+        }
+    }
+}
+
+impl TimeFormat for RateFormat {
+    /// Takes a `Duration` and formats it into a `String` representation of its rate.
+    fn format_duration(&self, duration: &Duration) -> String {
+        Self::format_hz(Self::hz(duration))
+    }
+}
+
+/// Parses a duration such as `250ms`, `1.5s`, `5m` or `1m30s`: one or more numbers, each with
+/// one of the units `ns`, `us`, `ms`, `s`, `m` and `h`, added up; a bare number is in seconds.
+/// Each unit is given at most once, from the longest to the shortest.
+///
+/// The duration is computed exactly, without rounding the decimals through floating point, and
+/// must fit the nanosecond counters of a run, of about 584 years. The error names the part of
+/// `s` that is invalid, and its position.
+pub fn parse_duration(s: &str) -> Result<Duration, TimelnError> {
+    let invalid = |reason: String| TimelnError::InvalidDuration {
+        input: s.to_string(),
+        reason,
+    };
+    if s.is_empty() {
+        return Err(invalid("expected e.g. 500ms, 30s or 1m30s".to_string()));
+    }
+    if s.starts_with('-') {
+        return Err(invalid("negative durations are not allowed".to_string()));
+    }
+    let is_number = |c: char| c.is_ascii_digit() || c == '.';
+    let mut nanos: u128 = 0;
+    // The unit of the previous part, which the next must be shorter than.
+    let mut previous: Option<(&str, u128)> = None;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let position = s.len() - rest.len() + 1;
+        let split = rest.find(|c| !is_number(c)).unwrap_or(rest.len());
+        let (number, after) = rest.split_at(split);
+        let split = after.find(is_number).unwrap_or(after.len());
+        let (unit, after) = after.split_at(split);
+        if number.is_empty() {
+            return Err(invalid(format!(
+                "missing number before '{}' at position {}",
+                unit, position
+            )));
+        }
+        let unit_nanos = match DURATION_UNITS.iter().find(|(name, _)| *name == unit) {
+            Some((_, unit_nanos)) => *unit_nanos,
+            // Only a duration that is a single number can leave out its unit.
+            None if unit.is_empty() && position == 1 => 1_000_000_000,
+            None if unit.is_empty() => {
+                return Err(invalid(format!(
+                    "missing unit after '{}' at position {}",
+                    number, position
+                )))
+            }
+            None => {
+                return Err(invalid(format!(
+                    "unknown unit '{}' at position {}, expected ns, us, ms, s, m or h",
+                    unit,
+                    position + number.len()
+                )))
+            }
+        };
+        match previous {
+            Some((previous, _)) if previous == unit => {
+                return Err(invalid(format!(
+                    "repeated unit '{}' at position {}",
+                    unit,
+                    position + number.len()
+                )))
+            }
+            Some((previous, previous_nanos)) if previous_nanos < unit_nanos => {
+                return Err(invalid(format!(
+                    "unit '{}' at position {} must come before '{}'",
+                    unit,
+                    position + number.len(),
+                    previous
+                )))
+            }
+            _ => previous = Some((unit, unit_nanos)),
+        }
+        let too_long = || {
+            invalid(format!(
+                "'{}{}' at position {} is too long, the longest is about 584 years",
+                number, unit, position
+            ))
+        };
+        let part = number_nanos(number, unit_nanos)
+            .ok_or_else(|| {
+                invalid(format!(
+                    "invalid number '{}' at position {}",
+                    number, position
+                ))
+            })?
+            .ok_or_else(too_long)?;
+        nanos = nanos
+            .checked_add(part)
+            .filter(|nanos| *nanos <= u64::MAX as u128)
+            .ok_or_else(too_long)?;
+        rest = after;
+    }
+    Ok(Duration::from_nanos(nanos as u64))
+}
+
+/// The nanoseconds in `number` of units of `unit_nanos` nanoseconds, truncated, or `None` if
+/// `number` is not a decimal number; `Some(None)` if they overflow.
+fn number_nanos(number: &str, unit_nanos: u128) -> Option<Option<u128>> {
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if (whole.is_empty() && fraction.is_empty()) || fraction.contains('.') {
+        return None;
+    }
+    let digits = |digits: &str| {
+        digits.bytes().try_fold(0u128, |value, digit| {
+            value.checked_mul(10)?.checked_add((digit - b'0') as u128)
+        })
+    };
+    let Some(whole) = digits(whole) else {
+        return Some(None);
+    };
+    // Digits beyond the 20th are below the nanosecond of every unit.
+    let fraction = &fraction[..fraction.len().min(20)];
+    let scale = 10u128.pow(fraction.len() as u32);
+    let fraction = digits(fraction)? * unit_nanos / scale;
+    Some(
+        whole
+            .checked_mul(unit_nanos)
+            .and_then(|whole| whole.checked_add(fraction)),
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_rate_format() {
+        let format = RateFormat;
+        assert_eq!(
+            format.format_duration(&Duration::from_millis(500)),
+            "2.00 Hz"
+        );
+        assert_eq!(format.format_duration(&Duration::from_secs(4)), "0.25 Hz");
+        // The unit switches to kHz at 1000 Hz.
+        assert_eq!(
+            format.format_duration(&Duration::from_micros(1001)),
+            "999.00 Hz"
+        );
+        assert_eq!(
+            format.format_duration(&Duration::from_millis(1)),
+            "1.00 kHz"
+        );
+        assert_eq!(
+            format.format_duration(&Duration::from_micros(400)),
+            "2.50 kHz"
+        );
+    }
+
+    #[test]
+    fn test_rate_format_clamps_short_deltas() {
+        let format = RateFormat;
+        assert_eq!(RateFormat::hz(&Duration::ZERO), MAX_RATE_HZ);
+        assert_eq!(RateFormat::hz(&Duration::from_nanos(1)), MAX_RATE_HZ);
+        assert_eq!(RateFormat::hz(&Duration::from_micros(1)), MAX_RATE_HZ);
+        assert_eq!(format.format_duration(&Duration::ZERO), "1000.00 kHz");
+        assert_eq!(
+            format.format_duration(&Duration::from_micros(2)),
+            "500.00 kHz"
+        );
+    }
+
     #[test]
     fn test_seconds_format() {
         let format = SecondsFormat;
@@ -78,4 +284,118 @@ mod tests {
         let duration = Duration::new(125, 0); // 125 seconds = 2 minutes and 5 seconds
         assert_eq!(format.format_duration(&duration), "2m 5s");
     }
+
+    #[test]
+    fn test_parse_duration_units() {
+        let cases = [
+            ("250ns", Duration::from_nanos(250)),
+            ("250us", Duration::from_micros(250)),
+            ("250ms", Duration::from_millis(250)),
+            ("30s", Duration::from_secs(30)),
+            ("5m", Duration::from_secs(300)),
+            ("1h", Duration::from_secs(3600)),
+            ("0s", Duration::ZERO),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_duration(input).unwrap(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_duration_decimals_and_bare_numbers() {
+        assert_eq!(parse_duration("1.5s").unwrap(), Duration::from_millis(1500));
+        assert_eq!(parse_duration("1.5").unwrap(), Duration::from_millis(1500));
+        assert_eq!(parse_duration("30").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration(".5s").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("2.s").unwrap(), Duration::from_secs(2));
+        assert_eq!(parse_duration("0.1h").unwrap(), Duration::from_secs(360));
+        // Exact, where floating point would give 299999999ns.
+        assert_eq!(parse_duration("0.3s").unwrap(), Duration::from_millis(300));
+        // Below a nanosecond is truncated.
+        assert_eq!(parse_duration("1.9ns").unwrap(), Duration::from_nanos(1));
+        assert_eq!(
+            parse_duration("0.0000000000000000000000000000000001h").unwrap(),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn test_parse_duration_compound() {
+        assert_eq!(parse_duration("1m30s").unwrap(), Duration::from_secs(90));
+        assert_eq!(
+            parse_duration("1h2m3s4ms").unwrap(),
+            Duration::from_millis(3_723_004)
+        );
+        assert_eq!(
+            parse_duration("1m0.5s").unwrap(),
+            Duration::from_millis(60_500)
+        );
+    }
+
+    #[test]
+    fn test_parse_duration_errors_point_at_token() {
+        let message = |input: &str| parse_duration(input).unwrap_err().to_string();
+        assert_eq!(
+            message(""),
+            "invalid duration '': expected e.g. 500ms, 30s or 1m30s"
+        );
+        assert_eq!(
+            message("-5s"),
+            "invalid duration '-5s': negative durations are not allowed"
+        );
+        assert_eq!(
+            message("s"),
+            "invalid duration 's': missing number before 's' at position 1"
+        );
+        assert_eq!(
+            message("1m3x"),
+            "invalid duration '1m3x': unknown unit 'x' at position 4, expected ns, us, ms, s, m or h"
+        );
+        assert_eq!(
+            message("5 days"),
+            "invalid duration '5 days': unknown unit ' days' at position 2, expected ns, us, ms, s, m or h"
+        );
+        assert_eq!(
+            message("1m30"),
+            "invalid duration '1m30': missing unit after '30' at position 3"
+        );
+        assert_eq!(
+            message("1.2.3s"),
+            "invalid duration '1.2.3s': invalid number '1.2.3' at position 1"
+        );
+        assert_eq!(
+            message("1m.s"),
+            "invalid duration '1m.s': invalid number '.' at position 3"
+        );
+        assert_eq!(
+            message("1M"),
+            "invalid duration '1M': unknown unit 'M' at position 2, expected ns, us, ms, s, m or h"
+        );
+        assert_eq!(
+            message("1m1m"),
+            "invalid duration '1m1m': repeated unit 'm' at position 4"
+        );
+        assert_eq!(
+            message("1s1m"),
+            "invalid duration '1s1m': unit 'm' at position 4 must come before 's'"
+        );
+    }
+
+    #[test]
+    fn test_parse_duration_overflow() {
+        assert_eq!(
+            parse_duration("9999999h").unwrap_err().to_string(),
+            "invalid duration '9999999h': '9999999h' at position 1 is too long, the longest is about 584 years"
+        );
+        // Too long only once added up.
+        assert!(parse_duration("5000000h8000000m").is_err());
+        assert!(parse_duration("8000000m").is_ok());
+        assert!(parse_duration("99999999999999999999999999999999999999999s").is_err());
+        // The longest duration that fits.
+        assert_eq!(
+            parse_duration("18446744073709551615ns").unwrap(),
+            Duration::from_nanos(u64::MAX)
+        );
+        assert!(parse_duration("18446744073709551616ns").is_err());
+    }
 }
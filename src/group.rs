@@ -0,0 +1,249 @@
+//! This module keeps separate timing statistics for each value of a capture group, such as the id
+//! of the worker that printed a line.
+//!
+//! `GroupStats` finds the named capture group in each line with the regex patterns that define it,
+//! and measures the interval between consecutive lines with the same captured value, independently
+//! of the lines of the other values. Only the first `MAX_GROUPS` distinct values get their own
+//! statistics, which bounds the memory used however many values there are; the lines of the later
+//! values are only counted, in the `other` bucket. `GroupStats::by_key` groups the lines of a
+//! logfmt log by the value of a key instead.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use regex::Regex;
+//! use timeln::group::GroupStats;
+//!
+//! let mut groups = GroupStats::new("id", vec![Regex::new(r"worker-(?P<id>\d+)")?]);
+//! groups.line("worker-1 start", Duration::from_secs(0));
+//! groups.line("worker-2 start", Duration::from_secs(1));
+//! groups.line("worker-1 done", Duration::from_secs(3));
+//! let worker_1 = groups.get("1").unwrap();
+//! assert_eq!(worker_1.count, 2);
+//! assert_eq!(worker_1.intervals.max(), Some(3.0));
+//! # Ok::<(), regex::Error>(())
+//! ```
+use std::collections::HashMap;
+use std::time::Duration;
+
+use regex::Regex;
+
+use crate::logfmt::{self, Pair};
+use crate::stats::RunningStats;
+
+/// Largest number of distinct captured values that get their own statistics.
+pub const MAX_GROUPS: usize = 50;
+
+/// The lines that captured the same value.
+#[derive(Debug, Clone, Default)]
+pub struct Group {
+    /// Number of lines that captured the value.
+    pub count: u64,
+    /// Statistics of the intervals between consecutive lines, in seconds.
+    pub intervals: RunningStats,
+    /// Time of the latest line since the start of the run.
+    last: Duration,
+}
+
+/// Timing statistics of the lines grouped by the value of a named capture group.
+#[derive(Debug)]
+pub struct GroupStats {
+    name: String,
+    patterns: Vec<Regex>,
+    /// Whether the lines are grouped by the logfmt key `name` rather than by the patterns.
+    by_key: bool,
+    groups: HashMap<String, Group>,
+    other: u64,
+}
+
+impl GroupStats {
+    /// Groups the lines by the capture group `name` of the first of `patterns` that matches with
+    /// it. Patterns without a group of that name are ignored.
+    pub fn new(name: &str, patterns: Vec<Regex>) -> Self {
+        let patterns = patterns
+            .into_iter()
+            .filter(|pattern| pattern.capture_names().any(|group| group == Some(name)))
+            .collect();
+        Self {
+            name: name.to_string(),
+            patterns,
+            by_key: false,
+            groups: HashMap::new(),
+            other: 0,
+        }
+    }
+
+    /// Groups the lines of a logfmt log by the value of the key `name`.
+    pub fn by_key(name: &str) -> Self {
+        Self {
+            by_key: true,
+            ..Self::new(name, Vec::new())
+        }
+    }
+
+    /// Name of the capture group.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the lines can be grouped at all: by key, or by a pattern with the capture group.
+    pub fn has_patterns(&self) -> bool {
+        self.by_key || !self.patterns.is_empty()
+    }
+
+    /// Adds `line`, which arrived `at` since the start of the run, to the group of the value it
+    /// captures, if any.
+    pub fn line(&mut self, line: &str, at: Duration) {
+        let Some(value) = self.patterns.iter().find_map(|pattern| {
+            pattern
+                .captures(line)
+                .and_then(|captures| captures.name(&self.name))
+        }) else {
+            return;
+        };
+        self.add(value.as_str(), at);
+    }
+
+    /// Adds a line of a logfmt log with `pairs`, which arrived `at` since the start of the run, to
+    /// the group of the value of the key, if grouping by key and the line has it.
+    pub fn pairs(&mut self, pairs: &[Pair], at: Duration) {
+        if !self.by_key {
+            return;
+        }
+        if let Some(value) = logfmt::get(pairs, &self.name) {
+            self.add(value, at);
+        }
+    }
+
+    fn add(&mut self, value: &str, at: Duration) {
+        if !self.groups.contains_key(value) && self.groups.len() >= MAX_GROUPS {
+            self.other += 1;
+            return;
+        }
+        let group = self.groups.entry(value.to_string()).or_default();
+        if group.count > 0 {
+            group
+                .intervals
+                .push(at.saturating_sub(group.last).as_secs_f64());
+        }
+        group.count += 1;
+        group.last = at;
+    }
+
+    /// The group of the captured `value`, if it has its own statistics.
+    pub fn get(&self, value: &str) -> Option<&Group> {
+        self.groups.get(value)
+    }
+
+    /// The groups, most lines first and then by value.
+    pub fn sorted(&self) -> Vec<(&str, &Group)> {
+        let mut groups: Vec<(&str, &Group)> = self
+            .groups
+            .iter()
+            .map(|(value, group)| (value.as_str(), group))
+            .collect();
+        groups.sort_by(|a, b| b.1.count.cmp(&a.1.count).then(a.0.cmp(b.0)));
+        groups
+    }
+
+    /// Number of lines of the values beyond the first `MAX_GROUPS`.
+    pub fn other(&self) -> u64 {
+        self.other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn groups(lines: &[(u64, &str)]) -> GroupStats {
+        let mut groups = GroupStats::new(
+            "id",
+            vec![
+                Regex::new(r"^skip (\d+)").unwrap(),
+                Regex::new(r"worker-(?P<id>\d+)").unwrap(),
+            ],
+        );
+        for (at, line) in lines {
+            groups.line(line, Duration::from_millis(*at));
+        }
+        groups
+    }
+
+    #[test]
+    fn test_interleaved_groups_have_independent_intervals() {
+        let groups = groups(&[
+            (0, "worker-1 start"),
+            (100, "worker-2 start"),
+            (300, "worker-1 step"),
+            (400, "idle"),
+            (1100, "worker-2 step"),
+            (1300, "worker-1 done"),
+        ]);
+        let worker_1 = groups.get("1").unwrap();
+        assert_eq!(worker_1.count, 3);
+        assert_eq!(worker_1.intervals.count(), 2);
+        assert_eq!(worker_1.intervals.min(), Some(0.3));
+        assert_eq!(worker_1.intervals.max(), Some(1.0));
+        let worker_2 = groups.get("2").unwrap();
+        assert_eq!(worker_2.count, 2);
+        assert_eq!(worker_2.intervals.mean(), 1.0);
+        assert_eq!(groups.other(), 0);
+    }
+
+    #[test]
+    fn test_sorted_by_count_then_value() {
+        let groups = groups(&[
+            (0, "worker-2"),
+            (1, "worker-3"),
+            (2, "worker-3"),
+            (3, "worker-1"),
+        ]);
+        let order: Vec<&str> = groups
+            .sorted()
+            .into_iter()
+            .map(|(value, _)| value)
+            .collect();
+        assert_eq!(order, vec!["3", "1", "2"]);
+    }
+
+    #[test]
+    fn test_values_beyond_cap_go_to_other() {
+        let lines: Vec<String> = (0..MAX_GROUPS + 5)
+            .map(|i| format!("worker-{}", i))
+            .chain(["worker-0".to_string(), "worker-60".to_string()])
+            .collect();
+        let lines: Vec<(u64, &str)> = lines.iter().map(|line| (0, line.as_str())).collect();
+        let groups = groups(&lines);
+        assert_eq!(groups.sorted().len(), MAX_GROUPS);
+        assert_eq!(groups.get("0").unwrap().count, 2);
+        assert!(groups.get("60").is_none());
+        assert_eq!(groups.other(), 6);
+    }
+
+    #[test]
+    fn test_group_by_key() {
+        let mut groups = GroupStats::by_key("worker");
+        assert!(groups.has_patterns());
+        for (at, line) in [
+            (0, "worker=a"),
+            (1, "worker=b msg=x"),
+            (3, "msg=y"),
+            (5, "worker=a"),
+        ] {
+            groups.line(line, Duration::from_secs(at));
+            groups.pairs(&logfmt::parse(line), Duration::from_secs(at));
+        }
+        assert_eq!(groups.get("a").unwrap().count, 2);
+        assert_eq!(groups.get("a").unwrap().intervals.max(), Some(5.0));
+        assert_eq!(groups.get("b").unwrap().count, 1);
+        assert_eq!(groups.sorted().len(), 2);
+    }
+
+    #[test]
+    fn test_patterns_without_the_group_are_ignored() {
+        let groups = GroupStats::new("id", vec![Regex::new(r"worker-(\d+)").unwrap()]);
+        assert!(!groups.has_patterns());
+    }
+}
@@ -0,0 +1,172 @@
+//! This module colors the delta of each line by how slow it is compared to the others, with
+//! `--heat`: on a gradient from blue for the fastest lines, through green, to red for the slowest.
+//!
+//! The scale adapts to the stream: a delta at or below the 5th percentile of the deltas so far is
+//! blue and one at or above the 95th percentile is red, so that a few extreme deltas do not wash
+//! out the others. The percentiles come from the same sketch as those of the summary.
+//!
+//! The gradient is drawn in 24-bit color on terminals that declare it in `COLORTERM`; elsewhere,
+//! deltas are graded in three levels, blue, green and red, in the basic colors of the terminal.
+//!
+//! # Example
+//!
+//! ```
+//! use timeln::heat::heat_rgb;
+//!
+//! assert_eq!(heat_rgb(0.0), (0, 0, 255));
+//! assert_eq!(heat_rgb(0.5), (0, 255, 0));
+//! assert_eq!(heat_rgb(1.0), (255, 0, 0));
+//! ```
+use std::time::Duration;
+
+use colored::Color;
+
+use crate::stats::QuantileSketch;
+
+/// Quantile of the deltas drawn in the fastest color.
+pub const HEAT_LOW: f64 = 0.05;
+
+/// Quantile of the deltas drawn in the slowest color.
+pub const HEAT_HIGH: f64 = 0.95;
+
+/// Whether the terminal draws 24-bit colors, given the value of `COLORTERM`.
+pub fn truecolor_supported(colorterm: Option<&str>) -> bool {
+    matches!(colorterm, Some("truecolor") | Some("24bit"))
+}
+
+/// Where `value` stands between `low` (0) and `high` (1), clamped to that range. Every value is in
+/// the middle of an empty range.
+pub fn heat_position(value: f64, low: f64, high: f64) -> f64 {
+    if high > low {
+        ((value - low) / (high - low)).clamp(0.0, 1.0)
+    } else {
+        0.5
+    }
+}
+
+/// The color at `position` (0 to 1, clamped) of the gradient from blue at 0, through green at
+/// 0.5, to red at 1, as red, green and blue components.
+pub fn heat_rgb(position: f64) -> (u8, u8, u8) {
+    let position = if position.is_nan() {
+        0.5
+    } else {
+        position.clamp(0.0, 1.0)
+    };
+    let component = |fraction: f64| (fraction * 255.0).round() as u8;
+    if position <= 0.5 {
+        let towards_green = position * 2.0;
+        (0, component(towards_green), component(1.0 - towards_green))
+    } else {
+        let towards_red = (position - 0.5) * 2.0;
+        (component(towards_red), component(1.0 - towards_red), 0)
+    }
+}
+
+/// The basic color of the level of `position` (0 to 1): blue for the fastest third, green for the
+/// middle one and red for the slowest.
+pub fn heat_grade(position: f64) -> Color {
+    if position < 1.0 / 3.0 {
+        Color::Blue
+    } else if position < 2.0 / 3.0 {
+        Color::Green
+    } else {
+        Color::Red
+    }
+}
+
+/// Colors deltas against the range of those seen so far.
+#[derive(Debug, Clone, Default)]
+pub struct Heat {
+    sketch: QuantileSketch,
+    truecolor: bool,
+}
+
+impl Heat {
+    /// Colors on the 24-bit gradient if `truecolor`, or else in three levels.
+    pub fn new(truecolor: bool) -> Self {
+        Self {
+            sketch: QuantileSketch::default(),
+            truecolor,
+        }
+    }
+
+    /// Adds `delta` to the deltas seen and returns its color, from its position in their range.
+    pub fn push(&mut self, delta: Duration) -> Color {
+        let value = delta.as_secs_f64();
+        self.sketch.push(value);
+        let low = self.sketch.quantile(HEAT_LOW).unwrap_or(value);
+        let high = self.sketch.quantile(HEAT_HIGH).unwrap_or(value);
+        let position = heat_position(value, low, high);
+        if self.truecolor {
+            let (r, g, b) = heat_rgb(position);
+            Color::TrueColor { r, g, b }
+        } else {
+            heat_grade(position)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truecolor_supported() {
+        assert!(truecolor_supported(Some("truecolor")));
+        assert!(truecolor_supported(Some("24bit")));
+        assert!(!truecolor_supported(Some("")));
+        assert!(!truecolor_supported(None));
+    }
+
+    #[test]
+    fn test_heat_rgb() {
+        assert_eq!(heat_rgb(0.0), (0, 0, 255));
+        assert_eq!(heat_rgb(0.25), (0, 128, 128));
+        assert_eq!(heat_rgb(0.5), (0, 255, 0));
+        assert_eq!(heat_rgb(0.75), (128, 128, 0));
+        assert_eq!(heat_rgb(1.0), (255, 0, 0));
+        // Out of range, the ends of the gradient.
+        assert_eq!(heat_rgb(-1.0), (0, 0, 255));
+        assert_eq!(heat_rgb(3.0), (255, 0, 0));
+        assert_eq!(heat_rgb(f64::NAN), (0, 255, 0));
+    }
+
+    #[test]
+    fn test_heat_position_and_grade() {
+        assert_eq!(heat_position(0.2, 0.1, 0.5), 0.25);
+        assert_eq!(heat_position(0.0, 0.1, 0.5), 0.0);
+        assert_eq!(heat_position(9.0, 0.1, 0.5), 1.0);
+        assert_eq!(heat_position(0.3, 0.3, 0.3), 0.5);
+        assert_eq!(heat_grade(0.1), Color::Blue);
+        assert_eq!(heat_grade(0.5), Color::Green);
+        assert_eq!(heat_grade(0.9), Color::Red);
+    }
+
+    #[test]
+    fn test_heat_adapts_to_deltas() {
+        let mut heat = Heat::new(true);
+        // A single delta is the middle of its own range.
+        assert_eq!(
+            heat.push(Duration::from_millis(100)),
+            Color::TrueColor { r: 0, g: 255, b: 0 }
+        );
+        for _ in 0..50 {
+            heat.push(Duration::from_millis(10));
+            heat.push(Duration::from_secs(1));
+        }
+        assert_eq!(
+            heat.push(Duration::from_secs(5)),
+            Color::TrueColor { r: 255, g: 0, b: 0 }
+        );
+        assert_eq!(
+            heat.push(Duration::from_millis(1)),
+            Color::TrueColor { r: 0, g: 0, b: 255 }
+        );
+
+        let mut graded = Heat::new(false);
+        graded.push(Duration::from_millis(10));
+        graded.push(Duration::from_secs(1));
+        assert_eq!(graded.push(Duration::from_secs(2)), Color::Red);
+        assert_eq!(graded.push(Duration::from_millis(1)), Color::Blue);
+    }
+}
@@ -0,0 +1,368 @@
+//! This module runs shell commands for some lines: when a line is slower than a threshold, with
+//! `--on-slow` and `--on-slow-cmd`, such as to be told that an unattended build stalled, and when
+//! a line matches a regex pattern, with `--on-match-cmd`.
+//!
+//! The commands are run by `sh -c`, or `cmd /C` on Windows, with the line in the environment:
+//!
+//! - `TIMELN_DELTA`: the time the line took, in seconds.
+//! - `TIMELN_ELAPSED`: the time from the start of the run to the line, in seconds.
+//! - `TIMELN_LINE`: the text of the line.
+//! - `TIMELN_MATCH`: for a match, the text matched by the pattern.
+//! - `TIMELN_GROUP_1`, `TIMELN_GROUP_2`, ...: for a match, the text of each capture group of the
+//!   pattern, empty for a group that did not take part in the match.
+//!
+//! The command for the slow lines runs at most once per interval, so that the slow lines following
+//! the first do not run it again; the one for the matches runs for each of them, up to a limit if
+//! given. The commands run in the background, so as not to slow the run down, each waited for by
+//! a thread so that none is left a zombie. A command that cannot be started or that fails is only
+//! logged to stderr.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use timeln::hook::hook_env;
+//!
+//! let env = hook_env(Duration::from_millis(2500), Duration::from_secs(60), "linking");
+//! assert_eq!(env[0], ("TIMELN_DELTA".to_string(), "2.500".to_string()));
+//! assert_eq!(env[1], ("TIMELN_ELAPSED".to_string(), "60.000".to_string()));
+//! assert_eq!(env[2], ("TIMELN_LINE".to_string(), "linking".to_string()));
+//! ```
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use regex::Captures;
+
+/// Shortest interval between two runs of the command, unless changed.
+pub const DEFAULT_HOOK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Environment variables added to those of a command, as names and values.
+pub type HookEnv = Vec<(String, String)>;
+
+/// The environment variables given to the command run for the line `text`, which took `delta` and
+/// arrived `elapsed` after the start of the run.
+pub fn hook_env(delta: Duration, elapsed: Duration, text: &str) -> HookEnv {
+    vec![
+        (
+            "TIMELN_DELTA".to_string(),
+            format!("{:.3}", delta.as_secs_f64()),
+        ),
+        (
+            "TIMELN_ELAPSED".to_string(),
+            format!("{:.3}", elapsed.as_secs_f64()),
+        ),
+        ("TIMELN_LINE".to_string(), text.to_string()),
+    ]
+}
+
+/// The environment variables given to the command run for the line `text`, like `hook_env`, with
+/// the match of a pattern in it and the capture groups of the match from `captures`.
+pub fn match_env(captures: &Captures, delta: Duration, elapsed: Duration, text: &str) -> HookEnv {
+    let mut env = hook_env(delta, elapsed, text);
+    env.push(("TIMELN_MATCH".to_string(), captures[0].to_string()));
+    env.extend(captures.iter().enumerate().skip(1).map(|(i, group)| {
+        (
+            format!("TIMELN_GROUP_{}", i),
+            group.map_or("", |group| group.as_str()).to_string(),
+        )
+    }));
+    env
+}
+
+/// Runs the commands of the hooks.
+pub trait HookExecutor: Send + Sync + std::fmt::Debug {
+    /// Starts `command` with the variables `env` added to the environment, without waiting for
+    /// it to finish.
+    fn execute(&self, command: &str, env: HookEnv);
+}
+
+/// Runs the commands in the shell, in the background, logging those that fail to stderr.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShellExecutor;
+
+impl ShellExecutor {
+    /// The shell running `command`.
+    fn shell(command: &str) -> Command {
+        let (shell, flag) = if cfg!(windows) {
+            ("cmd", "/C")
+        } else {
+            ("sh", "-c")
+        };
+        let mut shell = Command::new(shell);
+        shell.arg(flag).arg(command);
+        shell
+    }
+}
+
+impl HookExecutor for ShellExecutor {
+    fn execute(&self, command: &str, env: HookEnv) {
+        let command = command.to_string();
+        thread::spawn(move || {
+            let status = Self::shell(&command)
+                .envs(env)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .status();
+            match status {
+                Ok(status) if status.success() => {}
+                Ok(status) => eprintln!("timeln: {} failed: {}", command, status),
+                Err(err) => eprintln!("timeln: could not run {}: {}", command, err),
+            }
+        });
+    }
+}
+
+/// Runs a command for the lines slower than a threshold.
+#[derive(Debug)]
+pub struct SlowHook {
+    threshold: Duration,
+    command: String,
+    interval: Duration,
+    executor: Box<dyn HookExecutor>,
+    /// When the command was last run.
+    last_run: Mutex<Option<Instant>>,
+}
+
+impl SlowHook {
+    /// Runs `command` in the shell for the lines slower than `threshold`, at most once per
+    /// `interval`.
+    pub fn new(threshold: Duration, command: &str, interval: Duration) -> Self {
+        Self::with_executor(threshold, command, interval, Box::new(ShellExecutor))
+    }
+
+    /// Like `new`, but runs the command with `executor`.
+    pub fn with_executor(
+        threshold: Duration,
+        command: &str,
+        interval: Duration,
+        executor: Box<dyn HookExecutor>,
+    ) -> Self {
+        Self {
+            threshold,
+            command: command.to_string(),
+            interval,
+            executor,
+            last_run: Mutex::new(None),
+        }
+    }
+
+    /// Whether a line that took `delta` is slower than the threshold.
+    pub fn is_slow(&self, delta: Duration) -> bool {
+        delta > self.threshold
+    }
+
+    /// Runs the command for the line `text`, which took `delta` and arrived `elapsed` after the
+    /// start of the run, at `now`, if it is slow and the command was not run less than the
+    /// interval before. Returns whether the command is run.
+    pub fn line(&self, delta: Duration, elapsed: Duration, text: &str, now: Instant) -> bool {
+        if !self.is_slow(delta) {
+            return false;
+        }
+        let Ok(mut last_run) = self.last_run.lock() else {
+            return false;
+        };
+        if last_run.is_some_and(|last| now.saturating_duration_since(last) < self.interval) {
+            return false;
+        }
+        *last_run = Some(now);
+        self.executor
+            .execute(&self.command, hook_env(delta, elapsed, text));
+        true
+    }
+}
+
+/// Runs a command for the lines matching a regex pattern.
+#[derive(Debug)]
+pub struct MatchHook {
+    command: String,
+    /// Most times the command is run, if limited.
+    limit: Option<usize>,
+    executor: Box<dyn HookExecutor>,
+    /// Times the command was run.
+    runs: AtomicUsize,
+}
+
+impl MatchHook {
+    /// Runs `command` in the shell for each match, at most `limit` times if given.
+    pub fn new(command: &str, limit: Option<usize>) -> Self {
+        Self::with_executor(command, limit, Box::new(ShellExecutor))
+    }
+
+    /// Like `new`, but runs the command with `executor`.
+    pub fn with_executor(
+        command: &str,
+        limit: Option<usize>,
+        executor: Box<dyn HookExecutor>,
+    ) -> Self {
+        Self {
+            command: command.to_string(),
+            limit,
+            executor,
+            runs: AtomicUsize::new(0),
+        }
+    }
+
+    /// Runs the command for the line `text`, which took `delta`, arrived `elapsed` after the start
+    /// of the run and matched with `captures`, unless it already ran as many times as the limit.
+    /// Returns whether the command is run.
+    pub fn matched(
+        &self,
+        captures: &Captures,
+        delta: Duration,
+        elapsed: Duration,
+        text: &str,
+    ) -> bool {
+        let limit = self.limit.unwrap_or(usize::MAX);
+        let counted = self
+            .runs
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |runs| {
+                (runs < limit).then_some(runs + 1)
+            });
+        if counted.is_err() {
+            return false;
+        }
+        self.executor
+            .execute(&self.command, match_env(captures, delta, elapsed, text));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+    use std::sync::Arc;
+
+    /// Records the commands instead of running them.
+    #[derive(Debug, Clone, Default)]
+    struct RecordingExecutor {
+        runs: Arc<Mutex<Vec<(String, HookEnv)>>>,
+    }
+
+    impl HookExecutor for RecordingExecutor {
+        fn execute(&self, command: &str, env: HookEnv) {
+            self.runs.lock().unwrap().push((command.to_string(), env));
+        }
+    }
+
+    #[test]
+    fn test_hook_fires_for_slow_lines() {
+        let executor = RecordingExecutor::default();
+        let hook = SlowHook::with_executor(
+            Duration::from_secs(2),
+            "alert",
+            Duration::from_secs(60),
+            Box::new(executor.clone()),
+        );
+        let now = Instant::now();
+        assert!(!hook.line(Duration::from_secs(2), Duration::from_secs(2), "a", now));
+        assert!(hook.line(Duration::from_secs(3), Duration::from_secs(5), "b", now));
+        let runs = executor.runs.lock().unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].0, "alert");
+        assert_eq!(
+            runs[0].1,
+            hook_env(Duration::from_secs(3), Duration::from_secs(5), "b")
+        );
+    }
+
+    #[test]
+    fn test_hook_is_rate_limited() {
+        let executor = RecordingExecutor::default();
+        let interval = Duration::from_secs(60);
+        let hook = SlowHook::with_executor(
+            Duration::ZERO,
+            "alert",
+            interval,
+            Box::new(executor.clone()),
+        );
+        let slow = Duration::from_secs(1);
+        let now = Instant::now();
+        assert!(hook.line(slow, slow, "a", now));
+        assert!(!hook.line(slow, slow, "b", now + interval / 2));
+        // A fast line does not rearm it.
+        assert!(!hook.line(Duration::ZERO, slow, "c", now + interval / 2));
+        assert!(hook.line(slow, slow, "d", now + interval));
+        let runs = executor.runs.lock().unwrap();
+        let lines: Vec<_> = runs.iter().map(|(_, env)| env[2].1.as_str()).collect();
+        assert_eq!(lines, ["a", "d"]);
+    }
+
+    #[test]
+    fn test_match_env_has_groups() {
+        let regex = Regex::new(r"epoch (\d+)(?: loss (\S+))?(!)?").unwrap();
+        let text = "epoch 3 loss 0.25";
+        let captures = regex.captures(text).unwrap();
+        let env = match_env(
+            &captures,
+            Duration::from_secs(1),
+            Duration::from_secs(2),
+            text,
+        );
+        assert_eq!(
+            env[..3],
+            hook_env(Duration::from_secs(1), Duration::from_secs(2), text)
+        );
+        let added: Vec<(&str, &str)> = env[3..]
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(
+            added,
+            [
+                ("TIMELN_MATCH", "epoch 3 loss 0.25"),
+                ("TIMELN_GROUP_1", "3"),
+                ("TIMELN_GROUP_2", "0.25"),
+                ("TIMELN_GROUP_3", ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_match_hook_is_limited() {
+        let regex = Regex::new("ok").unwrap();
+        let captures = regex.captures("ok").unwrap();
+        let executor = RecordingExecutor::default();
+        let hook = MatchHook::with_executor("alert", Some(2), Box::new(executor.clone()));
+        let runs: Vec<bool> = (0..4)
+            .map(|_| hook.matched(&captures, Duration::ZERO, Duration::ZERO, "ok"))
+            .collect();
+        assert_eq!(runs, [true, true, false, false]);
+        assert_eq!(executor.runs.lock().unwrap().len(), 2);
+
+        let unlimited = MatchHook::with_executor("alert", None, Box::new(executor.clone()));
+        for _ in 0..5 {
+            assert!(unlimited.matched(&captures, Duration::ZERO, Duration::ZERO, "ok"));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shell_executor_sets_env() {
+        let dir = std::env::temp_dir().join(format!("timeln-hook-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("env");
+        let command = format!(
+            "printf '%s|%s|%s' \"$TIMELN_DELTA\" \"$TIMELN_ELAPSED\" \"$TIMELN_LINE\" > {}",
+            path.display()
+        );
+        ShellExecutor.execute(
+            &command,
+            hook_env(Duration::from_secs(3), Duration::from_secs(4), "it's slow"),
+        );
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut written = String::new();
+        while Instant::now() < deadline {
+            written = std::fs::read_to_string(&path).unwrap_or_default();
+            if written.ends_with("slow") {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(written, "3.000|4.000|it's slow");
+    }
+}
@@ -0,0 +1,191 @@
+//! This module reads the systemd journal, so that the entries of a unit can be timed like lines.
+//!
+//! The journal is read through `journalctl --output=json --follow`, one JSON object per entry,
+//! which needs no development files of libsystemd and keeps the filters of `journalctl`. Each
+//! entry has its time in `__REALTIME_TIMESTAMP`, in microseconds since the Unix epoch, and its
+//! text in `MESSAGE`: a string, or an array of bytes for a message that is not valid UTF-8.
+//!
+//! # Example
+//!
+//! ```
+//! use timeln::journal::{parse_entry, JournalPriority};
+//!
+//! let entry = parse_entry(r#"{"__REALTIME_TIMESTAMP": "1709553600250000", "MESSAGE": "started"}"#)
+//!     .unwrap();
+//! assert_eq!(entry.timestamp, 1_709_553_600_250_000_000);
+//! assert_eq!(entry.message, "started");
+//! assert_eq!("err".parse::<JournalPriority>().unwrap().to_string(), "0..3");
+//! ```
+use std::fmt;
+
+use serde_json::Value;
+
+/// The names of the journal priorities, from the most severe, 0, to the least, 7.
+const PRIORITY_NAMES: [&str; 8] = [
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+
+/// An entry of the journal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    /// The time of the entry, in nanoseconds since the Unix epoch.
+    pub timestamp: i64,
+    pub message: String,
+}
+
+/// Parses an entry written by `journalctl --output=json`, or returns `None` if `line` is not
+/// one.
+pub fn parse_entry(line: &str) -> Option<JournalEntry> {
+    let object: Value = serde_json::from_str(line).ok()?;
+    let micros: i64 = match object.get("__REALTIME_TIMESTAMP")? {
+        Value::String(text) => text.parse().ok()?,
+        Value::Number(number) => number.as_i64()?,
+        _ => return None,
+    };
+    let message = match object.get("MESSAGE") {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Array(bytes)) => {
+            let bytes: Option<Vec<u8>> = bytes
+                .iter()
+                .map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+                .collect();
+            String::from_utf8_lossy(&bytes?).into_owned()
+        }
+        // An entry may have no message, such as one only logging structured fields.
+        _ => String::new(),
+    };
+    Some(JournalEntry {
+        timestamp: micros.checked_mul(1000)?,
+        message,
+    })
+}
+
+/// The range of priorities of the entries to read, as given to `journalctl --priority`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JournalPriority {
+    /// The most severe priority, 0 unless a range is given.
+    pub from: u8,
+    /// The least severe priority.
+    pub to: u8,
+}
+
+impl std::str::FromStr for JournalPriority {
+    type Err = String;
+
+    /// Parses a priority such as `err` or `3`, which also selects the more severe priorities, or
+    /// a range such as `crit..warning`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let level = |name: &str| {
+            let name = name.trim();
+            name.parse::<u8>()
+                .ok()
+                .filter(|level| *level < 8)
+                .or_else(|| {
+                    PRIORITY_NAMES
+                        .iter()
+                        .position(|known| known.eq_ignore_ascii_case(name))
+                        .map(|level| level as u8)
+                })
+                .ok_or_else(|| {
+                    format!(
+                        "invalid priority '{}', expected 0-7 or one of {}",
+                        name,
+                        PRIORITY_NAMES.join(", ")
+                    )
+                })
+        };
+        let (from, to) = match s.split_once("..") {
+            Some((from, to)) => (level(from)?, level(to)?),
+            None => (0, level(s)?),
+        };
+        Ok(Self {
+            from: from.min(to),
+            to: from.max(to),
+        })
+    }
+}
+
+impl fmt::Display for JournalPriority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.from, self.to)
+    }
+}
+
+/// The `journalctl` command following the whole journal of `units`, or of every unit if there
+/// are none, with a `priority`, if given.
+pub fn journalctl_command(units: &[String], priority: Option<JournalPriority>) -> Vec<String> {
+    let mut command: Vec<String> = [
+        "journalctl",
+        "--output=json",
+        "--output-fields=MESSAGE",
+        "--follow",
+        "--lines=all",
+        "--quiet",
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect();
+    command.extend(units.iter().map(|unit| format!("--unit={}", unit)));
+    if let Some(priority) = priority {
+        command.push(format!("--priority={}", priority));
+    }
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_entry() {
+        let entry =
+            parse_entry(r#"{"__REALTIME_TIMESTAMP": "1000001", "MESSAGE": "a", "_PID": "1"}"#);
+        assert_eq!(
+            entry,
+            Some(JournalEntry {
+                timestamp: 1_000_001_000,
+                message: "a".to_string()
+            })
+        );
+        let binary = parse_entry(r#"{"__REALTIME_TIMESTAMP": "2", "MESSAGE": [104, 105, 255]}"#);
+        assert_eq!(binary.unwrap().message, "hi\u{fffd}");
+        let empty = parse_entry(r#"{"__REALTIME_TIMESTAMP": "2", "MESSAGE": null}"#);
+        assert_eq!(empty.unwrap().message, "");
+    }
+
+    #[test]
+    fn test_malformed_entries() {
+        assert_eq!(parse_entry("-- No entries --"), None);
+        assert_eq!(parse_entry(r#"{"MESSAGE": "no time"}"#), None);
+        assert_eq!(
+            parse_entry(r#"{"__REALTIME_TIMESTAMP": "soon", "MESSAGE": "a"}"#),
+            None
+        );
+        assert_eq!(
+            parse_entry(r#"{"__REALTIME_TIMESTAMP": "1", "MESSAGE": [300]}"#),
+            None
+        );
+    }
+
+    #[test]
+    fn test_priorities() {
+        let parse = |s: &str| s.parse::<JournalPriority>().map(|p| p.to_string());
+        assert_eq!(parse("err"), Ok("0..3".to_string()));
+        assert_eq!(parse("6"), Ok("0..6".to_string()));
+        assert_eq!(parse("WARNING..crit"), Ok("2..4".to_string()));
+        assert!(parse("8").is_err());
+        assert!(parse("loud").unwrap_err().contains("emerg"));
+    }
+
+    #[test]
+    fn test_journalctl_command() {
+        let priority = "warning".parse().ok();
+        let command = journalctl_command(&["app.service".to_string()], priority);
+        assert_eq!(command[0], "journalctl");
+        assert!(command.contains(&"--follow".to_string()));
+        assert_eq!(
+            &command[command.len() - 2..],
+            ["--unit=app.service", "--priority=0..4"]
+        );
+    }
+}
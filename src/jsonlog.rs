@@ -0,0 +1,137 @@
+//! This module reads structured logs, with one JSON object per line, so that their lines can be
+//! timed by a timestamp field and displayed by a message field.
+//!
+//! The time field holds seconds or milliseconds since the Unix epoch, as a number or a string, or
+//! an RFC 3339 timestamp such as `2024-03-04T12:00:00.250Z`, as parsed by `parse_timestamp`. A
+//! field may name a nested field with dots, as in `event.ts`.
+//!
+//! # Example
+//!
+//! ```
+//! use timeln::jsonlog::JsonFields;
+//!
+//! let fields = JsonFields::new("ts", Some("msg".to_string()));
+//! let line = fields.parse(r#"{"ts": 1709553600250, "msg": "started"}"#).unwrap();
+//! assert_eq!(line.timestamp, 1_709_553_600_250_000_000);
+//! assert_eq!(line.text.as_deref(), Some("started"));
+//! assert!(fields.parse("not json").is_none());
+//! ```
+use serde_json::Value;
+
+use crate::timestamp::parse_timestamp;
+
+/// Name of the time field, unless given.
+pub const DEFAULT_TIME_FIELD: &str = "ts";
+
+/// A line of a structured log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonLine {
+    /// The time of the line, in nanoseconds since the Unix epoch.
+    pub timestamp: i64,
+    /// The text to display for the line, if a line field was given and the line has it.
+    pub text: Option<String>,
+}
+
+/// The fields of a structured log holding the time and the text of each line.
+#[derive(Debug, Clone)]
+pub struct JsonFields {
+    time_field: String,
+    line_field: Option<String>,
+}
+
+impl JsonFields {
+    /// Reads the time from `time_field` and the text, if given, from `line_field`.
+    pub fn new(time_field: &str, line_field: Option<String>) -> Self {
+        Self {
+            time_field: time_field.to_string(),
+            line_field,
+        }
+    }
+
+    /// Parses `line` as a JSON object, or returns `None` if it is not one or has no valid time.
+    pub fn parse(&self, line: &str) -> Option<JsonLine> {
+        let object: Value = serde_json::from_str(line).ok()?;
+        let timestamp = parse_time(field(&object, &self.time_field)?)?;
+        let text = self
+            .line_field
+            .as_deref()
+            .and_then(|name| field(&object, name))
+            .map(|value| match value {
+                Value::String(text) => text.clone(),
+                other => other.to_string(),
+            });
+        Some(JsonLine { timestamp, text })
+    }
+}
+
+/// Looks up the field `name` of `object`, descending into nested objects at each dot.
+fn field<'a>(object: &'a Value, name: &str) -> Option<&'a Value> {
+    name.split('.')
+        .try_fold(object, |value, key| value.as_object()?.get(key))
+}
+
+/// Parses a time field, in nanoseconds since the Unix epoch.
+fn parse_time(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(number) => parse_timestamp(&number.to_string()),
+        Value::String(text) => parse_timestamp(text),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields() -> JsonFields {
+        JsonFields::new(DEFAULT_TIME_FIELD, Some("msg".to_string()))
+    }
+
+    #[test]
+    fn test_epoch_times() {
+        let millis = fields().parse(r#"{"ts": 1709553600250}"#).unwrap();
+        let seconds = fields().parse(r#"{"ts": 1709553600.25}"#).unwrap();
+        let string = fields().parse(r#"{"ts": "1709553600250"}"#).unwrap();
+        assert_eq!(millis.timestamp, 1_709_553_600_250_000_000);
+        assert!((seconds.timestamp - millis.timestamp).abs() < 1000);
+        assert_eq!(string.timestamp, millis.timestamp);
+    }
+
+    #[test]
+    fn test_rfc3339_times() {
+        let utc = fields()
+            .parse(r#"{"ts": "2024-03-04T12:00:00.250Z", "msg": "a"}"#)
+            .unwrap();
+        let offset = fields()
+            .parse(r#"{"ts": "2024-03-04T13:00:01+01:00", "msg": "b"}"#)
+            .unwrap();
+        assert_eq!(offset.timestamp - utc.timestamp, 750_000_000);
+        assert_eq!(utc.timestamp, 1_709_553_600_250_000_000);
+    }
+
+    #[test]
+    fn test_line_field() {
+        let line = fields().parse(r#"{"ts": 1, "msg": "started"}"#).unwrap();
+        assert_eq!(line.text.as_deref(), Some("started"));
+        let line = fields().parse(r#"{"ts": 1, "msg": {"code": 2}}"#).unwrap();
+        assert_eq!(line.text.as_deref(), Some(r#"{"code":2}"#));
+        let line = fields().parse(r#"{"ts": 1}"#).unwrap();
+        assert_eq!(line.text, None);
+        let nested = JsonFields::new("event.ts", Some("event.msg".to_string()));
+        let line = nested
+            .parse(r#"{"event": {"ts": 2, "msg": "nested"}}"#)
+            .unwrap();
+        assert_eq!(line.timestamp, 2_000_000_000);
+        assert_eq!(line.text.as_deref(), Some("nested"));
+    }
+
+    #[test]
+    fn test_malformed_lines() {
+        assert_eq!(fields().parse(r#"{"ts": 1, "msg": "cut"#), None);
+        assert_eq!(fields().parse("plain text"), None);
+        assert_eq!(fields().parse(r#"{"msg": "no time"}"#), None);
+        assert_eq!(fields().parse(r#"{"ts": "yesterday"}"#), None);
+        assert_eq!(fields().parse(r#"{"ts": true}"#), None);
+        assert_eq!(fields().parse("[1, 2]"), None);
+    }
+}
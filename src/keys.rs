@@ -0,0 +1,258 @@
+//! This module reads single-key commands from the terminal during a run, with `--keys`:
+//!
+//! - `s` prints the summary of the run so far;
+//! - `p` pauses the display of the lines, and resumes it: the lines are still timed while paused,
+//!   and are printed when the display resumes;
+//! - `q` finishes the run, as at the end of the input, and exits;
+//! - `c` turns the colors of a run with `--color` off and on.
+//!
+//! The keys are read from the controlling terminal (`/dev/tty`, or the console on Windows) rather
+//! than stdin, which is the input being timed. `open_terminal` puts the terminal in a mode where
+//! each key is read as soon as it is pressed, without echoing it, and `restore_terminal` restores
+//! the mode it had, which is also done if timeln panics. `spawn` reads the keys on a background
+//! thread and sends their commands to a channel, from which the run applies them.
+//!
+//! # Example
+//!
+//! ```
+//! use timeln::keys::{spawn, KeyCommand};
+//!
+//! let commands = spawn(std::io::Cursor::new(b"sxq"));
+//! let commands: Vec<KeyCommand> = commands.iter().collect();
+//! assert_eq!(commands, [KeyCommand::Summary, KeyCommand::Quit]);
+//! ```
+use std::fs::File;
+use std::io::{self, Read};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Once;
+use std::thread;
+
+/// A command given with a key during a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyCommand {
+    /// Print the summary of the run so far, with `s`.
+    Summary,
+    /// Pause or resume the display of the lines, with `p`.
+    TogglePause,
+    /// Finish the run and exit, with `q`.
+    Quit,
+    /// Turn the colors off or on, with `c`.
+    ToggleColor,
+}
+
+impl KeyCommand {
+    /// The command of `key`, in either case, if any.
+    pub fn from_key(key: u8) -> Option<Self> {
+        match key.to_ascii_lowercase() {
+            b's' => Some(KeyCommand::Summary),
+            b'p' => Some(KeyCommand::TogglePause),
+            b'q' => Some(KeyCommand::Quit),
+            b'c' => Some(KeyCommand::ToggleColor),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the keys of `source` on a background thread, sending the command of each to the
+/// returned channel and ignoring the other keys. The channel is closed at the end of the source,
+/// or if it fails.
+pub fn spawn(mut source: impl Read + Send + 'static) -> Receiver<KeyCommand> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut key = [0u8];
+        while let Ok(1) = source.read(&mut key) {
+            let Some(command) = KeyCommand::from_key(key[0]) else {
+                continue;
+            };
+            if sender.send(command).is_err() {
+                return;
+            }
+        }
+    });
+    receiver
+}
+
+/// Puts the controlling terminal in a mode where keys are read as soon as they are pressed,
+/// without being echoed, and returns it to read the keys from; or returns `None` if timeln has no
+/// controlling terminal. Ctrl-C still interrupts timeln. The mode is restored by
+/// `restore_terminal`, which is also called if timeln panics.
+pub fn open_terminal() -> io::Result<Option<File>> {
+    static PANIC_HOOK: Once = Once::new();
+    let terminal = terminal::open()?;
+    if terminal.is_some() {
+        PANIC_HOOK.call_once(|| {
+            let hook = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                restore_terminal();
+                hook(info);
+            }));
+        });
+    }
+    Ok(terminal)
+}
+
+/// Restores the mode the terminal had before `open_terminal`. Does nothing if it was not opened
+/// or is already restored.
+pub fn restore_terminal() {
+    terminal::restore();
+}
+
+#[cfg(unix)]
+mod terminal {
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+    use std::sync::Mutex;
+
+    /// The terminal and the mode it had before it was opened.
+    static SAVED: Mutex<Option<(File, libc::termios)>> = Mutex::new(None);
+
+    pub(super) fn open() -> io::Result<Option<File>> {
+        // Opening fails without a controlling terminal, as under cron or a service manager.
+        let Ok(tty) = OpenOptions::new().read(true).write(true).open("/dev/tty") else {
+            return Ok(None);
+        };
+        // SAFETY: termios is a plain C struct, fully written by tcgetattr before it is read.
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        // SAFETY: the descriptor is open and `saved` is a valid termios to write to.
+        if unsafe { libc::tcgetattr(tty.as_raw_fd(), &mut saved) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut keys = saved;
+        // Output processing and signals are kept, so that the lines and Ctrl-C work as before.
+        keys.c_lflag &= !(libc::ICANON | libc::ECHO);
+        keys.c_cc[libc::VMIN] = 1;
+        keys.c_cc[libc::VTIME] = 0;
+        // SAFETY: the descriptor is open and `keys` is a valid termios.
+        if unsafe { libc::tcsetattr(tty.as_raw_fd(), libc::TCSANOW, &keys) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut guard = SAVED.lock().unwrap_or_else(|err| err.into_inner());
+        *guard = Some((tty.try_clone()?, saved));
+        Ok(Some(tty))
+    }
+
+    pub(super) fn restore() {
+        let saved = SAVED.lock().unwrap_or_else(|err| err.into_inner()).take();
+        if let Some((tty, saved)) = saved {
+            // SAFETY: the descriptor is still open, as `tty` owns it, and `saved` is the valid
+            // termios read from it. A failure leaves nothing better to do.
+            unsafe {
+                libc::tcsetattr(tty.as_raw_fd(), libc::TCSANOW, &saved);
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+mod terminal {
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::os::windows::io::AsRawHandle;
+    use std::sync::Mutex;
+
+    use windows_sys::Win32::System::Console::{
+        GetConsoleMode, SetConsoleMode, CONSOLE_MODE, ENABLE_ECHO_INPUT, ENABLE_LINE_INPUT,
+    };
+
+    /// The console input and the mode it had before it was opened.
+    static SAVED: Mutex<Option<(File, CONSOLE_MODE)>> = Mutex::new(None);
+
+    pub(super) fn open() -> io::Result<Option<File>> {
+        // Opening fails when the process has no console.
+        let Ok(console) = OpenOptions::new().read(true).write(true).open("CONIN$") else {
+            return Ok(None);
+        };
+        let mut saved: CONSOLE_MODE = 0;
+        // SAFETY: the handle is an open console input and `saved` is valid to write to.
+        if unsafe { GetConsoleMode(console.as_raw_handle(), &mut saved) } == 0 {
+            return Ok(None);
+        }
+        let keys = saved & !(ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT);
+        // SAFETY: the handle is an open console input.
+        if unsafe { SetConsoleMode(console.as_raw_handle(), keys) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut guard = SAVED.lock().unwrap_or_else(|err| err.into_inner());
+        *guard = Some((console.try_clone()?, saved));
+        Ok(Some(console))
+    }
+
+    pub(super) fn restore() {
+        let saved = SAVED.lock().unwrap_or_else(|err| err.into_inner()).take();
+        if let Some((console, saved)) = saved {
+            // SAFETY: the handle is still open, as `console` owns it.
+            unsafe {
+                SetConsoleMode(console.as_raw_handle(), saved);
+            }
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod terminal {
+    use std::fs::File;
+    use std::io;
+
+    pub(super) fn open() -> io::Result<Option<File>> {
+        Ok(None)
+    }
+
+    pub(super) fn restore() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_commands_of_keys() {
+        assert_eq!(KeyCommand::from_key(b's'), Some(KeyCommand::Summary));
+        assert_eq!(KeyCommand::from_key(b'P'), Some(KeyCommand::TogglePause));
+        assert_eq!(KeyCommand::from_key(b'q'), Some(KeyCommand::Quit));
+        assert_eq!(KeyCommand::from_key(b'c'), Some(KeyCommand::ToggleColor));
+        assert_eq!(KeyCommand::from_key(b'\n'), None);
+        assert_eq!(KeyCommand::from_key(b'x'), None);
+    }
+
+    #[test]
+    fn test_spawn_dispatches_commands_in_order() {
+        let commands = spawn(Cursor::new(b"p\nsPxc q".to_vec()));
+        assert_eq!(
+            commands.iter().collect::<Vec<_>>(),
+            [
+                KeyCommand::TogglePause,
+                KeyCommand::Summary,
+                KeyCommand::TogglePause,
+                KeyCommand::ToggleColor,
+                KeyCommand::Quit
+            ]
+        );
+    }
+
+    /// A key source that fails after its keys, as a terminal that was closed.
+    struct Failing(Cursor<Vec<u8>>);
+
+    impl Read for Failing {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.read(buf)? {
+                0 => Err(io::Error::from(io::ErrorKind::BrokenPipe)),
+                read => Ok(read),
+            }
+        }
+    }
+
+    #[test]
+    fn test_spawn_closes_channel_on_error() {
+        let commands = spawn(Failing(Cursor::new(b"s".to_vec())));
+        assert_eq!(commands.recv(), Ok(KeyCommand::Summary));
+        assert!(commands.recv().is_err());
+    }
+
+    #[test]
+    fn test_restore_without_terminal_does_nothing() {
+        restore_terminal();
+        restore_terminal();
+    }
+}
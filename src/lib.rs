@@ -0,0 +1,89 @@
+//! The line-timing pipeline of the timeln utility, as a library.
+//!
+//! The `timeln` binary reads lines from stdin and annotates each of them with the time elapsed
+//! since the start and the time since the previous line (or regex match). This crate exposes the
+//! same machinery so that it can be embedded in other tools:
+//!
+//! - `Pipeline` times and annotates lines from any `ReadData` source, returning a `TimeSnapshot`
+//!   per timed line.
+//! - `TimelnContext` is the full command-line run: pipeline, summary, plots and reports.
+//! - `TimelnAnnotation`, `TimeFormat` and `Summarizer` customize how lines, durations and
+//!   summaries are formatted.
+//!
+//! # Example
+//!
+//! ```
+//! use std::sync::Arc;
+//! use timeln::{IterReadData, Pipeline, SecondsFormat, SimpleAnnotator};
+//!
+//! let annotator = SimpleAnnotator {
+//!     color: false,
+//!     time_format: Arc::new(Box::new(SecondsFormat)),
+//!     widths: None,
+//! };
+//! let mut pipeline = Pipeline::new(Box::new(annotator));
+//! let snapshots = pipeline.run(&mut IterReadData::new(["one", "two"]), &mut std::io::sink())?;
+//! assert_eq!(snapshots.len(), 2);
+//! # Ok::<(), timeln::TimelnError>(())
+//! ```
+pub mod activity;
+pub mod annotator;
+pub mod argopt;
+pub mod build_info;
+pub mod burst;
+pub mod context;
+pub mod csv;
+pub mod dashboard;
+pub mod decompress;
+pub mod desktop;
+pub mod error;
+pub mod events;
+pub mod extract;
+pub mod formatter;
+pub mod group;
+pub mod heat;
+pub mod hook;
+pub mod journal;
+pub mod jsonlog;
+pub mod keys;
+pub mod live;
+pub mod logfmt;
+pub mod metrics;
+pub mod notify;
+pub mod parquet;
+pub mod pause;
+pub mod phase;
+pub mod pipeline;
+pub mod plot;
+pub mod progress;
+pub mod reader;
+pub mod record;
+pub mod report;
+pub mod reported;
+pub mod reservoir;
+pub mod screen;
+pub mod signal;
+pub mod sink;
+pub mod span;
+pub mod sqlite;
+pub mod stats;
+pub mod statsd;
+pub mod status;
+pub mod summarizer;
+pub mod term_plot;
+pub mod timeln;
+pub mod timestamp;
+pub mod watch;
+
+pub use crate::annotator::{
+    ColumnWidths, SimpleAnnotator, TimelnAnnotation, TsAnnotator, TsMode, UnicodeAnnotator,
+};
+pub use crate::error::TimelnError;
+pub use crate::formatter::{
+    MillisecondsFormat, MinutesSecondsFormat, RateFormat, SecondsFormat, TimeFormat,
+};
+pub use crate::pipeline::{Pipeline, TimedLine};
+pub use crate::reader::{BufReadData, IterReadData, ReadData, ReadOutcome, StdinReadData};
+pub use crate::stats::RunningStats;
+pub use crate::summarizer::{DetailedSummarizer, SimpleSummarizer, StatsSummarizer, Summarizer};
+pub use crate::timeln::{TimeSnapshot, TimelnContext, TimelnContextBuilder};
@@ -0,0 +1,351 @@
+//! This module serves the progress of a running timeln to a browser, so that a long run on a
+//! remote machine can be checked without logging in.
+//!
+//! The server answers on a background thread:
+//!
+//! - `/`: a page showing the elapsed time, the lines, matches and bytes, a sparkline of the
+//!   recent deltas and the summary so far, refreshed every second from `/stats`.
+//! - `/stats`: the same as JSON, `{"elapsed_ns": .., "lines": .., "matches": .., "bytes": ..,
+//!   "last_delta_ns": .. | null, "recent_deltas_ns": [..], "summary": ".."}`.
+//!
+//! It stops when the run is finalized, and a request only waits for the counters of the run, so
+//! it never blocks reading the input.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use timeln::live::{LiveStats, RecentDeltas};
+//!
+//! let recent = RecentDeltas::new(2);
+//! for millis in [100, 200, 300] {
+//!     recent.push(Duration::from_millis(millis));
+//! }
+//! let stats = LiveStats {
+//!     lines: 3,
+//!     recent_deltas: recent.deltas(),
+//!     ..LiveStats::default()
+//! };
+//! assert!(stats.to_json().contains(r#""recent_deltas_ns":[200000000,300000000]"#));
+//! ```
+use std::collections::VecDeque;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::metrics::{read_request, write_response};
+use crate::report::escape_json;
+
+/// Number of recent deltas drawn in the sparkline.
+pub const SPARKLINE_DELTAS: usize = 60;
+
+/// The page served at `/`, which polls `/stats`.
+const PAGE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>timeln</title>
+<style>
+body { font-family: sans-serif; margin: 2em; }
+td { padding: 0.2em 1em 0.2em 0; }
+pre { background: #f4f4f4; padding: 0.5em; }
+#status { color: #888; }
+</style>
+</head>
+<body>
+<h1>timeln</h1>
+<table>
+<tr><td>Elapsed</td><td id="elapsed">-</td></tr>
+<tr><td>Lines</td><td id="lines">-</td></tr>
+<tr><td>Matches</td><td id="matches">-</td></tr>
+<tr><td>Bytes</td><td id="bytes">-</td></tr>
+<tr><td>Last delta</td><td id="last_delta">-</td></tr>
+</table>
+<svg id="sparkline" width="480" height="60"><polyline fill="none" stroke="steelblue" points=""/></svg>
+<pre id="summary"></pre>
+<p id="status"></p>
+<script>
+const seconds = (ns) => ns === null ? "-" : (ns / 1e9).toFixed(2) + " s";
+async function refresh() {
+  try {
+    const stats = await (await fetch("/stats")).json();
+    document.getElementById("elapsed").textContent = seconds(stats.elapsed_ns);
+    for (const name of ["lines", "matches", "bytes"]) {
+      document.getElementById(name).textContent = stats[name];
+    }
+    document.getElementById("last_delta").textContent = seconds(stats.last_delta_ns);
+    document.getElementById("summary").textContent = stats.summary;
+    const deltas = stats.recent_deltas_ns;
+    const max = Math.max(1, ...deltas);
+    const step = 480 / Math.max(1, deltas.length - 1);
+    const points = deltas.map((delta, i) => `${i * step},${60 - 58 * delta / max}`);
+    document.querySelector("#sparkline polyline").setAttribute("points", points.join(" "));
+    document.getElementById("status").textContent = "";
+  } catch (err) {
+    document.getElementById("status").textContent = "The run is over.";
+    return;
+  }
+  setTimeout(refresh, 1000);
+}
+refresh();
+</script>
+</body>
+</html>
+"##;
+
+/// The most recent deltas of a run, for the sparkline.
+#[derive(Debug)]
+pub struct RecentDeltas {
+    deltas: Mutex<VecDeque<Duration>>,
+    capacity: usize,
+}
+
+impl RecentDeltas {
+    /// Keeps the latest `capacity` deltas.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            deltas: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    pub fn push(&self, delta: Duration) {
+        let Ok(mut deltas) = self.deltas.lock() else {
+            return;
+        };
+        if deltas.len() == self.capacity {
+            deltas.pop_front();
+        }
+        deltas.push_back(delta);
+    }
+
+    /// The deltas kept, oldest first.
+    pub fn deltas(&self) -> Vec<Duration> {
+        self.deltas
+            .lock()
+            .map(|deltas| deltas.iter().copied().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// The progress of a run, as served at `/stats`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LiveStats {
+    pub elapsed: Duration,
+    pub lines: u64,
+    pub matches: u64,
+    pub bytes: u64,
+    pub last_delta: Option<Duration>,
+    pub recent_deltas: Vec<Duration>,
+    /// The summary of the run so far, as printed at its end.
+    pub summary: String,
+}
+
+impl LiveStats {
+    pub fn to_json(&self) -> String {
+        let last_delta = self
+            .last_delta
+            .map_or("null".to_string(), |delta| delta.as_nanos().to_string());
+        let recent: Vec<String> = self
+            .recent_deltas
+            .iter()
+            .map(|delta| delta.as_nanos().to_string())
+            .collect();
+        format!(
+            r#"{{"elapsed_ns":{},"lines":{},"matches":{},"bytes":{},"last_delta_ns":{},"recent_deltas_ns":[{}],"summary":"{}"}}"#,
+            self.elapsed.as_nanos(),
+            self.lines,
+            self.matches,
+            self.bytes,
+            last_delta,
+            recent.join(","),
+            escape_json(&strip_ansi(&self.summary))
+        )
+    }
+}
+
+/// Removes the ANSI escape sequences coloring `text`.
+fn strip_ansi(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            // Skips the sequence up to its final letter, as in `\x1b[32m`.
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+/// The server of the live stats page.
+#[derive(Debug)]
+pub struct LiveServer {
+    /// The listener, until it is handed to the thread answering.
+    listener: Mutex<Option<TcpListener>>,
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+}
+
+impl LiveServer {
+    /// Listens on `addr`, without answering until `start` is called.
+    pub fn bind(addr: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        Ok(Self {
+            addr: listener.local_addr()?,
+            listener: Mutex::new(Some(listener)),
+            stop: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// The address listened on, which tells the port when any free one was asked for.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Answers the requests from a background thread with the stats returned by `scrape`, until
+    /// `shutdown` is called or `scrape` returns `None`. Only the first call starts answering.
+    pub fn start(&self, scrape: impl Fn() -> Option<LiveStats> + Send + 'static) {
+        let listener = match self.listener.lock() {
+            Ok(mut listener) => listener.take(),
+            Err(_) => None,
+        };
+        let Some(listener) = listener else {
+            return;
+        };
+        let stop = Arc::clone(&self.stop);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stop.load(Ordering::Acquire) {
+                    return;
+                }
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                // A browser that goes away only loses its own response.
+                if let Ok(false) = respond(stream, &scrape) {
+                    return;
+                }
+            }
+        });
+    }
+
+    /// Stops answering, once the current request is answered, and closes the listener.
+    pub fn shutdown(&self) {
+        self.stop.store(true, Ordering::Release);
+        // Wakes the server up from waiting for a connection.
+        let mut wake = self.addr;
+        if wake.ip().is_unspecified() {
+            wake.set_ip(match wake.ip() {
+                IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            });
+        }
+        let _ = TcpStream::connect_timeout(&wake, Duration::from_secs(1));
+    }
+}
+
+/// Answers a request for the page or the stats. Returns whether the run is still going.
+fn respond(stream: TcpStream, scrape: &impl Fn() -> Option<LiveStats>) -> io::Result<bool> {
+    let (method, path, stream) = read_request(stream)?;
+    let mut running = true;
+    let (status, content_type, body) = match (method.as_deref(), path.as_deref()) {
+        (Some("GET"), Some("/")) => ("200 OK", "text/html; charset=utf-8", PAGE.to_string()),
+        (Some("GET"), Some("/stats")) => match scrape() {
+            Some(stats) => ("200 OK", "application/json", stats.to_json()),
+            None => {
+                running = false;
+                (
+                    "503 Service Unavailable",
+                    "text/plain; charset=utf-8",
+                    "the run is over\n".to_string(),
+                )
+            }
+        },
+        (Some("GET"), _) => (
+            "404 Not Found",
+            "text/plain; charset=utf-8",
+            "only / and /stats are served\n".to_string(),
+        ),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain; charset=utf-8",
+            "only GET is supported\n".to_string(),
+        ),
+    };
+    write_response(stream, status, content_type, &body)?;
+    Ok(running)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::tests::get;
+
+    #[test]
+    fn test_recent_deltas_keep_latest() {
+        let recent = RecentDeltas::new(3);
+        assert!(recent.deltas().is_empty());
+        for secs in 1..=5 {
+            recent.push(Duration::from_secs(secs));
+        }
+        assert_eq!(recent.deltas(), [3, 4, 5].map(Duration::from_secs).to_vec());
+    }
+
+    #[test]
+    fn test_stats_json() {
+        let stats = LiveStats {
+            elapsed: Duration::from_millis(1500),
+            lines: 3,
+            matches: 1,
+            bytes: 24,
+            last_delta: None,
+            recent_deltas: vec![Duration::from_millis(5)],
+            summary: "\u{1b}[32m[Processed Lines: 3]\u{1b}[0m".to_string(),
+        };
+        assert_eq!(
+            stats.to_json(),
+            r#"{"elapsed_ns":1500000000,"lines":3,"matches":1,"bytes":24,"last_delta_ns":null,"recent_deltas_ns":[5000000],"summary":"[Processed Lines: 3]"}"#
+        );
+        let parsed: serde_json::Value = serde_json::from_str(&stats.to_json()).unwrap();
+        assert_eq!(parsed["lines"], 3);
+    }
+
+    #[test]
+    fn test_serve_page_and_stats_until_shutdown() {
+        let server = LiveServer::bind("127.0.0.1:0").unwrap();
+        server.start(|| {
+            Some(LiveStats {
+                lines: 7,
+                ..LiveStats::default()
+            })
+        });
+        let page = get(server.addr(), "/");
+        assert!(page.starts_with("HTTP/1.1 200 OK\r\n"), "{}", page);
+        assert!(page.contains("Content-Type: text/html"), "{}", page);
+        assert!(page.contains("fetch(\"/stats\")"), "{}", page);
+        let stats = get(server.addr(), "/stats");
+        let (head, body) = stats.split_once("\r\n\r\n").unwrap();
+        assert!(head.contains("Content-Type: application/json"), "{}", head);
+        let parsed: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(parsed["lines"], 7);
+        assert!(get(server.addr(), "/other").starts_with("HTTP/1.1 404 "));
+
+        let addr = server.addr();
+        server.shutdown();
+        // The listener is closed once the thread has stopped.
+        let refused = (0..100).any(|_| {
+            thread::sleep(Duration::from_millis(10));
+            TcpStream::connect(addr).is_err()
+        });
+        assert!(refused);
+    }
+}
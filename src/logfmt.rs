@@ -0,0 +1,203 @@
+//! This module parses and formats logfmt, the `key=value` format of many structured logs, such as
+//! `ts=1709553600 level=info msg="request done" took=0.25 cached`.
+//!
+//! A value is bare, up to the next whitespace, or quoted, with `\"`, `\\`, `\n`, `\r` and `\t`
+//! escapes. A key without `=` is a bare key, with no value; it is kept so that the line can be
+//! formatted back. Parsing never fails: an unterminated quote ends the line, and a stray `=` or
+//! `"` is skipped.
+//!
+//! # Example
+//!
+//! ```
+//! use timeln::logfmt::{format, get, parse};
+//!
+//! let pairs = parse(r#"level=info msg="request \"a\" done" cached"#);
+//! assert_eq!(get(&pairs, "msg"), Some(r#"request "a" done"#));
+//! assert_eq!(pairs[2].value, None);
+//! assert_eq!(format(&pairs), r#"level=info msg="request \"a\" done" cached"#);
+//! ```
+
+/// A key of a logfmt line and its value, `None` for a bare key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pair {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+impl Pair {
+    pub fn new(key: &str, value: Option<&str>) -> Self {
+        Self {
+            key: key.to_string(),
+            value: value.map(str::to_string),
+        }
+    }
+}
+
+/// Parses the pairs of a logfmt `line`, in order.
+pub fn parse(line: &str) -> Vec<Pair> {
+    let mut pairs = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(&first) = chars.peek() else {
+            return pairs;
+        };
+        if first == '=' || first == '"' {
+            // Not the start of a key.
+            chars.next();
+            continue;
+        }
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|&c| !c.is_whitespace() && c != '=' && c != '"') {
+            key.push(c);
+        }
+        if chars.next_if_eq(&'=').is_none() {
+            pairs.push(Pair { key, value: None });
+            continue;
+        }
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => match chars.next() {
+                        Some('n') => value.push('\n'),
+                        Some('r') => value.push('\r'),
+                        Some('t') => value.push('\t'),
+                        Some(escaped) => value.push(escaped),
+                        None => value.push('\\'),
+                    },
+                    c => value.push(c),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                value.push(c);
+            }
+        }
+        pairs.push(Pair {
+            key,
+            value: Some(value),
+        });
+    }
+}
+
+/// The value of the first pair with `key`, if it has one.
+pub fn get<'a>(pairs: &'a [Pair], key: &str) -> Option<&'a str> {
+    pairs
+        .iter()
+        .find(|pair| pair.key == key)
+        .and_then(|pair| pair.value.as_deref())
+}
+
+/// Formats `pairs` as a logfmt line, quoting the values that need it.
+pub fn format(pairs: &[Pair]) -> String {
+    let mut line = String::new();
+    for pair in pairs {
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&pair.key);
+        let Some(value) = &pair.value else {
+            continue;
+        };
+        line.push('=');
+        let quoted = value
+            .chars()
+            .any(|c| c.is_whitespace() || c == '=' || c == '"' || c == '\\');
+        if !quoted {
+            line.push_str(value);
+            continue;
+        }
+        line.push('"');
+        for c in value.chars() {
+            match c {
+                '"' => line.push_str("\\\""),
+                '\\' => line.push_str("\\\\"),
+                '\n' => line.push_str("\\n"),
+                '\r' => line.push_str("\\r"),
+                '\t' => line.push_str("\\t"),
+                c => line.push(c),
+            }
+        }
+        line.push('"');
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bare_and_quoted_values() {
+        assert_eq!(
+            parse(r#"ts=2 level=info msg="took a while" empty= q="""#),
+            [
+                Pair::new("ts", Some("2")),
+                Pair::new("level", Some("info")),
+                Pair::new("msg", Some("took a while")),
+                Pair::new("empty", Some("")),
+                Pair::new("q", Some("")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_escapes() {
+        let pairs = parse(r#"msg="say \"hi\"\n\tback\\slash" path=C:\dir"#);
+        assert_eq!(get(&pairs, "msg"), Some("say \"hi\"\n\tback\\slash"));
+        assert_eq!(get(&pairs, "path"), Some("C:\\dir"));
+    }
+
+    #[test]
+    fn test_bare_keys() {
+        assert_eq!(
+            parse("cached  retry=1 done"),
+            [
+                Pair::new("cached", None),
+                Pair::new("retry", Some("1")),
+                Pair::new("done", None),
+            ]
+        );
+        assert_eq!(get(&parse("cached"), "cached"), None);
+    }
+
+    #[test]
+    fn test_malformed_input() {
+        assert_eq!(parse(""), []);
+        assert_eq!(parse("   \t"), []);
+        assert_eq!(parse("=x"), [Pair::new("x", None)]);
+        assert_eq!(
+            parse(r#"a=1 msg="unterminated"#),
+            [
+                Pair::new("a", Some("1")),
+                Pair::new("msg", Some("unterminated"))
+            ]
+        );
+        assert_eq!(
+            parse(r#"a"b=1"#),
+            [Pair::new("a", None), Pair::new("b", Some("1"))]
+        );
+    }
+
+    #[test]
+    fn test_first_value_wins() {
+        let pairs = parse("k=1 k=2");
+        assert_eq!(get(&pairs, "k"), Some("1"));
+        assert_eq!(get(&pairs, "missing"), None);
+    }
+
+    #[test]
+    fn test_format_round_trips() {
+        for line in [
+            r#"ts=2 msg="took a while" cached"#,
+            r#"msg="say \"hi\"\n" path="C:\\dir" eq="a=b""#,
+            "empty=",
+        ] {
+            assert_eq!(format(&parse(line)), line);
+        }
+        // Spacing and needless quotes are normalized.
+        assert_eq!(format(&parse(r#"  a="1"   b"#)), "a=1 b");
+    }
+}
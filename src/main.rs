@@ -6,47 +6,256 @@
 //! If no regex pattern is provided, the program times every line.
 //! If a regex pattern is provided, it times and prints only the lines that match the regex pattern.
 //!
-//! The utility uses the `StructOpt` crate for parsing command line arguments and the `regex` crate for matching regular expressions.
+//! The utility uses the `clap` crate for parsing command line arguments and the `regex` crate for matching regular expressions.
 //! It also uses the `colored` crate to colorize the output.
+//! The timing pipeline itself lives in the `timeln` library crate, which can be embedded in other tools; this binary
+//! parses the options, runs the `plot` and `watch` subcommands, and maps the outcome of a run to its exit status.
 //!
 //! # Usage
 //! To use this utility, compile it and run it from the command line.
 //! You can provide input directly from the command line or pipe input from another command.
-//!
-//! You can use the -c or --color option to enable colorization of the timing information.
-//! Use the -r or --regex option followed by a regex pattern to time and print only the lines that match the pattern.
-//! Use the -p or --plot option to generate svg plots of the cumulative time elapsed and the deltas.
+//! Every option is described by `timeln --help`, and in more detail in the README.
 //!
 //! ## Example
 //!     python your_script.py | timeln -c
 //!     python your_script.py | timeln -r "your_regex_pattern"
 //!     python your_script.py | timeln -r -p
+//!     timeln -c -- cargo build
+//!     cargo build 2>&1 | timeln --record build.rec
+//!     timeln plot build.rec --plot-log-y
 //!
 //! The script prints the elapsed time and the delta time between lines or regex matches in the format `"[time: XX.XX s, delta: XX.XX s]"`.
 //! If colorization is enabled, the timing information is printed in green and the matched strings are printed in red.
 
-mod annotator;
-mod argopt;
-mod error;
-mod formatter;
-mod plot;
-mod reader;
-mod summarizer;
-mod timeln;
+use std::sync::{Arc, Mutex};
+
+use clap::{CommandFactory, FromArgMatches, Parser};
+
+use timeln::argopt::{PlotOpt, TimelnOpt, WatchOpt};
+use timeln::build_info::BuildInfo;
+use timeln::formatter::SecondsFormat;
+use timeln::watch::{WatchHistory, Watcher, DEFAULT_WATCH_INTERVAL};
+use timeln::{signal, TimelnContext, TimelnContextBuilder, TimelnError};
+
+/// Exit status of a run with --exit-code whose regex patterns matched no line.
+const EXIT_NO_MATCH_STATUS: i32 = 1;
+
+/// Exit status of invalid options or regex patterns.
+const EXIT_USAGE_STATUS: i32 = 2;
+
+/// Exit status when the input could not be read, or was ended by --exit-idle, so that CI can tell a
+/// hung producer from one that finished.
+const EXIT_INPUT_STATUS: i32 = 3;
+
+/// Exit status when the output, a plot, the plot data, the report or another written file failed.
+const EXIT_OUTPUT_STATUS: i32 = 4;
+
+/// Exit status of a run interrupted by a signal, after printing the summary of the lines so far.
+const EXIT_INTERRUPTED_STATUS: i32 = 130;
+
+/// Exit status of an internal failure, such as a poisoned lock, that no option or input explains.
+const EXIT_INTERNAL_STATUS: i32 = 70;
+
+/// The exit status of a run that failed with `err`.
+fn exit_code(err: &TimelnError) -> i32 {
+    match err {
+        TimelnError::Usage(_)
+        | TimelnError::InvalidDuration { .. }
+        | TimelnError::RegexCompile { .. }
+        | TimelnError::MultiplePatternErrors(_) => EXIT_USAGE_STATUS,
+        TimelnError::InputIo { .. }
+        | TimelnError::Command { .. }
+        | TimelnError::RecordParse { .. } => EXIT_INPUT_STATUS,
+        // Reading stdin fails with `InputIo`, so a bare I/O error is a failed write of the output.
+        TimelnError::Io(_)
+        | TimelnError::OutputIo { .. }
+        | TimelnError::PlotRender { .. }
+        | TimelnError::PlotData { .. }
+        | TimelnError::Report { .. } => EXIT_OUTPUT_STATUS,
+        TimelnError::MutexPoisonedError(_) | TimelnError::BoxError(_) => EXIT_INTERNAL_STATUS,
+    }
+}
+
+/// Exits after printing the help or version requested, or the invalid options with the usage
+/// status.
+fn exit_with(err: clap::Error) -> ! {
+    if err.use_stderr() {
+        eprint!("{}", err.render());
+        std::process::exit(EXIT_USAGE_STATUS);
+    }
+    err.exit()
+}
+
+/// Runs `timeln plot`, which plots and summarizes a record file without printing its lines.
+fn plot() -> Result<i32, TimelnError> {
+    // The flattened options would otherwise override the name and description of the subcommand.
+    let matches = PlotOpt::command()
+        .bin_name("timeln plot")
+        .about(
+            "Plots and summarizes a record file written with --record, or a CSV file of timings.",
+        )
+        .try_get_matches_from(std::env::args().skip(1))
+        .unwrap_or_else(|err| exit_with(err));
+    let PlotOpt { path, opt } =
+        PlotOpt::from_arg_matches(&matches).unwrap_or_else(|err| exit_with(err));
+    let mut context = TimelnContextBuilder::from(opt)
+        .replay(Some(path))
+        .instant_replay(true)
+        .quiet(true)
+        .plot(true)
+        .build()?;
+    context.run()?;
+    context.summarize_and_plot()?;
+    Ok(0)
+}
+
+/// Runs `timeln watch`, which runs a command again and again and compares the times of its runs.
+fn watch() -> Result<i32, TimelnError> {
+    let matches = WatchOpt::command()
+        .bin_name("timeln watch")
+        .about("Runs a command on an interval or when files change, and compares its runs.")
+        .try_get_matches_from(std::env::args().skip(1))
+        .unwrap_or_else(|err| exit_with(err));
+    let parse = || WatchOpt::from_arg_matches(&matches).unwrap_or_else(|err| exit_with(err));
+    let WatchOpt {
+        interval,
+        watch_path,
+        runs,
+        opt,
+    } = parse();
+    if opt.command.is_empty() {
+        let err = "timeln watch needs a command to run, as in `timeln watch -- make`";
+        return Err(TimelnError::Usage(err.to_string()));
+    }
+    let mut watcher = Watcher::new(watch_path, interval.unwrap_or(DEFAULT_WATCH_INTERVAL));
+    let history = Arc::new(Mutex::new(WatchHistory::default()));
+    {
+        // The runs so far are tabulated on Ctrl-C, which also stops the command.
+        let history = Arc::clone(&history);
+        signal::install(move |_| {
+            let history = history.lock().unwrap_or_else(|err| err.into_inner());
+            println!("{}", history.table(&SecondsFormat));
+            std::process::exit(EXIT_INTERRUPTED_STATUS);
+        })?;
+    }
+    loop {
+        // The options are parsed again for each run, as the context takes them.
+        let opt = parse().opt;
+        let mut context = TimelnContextBuilder::from(opt).quiet(true).build()?;
+        watcher.mark();
+        context.run()?;
+        let totals = context.totals();
+        let mut history = history.lock()?;
+        let iteration = history.push(totals.lines, totals.matches, totals.total_time);
+        println!("{}", history.row(&iteration, context.time_format()));
+        if runs.is_some_and(|runs| iteration.number >= runs) {
+            println!("{}", history.table(context.time_format()));
+            return Ok(0);
+        }
+        drop(history);
+        watcher.wait();
+    }
+}
 
-use structopt::StructOpt;
+fn main() {
+    let result = match std::env::args().nth(1).as_deref() {
+        Some("plot") => plot(),
+        Some("watch") => watch(),
+        _ => run(),
+    };
+    match result {
+        Ok(status) => std::process::exit(status),
+        Err(err) => {
+            eprintln!("timeln: {}", err);
+            std::process::exit(exit_code(&err));
+        }
+    }
+}
 
-use crate::argopt::TimelnOpt;
-use crate::error::TimelnError;
-use crate::timeln::TimelnContext;
+/// Describes the build and the environment of timeln, to paste into a bug report.
+fn bug_report() -> String {
+    let mut report = format!(
+        "{}\nos: {} {}\n",
+        BuildInfo::current(),
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    );
+    let mut settings: Vec<(String, String)> = std::env::vars()
+        .filter(|(name, _)| name.starts_with("TIMELN_"))
+        .collect();
+    settings.sort();
+    for (name, value) in settings {
+        report.push_str(&format!("{}={}\n", name, value));
+    }
+    report
+}
 
-fn main() -> Result<(), TimelnError> {
-    let opt = TimelnOpt::from_args();
+/// Times the input, and returns the exit status of a run that did not fail.
+fn run() -> Result<i32, TimelnError> {
+    let opt = TimelnOpt::try_parse_from(std::env::args()).unwrap_or_else(|err| exit_with(err));
+    if opt.bugreport {
+        print!("{}", bug_report());
+        return Ok(0);
+    }
+    let exit_idle = opt.exit_idle;
+    let exit_on_no_match = opt.exit_code;
     let mut context = TimelnContext::new(opt)?;
+    context.handle_signals(EXIT_INTERRUPTED_STATUS)?;
+    context.handle_keys(0)?;
+    if let Some(addr) = context.metrics_addr() {
+        eprintln!("timeln: serving metrics on http://{}/metrics", addr);
+    }
+    if let Some(addr) = context.live_addr() {
+        eprintln!("timeln: serving live stats on http://{}/", addr);
+    }
 
     context.run()?;
 
     context.summarize_and_plot()?;
 
-    Ok(())
+    if context.idle_timed_out() {
+        if let Some(timeout) = exit_idle {
+            eprintln!("timeln: no input for {:?}, exiting", timeout);
+        }
+        return Ok(EXIT_INPUT_STATUS);
+    }
+    if exit_on_no_match && context.total_matches() == 0 {
+        return Ok(EXIT_NO_MATCH_STATUS);
+    }
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn test_exit_code() {
+        let io_error = || io::Error::from(io::ErrorKind::NotFound);
+        assert_eq!(exit_code(&TimelnError::Usage("no".to_string())), 2);
+        let regex = regex::Error::Syntax("unclosed group".to_string());
+        assert_eq!(exit_code(&TimelnError::regex("(", regex)), 2);
+        let patterns = TimelnError::MultiplePatternErrors(Vec::new());
+        assert_eq!(exit_code(&patterns), 2);
+        assert_eq!(exit_code(&TimelnError::input("in.log", io_error())), 3);
+        let record = TimelnError::RecordParse {
+            path: "run.rec".to_string(),
+            line: 3,
+            source: io_error(),
+        };
+        assert_eq!(exit_code(&record), 3);
+        assert_eq!(exit_code(&TimelnError::Io(io_error())), 4);
+        assert_eq!(exit_code(&TimelnError::output("out.rec", io_error())), 4);
+        assert_eq!(exit_code(&TimelnError::plot("deltas.svg", io_error())), 4);
+        assert_eq!(
+            exit_code(&TimelnError::plot_data("data.csv", io_error())),
+            4
+        );
+        assert_eq!(
+            exit_code(&TimelnError::report("report.html", io_error())),
+            4
+        );
+        assert_eq!(exit_code(&TimelnError::BoxError("other".into())), 70);
+    }
 }
@@ -0,0 +1,479 @@
+//! This module exports the counters of a running timeln as Prometheus metrics.
+//!
+//! The metrics are rendered in the Prometheus text exposition format, and either written to a
+//! textfile picked up by the textfile collector of node_exporter, or served over HTTP at
+//! `/metrics`:
+//!
+//! - `timeln_lines_total`: lines read.
+//! - `timeln_matches_total`: lines matching a regex pattern, with a `pattern` label per pattern
+//!   when the patterns are labelled or there are several of them.
+//! - `timeln_bytes_total`: bytes read.
+//! - `timeln_last_delta_seconds`: delta of the latest timed line, once a line was timed.
+//! - `timeln_elapsed_seconds`: time since the start of the run.
+//! - `timeln_delta_seconds`: a summary of the deltas of the timed lines, with their count, sum and
+//!   approximate 0.5, 0.95 and 0.99 quantiles, once a line was timed.
+//!
+//! The textfile is written to a temporary file next to it, then renamed over it, so that the
+//! collector never reads a partly written file.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use timeln::metrics::Metrics;
+//!
+//! let metrics = Metrics {
+//!     lines: 3,
+//!     elapsed: Duration::from_millis(1500),
+//!     ..Metrics::default()
+//! };
+//! assert!(metrics.render().contains("timeln_lines_total 3\n"));
+//! ```
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use crate::progress::Progress;
+use crate::stats::{RunningStats, REFERENCE_PERCENTILES};
+
+/// Interval between two writes of the textfile, unless changed.
+pub const DEFAULT_TEXTFILE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Time a scraper has to send its request before the connection is dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The counters of a run, as exported to Prometheus.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metrics {
+    pub lines: u64,
+    pub matches: u64,
+    /// Matches of each labelled regex pattern, which then replace the unlabelled total.
+    pub pattern_matches: Vec<(String, u64)>,
+    pub bytes: u64,
+    pub last_delta: Option<Duration>,
+    pub elapsed: Duration,
+    /// The length of the sliding window and the lines per second that arrived in it, if measured.
+    pub recent_rate: Option<(Duration, f64)>,
+    /// The distribution of the deltas, once a line was timed.
+    pub deltas: Option<DeltaSummary>,
+    /// The progress towards the matches expected with `--total-matches`, if given.
+    pub progress: Option<Progress>,
+}
+
+/// The distribution of the deltas of a run, from its streaming statistics, so that it takes the
+/// same memory however long the run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeltaSummary {
+    pub count: u64,
+    pub sum: Duration,
+    /// Approximate quantiles (0 to 1) of the deltas, for the `REFERENCE_PERCENTILES`.
+    pub quantiles: Vec<(f64, Duration)>,
+}
+
+impl DeltaSummary {
+    /// Summarizes the deltas of `stats`, in seconds, or returns `None` if there are none.
+    pub fn of(stats: &RunningStats) -> Option<Self> {
+        let seconds = |secs: f64| Duration::from_secs_f64(secs.max(0.0));
+        let quantiles = REFERENCE_PERCENTILES
+            .iter()
+            .map(|p| Some((p / 100.0, seconds(stats.percentile(*p)?))))
+            .collect::<Option<_>>()?;
+        Some(Self {
+            count: stats.count(),
+            sum: seconds(stats.sum()),
+            quantiles,
+        })
+    }
+}
+
+impl Metrics {
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        family(&mut out, "timeln_lines_total", "counter", "Lines read.");
+        sample(&mut out, "timeln_lines_total", None, self.lines as f64);
+        family(
+            &mut out,
+            "timeln_matches_total",
+            "counter",
+            "Lines matching a regex pattern.",
+        );
+        if self.pattern_matches.is_empty() {
+            sample(&mut out, "timeln_matches_total", None, self.matches as f64);
+        }
+        for (label, matches) in &self.pattern_matches {
+            let pattern = Some(("pattern", label.as_str()));
+            sample(&mut out, "timeln_matches_total", pattern, *matches as f64);
+        }
+        family(&mut out, "timeln_bytes_total", "counter", "Bytes read.");
+        sample(&mut out, "timeln_bytes_total", None, self.bytes as f64);
+        family(
+            &mut out,
+            "timeln_last_delta_seconds",
+            "gauge",
+            "Time between the latest timed line and the one before it.",
+        );
+        if let Some(delta) = self.last_delta {
+            let delta = delta.as_secs_f64();
+            sample(&mut out, "timeln_last_delta_seconds", None, delta);
+        }
+        family(
+            &mut out,
+            "timeln_elapsed_seconds",
+            "gauge",
+            "Time since the start of the run.",
+        );
+        let elapsed = self.elapsed.as_secs_f64();
+        sample(&mut out, "timeln_elapsed_seconds", None, elapsed);
+        family(
+            &mut out,
+            "timeln_delta_seconds",
+            "summary",
+            "Time between consecutive timed lines.",
+        );
+        if let Some(deltas) = &self.deltas {
+            for (quantile, delta) in &deltas.quantiles {
+                let quantile = Some(("quantile", &*quantile.to_string()));
+                let delta = delta.as_secs_f64();
+                sample(&mut out, "timeln_delta_seconds", quantile, delta);
+            }
+            let sum = deltas.sum.as_secs_f64();
+            sample(&mut out, "timeln_delta_seconds_sum", None, sum);
+            let count = deltas.count as f64;
+            sample(&mut out, "timeln_delta_seconds_count", None, count);
+        }
+        out
+    }
+}
+
+/// Writes the `# HELP` and `# TYPE` lines of a metric.
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    out.push_str(&format!(
+        "# HELP {} {}\n# TYPE {} {}\n",
+        name, help, name, kind
+    ));
+}
+
+/// Writes a sample of a metric, with an optional label.
+fn sample(out: &mut String, name: &str, label: Option<(&str, &str)>, value: f64) {
+    match label {
+        Some((label, label_value)) => out.push_str(&format!(
+            "{}{{{}=\"{}\"}} {}\n",
+            name,
+            label,
+            escape_label(label_value),
+            value
+        )),
+        None => out.push_str(&format!("{} {}\n", name, value)),
+    }
+}
+
+/// Escapes a label value of the exposition format.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Replaces the textfile at `path` with `metrics`, through a temporary file renamed over it.
+pub fn write_textfile(path: &Path, metrics: &str) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    fs::write(&temporary, metrics)?;
+    fs::rename(&temporary, path)
+}
+
+/// Rewrites the textfile at `path` with the metrics returned by `scrape` every `interval`, from a
+/// background thread, until `scrape` returns `None`. The thread stops at the first failed write,
+/// which is logged to stderr.
+pub fn export_textfile(
+    path: PathBuf,
+    interval: Duration,
+    scrape: impl Fn() -> Option<String> + Send + 'static,
+) {
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        let Some(metrics) = scrape() else {
+            return;
+        };
+        if let Err(err) = write_textfile(&path, &metrics) {
+            eprintln!(
+                "timeln: could not write the metrics to {}, no longer exporting them: {}",
+                path.display(),
+                err
+            );
+            return;
+        }
+    });
+}
+
+/// Serves the metrics returned by `scrape` at `/metrics` on `addr`, from a background thread,
+/// until `scrape` returns `None`. Returns the address actually listened on, which tells the port
+/// when `addr` asks for any free one.
+pub fn serve(
+    addr: &str,
+    scrape: impl Fn() -> Option<String> + Send + 'static,
+) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local = listener.local_addr()?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let metrics = scrape();
+            let stop = metrics.is_none();
+            // A scraper that goes away only loses its own response.
+            let _ = respond(stream, metrics);
+            if stop {
+                return;
+            }
+        }
+    });
+    Ok(local)
+}
+
+/// Answers an HTTP request for `/metrics` with `metrics`, or with 503 if the run is gone.
+fn respond(stream: TcpStream, metrics: Option<String>) -> io::Result<()> {
+    let (method, path, stream) = read_request(stream)?;
+    let (status, body) = match (method.as_deref(), path.as_deref(), metrics) {
+        (Some("GET"), Some("/metrics"), Some(metrics)) => ("200 OK", metrics),
+        (Some("GET"), Some("/metrics"), None) => {
+            ("503 Service Unavailable", "the run is over\n".to_string())
+        }
+        (Some("GET"), _, _) => ("404 Not Found", "only /metrics is served\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "only GET is supported\n".to_string(),
+        ),
+    };
+    write_response(
+        stream,
+        status,
+        "text/plain; version=0.0.4; charset=utf-8",
+        &body,
+    )
+}
+
+/// Reads an HTTP request from `stream`, returning its method and its path without the query.
+pub(crate) fn read_request(
+    stream: TcpStream,
+) -> io::Result<(Option<String>, Option<String>, TcpStream)> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // The headers are not needed, but are read so that closing does not reset the connection.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header.trim_end() != "" {
+        header.clear();
+    }
+    let mut parts = request.split_whitespace();
+    let method = parts.next().map(str::to_string);
+    let path = parts
+        .next()
+        .map(|target| target.split('?').next().unwrap_or_default().to_string());
+    Ok((method, path, reader.into_inner()))
+}
+
+/// Writes an HTTP response of `body` to `stream`, closing the connection.
+pub(crate) fn write_response(
+    mut stream: TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::stats::SKETCH_RELATIVE_ACCURACY;
+    use std::collections::HashMap;
+    use std::io::Read;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    /// Parses the text exposition format into the value of each sample, keyed by its name and
+    /// labels, checking that every sample follows the `# TYPE` line of its metric.
+    pub(crate) fn parse_exposition(text: &str) -> HashMap<String, f64> {
+        let mut samples = HashMap::new();
+        let mut family = None;
+        for line in text.lines() {
+            if let Some(comment) = line.strip_prefix("# ") {
+                let mut parts = comment.splitn(3, ' ');
+                match (parts.next(), parts.next(), parts.next()) {
+                    (Some("TYPE"), Some(name), Some("counter" | "gauge" | "summary")) => {
+                        family = Some(name.to_string())
+                    }
+                    (Some("HELP"), Some(_), Some(_)) => {}
+                    _ => panic!("unexpected comment {:?}", line),
+                }
+                continue;
+            }
+            let (key, value) = line.rsplit_once(' ').expect("sample without a value");
+            let name = key.split('{').next().unwrap();
+            // The count and sum of a summary belong to its family.
+            let family_name = name
+                .strip_suffix("_sum")
+                .or_else(|| name.strip_suffix("_count"))
+                .filter(|base| family.as_deref() == Some(*base))
+                .unwrap_or(name);
+            assert_eq!(
+                Some(family_name),
+                family.as_deref(),
+                "sample out of its family"
+            );
+            assert!(name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':'));
+            samples.insert(key.to_string(), value.parse().expect("invalid value"));
+        }
+        samples
+    }
+
+    /// Sends a GET request for `path` to `addr`, and returns the response.
+    pub(crate) fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_render_exposition_format() {
+        let metrics = Metrics {
+            lines: 10,
+            matches: 4,
+            bytes: 120,
+            last_delta: Some(Duration::from_millis(250)),
+            elapsed: Duration::from_millis(1500),
+            ..Metrics::default()
+        };
+        let samples = parse_exposition(&metrics.render());
+        assert_eq!(samples.len(), 5);
+        assert_eq!(samples["timeln_lines_total"], 10.0);
+        assert_eq!(samples["timeln_matches_total"], 4.0);
+        assert_eq!(samples["timeln_bytes_total"], 120.0);
+        assert_eq!(samples["timeln_last_delta_seconds"], 0.25);
+        assert_eq!(samples["timeln_elapsed_seconds"], 1.5);
+    }
+
+    #[test]
+    fn test_render_delta_summary() {
+        let mut stats = RunningStats::default();
+        assert_eq!(DeltaSummary::of(&stats), None);
+        for delta in [0.5, 1.0, 1.5, 2.0] {
+            stats.push(delta);
+        }
+        let metrics = Metrics {
+            deltas: DeltaSummary::of(&stats),
+            ..Metrics::default()
+        };
+        let rendered = metrics.render();
+        assert!(rendered.contains("# TYPE timeln_delta_seconds summary\n"));
+        let samples = parse_exposition(&rendered);
+        assert_eq!(samples["timeln_delta_seconds_count"], 4.0);
+        assert_eq!(samples["timeln_delta_seconds_sum"], 5.0);
+        for (quantile, exact) in [("0.5", 1.0), ("0.95", 1.5), ("0.99", 1.5)] {
+            let approx = samples[&format!("timeln_delta_seconds{{quantile=\"{}\"}}", quantile)];
+            let tolerance = exact * SKETCH_RELATIVE_ACCURACY + 1e-9;
+            assert!(
+                (approx - exact).abs() <= tolerance,
+                "{}: {}",
+                quantile,
+                approx
+            );
+        }
+    }
+
+    #[test]
+    fn test_render_pattern_labels() {
+        let metrics = Metrics {
+            matches: 3,
+            pattern_matches: vec![("err".to_string(), 1), ("say \"hi\"".to_string(), 2)],
+            ..Metrics::default()
+        };
+        let rendered = metrics.render();
+        let samples = parse_exposition(&rendered);
+        assert_eq!(samples["timeln_matches_total{pattern=\"err\"}"], 1.0);
+        assert_eq!(
+            samples["timeln_matches_total{pattern=\"say \\\"hi\\\"\"}"],
+            2.0
+        );
+        assert!(!samples.contains_key("timeln_matches_total"));
+        // No line was timed yet.
+        assert!(!samples.contains_key("timeln_last_delta_seconds"));
+        assert!(rendered.contains("# TYPE timeln_last_delta_seconds gauge\n"));
+    }
+
+    #[test]
+    fn test_write_textfile_replaces_file() {
+        let dir = std::env::temp_dir().join(format!("timeln-metrics-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("timeln.prom");
+        write_textfile(&path, "old\n").unwrap();
+        write_textfile(&path, "new\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        assert!(!dir.join("timeln.prom.tmp").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_export_textfile_until_run_is_gone() {
+        let dir = std::env::temp_dir().join(format!("timeln-export-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("timeln.prom");
+        let scrapes = Arc::new(AtomicU64::new(0));
+        let counter = Arc::clone(&scrapes);
+        export_textfile(path.clone(), Duration::from_millis(10), move || {
+            let scrape = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            let metrics = Metrics {
+                lines: scrape,
+                ..Metrics::default()
+            };
+            (scrape <= 3).then(|| metrics.render())
+        });
+        while scrapes.load(Ordering::SeqCst) <= 3 {
+            thread::sleep(Duration::from_millis(5));
+        }
+        thread::sleep(Duration::from_millis(50));
+        let samples = parse_exposition(&fs::read_to_string(&path).unwrap());
+        assert_eq!(samples["timeln_lines_total"], 3.0);
+        assert_eq!(scrapes.load(Ordering::SeqCst), 4);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_serve_metrics() {
+        let addr = serve("127.0.0.1:0", || {
+            let metrics = Metrics {
+                lines: 7,
+                ..Metrics::default()
+            };
+            Some(metrics.render())
+        })
+        .unwrap();
+        let response = get(addr, "/metrics");
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{}", head);
+        assert!(head.contains("Content-Type: text/plain; version=0.0.4"));
+        assert_eq!(parse_exposition(body)["timeln_lines_total"], 7.0);
+        assert!(get(addr, "/other").starts_with("HTTP/1.1 404 "));
+    }
+}
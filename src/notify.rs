@@ -0,0 +1,312 @@
+//! This module notifies a webhook of the end of a run, and of lines slower than a threshold, by
+//! POSTing a small JSON payload. Sending needs the `notify` cargo feature.
+//!
+//! The payload has the fields of the `finished` event of `--event-fd`, with the run label and why
+//! the notification was sent:
+//!
+//! - `{"event": "finished", "label": .. | null, "reason": "eof" | "idle-timeout" |
+//!   "output-closed" | "interrupted", "lines": .., "matches": .., "bytes": .., "total_ns": ..}`
+//!   once the run finished.
+//! - `{"event": "threshold", ..., "reason": "threshold", "delta_ns": .., "text": ".."}`, with the
+//!   same fields, for a line slower than the threshold, at most once per interval.
+//!
+//! Each request times out after `NOTIFY_TIMEOUT`, so that a dead endpoint cannot hang the end of
+//! the run, and a failed request is only logged to stderr. The threshold notifications are sent
+//! from a background thread, so as not to slow the run down.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use timeln::notify::{finished_payload, RunEnd};
+//! use timeln::sqlite::RunTotals;
+//!
+//! let totals = RunTotals {
+//!     lines: 3,
+//!     matches: 1,
+//!     bytes: 24,
+//!     total_time: Duration::from_millis(1500),
+//! };
+//! assert_eq!(
+//!     finished_payload(Some("nightly"), &totals, RunEnd::Eof),
+//!     r#"{"event":"finished","label":"nightly","reason":"eof","lines":3,"matches":1,"bytes":24,"total_ns":1500000000}"#
+//! );
+//! ```
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::report::escape_json;
+use crate::sqlite::RunTotals;
+
+/// Shortest interval between two threshold notifications, unless changed.
+pub const DEFAULT_NOTIFY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Longest time a notification may take to be sent.
+pub const NOTIFY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Why a run ended.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RunEnd {
+    /// The input ended.
+    #[default]
+    Eof,
+    /// No line arrived within the `--exit-idle` timeout.
+    IdleTimeout,
+    /// The reader of the output went away.
+    OutputClosed,
+    /// A signal stopped the run.
+    Interrupted,
+}
+
+impl RunEnd {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RunEnd::Eof => "eof",
+            RunEnd::IdleTimeout => "idle-timeout",
+            RunEnd::OutputClosed => "output-closed",
+            RunEnd::Interrupted => "interrupted",
+        }
+    }
+}
+
+/// The fields common to every payload.
+fn payload_fields(event: &str, label: Option<&str>, reason: &str, totals: &RunTotals) -> String {
+    let label = match label {
+        Some(label) => format!("\"{}\"", escape_json(label)),
+        None => "null".to_string(),
+    };
+    format!(
+        r#""event":"{}","label":{},"reason":"{}","lines":{},"matches":{},"bytes":{},"total_ns":{}"#,
+        event,
+        label,
+        reason,
+        totals.lines,
+        totals.matches,
+        totals.bytes,
+        totals.total_time.as_nanos()
+    )
+}
+
+/// Formats the payload sent when a run labelled `label` ended for `end`, with `totals`.
+pub fn finished_payload(label: Option<&str>, totals: &RunTotals, end: RunEnd) -> String {
+    format!(
+        "{{{}}}",
+        payload_fields("finished", label, end.as_str(), totals)
+    )
+}
+
+/// Formats the payload sent when the line `text` of a run labelled `label` took `delta`.
+pub fn threshold_payload(
+    label: Option<&str>,
+    totals: &RunTotals,
+    delta: Duration,
+    text: &str,
+) -> String {
+    format!(
+        r#"{{{},"delta_ns":{},"text":"{}"}}"#,
+        payload_fields("threshold", label, "threshold", totals),
+        delta.as_nanos(),
+        escape_json(text)
+    )
+}
+
+/// Sends the notifications of a run to a webhook.
+#[derive(Debug)]
+pub struct Notifier {
+    url: String,
+    label: Option<String>,
+    slower_than: Option<Duration>,
+    interval: Duration,
+    /// When the latest threshold notification was sent.
+    last_alert: Mutex<Option<Instant>>,
+}
+
+impl Notifier {
+    /// Creates a notifier POSTing to `url` for a run labelled `label`, also notifying of the lines
+    /// slower than `slower_than`, at most once per `interval`.
+    pub fn new(
+        url: &str,
+        label: Option<String>,
+        slower_than: Option<Duration>,
+        interval: Duration,
+    ) -> Self {
+        Self {
+            url: url.to_string(),
+            label,
+            slower_than,
+            interval,
+            last_alert: Mutex::new(None),
+        }
+    }
+
+    /// Whether a line that took `delta` is slower than the threshold.
+    pub fn is_slow(&self, delta: Duration) -> bool {
+        self.slower_than.is_some_and(|threshold| delta > threshold)
+    }
+
+    /// Notifies of the end of the run, waiting for the request to be sent or to time out.
+    pub fn finished(&self, totals: &RunTotals, end: RunEnd) {
+        let payload = finished_payload(self.label.as_deref(), totals, end);
+        if let Err(err) = post(&self.url, &payload, NOTIFY_TIMEOUT) {
+            eprintln!("timeln: could not notify {}: {}", self.url, err);
+        }
+    }
+
+    /// Notifies, in the background, of the line `text` that took `delta` at `now`, unless a line
+    /// was notified less than the interval before. Returns whether the line is notified.
+    pub fn threshold(&self, totals: &RunTotals, delta: Duration, text: &str, now: Instant) -> bool {
+        let Ok(mut last_alert) = self.last_alert.lock() else {
+            return false;
+        };
+        if last_alert.is_some_and(|last| now.saturating_duration_since(last) < self.interval) {
+            return false;
+        }
+        *last_alert = Some(now);
+        let payload = threshold_payload(self.label.as_deref(), totals, delta, text);
+        let url = self.url.clone();
+        thread::spawn(move || {
+            if let Err(err) = post(&url, &payload, NOTIFY_TIMEOUT) {
+                eprintln!("timeln: could not notify {}: {}", url, err);
+            }
+        });
+        true
+    }
+}
+
+/// POSTs the JSON `payload` to `url`, failing after `timeout`.
+#[cfg(feature = "notify")]
+fn post(url: &str, payload: &str, timeout: Duration) -> Result<(), String> {
+    let agent = ureq::AgentBuilder::new().timeout(timeout).build();
+    agent
+        .post(url)
+        .set("Content-Type", "application/json")
+        .send_string(payload)
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+/// Fails, as timeln was built without the `notify` feature.
+#[cfg(not(feature = "notify"))]
+fn post(_: &str, _: &str, _: Duration) -> Result<(), String> {
+    Err("timeln was built without the notify feature".to_string())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Starts a webhook on a free local port answering each request with `status`, and returns
+    /// its URL and the bodies of the requests it receives.
+    #[cfg(feature = "notify")]
+    pub(crate) fn webhook(status: u16) -> (String, std::sync::mpsc::Receiver<String>) {
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::net::TcpListener;
+        use std::sync::mpsc;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, bodies) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else {
+                    return;
+                };
+                let mut reader = BufReader::new(stream);
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    if reader.read_line(&mut header).unwrap_or(0) == 0 {
+                        break;
+                    }
+                    let header = header.trim_end().to_ascii_lowercase();
+                    if header.is_empty() {
+                        break;
+                    }
+                    if let Some(value) = header.strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let response = format!(
+                    "HTTP/1.1 {} Done\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+                let _ = reader.get_mut().write_all(response.as_bytes());
+                if sender.send(String::from_utf8(body).unwrap()).is_err() {
+                    return;
+                }
+            }
+        });
+        (url, bodies)
+    }
+
+    fn totals() -> RunTotals {
+        RunTotals {
+            lines: 2,
+            matches: 0,
+            bytes: 10,
+            total_time: Duration::from_secs(3),
+        }
+    }
+
+    #[test]
+    fn test_payloads() {
+        assert_eq!(
+            finished_payload(None, &totals(), RunEnd::Interrupted),
+            r#"{"event":"finished","label":null,"reason":"interrupted","lines":2,"matches":0,"bytes":10,"total_ns":3000000000}"#
+        );
+        assert_eq!(
+            threshold_payload(
+                Some("a \"b\""),
+                &totals(),
+                Duration::from_secs(2),
+                "slow\tstep"
+            ),
+            r#"{"event":"threshold","label":"a \"b\"","reason":"threshold","lines":2,"matches":0,"bytes":10,"total_ns":3000000000,"delta_ns":2000000000,"text":"slow\u0009step"}"#
+        );
+    }
+
+    #[test]
+    fn test_threshold_is_rate_limited() {
+        let interval = Duration::from_secs(60);
+        let notifier = Notifier::new(
+            "http://127.0.0.1:9/",
+            None,
+            Some(Duration::from_secs(1)),
+            interval,
+        );
+        assert!(!notifier.is_slow(Duration::from_secs(1)));
+        assert!(notifier.is_slow(Duration::from_millis(1001)));
+        let now = Instant::now();
+        assert!(notifier.threshold(&totals(), Duration::from_secs(2), "a", now));
+        assert!(!notifier.threshold(&totals(), Duration::from_secs(2), "b", now + interval / 2));
+        assert!(notifier.threshold(&totals(), Duration::from_secs(2), "c", now + interval));
+    }
+
+    #[cfg(feature = "notify")]
+    #[test]
+    fn test_notifications_arrive() {
+        let (url, bodies) = webhook(200);
+        let notifier = Notifier::new(&url, Some("ci".to_string()), None, DEFAULT_NOTIFY_INTERVAL);
+        notifier.threshold(&totals(), Duration::from_secs(2), "slow", Instant::now());
+        let threshold = bodies.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(threshold.starts_with(r#"{"event":"threshold","label":"ci""#));
+        notifier.finished(&totals(), RunEnd::Eof);
+        let finished = bodies.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(
+            finished,
+            finished_payload(Some("ci"), &totals(), RunEnd::Eof)
+        );
+    }
+
+    #[cfg(feature = "notify")]
+    #[test]
+    fn test_failed_request_is_reported() {
+        let (url, _bodies) = webhook(500);
+        assert!(post(&url, "{}", NOTIFY_TIMEOUT).is_err());
+        // Nothing listens on the discard port.
+        assert!(post("http://127.0.0.1:9/", "{}", NOTIFY_TIMEOUT).is_err());
+    }
+}
@@ -0,0 +1,271 @@
+//! This module writes the snapshots of a run as a Parquet file, for columnar analysis with data
+//! science tools. It needs the `parquet` cargo feature.
+//!
+//! The file has one row per timed line, with the columns `line_no` (UInt64), `elapsed_s` and
+//! `delta_s` (Float64), `matched` (Boolean), `pattern` (nullable Utf8, the label of the matched
+//! regex pattern) and `bytes` (UInt64). The key-value metadata of the file describes the run:
+//! `timeln.start_unix_ms` and `timeln.argv`, the command line as a JSON array.
+//!
+//! The rows are buffered in Arrow record batches of `batch_rows` rows, each written out as a row
+//! group once full, so that the memory used does not grow with the length of the run. The file
+//! is complete once `finish` wrote the last batch and the footer.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use timeln::parquet::ParquetWriter;
+//! use timeln::TimeSnapshot;
+//!
+//! let mut writer = ParquetWriter::create("snapshots.parquet", vec!["timeln".to_string()], 8192)?;
+//! writer.push(&TimeSnapshot::default(), None)?;
+//! writer.finish()?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+pub use writer::ParquetWriter;
+
+/// Number of rows of each record batch, unless changed.
+pub const DEFAULT_BATCH_ROWS: usize = 8192;
+
+#[cfg(feature = "parquet")]
+mod writer {
+    use std::fs::File;
+    use std::io;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use ::parquet::arrow::ArrowWriter;
+    use ::parquet::file::metadata::KeyValue;
+    use ::parquet::file::properties::WriterProperties;
+    use arrow_array::builder::{
+        ArrayBuilder, BooleanBuilder, Float64Builder, StringBuilder, UInt64Builder,
+    };
+    use arrow_array::{ArrayRef, RecordBatch};
+    use arrow_schema::{DataType, Field, Schema, SchemaRef};
+
+    use crate::report::escape_json;
+    use crate::timeln::TimeSnapshot;
+
+    /// Wraps a Parquet or Arrow error as an I/O error, since the Parquet file is an output file.
+    fn io_error(err: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+        io::Error::other(err)
+    }
+
+    /// The columns of the record batch being filled.
+    #[derive(Default)]
+    struct Columns {
+        line_no: UInt64Builder,
+        elapsed_s: Float64Builder,
+        delta_s: Float64Builder,
+        matched: BooleanBuilder,
+        pattern: StringBuilder,
+        bytes: UInt64Builder,
+    }
+
+    impl Columns {
+        fn len(&self) -> usize {
+            self.line_no.len()
+        }
+
+        /// Takes the rows appended so far as a record batch, leaving the columns empty.
+        fn finish(&mut self, schema: &SchemaRef) -> Result<RecordBatch, arrow_schema::ArrowError> {
+            let columns: Vec<ArrayRef> = vec![
+                Arc::new(self.line_no.finish()),
+                Arc::new(self.elapsed_s.finish()),
+                Arc::new(self.delta_s.finish()),
+                Arc::new(self.matched.finish()),
+                Arc::new(self.pattern.finish()),
+                Arc::new(self.bytes.finish()),
+            ];
+            RecordBatch::try_new(Arc::clone(schema), columns)
+        }
+    }
+
+    /// The snapshots of a run being written to a Parquet file.
+    pub struct ParquetWriter {
+        writer: ArrowWriter<File>,
+        schema: SchemaRef,
+        columns: Columns,
+        batch_rows: usize,
+    }
+
+    impl std::fmt::Debug for ParquetWriter {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("ParquetWriter")
+                .field("rows", &self.columns.len())
+                .field("batch_rows", &self.batch_rows)
+                .finish()
+        }
+    }
+
+    impl ParquetWriter {
+        /// Creates the Parquet file `path` for a run with command line `argv` starting now,
+        /// writing the rows in record batches of `batch_rows` rows.
+        pub fn create(
+            path: impl AsRef<Path>,
+            argv: Vec<String>,
+            batch_rows: usize,
+        ) -> io::Result<Self> {
+            let schema = Arc::new(Schema::new(vec![
+                Field::new("line_no", DataType::UInt64, false),
+                Field::new("elapsed_s", DataType::Float64, false),
+                Field::new("delta_s", DataType::Float64, false),
+                Field::new("matched", DataType::Boolean, false),
+                Field::new("pattern", DataType::Utf8, true),
+                Field::new("bytes", DataType::UInt64, false),
+            ]));
+            let start_unix_ms = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            let argv: Vec<String> = argv
+                .iter()
+                .map(|arg| format!("\"{}\"", escape_json(arg)))
+                .collect();
+            let batch_rows = batch_rows.max(1);
+            let properties = WriterProperties::builder()
+                .set_max_row_group_row_count(Some(batch_rows))
+                .set_key_value_metadata(Some(vec![
+                    KeyValue::new(
+                        "timeln.start_unix_ms".to_string(),
+                        start_unix_ms.to_string(),
+                    ),
+                    KeyValue::new("timeln.argv".to_string(), format!("[{}]", argv.join(","))),
+                ]))
+                .build();
+            let file = File::create(path)?;
+            let writer = ArrowWriter::try_new(file, Arc::clone(&schema), Some(properties))
+                .map_err(io_error)?;
+            Ok(Self {
+                writer,
+                schema,
+                columns: Columns::default(),
+                batch_rows,
+            })
+        }
+
+        /// Adds a snapshot of the run, which matched the regex pattern labelled `pattern` if any.
+        pub fn push(&mut self, snapshot: &TimeSnapshot, pattern: Option<&str>) -> io::Result<()> {
+            let columns = &mut self.columns;
+            columns.line_no.append_value(snapshot.line_no as u64);
+            columns
+                .elapsed_s
+                .append_value(snapshot.elapsed.as_secs_f64());
+            columns.delta_s.append_value(snapshot.delta.as_secs_f64());
+            columns.matched.append_value(snapshot.pattern.is_some());
+            columns.pattern.append_option(pattern);
+            columns.bytes.append_value(snapshot.bytes as u64);
+            if columns.len() >= self.batch_rows {
+                self.write_batch()?;
+            }
+            Ok(())
+        }
+
+        /// Writes the rows not written yet and the footer, completing the file.
+        pub fn finish(mut self) -> io::Result<()> {
+            if self.columns.len() > 0 {
+                self.write_batch()?;
+            }
+            self.writer.close().map_err(io_error)?;
+            Ok(())
+        }
+
+        fn write_batch(&mut self) -> io::Result<()> {
+            let batch = self.columns.finish(&self.schema).map_err(io_error)?;
+            self.writer.write(&batch).map_err(io_error)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use ::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+        use arrow_array::cast::AsArray;
+        use arrow_array::types::{Float64Type, UInt64Type};
+        use arrow_array::Array;
+        use std::time::Duration;
+
+        #[test]
+        fn test_read_back() {
+            let path =
+                std::env::temp_dir().join(format!("timeln-parquet-{}.parquet", std::process::id()));
+            let mut writer = ParquetWriter::create(&path, vec!["timeln".to_string()], 4).unwrap();
+            for line_no in 1..=10 {
+                let snapshot = TimeSnapshot {
+                    delta: Duration::from_millis(line_no as u64),
+                    elapsed: Duration::from_millis(10 * line_no as u64),
+                    pattern: (line_no == 3).then_some(0),
+                    line_no,
+                    bytes: 8,
+                };
+                let label = snapshot.pattern.map(|_| "three");
+                writer.push(&snapshot, label).unwrap();
+            }
+            writer.finish().unwrap();
+
+            let builder =
+                ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap();
+            let metadata = builder.metadata().clone();
+            let batches: Vec<RecordBatch> = builder.build().unwrap().map(Result::unwrap).collect();
+            std::fs::remove_file(&path).unwrap();
+
+            // Each batch of 4 rows was written as its own row group.
+            assert_eq!(metadata.num_row_groups(), 3);
+            let key_values = metadata.file_metadata().key_value_metadata().unwrap();
+            let argv = key_values
+                .iter()
+                .find(|key_value| key_value.key == "timeln.argv")
+                .unwrap();
+            assert_eq!(argv.value.as_deref(), Some("[\"timeln\"]"));
+            assert!(key_values
+                .iter()
+                .any(|key_value| key_value.key == "timeln.start_unix_ms"));
+
+            let rows: usize = batches.iter().map(RecordBatch::num_rows).sum();
+            assert_eq!(rows, 10);
+            let first = &batches[0];
+            let line_no = first.column(0).as_primitive::<UInt64Type>();
+            let delta_s = first.column(2).as_primitive::<Float64Type>();
+            let matched = first.column(3).as_boolean();
+            let pattern = first.column(4).as_string::<i32>();
+            assert_eq!(line_no.value(2), 3);
+            assert_eq!(delta_s.value(2), 0.003);
+            assert!(matched.value(2) && !matched.value(1));
+            assert_eq!(pattern.value(2), "three");
+            assert!(pattern.is_null(1));
+        }
+    }
+}
+
+/// Stand-in for builds without the `parquet` feature, which cannot write a Parquet file.
+#[cfg(not(feature = "parquet"))]
+mod writer {
+    use std::convert::Infallible;
+    use std::io;
+    use std::path::Path;
+
+    use crate::timeln::TimeSnapshot;
+
+    /// The snapshots of a run being written to a Parquet file; never created without the
+    /// `parquet` feature.
+    #[derive(Debug)]
+    pub struct ParquetWriter(Infallible);
+
+    impl ParquetWriter {
+        /// Fails, as timeln was built without the `parquet` feature.
+        pub fn create(_: impl AsRef<Path>, _: Vec<String>, _: usize) -> io::Result<Self> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "timeln was built without the parquet feature",
+            ))
+        }
+
+        pub fn push(&mut self, _: &TimeSnapshot, _: Option<&str>) -> io::Result<()> {
+            match self.0 {}
+        }
+
+        pub fn finish(self) -> io::Result<()> {
+            match self.0 {}
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! This module keeps track of the time during which timing was paused.
+//!
+//! While a run is paused, for example while the producing process is stopped in a debugger, its
+//! lines are still output but not timed, and the paused time is left out of the elapsed times and
+//! deltas of the lines that follow. `PauseClock` is shared between the main loop and the signal
+//! handler that pauses and resumes it, so it takes the instants of each event explicitly.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::{Duration, Instant};
+//! use timeln::pause::PauseClock;
+//!
+//! let start = Instant::now();
+//! let clock = PauseClock::default();
+//! clock.pause(start + Duration::from_secs(1));
+//! clock.resume(start + Duration::from_secs(3));
+//! assert_eq!(clock.paused_at(start + Duration::from_secs(5)), Duration::from_secs(2));
+//! ```
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Accumulates the time during which timing was paused.
+#[derive(Debug, Default)]
+pub struct PauseClock {
+    state: Mutex<PauseState>,
+}
+
+#[derive(Debug, Default)]
+struct PauseState {
+    /// When the current pause started, if paused.
+    since: Option<Instant>,
+    /// Length of the pauses that have ended.
+    total: Duration,
+}
+
+impl PauseClock {
+    /// Pauses timing from `at`. Does nothing if already paused.
+    pub fn pause(&self, at: Instant) {
+        let mut state = self.lock();
+        state.since.get_or_insert(at);
+    }
+
+    /// Resumes timing from `at`. Does nothing if not paused.
+    pub fn resume(&self, at: Instant) {
+        let mut state = self.lock();
+        if let Some(since) = state.since.take() {
+            state.total += at.saturating_duration_since(since);
+        }
+    }
+
+    /// Whether timing was paused at `at`.
+    pub fn is_paused_at(&self, at: Instant) -> bool {
+        self.lock().since.is_some_and(|since| since <= at)
+    }
+
+    /// Time spent paused up to `at`, including the current pause.
+    pub fn paused_at(&self, at: Instant) -> Duration {
+        let state = self.lock();
+        let current = state
+            .since
+            .map_or(Duration::ZERO, |since| at.saturating_duration_since(since));
+        state.total + current
+    }
+
+    /// Returns `at` moved back by the time spent paused before it, so that durations between
+    /// such instants leave out the pauses.
+    pub fn unpaused(&self, at: Instant) -> Instant {
+        let paused = self.paused_at(at);
+        at.checked_sub(paused).unwrap_or(at)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, PauseState> {
+        // The state is always consistent, even if a holder of the lock panicked.
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pause_clock_accumulates_pauses() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let clock = PauseClock::default();
+        assert!(!clock.is_paused_at(at(0)));
+
+        clock.pause(at(100));
+        clock.pause(at(150));
+        assert!(!clock.is_paused_at(at(50)));
+        assert!(clock.is_paused_at(at(120)));
+        assert_eq!(clock.paused_at(at(200)), Duration::from_millis(100));
+        clock.resume(at(300));
+        clock.resume(at(400));
+        assert!(!clock.is_paused_at(at(350)));
+
+        clock.pause(at(500));
+        clock.resume(at(550));
+        assert_eq!(clock.paused_at(at(1000)), Duration::from_millis(250));
+        assert_eq!(clock.unpaused(at(1000)), at(750));
+    }
+}
@@ -0,0 +1,325 @@
+//! This module detects the phases of a run, such as the download, compile and link steps of a
+//! build, from the changes of pace of its deltas.
+//!
+//! `PhaseDetector` compares the mean of the latest `window` deltas against the mean of the deltas
+//! of the current phase before them. When one is more than `ratio` times the other, a new phase is
+//! detected. It starts within the window, where the cumulative sum of the deviations of the deltas
+//! from the mean of the phase is the largest (CUSUM), that is at the first line of the new pace;
+//! the lines of the window from there on start its statistics. A phase is only judged once it has
+//! `window` deltas before the window.
+//!
+//! The heuristic trades false positives for delay. A change of pace by a factor `f` is detected
+//! after about `window * (ratio - 1) / (f - 1)` lines of the new pace when they are slower, so
+//! after one line for a sharp slowdown, and after `window * (1 - 1 / ratio) / (1 - f)` lines when
+//! they are faster. A small window or ratio detects changes sooner, but a short burst of slow
+//! lines, such as retries of a download, is then taken for a phase of its own: with a window of
+//! 10 and a ratio of 3, five lines at six times the usual pace are enough. A large window or ratio
+//! only reports sustained changes, and misses changes smaller than the ratio or phases much
+//! shorter than the window.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use timeln::phase::PhaseDetector;
+//!
+//! let mut detector = PhaseDetector::new(5, 3.0);
+//! let mut elapsed = Duration::ZERO;
+//! let mut detected = Vec::new();
+//! for line in 1..=40 {
+//!     let delta = Duration::from_millis(if line <= 20 { 10 } else { 100 });
+//!     elapsed += delta;
+//!     if let Some(phase) = detector.push(line, elapsed, delta) {
+//!         detected.push(phase.first_line);
+//!     }
+//! }
+//! assert_eq!(detected, [21]);
+//! let phases = detector.phases(elapsed);
+//! assert_eq!(phases[0].mean_delta, Duration::from_millis(10));
+//! assert_eq!(phases[1].lines, 20);
+//! ```
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Number of latest deltas compared against their phase, unless given.
+pub const DEFAULT_PHASE_WINDOW: usize = 20;
+
+/// How many times faster or slower than their phase the latest deltas must be to start a new
+/// phase, unless given.
+pub const DEFAULT_PHASE_RATIO: f64 = 3.0;
+
+/// A phase of a run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Phase {
+    /// Position of the phase in the run, from 1.
+    pub index: usize,
+    /// Line number of the first line of the phase.
+    pub first_line: usize,
+    /// Position of the first line of the phase among the timed lines, from 0.
+    pub first_timed: usize,
+    /// Time since the start of the run when the phase started: when the line before its first
+    /// line arrived.
+    pub start: Duration,
+    /// Time from the start of the phase to the start of the next one, or to the end of the run.
+    pub duration: Duration,
+    /// Number of timed lines in the phase.
+    pub lines: usize,
+    /// Mean delta of the lines of the phase.
+    pub mean_delta: Duration,
+}
+
+/// A timed line as seen by the detector.
+#[derive(Debug, Clone, Copy)]
+struct Timed {
+    line: usize,
+    timed: usize,
+    /// Time since the start of the run when the line before it arrived.
+    previous: Duration,
+    delta: f64,
+}
+
+/// The start of a phase and the totals of its lines so far.
+#[derive(Debug, Clone, Copy)]
+struct PhaseStart {
+    line: usize,
+    timed: usize,
+    start: Duration,
+    lines: usize,
+    delta_sum: f64,
+}
+
+/// Splits a stream of deltas into phases of distinct pace.
+#[derive(Debug)]
+pub struct PhaseDetector {
+    window: usize,
+    ratio: f64,
+    /// The latest deltas of the current phase, at most `window` of them.
+    recent: VecDeque<Timed>,
+    recent_sum: f64,
+    /// The deltas of the current phase before `recent`.
+    settled_lines: usize,
+    settled_sum: f64,
+    phases: Vec<PhaseStart>,
+    timed: usize,
+}
+
+impl PhaseDetector {
+    /// Compares the mean of the latest `window` deltas (at least 1) against their phase, starting
+    /// a new phase when one is more than `ratio` (above 1) times the other.
+    pub fn new(window: usize, ratio: f64) -> Self {
+        Self {
+            window: window.max(1),
+            ratio,
+            recent: VecDeque::new(),
+            recent_sum: 0.0,
+            settled_lines: 0,
+            settled_sum: 0.0,
+            phases: Vec::new(),
+            timed: 0,
+        }
+    }
+
+    /// Adds the timed line `line`, which arrived `elapsed` after the start of the run and `delta`
+    /// after the previous timed line. Returns the phase it detected, if the latest deltas changed
+    /// pace: the phase started at most `window` lines back, and is still growing.
+    pub fn push(&mut self, line: usize, elapsed: Duration, delta: Duration) -> Option<Phase> {
+        let timed = Timed {
+            line,
+            timed: self.timed,
+            previous: elapsed.saturating_sub(delta),
+            delta: delta.as_secs_f64(),
+        };
+        self.timed += 1;
+        if self.phases.is_empty() {
+            self.phases.push(PhaseStart {
+                line,
+                timed: timed.timed,
+                start: Duration::ZERO,
+                lines: 0,
+                delta_sum: 0.0,
+            });
+        }
+        let current = self.phases.last_mut()?;
+        current.lines += 1;
+        current.delta_sum += timed.delta;
+        self.recent.push_back(timed);
+        self.recent_sum += timed.delta;
+        if self.recent.len() > self.window {
+            let settled = self.recent.pop_front()?;
+            self.recent_sum -= settled.delta;
+            self.settled_lines += 1;
+            self.settled_sum += settled.delta;
+        }
+        if self.settled_lines < self.window || !self.changed_pace() {
+            return None;
+        }
+
+        // The lines of the window from the change on move to the new phase, with their totals.
+        let split = self.change_point();
+        self.recent.drain(..split);
+        self.recent_sum = self.recent.iter().map(|timed| timed.delta).sum();
+        let first = *self.recent.front()?;
+        let current = self.phases.last_mut()?;
+        current.lines -= self.recent.len();
+        current.delta_sum -= self.recent_sum;
+        self.phases.push(PhaseStart {
+            line: first.line,
+            timed: first.timed,
+            start: first.previous,
+            lines: self.recent.len(),
+            delta_sum: self.recent_sum,
+        });
+        self.settled_lines = 0;
+        self.settled_sum = 0.0;
+        let index = self.phases.len() - 1;
+        Some(self.phase(index, elapsed))
+    }
+
+    /// Whether the mean of the latest deltas is more than `ratio` times that of their phase, or
+    /// less than it divided by `ratio`.
+    fn changed_pace(&self) -> bool {
+        let recent = self.recent_sum / self.recent.len() as f64;
+        let settled = self.settled_sum / self.settled_lines as f64;
+        recent > settled * self.ratio || recent * self.ratio < settled
+    }
+
+    /// Position in the window of the first line of the new pace: the start of the suffix of the
+    /// window whose deltas deviate the most from the mean of the phase, in the direction of the
+    /// change. On a tie the later start is taken.
+    fn change_point(&self) -> usize {
+        let mean = self.settled_sum / self.settled_lines as f64;
+        let slower = self.recent_sum / self.recent.len() as f64 > mean;
+        let mut best = (0.0, self.recent.len() - 1);
+        let mut deviation = 0.0;
+        for (i, timed) in self.recent.iter().enumerate().rev() {
+            deviation += if slower {
+                timed.delta - mean
+            } else {
+                mean - timed.delta
+            };
+            if deviation > best.0 {
+                best = (deviation, i);
+            }
+        }
+        best.1
+    }
+
+    /// The phase at `index`, the last one lasting until `end`.
+    fn phase(&self, index: usize, end: Duration) -> Phase {
+        let start = self.phases[index];
+        let next = self.phases.get(index + 1).map_or(end, |next| next.start);
+        let mean = if start.lines > 0 {
+            start.delta_sum / start.lines as f64
+        } else {
+            0.0
+        };
+        Phase {
+            index: index + 1,
+            first_line: start.line,
+            first_timed: start.timed,
+            start: start.start,
+            duration: next.saturating_sub(start.start),
+            lines: start.lines,
+            mean_delta: Duration::from_secs_f64(mean.max(0.0)),
+        }
+    }
+
+    /// The phases detected so far, the last one lasting until `end`, the end of the run. There
+    /// are none until a line is timed.
+    pub fn phases(&self, end: Duration) -> Vec<Phase> {
+        (0..self.phases.len())
+            .map(|index| self.phase(index, end))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds lines with the deltas `deltas_ms` to `detector`, and returns the first lines of the
+    /// phases it detected and the time of the last line.
+    fn feed(detector: &mut PhaseDetector, deltas_ms: &[u64]) -> (Vec<usize>, Duration) {
+        let mut elapsed = Duration::ZERO;
+        let mut detected = Vec::new();
+        for (i, millis) in deltas_ms.iter().enumerate() {
+            let delta = Duration::from_millis(*millis);
+            elapsed += delta;
+            if let Some(phase) = detector.push(i + 1, elapsed, delta) {
+                detected.push(phase.first_line);
+            }
+        }
+        (detected, elapsed)
+    }
+
+    fn repeat(parts: &[(u64, usize)]) -> Vec<u64> {
+        parts
+            .iter()
+            .flat_map(|(millis, n)| std::iter::repeat_n(*millis, *n))
+            .collect()
+    }
+
+    #[test]
+    fn test_detects_three_phases() {
+        let mut detector = PhaseDetector::new(10, 3.0);
+        let deltas = repeat(&[(10, 50), (200, 50), (20, 50)]);
+        let (detected, end) = feed(&mut detector, &deltas);
+        // The slowdown is found at its first line, the speedup 8 lines later, dated back to its
+        // first line.
+        assert_eq!(detected, [51, 101]);
+        let phases = detector.phases(end);
+        assert_eq!(phases.len(), 3);
+        assert_eq!(
+            phases.iter().map(|phase| phase.lines).sum::<usize>(),
+            deltas.len()
+        );
+        assert_eq!(
+            phases.iter().map(|phase| phase.duration).sum::<Duration>(),
+            end
+        );
+        assert_eq!(phases[0].index, 1);
+        assert_eq!(phases[0].first_line, 1);
+        assert_eq!(phases[0].start, Duration::ZERO);
+        assert_eq!(phases[0].mean_delta, Duration::from_millis(10));
+        assert_eq!(phases[1].first_timed, 50);
+        assert_eq!(phases[1].start, Duration::from_millis(500));
+        assert_eq!(phases[1].duration, Duration::from_secs(10));
+        assert_eq!(phases[1].mean_delta, Duration::from_millis(200));
+        assert_eq!(phases[2].lines, 50);
+        assert_eq!(phases[2].mean_delta, Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_steady_noise_is_one_phase() {
+        let mut detector = PhaseDetector::new(10, 3.0);
+        // Deltas alternating between 5 and 15 ms, a ratio of 3 between single lines.
+        let deltas: Vec<u64> = (0..1000).map(|i| if i % 2 == 0 { 5 } else { 15 }).collect();
+        let (detected, end) = feed(&mut detector, &deltas);
+        assert!(detected.is_empty());
+        let phases = detector.phases(end);
+        assert_eq!(phases.len(), 1);
+        assert_eq!(phases[0].mean_delta, Duration::from_millis(10));
+        assert_eq!(phases[0].duration, end);
+    }
+
+    #[test]
+    fn test_sensitivity_tradeoff() {
+        // A burst of 8 slow lines in a steady run.
+        let deltas = repeat(&[(10, 100), (50, 8), (10, 100)]);
+        // A small window takes the burst for phases of its own.
+        let (detected, _) = feed(&mut PhaseDetector::new(4, 3.0), &deltas);
+        assert_eq!(detected.len(), 2);
+        // A window larger than the burst ignores it.
+        let (detected, _) = feed(&mut PhaseDetector::new(20, 3.0), &deltas);
+        assert!(detected.is_empty());
+        // So does a ratio above the change of pace.
+        let (detected, _) = feed(&mut PhaseDetector::new(4, 6.0), &deltas);
+        assert!(detected.is_empty());
+    }
+
+    #[test]
+    fn test_no_phases_before_a_line() {
+        let detector = PhaseDetector::new(DEFAULT_PHASE_WINDOW, DEFAULT_PHASE_RATIO);
+        assert!(detector.phases(Duration::from_secs(1)).is_empty());
+    }
+}
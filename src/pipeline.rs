@@ -0,0 +1,953 @@
+//! This module provides the `Pipeline` struct, the line-timing core of timeln.
+//!
+//! A `Pipeline` decides which lines are timed (all of them, or only those matching one of its
+//! regex patterns), measures the time since the previously timed line, highlights the match and
+//! annotates the line. It does not read stdin, print, summarize or plot by itself, so that it can
+//! be embedded in other tools; `TimelnContext` builds the command-line utility on top of it.
+//!
+//! # Example
+//!
+//! ```
+//! use std::sync::Arc;
+//! use timeln::{IterReadData, Pipeline, SecondsFormat, SimpleAnnotator};
+//!
+//! let annotator = SimpleAnnotator {
+//!     color: false,
+//!     time_format: Arc::new(Box::new(SecondsFormat)),
+//!     widths: None,
+//! };
+//! let mut pipeline = Pipeline::new(Box::new(annotator)).regex("ok")?;
+//! let mut reader = IterReadData::new(["test a ok", "test b failed", "test c ok"]);
+//! let mut out = Vec::new();
+//! let snapshots = pipeline.run(&mut reader, &mut out)?;
+//! assert_eq!(snapshots.len(), 2);
+//! # Ok::<(), timeln::TimelnError>(())
+//! ```
+use std::io::Write;
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use colored::{Color, Colorize};
+use regex::{Captures, Match, Regex};
+
+use crate::annotator::{AnnotationContext, TimelnAnnotation};
+use crate::error::TimelnError;
+use crate::heat::Heat;
+use crate::reader::{ReadData, Stream};
+use crate::stats::{trend, Ewma, RunningStats, SlidingWindow, Trend};
+use crate::timeln::TimeSnapshot;
+
+/// A line timed by a `Pipeline`.
+#[derive(Debug, Clone)]
+pub struct TimedLine {
+    /// The timing information of the line.
+    pub snapshot: TimeSnapshot,
+    /// The line with its match highlighted and its annotation, ready to be printed.
+    pub output: String,
+    /// The moving average of the deltas up to this line, if the pipeline computes it.
+    pub ewma: Option<Duration>,
+}
+
+/// Colors of the labels of the patterns, cycled through by pattern index.
+const LABEL_COLORS: [Color; 6] = [
+    Color::Cyan,
+    Color::Magenta,
+    Color::Blue,
+    Color::BrightCyan,
+    Color::BrightMagenta,
+    Color::BrightBlue,
+];
+
+/// The glyph of `trend`, colored red when slower and green when faster if `color`, and in ASCII if
+/// `ascii`.
+fn trend_glyph(trend: Trend, ascii: bool, color: bool) -> String {
+    let glyph = match (trend, ascii) {
+        (Trend::Slower, false) => "↑",
+        (Trend::Steady, false) => "→",
+        (Trend::Faster, false) => "↓",
+        (Trend::Slower, true) => "+",
+        (Trend::Steady, true) => "=",
+        (Trend::Faster, true) => "-",
+    };
+    match trend {
+        Trend::Slower if color => glyph.red().to_string(),
+        Trend::Faster if color => glyph.green().to_string(),
+        _ => glyph.to_string(),
+    }
+}
+
+/// Removes the line ending of `line`: one trailing `\n`, and a `\r` before it or at the end of a
+/// line without a newline. Any other whitespace is kept.
+pub fn strip_line_ending(line: &str) -> &str {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+/// The non-empty matches in `line` of each of `regexes`, in the order of the line, with the index
+/// of their regex. A match overlapping one before it is dropped; of matches starting at the same
+/// place, that of the first regex is kept.
+fn match_spans<'r>(
+    line: &str,
+    regexes: impl IntoIterator<Item = &'r Regex>,
+) -> Vec<(Range<usize>, usize)> {
+    let mut found: Vec<(Range<usize>, usize)> = regexes
+        .into_iter()
+        .enumerate()
+        .flat_map(|(i, regex)| {
+            regex
+                .find_iter(line)
+                .filter(|m| !m.is_empty())
+                .map(move |m| (m.range(), i))
+        })
+        .collect();
+    found.sort_by_key(|(range, i)| (range.start, *i));
+    let mut spans: Vec<(Range<usize>, usize)> = Vec::with_capacity(found.len());
+    for (range, i) in found {
+        if spans.last().is_none_or(|(last, _)| range.start >= last.end) {
+            spans.push((range, i));
+        }
+    }
+    spans
+}
+
+/// Colors each of the `spans` of `line` in the `color` of its regex, leaving the text between the
+/// spans as it is.
+fn paint_spans(
+    line: &str,
+    spans: &[(Range<usize>, usize)],
+    color: impl Fn(usize) -> Color,
+) -> String {
+    let mut highlighted = String::with_capacity(line.len());
+    let mut end = 0;
+    for (range, i) in spans {
+        highlighted.push_str(&line[end..range.start]);
+        highlighted.push_str(&line[range.clone()].color(color(*i)).to_string());
+        end = range.end;
+    }
+    highlighted.push_str(&line[end..]);
+    highlighted
+}
+
+/// The capture groups of the matches in `line` of each of `regexes`, in the order of the line,
+/// with the index of their regex. Overlapping matches are dropped as by `match_spans`, then the
+/// matches without the group, or with an empty one.
+fn group_spans(line: &str, regexes: &[Regex], group: &CaptureGroup) -> Vec<(Range<usize>, usize)> {
+    let mut found: Vec<(Range<usize>, usize, Option<Range<usize>>)> = regexes
+        .iter()
+        .enumerate()
+        .flat_map(|(i, regex)| {
+            regex.captures_iter(line).filter_map(move |captures| {
+                let whole = captures.get(0).filter(|m| !m.is_empty())?;
+                Some((whole.range(), i, group.get(&captures).map(|m| m.range())))
+            })
+        })
+        .collect();
+    found.sort_by_key(|(range, i, _)| (range.start, *i));
+    let mut end = 0;
+    let mut spans = Vec::with_capacity(found.len());
+    for (whole, i, part) in found {
+        if whole.start < end {
+            continue;
+        }
+        end = whole.end;
+        if let Some(part) = part.filter(|part| !part.is_empty()) {
+            spans.push((part, i));
+        }
+    }
+    spans
+}
+
+/// Colors every match of `regex` in `line` red, leaving the text between the matches as it is.
+fn highlight(line: &str, regex: &Regex) -> String {
+    paint_spans(line, &match_spans(line, [regex]), |_| Color::Red)
+}
+
+/// A capture group of the regex patterns, by index (0 for the whole match) or by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CaptureGroup {
+    Index(usize),
+    Name(String),
+}
+
+impl CaptureGroup {
+    /// The group in `captures`, if it took part in the match.
+    fn get<'t>(&self, captures: &Captures<'t>) -> Option<Match<'t>> {
+        match self {
+            CaptureGroup::Index(index) => captures.get(*index),
+            CaptureGroup::Name(name) => captures.name(name),
+        }
+    }
+
+    /// Whether `regex` has the group.
+    fn in_regex(&self, regex: &Regex) -> bool {
+        match self {
+            CaptureGroup::Index(index) => *index < regex.captures_len(),
+            CaptureGroup::Name(name) => regex.capture_names().flatten().any(|n| n == name),
+        }
+    }
+}
+
+impl std::fmt::Display for CaptureGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CaptureGroup::Index(index) => write!(f, "{}", index),
+            CaptureGroup::Name(name) => write!(f, "'{}'", name),
+        }
+    }
+}
+
+/// Times lines, optionally only those matching a set of regex patterns.
+pub struct Pipeline {
+    annotator: Box<dyn TimelnAnnotation>,
+    regexes: Vec<Regex>,
+    labels: Vec<String>,
+    /// Colors of the matches of each pattern, cycled through by pattern index, if any.
+    match_colors: Vec<Color>,
+    /// Whether only the annotation is output, without the line.
+    no_line: bool,
+    /// The capture group of the matches output instead of the lines, if only they are.
+    only_matching: Option<CaptureGroup>,
+    passthrough: bool,
+    trim: bool,
+    color: bool,
+    /// Statistics of the deltas averaged in the annotations, if the mean is shown.
+    mean: Option<RunningStats>,
+    /// Whether the delta of the next timed line is left out of the mean: that of the first line,
+    /// since the start, until it is timed.
+    skip_delta: bool,
+    ewma: Option<Ewma>,
+    rate_window: Option<Arc<SlidingWindow>>,
+    heat: Option<Heat>,
+    as_rate: bool,
+    /// Tolerance of the trend of each delta against the moving average, and whether its glyph is
+    /// ASCII.
+    trend: Option<(f64, bool)>,
+    start_time: Instant,
+    last_time: Instant,
+    /// Number of lines offered so far, whether or not they were timed.
+    lines: usize,
+}
+
+impl Pipeline {
+    /// Creates a pipeline that times every line and annotates it with `annotator`.
+    /// The clock starts now.
+    pub fn new(annotator: Box<dyn TimelnAnnotation>) -> Self {
+        let now = Instant::now();
+        Self {
+            annotator,
+            regexes: Vec::new(),
+            labels: Vec::new(),
+            match_colors: Vec::new(),
+            no_line: false,
+            only_matching: None,
+            passthrough: false,
+            trim: false,
+            color: false,
+            mean: None,
+            skip_delta: false,
+            ewma: None,
+            rate_window: None,
+            heat: None,
+            as_rate: false,
+            trend: None,
+            start_time: now,
+            last_time: now,
+            lines: 0,
+        }
+    }
+
+    /// Adds a regex pattern. Once a pattern is added, only the lines matching one of the patterns
+    /// are timed, unless passthrough is enabled.
+    pub fn regex(mut self, pattern: &str) -> Result<Self, TimelnError> {
+        let regex = Regex::new(pattern).map_err(|err| TimelnError::regex(pattern, err))?;
+        self.regexes.push(regex);
+        Ok(self)
+    }
+
+    /// Prefixes each line matching a pattern with `[label]`, the label at the index of the
+    /// pattern, in a color of its own when color is enabled. Patterns without a label get no
+    /// prefix.
+    pub fn labels(mut self, labels: Vec<String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    /// Highlights the matches of each pattern in the color at its index in `colors`, cycling
+    /// through them if there are more patterns, along with its label. Every pattern matching the
+    /// line is then highlighted, rather than only the first one. Without colors, matches are red.
+    pub fn match_colors(mut self, colors: Vec<Color>) -> Self {
+        self.match_colors = colors;
+        self
+    }
+
+    /// The color of the matches of the pattern at index `pattern`, if set by `match_colors`.
+    pub fn match_color(&self, pattern: usize) -> Option<Color> {
+        (!self.match_colors.is_empty())
+            .then(|| self.match_colors[pattern % self.match_colors.len()])
+    }
+
+    /// Sets whether lines that match no pattern are still timed and output.
+    pub fn passthrough(mut self, passthrough: bool) -> Self {
+        self.passthrough = passthrough;
+        self
+    }
+
+    /// Shows the mean of the deltas so far in the annotations, if `show_avg`.
+    pub fn show_avg(mut self, show_avg: bool) -> Self {
+        self.mean = show_avg.then(RunningStats::default);
+        self
+    }
+
+    /// Leaves the delta of the first timed line, the time from the start to that line, out of the
+    /// mean of `show_avg`, if `skip`.
+    pub fn skip_first_delta(mut self, skip: bool) -> Self {
+        self.skip_delta = skip;
+        self
+    }
+
+    /// Computes the exponentially weighted moving average of the deltas, each new delta weighing
+    /// `alpha`, and shows it in the annotations.
+    pub fn ewma(mut self, alpha: Option<f64>) -> Self {
+        self.ewma = alpha.map(Ewma::new);
+        self
+    }
+
+    /// Prefixes each line with whether its delta is slower (`↑`), about the same (`→`, within
+    /// `tolerance` such as 0.1 for 10%) or faster (`↓`) than the moving average of the deltas
+    /// before it, or `+`, `=` and `-` if `ascii`. Needs the moving average of `ewma`.
+    pub fn trend(mut self, tolerance: Option<f64>, ascii: bool) -> Self {
+        self.trend = tolerance.map(|tolerance| (tolerance, ascii));
+        self
+    }
+
+    /// Colors the delta in the annotations by its place among the deltas so far, with `heat`.
+    pub fn heat(mut self, heat: Option<Heat>) -> Self {
+        self.heat = heat;
+        self
+    }
+
+    /// Shows the delta and its moving average in the annotations as the rates they stand for,
+    /// `1/delta` in Hz, if `as_rate`.
+    pub fn as_rate(mut self, as_rate: bool) -> Self {
+        self.as_rate = as_rate;
+        self
+    }
+
+    /// Shows the rate of lines in the sliding `window` in the annotations. The lines are added to
+    /// the window as they arrive, by the owner of the pipeline.
+    pub fn annotate_rate(mut self, window: Option<Arc<SlidingWindow>>) -> Self {
+        self.rate_window = window;
+        self
+    }
+
+    /// Sets whether only the annotation of each timed line is output, without the line, which is
+    /// then neither trimmed nor highlighted.
+    pub fn no_line(mut self, no_line: bool) -> Self {
+        self.no_line = no_line;
+        self
+    }
+
+    /// Outputs only the matches of the patterns in each matching line, like `grep -o`, or the
+    /// given capture `group` of them: each on a line of its own, annotated with the times of the
+    /// line. Fails if no pattern has the group, so patterns must be added first.
+    pub fn only_matching(mut self, group: Option<CaptureGroup>) -> Result<Self, TimelnError> {
+        if let Some(group) = &group {
+            if !self.regexes.iter().any(|regex| group.in_regex(regex)) {
+                return Err(TimelnError::Usage(format!(
+                    "no regex pattern has the capture group {}",
+                    group
+                )));
+            }
+        }
+        self.only_matching = group;
+        Ok(self)
+    }
+
+    /// Sets whether leading and trailing whitespace is trimmed from the lines, rather than only
+    /// their line ending.
+    pub fn trim(mut self, trim: bool) -> Self {
+        self.trim = trim;
+        self
+    }
+
+    /// The text of `line` as it is matched and output: without its line ending, and trimmed if
+    /// trimming is enabled.
+    pub fn line_text<'a>(&self, line: &'a str) -> &'a str {
+        if self.trim {
+            line.trim()
+        } else {
+            strip_line_ending(line)
+        }
+    }
+
+    /// The captures of the match of the pattern at index `pattern` in the text of `line`, if it
+    /// matches.
+    pub fn captures<'a>(&self, line: &'a str, pattern: usize) -> Option<Captures<'a>> {
+        self.regexes.get(pattern)?.captures(self.line_text(line))
+    }
+
+    /// Sets whether lines from the stderr of a child process are colored.
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// The instant the pipeline was created, from which elapsed times are measured.
+    pub fn start_time(&self) -> Instant {
+        self.start_time
+    }
+
+    /// Measures elapsed times from `at` instead of the start of the pipeline. Deltas are not
+    /// affected.
+    pub fn reset_elapsed(&mut self, at: Instant) {
+        self.start_time = at;
+    }
+
+    /// Measures elapsed times from `at`, and the delta of the next timed line too, as if the
+    /// pipeline had started then.
+    pub fn reset(&mut self, at: Instant) {
+        self.start_time = at;
+        self.last_time = at;
+    }
+
+    /// Times `line` as arriving now. Returns `None` if the line is filtered out by the regex
+    /// patterns, in which case it does not count as the previous line of the next one.
+    pub fn time_line(&mut self, line: &str) -> Option<TimedLine> {
+        self.time_line_at(line, Instant::now())
+    }
+
+    /// Times `line` as arriving at `now`, which should be taken as soon as the line was read so
+    /// that matching and formatting it are not part of any delta.
+    pub fn time_line_at(&mut self, line: &str, now: Instant) -> Option<TimedLine> {
+        self.time_line_from(line, now, None)
+    }
+
+    /// The text of `line` as output: its matches highlighted and prefixed with the label of the
+    /// `found` pattern, the tag of its `stream` and the trend of its `delta`, as enabled.
+    fn decorate(
+        &self,
+        line: &str,
+        found: Option<usize>,
+        stream: Option<Stream>,
+        delta: Duration,
+    ) -> String {
+        let text = match found {
+            Some(_) if !self.match_colors.is_empty() => {
+                let spans = match_spans(line, &self.regexes);
+                paint_spans(line, &spans, |i| {
+                    self.match_colors[i % self.match_colors.len()]
+                })
+            }
+            Some(i) => highlight(line, &self.regexes[i]),
+            None => line.to_string(),
+        };
+        self.prefix(text, found, stream, delta)
+    }
+
+    /// The texts output for `line` with only its matches: each match, or its capture `group`, in
+    /// the color of its pattern and prefixed as by `decorate`.
+    fn decorate_matches(
+        &self,
+        line: &str,
+        group: &CaptureGroup,
+        stream: Option<Stream>,
+        delta: Duration,
+    ) -> Vec<String> {
+        group_spans(line, &self.regexes, group)
+            .into_iter()
+            .map(|(range, i)| {
+                let color = self.match_color(i).unwrap_or(Color::Red);
+                let text = line[range].color(color).to_string();
+                self.prefix(text, Some(i), stream, delta)
+            })
+            .collect()
+    }
+
+    /// Prefixes `text` with the label of the `found` pattern, the tag of its `stream` and the
+    /// trend of its `delta`, as enabled.
+    fn prefix(
+        &self,
+        text: String,
+        found: Option<usize>,
+        stream: Option<Stream>,
+        delta: Duration,
+    ) -> String {
+        let text = match found.and_then(|i| Some((i, self.labels.get(i)?))) {
+            Some((i, label)) if self.color => format!(
+                "{} {}",
+                format!("[{}]", label).color(
+                    self.match_color(i)
+                        .unwrap_or(LABEL_COLORS[i % LABEL_COLORS.len()])
+                ),
+                text
+            ),
+            Some((_, label)) => format!("[{}] {}", label, text),
+            None => text,
+        };
+        let text = match stream {
+            Some(Stream::Stderr) if self.color => format!("{} {}", Stream::Stderr.tag(), text)
+                .yellow()
+                .to_string(),
+            Some(stream) => format!("{} {}", stream.tag(), text),
+            None => text,
+        };
+        let average = self.ewma.as_ref().and_then(Ewma::value);
+        match (self.trend, average) {
+            (Some((tolerance, ascii)), Some(average)) => {
+                let glyph = trend_glyph(trend(delta, average, tolerance), ascii, self.color);
+                format!("{} {}", glyph, text)
+            }
+            _ => text,
+        }
+    }
+
+    /// Times `line` as arriving at `now` on `stream` of a child process, tagging it with the
+    /// stream so that stdout and stderr lines can be told apart.
+    pub fn time_line_from(
+        &mut self,
+        line: &str,
+        now: Instant,
+        stream: Option<Stream>,
+    ) -> Option<TimedLine> {
+        self.lines += 1;
+        let bytes = line.len();
+        // Without the line and patterns, its text is not needed at all.
+        let line = if self.no_line && self.regexes.is_empty() {
+            ""
+        } else {
+            self.line_text(line)
+        };
+        let found = self.regexes.iter().position(|re| re.is_match(line));
+        if !self.regexes.is_empty() && found.is_none() && !self.passthrough {
+            return None;
+        }
+
+        let delta = now.duration_since(self.last_time);
+        self.last_time = now;
+        let elapsed = now.duration_since(self.start_time);
+
+        // Without the line, only the annotation is formatted.
+        let texts = match (&self.only_matching, found) {
+            _ if self.no_line => Vec::new(),
+            (Some(group), Some(_)) => self.decorate_matches(line, group, stream, delta),
+            _ => vec![self.decorate(line, found, stream, delta)],
+        };
+        // Only the first timed line can have its delta skipped.
+        let skip_delta = std::mem::take(&mut self.skip_delta);
+        let context = AnnotationContext {
+            now: elapsed,
+            delta,
+            mean: self.mean.as_mut().and_then(|mean| {
+                if !skip_delta {
+                    mean.push(delta.as_secs_f64());
+                }
+                (mean.count() > 0).then(|| Duration::from_secs_f64(mean.mean()))
+            }),
+            ewma: self.ewma.as_mut().map(|ewma| ewma.push(delta)),
+            rate: self
+                .rate_window
+                .as_ref()
+                .map(|window| (window.window(), window.rate_at(now))),
+            delta_color: self.heat.as_mut().map(|heat| heat.push(delta)),
+            as_rate: self.as_rate,
+        };
+        Some(TimedLine {
+            snapshot: TimeSnapshot {
+                delta,
+                elapsed,
+                pattern: found,
+                line_no: self.lines,
+                bytes,
+            },
+            output: if texts.is_empty() {
+                self.annotator.format_annotation(&context)
+            } else {
+                texts
+                    .iter()
+                    .map(|text| self.annotator.format_line_with(text, &context))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            },
+            ewma: context.ewma,
+        })
+    }
+
+    /// Annotates `line`, arriving at `now`, as the context of a match: with its own times, but
+    /// without timing it, so that the next timed line still gets its delta from the previous one.
+    pub fn context_line(&self, line: &str, now: Instant) -> String {
+        self.annotator.format_line(
+            self.line_text(line),
+            &now.duration_since(self.start_time),
+            &now.duration_since(self.last_time),
+        )
+    }
+
+    /// Annotates a notice about the input at `now`, such as a rotated log, without timing it as
+    /// a line.
+    pub fn notice(&self, text: &str, now: Instant) -> String {
+        self.annotator.format_line(
+            text,
+            &now.duration_since(self.start_time),
+            &now.duration_since(self.last_time),
+        )
+    }
+
+    /// Times every line of `reader`, writing the annotated lines to `out`, and returns the
+    /// snapshots of the timed lines.
+    pub fn run(
+        &mut self,
+        reader: &mut dyn ReadData,
+        out: &mut dyn Write,
+    ) -> Result<Vec<TimeSnapshot>, TimelnError> {
+        let mut snapshots = Vec::new();
+        let mut buffer = String::new();
+        loop {
+            buffer.clear();
+            if reader.read_line(&mut buffer)? == 0 {
+                break;
+            }
+            if let Some(timed) = self.time_line(&buffer) {
+                writeln!(out, "{}", timed.output)?;
+                snapshots.push(timed.snapshot);
+            }
+        }
+        Ok(snapshots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotator::SimpleAnnotator;
+    use crate::formatter::SecondsFormat;
+    use crate::reader::IterReadData;
+    use std::sync::Arc;
+
+    fn pipeline() -> Pipeline {
+        Pipeline::new(Box::new(SimpleAnnotator {
+            color: false,
+            time_format: Arc::new(Box::new(SecondsFormat)),
+            widths: None,
+        }))
+    }
+
+    #[test]
+    fn test_pipeline_times_every_line() -> Result<(), TimelnError> {
+        let mut reader = IterReadData::new(["a", "b", "c"]);
+        let mut out = Vec::new();
+        let snapshots = pipeline().run(&mut reader, &mut out)?;
+        assert_eq!(snapshots.len(), 3);
+        assert!(snapshots
+            .windows(2)
+            .all(|pair| pair[0].elapsed <= pair[1].elapsed));
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().count(), 3);
+        assert!(out.lines().last().unwrap().ends_with("] c"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pipeline_snapshots_number_and_measure_lines() -> Result<(), TimelnError> {
+        let mut filtered = pipeline().regex("^x")?.trim(true);
+        assert!(filtered.time_line("a\n").is_none());
+        let snapshot = filtered.time_line("  x é \r\n").unwrap().snapshot;
+        assert_eq!(snapshot.line_no, 2);
+        assert_eq!(snapshot.bytes, 9);
+        assert_eq!(snapshot.pattern, Some(0));
+        let snapshot = filtered.time_line("x\n").unwrap().snapshot;
+        assert_eq!((snapshot.line_no, snapshot.bytes), (3, 2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pipeline_filters_and_passthrough() -> Result<(), TimelnError> {
+        let mut filtered = pipeline().regex("^x")?.regex("^y")?;
+        assert!(filtered.time_line("a").is_none());
+        assert_eq!(filtered.time_line("y1").unwrap().snapshot.pattern, Some(1));
+
+        let mut passthrough = pipeline().regex("^x")?.passthrough(true);
+        assert_eq!(passthrough.time_line("a").unwrap().snapshot.pattern, None);
+        assert_eq!(
+            passthrough.time_line("x").unwrap().snapshot.pattern,
+            Some(0)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_pipeline_smooths_deltas() {
+        let mut plain = pipeline();
+        assert_eq!(plain.time_line("a").unwrap().ewma, None);
+
+        let mut smoothed = pipeline().ewma(Some(1.0));
+        let start = smoothed.start_time();
+        for millis in [100, 350, 400] {
+            let timed = smoothed
+                .time_line_at("a", start + Duration::from_millis(millis))
+                .unwrap();
+            // With an alpha of 1 the average is the latest delta.
+            assert_eq!(timed.ewma, Some(timed.snapshot.delta));
+        }
+        let timed = smoothed
+            .time_line_at("b", start + Duration::from_millis(1400))
+            .unwrap();
+        assert_eq!(timed.output, "[time: 1.40 s, delta: 1.00 s, ~1.00 s] b");
+    }
+
+    #[test]
+    fn test_pipeline_shows_mean() {
+        let mut averaged = pipeline().show_avg(true);
+        let start = averaged.start_time();
+        let outputs: Vec<String> = [1000, 1100, 1400]
+            .iter()
+            .map(|millis| {
+                let now = start + Duration::from_millis(*millis);
+                averaged.time_line_at("x", now).unwrap().output
+            })
+            .collect();
+        assert_eq!(
+            outputs,
+            [
+                "[time: 1.00 s, delta: 1.00 s, avg: 1.00 s] x",
+                "[time: 1.10 s, delta: 0.10 s, avg: 0.55 s] x",
+                "[time: 1.40 s, delta: 0.30 s, avg: 0.47 s] x",
+            ]
+        );
+
+        // Without the delta since the start, the first line has nothing to average.
+        let mut skipping = pipeline().show_avg(true).skip_first_delta(true);
+        let start = skipping.start_time();
+        let outputs: Vec<String> = [1000, 1100, 1400]
+            .iter()
+            .map(|millis| {
+                let now = start + Duration::from_millis(*millis);
+                skipping.time_line_at("x", now).unwrap().output
+            })
+            .collect();
+        assert_eq!(
+            outputs,
+            [
+                "[time: 1.00 s, delta: 1.00 s] x",
+                "[time: 1.10 s, delta: 0.10 s, avg: 0.10 s] x",
+                "[time: 1.40 s, delta: 0.30 s, avg: 0.20 s] x",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pipeline_shows_trend() {
+        let mut unicode = pipeline().ewma(Some(0.5)).trend(Some(0.1), false);
+        let start = unicode.start_time();
+        let mut outputs = Vec::new();
+        for millis in [100, 200, 400, 430, 515] {
+            let timed = unicode
+                .time_line_at("x", start + Duration::from_millis(millis))
+                .unwrap();
+            outputs.push(timed.output.split("] ").nth(1).unwrap().to_string());
+        }
+        // The first delta has no average to compare with; the averages are then 100, 100, 150
+        // and 90 ms.
+        assert_eq!(outputs, ["x", "→ x", "↑ x", "↓ x", "→ x"]);
+
+        let mut ascii = pipeline().ewma(Some(0.5)).trend(Some(0.1), true);
+        let start = ascii.start_time();
+        let outputs: Vec<String> = [100, 300, 310]
+            .iter()
+            .map(|millis| {
+                let timed = ascii
+                    .time_line_at("x", start + Duration::from_millis(*millis))
+                    .unwrap();
+                timed.output.split("] ").nth(1).unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(outputs, ["x", "+ x", "- x"]);
+    }
+
+    #[test]
+    fn test_trend_glyph_colors() {
+        assert_eq!(
+            trend_glyph(Trend::Slower, false, true),
+            "↑".red().to_string()
+        );
+        assert_eq!(
+            trend_glyph(Trend::Faster, true, true),
+            "-".green().to_string()
+        );
+        assert_eq!(trend_glyph(Trend::Steady, false, true), "→");
+    }
+
+    #[test]
+    fn test_pipeline_annotates_rate() {
+        let window = Arc::new(SlidingWindow::new(Duration::from_secs(1)));
+        let mut pipeline = pipeline().annotate_rate(Some(Arc::clone(&window)));
+        let start = pipeline.start_time();
+        for millis in [100, 200, 300] {
+            window.push(start + Duration::from_millis(millis));
+        }
+        let timed = pipeline
+            .time_line_at("c", start + Duration::from_millis(300))
+            .unwrap();
+        assert_eq!(
+            timed.output,
+            "[time: 0.30 s, delta: 0.30 s, rate_1s: 3.0/s] c"
+        );
+    }
+
+    #[test]
+    fn test_highlight_colors_only_matched_ranges() {
+        let red = |text: &str| text.red().to_string();
+        let word = Regex::new(r"\bcat\b").unwrap();
+        assert_eq!(
+            highlight("concat cat catalog", &word),
+            format!("concat {} catalog", red("cat"))
+        );
+        let anchored = Regex::new("^ab").unwrap();
+        assert_eq!(highlight("abab", &anchored), format!("{}ab", red("ab")));
+        let repeated = Regex::new("an").unwrap();
+        assert_eq!(
+            highlight("banana", &repeated),
+            format!("b{}{}a", red("an"), red("an"))
+        );
+        assert_eq!(highlight("plain", &Regex::new("x*").unwrap()), "plain");
+    }
+
+    #[test]
+    fn test_match_spans_of_several_patterns() {
+        let regexes = [
+            Regex::new("disk").unwrap(),
+            Regex::new("disk full").unwrap(),
+            Regex::new("ERR|full").unwrap(),
+        ];
+        // The earlier pattern wins a tie, and a match overlapping an earlier one is dropped.
+        assert_eq!(
+            match_spans("ERR: disk full", &regexes),
+            vec![(0..3, 2), (5..9, 0), (10..14, 2)]
+        );
+        let spans = match_spans("ERR: disk full", &regexes);
+        let colors = [Color::Red, Color::Yellow, Color::Cyan];
+        assert_eq!(
+            paint_spans("ERR: disk full", &spans, |i| colors[i]),
+            format!(
+                "{}: {} {}",
+                "ERR".color(Color::Cyan),
+                "disk".color(Color::Red),
+                "full".color(Color::Cyan)
+            )
+        );
+    }
+
+    #[test]
+    fn test_only_matching_outputs_each_match() -> Result<(), TimelnError> {
+        let mut pipeline = pipeline()
+            .regex(r"\d+ms")?
+            .regex("ok")?
+            .only_matching(Some(CaptureGroup::Index(0)))?;
+        let timed = pipeline.time_line("a 12ms b ok c 7ms").unwrap();
+        let lines: Vec<&str> = timed.output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        // Each match gets the same times as the line.
+        let annotation = lines[0].split("] ").next().unwrap();
+        for (line, text) in lines.iter().zip(["12ms", "ok", "7ms"]) {
+            assert_eq!(*line, format!("{}] {}", annotation, text.red()));
+        }
+        assert!(pipeline.time_line("nothing").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_only_matching_named_group() -> Result<(), TimelnError> {
+        let mut epochs = pipeline()
+            .regex(r"epoch (?P<epoch>\d+)(/\d+)?")?
+            .only_matching(Some(CaptureGroup::Name("epoch".to_string())))?;
+        let timed = epochs.time_line("epoch 3/10 loss 0.5, epoch 4").unwrap();
+        let texts: Vec<&str> = timed
+            .output
+            .lines()
+            .map(|line| line.split("] ").nth(1).unwrap())
+            .collect();
+        assert_eq!(texts, ["3".red().to_string(), "4".red().to_string()]);
+
+        let missing = pipeline().regex("epoch (\\d+)")?;
+        assert!(missing
+            .only_matching(Some(CaptureGroup::Name("epoch".to_string())))
+            .is_err());
+        let unnumbered = pipeline().regex("epoch (\\d+)")?;
+        assert!(unnumbered
+            .only_matching(Some(CaptureGroup::Index(2)))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_strip_line_ending() {
+        assert_eq!(strip_line_ending("text\n"), "text");
+        assert_eq!(strip_line_ending("text\r\n"), "text");
+        assert_eq!(strip_line_ending("  text  \n"), "  text  ");
+        assert_eq!(strip_line_ending("last"), "last");
+        assert_eq!(strip_line_ending("last\r"), "last");
+        assert_eq!(strip_line_ending("two\n\n"), "two\n");
+    }
+
+    #[test]
+    fn test_pipeline_keeps_whitespace_unless_trimming() -> Result<(), TimelnError> {
+        let mut keeping = pipeline().regex("ok$")?.passthrough(true);
+        let timed = keeping.time_line("  ok  \r\n").unwrap();
+        assert!(timed.output.ends_with("]   ok  "));
+        let timed = keeping.time_line("  ok\r\n").unwrap();
+        assert!(timed.output.ends_with(&format!("]   {}", "ok".red())));
+
+        let mut trimming = pipeline().regex("^ok$")?.trim(true);
+        let timed = trimming.time_line("  ok  \n").unwrap();
+        assert!(timed.output.ends_with(&format!("] {}", "ok".red())));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pipeline_prefixes_labels() -> Result<(), TimelnError> {
+        let mut pipeline = pipeline()
+            .regex("ERROR")?
+            .regex("WARN")?
+            .labels(vec!["err".to_string(), "warn".to_string()])
+            .passthrough(true);
+        assert!(pipeline
+            .time_line("WARN disk")
+            .unwrap()
+            .output
+            .ends_with("] [warn] WARN disk"));
+        assert!(pipeline
+            .time_line("ERROR io")
+            .unwrap()
+            .output
+            .ends_with("] [err] ERROR io"));
+        assert!(pipeline
+            .time_line("INFO ok")
+            .unwrap()
+            .output
+            .ends_with("] INFO ok"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pipeline_tags_streams() {
+        let mut pipeline = pipeline();
+        let now = Instant::now();
+        let out = pipeline
+            .time_line_from("a\n", now, Some(Stream::Stdout))
+            .unwrap();
+        assert!(out.output.ends_with("] [out] a"));
+        let err = pipeline
+            .time_line_from("b\n", now, Some(Stream::Stderr))
+            .unwrap();
+        assert!(err.output.ends_with("] [err] b"));
+        assert!(pipeline.time_line("c").unwrap().output.ends_with("] c"));
+    }
+
+    #[test]
+    fn test_pipeline_invalid_regex() {
+        assert!(matches!(
+            pipeline().regex("("),
+            Err(TimelnError::RegexCompile { pattern, .. }) if pattern == "("
+        ));
+    }
+}
@@ -1,16 +1,354 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::Duration;
+
+use plotters::coord::Shift;
 use plotters::prelude::*;
 
-/// Plots time deltas and saves the plot as a PNG file.
+use crate::extract::ValueSeries;
+use crate::formatter::RateFormat;
+use crate::stats::{percentiles, REFERENCE_PERCENTILES};
+
+/// Error produced when a chart cannot be drawn or written.
+#[derive(Debug)]
+pub struct PlotError(String);
+
+impl std::fmt::Display for PlotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed to draw plot: {}", self.0)
+    }
+}
+
+impl std::error::Error for PlotError {}
+
+impl From<std::io::Error> for PlotError {
+    /// Converts an I/O error raised while writing plot output into a `PlotError`.
+    fn from(err: std::io::Error) -> Self {
+        PlotError(err.to_string())
+    }
+}
+
+impl<E: std::error::Error + Send + Sync> From<DrawingAreaErrorKind<E>> for PlotError {
+    /// Converts a plotters drawing error into a `PlotError`.
+    fn from(err: DrawingAreaErrorKind<E>) -> Self {
+        PlotError(err.to_string())
+    }
+}
+
+/// Returns `min..max`, widened to `min..min + 1` when the data does not span a range
+/// (no points, a single point, or all points equal).
+fn padded_range(min: f64, max: f64) -> std::ops::Range<f64> {
+    if max > min {
+        min..max
+    } else {
+        min..min + 1.0
+    }
+}
+
+/// Smallest delta (in seconds) drawn on a log-scaled axis; zero deltas are clamped up to this.
+const LOG_EPSILON: f64 = 1e-6;
+
+/// A unit in which durations are displayed on plot axes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeUnit {
+    Nanoseconds,
+    Microseconds,
+    Milliseconds,
+    Seconds,
+}
+
+impl TimeUnit {
+    /// Picks the largest unit in which `secs` is at least 1, so that a series whose maximum is
+    /// `secs` reads as e.g. 0–12 ms rather than 0.000–0.012 s.
+    pub fn for_seconds(secs: f64) -> Self {
+        if secs >= 1.0 {
+            TimeUnit::Seconds
+        } else if secs >= 1e-3 {
+            TimeUnit::Milliseconds
+        } else if secs >= 1e-6 {
+            TimeUnit::Microseconds
+        } else {
+            TimeUnit::Nanoseconds
+        }
+    }
+
+    /// Factor that converts a value in seconds into this unit.
+    pub fn per_second(self) -> f64 {
+        match self {
+            TimeUnit::Nanoseconds => 1e9,
+            TimeUnit::Microseconds => 1e6,
+            TimeUnit::Milliseconds => 1e3,
+            TimeUnit::Seconds => 1.0,
+        }
+    }
+
+    /// Short symbol of the unit.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            TimeUnit::Nanoseconds => "ns",
+            TimeUnit::Microseconds => "µs",
+            TimeUnit::Milliseconds => "ms",
+            TimeUnit::Seconds => "s",
+        }
+    }
+}
+
+/// Formats a value in seconds as a short axis label in the unit that suits it, so that
+/// decades read naturally (1ms, 10ms, 100ms, 1s, ...).
+pub(crate) fn format_axis_seconds(secs: f64) -> String {
+    let unit = TimeUnit::for_seconds(secs);
+    let value = format!("{:.3}", secs * unit.per_second());
+    let value = value.trim_end_matches('0').trim_end_matches('.');
+    format!("{}{}", value, unit.symbol())
+}
+
+/// Selects what the x-axis of the delta plot represents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum PlotX {
+    /// Plot against the line (or match) number.
+    #[default]
+    Index,
+    /// Plot against the elapsed time of each line, so gaps are visually proportional.
+    Time,
+}
+
+impl std::str::FromStr for PlotX {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "index" => Ok(PlotX::Index),
+            "time" => Ok(PlotX::Time),
+            _ => Err(format!(
+                "invalid plot x-axis '{}', expected 'index' or 'time'",
+                s
+            )),
+        }
+    }
+}
+
+/// Width and height of a plot in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlotSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for PlotSize {
+    fn default() -> Self {
+        Self {
+            width: 640,
+            height: 480,
+        }
+    }
+}
+
+impl std::str::FromStr for PlotSize {
+    type Err = String;
+
+    /// Parses a size given as `WIDTHxHEIGHT`, e.g. `1280x720`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid plot size '{}', expected WIDTHxHEIGHT", s);
+        let (width, height) = s.split_once(['x', 'X']).ok_or_else(invalid)?;
+        let width: u32 = width.trim().parse().map_err(|_| invalid())?;
+        let height: u32 = height.trim().parse().map_err(|_| invalid())?;
+        if width == 0 || height == 0 {
+            return Err(invalid());
+        }
+        Ok(Self { width, height })
+    }
+}
+
+/// Options shared by all plots.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlotConfig {
+    /// Dimensions of the generated image.
+    pub size: PlotSize,
+    /// Caption used instead of the default one for each chart.
+    pub title: Option<String>,
+    /// What the x-axis of the delta plot represents.
+    pub x: PlotX,
+    /// Whether to use a logarithmic y-axis for the delta plot. Deltas below 1µs are clamped so
+    /// that zero deltas stay on the chart.
+    pub log_y: bool,
+    /// Whether to mark the points of lines that matched the regex.
+    pub mark_matches: bool,
+    /// Legend label of each regex pattern. When there is more than one pattern, the delta plot
+    /// draws the deltas between consecutive matches of each pattern as separate series.
+    pub pattern_labels: Vec<String>,
+    /// Largest number of points drawn per series. Longer series are downsampled with
+    /// `downsample_min_max`; `None` draws every point.
+    pub max_points: Option<usize>,
+    /// Whether to draw the p50, p95 and p99 deltas as reference lines on the delta plot.
+    pub percentiles: bool,
+    /// Phase boundaries drawn as vertical lines on the delta plot, as the index among the timed
+    /// lines and the elapsed seconds at which each phase after the first starts.
+    pub phase_boundaries: Vec<(usize, f64)>,
+    /// Whether the delta plot shows the rate each delta stands for, `1/delta` in Hz, instead of
+    /// the delta.
+    pub rate: bool,
+}
+
+impl PlotConfig {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.size.width, self.size.height)
+    }
+
+    /// Returns the configured title, or `default` if none was given, noting when the plotted
+    /// data is a sample of the run (as `(shown, total)` points) or was downsampled.
+    fn caption(&self, default: &str, sampled: Option<(usize, usize)>, downsampled: bool) -> String {
+        let caption = self.title.as_deref().unwrap_or(default);
+        let mut notes = Vec::new();
+        if let Some((shown, total)) = sampled {
+            notes.push(format!("sampled {} of {} points", shown, total));
+        }
+        if downsampled {
+            notes.push("downsampled".to_string());
+        }
+        if notes.is_empty() {
+            caption.to_string()
+        } else {
+            format!("{} ({})", caption, notes.join(", "))
+        }
+    }
+
+    /// Whether a series of `len` points exceeds `max_points` and will be downsampled.
+    fn exceeds_max_points(&self, len: usize) -> bool {
+        self.max_points.is_some_and(|max| len > max)
+    }
+
+    /// Downsamples `points` to `max_points` if needed.
+    fn reduce(&self, points: Vec<(f64, f64)>) -> Vec<(f64, f64)> {
+        match self.max_points {
+            Some(max) if points.len() > max => downsample_min_max(&points, max),
+            _ => points,
+        }
+    }
+
+    /// Caption font, scaled with the image height so that large plots stay legible.
+    fn caption_font(&self) -> FontDesc<'static> {
+        (PLOT_FONT, (self.size.height / 16).max(12)).into_font()
+    }
+}
+
+/// Font family used for all plot text; a generic family is always resolvable.
+const PLOT_FONT: &str = "sans-serif";
+
+/// Default largest number of points drawn per series.
+pub const DEFAULT_PLOT_MAX_POINTS: usize = 10_000;
+
+/// Reduces `points` to at most `max_points` (but no fewer than 2) points.
+///
+/// The points are split into buckets of consecutive points, and the lowest and highest point of
+/// each bucket are kept in their original order. Unlike keeping every n-th point, this preserves
+/// spikes and dips however short they are. Series that already fit are returned unchanged.
+pub fn downsample_min_max(points: &[(f64, f64)], max_points: usize) -> Vec<(f64, f64)> {
+    let max_points = max_points.max(2);
+    if points.len() <= max_points {
+        return points.to_vec();
+    }
+    let buckets = max_points / 2;
+    let mut reduced = Vec::with_capacity(max_points);
+    for bucket in 0..buckets {
+        let start = bucket * points.len() / buckets;
+        let end = (bucket + 1) * points.len() / buckets;
+        let bucket = &points[start..end];
+        let (mut min, mut max) = (0, 0);
+        for (i, (_, y)) in bucket.iter().enumerate() {
+            if *y < bucket[min].1 {
+                min = i;
+            }
+            if *y > bucket[max].1 {
+                max = i;
+            }
+        }
+        reduced.push(bucket[min.min(max)]);
+        if min != max {
+            reduced.push(bucket[min.max(max)]);
+        }
+    }
+    reduced
+}
+
+/// The series drawn by the plots, with one entry per timed line.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlotData {
+    /// Elapsed time of each line in seconds.
+    pub times: Vec<f64>,
+    /// Time delta of each line in seconds.
+    pub deltas: Vec<f64>,
+    /// Index of the regex pattern each line matched, if any.
+    pub patterns: Vec<Option<usize>>,
+    /// Where the points come from when they are only a sample of the timed lines.
+    pub sample: Option<PlotSample>,
+}
+
+/// Positions of the points of a sampled series within the full run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlotSample {
+    /// Index of each point among all the timed lines.
+    pub indices: Vec<usize>,
+    /// Number of timed lines in the full run.
+    pub total: usize,
+}
+
+impl PlotData {
+    /// Number of points in the series.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    /// Whether the series has no points.
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Whether the line at `index` matched a regex pattern.
+    pub fn is_matched(&self, index: usize) -> bool {
+        self.pattern(index).is_some()
+    }
+
+    /// Index of the regex pattern matched by the line at `index`.
+    pub fn pattern(&self, index: usize) -> Option<usize> {
+        self.patterns.get(index).copied().flatten()
+    }
+
+    /// Index of the point at `index` among all the timed lines, used as its x value.
+    pub fn line_index(&self, index: usize) -> usize {
+        self.sample
+            .as_ref()
+            .and_then(|sample| sample.indices.get(index).copied())
+            .unwrap_or(index)
+    }
+
+    /// Number of timed lines in the full run.
+    pub fn total_len(&self) -> usize {
+        self.sample
+            .as_ref()
+            .map_or(self.len(), |sample| sample.total)
+    }
+
+    /// The number of points shown and in the full run, if the points are a sample.
+    fn sampled(&self) -> Option<(usize, usize)> {
+        self.sample
+            .as_ref()
+            .map(|sample| (self.len(), sample.total))
+    }
+}
+
+/// Plots time deltas and saves the plot as an SVG file.
 ///
-/// This function takes a vector of time deltas in seconds and a filename as arguments.
-/// It creates a line plot of these deltas and saves it as a PNG file.
-/// The x-axis of the plot represents the line number, and the y-axis represents the time delta.
+/// This function takes the collected series and a filename as arguments.
+/// It creates a line plot of the deltas and saves it as an SVG file.
+/// Depending on `config.x`, the x-axis of the plot represents the line number or the elapsed
+/// time, and the y-axis represents the time delta.
 /// The plot also includes a title and labels for both axes.
 ///
 /// # Arguments
 ///
-/// * `deltas` - A vector of f64 values representing time deltas in seconds.
+/// * `data` - The elapsed times, deltas (in seconds) and match flags of each line.
 /// * `filename` - The name of the file (including the extension) where the plot should be saved.
+/// * `config` - Size, title and axis options for the plot.
 ///
 /// # Errors
 ///
@@ -18,69 +356,679 @@ use plotters::prelude::*;
 ///
 /// # Example
 ///
+/// ```no_run
+/// use timeln::plot::{plot_deltas, PlotConfig, PlotData};
+///
+/// let data = PlotData {
+///     times: vec![0.1, 0.3, 0.6, 1.0, 1.5],
+///     deltas: vec![0.1, 0.2, 0.3, 0.4, 0.5],
+///     patterns: vec![None; 5],
+///     sample: None,
+/// };
+/// plot_deltas(&data, "deltas.svg", &PlotConfig::default()).unwrap();
 /// ```
-/// let deltas = vec![0.1, 0.2, 0.3, 0.4, 0.5];
-/// let filename = "deltas.png";
-/// plot_deltas(&deltas, filename).unwrap();
-/// ```
-pub fn plot_deltas(deltas: &Vec<f64>, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let root = SVGBackend::new(filename, (640, 480)).into_drawing_area();
+pub fn plot_deltas(data: &PlotData, filename: &str, config: &PlotConfig) -> Result<(), PlotError> {
+    let root = SVGBackend::new(filename, config.dimensions()).into_drawing_area();
+    draw_deltas(&root, &DeltaSeries::new(data, config), config)?;
+    root.present()?;
+    Ok(())
+}
+
+/// Plots the elapsed time of each line and saves the plot as an SVG file.
+pub fn plot_times(data: &PlotData, filename: &str, config: &PlotConfig) -> Result<(), PlotError> {
+    let root = SVGBackend::new(filename, config.dimensions()).into_drawing_area();
+    draw_times(&root, data, config)?;
+    root.present()?;
+    Ok(())
+}
+
+/// Renders the delta plot as an SVG document held in memory, for embedding in other documents.
+pub fn render_deltas_svg(data: &PlotData, config: &PlotConfig) -> Result<String, PlotError> {
+    let mut svg = String::new();
+    {
+        let root = SVGBackend::with_string(&mut svg, config.dimensions()).into_drawing_area();
+        draw_deltas(&root, &DeltaSeries::new(data, config), config)?;
+        root.present()?;
+    }
+    Ok(svg)
+}
+
+/// Renders the elapsed time plot as an SVG document held in memory.
+pub fn render_times_svg(data: &PlotData, config: &PlotConfig) -> Result<String, PlotError> {
+    let mut svg = String::new();
+    {
+        let root = SVGBackend::with_string(&mut svg, config.dimensions()).into_drawing_area();
+        draw_times(&root, data, config)?;
+        root.present()?;
+    }
+    Ok(svg)
+}
+
+/// Plots the values extracted from the lines against the time elapsed and saves the plot as an SVG
+/// file.
+pub fn plot_values(
+    series: &ValueSeries,
+    filename: &str,
+    config: &PlotConfig,
+) -> Result<(), PlotError> {
+    let root = SVGBackend::new(filename, config.dimensions()).into_drawing_area();
+    draw_values(&root, series, config)?;
+    root.present()?;
+    Ok(())
+}
+
+/// Renders the plot of extracted values as an SVG document held in memory.
+pub fn render_values_svg(series: &ValueSeries, config: &PlotConfig) -> Result<String, PlotError> {
+    let mut svg = String::new();
+    {
+        let root = SVGBackend::with_string(&mut svg, config.dimensions()).into_drawing_area();
+        draw_values(&root, series, config)?;
+        root.present()?;
+    }
+    Ok(svg)
+}
+
+/// Writes the points of the delta plot to a plain text file so they can be re-plotted elsewhere.
+///
+/// The points are exactly those drawn by `plot_deltas` with the same `config`: the first column
+/// is the line number or elapsed time (depending on `config.x`) and the second is the delta in
+/// seconds. If `filename` ends in `.csv` a CSV file with a header row is written, otherwise a
+/// whitespace-separated file suitable for gnuplot, with the header as a `#` comment.
+pub fn write_plot_data(
+    data: &PlotData,
+    filename: &str,
+    config: &PlotConfig,
+) -> Result<(), PlotError> {
+    // The file holds deltas, as its header says, even when the plot shows rates.
+    let config = PlotConfig {
+        rate: false,
+        ..config.clone()
+    };
+    let series = DeltaSeries::new(data, &config);
+    let mut out = PlotDataWriter::create(filename, config.x)?;
+    for (x, y) in &series.points {
+        out.write_point(*x, *y)?;
+    }
+    out.finish()?;
+    Ok(())
+}
+
+/// Writes plot data points one at a time, in the format described in `write_plot_data`.
+pub struct PlotDataWriter {
+    out: BufWriter<File>,
+    separator: &'static str,
+}
+
+impl PlotDataWriter {
+    /// Creates `filename` and writes the header for an x column of kind `x`.
+    pub fn create(filename: &str, x: PlotX) -> std::io::Result<Self> {
+        let x_column = match x {
+            PlotX::Index => "line_number",
+            PlotX::Time => "elapsed_seconds",
+        };
+        let csv = std::path::Path::new(filename)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+
+        let mut out = BufWriter::new(File::create(filename)?);
+        if csv {
+            writeln!(out, "{},delta_seconds", x_column)?;
+        } else {
+            writeln!(out, "# {} delta_seconds", x_column)?;
+        }
+        let separator = if csv { "," } else { " " };
+        Ok(Self { out, separator })
+    }
+
+    /// Appends the point `(x, delta)`, with `delta` in seconds.
+    pub fn write_point(&mut self, x: f64, delta: f64) -> std::io::Result<()> {
+        writeln!(self.out, "{}{}{}", x, self.separator, delta)
+    }
+
+    /// Flushes the points written so far to the file.
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// The points of a delta plot together with the description of its x-axis.
+/// All series are already downsampled according to `PlotConfig::max_points`.
+struct DeltaSeries {
+    points: Vec<(f64, f64)>,
+    matched: Vec<(f64, f64)>,
+    /// One labelled series per regex pattern, only used when there are several patterns.
+    patterns: Vec<(String, Vec<(f64, f64)>)>,
+    max_x: f64,
+    x_desc: &'static str,
+    caption: &'static str,
+    /// Points shown and in the full run, if the data is a sample.
+    sampled: Option<(usize, usize)>,
+    downsampled: bool,
+    /// Label and value of each reference percentile, computed before downsampling.
+    percentiles: Vec<(String, f64)>,
+    /// Whether the y values are rates in Hz rather than deltas in seconds.
+    rate: bool,
+}
+
+impl DeltaSeries {
+    fn new(data: &PlotData, config: &PlotConfig) -> Self {
+        let y = |delta: f64| match config.rate {
+            true => rate_hz(delta),
+            false => delta,
+        };
+        let values: Vec<f64> = data.deltas.iter().map(|delta| y(*delta)).collect();
+        let points: Vec<(f64, f64)> = match config.x {
+            PlotX::Index => values
+                .iter()
+                .enumerate()
+                .map(|(i, y)| (data.line_index(i) as f64, *y))
+                .collect(),
+            PlotX::Time => data
+                .times
+                .iter()
+                .cloned()
+                .zip(values.iter().cloned())
+                .collect(),
+        };
+        let matched: Vec<(f64, f64)> = points
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| data.is_matched(*i))
+            .map(|(_, point)| *point)
+            .collect();
+        let patterns: Vec<(String, Vec<(f64, f64)>)> = if config.pattern_labels.len() > 1 {
+            pattern_series(data, &points, &config.pattern_labels)
+                .into_iter()
+                .map(|(label, points)| {
+                    let points = points.into_iter().map(|(x, delta)| (x, y(delta)));
+                    (label, points.collect())
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let (max_x, x_desc, caption) = match (config.x, config.rate) {
+            (PlotX::Index, false) => (
+                data.total_len() as f64,
+                "Line number",
+                "Line number vs Time delta",
+            ),
+            (PlotX::Index, true) => (
+                data.total_len() as f64,
+                "Line number",
+                "Line number vs Rate",
+            ),
+            (PlotX::Time, rate) => (
+                points.iter().map(|(x, _)| *x).fold(0f64, f64::max),
+                "Time elapsed (seconds)",
+                match rate {
+                    true => "Time elapsed vs Rate",
+                    false => "Time elapsed vs Time delta",
+                },
+            ),
+        };
+        let downsampled = config.exceeds_max_points(points.len())
+            || patterns
+                .iter()
+                .any(|(_, points)| config.exceeds_max_points(points.len()));
+        let percentiles = if config.percentiles {
+            REFERENCE_PERCENTILES
+                .iter()
+                .zip(percentiles(&values, &REFERENCE_PERCENTILES))
+                .map(|(p, value)| (format!("p{}", p), value))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Self {
+            points: config.reduce(points),
+            matched: config.reduce(matched),
+            patterns: patterns
+                .into_iter()
+                .map(|(label, points)| (label, config.reduce(points)))
+                .collect(),
+            max_x,
+            x_desc,
+            caption,
+            sampled: data.sampled(),
+            downsampled,
+            percentiles,
+            rate: config.rate,
+        }
+    }
+
+    /// Formats a y value as a short axis label, in its unit.
+    fn format_y(&self, y: f64) -> String {
+        match self.rate {
+            true => RateFormat::format_hz(y),
+            false => format_axis_seconds(y),
+        }
+    }
+
+    /// The y values of every point that will be drawn.
+    fn y_values(&self) -> Box<dyn Iterator<Item = f64> + '_> {
+        if self.patterns.is_empty() {
+            Box::new(self.points.iter().map(|(_, y)| *y))
+        } else {
+            Box::new(
+                self.patterns
+                    .iter()
+                    .flat_map(|(_, points)| points.iter().map(|(_, y)| *y)),
+            )
+        }
+    }
+}
+
+/// The rate in Hz of a delta of `seconds`, clamped for deltas that are zero or too short as in
+/// `RateFormat`.
+fn rate_hz(seconds: f64) -> f64 {
+    RateFormat::hz(&Duration::from_secs_f64(seconds.max(0.0)))
+}
+
+/// Splits the matched lines into one series per pattern, where each point's y value is the
+/// time since the previous match of the same pattern (or since the start, for its first match).
+/// Patterns without matches get an empty series.
+fn pattern_series(
+    data: &PlotData,
+    points: &[(f64, f64)],
+    labels: &[String],
+) -> Vec<(String, Vec<(f64, f64)>)> {
+    let mut series: Vec<(String, Vec<(f64, f64)>)> = labels
+        .iter()
+        .map(|label| (truncate_label(label, LEGEND_LABEL_MAX_CHARS), Vec::new()))
+        .collect();
+    let mut last_match = vec![0f64; labels.len()];
+    for (i, (x, _)) in points.iter().enumerate() {
+        let Some(pattern) = data.pattern(i).filter(|p| *p < labels.len()) else {
+            continue;
+        };
+        let time = data.times[i];
+        series[pattern].1.push((*x, time - last_match[pattern]));
+        last_match[pattern] = time;
+    }
+    series
+}
+
+/// Longest legend label, in characters, before it is truncated.
+const LEGEND_LABEL_MAX_CHARS: usize = 20;
+
+/// Shortens `label` to at most `max_chars` characters, marking the cut with an ellipsis.
+fn truncate_label(label: &str, max_chars: usize) -> String {
+    if label.chars().count() <= max_chars {
+        return label.to_string();
+    }
+    let mut truncated: String = label.chars().take(max_chars.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// Colors cycled through for per-pattern series.
+const PALETTE: [RGBColor; 8] = [
+    RGBColor(228, 26, 28),
+    RGBColor(55, 126, 184),
+    RGBColor(77, 175, 74),
+    RGBColor(152, 78, 163),
+    RGBColor(255, 127, 0),
+    RGBColor(166, 86, 40),
+    RGBColor(247, 129, 191),
+    RGBColor(153, 153, 153),
+];
+
+/// Returns the color of the `index`-th series, cycling through the palette.
+pub fn palette_color(index: usize) -> RGBColor {
+    PALETTE[index % PALETTE.len()]
+}
+
+/// Color of the markers drawn at regex matches.
+const MATCH_COLOR: RGBColor = RGBColor(255, 140, 0);
+
+/// Color of the lines drawn at phase boundaries.
+const PHASE_COLOR: RGBColor = RGBColor(70, 130, 180);
+
+/// Draws the legend box for all labelled series of the chart.
+fn draw_legend<'a, DB, CT>(chart: &mut ChartContext<'a, DB, CT>) -> Result<(), PlotError>
+where
+    DB: DrawingBackend + 'a,
+    DB::ErrorType: 'static,
+    CT: CoordTranslate,
+{
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+    Ok(())
+}
+
+/// Draws dashed horizontal lines at the reference percentiles of a delta series, each labelled
+/// with its name and value.
+fn draw_percentile_lines<'a, DB, CT>(
+    chart: &mut ChartContext<'a, DB, CT>,
+    series: &DeltaSeries,
+    scale: f64,
+    floor: f64,
+) -> Result<(), PlotError>
+where
+    DB: DrawingBackend + 'a,
+    DB::ErrorType: 'static,
+    CT: CoordTranslate<From = (f64, f64)>,
+{
+    let style = ShapeStyle::from(&BLACK.mix(0.6)).stroke_width(1);
+    for (label, value) in &series.percentiles {
+        let y = (value * scale).max(floor);
+        chart.draw_series(DashedLineSeries::new(
+            vec![(0f64, y), (series.max_x, y)],
+            6,
+            4,
+            style,
+        ))?;
+        chart.draw_series(std::iter::once(Text::new(
+            format!("{} {}", label, series.format_y(*value)),
+            (0f64, y),
+            (PLOT_FONT, 11).into_font().color(&BLACK.mix(0.8)),
+        )))?;
+    }
+    Ok(())
+}
+
+/// Draws dotted vertical lines at the phase boundaries of `config`, across the deltas of a series.
+fn draw_phase_boundaries<'a, DB, CT>(
+    chart: &mut ChartContext<'a, DB, CT>,
+    series: &DeltaSeries,
+    config: &PlotConfig,
+    scale: f64,
+    floor: f64,
+) -> Result<(), PlotError>
+where
+    DB: DrawingBackend + 'a,
+    DB::ErrorType: 'static,
+    CT: CoordTranslate<From = (f64, f64)>,
+{
+    let top = series.y_values().fold(floor, |top, y| top.max(y * scale));
+    let style = ShapeStyle::from(&PHASE_COLOR).stroke_width(1);
+    for (index, start) in &config.phase_boundaries {
+        let x = match config.x {
+            PlotX::Index => *index as f64,
+            PlotX::Time => *start,
+        };
+        chart.draw_series(DashedLineSeries::new(
+            vec![(x, floor), (x, top)],
+            2,
+            3,
+            style,
+        ))?;
+    }
+    Ok(())
+}
+
+/// Draws the delta lines, and the match markers if enabled, onto a chart whose axes are set up.
+/// Deltas are multiplied by `scale` to convert them into the axis unit, then raised to `floor`
+/// so that they can be drawn on a log-scaled axis.
+fn draw_delta_lines<'a, DB, CT>(
+    chart: &mut ChartContext<'a, DB, CT>,
+    series: &DeltaSeries,
+    config: &PlotConfig,
+    scale: f64,
+    floor: f64,
+) -> Result<(), PlotError>
+where
+    DB: DrawingBackend + 'a,
+    DB::ErrorType: 'static,
+    CT: CoordTranslate<From = (f64, f64)>,
+{
+    let clamp = |points: &[(f64, f64)]| -> Vec<(f64, f64)> {
+        points
+            .iter()
+            .map(|(x, y)| (*x, (y * scale).max(floor)))
+            .collect()
+    };
+
+    draw_percentile_lines(chart, series, scale, floor)?;
+    draw_phase_boundaries(chart, series, config, scale, floor)?;
+
+    if series.patterns.is_empty() {
+        chart.draw_series(LineSeries::new(clamp(&series.points), &RED))?;
+
+        if config.mark_matches && !series.matched.is_empty() {
+            chart
+                .draw_series(
+                    clamp(&series.matched)
+                        .into_iter()
+                        .map(|point| Circle::new(point, 3, MATCH_COLOR.filled())),
+                )?
+                .label("regex match")
+                .legend(|(x, y)| Circle::new((x, y), 3, MATCH_COLOR.filled()));
+            draw_legend(chart)?;
+        }
+        return Ok(());
+    }
+
+    let mut labelled = false;
+    for (i, (label, points)) in series.patterns.iter().enumerate() {
+        if points.is_empty() {
+            continue;
+        }
+        let color = palette_color(i);
+        chart
+            .draw_series(LineSeries::new(clamp(points), &color))?
+            .label(label.as_str())
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+        labelled = true;
+    }
+    if labelled {
+        draw_legend(chart)?;
+    }
+
+    Ok(())
+}
+
+/// Draws a delta series as a red line, on a linear or log-scaled y-axis.
+fn draw_deltas<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    series: &DeltaSeries,
+    config: &PlotConfig,
+) -> Result<(), PlotError>
+where
+    DB::ErrorType: 'static,
+{
+    if config.log_y {
+        return draw_deltas_log(root, series, config);
+    }
+
+    root.fill(&WHITE)?;
+
+    let max_y = series.y_values().fold(0f64, f64::max);
+    let (scale, y_desc) = match series.rate {
+        true => (1f64, "Rate (Hz)".to_string()),
+        false => {
+            let unit = TimeUnit::for_seconds(max_y);
+            let y_desc = format!("Time delta ({})", unit.symbol());
+            (unit.per_second(), y_desc)
+        }
+    };
+
+    let mut chart = ChartBuilder::on(root)
+        .margin(5)
+        .caption(
+            config.caption(series.caption, series.sampled, series.downsampled),
+            config.caption_font(),
+        )
+        .set_all_label_area_size(50)
+        .build_cartesian_2d(
+            padded_range(0f64, series.max_x),
+            padded_range(0f64, max_y * scale),
+        )?;
+
+    chart
+        .configure_mesh()
+        .x_desc(series.x_desc)
+        .y_desc(y_desc)
+        .draw()?;
+
+    draw_delta_lines(&mut chart, series, config, scale, 0f64)
+}
+
+/// Draws a delta series on a log-scaled y-axis, clamping deltas to `LOG_EPSILON`.
+fn draw_deltas_log<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    series: &DeltaSeries,
+    config: &PlotConfig,
+) -> Result<(), PlotError>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+
+    let min_y = series
+        .y_values()
+        .map(|y| y.max(LOG_EPSILON))
+        .fold(f64::INFINITY, f64::min);
+    let min_y = if min_y.is_finite() {
+        min_y
+    } else {
+        LOG_EPSILON
+    };
+    let max_y = series.y_values().fold(min_y, f64::max);
+    let max_y = if max_y > min_y { max_y } else { min_y * 10.0 };
+
+    let mut chart = ChartBuilder::on(root)
+        .margin(5)
+        .caption(
+            config.caption(series.caption, series.sampled, series.downsampled),
+            config.caption_font(),
+        )
+        .set_all_label_area_size(50)
+        .build_cartesian_2d(padded_range(0f64, series.max_x), (min_y..max_y).log_scale())?;
+
+    chart
+        .configure_mesh()
+        .x_desc(series.x_desc)
+        .y_desc(match series.rate {
+            true => "Rate",
+            false => "Time delta",
+        })
+        .y_label_formatter(&|y| series.format_y(*y))
+        .draw()?;
+
+    draw_delta_lines(&mut chart, series, config, 1f64, LOG_EPSILON)
+}
+
+/// Draws the elapsed time of each line as a blue line against its line number.
+fn draw_times<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    data: &PlotData,
+    config: &PlotConfig,
+) -> Result<(), PlotError>
+where
+    DB::ErrorType: 'static,
+{
     root.fill(&WHITE)?;
 
-    let max_y = *deltas
+    let max_y = data.times.iter().cloned().fold(0f64, f64::max);
+    let max_x = data.total_len() as f64;
+    let unit = TimeUnit::for_seconds(max_y);
+    let points: Vec<(f64, f64)> = data
+        .times
+        .iter()
+        .enumerate()
+        .map(|(i, time)| (data.line_index(i) as f64, time * unit.per_second()))
+        .collect();
+    let matched: Vec<(f64, f64)> = points
         .iter()
-        .max_by(|a, b| a.partial_cmp(b).unwrap())
-        .unwrap_or(&1f64);
-    let min_y = 0f64;
-    let max_x = deltas.len() as f64;
+        .enumerate()
+        .filter(|(i, _)| data.is_matched(*i))
+        .map(|(_, point)| *point)
+        .collect();
+    let downsampled = config.exceeds_max_points(points.len());
 
-    let mut chart = ChartBuilder::on(&root)
+    let mut chart = ChartBuilder::on(root)
         .margin(5)
-        .caption("Line number vs Time delta", ("Arial", 30).into_font())
+        .caption(
+            config.caption("Line number vs Time Elapsed", data.sampled(), downsampled),
+            config.caption_font(),
+        )
         .set_all_label_area_size(50)
-        .build_cartesian_2d(0f64..max_x, min_y..max_y)?;
+        .build_cartesian_2d(
+            padded_range(0f64, max_x),
+            padded_range(0f64, max_y * unit.per_second()),
+        )?;
 
     chart
         .configure_mesh()
         .x_desc("Line number")
-        .y_desc("Time delta (seconds)")
+        .y_desc(format!("Time Elapsed ({})", unit.symbol()))
         .draw()?;
 
-    chart.draw_series(LineSeries::new(
-        deltas.iter().enumerate().map(|(x, y)| (x as f64, *y)),
-        &RED,
-    ))?;
+    chart.draw_series(LineSeries::new(config.reduce(points), &BLUE))?;
+
+    if config.mark_matches && !matched.is_empty() {
+        chart
+            .draw_series(
+                config
+                    .reduce(matched)
+                    .into_iter()
+                    .map(|point| Circle::new(point, 3, MATCH_COLOR.filled())),
+            )?
+            .label("regex match")
+            .legend(|(x, y)| Circle::new((x, y), 3, MATCH_COLOR.filled()));
+        draw_legend(&mut chart)?;
+    }
 
     Ok(())
 }
 
-pub fn plot_times(times: &Vec<f64>, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let root = SVGBackend::new(filename, (640, 480)).into_drawing_area();
+/// Draws the extracted values as a blue line against the time elapsed, marking each value.
+fn draw_values<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    series: &ValueSeries,
+    config: &PlotConfig,
+) -> Result<(), PlotError>
+where
+    DB::ErrorType: 'static,
+{
     root.fill(&WHITE)?;
 
-    let max_y = *times
+    let max_x = series.points.iter().map(|(x, _)| *x).fold(0f64, f64::max);
+    let unit = TimeUnit::for_seconds(max_x);
+    let points: Vec<(f64, f64)> = series
+        .points
         .iter()
-        .max_by(|a, b| a.partial_cmp(b).unwrap())
-        .unwrap_or(&1f64);
-    let min_y = 0f64;
-    let max_x = times.len() as f64;
+        .map(|(x, y)| (x * unit.per_second(), *y))
+        .collect();
+    let downsampled = config.exceeds_max_points(points.len());
+    let points = config.reduce(points);
 
-    let mut chart = ChartBuilder::on(&root)
+    let mut chart = ChartBuilder::on(root)
         .margin(5)
-        .caption("Line number vs Time Elapsed", ("Arial", 30).into_font())
+        .caption(
+            config.caption(
+                &format!("Time elapsed vs {}", series.name),
+                None,
+                downsampled,
+            ),
+            config.caption_font(),
+        )
         .set_all_label_area_size(50)
-        .build_cartesian_2d(0f64..max_x, min_y..max_y)?;
+        .build_cartesian_2d(
+            padded_range(0f64, max_x * unit.per_second()),
+            padded_range(
+                series.stats.min().unwrap_or(0.0),
+                series.stats.max().unwrap_or(0.0),
+            ),
+        )?;
 
     chart
         .configure_mesh()
-        .x_desc("Line number")
-        .y_desc("Time Elapsed (seconds)")
+        .x_desc(format!("Time Elapsed ({})", unit.symbol()))
+        .y_desc(truncate_label(&series.name, 40))
         .draw()?;
 
-    chart.draw_series(LineSeries::new(
-        times.iter().enumerate().map(|(x, y)| (x as f64, *y)),
-        &BLUE,
-    ))?;
+    chart.draw_series(LineSeries::new(points.iter().copied(), &BLUE))?;
+    chart.draw_series(
+        points
+            .into_iter()
+            .map(|point| Circle::new(point, 2, BLUE.filled())),
+    )?;
 
     Ok(())
 }
@@ -88,13 +1036,35 @@ pub fn plot_times(times: &Vec<f64>, filename: &str) -> Result<(), Box<dyn std::e
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::formatter::MAX_RATE_HZ;
     use std::path::Path;
 
+    fn plot_data(times: &[f64], deltas: &[f64]) -> PlotData {
+        PlotData {
+            times: times.to_vec(),
+            deltas: deltas.to_vec(),
+            patterns: vec![None; deltas.len()],
+            sample: None,
+        }
+    }
+
+    /// Elapsed times that are consistent with `deltas`.
+    fn cumulative(deltas: &[f64]) -> Vec<f64> {
+        deltas
+            .iter()
+            .scan(0.0, |elapsed, delta| {
+                *elapsed += delta;
+                Some(*elapsed)
+            })
+            .collect()
+    }
+
     #[test]
     fn test_plot_deltas() -> Result<(), Box<dyn std::error::Error>> {
         let deltas = vec![0.1, 0.2, 0.3, 0.4, 0.5];
         let filename = "test_deltas.svg";
-        plot_deltas(&deltas, filename)?;
+        let data = plot_data(&cumulative(&deltas), &deltas);
+        plot_deltas(&data, filename, &PlotConfig::default())?;
 
         // Check that the file was created
         assert!(Path::new(filename).exists());
@@ -108,4 +1078,503 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_plot_deltas_log_y_with_zero_deltas() -> Result<(), Box<dyn std::error::Error>> {
+        let deltas = vec![0.0, 0.001, 0.0, 0.5, 30.0];
+        let filename = "test_deltas_log.svg";
+        let config = PlotConfig {
+            log_y: true,
+            ..Default::default()
+        };
+        plot_deltas(&plot_data(&cumulative(&deltas), &deltas), filename, &config)?;
+
+        let metadata = std::fs::metadata(filename)?;
+        assert!(metadata.len() > 0);
+
+        std::fs::remove_file(filename)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plot_deltas_log_y_all_zero() -> Result<(), Box<dyn std::error::Error>> {
+        let deltas = vec![0.0, 0.0, 0.0];
+        let filename = "test_deltas_log_zero.svg";
+        let config = PlotConfig {
+            log_y: true,
+            ..Default::default()
+        };
+        plot_deltas(&plot_data(&cumulative(&deltas), &deltas), filename, &config)?;
+
+        assert!(Path::new(filename).exists());
+
+        std::fs::remove_file(filename)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_axis_seconds() {
+        assert_eq!(format_axis_seconds(0.001), "1ms");
+        assert_eq!(format_axis_seconds(0.01), "10ms");
+        assert_eq!(format_axis_seconds(0.1), "100ms");
+        assert_eq!(format_axis_seconds(1.0), "1s");
+        assert_eq!(format_axis_seconds(0.000_01), "10µs");
+        assert_eq!(format_axis_seconds(2.5), "2.5s");
+        assert_eq!(format_axis_seconds(0.0), "0ns");
+    }
+
+    #[test]
+    fn test_time_unit_thresholds() {
+        assert_eq!(TimeUnit::for_seconds(0.0), TimeUnit::Nanoseconds);
+        assert_eq!(TimeUnit::for_seconds(999e-9), TimeUnit::Nanoseconds);
+        assert_eq!(TimeUnit::for_seconds(1e-6), TimeUnit::Microseconds);
+        assert_eq!(TimeUnit::for_seconds(999e-6), TimeUnit::Microseconds);
+        assert_eq!(TimeUnit::for_seconds(1e-3), TimeUnit::Milliseconds);
+        assert_eq!(TimeUnit::for_seconds(0.999), TimeUnit::Milliseconds);
+        assert_eq!(TimeUnit::for_seconds(1.0), TimeUnit::Seconds);
+        assert_eq!(TimeUnit::for_seconds(3600.0), TimeUnit::Seconds);
+        assert_eq!(TimeUnit::Milliseconds.per_second(), 1e3);
+        assert_eq!(TimeUnit::Microseconds.symbol(), "µs");
+    }
+
+    #[test]
+    fn test_plot_axis_adapts_unit() -> Result<(), Box<dyn std::error::Error>> {
+        let deltas = vec![0.002, 0.004, 0.003];
+        let data = plot_data(&cumulative(&deltas), &deltas);
+        let config = PlotConfig::default();
+        let deltas_svg = render_deltas_svg(&data, &config)?;
+        let times_svg = render_times_svg(&data, &config)?;
+        assert!(deltas_svg.contains("Time delta (ms)"));
+        assert!(times_svg.contains("Time Elapsed (ms)"));
+
+        let deltas = vec![1.5, 2.0];
+        let data = plot_data(&cumulative(&deltas), &deltas);
+        assert!(render_deltas_svg(&data, &config)?.contains("Time delta (s)"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_plot_deltas_index_and_time_variants() -> Result<(), Box<dyn std::error::Error>> {
+        let times = vec![0.1, 0.2, 5.0, 5.1, 12.0];
+        let deltas = vec![0.1, 0.1, 4.8, 0.1, 6.9];
+
+        let index_file = "test_deltas_index.svg";
+        let time_file = "test_deltas_time.svg";
+        let data = plot_data(&times, &deltas);
+        plot_deltas(&data, index_file, &PlotConfig::default())?;
+        let config = PlotConfig {
+            x: PlotX::Time,
+            ..Default::default()
+        };
+        plot_deltas(&data, time_file, &config)?;
+
+        let index_svg = std::fs::read_to_string(index_file)?;
+        let time_svg = std::fs::read_to_string(time_file)?;
+        assert!(index_svg.contains("Line number"));
+        assert!(time_svg.contains("Time elapsed (seconds)"));
+
+        std::fs::remove_file(index_file)?;
+        std::fs::remove_file(time_file)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plot_x_from_str() {
+        assert_eq!("index".parse::<PlotX>(), Ok(PlotX::Index));
+        assert_eq!("time".parse::<PlotX>(), Ok(PlotX::Time));
+        assert!("line".parse::<PlotX>().is_err());
+    }
+
+    #[test]
+    fn test_render_svgs_in_memory() -> Result<(), Box<dyn std::error::Error>> {
+        let times = vec![0.1, 0.3, 0.6];
+        let deltas = vec![0.1, 0.2, 0.3];
+        let data = plot_data(&times, &deltas);
+        let config = PlotConfig::default();
+        let deltas_svg = render_deltas_svg(&data, &config)?;
+        let times_svg = render_times_svg(&data, &config)?;
+        assert!(deltas_svg.starts_with("<svg"));
+        assert!(deltas_svg.contains("Line number vs Time delta"));
+        assert!(times_svg.contains("Line number vs Time Elapsed"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_plot_values() -> Result<(), Box<dyn std::error::Error>> {
+        let mut series = ValueSeries::new("loss");
+        for (i, value) in ["0.5", "0.3", "0.2"].iter().enumerate() {
+            series.push(value, std::time::Duration::from_millis(250 * i as u64));
+        }
+        let svg = render_values_svg(&series, &PlotConfig::default())?;
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("Time elapsed vs loss"));
+        assert!(svg.contains("Time Elapsed (ms)"));
+
+        let empty = render_values_svg(&ValueSeries::new("acc"), &PlotConfig::default())?;
+        assert!(empty.contains("Time elapsed vs acc"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_plot_size_and_title() -> Result<(), Box<dyn std::error::Error>> {
+        let times = vec![0.1, 0.3, 0.6];
+        let deltas = vec![0.1, 0.2, 0.3];
+        let config = PlotConfig {
+            size: "1280x720".parse()?,
+            title: Some("Nightly build".to_string()),
+            ..Default::default()
+        };
+        let filename = "test_deltas_sized.svg";
+        plot_deltas(&plot_data(&times, &deltas), filename, &config)?;
+
+        let svg = std::fs::read_to_string(filename)?;
+        assert!(svg.contains("width=\"1280\""));
+        assert!(svg.contains("height=\"720\""));
+        assert!(svg.contains("Nightly build"));
+        assert!(svg.contains("sans-serif"));
+
+        std::fs::remove_file(filename)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plot_size_from_str() {
+        assert_eq!(
+            "1280x720".parse::<PlotSize>(),
+            Ok(PlotSize {
+                width: 1280,
+                height: 720
+            })
+        );
+        assert!("1280".parse::<PlotSize>().is_err());
+        assert!("0x720".parse::<PlotSize>().is_err());
+        assert!("axb".parse::<PlotSize>().is_err());
+    }
+
+    #[test]
+    fn test_plot_marks_matches() -> Result<(), Box<dyn std::error::Error>> {
+        let deltas = vec![0.1, 0.5, 0.1, 0.1, 0.7, 0.1, 0.9];
+        let mut data = plot_data(&cumulative(&deltas), &deltas);
+        data.patterns = vec![None, Some(0), None, None, Some(0), None, Some(0)];
+        let config = PlotConfig {
+            mark_matches: true,
+            ..Default::default()
+        };
+
+        let deltas_svg = render_deltas_svg(&data, &config)?;
+        let times_svg = render_times_svg(&data, &config)?;
+        assert_eq!(deltas_svg.matches("<circle").count(), 3 + 1);
+        assert_eq!(times_svg.matches("<circle").count(), 3 + 1);
+        assert!(deltas_svg.contains("regex match"));
+
+        let log_config = PlotConfig {
+            log_y: true,
+            ..config
+        };
+        let log_svg = render_deltas_svg(&data, &log_config)?;
+        assert!(log_svg.contains("regex match"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plot_marks_no_matches() -> Result<(), Box<dyn std::error::Error>> {
+        let deltas = vec![0.1, 0.5, 0.1];
+        let data = plot_data(&cumulative(&deltas), &deltas);
+        let config = PlotConfig {
+            mark_matches: true,
+            ..Default::default()
+        };
+
+        let deltas_svg = render_deltas_svg(&data, &config)?;
+        assert!(!deltas_svg.contains("<circle"));
+        assert!(!deltas_svg.contains("regex match"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plot_zero_one_and_two_points() -> Result<(), Box<dyn std::error::Error>> {
+        for (n, deltas) in [vec![], vec![0.0], vec![0.2, 0.2]].iter().enumerate() {
+            let data = plot_data(&cumulative(deltas), deltas);
+            for x in [PlotX::Index, PlotX::Time] {
+                for log_y in [false, true] {
+                    let config = PlotConfig {
+                        x,
+                        log_y,
+                        ..Default::default()
+                    };
+                    let deltas_file = format!("test_deltas_{}_{:?}_{}.svg", n, x, log_y);
+                    let times_file = format!("test_times_{}_{:?}_{}.svg", n, x, log_y);
+                    plot_deltas(&data, &deltas_file, &config)?;
+                    plot_times(&data, &times_file, &config)?;
+                    assert!(std::fs::metadata(&deltas_file)?.len() > 0);
+                    assert!(std::fs::metadata(&times_file)?.len() > 0);
+                    std::fs::remove_file(&deltas_file)?;
+                    std::fs::remove_file(&times_file)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_padded_range() {
+        assert_eq!(padded_range(0.0, 2.0), 0.0..2.0);
+        assert_eq!(padded_range(0.0, 0.0), 0.0..1.0);
+        assert_eq!(padded_range(3.0, 3.0), 3.0..4.0);
+    }
+
+    #[test]
+    fn test_plot_error_on_unwritable_path() {
+        let data = plot_data(&[0.1], &[0.1]);
+        let result = plot_deltas(&data, "/nonexistent-dir/deltas.svg", &PlotConfig::default());
+        assert!(result.is_err());
+    }
+
+    fn read_rows(filename: &str, separator: char) -> Vec<(f64, f64)> {
+        std::fs::read_to_string(filename)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|line| {
+                let (x, y) = line.split_once(separator).unwrap();
+                (x.parse().unwrap(), y.parse().unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_write_plot_data_gnuplot() -> Result<(), Box<dyn std::error::Error>> {
+        let deltas = vec![0.1, 0.25, 1e-7, 3.0];
+        let data = plot_data(&cumulative(&deltas), &deltas);
+        let filename = "test_plot_data.dat";
+        write_plot_data(&data, filename, &PlotConfig::default())?;
+
+        let contents = std::fs::read_to_string(filename)?;
+        assert!(contents.starts_with("# line_number delta_seconds\n"));
+        let rows = read_rows(filename, ' ');
+        assert_eq!(rows.len(), data.len());
+        for (i, (x, y)) in rows.iter().enumerate() {
+            assert_eq!(*x, i as f64);
+            assert_eq!(*y, deltas[i]);
+        }
+
+        std::fs::remove_file(filename)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_plot_data_csv_over_time() -> Result<(), Box<dyn std::error::Error>> {
+        let deltas = vec![0.1, 0.25, 0.5];
+        let times = cumulative(&deltas);
+        let data = plot_data(&times, &deltas);
+        let filename = "test_plot_data.csv";
+        let config = PlotConfig {
+            x: PlotX::Time,
+            ..Default::default()
+        };
+        write_plot_data(&data, filename, &config)?;
+
+        let contents = std::fs::read_to_string(filename)?;
+        assert!(contents.starts_with("elapsed_seconds,delta_seconds\n"));
+        let rows = read_rows(filename, ',');
+        assert_eq!(rows.len(), data.len());
+        for (i, (x, y)) in rows.iter().enumerate() {
+            assert_eq!(*x, times[i]);
+            assert_eq!(*y, deltas[i]);
+        }
+
+        std::fs::remove_file(filename)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_plot_per_pattern_series() -> Result<(), Box<dyn std::error::Error>> {
+        let times = vec![1.0, 1.5, 2.0, 3.0, 3.5, 5.0];
+        let deltas = vec![1.0, 0.5, 0.5, 1.0, 0.5, 1.5];
+        let mut data = plot_data(&times, &deltas);
+        data.patterns = vec![Some(0), Some(1), Some(0), Some(0), Some(1), Some(0)];
+        let config = PlotConfig {
+            pattern_labels: vec![
+                "epoch".to_string(),
+                "checkpoint saved to disk at step".to_string(),
+                "never".to_string(),
+            ],
+            ..Default::default()
+        };
+
+        let series = DeltaSeries::new(&data, &config);
+        assert_eq!(
+            series.patterns[0].1,
+            vec![(0.0, 1.0), (2.0, 1.0), (3.0, 1.0), (5.0, 2.0)]
+        );
+        assert_eq!(series.patterns[1].1, vec![(1.0, 1.5), (4.0, 2.0)]);
+        assert!(series.patterns[2].1.is_empty());
+
+        let svg = render_deltas_svg(&data, &config)?;
+        assert!(svg.contains("epoch"));
+        assert!(svg.contains("checkpoint saved to…"));
+        assert!(!svg.contains("never"));
+
+        let log_svg = render_deltas_svg(
+            &data,
+            &PlotConfig {
+                log_y: true,
+                ..config
+            },
+        )?;
+        assert!(log_svg.contains("epoch"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_downsample_min_max_keeps_spike() {
+        let mut points: Vec<(f64, f64)> = (0..1_000_000)
+            .map(|i| (i as f64, 0.001 + (i % 7) as f64 * 1e-5))
+            .collect();
+        points[765_432].1 = 5.0;
+        let reduced = downsample_min_max(&points, DEFAULT_PLOT_MAX_POINTS);
+        assert!(reduced.len() <= DEFAULT_PLOT_MAX_POINTS);
+        assert!(reduced.contains(&(765_432.0, 5.0)));
+        assert!(reduced.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn test_downsample_min_max_short_series() {
+        let points = vec![(0.0, 1.0), (1.0, 3.0), (2.0, 2.0)];
+        assert_eq!(downsample_min_max(&points, 3), points);
+        assert_eq!(downsample_min_max(&points, 2), vec![(0.0, 1.0), (1.0, 3.0)]);
+    }
+
+    #[test]
+    fn test_plot_notes_downsampling() -> Result<(), Box<dyn std::error::Error>> {
+        let deltas = vec![0.01; 100];
+        let data = plot_data(&cumulative(&deltas), &deltas);
+        let config = PlotConfig {
+            max_points: Some(10),
+            ..PlotConfig::default()
+        };
+        assert!(render_deltas_svg(&data, &config)?.contains("(downsampled)"));
+        assert!(render_times_svg(&data, &config)?.contains("(downsampled)"));
+        assert!(!render_deltas_svg(&data, &PlotConfig::default())?.contains("(downsampled)"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_plot_percentile_lines() -> Result<(), Box<dyn std::error::Error>> {
+        let deltas: Vec<f64> = (1..=100).map(|i| i as f64 * 0.001).collect();
+        let data = plot_data(&cumulative(&deltas), &deltas);
+        let config = PlotConfig {
+            percentiles: true,
+            ..PlotConfig::default()
+        };
+        let svg = render_deltas_svg(&data, &config)?;
+        for label in ["p50 50ms", "p95 95ms", "p99 99ms"] {
+            assert!(svg.contains(label), "missing {}", label);
+        }
+        let log_svg = render_deltas_svg(
+            &data,
+            &PlotConfig {
+                log_y: true,
+                ..config
+            },
+        )?;
+        assert!(log_svg.contains("p99 99ms"));
+        assert!(!render_deltas_svg(&data, &PlotConfig::default())?.contains("p50"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_plot_phase_boundaries() -> Result<(), Box<dyn std::error::Error>> {
+        let deltas: Vec<f64> = (1..=100)
+            .map(|i| if i <= 50 { 0.01 } else { 0.2 })
+            .collect();
+        let data = plot_data(&cumulative(&deltas), &deltas);
+        let config = PlotConfig {
+            phase_boundaries: vec![(50, 0.5)],
+            ..PlotConfig::default()
+        };
+        let phase_color = "#4682B4";
+        assert!(render_deltas_svg(&data, &config)?.contains(phase_color));
+        for config in [
+            PlotConfig {
+                x: PlotX::Time,
+                ..config.clone()
+            },
+            PlotConfig {
+                log_y: true,
+                ..config
+            },
+        ] {
+            assert!(render_deltas_svg(&data, &config)?.contains(phase_color));
+        }
+        assert!(!render_deltas_svg(&data, &PlotConfig::default())?.contains(phase_color));
+        Ok(())
+    }
+
+    #[test]
+    fn test_plot_rate() -> Result<(), Box<dyn std::error::Error>> {
+        let deltas = [0.5, 0.001, 0.0];
+        let data = plot_data(&cumulative(&deltas), &deltas);
+        let config = PlotConfig {
+            rate: true,
+            ..PlotConfig::default()
+        };
+        let series = DeltaSeries::new(&data, &config);
+        let rates: Vec<f64> = series.points.iter().map(|(_, y)| *y).collect();
+        // The zero delta is clamped rather than infinite.
+        assert_eq!(rates, [2.0, 1000.0, MAX_RATE_HZ]);
+        assert_eq!(series.format_y(1000.0), "1.00 kHz");
+        let svg = render_deltas_svg(&data, &config)?;
+        assert!(svg.contains("Line number vs Rate"));
+        assert!(svg.contains("Rate (Hz)"));
+        let log_svg = render_deltas_svg(
+            &data,
+            &PlotConfig {
+                log_y: true,
+                ..config
+            },
+        )?;
+        assert!(log_svg.contains("kHz"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_plot_sampled_data() -> Result<(), Box<dyn std::error::Error>> {
+        let data = PlotData {
+            sample: Some(PlotSample {
+                indices: vec![2, 40, 97],
+                total: 100,
+            }),
+            ..plot_data(&[0.1, 0.5, 1.2], &[0.01, 0.02, 0.03])
+        };
+        assert_eq!(data.line_index(1), 40);
+        assert_eq!(data.total_len(), 100);
+        let series = DeltaSeries::new(&data, &PlotConfig::default());
+        assert_eq!(series.points[2], (97.0, 0.03));
+        assert_eq!(series.max_x, 100.0);
+
+        let config = PlotConfig::default();
+        assert!(render_deltas_svg(&data, &config)?.contains("(sampled 3 of 100 points)"));
+        assert!(render_times_svg(&data, &config)?.contains("(sampled 3 of 100 points)"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncate_label_and_palette() {
+        assert_eq!(truncate_label("short", 20), "short");
+        assert_eq!(
+            truncate_label("abcdefghijklmnopqrstuvwxyz", 20)
+                .chars()
+                .count(),
+            20
+        );
+        assert_eq!(palette_color(0), palette_color(PALETTE.len()));
+        assert_ne!(palette_color(0), palette_color(1));
+    }
 }
@@ -0,0 +1,252 @@
+//! This module tracks the progress of a run towards a known number of regex matches, with
+//! `--total-matches`, such as the 100 epochs of a training run matched by `-r epoch`.
+//!
+//! The time left is estimated from the interval between matches: the mean of all of them so far,
+//! or their moving average with `--ewma`, so that the estimate follows a run that speeds up or
+//! slows down. The first interval is the time from the start of the run to the first match. Once
+//! the run ends, the estimate of its total time made at the last match is compared to the time it
+//! really took, to tell how far off the estimates were.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use timeln::progress::MatchProgress;
+//!
+//! let mut progress = MatchProgress::new(4, None);
+//! progress.record(Duration::from_secs(10));
+//! progress.record(Duration::from_secs(20));
+//! // Two matches left, 10 s apart: the run should take 40 s, so 15 s are left at 25 s.
+//! assert_eq!(progress.estimated_total(), Some(Duration::from_secs(40)));
+//! assert_eq!(progress.snapshot(Duration::from_secs(25)).eta, Some(Duration::from_secs(15)));
+//! ```
+use std::time::Duration;
+
+use crate::formatter::TimeFormat;
+
+/// Characters of the bar drawn by `Progress::bar`, between its brackets.
+pub const BAR_WIDTH: usize = 20;
+
+/// Matches towards the total of a run, and the estimates of when it ends.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchProgress {
+    total: u64,
+    /// Weight of each interval in their moving average, if the estimate is smoothed.
+    alpha: Option<f64>,
+    matches: u64,
+    /// Time since the start of the run of the latest match.
+    last_match: Duration,
+    /// Moving average of the intervals between matches, in seconds.
+    smoothed: Option<f64>,
+    /// The estimate of the total time of the run made at the latest match before the total.
+    last_estimate: Option<Duration>,
+}
+
+/// The progress of a run at some time, as shown while it runs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub matches: u64,
+    pub total: u64,
+    /// Time left until the last match, once it can be estimated and until the total is reached.
+    pub eta: Option<Duration>,
+}
+
+impl MatchProgress {
+    /// Progress towards `total` matches, estimated from the moving average of the intervals
+    /// between matches, each weighing `alpha`, if given, or else from their mean.
+    pub fn new(total: u64, alpha: Option<f64>) -> Self {
+        Self {
+            total,
+            alpha,
+            matches: 0,
+            last_match: Duration::ZERO,
+            smoothed: None,
+            last_estimate: None,
+        }
+    }
+
+    /// Counts a match `at` the given time since the start of the run, no earlier than the
+    /// previous match.
+    pub fn record(&mut self, at: Duration) {
+        let interval = at.saturating_sub(self.last_match).as_secs_f64();
+        self.smoothed = Some(match (self.alpha, self.smoothed) {
+            (Some(alpha), Some(smoothed)) => alpha * interval + (1.0 - alpha) * smoothed,
+            _ => interval,
+        });
+        self.matches += 1;
+        self.last_match = at;
+        if self.matches < self.total {
+            self.last_estimate = self.estimated_total();
+        }
+    }
+
+    /// The interval expected between the next matches, once there was a match.
+    pub fn interval(&self) -> Option<Duration> {
+        if self.matches == 0 {
+            return None;
+        }
+        match self.alpha {
+            Some(_) => self.smoothed.map(Duration::from_secs_f64),
+            None => Some(self.last_match / self.matches as u32),
+        }
+    }
+
+    /// The estimated total time of the run, from the start to the last of its matches, once there
+    /// was a match.
+    pub fn estimated_total(&self) -> Option<Duration> {
+        let left = self.total.saturating_sub(self.matches);
+        let interval = self.interval()?.as_secs_f64();
+        Some(self.last_match + Duration::from_secs_f64(interval * left as f64))
+    }
+
+    /// The progress `now`, as a time since the start of the run.
+    pub fn snapshot(&self, now: Duration) -> Progress {
+        Progress {
+            matches: self.matches,
+            total: self.total,
+            eta: self
+                .estimated_total()
+                .filter(|_| self.matches < self.total)
+                .map(|total| total.saturating_sub(now)),
+        }
+    }
+
+    /// The last estimate of the total time of the run, made at its last match before the total,
+    /// if any.
+    pub fn last_estimate(&self) -> Option<Duration> {
+        self.last_estimate
+    }
+}
+
+impl Progress {
+    /// The progress as a bar of `BAR_WIDTH` characters and the matches, as
+    /// `[########------------] 40/100`.
+    pub fn bar(&self) -> String {
+        let done = match self.total {
+            0 => BAR_WIDTH,
+            total => (self.matches.min(total) as usize * BAR_WIDTH) / total as usize,
+        };
+        format!(
+            "[{}{}] {}/{}",
+            "#".repeat(done),
+            "-".repeat(BAR_WIDTH - done),
+            self.matches,
+            self.total
+        )
+    }
+
+    /// The bar and the time left, as `[########------------] 40/100, ETA: 12.00 s`.
+    pub fn label(&self, time_format: &dyn TimeFormat) -> String {
+        match self.eta {
+            Some(eta) => format!("{}, ETA: {}", self.bar(), time_format.format_duration(&eta)),
+            None => self.bar(),
+        }
+    }
+}
+
+/// Formats how far `actual` is from `estimate`, as `+3.20 s` when it took longer than estimated
+/// and `-3.20 s` when it took less.
+pub fn signed_error(
+    estimate: &Duration,
+    actual: &Duration,
+    time_format: &dyn TimeFormat,
+) -> String {
+    if actual >= estimate {
+        format!("+{}", time_format.format_duration(&(*actual - *estimate)))
+    } else {
+        format!("-{}", time_format.format_duration(&(*estimate - *actual)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatter::SecondsFormat;
+
+    /// Records matches at the given seconds since the start.
+    fn run(total: u64, alpha: Option<f64>, secs: &[f64]) -> MatchProgress {
+        let mut progress = MatchProgress::new(total, alpha);
+        for &at in secs {
+            progress.record(Duration::from_secs_f64(at));
+        }
+        progress
+    }
+
+    #[test]
+    fn test_eta_from_mean_interval() {
+        let progress = run(10, None, &[]);
+        assert_eq!(progress.interval(), None);
+        assert_eq!(progress.snapshot(Duration::from_secs(5)).eta, None);
+
+        // Steady pacing of 2 s a match: 4 matches done at 8 s, 6 left.
+        let progress = run(10, None, &[2.0, 4.0, 6.0, 8.0]);
+        assert_eq!(progress.interval(), Some(Duration::from_secs(2)));
+        assert_eq!(progress.estimated_total(), Some(Duration::from_secs(20)));
+        let snapshot = progress.snapshot(Duration::from_secs(9));
+        assert_eq!(snapshot.matches, 4);
+        assert_eq!(snapshot.eta, Some(Duration::from_secs(11)));
+        // Late beyond the estimate, nothing is left rather than a negative time.
+        assert_eq!(
+            progress.snapshot(Duration::from_secs(30)).eta,
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_eta_smoothed_by_ewma() {
+        // The run slows down from 1 s to 5 s a match: the moving average follows it faster than
+        // the mean (2.6 s).
+        let secs = [1.0, 2.0, 3.0, 8.0, 13.0];
+        let mean = run(10, None, &secs);
+        assert_eq!(mean.estimated_total(), Some(Duration::from_secs(26)));
+        let smoothed = run(10, Some(0.5), &secs);
+        // 1, 1, 1, then 0.5 * 5 + 0.5 * 1 = 3, then 0.5 * 5 + 0.5 * 3 = 4.
+        assert_eq!(smoothed.interval(), Some(Duration::from_secs(4)));
+        assert_eq!(smoothed.estimated_total(), Some(Duration::from_secs(33)));
+    }
+
+    #[test]
+    fn test_last_estimate_before_total() {
+        let progress = run(3, None, &[2.0, 4.0, 7.0]);
+        // Made at the second match: 4 s plus one more interval of 2 s.
+        assert_eq!(progress.last_estimate(), Some(Duration::from_secs(6)));
+        assert_eq!(progress.snapshot(Duration::from_secs(7)).eta, None);
+        assert_eq!(
+            signed_error(
+                &Duration::from_secs(6),
+                &Duration::from_secs(7),
+                &SecondsFormat
+            ),
+            "+1.00 s"
+        );
+        assert_eq!(
+            signed_error(
+                &Duration::from_secs(6),
+                &Duration::from_millis(5500),
+                &SecondsFormat
+            ),
+            "-0.50 s"
+        );
+        assert_eq!(run(1, None, &[2.0]).last_estimate(), None);
+    }
+
+    #[test]
+    fn test_bar() {
+        let progress = Progress {
+            matches: 40,
+            total: 100,
+            eta: Some(Duration::from_secs(12)),
+        };
+        assert_eq!(progress.bar(), "[########------------] 40/100");
+        assert_eq!(
+            progress.label(&SecondsFormat),
+            "[########------------] 40/100, ETA: 12.00 s"
+        );
+        let over = Progress {
+            matches: 120,
+            total: 100,
+            eta: None,
+        };
+        assert_eq!(over.label(&SecondsFormat), "[####################] 120/100");
+    }
+}
@@ -1,18 +1,25 @@
 //! This module provides implementations of the `ReadData` trait for reading data from different sources.
 //!
-//! The `ReadData` trait defines a common interface for reading lines of data into a buffer. Two implementations
-//! are provided: `StdinReadData` for reading from standard input, and `TestReadData` for reading from test data.
+//! The `ReadData` trait defines a common interface for reading lines of data into a buffer. Four implementations
+//! are provided: `BufReadData` for reading from any buffered reader, such as standard input with `StdinReadData`,
+//! `IterReadData` for reading from an iterator of lines, such as an in-memory list, `ExecReadData` for reading the merged stdout and stderr of a child
+//! process, and `FileReadData` for reading a file, optionally following it as it grows like `tail -f` or
+//! `tail -F`. `FilesReadData` reads several files one after the other, telling which file each line was read from. On Unix, `SocketReadData` reads the lines written to a Unix domain socket or a named pipe, and `TcpReadData` those of a TCP connection, made or accepted. `ReplayReadData` replays a record file written with `--record`, or timing data written by another tool as CSV. `ThreadedReadData` reads any of them on a helper thread, so that waiting for a line can time out, `JoinReadData` to join multi-line events such as stack traces into one line, `ParsedTimeReadData` to time the lines by the timestamps written in them, and `JsonReadData` and `LogfmtReadData` to time and display the lines of a structured log by its fields. Unit tests additionally use `TestReadData` to read from a cursor with `BufReadData`.
+//! Input is read as raw bytes and invalid UTF-8 is replaced with U+FFFD, so that binary noise in a log
+//! does not abort the run.
+//! Lines longer than `MAX_LINE_BYTES` are cut, so that an input without line endings cannot exhaust the memory.
+//! `read_line_timeout` waits at most a given time for the next line, telling a line, a timeout and the end of the
+//! input apart with `ReadOutcome`. Commands, sockets and `ThreadedReadData` time out, and so do TCP connections; the other readers block as
+//! `read_line` does, and the readers wrapping another one wait as it does.
 //!
 //! # Examples
 //!
 //! Reading from standard input:
 //!
-//! ```
-//! use crate::ReadData;
+//! ```no_run
+//! use timeln::reader::{ReadData, StdinReadData};
 //!
-//! let stdin = std::io::stdin();
-//! let handle = stdin.lock();
-//! let mut reader = StdinReadData { stdin: handle };
+//! let mut reader = StdinReadData::stdin();
 //!
 //! let mut buf = String::new();
 //! let result = reader.read_line(&mut buf);
@@ -23,90 +30,2545 @@
 //! }
 //! ```
 //!
-//! Reading from test data:
+//! Reading from a list of lines:
 //!
 //! ```
-//! use crate::ReadData;
+//! use timeln::reader::{IterReadData, ReadData};
 //!
-//! let input = "Hello, world!\n".to_string();
-//! let cursor = std::io::Cursor::new(input);
-//! let mut reader = TestReadData { data: cursor };
+//! let mut reader = IterReadData::new(["Hello, world!"]);
 //!
 //! let mut buf = String::new();
 //! let result = reader.read_line(&mut buf);
 //!
 //! match result {
-//!     Ok(bytes) => println!("Read {} bytes from test data.", bytes),
-//!     Err(e) => eprintln!("Error reading from test data: {:?}", e),
+//!     Ok(bytes) => println!("Read {} bytes from the list.", bytes),
+//!     Err(e) => eprintln!("Error reading from the list: {:?}", e),
 //! }
 //! ```
 //!
 //! # Testing
 //!
 //! Unit tests are provided for each implementation. They can be run using the command `cargo test`.
-//! The tests verify the functionality of the `read_line` method for both `StdinReadData` and `TestReadData`.
-//!
-//! Note: The `ReadData` trait and its implementations are intended for demonstration purposes and may
-//! require additional error handling and validation for production use.
 //!
-use std::io::BufRead;
+use std::fs::{File, Metadata};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use regex::Regex;
+
+use crate::csv::{CsvColumns, CsvReader};
+use crate::decompress::DecompressReadData;
 use crate::error::TimelnError;
+use crate::journal::parse_entry;
+use crate::jsonlog::JsonFields;
+use crate::logfmt::{self, Pair};
+use crate::record::{Record, RecordReader};
+use crate::timestamp::{parse_timestamp, TimestampCounts, TimestampParser};
 
 /// New trait for reading data
-pub trait ReadData {
+///
+/// Readers are `Send` so that `ThreadedReadData` can wait for them on another thread.
+pub trait ReadData: Send {
     fn read_line(&mut self, buf: &mut String) -> Result<usize, TimelnError>;
+
+    /// Reads the next line like `read_line`, waiting at most `timeout` for it. Readers that cannot
+    /// stop waiting block until the line arrives, as `read_line` does; read them with a
+    /// `ThreadedReadData` to time out.
+    fn read_line_timeout(
+        &mut self,
+        buf: &mut String,
+        _timeout: Duration,
+    ) -> Result<ReadOutcome, TimelnError> {
+        self.read_line(buf).map(ReadOutcome::from_bytes)
+    }
+
+    /// The stream the last line was read from, for readers that merge several streams.
+    fn stream(&self) -> Option<Stream> {
+        None
+    }
+
+    /// When the last line was received, for readers that receive lines before `read_line` is
+    /// called; `None` means the line arrived when `read_line` returned.
+    fn arrived(&self) -> Option<Instant> {
+        None
+    }
+
+    /// Takes a notice about the input that happened before the last line, such as a rotated log,
+    /// to be shown to the user without being timed as a line.
+    fn take_notice(&mut self) -> Option<String> {
+        None
+    }
+
+    /// Number of input lines in the last line read, for readers that join several lines into one.
+    fn lines(&self) -> usize {
+        1
+    }
+
+    /// Index of the file the last line was read from, for readers of several files.
+    fn file(&self) -> Option<usize> {
+        None
+    }
+
+    /// The logfmt pairs of the last line read, for readers that parse them.
+    fn pairs(&self) -> &[Pair] {
+        &[]
+    }
+}
+
+/// What `read_line_timeout` read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadOutcome {
+    /// A line of that many bytes.
+    Line(usize),
+    /// No line arrived within the timeout.
+    TimedOut,
+    /// The input ended.
+    Eof,
+}
+
+impl ReadOutcome {
+    /// The outcome of a `read_line` that read `bytes`, which never times out.
+    pub fn from_bytes(bytes: usize) -> Self {
+        match bytes {
+            0 => ReadOutcome::Eof,
+            bytes => ReadOutcome::Line(bytes),
+        }
+    }
+
+    /// The bytes read, or 0 if no line was.
+    pub fn bytes(self) -> usize {
+        match self {
+            ReadOutcome::Line(bytes) => bytes,
+            ReadOutcome::TimedOut | ReadOutcome::Eof => 0,
+        }
+    }
+}
+
+/// The output stream of a child process a line was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    /// The tag prefixed to the lines of this stream.
+    pub fn tag(self) -> &'static str {
+        match self {
+            Stream::Stdout => "[out]",
+            Stream::Stderr => "[err]",
+        }
+    }
 }
 
-/// Stdin implementation
-pub struct StdinReadData {
-    pub stdin: std::io::StdinLock<'static>,
+/// Longest line kept by the readers, in bytes; the rest of a longer line is read and dropped, so
+/// that an input without line endings, such as a binary file, cannot exhaust the memory.
+pub const MAX_LINE_BYTES: usize = 16 << 20;
+
+/// Reads a line of raw bytes from `reader` and appends it to `buf`, replacing invalid UTF-8
+/// sequences with U+FFFD so that a stray byte does not end the run, and keeping at most
+/// `MAX_LINE_BYTES` of it, as `read_line_within` does.
+/// Returns the number of bytes read, which may differ from the number of bytes appended.
+pub fn read_line_lossy(reader: &mut impl BufRead, buf: &mut String) -> Result<usize, TimelnError> {
+    read_line_within(reader, buf, MAX_LINE_BYTES)
+}
+
+/// Like `read_line_lossy`, keeping at most the first `max_bytes` of the line: the rest is read
+/// and dropped, up to and including its line ending, for which a newline is kept.
+/// Returns the number of bytes read, including those dropped.
+pub fn read_line_within(
+    reader: &mut impl BufRead,
+    buf: &mut String,
+    max_bytes: usize,
+) -> Result<usize, TimelnError> {
+    let mut bytes = Vec::new();
+    let read = read_partial_within(reader, &mut bytes, max_bytes)?;
+    buf.push_str(&String::from_utf8_lossy(&bytes));
+    Ok(read)
+}
+
+/// Appends what `reader` has of the line started in `partial`, up to and including its line
+/// ending, keeping at most `max_bytes` of the line as `read_line_within` does. Stops early at the
+/// end of the data, so that a followed file can finish the line once more is written; the line is
+/// complete once `partial` ends with a newline. Returns the number of bytes read.
+fn read_partial_within(
+    reader: &mut impl BufRead,
+    partial: &mut Vec<u8>,
+    max_bytes: usize,
+) -> std::io::Result<usize> {
+    let mut read = 0;
+    while !partial.ends_with(b"\n") {
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            break;
+        }
+        let end = available.iter().position(|byte| *byte == b'\n');
+        let taken = end.map_or(available.len(), |end| end + 1);
+        let kept = taken.min(max_bytes.saturating_sub(partial.len()));
+        partial.extend_from_slice(&available[..kept]);
+        if end.is_some() && kept < taken {
+            partial.push(b'\n');
+        }
+        reader.consume(taken);
+        read += taken;
+    }
+    Ok(read)
+}
+
+/// Reads the lines of any buffered reader: standard input, a pipe, a decompressed stream or an
+/// in-memory cursor.
+///
+/// Every source read through it shares the same handling of the lines: invalid UTF-8 is replaced,
+/// lines longer than `MAX_LINE_BYTES` (or `max_line_bytes`) are cut, and line endings are kept as
+/// read, for the pipeline to strip, so that byte counts and the `--tee` copy match the input.
+/// Adding a source only takes a `BufRead` implementation.
+pub struct BufReadData<R> {
+    reader: R,
+    /// What the lines are read from, named in the errors.
+    name: String,
+    max_line_bytes: usize,
+}
+
+impl<R: BufRead + Send> BufReadData<R> {
+    /// Creates a reader of the lines of `reader`, named `name` in the errors.
+    pub fn new(reader: R, name: impl Into<String>) -> Self {
+        Self {
+            reader,
+            name: name.into(),
+            max_line_bytes: MAX_LINE_BYTES,
+        }
+    }
+
+    /// Keeps at most the first `bytes` of each line instead of `MAX_LINE_BYTES`.
+    pub fn max_line_bytes(mut self, bytes: usize) -> Self {
+        self.max_line_bytes = bytes;
+        self
+    }
+
+    /// The reader the lines are read from.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
 }
 
-impl ReadData for StdinReadData {
-    /// Reads a line from standard input into the provided buffer.
+impl<R: BufRead + Send> ReadData for BufReadData<R> {
+    /// Reads a line into the provided buffer, replacing invalid UTF-8 and cutting it if too long.
     /// Returns the number of bytes read or an error if encountered.
     fn read_line(&mut self, buf: &mut String) -> Result<usize, TimelnError> {
-        match self.stdin.read_line(buf) {
-            Ok(bytes) => Ok(bytes),
-            Err(e) => Err(TimelnError::Io(e)),
+        read_line_within(&mut self.reader, buf, self.max_line_bytes).map_err(|err| match err {
+            TimelnError::Io(source) => TimelnError::input(&self.name, source),
+            err => err,
+        })
+    }
+}
+
+/// Reads the lines of standard input.
+pub type StdinReadData = BufReadData<BufReader<std::io::Stdin>>;
+
+impl StdinReadData {
+    /// Creates a reader of the lines of standard input.
+    pub fn stdin() -> Self {
+        BufReadData::new(BufReader::new(std::io::stdin()), "stdin")
+    }
+}
+
+/// Reads several files one after the other, decompressing them if needed, and tells which file
+/// each line was read from.
+pub struct FilesReadData {
+    files: Vec<DecompressReadData>,
+    /// Index of the file being read.
+    current: usize,
+}
+
+impl FilesReadData {
+    /// Opens all the files at `paths`, so that a missing one fails before any line is read.
+    pub fn open<P: AsRef<Path>>(paths: &[P]) -> Result<Self, TimelnError> {
+        let files = paths
+            .iter()
+            .map(DecompressReadData::open)
+            .collect::<Result<_, _>>()?;
+        Ok(Self { files, current: 0 })
+    }
+}
+
+impl ReadData for FilesReadData {
+    /// Reads the next line of the file being read into the provided buffer, moving on to the next
+    /// file at the end of each. Returns 0 at the end of the last file.
+    fn read_line(&mut self, buf: &mut String) -> Result<usize, TimelnError> {
+        while let Some(file) = self.files.get_mut(self.current) {
+            let read = file.read_line(buf)?;
+            if read > 0 {
+                return Ok(read);
+            }
+            self.current += 1;
+        }
+        Ok(0)
+    }
+
+    fn file(&self) -> Option<usize> {
+        Some(self.current)
+    }
+}
+
+/// Reads lines from an iterator, for example an in-memory list of lines.
+/// A newline is appended to each line that does not already end with one.
+pub struct IterReadData<I> {
+    lines: I,
+}
+
+impl<I: Iterator> IterReadData<I> {
+    /// Creates a reader returning the items of `lines` one line at a time.
+    pub fn new(lines: impl IntoIterator<IntoIter = I>) -> Self {
+        Self {
+            lines: lines.into_iter(),
+        }
+    }
+}
+
+impl<I> ReadData for IterReadData<I>
+where
+    I: Iterator + Send,
+    I::Item: AsRef<str>,
+{
+    /// Appends the next line to the provided buffer.
+    /// Returns the number of bytes appended, or 0 once the iterator is exhausted.
+    fn read_line(&mut self, buf: &mut String) -> Result<usize, TimelnError> {
+        let Some(line) = self.lines.next() else {
+            return Ok(0);
+        };
+        let line = line.as_ref();
+        let start = buf.len();
+        buf.push_str(line);
+        if !line.ends_with('\n') {
+            buf.push('\n');
         }
+        Ok(buf.len() - start)
     }
 }
 
-// Test data implementation
-pub struct TestReadData {
-    pub data: std::io::Cursor<String>,
+/// Runs a command and reads the lines of its stdout and stderr as they are written.
+///
+/// Each stream is read by its own thread, which timestamps every line as it arrives and sends it
+/// over a shared channel, so that lines of the same stream keep their order.
+pub struct ExecReadData {
+    /// The program run, named in the errors.
+    program: String,
+    child: Child,
+    lines: Receiver<ChildLine>,
+    stream: Option<Stream>,
+    arrived: Option<Instant>,
 }
 
-impl ReadData for TestReadData {
-    /// Reads a line from test data into the provided buffer.
-    /// Returns the number of bytes read or an error if encountered.
+impl ExecReadData {
+    /// Spawns `command`, whose first element is the program and the rest its arguments.
+    pub fn spawn(command: &[String]) -> Result<Self, TimelnError> {
+        let (program, args) = command
+            .split_first()
+            .ok_or_else(|| TimelnError::Usage("no command to run".to_string()))?;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|source| TimelnError::Command {
+                command: program.clone(),
+                source,
+            })?;
+
+        let (sender, lines) = mpsc::channel();
+        if let Some(stdout) = child.stdout.take() {
+            forward_lines(stdout, Stream::Stdout, sender.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            forward_lines(stderr, Stream::Stderr, sender);
+        }
+        Ok(Self {
+            program: program.clone(),
+            child,
+            lines,
+            stream: None,
+            arrived: None,
+        })
+    }
+}
+
+/// A line written by a child process, as forwarded by `forward_lines`.
+struct ChildLine {
+    stream: Stream,
+    text: String,
+    /// Number of bytes read for the line, which the replaced and cut bytes of `text` change.
+    bytes: usize,
+    arrived: Instant,
+}
+
+/// Sends each line of `pipe` with its stream and arrival time until the pipe is closed.
+fn forward_lines(pipe: impl Read + Send + 'static, stream: Stream, sender: Sender<ChildLine>) {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(pipe);
+        loop {
+            let mut text = String::new();
+            match read_line_lossy(&mut reader, &mut text) {
+                Ok(0) | Err(_) => break,
+                Ok(bytes) => {
+                    let line = ChildLine {
+                        stream,
+                        text,
+                        bytes,
+                        arrived: Instant::now(),
+                    };
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+impl ReadData for ExecReadData {
+    /// Reads the next line written by the child to either stream.
+    /// Returns 0 once both streams are closed, after waiting for the child to exit.
     fn read_line(&mut self, buf: &mut String) -> Result<usize, TimelnError> {
-        match self.data.read_line(buf) {
-            Ok(bytes) => Ok(bytes),
-            Err(e) => Err(TimelnError::Io(e)),
+        self.read_line_timeout(buf, Duration::MAX)
+            .map(ReadOutcome::bytes)
+    }
+
+    fn read_line_timeout(
+        &mut self,
+        buf: &mut String,
+        timeout: Duration,
+    ) -> Result<ReadOutcome, TimelnError> {
+        match self.lines.recv_timeout(timeout) {
+            Ok(line) => {
+                self.stream = Some(line.stream);
+                self.arrived = Some(line.arrived);
+                buf.push_str(&line.text);
+                if !line.text.ends_with('\n') {
+                    buf.push('\n');
+                }
+                Ok(ReadOutcome::Line(line.bytes))
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => Ok(ReadOutcome::TimedOut),
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                self.child.wait().map_err(|source| TimelnError::Command {
+                    command: self.program.clone(),
+                    source,
+                })?;
+                Ok(ReadOutcome::Eof)
+            }
         }
     }
+
+    fn stream(&self) -> Option<Stream> {
+        self.stream
+    }
+
+    fn arrived(&self) -> Option<Instant> {
+        self.arrived
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// How a followed file is tracked when it is rotated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum FollowMode {
+    /// Keeps reading the opened file, like `tail -f`, even if it is renamed.
+    Descriptor,
+    /// Reopens the path when the file is replaced, like `tail -F`.
+    Name,
+}
 
-    #[test]
-    fn test_test_read_line() {
-        // Arrange
-        let input = "Hello, world!\n".to_string();
-        let cursor = std::io::Cursor::new(input);
-        let mut reader = TestReadData { data: cursor };
+impl std::str::FromStr for FollowMode {
+    type Err = String;
 
-        // Act
-        let mut buf = String::new();
-        let result = reader.read_line(&mut buf);
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "descriptor" => Ok(FollowMode::Descriptor),
+            "name" => Ok(FollowMode::Name),
+            _ => Err(format!(
+                "invalid follow mode '{}', expected 'descriptor' or 'name'",
+                s
+            )),
+        }
+    }
+}
 
-        // Assert
-        assert_eq!(result.unwrap(), 14);
-        assert_eq!(buf, "Hello, world!\n");
+/// Default time between two checks of a followed file for new data.
+pub const DEFAULT_FOLLOW_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reads the lines of a file from its start, optionally waiting for more lines at its end.
+///
+/// When following, the file is polled every `interval`. A truncated file is read again from its
+/// start and, in `FollowMode::Name`, a file replaced by a new one at the same path (a new inode
+/// on Unix) is reopened; both are reported with a notice.
+pub struct FileReadData {
+    path: PathBuf,
+    reader: BufReader<File>,
+    follow: Option<FollowMode>,
+    interval: Duration,
+    position: u64,
+    /// The start of the line being written, cut at `max_line_bytes`.
+    partial: Vec<u8>,
+    /// Bytes read of the line being written, including those cut.
+    partial_bytes: usize,
+    max_line_bytes: usize,
+    notice: Option<String>,
+}
+
+impl FileReadData {
+    /// Opens `path`, following it in `follow` mode if given.
+    pub fn open(
+        path: impl Into<PathBuf>,
+        follow: Option<FollowMode>,
+        interval: Duration,
+    ) -> Result<Self, TimelnError> {
+        let path = path.into();
+        let file = File::open(&path).map_err(|err| TimelnError::input(&path, err))?;
+        let reader = BufReader::new(file);
+        Ok(Self {
+            path,
+            reader,
+            follow,
+            interval,
+            position: 0,
+            partial: Vec::new(),
+            partial_bytes: 0,
+            max_line_bytes: MAX_LINE_BYTES,
+            notice: None,
+        })
+    }
+
+    /// Keeps at most the first `bytes` of each line instead of `MAX_LINE_BYTES`.
+    pub fn max_line_bytes(mut self, bytes: usize) -> Self {
+        self.max_line_bytes = bytes;
+        self
+    }
+
+    /// Checks whether the followed file was truncated or replaced, and if so starts reading the
+    /// new contents from their start.
+    fn check_rotation(&mut self) -> std::io::Result<()> {
+        let current = self.reader.get_ref().metadata()?;
+        if self.follow == Some(FollowMode::Name) {
+            // The path may briefly not exist while the log is being rotated.
+            if let Ok(latest) = std::fs::metadata(&self.path) {
+                if !same_file(&current, &latest) {
+                    self.reader = BufReader::new(File::open(&self.path)?);
+                    self.restart("[log rotated]");
+                    return Ok(());
+                }
+            }
+        }
+        if current.len() < self.position {
+            self.reader.seek(SeekFrom::Start(0))?;
+            self.restart("[log truncated]");
+        }
+        Ok(())
+    }
+
+    fn restart(&mut self, notice: &str) {
+        self.position = 0;
+        self.partial.clear();
+        self.partial_bytes = 0;
+        self.notice = Some(notice.to_string());
+    }
+}
+
+#[cfg(unix)]
+fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    (a.dev(), a.ino()) == (b.dev(), b.ino())
+}
+
+#[cfg(not(unix))]
+fn same_file(_a: &Metadata, _b: &Metadata) -> bool {
+    // Without inodes a replaced file can only be noticed once it is shorter than the old one.
+    true
+}
+
+impl ReadData for FileReadData {
+    /// Reads the next complete line of the file into the provided buffer, replacing invalid
+    /// UTF-8 and cutting it if too long. When following, waits for the line to be written instead
+    /// of returning 0 at the end. Returns the number of bytes read, including those cut.
+    fn read_line(&mut self, buf: &mut String) -> Result<usize, TimelnError> {
+        loop {
+            let read =
+                read_partial_within(&mut self.reader, &mut self.partial, self.max_line_bytes)
+                    .map_err(|err| TimelnError::input(&self.path, err))?;
+            self.position += read as u64;
+            self.partial_bytes += read;
+            let complete = self.partial.ends_with(b"\n");
+            if complete || (read == 0 && self.follow.is_none()) {
+                buf.push_str(&String::from_utf8_lossy(&self.partial));
+                self.partial.clear();
+                return Ok(std::mem::take(&mut self.partial_bytes));
+            }
+            if read == 0 {
+                self.check_rotation()
+                    .map_err(|err| TimelnError::input(&self.path, err))?;
+                if self.notice.is_none() {
+                    thread::sleep(self.interval);
+                }
+            }
+        }
+    }
+
+    fn take_notice(&mut self) -> Option<String> {
+        self.notice.take()
+    }
+}
+
+/// Where a `SocketReadData` waits for a writer.
+#[cfg(unix)]
+enum ListenSource {
+    /// A Unix domain socket created by timeln, accepting one connection at a time.
+    Socket(std::os::unix::net::UnixListener),
+    /// An existing named pipe, opened again for each writer.
+    Fifo,
+}
+
+/// Reads the lines written to a Unix domain socket or a named pipe, for producers that cannot be
+/// piped into timeln, such as daemons.
+///
+/// A socket is created at the path, unless a named pipe already exists there. The lines are read
+/// from the first connection, or the first writer of the pipe, until it closes; when `keep` is
+/// set, the next connection is then waited for and read as part of the same run, with a notice.
+/// The created socket file is left for the caller to remove, see `socket_path`.
+///
+/// With a timeout, the start of each line and the next connection to the socket are waited for
+/// with `poll`; a line that started is read to its end, and the writer of a named pipe is waited
+/// for without a timeout.
+#[cfg(unix)]
+pub struct SocketReadData {
+    path: PathBuf,
+    source: ListenSource,
+    keep: bool,
+    /// The current connection, and its file descriptor.
+    reader: Option<(BufReader<Box<dyn Read + Send>>, RawFd)>,
+    connections: usize,
+    notice: Option<String>,
+}
+
+#[cfg(unix)]
+impl SocketReadData {
+    /// Listens on `path`, reading every connection in turn if `keep` is set.
+    pub fn listen(path: impl Into<PathBuf>, keep: bool) -> Result<Self, TimelnError> {
+        use std::os::unix::fs::FileTypeExt;
+        use std::os::unix::net::{UnixListener, UnixStream};
+
+        let path = path.into();
+        let source = match std::fs::metadata(&path).map(|meta| meta.file_type()) {
+            Ok(file_type) if file_type.is_fifo() => ListenSource::Fifo,
+            Ok(file_type) if file_type.is_socket() => {
+                // A socket left by a run that was killed is replaced, but not one still in use.
+                if UnixStream::connect(&path).is_err() {
+                    std::fs::remove_file(&path).map_err(|err| TimelnError::input(&path, err))?;
+                }
+                ListenSource::Socket(
+                    UnixListener::bind(&path).map_err(|err| TimelnError::input(&path, err))?,
+                )
+            }
+            Ok(_) => {
+                let err = format!("{} is neither a socket nor a named pipe", path.display());
+                return Err(TimelnError::Usage(err));
+            }
+            Err(_) => ListenSource::Socket(
+                UnixListener::bind(&path).map_err(|err| TimelnError::input(&path, err))?,
+            ),
+        };
+        Ok(Self {
+            path,
+            source,
+            keep,
+            reader: None,
+            connections: 0,
+            notice: None,
+        })
+    }
+
+    /// The socket file created by `listen`, to be removed at the end of the run, or `None` when
+    /// reading an existing named pipe.
+    pub fn socket_path(&self) -> Option<&Path> {
+        match self.source {
+            ListenSource::Socket(_) => Some(&self.path),
+            ListenSource::Fifo => None,
+        }
+    }
+
+    /// Waits for the next connection or writer, returning it with its file descriptor.
+    fn connect(&mut self) -> std::io::Result<(BufReader<Box<dyn Read + Send>>, RawFd)> {
+        let (input, fd): (Box<dyn Read + Send>, _) = match &self.source {
+            ListenSource::Socket(listener) => {
+                let stream = listener.accept()?.0;
+                let fd = stream.as_raw_fd();
+                (Box::new(stream), fd)
+            }
+            // Opening a named pipe for reading blocks until it has a writer.
+            ListenSource::Fifo => {
+                let file = File::open(&self.path)?;
+                let fd = file.as_raw_fd();
+                (Box::new(file), fd)
+            }
+        };
+        self.connections += 1;
+        if self.connections > 1 {
+            self.notice = Some(format!("[connection {}]", self.connections));
+        }
+        Ok((BufReader::new(input), fd))
+    }
+}
+
+/// Waits until `fd` can be read without blocking, or has a connection to accept, or until
+/// `deadline` if any. Returns whether it can.
+#[cfg(unix)]
+fn wait_readable(fd: RawFd, deadline: Option<Instant>) -> std::io::Result<bool> {
+    loop {
+        let millis = match deadline {
+            Some(deadline) => deadline
+                .saturating_duration_since(Instant::now())
+                .as_millis()
+                .min(libc::c_int::MAX as u128) as libc::c_int,
+            None => -1,
+        };
+        let mut pollfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        match unsafe { libc::poll(&mut pollfd, 1, millis) } {
+            0 => return Ok(false),
+            -1 => {
+                // A signal, such as the one pausing the timing, cuts the wait short.
+                let err = std::io::Error::last_os_error();
+                if err.kind() != std::io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            _ => return Ok(true),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl ReadData for SocketReadData {
+    /// Reads the next line of the current connection into the provided buffer, replacing invalid
+    /// UTF-8, waiting for a connection first if there is none. Returns 0 once the connection
+    /// closes, unless the next one is to be read.
+    fn read_line(&mut self, buf: &mut String) -> Result<usize, TimelnError> {
+        self.read_line_timeout(buf, Duration::MAX)
+            .map(ReadOutcome::bytes)
+    }
+
+    fn read_line_timeout(
+        &mut self,
+        buf: &mut String,
+        timeout: Duration,
+    ) -> Result<ReadOutcome, TimelnError> {
+        let deadline = Instant::now().checked_add(timeout);
+        loop {
+            match &mut self.reader {
+                Some((reader, fd)) => {
+                    if reader.buffer().is_empty()
+                        && !wait_readable(*fd, deadline)
+                            .map_err(|err| TimelnError::input(&self.path, err))?
+                    {
+                        return Ok(ReadOutcome::TimedOut);
+                    }
+                    let read = read_line_lossy(reader, buf).map_err(|err| match err {
+                        TimelnError::Io(source) => TimelnError::input(&self.path, source),
+                        err => err,
+                    })?;
+                    if read > 0 {
+                        return Ok(ReadOutcome::Line(read));
+                    }
+                    self.reader = None;
+                }
+                None if self.connections > 0 && !self.keep => return Ok(ReadOutcome::Eof),
+                None => {
+                    if let ListenSource::Socket(listener) = &self.source {
+                        if !wait_readable(listener.as_raw_fd(), deadline)
+                            .map_err(|err| TimelnError::input(&self.path, err))?
+                        {
+                            return Ok(ReadOutcome::TimedOut);
+                        }
+                    }
+                    let reader = self
+                        .connect()
+                        .map_err(|err| TimelnError::input(&self.path, err))?;
+                    self.reader = Some(reader);
+                }
+            }
+        }
+    }
+
+    fn take_notice(&mut self) -> Option<String> {
+        self.notice.take()
+    }
+}
+
+/// Default longest wait for a TCP connection to be made with `--connect`.
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time between two attempts to connect again after a TCP connection dropped.
+pub const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Where a `TcpReadData` gets its connections.
+enum TcpSource {
+    /// Connects to the address, waiting at most the timeout for each attempt.
+    Connect(String, Duration),
+    /// Accepts the connections made to the listener.
+    Listen(TcpListener),
+}
+
+/// Reads the lines of a TCP connection, made to a server like `nc host port` does, or accepted
+/// from a client like `nc -l port` does.
+///
+/// The connection is read until it closes or drops, which ends the input unless `reconnect` is
+/// set: the address is then connected to again, every `RECONNECT_INTERVAL` until it succeeds, or
+/// the next client accepted, and read as part of the same run, with a notice.
+///
+/// With a timeout, the start of each line is waited for with it, and so are the next client, on
+/// Unix, and a connection made again; a line that started is read to its end.
+pub struct TcpReadData {
+    /// The address connected to or listened on, named in the errors.
+    name: String,
+    source: TcpSource,
+    reconnect: bool,
+    /// The current connection, and a handle on its stream to wait for its lines.
+    reader: Option<(BufReadData<BufReader<TcpStream>>, TcpStream)>,
+    connections: usize,
+    notice: Option<String>,
+}
+
+impl TcpReadData {
+    /// Connects to `addr`, such as `localhost:9000`, waiting at most `timeout`.
+    pub fn connect(addr: &str, timeout: Duration, reconnect: bool) -> Result<Self, TimelnError> {
+        let mut reader = Self::new(
+            addr,
+            TcpSource::Connect(addr.to_string(), timeout),
+            reconnect,
+        );
+        let connection = reader.open().map_err(|err| TimelnError::input(addr, err))?;
+        reader.reader = Some(connection);
+        Ok(reader)
+    }
+
+    /// Listens on `addr`, such as `0.0.0.0:9000`, for a client to connect.
+    pub fn listen(addr: &str, reconnect: bool) -> Result<Self, TimelnError> {
+        let listener = TcpListener::bind(addr).map_err(|err| TimelnError::input(addr, err))?;
+        Ok(Self::new(addr, TcpSource::Listen(listener), reconnect))
+    }
+
+    fn new(name: &str, source: TcpSource, reconnect: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            source,
+            reconnect,
+            reader: None,
+            connections: 0,
+            notice: None,
+        }
+    }
+
+    /// The address listened on, such as the port picked for port 0, or `None` when connecting.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match &self.source {
+            TcpSource::Listen(listener) => listener.local_addr().ok(),
+            TcpSource::Connect(..) => None,
+        }
+    }
+
+    /// Makes or accepts the next connection, returning it with a handle on its stream.
+    fn open(&mut self) -> std::io::Result<(BufReadData<BufReader<TcpStream>>, TcpStream)> {
+        let stream = match &self.source {
+            TcpSource::Connect(addr, timeout) => connect_any(addr, *timeout)?,
+            TcpSource::Listen(listener) => listener.accept()?.0,
+        };
+        let handle = stream.try_clone()?;
+        self.connections += 1;
+        if self.connections > 1 {
+            self.notice = Some(format!("[connection {}]", self.connections));
+        }
+        let reader = BufReadData::new(BufReader::new(stream), self.name.clone());
+        Ok((reader, handle))
+    }
+}
+
+/// Connects to the first address `addr` resolves to that accepts, waiting at most `timeout` for
+/// each.
+fn connect_any(addr: &str, timeout: Duration) -> std::io::Result<TcpStream> {
+    let mut last = None;
+    for addr in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last = Some(err),
+        }
+    }
+    Err(last.unwrap_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "no address to connect to")
+    }))
+}
+
+/// Whether `err` means that the other end of a connection went away.
+fn dropped(err: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+    matches!(
+        err.kind(),
+        ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::BrokenPipe
+    )
+}
+
+/// Waits until `stream` has data to read, or is closed, or until `deadline` if any. Returns
+/// whether it has.
+fn wait_stream(stream: &TcpStream, deadline: Option<Instant>) -> std::io::Result<bool> {
+    let Some(deadline) = deadline else {
+        return Ok(true);
+    };
+    loop {
+        // A read timeout of zero is an error, so the last wait is of a millisecond.
+        let left = deadline.saturating_duration_since(Instant::now());
+        stream.set_read_timeout(Some(left.max(Duration::from_millis(1))))?;
+        let peeked = stream.peek(&mut [0]);
+        stream.set_read_timeout(None)?;
+        match peeked {
+            Ok(_) => return Ok(true),
+            Err(err) if dropped(&err) => return Ok(true),
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Ok(false)
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+impl ReadData for TcpReadData {
+    /// Reads the next line of the current connection into the provided buffer, replacing invalid
+    /// UTF-8, making or accepting a connection first if there is none. Returns 0 once the
+    /// connection closes or drops, unless it is to be made again.
+    fn read_line(&mut self, buf: &mut String) -> Result<usize, TimelnError> {
+        self.read_line_timeout(buf, Duration::MAX)
+            .map(ReadOutcome::bytes)
+    }
+
+    fn read_line_timeout(
+        &mut self,
+        buf: &mut String,
+        timeout: Duration,
+    ) -> Result<ReadOutcome, TimelnError> {
+        let deadline = Instant::now().checked_add(timeout);
+        loop {
+            match &mut self.reader {
+                Some((reader, stream)) => {
+                    if reader.get_ref().buffer().is_empty()
+                        && !wait_stream(stream, deadline)
+                            .map_err(|err| TimelnError::input(&self.name, err))?
+                    {
+                        return Ok(ReadOutcome::TimedOut);
+                    }
+                    match reader.read_line(buf) {
+                        Ok(0) => {}
+                        Ok(read) => return Ok(ReadOutcome::Line(read)),
+                        Err(TimelnError::InputIo { source, .. }) if dropped(&source) => {}
+                        Err(err) => return Err(err),
+                    }
+                    self.reader = None;
+                }
+                None if self.connections > 0 && !self.reconnect => return Ok(ReadOutcome::Eof),
+                None => {
+                    #[cfg(unix)]
+                    if let TcpSource::Listen(listener) = &self.source {
+                        if !wait_readable(listener.as_raw_fd(), deadline)
+                            .map_err(|err| TimelnError::input(&self.name, err))?
+                        {
+                            return Ok(ReadOutcome::TimedOut);
+                        }
+                    }
+                    match self.open() {
+                        Ok(connection) => self.reader = Some(connection),
+                        // The server may not be back yet.
+                        Err(_) if matches!(self.source, TcpSource::Connect(..)) => {
+                            let left = deadline
+                                .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+                            if left.is_some_and(|left| left.is_zero()) {
+                                return Ok(ReadOutcome::TimedOut);
+                            }
+                            thread::sleep(
+                                left.map_or(RECONNECT_INTERVAL, |left| {
+                                    left.min(RECONNECT_INTERVAL)
+                                }),
+                            );
+                        }
+                        Err(err) => return Err(TimelnError::input(&self.name, err)),
+                    }
+                }
+            }
+        }
+    }
+
+    fn take_notice(&mut self) -> Option<String> {
+        self.notice.take()
+    }
+}
+
+/// A line read by the thread of a `ThreadedReadData`, with what its reader knew about it.
+struct ThreadedLine {
+    line: String,
+    bytes: usize,
+    stream: Option<Stream>,
+    arrived: Instant,
+    notice: Option<String>,
+    file: Option<usize>,
+}
+
+/// Reads another reader on its own thread, so that waiting for its next line can time out.
+///
+/// A blocking read cannot be interrupted, so when the input times out that thread is left
+/// waiting, and ends with the process.
+pub struct ThreadedReadData {
+    lines: Receiver<Result<ThreadedLine, TimelnError>>,
+    stream: Option<Stream>,
+    arrived: Option<Instant>,
+    notice: Option<String>,
+    file: Option<usize>,
+}
+
+impl ThreadedReadData {
+    /// Starts reading `reader` on a new thread.
+    pub fn spawn(mut reader: Box<dyn ReadData>) -> Self {
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || loop {
+            let mut line = String::new();
+            let read = match reader.read_line(&mut line) {
+                Ok(0) => break,
+                read => read.map(|bytes| ThreadedLine {
+                    line,
+                    bytes,
+                    stream: reader.stream(),
+                    arrived: reader.arrived().unwrap_or_else(Instant::now),
+                    notice: reader.take_notice(),
+                    file: reader.file(),
+                }),
+            };
+            let failed = read.is_err();
+            if sender.send(read).is_err() || failed {
+                break;
+            }
+        });
+        Self {
+            lines,
+            stream: None,
+            arrived: None,
+            notice: None,
+            file: None,
+        }
+    }
+}
+
+impl ReadData for ThreadedReadData {
+    /// Reads the next line of the wrapped reader into the provided buffer.
+    /// Returns 0 at the end of its input.
+    fn read_line(&mut self, buf: &mut String) -> Result<usize, TimelnError> {
+        self.read_line_timeout(buf, Duration::MAX)
+            .map(ReadOutcome::bytes)
+    }
+
+    fn read_line_timeout(
+        &mut self,
+        buf: &mut String,
+        timeout: Duration,
+    ) -> Result<ReadOutcome, TimelnError> {
+        match self.lines.recv_timeout(timeout) {
+            Ok(Ok(read)) => {
+                buf.push_str(&read.line);
+                self.stream = read.stream;
+                self.arrived = Some(read.arrived);
+                self.notice = read.notice;
+                self.file = read.file;
+                Ok(ReadOutcome::Line(read.bytes))
+            }
+            Ok(Err(err)) => Err(err),
+            Err(mpsc::RecvTimeoutError::Timeout) => Ok(ReadOutcome::TimedOut),
+            Err(mpsc::RecvTimeoutError::Disconnected) => Ok(ReadOutcome::Eof),
+        }
+    }
+
+    fn stream(&self) -> Option<Stream> {
+        self.stream
+    }
+
+    fn arrived(&self) -> Option<Instant> {
+        self.arrived
+    }
+
+    fn take_notice(&mut self) -> Option<String> {
+        self.notice.take()
+    }
+
+    fn file(&self) -> Option<usize> {
+        self.file
+    }
+}
+
+/// Where a `JoinReadData` ends each joined line.
+#[derive(Debug, Clone)]
+pub enum JoinBoundary {
+    /// After the first line matching the regex, which is joined too.
+    Until(Regex),
+    /// At a blank line, which is joined too. Blank lines before the first line with text are
+    /// joined to it rather than ending an empty line.
+    Paragraph,
+}
+
+impl JoinBoundary {
+    /// Whether `line`, after the lines before it in `joined`, ends the joined line.
+    fn ends(&self, line: &str, joined: &str) -> bool {
+        match self {
+            JoinBoundary::Until(regex) => regex.is_match(line.trim_end_matches(['\n', '\r'])),
+            JoinBoundary::Paragraph => line.trim().is_empty() && !joined.trim().is_empty(),
+        }
+    }
+}
+
+/// Joins consecutive lines of another reader into one line, up to a boundary, so that a
+/// multi-line event such as a stack trace is timed once.
+///
+/// The joined line keeps the line endings of its lines and arrives with its first line; the end
+/// of the input ends the last joined line.
+pub struct JoinReadData {
+    reader: Box<dyn ReadData>,
+    boundary: JoinBoundary,
+    lines: usize,
+    stream: Option<Stream>,
+    arrived: Option<Instant>,
+    notice: Option<String>,
+    file: Option<usize>,
+}
+
+impl JoinReadData {
+    /// Joins the lines of `reader` up to each `boundary`.
+    pub fn new(reader: Box<dyn ReadData>, boundary: JoinBoundary) -> Self {
+        Self {
+            reader,
+            boundary,
+            lines: 0,
+            stream: None,
+            arrived: None,
+            notice: None,
+            file: None,
+        }
+    }
+}
+
+impl ReadData for JoinReadData {
+    /// Appends the lines of the wrapped reader up to the next boundary to the provided buffer.
+    /// Returns the number of bytes read, or 0 at the end of its input.
+    fn read_line(&mut self, buf: &mut String) -> Result<usize, TimelnError> {
+        self.read_line_timeout(buf, Duration::MAX)
+            .map(ReadOutcome::bytes)
+    }
+
+    fn read_line_timeout(
+        &mut self,
+        buf: &mut String,
+        timeout: Duration,
+    ) -> Result<ReadOutcome, TimelnError> {
+        let start = buf.len();
+        let mut bytes = 0;
+        self.lines = 0;
+        let mut line = String::new();
+        // Only the first line of a joined line is waited for with the timeout; the rest of it is
+        // waited for to its end.
+        let mut outcome = self.reader.read_line_timeout(&mut line, timeout)?;
+        loop {
+            let read = match outcome {
+                ReadOutcome::Line(read) => read,
+                ReadOutcome::TimedOut if self.lines == 0 => return Ok(ReadOutcome::TimedOut),
+                ReadOutcome::TimedOut | ReadOutcome::Eof => break,
+            };
+            if self.lines == 0 {
+                self.stream = self.reader.stream();
+                self.arrived = Some(self.reader.arrived().unwrap_or_else(Instant::now));
+                self.file = self.reader.file();
+            }
+            if let Some(notice) = self.reader.take_notice() {
+                self.notice.get_or_insert(notice);
+            }
+            bytes += read;
+            self.lines += 1;
+            let ends = self.boundary.ends(&line, &buf[start..]);
+            buf.push_str(&line);
+            if ends {
+                break;
+            }
+            line.clear();
+            outcome = ReadOutcome::from_bytes(self.reader.read_line(&mut line)?);
+        }
+        Ok(ReadOutcome::from_bytes(bytes))
+    }
+
+    fn stream(&self) -> Option<Stream> {
+        self.stream
+    }
+
+    fn arrived(&self) -> Option<Instant> {
+        self.arrived
+    }
+
+    fn take_notice(&mut self) -> Option<String> {
+        self.notice.take()
+    }
+
+    fn lines(&self) -> usize {
+        self.lines
+    }
+
+    fn file(&self) -> Option<usize> {
+        self.file
+    }
+}
+
+/// Times the lines of another reader by the timestamps written in them rather than by when they
+/// were read, each arriving at the time since the first timestamp after `start`.
+///
+/// A line without a timestamp arrives with the line before it, and a line whose timestamp is
+/// earlier than that of a line before it with that line, so that its delta is zero; both are
+/// counted in the `TimestampCounts`.
+pub struct ParsedTimeReadData {
+    reader: Box<dyn ReadData>,
+    parser: TimestampParser,
+    counts: Arc<TimestampCounts>,
+    start: Instant,
+    /// The first timestamp, in nanoseconds since the Unix epoch.
+    first: Option<i64>,
+    /// Time of the latest line since the first timestamp.
+    latest: Duration,
+}
+
+impl ParsedTimeReadData {
+    /// Times the lines of `reader` by the timestamps `parser` finds in them.
+    pub fn new(
+        reader: Box<dyn ReadData>,
+        parser: TimestampParser,
+        counts: Arc<TimestampCounts>,
+        start: Instant,
+    ) -> Self {
+        Self {
+            reader,
+            parser,
+            counts,
+            start,
+            first: None,
+            latest: Duration::ZERO,
+        }
+    }
+}
+
+impl ReadData for ParsedTimeReadData {
+    /// Reads the next line of the wrapped reader into the provided buffer, and its timestamp.
+    /// Returns 0 at the end of its input.
+    fn read_line(&mut self, buf: &mut String) -> Result<usize, TimelnError> {
+        self.read_line_timeout(buf, Duration::MAX)
+            .map(ReadOutcome::bytes)
+    }
+
+    fn read_line_timeout(
+        &mut self,
+        buf: &mut String,
+        timeout: Duration,
+    ) -> Result<ReadOutcome, TimelnError> {
+        let start = buf.len();
+        let read = match self.reader.read_line_timeout(buf, timeout)? {
+            ReadOutcome::Line(read) => read,
+            outcome => return Ok(outcome),
+        };
+        match self.parser.parse(&buf[start..]) {
+            Some(timestamp) => {
+                let first = *self.first.get_or_insert(timestamp);
+                match u64::try_from(timestamp - first).map(Duration::from_nanos) {
+                    Ok(since_first) if since_first >= self.latest => self.latest = since_first,
+                    _ => self.counts.out_of_order(),
+                }
+            }
+            None => self.counts.untimed(),
+        }
+        Ok(ReadOutcome::Line(read))
+    }
+
+    fn stream(&self) -> Option<Stream> {
+        self.reader.stream()
+    }
+
+    fn arrived(&self) -> Option<Instant> {
+        Some(self.start + self.latest)
+    }
+
+    fn take_notice(&mut self) -> Option<String> {
+        self.reader.take_notice()
+    }
+
+    fn lines(&self) -> usize {
+        self.reader.lines()
+    }
+
+    fn file(&self) -> Option<usize> {
+        self.reader.file()
+    }
+}
+
+/// Times and displays the lines of another reader, one JSON object each, by their fields: each
+/// line arrives at the time of its time field since the first one after `start`, and reads as
+/// its line field, if it has one.
+///
+/// A line that is not a JSON object with a valid time is read as it is, arriving when it was
+/// read, and counted in `failures`. As with `ParsedTimeReadData`, a time earlier than that of a
+/// line before it gives a delta of zero.
+pub struct JsonReadData {
+    reader: Box<dyn ReadData>,
+    fields: JsonFields,
+    failures: Arc<AtomicUsize>,
+    start: Instant,
+    /// The first time, in nanoseconds since the Unix epoch.
+    first: Option<i64>,
+    /// Time of the latest line since the first time.
+    latest: Duration,
+    arrived: Option<Instant>,
+}
+
+impl JsonReadData {
+    /// Reads the lines of `reader` as JSON objects with `fields`.
+    pub fn new(
+        reader: Box<dyn ReadData>,
+        fields: JsonFields,
+        failures: Arc<AtomicUsize>,
+        start: Instant,
+    ) -> Self {
+        Self {
+            reader,
+            fields,
+            failures,
+            start,
+            first: None,
+            latest: Duration::ZERO,
+            arrived: None,
+        }
+    }
+}
+
+impl ReadData for JsonReadData {
+    /// Reads the next line of the wrapped reader, and puts its line field or else the line itself
+    /// into the provided buffer. Returns the number of bytes read, or 0 at the end of its input.
+    fn read_line(&mut self, buf: &mut String) -> Result<usize, TimelnError> {
+        self.read_line_timeout(buf, Duration::MAX)
+            .map(ReadOutcome::bytes)
+    }
+
+    fn read_line_timeout(
+        &mut self,
+        buf: &mut String,
+        timeout: Duration,
+    ) -> Result<ReadOutcome, TimelnError> {
+        let mut line = String::new();
+        let read = match self.reader.read_line_timeout(&mut line, timeout)? {
+            ReadOutcome::Line(read) => read,
+            outcome => return Ok(outcome),
+        };
+        match self.fields.parse(&line) {
+            Some(parsed) => {
+                let first = *self.first.get_or_insert(parsed.timestamp);
+                if let Ok(since_first) = u64::try_from(parsed.timestamp - first) {
+                    self.latest = self.latest.max(Duration::from_nanos(since_first));
+                }
+                self.arrived = Some(self.start + self.latest);
+                match parsed.text {
+                    Some(text) => {
+                        buf.push_str(&text);
+                        buf.push('\n');
+                    }
+                    None => buf.push_str(&line),
+                }
+            }
+            None => {
+                self.failures.fetch_add(1, Ordering::Relaxed);
+                self.arrived = self.reader.arrived();
+                buf.push_str(&line);
+            }
+        }
+        Ok(ReadOutcome::Line(read))
+    }
+
+    fn stream(&self) -> Option<Stream> {
+        self.reader.stream()
+    }
+
+    fn arrived(&self) -> Option<Instant> {
+        self.arrived
+    }
+
+    fn take_notice(&mut self) -> Option<String> {
+        self.reader.take_notice()
+    }
+
+    fn lines(&self) -> usize {
+        self.reader.lines()
+    }
+
+    fn file(&self) -> Option<usize> {
+        self.reader.file()
+    }
+}
+
+/// Times and displays the logfmt lines of another reader by their keys: each line arrives at the
+/// time of its time key since the first one after `start`, and reads as the keys in `fields`, or
+/// else as all its keys.
+///
+/// As with `JsonReadData`, a line without a valid time arrives when it was read and is counted in
+/// `untimed`, and a time earlier than that of a line before it gives a delta of zero.
+pub struct LogfmtReadData {
+    reader: Box<dyn ReadData>,
+    time_key: String,
+    fields: Vec<String>,
+    untimed: Arc<AtomicUsize>,
+    start: Instant,
+    /// The first time, in nanoseconds since the Unix epoch.
+    first: Option<i64>,
+    /// Time of the latest line since the first time.
+    latest: Duration,
+    arrived: Option<Instant>,
+    pairs: Vec<Pair>,
+}
+
+impl LogfmtReadData {
+    /// Reads the lines of `reader` as logfmt, timed by `time_key` and displayed as `fields`.
+    pub fn new(
+        reader: Box<dyn ReadData>,
+        time_key: &str,
+        fields: Vec<String>,
+        untimed: Arc<AtomicUsize>,
+        start: Instant,
+    ) -> Self {
+        Self {
+            reader,
+            time_key: time_key.to_string(),
+            fields,
+            untimed,
+            start,
+            first: None,
+            latest: Duration::ZERO,
+            arrived: None,
+            pairs: Vec::new(),
+        }
+    }
+}
+
+impl ReadData for LogfmtReadData {
+    /// Reads the next line of the wrapped reader, and puts its formatted keys into the provided
+    /// buffer. Returns the number of bytes read, or 0 at the end of its input.
+    fn read_line(&mut self, buf: &mut String) -> Result<usize, TimelnError> {
+        self.read_line_timeout(buf, Duration::MAX)
+            .map(ReadOutcome::bytes)
+    }
+
+    fn read_line_timeout(
+        &mut self,
+        buf: &mut String,
+        timeout: Duration,
+    ) -> Result<ReadOutcome, TimelnError> {
+        let mut line = String::new();
+        let read = match self.reader.read_line_timeout(&mut line, timeout)? {
+            ReadOutcome::Line(read) => read,
+            outcome => return Ok(outcome),
+        };
+        self.pairs = logfmt::parse(&line);
+        let timestamp = logfmt::get(&self.pairs, &self.time_key).and_then(parse_timestamp);
+        match timestamp {
+            Some(timestamp) => {
+                let first = *self.first.get_or_insert(timestamp);
+                if let Ok(since_first) = u64::try_from(timestamp - first) {
+                    self.latest = self.latest.max(Duration::from_nanos(since_first));
+                }
+                self.arrived = Some(self.start + self.latest);
+            }
+            None => {
+                self.untimed.fetch_add(1, Ordering::Relaxed);
+                self.arrived = self.reader.arrived();
+            }
+        }
+        let shown = if self.fields.is_empty() {
+            logfmt::format(&self.pairs)
+        } else {
+            let selected: Vec<Pair> = self
+                .fields
+                .iter()
+                .filter_map(|key| self.pairs.iter().find(|pair| &pair.key == key).cloned())
+                .collect();
+            logfmt::format(&selected)
+        };
+        buf.push_str(&shown);
+        buf.push('\n');
+        Ok(ReadOutcome::Line(read))
+    }
+
+    fn stream(&self) -> Option<Stream> {
+        self.reader.stream()
+    }
+
+    fn arrived(&self) -> Option<Instant> {
+        self.arrived
+    }
+
+    fn take_notice(&mut self) -> Option<String> {
+        self.reader.take_notice()
+    }
+
+    fn lines(&self) -> usize {
+        self.reader.lines()
+    }
+
+    fn file(&self) -> Option<usize> {
+        self.reader.file()
+    }
+
+    fn pairs(&self) -> &[Pair] {
+        &self.pairs
+    }
+}
+
+/// Times and displays the entries of the systemd journal read from another reader, as written by
+/// `journalctl --output=json`, by their messages.
+///
+/// The entries logged before `started_at`, when timeln started, arrive at the time of their
+/// journal timestamp since the first one after `start`, so that a past run of a unit is timed as
+/// it happened. The entries logged later arrive when they are read, after the history, so that
+/// tailing the journal times the entries live. A line that is not an entry is read as it is,
+/// arriving with the entry before it.
+pub struct JournalReadData {
+    reader: Box<dyn ReadData>,
+    start: Instant,
+    /// When timeln started, in nanoseconds since the Unix epoch.
+    started_at: i64,
+    /// The first timestamp, in nanoseconds since the Unix epoch.
+    first: Option<i64>,
+    /// Time of the latest entry since the first timestamp.
+    latest: Duration,
+    arrived: Option<Instant>,
+}
+
+impl JournalReadData {
+    /// Reads the journal entries of `reader`, logged before `started_at` in the past.
+    pub fn new(reader: Box<dyn ReadData>, start: Instant, started_at: SystemTime) -> Self {
+        let started_at = started_at
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos() as i64);
+        Self {
+            reader,
+            start,
+            started_at,
+            first: None,
+            latest: Duration::ZERO,
+            arrived: None,
+        }
+    }
+}
+
+impl ReadData for JournalReadData {
+    /// Reads the next entry of the wrapped reader, and puts its message, or else the line itself,
+    /// into the provided buffer. Returns the number of bytes read, or 0 at the end of its input.
+    fn read_line(&mut self, buf: &mut String) -> Result<usize, TimelnError> {
+        self.read_line_timeout(buf, Duration::MAX)
+            .map(ReadOutcome::bytes)
+    }
+
+    fn read_line_timeout(
+        &mut self,
+        buf: &mut String,
+        timeout: Duration,
+    ) -> Result<ReadOutcome, TimelnError> {
+        let mut line = String::new();
+        let read = match self.reader.read_line_timeout(&mut line, timeout)? {
+            ReadOutcome::Line(read) => read,
+            outcome => return Ok(outcome),
+        };
+        let read_at = self.reader.arrived().unwrap_or_else(Instant::now);
+        let Some(entry) = parse_entry(&line) else {
+            self.arrived = Some(self.start + self.latest);
+            buf.push_str(&line);
+            return Ok(ReadOutcome::Line(read));
+        };
+        let first = *self.first.get_or_insert(entry.timestamp);
+        let since_first = if entry.timestamp < self.started_at {
+            Duration::from_nanos(u64::try_from(entry.timestamp - first).unwrap_or(0))
+        } else {
+            // Live entries follow the history, which lasted until timeln started.
+            let history = u64::try_from(self.started_at - first).unwrap_or(0);
+            Duration::from_nanos(history) + read_at.saturating_duration_since(self.start)
+        };
+        self.latest = self.latest.max(since_first);
+        self.arrived = Some(self.start + self.latest);
+        buf.push_str(&entry.message);
+        buf.push('\n');
+        Ok(ReadOutcome::Line(read))
+    }
+
+    fn arrived(&self) -> Option<Instant> {
+        self.arrived
+    }
+
+    fn take_notice(&mut self) -> Option<String> {
+        self.reader.take_notice()
+    }
+}
+
+/// How fast a record file is replayed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayPace {
+    /// Factor by which the recorded gaps between lines are shortened; 2 replays twice as fast.
+    pub speed: f64,
+    /// Longest wait between two lines, after scaling.
+    pub clamp: Option<Duration>,
+}
+
+impl Default for ReplayPace {
+    /// Replays at the recorded pace.
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            clamp: None,
+        }
+    }
+}
+
+impl ReplayPace {
+    /// Returns how long to wait before replaying a line that was recorded `gap` after the
+    /// previous one.
+    pub fn scale(&self, gap: Duration) -> Duration {
+        let scaled = gap.div_f64(self.speed);
+        match self.clamp {
+            Some(clamp) => scaled.min(clamp),
+            None => scaled,
+        }
+    }
+}
+
+/// The records replayed by `ReplayReadData`, read from a record file or a CSV file.
+trait RecordSource: Iterator<Item = std::io::Result<Record>> + Send {
+    /// Number of the last line read, counting from 1.
+    fn line_number(&self) -> usize;
+}
+
+impl<R: BufRead + Send> RecordSource for RecordReader<R> {
+    fn line_number(&self) -> usize {
+        RecordReader::line_number(self)
+    }
+}
+
+impl<R: BufRead + Send> RecordSource for CsvReader<R> {
+    fn line_number(&self) -> usize {
+        CsvReader::line_number(self)
+    }
+}
+
+/// Reads the lines of a record file written with `--record`, each arriving at its recorded time
+/// since `start`.
+///
+/// When paced, each line is only returned once the recorded gap since the previous line, scaled
+/// by the `ReplayPace`, has passed; otherwise the lines are returned at once. Either way the
+/// arrival times are the recorded ones, so that the deltas are those of the recorded run and not
+/// the time spent waiting. A file without a footer, from a run that was killed, is read up to its
+/// last complete record. A CSV file is replayed the same way, one line per row.
+pub struct ReplayReadData {
+    path: PathBuf,
+    records: Box<dyn RecordSource>,
+    start: Instant,
+    pace: Option<ReplayPace>,
+    /// Recorded time of the previous line, and when it was replayed.
+    previous: (Duration, Instant),
+    stream: Option<Stream>,
+    arrived: Option<Instant>,
+}
+
+impl ReplayReadData {
+    /// Opens the record file `path`, checking its header. Lines are paced by `pace`, if given.
+    pub fn open(
+        path: impl AsRef<Path>,
+        start: Instant,
+        pace: Option<ReplayPace>,
+    ) -> Result<Self, TimelnError> {
+        let path = path.as_ref().to_path_buf();
+        let file = File::open(&path).map_err(|err| TimelnError::input(&path, err))?;
+        let mut records = RecordReader::new(BufReader::new(file));
+        match records.next().transpose() {
+            Ok(Some(Record::Header { .. })) => {}
+            Ok(_) => {
+                return Err(TimelnError::RecordParse {
+                    path: path.display().to_string(),
+                    line: 1,
+                    source: std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "not a record file: missing header",
+                    ),
+                })
+            }
+            Err(err) => return Err(record_error(&path, &records, err)),
+        }
+        Ok(Self::new(path, Box::new(records), start, pace))
+    }
+
+    /// Opens the CSV file `path`, with the times in `columns`. Lines are paced by `pace`, if
+    /// given.
+    pub fn open_csv(
+        path: impl AsRef<Path>,
+        columns: CsvColumns,
+        start: Instant,
+        pace: Option<ReplayPace>,
+    ) -> Result<Self, TimelnError> {
+        let path = path.as_ref().to_path_buf();
+        let file = File::open(&path).map_err(|err| TimelnError::input(&path, err))?;
+        let records = CsvReader::new(BufReader::new(file), columns);
+        Ok(Self::new(path, Box::new(records), start, pace))
+    }
+
+    fn new(
+        path: PathBuf,
+        records: Box<dyn RecordSource>,
+        start: Instant,
+        pace: Option<ReplayPace>,
+    ) -> Self {
+        Self {
+            path,
+            records,
+            start,
+            pace,
+            previous: (Duration::ZERO, Instant::now()),
+            stream: None,
+            arrived: None,
+        }
+    }
+}
+
+/// Wraps an error reading the record file `path`: a malformed record is a parse error at the line
+/// `records` just read, anything else an I/O error.
+fn record_error(path: &Path, records: &dyn RecordSource, err: std::io::Error) -> TimelnError {
+    if err.kind() == std::io::ErrorKind::InvalidData {
+        TimelnError::RecordParse {
+            path: path.display().to_string(),
+            line: records.line_number(),
+            source: err,
+        }
+    } else {
+        TimelnError::input(path, err)
+    }
+}
+
+impl ReadData for ReplayReadData {
+    /// Reads the next recorded line into the provided buffer, waiting for its scaled time if paced.
+    /// Returns 0 at the footer or the end of the file.
+    fn read_line(&mut self, buf: &mut String) -> Result<usize, TimelnError> {
+        loop {
+            let record = self
+                .records
+                .next()
+                .transpose()
+                .map_err(|err| record_error(&self.path, self.records.as_ref(), err))?;
+            match record {
+                Some(Record::Line {
+                    elapsed,
+                    stream,
+                    text,
+                }) => {
+                    if let Some(pace) = self.pace {
+                        let (previous, replayed) = self.previous;
+                        let due = replayed + pace.scale(elapsed.saturating_sub(previous));
+                        thread::sleep(due.saturating_duration_since(Instant::now()));
+                        self.previous = (elapsed, due);
+                    }
+                    let arrived = self.start + elapsed;
+                    self.stream = stream;
+                    self.arrived = Some(arrived);
+                    buf.push_str(&text);
+                    buf.push('\n');
+                    return Ok(text.len() + 1);
+                }
+                Some(Record::Header { .. }) => continue,
+                Some(Record::Footer { .. }) | None => return Ok(0),
+            }
+        }
+    }
+
+    fn stream(&self) -> Option<Stream> {
+        self.stream
+    }
+
+    fn arrived(&self) -> Option<Instant> {
+        self.arrived
+    }
+}
+
+// Test data implementation
+#[cfg(test)]
+pub type TestReadData<T = String> = BufReadData<std::io::Cursor<T>>;
+
+#[cfg(test)]
+impl<T: AsRef<[u8]> + Send> TestReadData<T> {
+    /// Creates a reader of the lines of `data`.
+    pub fn cursor(data: T) -> Self {
+        BufReadData::new(std::io::Cursor::new(data), "test data")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_test_read_line() {
+        // Arrange
+        let input = "Hello, world!\n".to_string();
+        let mut reader = TestReadData::cursor(input);
+
+        // Act
+        let mut buf = String::new();
+        let result = reader.read_line(&mut buf);
+
+        // Assert
+        assert_eq!(result.unwrap(), 14);
+        assert_eq!(buf, "Hello, world!\n");
+    }
+
+    #[test]
+    fn test_read_line_invalid_utf8() {
+        let mut reader = TestReadData::cursor(b"bad \xFF byte\nnext\n".to_vec());
+        let mut buf = String::new();
+        assert_eq!(reader.read_line(&mut buf).unwrap(), 11);
+        assert_eq!(buf, "bad \u{FFFD} byte\n");
+        buf.clear();
+        assert_eq!(reader.read_line(&mut buf).unwrap(), 5);
+        assert_eq!(buf, "next\n");
+    }
+
+    #[test]
+    fn test_read_line_cuts_long_lines() {
+        let mut reader =
+            TestReadData::cursor("0123456789\nshort\r\n0123456789".to_string()).max_line_bytes(4);
+        let mut lines = Vec::new();
+        let mut buf = String::new();
+        loop {
+            let read = reader.read_line(&mut buf).unwrap();
+            if read == 0 {
+                break;
+            }
+            lines.push((read, buf.clone()));
+            buf.clear();
+        }
+        // Every byte is counted, even those dropped, and the cut lines keep their line ending.
+        assert_eq!(
+            lines,
+            [
+                (11, "0123\n".to_string()),
+                (7, "shor\n".to_string()),
+                (10, "0123".to_string()),
+            ]
+        );
+
+        let mut exact = TestReadData::cursor("abcd\n".to_string()).max_line_bytes(5);
+        assert_eq!(exact.read_line(&mut buf).unwrap(), 5);
+        assert_eq!(buf, "abcd\n");
+    }
+
+    #[test]
+    fn test_buf_read_data_reads_any_source() {
+        let compressed_or_not: Box<dyn BufRead + Send> =
+            Box::new(BufReader::new(&b"first\nsecond"[..]));
+        let mut reader = BufReadData::new(compressed_or_not, "source");
+        let mut buf = String::new();
+        assert_eq!(reader.read_line(&mut buf).unwrap(), 6);
+        assert_eq!(reader.read_line(&mut buf).unwrap(), 6);
+        assert_eq!(reader.read_line(&mut buf).unwrap(), 0);
+        assert_eq!(buf, "first\nsecond");
+    }
+
+    #[test]
+    fn test_exec_read_line_merges_streams() {
+        let command = ["sh", "-c", "echo out1; echo err1 >&2; echo out2"].map(String::from);
+        let mut reader = ExecReadData::spawn(&command).unwrap();
+        let mut lines = Vec::new();
+        let mut buf = String::new();
+        while reader.read_line(&mut buf).unwrap() > 0 {
+            lines.push((reader.stream().unwrap(), buf.clone()));
+            buf.clear();
+        }
+        assert_eq!(lines.len(), 3);
+        assert!(lines.contains(&(Stream::Stderr, "err1\n".to_string())));
+        let stdout: Vec<_> = lines
+            .iter()
+            .filter(|(stream, _)| *stream == Stream::Stdout)
+            .map(|(_, line)| line.as_str())
+            .collect();
+        assert_eq!(stdout, vec!["out1\n", "out2\n"]);
+    }
+
+    #[test]
+    fn test_exec_counts_raw_bytes() {
+        let command = ["sh", "-c", r"printf 'a\377b\n'"].map(String::from);
+        let mut reader = ExecReadData::spawn(&command).unwrap();
+        let mut buf = String::new();
+        // The invalid byte is read as one byte, though it is replaced by a three-byte U+FFFD.
+        assert_eq!(reader.read_line(&mut buf).unwrap(), 4);
+        assert_eq!(buf, "a\u{FFFD}b\n");
+        assert_eq!(reader.read_line(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_exec_no_command() {
+        assert!(matches!(
+            ExecReadData::spawn(&[]),
+            Err(TimelnError::Usage(_))
+        ));
+        let missing = ["timeln-no-such-command".to_string()];
+        assert!(matches!(
+            ExecReadData::spawn(&missing),
+            Err(TimelnError::Command { command, .. }) if command == missing[0]
+        ));
+    }
+
+    /// A file in the temporary directory, removed when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, contents: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("timeln-{}-{}", std::process::id(), name));
+            std::fs::write(&path, contents).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn read_lines(reader: &mut impl ReadData, count: usize) -> Vec<String> {
+        (0..count)
+            .map(|_| {
+                let mut buf = String::new();
+                reader.read_line(&mut buf).unwrap();
+                buf
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_file_read_line() {
+        let file = TempFile::new("read", "one\ntwo");
+        let mut reader = FileReadData::open(&file.0, None, DEFAULT_FOLLOW_INTERVAL).unwrap();
+        assert_eq!(read_lines(&mut reader, 3), vec!["one\n", "two", ""]);
+
+        let missing = file.0.with_extension("missing");
+        assert!(matches!(
+            FileReadData::open(&missing, None, DEFAULT_FOLLOW_INTERVAL),
+            Err(TimelnError::InputIo { path, .. }) if path == missing.display().to_string()
+        ));
+    }
+
+    #[test]
+    fn test_file_cuts_long_lines() {
+        let file = TempFile::new("long", "0123456789\nshort\n0123456789");
+        let mut reader = FileReadData::open(&file.0, None, DEFAULT_FOLLOW_INTERVAL)
+            .unwrap()
+            .max_line_bytes(4);
+        let mut buf = String::new();
+        assert_eq!(reader.read_line(&mut buf).unwrap(), 11);
+        assert_eq!(reader.read_line(&mut buf).unwrap(), 6);
+        // The last line has no line ending, and is cut all the same.
+        assert_eq!(reader.read_line(&mut buf).unwrap(), 10);
+        assert_eq!(reader.read_line(&mut buf).unwrap(), 0);
+        assert_eq!(buf, "0123\nshor\n0123");
+    }
+
+    #[test]
+    fn test_files_read_one_after_the_other() {
+        let first = TempFile::new("files-1", "one\ntwo");
+        let empty = TempFile::new("files-2", "");
+        let last = TempFile::new("files-3", "three\n");
+        let mut reader = FilesReadData::open(&[&first.0, &empty.0, &last.0]).unwrap();
+        let mut read = Vec::new();
+        let mut buf = String::new();
+        while reader.read_line(&mut buf).unwrap() > 0 {
+            read.push((reader.file(), std::mem::take(&mut buf)));
+        }
+        assert_eq!(
+            read,
+            [
+                (Some(0), "one\n".to_string()),
+                (Some(0), "two".to_string()),
+                (Some(2), "three\n".to_string())
+            ]
+        );
+
+        let missing = first.0.with_extension("missing");
+        assert!(matches!(
+            FilesReadData::open(&[&first.0, &missing]),
+            Err(TimelnError::InputIo { path, .. }) if path == missing.display().to_string()
+        ));
+    }
+
+    /// Writes each of `connections` to the socket at `path`, one connection after the other.
+    #[cfg(unix)]
+    fn connect_and_write(path: PathBuf, connections: &[&'static str]) -> thread::JoinHandle<()> {
+        let connections = connections.to_vec();
+        thread::spawn(move || {
+            for lines in connections {
+                let mut stream = std::os::unix::net::UnixStream::connect(&path).unwrap();
+                std::io::Write::write_all(&mut stream, lines.as_bytes()).unwrap();
+            }
+        })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_socket_reads_first_connection() {
+        let path = std::env::temp_dir().join(format!("timeln-{}-socket", std::process::id()));
+        let mut reader = SocketReadData::listen(&path, false).unwrap();
+        assert_eq!(reader.socket_path(), Some(path.as_path()));
+        let writer = connect_and_write(path.clone(), &["one\ntwo"]);
+        assert_eq!(read_lines(&mut reader, 3), vec!["one\n", "two", ""]);
+        assert_eq!(reader.take_notice(), None);
+        drop(reader);
+        writer.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_socket_read_line_timeout() {
+        let path = std::env::temp_dir().join(format!("timeln-{}-timeout", std::process::id()));
+        let mut reader = SocketReadData::listen(&path, false).unwrap();
+        let mut buf = String::new();
+        let timeout = Duration::from_millis(50);
+        // No connection yet.
+        assert_eq!(
+            reader.read_line_timeout(&mut buf, timeout).unwrap(),
+            ReadOutcome::TimedOut
+        );
+        let mut stream = std::os::unix::net::UnixStream::connect(&path).unwrap();
+        std::io::Write::write_all(&mut stream, b"one\n").unwrap();
+        assert_eq!(
+            reader.read_line_timeout(&mut buf, timeout).unwrap(),
+            ReadOutcome::Line(4)
+        );
+        // Connected, but silent.
+        assert_eq!(
+            reader.read_line_timeout(&mut buf, timeout).unwrap(),
+            ReadOutcome::TimedOut
+        );
+        drop(stream);
+        assert_eq!(
+            reader.read_line_timeout(&mut buf, timeout).unwrap(),
+            ReadOutcome::Eof
+        );
+        assert_eq!(buf, "one\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_tcp_listen_reads_one_connection() {
+        let mut reader = TcpReadData::listen("127.0.0.1:0", false).unwrap();
+        let addr = reader.local_addr().unwrap();
+        let mut buf = String::new();
+        let timeout = Duration::from_millis(50);
+        // No client yet.
+        #[cfg(unix)]
+        assert_eq!(
+            reader.read_line_timeout(&mut buf, timeout).unwrap(),
+            ReadOutcome::TimedOut
+        );
+        let mut stream = TcpStream::connect(addr).unwrap();
+        std::io::Write::write_all(&mut stream, b"one\ntwo\n").unwrap();
+        assert_eq!(read_lines(&mut reader, 2), vec!["one\n", "two\n"]);
+        // Connected, but silent.
+        assert_eq!(
+            reader.read_line_timeout(&mut buf, timeout).unwrap(),
+            ReadOutcome::TimedOut
+        );
+        drop(stream);
+        assert_eq!(
+            reader.read_line_timeout(&mut buf, timeout).unwrap(),
+            ReadOutcome::Eof
+        );
+        assert_eq!(reader.take_notice(), None);
+    }
+
+    #[test]
+    fn test_tcp_connect_reconnects() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            for text in ["one\n", "two\n"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                std::io::Write::write_all(&mut stream, text.as_bytes()).unwrap();
+            }
+        });
+        let mut reader = TcpReadData::connect(&addr, Duration::from_secs(5), true).unwrap();
+        assert_eq!(read_lines(&mut reader, 1), vec!["one\n"]);
+        assert_eq!(reader.take_notice(), None);
+        assert_eq!(read_lines(&mut reader, 1), vec!["two\n"]);
+        assert_eq!(reader.take_notice().as_deref(), Some("[connection 2]"));
+        server.join().unwrap();
+
+        // The server is gone, so the reader keeps trying to connect again.
+        let mut buf = String::new();
+        assert_eq!(
+            reader
+                .read_line_timeout(&mut buf, Duration::from_millis(50))
+                .unwrap(),
+            ReadOutcome::TimedOut
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_socket_keeps_reading_reconnections() {
+        let path = std::env::temp_dir().join(format!("timeln-{}-keep", std::process::id()));
+        let mut reader = SocketReadData::listen(&path, true).unwrap();
+        let writer = connect_and_write(path.clone(), &["one\ntwo\n", "", "three\n"]);
+        assert_eq!(read_lines(&mut reader, 2), vec!["one\n", "two\n"]);
+        assert_eq!(reader.take_notice(), None);
+        assert_eq!(read_lines(&mut reader, 1), vec!["three\n"]);
+        assert_eq!(reader.take_notice().as_deref(), Some("[connection 3]"));
+        writer.join().unwrap();
+
+        // The socket of a run that was killed is replaced by the next run.
+        drop(reader);
+        let mut reader = SocketReadData::listen(&path, false).unwrap();
+        let writer = connect_and_write(path.clone(), &["again\n"]);
+        assert_eq!(read_lines(&mut reader, 2), vec!["again\n", ""]);
+        writer.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fifo_reopened_for_each_writer() {
+        let path = std::env::temp_dir().join(format!("timeln-{}-fifo", std::process::id()));
+        let c_path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+        let mut reader = SocketReadData::listen(&path, true).unwrap();
+        assert_eq!(reader.socket_path(), None);
+        let writer = {
+            let path = path.clone();
+            thread::spawn(move || {
+                for lines in ["a\n", "b\n"] {
+                    let mut fifo = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+                    std::io::Write::write_all(&mut fifo, lines.as_bytes()).unwrap();
+                    drop(fifo);
+                    // Lets the reader see the end of the first writer before the next one opens.
+                    thread::sleep(Duration::from_millis(50));
+                }
+            })
+        };
+        assert_eq!(read_lines(&mut reader, 2), vec!["a\n", "b\n"]);
+        assert_eq!(reader.take_notice().as_deref(), Some("[connection 2]"));
+        writer.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let file = TempFile::new("not-a-socket", "");
+        assert!(matches!(
+            SocketReadData::listen(&file.0, false),
+            Err(TimelnError::Usage(_))
+        ));
+    }
+
+    #[test]
+    fn test_follow_waits_for_complete_lines() {
+        let file = TempFile::new("follow", "first\n");
+        let interval = Duration::from_millis(5);
+        let mut reader =
+            FileReadData::open(&file.0, Some(FollowMode::Descriptor), interval).unwrap();
+        assert_eq!(read_lines(&mut reader, 1), vec!["first\n"]);
+
+        let path = file.0.clone();
+        let writer = thread::spawn(move || {
+            let mut log = std::fs::OpenOptions::new().append(true).open(path).unwrap();
+            std::io::Write::write_all(&mut log, b"sec").unwrap();
+            thread::sleep(Duration::from_millis(30));
+            std::io::Write::write_all(&mut log, b"ond\n").unwrap();
+        });
+        assert_eq!(read_lines(&mut reader, 1), vec!["second\n"]);
+        writer.join().unwrap();
+        assert_eq!(reader.take_notice(), None);
+    }
+
+    #[test]
+    fn test_follow_cuts_unterminated_line() {
+        let file = TempFile::new("follow-long", "");
+        let interval = Duration::from_millis(5);
+        let mut reader = FileReadData::open(&file.0, Some(FollowMode::Descriptor), interval)
+            .unwrap()
+            .max_line_bytes(4);
+
+        let path = file.0.clone();
+        let writer = thread::spawn(move || {
+            let mut log = std::fs::OpenOptions::new().append(true).open(path).unwrap();
+            for chunk in ["0123", "4567", "89\n"] {
+                std::io::Write::write_all(&mut log, chunk.as_bytes()).unwrap();
+                thread::sleep(Duration::from_millis(20));
+            }
+        });
+        let mut buf = String::new();
+        // The part of the line past the limit is dropped as it is written, rather than kept.
+        assert_eq!(reader.read_line(&mut buf).unwrap(), 11);
+        assert_eq!(buf, "0123\n");
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn test_follow_name_reopens_rotated_file() {
+        let file = TempFile::new("rotate", "old 1\nold 2\n");
+        let rotated = TempFile::new("rotate.1", "");
+        let interval = Duration::from_millis(5);
+        let mut reader = FileReadData::open(&file.0, Some(FollowMode::Name), interval).unwrap();
+        assert_eq!(read_lines(&mut reader, 2), vec!["old 1\n", "old 2\n"]);
+
+        let (path, rotated_path) = (file.0.clone(), rotated.0.clone());
+        let rotator = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            std::fs::rename(&path, rotated_path).unwrap();
+            std::fs::write(&path, "new 1\n").unwrap();
+        });
+        assert_eq!(read_lines(&mut reader, 1), vec!["new 1\n"]);
+        rotator.join().unwrap();
+        assert_eq!(reader.take_notice().as_deref(), Some("[log rotated]"));
+    }
+
+    #[test]
+    fn test_follow_restarts_truncated_file() {
+        let file = TempFile::new("truncate", "a long first line\n");
+        let interval = Duration::from_millis(5);
+        let mut reader =
+            FileReadData::open(&file.0, Some(FollowMode::Descriptor), interval).unwrap();
+        assert_eq!(read_lines(&mut reader, 1), vec!["a long first line\n"]);
+
+        std::fs::write(&file.0, "short\n").unwrap();
+        assert_eq!(read_lines(&mut reader, 1), vec!["short\n"]);
+        assert_eq!(reader.take_notice().as_deref(), Some("[log truncated]"));
+    }
+
+    #[test]
+    fn test_follow_mode_from_str() {
+        assert_eq!("name".parse(), Ok(FollowMode::Name));
+        assert_eq!("descriptor".parse(), Ok(FollowMode::Descriptor));
+        assert!("inode".parse::<FollowMode>().is_err());
+    }
+
+    #[test]
+    fn test_replay_reads_crashed_record_file() {
+        // No footer, and the last record was cut off while being written.
+        let file = TempFile::new(
+            "replay",
+            "H\t1\t0\ttimeln\nL\t1000000\to\tfirst\nL\t3000000\te\tsec\\tond\nL\t40",
+        );
+        let start = Instant::now();
+        let mut reader = ReplayReadData::open(&file.0, start, None).unwrap();
+        let mut buf = String::new();
+        assert_eq!(reader.read_line(&mut buf).unwrap(), 6);
+        assert_eq!(reader.stream(), Some(Stream::Stdout));
+        assert_eq!(reader.arrived(), Some(start + Duration::from_millis(1)));
+        buf.clear();
+        assert_eq!(reader.read_line(&mut buf).unwrap(), 8);
+        assert_eq!(buf, "sec\tond\n");
+        assert_eq!(reader.stream(), Some(Stream::Stderr));
+        assert_eq!(reader.arrived(), Some(start + Duration::from_millis(3)));
+        assert_eq!(reader.read_line(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_replay_rejects_file_without_header() {
+        let file = TempFile::new("replay-headerless", "L\t1\t-\tline\n");
+        assert!(matches!(
+            ReplayReadData::open(&file.0, Instant::now(), None),
+            Err(TimelnError::RecordParse { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_replay_names_malformed_record() {
+        let file = TempFile::new(
+            "replay-malformed",
+            "H\t1\t0\ttimeln\nL\t1000\to\tfirst\nL\tsoon\to\tsecond\n",
+        );
+        let mut reader = ReplayReadData::open(&file.0, Instant::now(), None).unwrap();
+        let mut buf = String::new();
+        reader.read_line(&mut buf).unwrap();
+        match reader.read_line(&mut buf) {
+            Err(TimelnError::RecordParse { path, line, .. }) => {
+                assert_eq!(path, file.0.display().to_string());
+                assert_eq!(line, 3);
+            }
+            other => panic!("expected a record parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_replay_pace_scale() {
+        let gap = Duration::from_secs(10);
+        assert_eq!(ReplayPace::default().scale(gap), gap);
+        let pace = |speed, clamp| ReplayPace { speed, clamp };
+        assert_eq!(pace(2.0, None).scale(gap), Duration::from_secs(5));
+        assert_eq!(pace(0.5, None).scale(gap), Duration::from_secs(20));
+        let clamp = Duration::from_secs(5);
+        assert_eq!(
+            pace(1.0, Some(clamp)).scale(Duration::from_secs(1200)),
+            clamp
+        );
+        assert_eq!(pace(10.0, Some(clamp)).scale(gap), Duration::from_secs(1));
+        assert_eq!(
+            pace(10.0, Some(clamp)).scale(Duration::ZERO),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn test_replay_pace_keeps_recorded_arrival_times() {
+        let file = TempFile::new(
+            "replay-paced",
+            "H\t1\t0\nL\t0\t-\tfirst\nL\t600000000\t-\tsecond\nF\t2\t600000000\n",
+        );
+        let start = Instant::now();
+        let pace = ReplayPace {
+            speed: 2.0,
+            clamp: Some(Duration::from_millis(100)),
+        };
+        let mut reader = ReplayReadData::open(&file.0, start, Some(pace)).unwrap();
+        let mut buf = String::new();
+        reader.read_line(&mut buf).unwrap();
+        let replaying = Instant::now();
+        reader.read_line(&mut buf).unwrap();
+        let waited = replaying.elapsed();
+        // The 600ms gap is halved, then clamped, but the line still arrives 600ms in.
+        assert!(waited >= Duration::from_millis(90), "{:?}", waited);
+        assert!(waited < Duration::from_millis(300), "{:?}", waited);
+        assert_eq!(reader.arrived(), Some(start + Duration::from_millis(600)));
+        assert_eq!(reader.read_line(&mut buf).unwrap(), 0);
+    }
+
+    /// Reads the lines sent to it, waiting for each, until its sender is dropped.
+    struct ChannelReadData(Receiver<&'static str>);
+
+    impl ReadData for ChannelReadData {
+        fn read_line(&mut self, buf: &mut String) -> Result<usize, TimelnError> {
+            match self.0.recv() {
+                Ok(line) => {
+                    buf.push_str(line);
+                    Ok(line.len())
+                }
+                Err(_) => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_line_timeout_blocks_by_default() {
+        let mut reader = IterReadData::new(["a"]);
+        let mut buf = String::new();
+        let timeout = Duration::from_millis(1);
+        assert_eq!(
+            reader.read_line_timeout(&mut buf, timeout).unwrap(),
+            ReadOutcome::Line(2)
+        );
+        assert_eq!(
+            reader.read_line_timeout(&mut buf, timeout).unwrap(),
+            ReadOutcome::Eof
+        );
+        assert_eq!(ReadOutcome::TimedOut.bytes(), 0);
+    }
+
+    #[test]
+    fn test_threaded_read_line_timeout() {
+        let (sender, lines) = mpsc::channel();
+        let mut reader = ThreadedReadData::spawn(Box::new(ChannelReadData(lines)));
+        let mut buf = String::new();
+        let timeout = Duration::from_millis(50);
+
+        let waited = Instant::now();
+        assert_eq!(
+            reader.read_line_timeout(&mut buf, timeout).unwrap(),
+            ReadOutcome::TimedOut
+        );
+        assert!(waited.elapsed() >= timeout);
+
+        sender.send("first\n").unwrap();
+        assert_eq!(
+            reader.read_line_timeout(&mut buf, timeout).unwrap(),
+            ReadOutcome::Line(6)
+        );
+        assert!(reader.arrived().is_some());
+        sender.send("second\n").unwrap();
+        assert_eq!(reader.read_line(&mut buf).unwrap(), 7);
+        assert_eq!(buf, "first\nsecond\n");
+
+        drop(sender);
+        assert_eq!(
+            reader.read_line_timeout(&mut buf, timeout).unwrap(),
+            ReadOutcome::Eof
+        );
+    }
+
+    #[test]
+    fn test_exec_read_line_timeout() {
+        let command = ["sh", "-c", "echo first; sleep 1; echo second"].map(String::from);
+        let mut reader = ExecReadData::spawn(&command).unwrap();
+        let mut buf = String::new();
+        let timeout = Duration::from_millis(200);
+        assert_eq!(
+            reader.read_line_timeout(&mut buf, timeout).unwrap(),
+            ReadOutcome::Line(6)
+        );
+        assert_eq!(
+            reader.read_line_timeout(&mut buf, timeout).unwrap(),
+            ReadOutcome::TimedOut
+        );
+        assert_eq!(reader.read_line(&mut buf).unwrap(), 7);
+        assert_eq!(
+            reader.read_line_timeout(&mut buf, timeout).unwrap(),
+            ReadOutcome::Eof
+        );
+    }
+
+    #[test]
+    fn test_join_read_line_timeout_between_joined_lines() {
+        let (sender, lines) = mpsc::channel();
+        let threaded = ThreadedReadData::spawn(Box::new(ChannelReadData(lines)));
+        let mut reader = JoinReadData::new(Box::new(threaded), JoinBoundary::Paragraph);
+        let mut buf = String::new();
+        let timeout = Duration::from_millis(50);
+        assert_eq!(
+            reader.read_line_timeout(&mut buf, timeout).unwrap(),
+            ReadOutcome::TimedOut
+        );
+        for line in ["Traceback\n", "  at main\n", "\n"] {
+            sender.send(line).unwrap();
+        }
+        assert_eq!(
+            reader.read_line_timeout(&mut buf, timeout).unwrap(),
+            ReadOutcome::Line(21)
+        );
+        assert_eq!(reader.lines(), 3);
+        drop(sender);
+        assert_eq!(
+            reader.read_line_timeout(&mut buf, timeout).unwrap(),
+            ReadOutcome::Eof
+        );
+    }
+
+    /// Reads every joined line of `lines` with `boundary`, with its number of input lines.
+    fn join(lines: &[&str], boundary: JoinBoundary) -> Vec<(String, usize)> {
+        let lines: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        let mut reader = JoinReadData::new(Box::new(IterReadData::new(lines)), boundary);
+        let mut joined = Vec::new();
+        let mut buf = String::new();
+        while reader.read_line(&mut buf).unwrap() > 0 {
+            joined.push((buf.clone(), reader.lines()));
+            buf.clear();
+        }
+        joined
+    }
+
+    #[test]
+    fn test_join_until_boundary_line() {
+        let boundary = JoinBoundary::Until(Regex::new(r"^\w*Error").unwrap());
+        let joined = join(
+            &[
+                "Traceback (most recent call last):",
+                "  File \"app.py\", line 3",
+                "ValueError: bad",
+                "retrying",
+            ],
+            boundary,
+        );
+        assert_eq!(
+            joined,
+            vec![
+                (
+                    "Traceback (most recent call last):\n  File \"app.py\", line 3\nValueError: bad\n"
+                        .to_string(),
+                    3
+                ),
+                ("retrying\n".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_join_paragraphs() {
+        let joined = join(&["", "a", "b", "", "", "c"], JoinBoundary::Paragraph);
+        assert_eq!(
+            joined,
+            vec![("\na\nb\n\n".to_string(), 4), ("\nc\n".to_string(), 2),]
+        );
+    }
+
+    #[test]
+    fn test_journal_times_history_then_live() {
+        // Timeln started 10 s after the first entry, the last of which is live.
+        let started_at = UNIX_EPOCH + Duration::from_secs(1010);
+        let entries = [
+            r#"{"__REALTIME_TIMESTAMP": "1000000000", "MESSAGE": "booted"}"#,
+            r#"{"__REALTIME_TIMESTAMP": "1004500000", "MESSAGE": "ready"}"#,
+            "journalctl: some warning",
+            r#"{"__REALTIME_TIMESTAMP": "1011000000", "MESSAGE": "request"}"#,
+        ];
+        let start = Instant::now();
+        let mut reader =
+            JournalReadData::new(Box::new(IterReadData::new(entries)), start, started_at);
+        let mut read = Vec::new();
+        let mut buf = String::new();
+        while reader.read_line(&mut buf).unwrap() > 0 {
+            read.push((buf.clone(), reader.arrived().unwrap()));
+            buf.clear();
+        }
+        let lines: Vec<&str> = read.iter().map(|(line, _)| line.as_str()).collect();
+        assert_eq!(
+            lines,
+            [
+                "booted\n",
+                "ready\n",
+                "journalctl: some warning\n",
+                "request\n"
+            ]
+        );
+        assert_eq!(read[0].1, start);
+        assert_eq!(read[1].1 - start, Duration::from_millis(4500));
+        assert_eq!(read[2].1, read[1].1);
+        // The live entry arrives after the 10 s of history, when it was read.
+        let live = read[3].1 - start;
+        assert!(live >= Duration::from_secs(10), "{:?}", live);
+        assert!(live < Duration::from_secs(11), "{:?}", live);
+    }
+
+    #[test]
+    fn test_iter_read_line() {
+        let mut reader = IterReadData::new(vec!["first".to_string(), "second\n".to_string()]);
+        let mut buf = String::new();
+        assert_eq!(reader.read_line(&mut buf).unwrap(), 6);
+        assert_eq!(buf, "first\n");
+        buf.clear();
+        assert_eq!(reader.read_line(&mut buf).unwrap(), 7);
+        assert_eq!(buf, "second\n");
+        assert_eq!(reader.read_line(&mut buf).unwrap(), 0);
     }
 }
@@ -0,0 +1,411 @@
+//! This module reads and writes record files, which capture the input of a run with the time each
+//! line arrived, so that the run can be replayed or plotted again later.
+//!
+//! A record file is line-based text, one record per line with tab-separated fields:
+//!
+//! - `H <version> <start unix ms> <timeln version> <commit> <build date> <target> <features>
+//!   <argv...>`: the header, written first, with the build of timeln that wrote the file. Record
+//!   files of version 1 have no build fields.
+//! - `L <elapsed ns> <stream> <text>`: an input line, with its arrival time since the start of the
+//!   run and the stream it came from (`o` or `e` for the stdout or stderr of a command, `-`
+//!   otherwise).
+//! - `F <lines> <total ns>`: the footer, written when the run finishes cleanly.
+//!
+//! Tabs, newlines, carriage returns and backslashes in the fields are escaped. Each record is
+//! written with a single write, so a run that is killed leaves at most one incomplete record at the
+//! end of the file; readers ignore it, and a missing footer tells them that the run did not finish.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use timeln::record::Record;
+//!
+//! let record = Record::Line {
+//!     elapsed: Duration::from_millis(1500),
+//!     stream: None,
+//!     text: "compiling\tcrate".to_string(),
+//! };
+//! let line = record.to_line();
+//! assert_eq!(line, "L\t1500000000\t-\tcompiling\\tcrate\n");
+//! assert_eq!(Record::parse(line.trim_end_matches('\n')), Ok(record));
+//! ```
+use std::fs::File;
+use std::io::{BufRead, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::build_info::{split_features, BuildInfo};
+use crate::reader::Stream;
+
+/// Version of the record format written by this version of timeln.
+pub const RECORD_VERSION: u32 = 2;
+
+/// A record of a record file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Record {
+    /// Describes the run; always the first record.
+    Header {
+        version: u32,
+        /// Wall-clock start of the run, in milliseconds since the Unix epoch.
+        start_unix_ms: u128,
+        /// Build of timeln that wrote the file, unknown before version 2.
+        build: Option<BuildInfo>,
+        /// Command line of the recording process.
+        argv: Vec<String>,
+    },
+    /// An input line.
+    Line {
+        /// Arrival time of the line since the start of the run.
+        elapsed: Duration,
+        /// Stream of a child process the line was read from.
+        stream: Option<Stream>,
+        /// The line, without its line ending.
+        text: String,
+    },
+    /// Marks a run that finished cleanly; always the last record.
+    Footer {
+        /// Number of input lines recorded.
+        lines: u64,
+        /// Duration of the run.
+        total: Duration,
+    },
+}
+
+impl Record {
+    /// The header of a run of this version of timeln starting now, with command line `argv`.
+    pub fn header(argv: Vec<String>) -> Record {
+        let start_unix_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        Record::Header {
+            version: RECORD_VERSION,
+            start_unix_ms,
+            build: Some(BuildInfo::current()),
+            argv,
+        }
+    }
+
+    /// Formats the record as a line of a record file, including its newline.
+    pub fn to_line(&self) -> String {
+        match self {
+            Record::Header {
+                version,
+                start_unix_ms,
+                build,
+                argv,
+            } => {
+                let mut line = format!("H\t{}\t{}", version, start_unix_ms);
+                let fields = build.iter().flat_map(|build| {
+                    [
+                        build.version.clone(),
+                        build.commit.clone(),
+                        build.date.clone(),
+                        build.target.clone(),
+                        build.features.join(","),
+                    ]
+                });
+                for arg in fields.chain(argv.iter().cloned()) {
+                    line.push('\t');
+                    line.push_str(&escape(&arg));
+                }
+                line.push('\n');
+                line
+            }
+            Record::Line {
+                elapsed,
+                stream,
+                text,
+            } => {
+                let stream = match stream {
+                    Some(Stream::Stdout) => "o",
+                    Some(Stream::Stderr) => "e",
+                    None => "-",
+                };
+                format!("L\t{}\t{}\t{}\n", elapsed.as_nanos(), stream, escape(text))
+            }
+            Record::Footer { lines, total } => format!("F\t{}\t{}\n", lines, total.as_nanos()),
+        }
+    }
+
+    /// Parses a line of a record file, without its newline.
+    pub fn parse(line: &str) -> Result<Record, String> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let number = |field: Option<&&str>| -> Result<u128, String> {
+            field
+                .ok_or_else(|| "missing field".to_string())?
+                .parse()
+                .map_err(|err| format!("invalid number: {}", err))
+        };
+        let duration = |field: Option<&&str>| -> Result<Duration, String> {
+            let nanos = number(field)?;
+            Ok(Duration::new(
+                (nanos / 1_000_000_000) as u64,
+                (nanos % 1_000_000_000) as u32,
+            ))
+        };
+        match fields[0] {
+            "H" => {
+                let version = number(fields.get(1))? as u32;
+                if version > RECORD_VERSION {
+                    return Err(format!("unsupported record version {}", version));
+                }
+                let (build, argv) = if version >= 2 {
+                    if fields.len() < 8 {
+                        return Err("missing build fields".to_string());
+                    }
+                    let build = BuildInfo {
+                        version: unescape(fields[3]),
+                        commit: unescape(fields[4]),
+                        date: unescape(fields[5]),
+                        target: unescape(fields[6]),
+                        features: split_features(&unescape(fields[7])),
+                    };
+                    (Some(build), &fields[8..])
+                } else {
+                    (None, fields.get(3..).unwrap_or_default())
+                };
+                Ok(Record::Header {
+                    version,
+                    start_unix_ms: number(fields.get(2))?,
+                    build,
+                    argv: argv.iter().map(|arg| unescape(arg)).collect(),
+                })
+            }
+            "L" if fields.len() == 4 => Ok(Record::Line {
+                elapsed: duration(fields.get(1))?,
+                stream: match fields[2] {
+                    "o" => Some(Stream::Stdout),
+                    "e" => Some(Stream::Stderr),
+                    "-" => None,
+                    other => return Err(format!("invalid stream '{}'", other)),
+                },
+                text: unescape(fields[3]),
+            }),
+            "F" if fields.len() == 3 => Ok(Record::Footer {
+                lines: number(fields.get(1))? as u64,
+                total: duration(fields.get(2))?,
+            }),
+            _ => Err(format!("invalid record '{}'", line)),
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Appends records to a record file as they happen.
+pub struct RecordWriter {
+    path: String,
+    file: File,
+    sync: bool,
+}
+
+impl RecordWriter {
+    /// Creates the record file `path` and writes its header, with `argv` as the command line.
+    /// If `sync` is set, every record is also flushed to disk before `write` returns.
+    pub fn create(path: &str, argv: Vec<String>, sync: bool) -> std::io::Result<Self> {
+        let mut writer = Self {
+            path: path.to_string(),
+            file: File::create(path)?,
+            sync,
+        };
+        writer.write(&Record::header(argv))?;
+        Ok(writer)
+    }
+
+    /// Path of the record file.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Writes a record, unbuffered, so that it survives the process being killed.
+    pub fn write(&mut self, record: &Record) -> std::io::Result<()> {
+        self.file.write_all(record.to_line().as_bytes())?;
+        if self.sync {
+            self.file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the records of a record file, stopping before an incomplete last record.
+pub struct RecordReader<R> {
+    reader: R,
+    line_number: usize,
+    buffer: Vec<u8>,
+}
+
+impl<R: BufRead> RecordReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line_number: 0,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Number of the last line read, counting from 1.
+    pub fn line_number(&self) -> usize {
+        self.line_number
+    }
+}
+
+impl<R: BufRead> Iterator for RecordReader<R> {
+    type Item = std::io::Result<Record>;
+
+    /// Returns the next complete record, or an `InvalidData` error naming the line of a malformed
+    /// one. A last line without a newline was cut off while being written and is skipped.
+    fn next(&mut self) -> Option<Self::Item> {
+        self.buffer.clear();
+        match self.reader.read_until(b'\n', &mut self.buffer) {
+            Err(err) => return Some(Err(err)),
+            Ok(_) if !self.buffer.ends_with(b"\n") => return None,
+            Ok(_) => {}
+        }
+        self.line_number += 1;
+        let line = String::from_utf8_lossy(&self.buffer[..self.buffer.len() - 1]);
+        Some(Record::parse(&line).map_err(|err| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("record line {}: {}", self.line_number, err),
+            )
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records() -> Vec<Record> {
+        vec![
+            Record::Header {
+                version: RECORD_VERSION,
+                start_unix_ms: 1_700_000_000_000,
+                build: Some(BuildInfo::current()),
+                argv: vec!["timeln".to_string(), "-r".to_string(), "a\tb".to_string()],
+            },
+            Record::Line {
+                elapsed: Duration::from_nanos(1_234_567_890),
+                stream: Some(Stream::Stderr),
+                text: "back\\slash\r".to_string(),
+            },
+            Record::Line {
+                elapsed: Duration::from_secs(2),
+                stream: None,
+                text: String::new(),
+            },
+            Record::Footer {
+                lines: 2,
+                total: Duration::from_secs(3),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        for record in records() {
+            let line = record.to_line();
+            assert_eq!(line.matches('\n').count(), 1);
+            assert_eq!(Record::parse(line.trim_end_matches('\n')), Ok(record));
+        }
+    }
+
+    #[test]
+    fn test_record_parse_errors() {
+        assert!(Record::parse("L\tsoon\t-\ttext").is_err());
+        assert!(Record::parse("L\t1\tx\ttext").is_err());
+        assert!(Record::parse("H\t99\t0").is_err());
+        assert!(Record::parse("H\t2\t0\t0.1.4").is_err());
+        assert!(Record::parse("Q").is_err());
+    }
+
+    #[test]
+    fn test_header_has_build() {
+        let line = Record::header(vec!["timeln".to_string()]).to_line();
+        let build = BuildInfo::current();
+        let fields: Vec<&str> = line.trim_end().split('\t').collect();
+        assert_eq!(fields[1], RECORD_VERSION.to_string());
+        assert_eq!(
+            fields[3..7],
+            [&build.version, &build.commit, &build.date, &build.target]
+        );
+        assert!(fields[3..7].iter().all(|field| !field.is_empty()));
+        assert_eq!(fields[8], "timeln");
+        // Version 1 headers, without build fields, can still be read.
+        assert_eq!(
+            Record::parse("H\t1\t42\ttimeln\t-r"),
+            Ok(Record::Header {
+                version: 1,
+                start_unix_ms: 42,
+                build: None,
+                argv: vec!["timeln".to_string(), "-r".to_string()],
+            })
+        );
+    }
+
+    #[test]
+    fn test_reader_recovers_complete_records_of_truncated_file() {
+        let contents: String = records().iter().map(Record::to_line).collect();
+        // Cut the file in the middle of the second line record, as if the recording was killed.
+        let cut = contents.find("\nF\t").unwrap() - 3;
+        let reader = RecordReader::new(&contents.as_bytes()[..cut]);
+        let recovered: Vec<Record> = reader.collect::<std::io::Result<_>>().unwrap();
+        assert_eq!(recovered, records()[..2]);
+    }
+
+    #[test]
+    fn test_reader_reports_malformed_line() {
+        let contents = "H\t1\t0\nL\t1\t-\tok\nL\tbad\n";
+        let results: Vec<_> = RecordReader::new(contents.as_bytes()).collect();
+        assert_eq!(results.len(), 3);
+        let err = results[2].as_ref().unwrap_err();
+        assert!(err.to_string().starts_with("record line 3:"));
+    }
+
+    #[test]
+    fn test_writer_writes_header_first() -> std::io::Result<()> {
+        let path = std::env::temp_dir().join(format!("timeln-record-{}", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let mut writer = RecordWriter::create(&path, vec!["timeln".to_string()], true)?;
+        writer.write(&records()[1])?;
+        let contents = std::fs::read_to_string(&path)?;
+        std::fs::remove_file(&path)?;
+
+        let read: Vec<Record> = RecordReader::new(contents.as_bytes()).collect::<Result<_, _>>()?;
+        assert!(matches!(&read[0], Record::Header { argv, .. } if argv == &["timeln"]));
+        assert_eq!(read[1], records()[1]);
+        Ok(())
+    }
+}
@@ -0,0 +1,549 @@
+//! This module generates a single, self-contained HTML report of a timeln run.
+//!
+//! The report contains a summary table, the delta and elapsed time charts rendered as inline SVG,
+//! the slowest lines of the run, and the raw snapshot data as an inlined JSON array so that it can
+//! be reused by other tools. The file does not reference any external resources and can be viewed
+//! offline.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use timeln::plot::{PlotConfig, PlotData};
+//! use timeln::report::{write_report, Report};
+//! use timeln::{RunningStats, SecondsFormat};
+//!
+//! let data = PlotData {
+//!     times: vec![0.1, 0.3],
+//!     deltas: vec![0.1, 0.2],
+//!     patterns: vec![None, None],
+//!     sample: None,
+//! };
+//! let mut stats = RunningStats::default();
+//! stats.push(0.1);
+//! stats.push(0.2);
+//! let report = Report {
+//!     total_lines: 2,
+//!     total_matches: 0,
+//!     total_bytes: 12,
+//!     total_time: Duration::from_millis(300),
+//!     data: &data,
+//!     stats: &stats,
+//!     slowest: &[],
+//!     plot_config: &PlotConfig::default(),
+//! };
+//! write_report("report.html", &report, &SecondsFormat).unwrap();
+//! ```
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::time::Duration;
+
+use crate::formatter::TimeFormat;
+use crate::plot::{render_deltas_svg, render_times_svg, PlotConfig, PlotData};
+use crate::stats::RunningStats;
+use crate::summarizer::format_bytes;
+
+/// Number of slowest lines listed in a report.
+pub const REPORT_SLOWEST_LINES: usize = 10;
+
+const TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>timeln report</title>
+<style>
+body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.8em; text-align: left; }
+th { background: #f0f0f0; }
+td.num { text-align: right; font-family: monospace; }
+.charts svg { max-width: 100%; height: auto; margin-right: 1em; }
+</style>
+</head>
+<body>
+<h1>timeln report</h1>
+<section id="summary">
+<h2>Summary</h2>
+{{summary}}
+</section>
+<section id="charts" class="charts">
+<h2>Charts</h2>
+{{delta_chart}}
+{{elapsed_chart}}
+</section>
+<section id="slowest">
+<h2>Slowest lines and the gaps before them</h2>
+{{slowest}}
+</section>
+<script type="application/json" id="timeln-data">
+{{data}}
+</script>
+</body>
+</html>
+"#;
+
+/// Number of characters kept of the line before each slow line.
+pub const PREVIOUS_TEXT_CHARS: usize = 80;
+
+/// A line that took a long time to arrive, ending a gap, kept for the report and the summary.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SlowLine {
+    pub delta: Duration,
+    pub line_number: usize,
+    pub text: String,
+    /// Time since the start of the run when the line arrived, ending the gap.
+    pub elapsed: Duration,
+    /// The line before the gap, truncated to `PREVIOUS_TEXT_CHARS` characters, or empty for the
+    /// first line.
+    pub previous: String,
+}
+
+/// Keeps the `capacity` lines with the largest deltas seen so far, and the line before each.
+pub struct SlowestLines {
+    capacity: usize,
+    heap: BinaryHeap<Reverse<SlowLine>>,
+    keep_text: bool,
+    text_bytes: usize,
+    /// The latest line recorded, truncated.
+    previous: String,
+}
+
+/// Truncates `text` to `PREVIOUS_TEXT_CHARS` characters, marking the cut with `…`.
+fn truncate(text: &str) -> String {
+    match text.char_indices().nth(PREVIOUS_TEXT_CHARS) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+impl SlowestLines {
+    /// Creates an empty collection holding at most `capacity` lines.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            heap: BinaryHeap::with_capacity(capacity + 1),
+            keep_text: true,
+            text_bytes: 0,
+            previous: String::new(),
+        }
+    }
+
+    /// Records a line that arrived `elapsed` after the start of the run, evicting the fastest one
+    /// kept if the collection is full. The text is only copied if the line makes it into the
+    /// collection, apart from its first characters, kept as the line before the next one.
+    pub fn record(&mut self, line_number: usize, delta: Duration, elapsed: Duration, text: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+        let previous = std::mem::replace(
+            &mut self.previous,
+            if self.keep_text {
+                truncate(text)
+            } else {
+                String::new()
+            },
+        );
+        if self.heap.len() == self.capacity {
+            match self.heap.peek() {
+                Some(Reverse(fastest)) if fastest.delta >= delta => return,
+                _ => {}
+            }
+            if let Some(Reverse(evicted)) = self.heap.pop() {
+                self.text_bytes -= evicted.text.len() + evicted.previous.len();
+            }
+        }
+        let text = if self.keep_text {
+            text.to_string()
+        } else {
+            String::new()
+        };
+        self.text_bytes += text.len() + previous.len();
+        self.heap.push(Reverse(SlowLine {
+            delta,
+            line_number,
+            text,
+            elapsed,
+            previous,
+        }));
+    }
+
+    /// Number of bytes of line text kept.
+    pub fn text_bytes(&self) -> usize {
+        self.text_bytes
+    }
+
+    /// Drops the text of the kept lines and the lines before them, and of the lines recorded from
+    /// now on, keeping only their numbers, deltas and times.
+    pub fn drop_text(&mut self) {
+        self.keep_text = false;
+        self.text_bytes = 0;
+        self.previous = String::new();
+        self.heap = self
+            .heap
+            .drain()
+            .map(|Reverse(line)| {
+                Reverse(SlowLine {
+                    text: String::new(),
+                    previous: String::new(),
+                    ..line
+                })
+            })
+            .collect();
+    }
+
+    /// Returns the kept lines, slowest first.
+    pub fn to_sorted_vec(&self) -> Vec<SlowLine> {
+        let mut lines: Vec<SlowLine> = self.heap.iter().map(|Reverse(l)| l.clone()).collect();
+        lines.sort_by(|a, b| {
+            b.delta
+                .cmp(&a.delta)
+                .then(a.line_number.cmp(&b.line_number))
+        });
+        lines
+    }
+}
+
+/// The data needed to generate a report.
+pub struct Report<'a> {
+    pub total_lines: usize,
+    pub total_matches: usize,
+    /// Number of bytes read, including line endings.
+    pub total_bytes: u64,
+    pub total_time: Duration,
+    /// The plotted points, which may be a sample of the run.
+    pub data: &'a PlotData,
+    /// Statistics over every timed line of the run.
+    pub stats: &'a RunningStats,
+    pub slowest: &'a [SlowLine],
+    pub plot_config: &'a PlotConfig,
+}
+
+/// Escapes the characters that are significant in HTML text and attribute values.
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escapes `text` for a JSON string embedded in a script element, where `<` is escaped too so
+/// that the text cannot close the element.
+pub(crate) fn escape_json(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '<' => escaped.push_str("\\u003c"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Serializes the snapshots as a JSON array of
+/// `{"elapsed": .., "delta": .., "matched": .., "pattern": ..}` objects, with times in seconds
+/// and `pattern` the label of the matched regex pattern (`p0`, `p1`, ... if it has none) or
+/// `null`.
+fn snapshots_json(data: &PlotData, labels: &[String]) -> String {
+    let entries: Vec<String> = (0..data.len())
+        .map(|i| {
+            format!(
+                "{{\"elapsed\":{},\"delta\":{},\"matched\":{},\"pattern\":{}}}",
+                data.times[i],
+                data.deltas[i],
+                data.is_matched(i),
+                data.pattern(i).map_or("null".to_string(), |pattern| {
+                    let label = labels
+                        .get(pattern)
+                        .cloned()
+                        .unwrap_or_else(|| format!("p{}", pattern));
+                    format!("\"{}\"", escape_json(&label))
+                })
+            )
+        })
+        .collect();
+    format!("[{}]", entries.join(","))
+}
+
+/// Substitutes `{{key}}` placeholders in a single pass, so that substituted values are never
+/// themselves scanned for placeholders.
+fn fill_template(template: &str, values: &[(&str, &str)]) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        filled.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        let key = &after[..end];
+        match values.iter().find(|(k, _)| *k == key) {
+            Some((_, value)) => filled.push_str(value),
+            None => filled.push_str(&rest[start..start + end + 4]),
+        }
+        rest = &after[end + 2..];
+    }
+    filled.push_str(rest);
+    filled
+}
+
+fn summary_table(report: &Report, time_format: &dyn TimeFormat) -> String {
+    let stats = report.stats;
+    let rows = [
+        ("Processed lines", report.total_lines.to_string()),
+        ("Matches", report.total_matches.to_string()),
+        (
+            "Processed bytes",
+            format!(
+                "{} ({})",
+                report.total_bytes,
+                format_bytes(report.total_bytes)
+            ),
+        ),
+        (
+            "Total time",
+            time_format.format_duration(&report.total_time),
+        ),
+        ("Timed lines", stats.count().to_string()),
+        (
+            "Mean delta",
+            time_format.format_duration(&Duration::from_secs_f64(stats.mean())),
+        ),
+        (
+            "Max delta",
+            time_format.format_duration(&Duration::from_secs_f64(stats.max().unwrap_or(0.0))),
+        ),
+    ];
+    let rows: Vec<String> = rows
+        .iter()
+        .map(|(name, value)| {
+            format!(
+                "<tr><th>{}</th><td class=\"num\">{}</td></tr>",
+                name,
+                escape_html(value)
+            )
+        })
+        .collect();
+    format!("<table>\n{}\n</table>", rows.join("\n"))
+}
+
+fn slowest_table(slowest: &[SlowLine], time_format: &dyn TimeFormat) -> String {
+    if slowest.is_empty() {
+        return "<p>No lines were timed.</p>".to_string();
+    }
+    let rows: Vec<String> = slowest
+        .iter()
+        .map(|line| {
+            format!(
+                "<tr><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td>{}</td><td>{}</td></tr>",
+                line.line_number,
+                escape_html(&time_format.format_duration(&line.delta)),
+                escape_html(&time_format.format_duration(&line.elapsed)),
+                escape_html(&line.previous),
+                escape_html(&line.text)
+            )
+        })
+        .collect();
+    format!(
+        "<table>\n<tr><th>Line</th><th>Delta</th><th>Ended At</th><th>Before</th><th>Text</th></tr>\n{}\n</table>",
+        rows.join("\n")
+    )
+}
+
+/// Renders the report as a self-contained HTML document.
+pub fn render_report(
+    report: &Report,
+    time_format: &dyn TimeFormat,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let delta_chart = render_deltas_svg(report.data, report.plot_config)?;
+    let elapsed_chart = render_times_svg(report.data, report.plot_config)?;
+    let summary = summary_table(report, time_format);
+    let slowest = slowest_table(report.slowest, time_format);
+    let data = snapshots_json(report.data, &report.plot_config.pattern_labels);
+    Ok(fill_template(
+        TEMPLATE,
+        &[
+            ("summary", &summary),
+            ("delta_chart", &delta_chart),
+            ("elapsed_chart", &elapsed_chart),
+            ("slowest", &slowest),
+            ("data", &data),
+        ],
+    ))
+}
+
+/// Renders the report and writes it to `path`.
+pub fn write_report(
+    path: &str,
+    report: &Report,
+    time_format: &dyn TimeFormat,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let html = render_report(report, time_format)?;
+    std::fs::write(path, html)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatter::SecondsFormat;
+
+    #[test]
+    fn test_slowest_lines_keeps_top_k() {
+        let mut slowest = SlowestLines::new(2);
+        let ms = Duration::from_millis;
+        slowest.record(1, ms(10), ms(10), "a");
+        slowest.record(2, ms(30), ms(40), "b");
+        slowest.record(3, ms(5), ms(45), "c");
+        slowest.record(4, ms(20), ms(65), "d");
+        let lines = slowest.to_sorted_vec();
+        assert_eq!(
+            lines.iter().map(|l| l.line_number).collect::<Vec<_>>(),
+            vec![2, 4]
+        );
+    }
+
+    #[test]
+    fn test_slowest_lines_keep_line_before() {
+        let mut slowest = SlowestLines::new(2);
+        let ms = Duration::from_millis;
+        slowest.record(1, ms(10), ms(10), "start");
+        slowest.record(2, ms(5), ms(15), "fetching");
+        slowest.record(3, ms(900), ms(915), "fetched");
+        slowest.record(4, ms(5), ms(920), &"x".repeat(100));
+        slowest.record(5, ms(500), ms(1420), "done");
+        let lines = slowest.to_sorted_vec();
+        assert_eq!(lines[0].line_number, 3);
+        assert_eq!(lines[0].elapsed, ms(915));
+        assert_eq!(lines[0].previous, "fetching");
+        assert_eq!(lines[0].text, "fetched");
+        assert_eq!(lines[1].line_number, 5);
+        assert_eq!(
+            lines[1].previous,
+            format!("{}…", "x".repeat(PREVIOUS_TEXT_CHARS))
+        );
+        // The first line has no line before it.
+        let mut first = SlowestLines::new(1);
+        first.record(1, ms(10), ms(10), "only");
+        assert_eq!(first.to_sorted_vec()[0].previous, "");
+    }
+
+    #[test]
+    fn test_slowest_lines_drop_text() {
+        let mut slowest = SlowestLines::new(2);
+        let ms = Duration::from_millis;
+        slowest.record(1, ms(10), ms(10), "first");
+        slowest.record(2, ms(30), ms(40), "second");
+        slowest.record(3, ms(20), ms(60), "third");
+        // The text of the kept lines, and of the lines before them.
+        assert_eq!(
+            slowest.text_bytes(),
+            "second".len() + "first".len() + "third".len() + "second".len()
+        );
+        slowest.drop_text();
+        slowest.record(4, ms(40), ms(100), "fourth");
+        assert_eq!(slowest.text_bytes(), 0);
+        let lines = slowest.to_sorted_vec();
+        assert_eq!(
+            lines.iter().map(|l| l.line_number).collect::<Vec<_>>(),
+            vec![4, 2]
+        );
+        assert!(lines
+            .iter()
+            .all(|l| l.text.is_empty() && l.previous.is_empty()));
+    }
+
+    #[test]
+    fn test_render_report_sections_and_data() -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+    {
+        let data = PlotData {
+            times: vec![0.1, 0.3, 1.3],
+            deltas: vec![0.1, 0.2, 1.0],
+            patterns: vec![None, None, Some(0)],
+            sample: None,
+        };
+        let mut stats = RunningStats::default();
+        for delta in &data.deltas {
+            stats.push(*delta);
+        }
+        let mut slowest = SlowestLines::new(REPORT_SLOWEST_LINES);
+        slowest.record(
+            2,
+            Duration::from_millis(200),
+            Duration::from_millis(300),
+            "a < b",
+        );
+        slowest.record(
+            3,
+            Duration::from_secs(1),
+            Duration::from_millis(1300),
+            "<slow> & steady {{data}}",
+        );
+        let slowest = slowest.to_sorted_vec();
+        let report = Report {
+            total_lines: 3,
+            total_matches: 0,
+            total_bytes: 2048,
+            total_time: Duration::from_millis(1300),
+            data: &data,
+            stats: &stats,
+            slowest: &slowest,
+            plot_config: &PlotConfig::default(),
+        };
+
+        let html = render_report(&report, &SecondsFormat)?;
+        for section in [
+            "id=\"summary\"",
+            "id=\"charts\"",
+            "id=\"slowest\"",
+            "id=\"timeln-data\"",
+        ] {
+            assert!(html.contains(section), "missing {}", section);
+        }
+        assert!(html.contains("&lt;slow&gt; &amp; steady {{data}}"));
+        assert!(html.contains("<td>a &lt; b</td><td>&lt;slow&gt;"));
+        assert!(html.contains("2048 (2.0 KiB)"));
+        assert!(!html.contains("src="));
+        assert!(!html.contains("<link"));
+
+        let start = html.find("id=\"timeln-data\">").unwrap() + "id=\"timeln-data\">".len();
+        let end = start + html[start..].find("</script>").unwrap();
+        let data: serde_json::Value = serde_json::from_str(html[start..end].trim())?;
+        let entries = data.as_array().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[2]["delta"].as_f64(), Some(1.0));
+        assert_eq!(entries[1]["elapsed"].as_f64(), Some(0.3));
+        assert_eq!(entries[2]["matched"].as_bool(), Some(true));
+        assert_eq!(entries[2]["pattern"].as_str(), Some("p0"));
+        assert!(entries[0]["pattern"].is_null());
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshots_json_uses_labels() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let data = PlotData {
+            times: vec![0.1, 0.2],
+            deltas: vec![0.1, 0.1],
+            patterns: vec![Some(1), Some(0)],
+            sample: None,
+        };
+        let labels = vec!["</script> \"err\"".to_string(), "warn".to_string()];
+        let json = snapshots_json(&data, &labels);
+        assert!(!json.contains("</script>"));
+        let entries: serde_json::Value = serde_json::from_str(&json)?;
+        assert_eq!(entries[0]["pattern"].as_str(), Some("warn"));
+        assert_eq!(entries[1]["pattern"].as_str(), Some("</script> \"err\""));
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+//! This module compares the durations that lines report about themselves, such as `took 532ms`,
+//! with the deltas timeln measures for them.
+//!
+//! `SelfReported` parses the first capture group of its regex as a duration like `532ms`, `1.5s`
+//! or `2` (seconds), and diffs it with the measured delta of the line. A capture that is not a
+//! duration is skipped and counted as malformed.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use timeln::reported::SelfReported;
+//!
+//! let mut reported = SelfReported::new(r"took (\S+)")?;
+//! let comparison = reported.compare("took 500ms", Duration::from_millis(600)).unwrap();
+//! assert_eq!(comparison.reported, Duration::from_millis(500));
+//! assert!((comparison.difference - 0.1).abs() < 1e-9);
+//! # Ok::<(), regex::Error>(())
+//! ```
+use std::time::Duration;
+
+use regex::Regex;
+
+use crate::formatter::{parse_duration, TimeFormat};
+use crate::stats::RunningStats;
+
+/// A self-reported duration and how it compares with the measured delta.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Comparison {
+    /// The duration reported by the line.
+    pub reported: Duration,
+    /// The measured delta minus the reported duration, in seconds.
+    pub difference: f64,
+}
+
+/// Compares the durations reported by the lines with their measured deltas.
+#[derive(Debug)]
+pub struct SelfReported {
+    regex: Regex,
+    differences: RunningStats,
+    malformed: usize,
+}
+
+impl SelfReported {
+    /// Creates a comparison of the durations captured by the first group of `pattern`, or by the
+    /// whole match if it has no group.
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            regex: Regex::new(pattern)?,
+            differences: RunningStats::default(),
+            malformed: 0,
+        })
+    }
+
+    /// Compares the duration reported by `line`, if it has one, with its measured `delta`.
+    pub fn compare(&mut self, line: &str, delta: Duration) -> Option<Comparison> {
+        let captures = self.regex.captures(line)?;
+        let capture = captures.get(1).or_else(|| captures.get(0))?;
+        let Ok(reported) = parse_duration(capture.as_str().trim()) else {
+            self.malformed += 1;
+            return None;
+        };
+        let difference = delta.as_secs_f64() - reported.as_secs_f64();
+        self.differences.push(difference);
+        Some(Comparison {
+            reported,
+            difference,
+        })
+    }
+
+    /// Statistics of the differences, in seconds.
+    pub fn differences(&self) -> &RunningStats {
+        &self.differences
+    }
+
+    /// Number of captures that were not durations.
+    pub fn malformed(&self) -> usize {
+        self.malformed
+    }
+
+    /// Largest difference in either direction, in seconds.
+    pub fn max_abs_difference(&self) -> Option<f64> {
+        let min = self.differences.min()?;
+        let max = self.differences.max()?;
+        Some(min.abs().max(max.abs()))
+    }
+}
+
+/// Formats a signed difference in seconds as a duration with a `+` or `-` sign.
+pub fn format_difference(difference: f64, time_format: &dyn TimeFormat) -> String {
+    let sign = if difference < 0.0 { '-' } else { '+' };
+    let magnitude = Duration::from_secs_f64(difference.abs());
+    format!("{}{}", sign, time_format.format_duration(&magnitude))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatter::SecondsFormat;
+
+    #[test]
+    fn test_compares_reported_durations() {
+        let mut reported = SelfReported::new(r"took (\S+)").unwrap();
+        let slower = reported
+            .compare("step took 1.5s", Duration::from_secs(2))
+            .unwrap();
+        assert_eq!(slower.reported, Duration::from_millis(1500));
+        assert_eq!(slower.difference, 0.5);
+        let faster = reported
+            .compare("took 250ms", Duration::from_millis(200))
+            .unwrap();
+        assert!((faster.difference + 0.05).abs() < 1e-9);
+        assert!(reported.compare("no timing", Duration::ZERO).is_none());
+
+        assert_eq!(reported.differences().count(), 2);
+        assert_eq!(reported.max_abs_difference(), Some(0.5));
+        assert_eq!(reported.malformed(), 0);
+    }
+
+    #[test]
+    fn test_skips_malformed_durations() {
+        let mut reported = SelfReported::new(r"took (\S+)").unwrap();
+        assert!(reported.compare("took a while", Duration::ZERO).is_none());
+        assert!(reported.compare("took 5parsecs", Duration::ZERO).is_none());
+        assert!(reported.compare("took 3", Duration::from_secs(3)).is_some());
+        assert_eq!(reported.malformed(), 2);
+        assert_eq!(reported.differences().count(), 1);
+    }
+
+    #[test]
+    fn test_format_difference() {
+        assert_eq!(format_difference(0.25, &SecondsFormat), "+0.25 s");
+        assert_eq!(format_difference(-1.5, &SecondsFormat), "-1.50 s");
+        assert_eq!(format_difference(0.0, &SecondsFormat), "+0.00 s");
+    }
+}
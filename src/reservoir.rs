@@ -0,0 +1,209 @@
+//! This module provides a fixed-size uniform sample of a stream of unknown length.
+//!
+//! `Reservoir` implements reservoir sampling (Vitter's algorithm R): the first `capacity` items
+//! are kept, and every later item replaces a random kept item with a probability chosen so that
+//! each item of the stream is equally likely to be in the sample. Each kept item remembers its
+//! position in the stream, so that a plot of the sample keeps a meaningful x-axis.
+//!
+//! # Example
+//!
+//! ```
+//! use timeln::reservoir::Reservoir;
+//!
+//! let mut reservoir = Reservoir::new(100);
+//! for i in 0..10_000 {
+//!     reservoir.push(i);
+//! }
+//! assert_eq!(reservoir.seen(), 10_000);
+//! let sample = reservoir.take();
+//! assert_eq!(sample.len(), 100);
+//! ```
+
+/// Seed used by `Reservoir::new`, so that runs over the same input keep the same sample.
+const DEFAULT_SEED: u64 = 0x2545_F491_4F6C_DD1D;
+
+/// A uniform random sample of at most `capacity` items of a stream.
+pub struct Reservoir<T> {
+    capacity: usize,
+    seen: usize,
+    items: Vec<(usize, T)>,
+    rng: SplitMix64,
+}
+
+impl<T> Reservoir<T> {
+    /// Creates an empty reservoir keeping at most `capacity` items.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_seed(capacity, DEFAULT_SEED)
+    }
+
+    /// Creates an empty reservoir whose random choices are determined by `seed`.
+    pub fn with_seed(capacity: usize, seed: u64) -> Self {
+        Self {
+            capacity,
+            seen: 0,
+            items: Vec::new(),
+            rng: SplitMix64(seed),
+        }
+    }
+
+    /// Offers the next item of the stream to the sample. Returns the item left out of the
+    /// sample, either `item` itself or the kept item it replaced, if any.
+    pub fn push(&mut self, item: T) -> Option<T> {
+        let index = self.seen;
+        self.seen += 1;
+        if self.items.len() < self.capacity {
+            self.items.push((index, item));
+            return None;
+        }
+        let slot = (self.rng.next_u64() % self.seen as u64) as usize;
+        if slot < self.capacity {
+            Some(std::mem::replace(&mut self.items[slot], (index, item)).1)
+        } else {
+            Some(item)
+        }
+    }
+
+    /// Keeps at most `capacity` items from now on, dropping random kept items if there are more.
+    /// A random subset of a uniform sample is still uniform, so the sample stays uniform.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.items.len() > capacity {
+            let slot = (self.rng.next_u64() % self.items.len() as u64) as usize;
+            self.items.swap_remove(slot);
+        }
+    }
+
+    /// Number of items offered so far.
+    pub fn seen(&self) -> usize {
+        self.seen
+    }
+
+    /// Number of items kept.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Whether no items are kept.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// The kept items, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.items.iter().map(|(_, item)| item)
+    }
+
+    /// Removes and returns the kept items with their positions in the stream, in stream order,
+    /// leaving the reservoir empty.
+    pub fn take(&mut self) -> Vec<(usize, T)> {
+        let mut items = std::mem::take(&mut self.items);
+        items.sort_by_key(|(index, _)| *index);
+        self.seen = 0;
+        items
+    }
+}
+
+/// A small, fast pseudo-random generator; sampling does not need cryptographic quality.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reservoir_keeps_everything_under_capacity() {
+        let mut reservoir = Reservoir::new(10);
+        for i in 0..5 {
+            reservoir.push(i * 10);
+        }
+        assert_eq!(reservoir.len(), 5);
+        assert_eq!(
+            reservoir.take(),
+            vec![(0, 0), (1, 10), (2, 20), (3, 30), (4, 40)]
+        );
+        assert_eq!(reservoir.len(), 0);
+        assert_eq!(reservoir.seen(), 0);
+    }
+
+    #[test]
+    fn test_reservoir_bounded_and_indexed() {
+        let mut reservoir = Reservoir::new(100);
+        for i in 0..100_000 {
+            reservoir.push(i * 2);
+        }
+        assert_eq!(reservoir.len(), 100);
+        assert_eq!(reservoir.seen(), 100_000);
+        let sample = reservoir.take();
+        assert!(sample.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(sample.iter().all(|(index, item)| *item == index * 2));
+    }
+
+    #[test]
+    fn test_reservoir_set_capacity() {
+        let mut reservoir = Reservoir::new(100);
+        for i in 0..50 {
+            reservoir.push(i);
+        }
+        reservoir.set_capacity(10);
+        assert_eq!(reservoir.len(), 10);
+        for i in 50..1000 {
+            reservoir.push(i);
+        }
+        assert_eq!(reservoir.len(), 10);
+        assert_eq!(reservoir.seen(), 1000);
+        assert_eq!(reservoir.iter().count(), 10);
+        let sample = reservoir.take();
+        assert!(sample.iter().all(|(index, item)| index == item));
+    }
+
+    #[test]
+    fn test_reservoir_push_returns_left_out_item() {
+        let mut reservoir = Reservoir::new(10);
+        let mut left_out = Vec::new();
+        for i in 0..1000 {
+            left_out.extend(reservoir.push(i));
+        }
+        assert_eq!(left_out.len(), 990);
+        let mut all: Vec<usize> = left_out
+            .into_iter()
+            .chain(reservoir.iter().copied())
+            .collect();
+        all.sort_unstable();
+        assert_eq!(all, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_reservoir_is_uniform() {
+        // Each item is kept with probability 100 / 1000, so each tenth of the stream should
+        // contribute about 10 items per sample.
+        let trials = 500;
+        let mut per_tenth = [0usize; 10];
+        for seed in 0..trials {
+            let mut reservoir = Reservoir::with_seed(100, seed);
+            for i in 0..1000 {
+                reservoir.push(i);
+            }
+            for (index, _) in reservoir.take() {
+                per_tenth[index / 100] += 1;
+            }
+        }
+        let expected = 10 * trials as usize;
+        for count in per_tenth {
+            assert!(
+                count.abs_diff(expected) < expected / 10,
+                "{:?} is not uniform",
+                per_tenth
+            );
+        }
+    }
+}
@@ -0,0 +1,294 @@
+//! This module redraws the summary of a run on the whole terminal with `--watch-summary`, like
+//! `watch` run on timeln itself, while the lines are read without being printed:
+//!
+//! ```text
+//! [Total Time: 12.30 s, Lines: 412, ...]
+//!
+//! Deltas
+//! ≤0.01 s │########            │ 40%
+//! ≤0.10 s │############        │ 60%
+//!
+//! Slowest
+//! [Gap 1: Duration: 2.10 s, Ended At: 8.40 s, Line: 97, Before: "..", After: ".."]
+//!
+//! Last deltas: ▁▁▂▁█▃▁▁
+//! ```
+//!
+//! The frame is the summary so far, as worded by the summarizer, the histogram of the deltas
+//! between the thresholds of `--cdf`, the slowest lines as listed by `--gaps` and a sparkline of
+//! the latest deltas. It is redrawn every `SCREEN_INTERVAL` on the alternate screen of the
+//! terminal, with the cursor hidden. When the run ends, before its summary, the terminal is given
+//! back as it was, with its cursor.
+//!
+//! The frame is composed by a pure function, `compose`, and the escape sequences of each step by
+//! `enter`, `draw` and `leave`.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use timeln::screen::sparkline;
+//!
+//! let deltas = [1, 2, 8, 1].map(Duration::from_secs);
+//! assert_eq!(sparkline(&deltas), "▁▂█▁");
+//! ```
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::dashboard::fit;
+use crate::formatter::TimeFormat;
+use crate::summarizer::format_percent;
+
+/// Time between two redraws of the screen.
+pub const SCREEN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of slowest lines listed on the screen.
+pub const SCREEN_SLOWEST: usize = 5;
+
+/// Characters of the bars of the histogram, between their separators.
+pub const HISTOGRAM_WIDTH: usize = 20;
+
+/// The levels of the sparkline, lowest first.
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Switches to the alternate screen and hides the cursor.
+const ENTER: &str = "\x1b[?1049h\x1b[?25l";
+
+/// Shows the cursor and switches back to the main screen, as it was before `ENTER`.
+const LEAVE: &str = "\x1b[?25h\x1b[?1049l";
+
+/// What the screen shows.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScreenStats {
+    /// The summary of the run so far.
+    pub summary: String,
+    /// The thresholds of the histogram of the deltas, each with the fraction of the deltas above
+    /// the previous threshold and at most it, smallest first.
+    pub histogram: Vec<(Duration, f64)>,
+    /// The summaries of the slowest lines, slowest first.
+    pub slowest: Vec<String>,
+    /// The latest deltas, oldest first.
+    pub recent_deltas: Vec<Duration>,
+}
+
+/// Draws `deltas` as a sparkline, one character each, from the smallest to the largest of them.
+pub fn sparkline(deltas: &[Duration]) -> String {
+    let (Some(min), Some(max)) = (deltas.iter().min(), deltas.iter().max()) else {
+        return String::new();
+    };
+    let range = (*max - *min).as_secs_f64();
+    deltas
+        .iter()
+        .map(|delta| {
+            let level = match range > 0.0 {
+                true => (*delta - *min).as_secs_f64() / range * (SPARKS.len() - 1) as f64,
+                false => 0.0,
+            };
+            SPARKS[level.round() as usize]
+        })
+        .collect()
+}
+
+/// The rows of the histogram of the deltas, as `≤0.10 s │######      │ 30%`, with the thresholds
+/// aligned.
+pub fn histogram_rows(histogram: &[(Duration, f64)], time_format: &dyn TimeFormat) -> Vec<String> {
+    let labels: Vec<String> = histogram
+        .iter()
+        .map(|(threshold, _)| format!("≤{}", time_format.format_duration(threshold)))
+        .collect();
+    let label_width = labels
+        .iter()
+        .map(|label| label.chars().count())
+        .max()
+        .unwrap_or(0);
+    labels
+        .iter()
+        .zip(histogram)
+        .map(|(label, (_, fraction))| {
+            let filled = (fraction.clamp(0.0, 1.0) * HISTOGRAM_WIDTH as f64).round() as usize;
+            format!(
+                "{:>width$} │{}{}│ {}",
+                label,
+                "#".repeat(filled),
+                " ".repeat(HISTOGRAM_WIDTH - filled),
+                format_percent(*fraction),
+                width = label_width
+            )
+        })
+        .collect()
+}
+
+/// The frame showing `stats` on a terminal of `width` columns and `height` rows, as its rows
+/// separated by newlines, each cut to the width and at most `height` of them.
+pub fn compose(
+    width: usize,
+    height: usize,
+    stats: &ScreenStats,
+    time_format: &dyn TimeFormat,
+) -> String {
+    let mut rows = vec![stats.summary.clone(), String::new(), "Deltas".to_string()];
+    match stats.histogram.is_empty() {
+        true => rows.push("(no data)".to_string()),
+        false => rows.extend(histogram_rows(&stats.histogram, time_format)),
+    }
+    rows.extend([String::new(), "Slowest".to_string()]);
+    match stats.slowest.is_empty() {
+        true => rows.push("(no data)".to_string()),
+        false => rows.extend(stats.slowest.iter().cloned()),
+    }
+    rows.extend([
+        String::new(),
+        format!("Last deltas: {}", sparkline(&stats.recent_deltas)),
+    ]);
+    rows.into_iter()
+        .take(height)
+        .map(|row| fit(row, width))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Switches to the screen the frames are drawn on.
+pub fn enter() -> &'static str {
+    ENTER
+}
+
+/// Draws `frame` over the previous one, from the top left of the screen, clearing what it does not
+/// cover.
+pub fn draw(frame: &str) -> String {
+    format!("\x1b[H{}\x1b[J", frame.replace('\n', "\x1b[K\n"))
+}
+
+/// Gives the terminal back as it was before `enter`.
+pub fn leave() -> &'static str {
+    LEAVE
+}
+
+/// Redraws the summary on the whole terminal from a background thread, until it is cleared.
+#[derive(Debug, Default)]
+pub struct SummaryScreen {
+    stop: Arc<AtomicBool>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl SummaryScreen {
+    /// Switches `out` to the screen and draws it every `interval` with the stats returned by
+    /// `scrape` on a terminal of `width` columns and `height` rows, until `clear` is called or
+    /// `scrape` returns `None`. A frame is skipped while `out` is in use.
+    pub fn start(
+        &self,
+        out: Arc<Mutex<Box<dyn Write + Send>>>,
+        (width, height): (usize, usize),
+        interval: Duration,
+        time_format: Arc<Box<dyn TimeFormat>>,
+        scrape: impl Fn() -> Option<ScreenStats> + Send + 'static,
+    ) {
+        let stop = Arc::clone(&self.stop);
+        if let Ok(mut out) = out.lock() {
+            // A frame that cannot be drawn is skipped: a broken output fails the summary written
+            // once the screen is left.
+            let _ = write!(out, "{}", enter());
+            let _ = out.flush();
+        }
+        let handle = thread::spawn(move || {
+            while !stop.load(Ordering::Acquire) {
+                let Some(stats) = scrape() else {
+                    break;
+                };
+                // Waiting for the output could deadlock with the finalizer clearing the screen.
+                if let Ok(mut out) = out.try_lock() {
+                    let frame = compose(width, height, &stats, &**time_format);
+                    let _ = write!(out, "{}", draw(&frame));
+                    let _ = out.flush();
+                }
+                thread::park_timeout(interval);
+            }
+        });
+        if let Ok(mut thread) = self.thread.lock() {
+            *thread = Some(handle);
+        }
+    }
+
+    /// Stops drawing, waiting for the thread drawing the screen, and gives the terminal back on
+    /// `out`, with its cursor. Does nothing if the screen was not started or is already cleared.
+    pub fn clear(&self, out: &mut dyn Write) {
+        self.stop.store(true, Ordering::Release);
+        let handle = match self.thread.lock() {
+            Ok(mut thread) => thread.take(),
+            Err(_) => None,
+        };
+        if let Some(handle) = handle {
+            handle.thread().unpark();
+            let _ = handle.join();
+            let _ = write!(out, "{}", leave());
+            let _ = out.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatter::SecondsFormat;
+
+    fn stats() -> ScreenStats {
+        ScreenStats {
+            summary: "[Total Time: 12.30 s, Lines: 412]".to_string(),
+            histogram: vec![
+                (Duration::from_millis(10), 0.4),
+                (Duration::from_millis(100), 0.55),
+                (Duration::from_secs(10), 0.05),
+            ],
+            slowest: vec!["[Gap 1: Duration: 2.10 s]".to_string()],
+            recent_deltas: [10, 10, 20, 10, 80].map(Duration::from_millis).to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_compose() {
+        let frame = compose(60, 24, &stats(), &SecondsFormat);
+        let expected = [
+            "[Total Time: 12.30 s, Lines: 412]",
+            "",
+            "Deltas",
+            " ≤0.01 s │########            │ 40%",
+            " ≤0.10 s │###########         │ 55%",
+            "≤10.00 s │#                   │ 5%",
+            "",
+            "Slowest",
+            "[Gap 1: Duration: 2.10 s]",
+            "",
+            "Last deltas: ▁▁▂▁█",
+        ]
+        .join("\n");
+        assert_eq!(frame, expected);
+    }
+
+    #[test]
+    fn test_compose_fits_terminal() {
+        let frame = compose(10, 4, &stats(), &SecondsFormat);
+        assert_eq!(frame, "[Total Tim\n\nDeltas\n ≤0.01 s │");
+        let empty = compose(60, 24, &ScreenStats::default(), &SecondsFormat);
+        assert_eq!(
+            empty,
+            "\n\nDeltas\n(no data)\n\nSlowest\n(no data)\n\nLast deltas: "
+        );
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[]), "");
+        assert_eq!(sparkline(&[Duration::from_secs(1); 3]), "▁▁▁");
+        let deltas: Vec<Duration> = (0..8).map(Duration::from_secs).collect();
+        assert_eq!(sparkline(&deltas), "▁▂▃▄▅▆▇█");
+    }
+
+    #[test]
+    fn test_draw_and_leave() {
+        assert_eq!(draw("a\nb"), "\x1b[Ha\x1b[K\nb\x1b[J");
+        assert!(enter().contains("\x1b[?1049h"));
+        assert!(leave().contains("\x1b[?25h"));
+    }
+}
@@ -0,0 +1,187 @@
+//! This module installs the handler that finishes a run when timeln is asked to stop.
+//!
+//! On Unix the handler runs on SIGINT (Ctrl-C), SIGTERM (sent by process managers) and SIGHUP
+//! (sent when the terminal is closed). Once the first signal arrives the default dispositions are
+//! restored, so that a second signal kills timeln even if finishing the run is slow.
+//!
+//! On Windows the handler runs on Ctrl-C, and also on Ctrl-Break and when the console window is
+//! closed or the user logs off or the system shuts down. For the latter Windows ends the process
+//! a few seconds after the handler is called, so the handler is given `CONSOLE_CLOSE_BUDGET` to
+//! finish and must skip whatever it cannot complete in time.
+//!
+//! On Unix `install_pause` also lets SIGUSR1 pause timing and SIGUSR2 resume it. The signal
+//! handler itself only writes the signal to a pipe, since little is safe to do in a signal
+//! handler; the pause handler is then called from a thread reading that pipe.
+//!
+//! Manual test on Windows: run `ping -t localhost | timeln -p` in a new console window, then
+//! close the window after a few lines, or press Ctrl-Break; `deltas.svg` and `times.svg` should be
+//! written in the working directory.
+//!
+//! # Example
+//!
+//! ```no_run
+//! timeln::signal::install(|budget| {
+//!     eprintln!("stopping, with {:?} left", budget);
+//!     std::process::exit(0);
+//! })?;
+//! # Ok::<(), timeln::TimelnError>(())
+//! ```
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::TimelnError;
+
+/// Time the handler may take when Windows is about to end the process, which it does about five
+/// seconds after the console is closed.
+pub const CONSOLE_CLOSE_BUDGET: Duration = Duration::from_millis(4000);
+
+/// Runs `handler` when timeln is interrupted or terminated. Its argument is `None`, or the time
+/// left before the process is killed regardless. The handler can only be installed once per
+/// process.
+pub fn install(
+    handler: impl Fn(Option<Duration>) + Send + Sync + 'static,
+) -> Result<(), TimelnError> {
+    let handler = Arc::new(handler);
+    let on_signal = Arc::clone(&handler);
+    ctrlc::set_handler(move || {
+        restore_default_dispositions();
+        on_signal(None);
+    })
+    .map_err(|err| TimelnError::BoxError(Box::new(err)))?;
+    #[cfg(windows)]
+    windows::install(handler)?;
+    Ok(())
+}
+
+/// Runs `handler` with `true` when SIGUSR1 is received and `false` when SIGUSR2 is received.
+/// The handler can only be installed once per process.
+#[cfg(unix)]
+pub fn install_pause(handler: impl Fn(bool) + Send + 'static) -> Result<(), TimelnError> {
+    pause::install(handler)
+}
+
+/// Pause and resume requests sent with SIGUSR1 and SIGUSR2.
+#[cfg(unix)]
+mod pause {
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::unix::io::FromRawFd;
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::thread;
+
+    use crate::error::TimelnError;
+
+    /// Write end of the pipe to the thread calling the pause handler.
+    static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+    extern "C" fn on_signal(signal: libc::c_int) {
+        let paused = u8::from(signal == libc::SIGUSR1);
+        // SAFETY: write is async-signal-safe and `paused` outlives the call. If the pipe is full
+        // the request is dropped, which only happens if the handler thread is stuck.
+        unsafe {
+            libc::write(
+                PIPE.load(Ordering::Relaxed),
+                &paused as *const u8 as *const libc::c_void,
+                1,
+            );
+        }
+    }
+
+    pub(super) fn install(handler: impl Fn(bool) + Send + 'static) -> Result<(), TimelnError> {
+        let mut fds = [0; 2];
+        // SAFETY: `fds` has room for the two descriptors written by pipe.
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        // SAFETY: the read end was just created and is owned by nothing else.
+        let mut requests = unsafe { File::from_raw_fd(fds[0]) };
+        if PIPE
+            .compare_exchange(-1, fds[1], Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            // SAFETY: the write end was just created and is not shared.
+            unsafe { libc::close(fds[1]) };
+            return Err(std::io::Error::other("pause handler already installed").into());
+        }
+        thread::spawn(move || {
+            let mut paused = [0u8];
+            while requests.read_exact(&mut paused).is_ok() {
+                handler(paused[0] == 1);
+            }
+        });
+        for signal in [libc::SIGUSR1, libc::SIGUSR2] {
+            // SAFETY: the action is fully initialized, and `on_signal` only calls
+            // async-signal-safe functions. SA_RESTART keeps blocking reads of the input going.
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = on_signal as *const () as libc::sighandler_t;
+                action.sa_flags = libc::SA_RESTART;
+                libc::sigemptyset(&mut action.sa_mask);
+                if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+                    return Err(std::io::Error::last_os_error().into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Makes the next interrupting signal terminate the process immediately.
+#[cfg(unix)]
+fn restore_default_dispositions() {
+    for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+        // SAFETY: resetting a signal to its default disposition has no preconditions.
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn restore_default_dispositions() {}
+
+/// Console control handling beyond the Ctrl-C handled by `ctrlc`.
+#[cfg(windows)]
+mod windows {
+    use std::sync::{Arc, OnceLock};
+    use std::time::Duration;
+
+    use windows_sys::core::BOOL;
+    use windows_sys::Win32::System::Console::{
+        SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_CLOSE_EVENT, CTRL_LOGOFF_EVENT,
+        CTRL_SHUTDOWN_EVENT,
+    };
+
+    use super::CONSOLE_CLOSE_BUDGET;
+    use crate::error::TimelnError;
+
+    type Handler = Arc<dyn Fn(Option<Duration>) + Send + Sync>;
+
+    static HANDLER: OnceLock<Handler> = OnceLock::new();
+
+    /// Called by Windows on its own thread. Handlers are called from the most recently
+    /// registered, so this runs before the one registered by `ctrlc`, and passes Ctrl-C on to it.
+    unsafe extern "system" fn console_handler(event: u32) -> BOOL {
+        match event {
+            CTRL_BREAK_EVENT | CTRL_CLOSE_EVENT | CTRL_LOGOFF_EVENT | CTRL_SHUTDOWN_EVENT => {
+                // The process is ended when this returns, so the run must be finished here.
+                if let Some(handler) = HANDLER.get() {
+                    handler(Some(CONSOLE_CLOSE_BUDGET));
+                }
+                1
+            }
+            _ => 0,
+        }
+    }
+
+    pub(super) fn install(handler: Handler) -> Result<(), TimelnError> {
+        HANDLER.set(handler).map_err(|_| {
+            TimelnError::Io(std::io::Error::other("console handler already installed"))
+        })?;
+        // SAFETY: `console_handler` is a valid handler routine for the life of the process.
+        if unsafe { SetConsoleCtrlHandler(Some(console_handler), 1) } == 0 {
+            return Err(TimelnError::Io(std::io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+}
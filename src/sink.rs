@@ -0,0 +1,394 @@
+//! This module defines where the `TimeSnapshot` of each timed line goes.
+//!
+//! Snapshots are only needed by consumers that look at the whole run: the SVG, terminal and HTML
+//! plots keep them in memory until the end (or a uniform sample of them, for long runs), while `--plot-data` alone can write each point to its
+//! file as it arrives. When no consumer is configured the snapshots are dropped immediately, so
+//! that a long-running stream uses constant memory.
+//!
+//! The text of the lines is not part of their snapshots. An in-memory sink can be asked to keep the
+//! text of the lines it samples alongside them, keyed by line number; it is off by default since
+//! lines can be arbitrarily long.
+//!
+//! The in-memory sink can also be given a memory cap covering everything a run retains: its own
+//! snapshots and, as reported by the caller, other data such as the text of the slowest lines.
+//! Once the cap is exceeded the sink degrades once, with a warning: it shrinks its sample to fit
+//! the cap and tells the caller to drop what it retains.
+//!
+//! # Example
+//!
+//! ```
+//! use timeln::sink::{SnapshotSink, DEFAULT_PLOT_MAX_SAMPLES};
+//! use timeln::TimeSnapshot;
+//!
+//! let mut sink = SnapshotSink::in_memory(DEFAULT_PLOT_MAX_SAMPLES);
+//! sink.push(TimeSnapshot::default())?;
+//! let (snapshots, total) = sink.take();
+//! assert_eq!((snapshots.len(), total), (1, 1));
+//! # Ok::<(), std::io::Error>(())
+//! ```
+use std::collections::HashMap;
+
+use crate::plot::{PlotDataWriter, PlotX};
+use crate::reservoir::Reservoir;
+use crate::timeln::TimeSnapshot;
+
+/// Default largest number of snapshots kept in memory for plotting.
+pub const DEFAULT_PLOT_MAX_SAMPLES: usize = 100_000;
+
+/// Memory retained per snapshot held in memory, with its position in the stream.
+pub const SNAPSHOT_BYTES: usize = std::mem::size_of::<(usize, TimeSnapshot)>();
+
+/// The destination of the snapshots collected by the main loop.
+pub enum SnapshotSink {
+    /// Drops every snapshot.
+    Null,
+    /// Keeps a uniform sample of the snapshots until the end of the run.
+    InMemory {
+        reservoir: Reservoir<TimeSnapshot>,
+        /// The text of the sampled lines, if it is kept.
+        text: Option<LineText>,
+        /// Cap on the memory retained by the run, in bytes.
+        max_memory: Option<usize>,
+        /// Whether the cap was exceeded and the sample shrunk to fit it.
+        degraded: bool,
+    },
+    /// Writes each snapshot to a plot data file as it arrives.
+    Streaming {
+        writer: PlotDataWriter,
+        x: PlotX,
+        index: usize,
+    },
+}
+
+impl SnapshotSink {
+    /// Creates a sink keeping at most `max_samples` snapshots in memory.
+    pub fn in_memory(max_samples: usize) -> Self {
+        SnapshotSink::InMemory {
+            reservoir: Reservoir::new(max_samples),
+            text: None,
+            max_memory: None,
+            degraded: false,
+        }
+    }
+
+    /// Caps the memory retained by the run at `max_bytes`, if this sink keeps snapshots in memory.
+    pub fn max_memory(mut self, max_bytes: Option<usize>) -> Self {
+        if let SnapshotSink::InMemory { max_memory, .. } = &mut self {
+            *max_memory = max_bytes;
+        }
+        self
+    }
+
+    /// Sets whether the text of the sampled lines is kept, if this sink keeps snapshots in memory.
+    pub fn keep_text(mut self, keep: bool) -> Self {
+        if let SnapshotSink::InMemory { text, .. } = &mut self {
+            *text = keep.then(LineText::default);
+        }
+        self
+    }
+
+    /// Creates a sink streaming to the plot data file `filename`, with an x column of kind `x`.
+    pub fn streaming(filename: &str, x: PlotX) -> std::io::Result<Self> {
+        Ok(SnapshotSink::Streaming {
+            writer: PlotDataWriter::create(filename, x)?,
+            x,
+            index: 0,
+        })
+    }
+
+    /// Hands a snapshot to the sink.
+    pub fn push(&mut self, snapshot: TimeSnapshot) -> std::io::Result<()> {
+        self.push_snapshot(snapshot, None)
+    }
+
+    /// Hands a snapshot to the sink, with the text of its line in case the sink keeps it.
+    pub fn push_line(&mut self, snapshot: TimeSnapshot, line: &str) -> std::io::Result<()> {
+        self.push_snapshot(snapshot, Some(line))
+    }
+
+    fn push_snapshot(&mut self, snapshot: TimeSnapshot, line: Option<&str>) -> std::io::Result<()> {
+        match self {
+            SnapshotSink::Null => Ok(()),
+            SnapshotSink::InMemory {
+                reservoir, text, ..
+            } => {
+                let left_out = reservoir.push(snapshot);
+                if let (Some(text), Some(line)) = (text, line) {
+                    text.insert(snapshot.line_no, line);
+                    if let Some(left_out) = left_out {
+                        text.remove(left_out.line_no);
+                    }
+                }
+                Ok(())
+            }
+            SnapshotSink::Streaming { writer, x, index } => {
+                let x = match x {
+                    PlotX::Index => *index as f64,
+                    PlotX::Time => snapshot.elapsed.as_secs_f64(),
+                };
+                *index += 1;
+                writer.write_point(x, snapshot.delta.as_secs_f64())
+            }
+        }
+    }
+
+    /// Number of snapshots held in memory.
+    pub fn len(&self) -> usize {
+        match self {
+            SnapshotSink::InMemory { reservoir, .. } => reservoir.len(),
+            _ => 0,
+        }
+    }
+
+    /// Whether no snapshots are held in memory.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes and returns the text kept for the snapshots held in memory, by line number.
+    pub fn take_text(&mut self) -> HashMap<usize, String> {
+        match self {
+            SnapshotSink::InMemory {
+                text: Some(text), ..
+            } => std::mem::take(text).lines,
+            _ => HashMap::new(),
+        }
+    }
+
+    /// Removes and returns the snapshots held in memory, each with its position among all the
+    /// snapshots pushed, together with the number of snapshots pushed.
+    pub fn take(&mut self) -> (Vec<(usize, TimeSnapshot)>, usize) {
+        match self {
+            SnapshotSink::InMemory { reservoir, .. } => {
+                let total = reservoir.seen();
+                (reservoir.take(), total)
+            }
+            _ => (Vec::new(), 0),
+        }
+    }
+
+    /// Whether this sink keeps snapshots in memory under a cap that was not exceeded yet, so that
+    /// `check_memory` has anything to check.
+    pub fn limits_memory(&self) -> bool {
+        matches!(
+            self,
+            SnapshotSink::InMemory {
+                max_memory: Some(_),
+                degraded: false,
+                ..
+            }
+        )
+    }
+
+    /// Checks the memory retained by the run, that is the snapshots and line text held in memory
+    /// and `other_bytes` retained by the caller, against the memory cap.
+    ///
+    /// The first time the cap is exceeded, warns on stderr, shrinks the sample to fit the cap on
+    /// its own, drops the line text and returns `true`: the caller should then drop the data it
+    /// retains, and keep none from now on. Returns `false` otherwise.
+    pub fn check_memory(&mut self, other_bytes: usize) -> bool {
+        let SnapshotSink::InMemory {
+            reservoir,
+            text,
+            max_memory: Some(max_memory),
+            degraded,
+        } = self
+        else {
+            return false;
+        };
+        let text_bytes = text.as_ref().map_or(0, |text| text.bytes);
+        if *degraded || reservoir.len() * SNAPSHOT_BYTES + text_bytes + other_bytes <= *max_memory {
+            return false;
+        }
+        let capacity = (*max_memory / SNAPSHOT_BYTES).max(1).min(reservoir.len());
+        reservoir.set_capacity(capacity);
+        *text = None;
+        *degraded = true;
+        eprintln!(
+            "timeln: retained data exceeds --max-memory of {} bytes, plotting a sample of at most {} \
+             lines and dropping the text of the slowest lines",
+            max_memory, capacity
+        );
+        true
+    }
+
+    /// Flushes any snapshots not yet written to their file.
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        match self {
+            SnapshotSink::Streaming { writer, .. } => writer.finish(),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The text of the sampled lines, by line number.
+#[derive(Default)]
+pub struct LineText {
+    lines: HashMap<usize, String>,
+    /// Total length of the kept text.
+    bytes: usize,
+}
+
+impl LineText {
+    fn insert(&mut self, line_no: usize, line: &str) {
+        self.bytes += line.len();
+        if let Some(old) = self.lines.insert(line_no, line.to_string()) {
+            self.bytes -= old.len();
+        }
+    }
+
+    fn remove(&mut self, line_no: usize) {
+        if let Some(old) = self.lines.remove(&line_no) {
+            self.bytes -= old.len();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn snapshot(millis: u64) -> TimeSnapshot {
+        TimeSnapshot {
+            delta: Duration::from_millis(millis),
+            elapsed: Duration::from_millis(millis * 2),
+            pattern: None,
+            line_no: millis as usize + 1,
+            bytes: 0,
+        }
+    }
+
+    #[test]
+    fn test_null_sink_retains_nothing() -> std::io::Result<()> {
+        let mut sink = SnapshotSink::Null;
+        for i in 0..1000 {
+            sink.push(snapshot(i))?;
+        }
+        assert_eq!(sink.len(), 0);
+        assert!(sink.take().0.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_memory_sink_take() -> std::io::Result<()> {
+        let mut sink = SnapshotSink::in_memory(DEFAULT_PLOT_MAX_SAMPLES);
+        sink.push(snapshot(1))?;
+        sink.push(snapshot(2))?;
+        assert_eq!(sink.len(), 2);
+        let (snapshots, total) = sink.take();
+        assert_eq!(total, 2);
+        assert_eq!(snapshots[1].0, 1);
+        assert_eq!(snapshots[1].1.delta, Duration::from_millis(2));
+        assert_eq!(sink.len(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_memory_sink_is_bounded() -> std::io::Result<()> {
+        let mut sink = SnapshotSink::in_memory(10);
+        for i in 0..1000 {
+            sink.push(snapshot(i))?;
+        }
+        assert_eq!(sink.len(), 10);
+        let (snapshots, total) = sink.take();
+        assert_eq!(total, 1000);
+        assert!(snapshots
+            .iter()
+            .all(|(index, snapshot)| snapshot.delta == Duration::from_millis(*index as u64)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_memory_sink_keeps_text_of_sampled_lines() -> std::io::Result<()> {
+        let mut sink = SnapshotSink::in_memory(10).keep_text(true);
+        for i in 0..1000 {
+            sink.push_line(snapshot(i), &format!("line {}", i + 1))?;
+        }
+        let text = sink.take_text();
+        let (snapshots, _) = sink.take();
+        assert_eq!(text.len(), 10);
+        for (_, snapshot) in snapshots {
+            assert_eq!(
+                text[&snapshot.line_no],
+                format!("line {}", snapshot.line_no)
+            );
+        }
+
+        let mut without = SnapshotSink::in_memory(10);
+        without.push_line(snapshot(1), "line")?;
+        assert!(without.take_text().is_empty());
+        assert!(!without.limits_memory());
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_memory_sink_drops_text_over_memory_cap() -> std::io::Result<()> {
+        let mut sink = SnapshotSink::in_memory(DEFAULT_PLOT_MAX_SAMPLES)
+            .keep_text(true)
+            .max_memory(Some(2 * SNAPSHOT_BYTES + 100));
+        sink.push_line(snapshot(0), "short")?;
+        assert!(!sink.check_memory(0));
+        sink.push_line(snapshot(1), &"long".repeat(100))?;
+        assert!(sink.check_memory(0));
+        sink.push_line(snapshot(2), "short")?;
+        assert!(sink.take_text().is_empty());
+        assert_eq!(sink.take().1, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_memory_sink_degrades_over_memory_cap() -> std::io::Result<()> {
+        let max_memory = 100 * SNAPSHOT_BYTES;
+        let mut sink =
+            SnapshotSink::in_memory(DEFAULT_PLOT_MAX_SAMPLES).max_memory(Some(max_memory));
+        for i in 0..90 {
+            sink.push(snapshot(i))?;
+            assert!(!sink.check_memory(0));
+        }
+        // The caller's retained data tips the run over the cap.
+        assert!(sink.limits_memory());
+        assert!(sink.check_memory(20 * SNAPSHOT_BYTES));
+        assert!(!sink.limits_memory());
+        assert_eq!(sink.len(), 90);
+        for i in 90..10_000 {
+            sink.push(snapshot(i))?;
+            assert!(!sink.check_memory(0));
+        }
+        assert_eq!(sink.len(), 90);
+        assert_eq!(sink.take().1, 10_000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_memory_sink_shrinks_to_memory_cap() -> std::io::Result<()> {
+        let mut sink = SnapshotSink::in_memory(DEFAULT_PLOT_MAX_SAMPLES);
+        for i in 0..1000 {
+            sink.push(snapshot(i))?;
+        }
+        let mut sink = sink.max_memory(Some(10 * SNAPSHOT_BYTES));
+        assert!(sink.check_memory(0));
+        assert_eq!(sink.len(), 10);
+        assert!(!SnapshotSink::Null.max_memory(Some(0)).check_memory(1));
+        assert!(!SnapshotSink::Null.max_memory(Some(0)).limits_memory());
+        Ok(())
+    }
+
+    #[test]
+    fn test_streaming_sink_writes_points() -> std::io::Result<()> {
+        let filename = "test_streaming_sink.csv";
+        let mut sink = SnapshotSink::streaming(filename, PlotX::Time)?;
+        sink.push(snapshot(100))?;
+        sink.push(snapshot(250))?;
+        sink.finish()?;
+        assert_eq!(sink.len(), 0);
+
+        let contents = std::fs::read_to_string(filename)?;
+        std::fs::remove_file(filename)?;
+        assert_eq!(
+            contents.lines().collect::<Vec<_>>(),
+            vec!["elapsed_seconds,delta_seconds", "0.2,0.1", "0.5,0.25"]
+        );
+        Ok(())
+    }
+}
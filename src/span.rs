@@ -0,0 +1,167 @@
+//! This module measures spans of work bracketed by a start line and an end line, such as
+//! `Compiling x` and `Finished x`.
+//!
+//! Each line matching the end pattern closes the most recently started span that is still open,
+//! so nested spans are paired like brackets: in `start A, start B, end, end` the first end closes
+//! B and the second closes A. An end with no open span is ignored, and spans still open at the end
+//! of the run are incomplete. A line matching both patterns closes a span, then starts a new one.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use timeln::span::SpanTracker;
+//!
+//! let mut spans = SpanTracker::new("^Compiling", "^Finished")?;
+//! assert!(spans.line("Compiling timeln", Duration::from_secs(1)).is_none());
+//! let span = spans.line("Finished dev profile", Duration::from_secs(4)).unwrap();
+//! assert_eq!(span.duration, Duration::from_secs(3));
+//! assert_eq!(span.start_text, "Compiling timeln");
+//! # Ok::<(), regex::Error>(())
+//! ```
+use std::time::Duration;
+
+use regex::Regex;
+
+use crate::stats::RunningStats;
+
+/// A span from a start line to the end line that closed it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    /// The start line, trimmed.
+    pub start_text: String,
+    /// Time from the start line to the end line.
+    pub duration: Duration,
+}
+
+/// A span that was started but not yet closed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenSpan {
+    /// The start line, trimmed.
+    pub start_text: String,
+    /// Arrival of the start line since the start of the run.
+    pub started: Duration,
+}
+
+/// Pairs start and end lines into spans and accumulates their statistics.
+#[derive(Debug)]
+pub struct SpanTracker {
+    start: Regex,
+    end: Regex,
+    open: Vec<OpenSpan>,
+    stats: RunningStats,
+}
+
+impl SpanTracker {
+    /// Creates a tracker of the spans from lines matching `start` to lines matching `end`.
+    pub fn new(start: &str, end: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            start: Regex::new(start)?,
+            end: Regex::new(end)?,
+            open: Vec::new(),
+            stats: RunningStats::default(),
+        })
+    }
+
+    /// Processes a line that arrived `at` since the start of the run, returning the span it
+    /// closes, if any.
+    pub fn line(&mut self, line: &str, at: Duration) -> Option<Span> {
+        let closed = if self.end.is_match(line) {
+            self.open.pop().map(|open| Span {
+                duration: at.saturating_sub(open.started),
+                start_text: open.start_text,
+            })
+        } else {
+            None
+        };
+        if let Some(span) = &closed {
+            self.stats.push(span.duration.as_secs_f64());
+        }
+        if self.start.is_match(line) {
+            self.open.push(OpenSpan {
+                start_text: line.trim().to_string(),
+                started: at,
+            });
+        }
+        closed
+    }
+
+    /// Statistics of the durations of the closed spans, in seconds.
+    pub fn stats(&self) -> &RunningStats {
+        &self.stats
+    }
+
+    /// The spans not closed yet, oldest first.
+    pub fn open(&self) -> &[OpenSpan] {
+        &self.open
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds `lines`, one per second, and returns the closed spans as (start text, seconds).
+    fn spans(tracker: &mut SpanTracker, lines: &[&str]) -> Vec<(String, u64)> {
+        lines
+            .iter()
+            .enumerate()
+            .filter_map(|(i, line)| tracker.line(line, Duration::from_secs(i as u64)))
+            .map(|span| (span.start_text, span.duration.as_secs()))
+            .collect()
+    }
+
+    #[test]
+    fn test_sequential_spans() {
+        let mut tracker = SpanTracker::new("^start", "^end").unwrap();
+        let closed = spans(
+            &mut tracker,
+            &["start a", "work", "end", "idle", "start b", "end"],
+        );
+        assert_eq!(
+            closed,
+            vec![("start a".to_string(), 2), ("start b".to_string(), 1)]
+        );
+        assert_eq!(tracker.stats().count(), 2);
+        assert_eq!(tracker.stats().max(), Some(2.0));
+        assert!(tracker.open().is_empty());
+    }
+
+    #[test]
+    fn test_nested_spans_pair_with_latest_start() {
+        let mut tracker = SpanTracker::new("^start", "^end").unwrap();
+        let closed = spans(&mut tracker, &["start outer", "start inner", "end", "end"]);
+        assert_eq!(
+            closed,
+            vec![
+                ("start inner".to_string(), 1),
+                ("start outer".to_string(), 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unmatched_end_and_incomplete_start() {
+        let mut tracker = SpanTracker::new("^start", "^end").unwrap();
+        let closed = spans(&mut tracker, &["end", "start a", "start b", "end"]);
+        assert_eq!(closed, vec![("start b".to_string(), 1)]);
+        assert_eq!(
+            tracker.open(),
+            &[OpenSpan {
+                start_text: "start a".to_string(),
+                started: Duration::from_secs(1),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_line_matching_both_closes_then_starts() {
+        let mut tracker = SpanTracker::new("step", "step").unwrap();
+        let closed = spans(&mut tracker, &["step 1", "step 2", "step 3"]);
+        assert_eq!(
+            closed,
+            vec![("step 1".to_string(), 1), ("step 2".to_string(), 1)]
+        );
+        assert_eq!(tracker.open().len(), 1);
+    }
+}
@@ -0,0 +1,321 @@
+//! This module stores runs and their snapshots in an SQLite database, for analysis across many
+//! runs. It needs the `sqlite` cargo feature.
+//!
+//! Each run adds a row to the `runs` table:
+//!
+//! | Column | Contents |
+//! |--------|----------|
+//! | `id` | identifier of the run |
+//! | `started_at` | start of the run, in milliseconds since the Unix epoch |
+//! | `argv` | command line, as a JSON array |
+//! | `total_lines`, `total_matches`, `total_bytes`, `total_time_s` | totals, once the run finished |
+//!
+//! and a row per timed line to the `snapshots` table, with columns `run_id`, `line_no`,
+//! `elapsed_s`, `delta_s`, `matched` (0 or 1) and `pattern`, the label of the matched regex
+//! pattern or `NULL`.
+//!
+//! The snapshots are inserted in transactions of `BATCH_ROWS` rows, so that a line costs little
+//! more than appending to a buffer, and the last batch is committed with the totals of the run.
+//! The schema version is kept in the `user_version` pragma, and the databases written by older
+//! versions of timeln are migrated when opened.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use timeln::sqlite::{RunTotals, SqliteStore};
+//! use timeln::TimeSnapshot;
+//!
+//! let mut store = SqliteStore::open("runs.db", vec!["timeln".to_string()])?;
+//! store.push(&TimeSnapshot::default(), None)?;
+//! store.finish(&RunTotals {
+//!     lines: 1,
+//!     matches: 0,
+//!     bytes: 6,
+//!     total_time: Duration::from_millis(20),
+//! })?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+use std::time::Duration;
+
+pub use store::SqliteStore;
+
+/// Number of snapshots inserted in each transaction.
+pub const BATCH_ROWS: usize = 500;
+
+/// Totals of a run, stored with it once it finished.
+#[derive(Debug, Clone, Default)]
+pub struct RunTotals {
+    pub lines: u64,
+    pub matches: u64,
+    pub bytes: u64,
+    pub total_time: Duration,
+}
+
+#[cfg(feature = "sqlite")]
+mod store {
+    use std::io;
+    use std::path::Path;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use rusqlite::{params, Connection};
+
+    use super::{RunTotals, BATCH_ROWS};
+    use crate::report::escape_json;
+    use crate::timeln::TimeSnapshot;
+
+    /// Statements bringing the schema from each version to the next; the schema version of a
+    /// database is the number of them applied to it.
+    const MIGRATIONS: &[&str] = &["CREATE TABLE runs (
+            id INTEGER PRIMARY KEY,
+            started_at INTEGER NOT NULL,
+            argv TEXT NOT NULL,
+            total_lines INTEGER,
+            total_matches INTEGER,
+            total_bytes INTEGER,
+            total_time_s REAL
+        );
+        CREATE TABLE snapshots (
+            run_id INTEGER NOT NULL REFERENCES runs (id),
+            line_no INTEGER NOT NULL,
+            elapsed_s REAL NOT NULL,
+            delta_s REAL NOT NULL,
+            matched INTEGER NOT NULL,
+            pattern TEXT
+        );
+        CREATE INDEX snapshots_run_id ON snapshots (run_id);"];
+
+    /// Wraps an SQLite error as an I/O error, since the database is an output file.
+    fn io_error(err: rusqlite::Error) -> io::Error {
+        io::Error::other(err)
+    }
+
+    /// Brings the schema of the database to the latest version.
+    fn migrate(connection: &mut Connection) -> rusqlite::Result<()> {
+        let version: usize = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > MIGRATIONS.len() {
+            return Err(rusqlite::Error::InvalidParameterName(format!(
+                "schema version {} is newer than this version of timeln supports ({})",
+                version,
+                MIGRATIONS.len()
+            )));
+        }
+        for (applied, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let transaction = connection.transaction()?;
+            transaction.execute_batch(migration)?;
+            transaction.pragma_update(None, "user_version", applied + 1)?;
+            transaction.commit()?;
+        }
+        Ok(())
+    }
+
+    /// A run being written to an SQLite database.
+    #[derive(Debug)]
+    pub struct SqliteStore {
+        connection: Connection,
+        run_id: i64,
+        /// Snapshots inserted in the open transaction.
+        pending: usize,
+    }
+
+    impl SqliteStore {
+        /// Opens or creates the database at `path`, migrating its schema if needed, and adds a
+        /// run with command line `argv` starting now.
+        pub fn open(path: impl AsRef<Path>, argv: Vec<String>) -> io::Result<Self> {
+            let mut connection = Connection::open(path).map_err(io_error)?;
+            migrate(&mut connection).map_err(io_error)?;
+            let started_at = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as i64;
+            let argv: Vec<String> = argv
+                .iter()
+                .map(|arg| format!("\"{}\"", escape_json(arg)))
+                .collect();
+            connection
+                .execute(
+                    "INSERT INTO runs (started_at, argv) VALUES (?1, ?2)",
+                    params![started_at, format!("[{}]", argv.join(","))],
+                )
+                .map_err(io_error)?;
+            let run_id = connection.last_insert_rowid();
+            connection.execute_batch("BEGIN").map_err(io_error)?;
+            Ok(Self {
+                connection,
+                run_id,
+                pending: 0,
+            })
+        }
+
+        /// Identifier of the run in the `runs` table.
+        pub fn run_id(&self) -> i64 {
+            self.run_id
+        }
+
+        /// Adds a snapshot of the run, which matched the regex pattern labelled `pattern` if any.
+        pub fn push(&mut self, snapshot: &TimeSnapshot, pattern: Option<&str>) -> io::Result<()> {
+            self.connection
+                .prepare_cached(
+                    "INSERT INTO snapshots (run_id, line_no, elapsed_s, delta_s, matched, pattern)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                )
+                .and_then(|mut insert| {
+                    insert.execute(params![
+                        self.run_id,
+                        snapshot.line_no as i64,
+                        snapshot.elapsed.as_secs_f64(),
+                        snapshot.delta.as_secs_f64(),
+                        snapshot.pattern.is_some(),
+                        pattern,
+                    ])
+                })
+                .map_err(io_error)?;
+            self.pending += 1;
+            if self.pending >= BATCH_ROWS {
+                self.connection
+                    .execute_batch("COMMIT; BEGIN")
+                    .map_err(io_error)?;
+                self.pending = 0;
+            }
+            Ok(())
+        }
+
+        /// Stores the totals of the run and commits the snapshots not committed yet.
+        pub fn finish(&mut self, totals: &RunTotals) -> io::Result<()> {
+            self.connection
+                .execute(
+                    "UPDATE runs SET total_lines = ?1, total_matches = ?2, total_bytes = ?3,
+                     total_time_s = ?4 WHERE id = ?5",
+                    params![
+                        totals.lines as i64,
+                        totals.matches as i64,
+                        totals.bytes as i64,
+                        totals.total_time.as_secs_f64(),
+                        self.run_id,
+                    ],
+                )
+                .map_err(io_error)?;
+            self.connection.execute_batch("COMMIT").map_err(io_error)?;
+            self.pending = 0;
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::time::Duration;
+
+        fn snapshot(line_no: usize, pattern: Option<usize>) -> TimeSnapshot {
+            TimeSnapshot {
+                delta: Duration::from_millis(10),
+                elapsed: Duration::from_millis(10 * line_no as u64),
+                pattern,
+                line_no,
+                bytes: 5,
+            }
+        }
+
+        fn write_run(path: &Path, lines: usize) -> i64 {
+            let mut store = SqliteStore::open(path, vec!["timeln".to_string()]).unwrap();
+            for line_no in 1..=lines {
+                let pattern = (line_no % 2 == 0).then_some(0);
+                let label = pattern.map(|_| "even");
+                store.push(&snapshot(line_no, pattern), label).unwrap();
+            }
+            store
+                .finish(&RunTotals {
+                    lines: lines as u64,
+                    matches: (lines / 2) as u64,
+                    bytes: 5 * lines as u64,
+                    total_time: Duration::from_secs(1),
+                })
+                .unwrap();
+            store.run_id()
+        }
+
+        #[test]
+        fn test_runs_accumulate() {
+            let path =
+                std::env::temp_dir().join(format!("timeln-sqlite-{}.db", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            let first = write_run(&path, BATCH_ROWS + 10);
+            let second = write_run(&path, 3);
+            assert_ne!(first, second);
+
+            let connection = Connection::open(&path).unwrap();
+            let count = |sql: &str, run_id: i64| -> i64 {
+                connection
+                    .query_row(sql, [run_id], |row| row.get(0))
+                    .unwrap()
+            };
+            let snapshots = "SELECT COUNT(*) FROM snapshots WHERE run_id = ?1";
+            assert_eq!(count(snapshots, first), (BATCH_ROWS + 10) as i64);
+            assert_eq!(count(snapshots, second), 3);
+            let matched = "SELECT COUNT(*) FROM snapshots WHERE run_id = ?1 AND pattern = 'even'";
+            assert_eq!(count(matched, second), 1);
+            let totals = "SELECT total_lines FROM runs WHERE id = ?1";
+            assert_eq!(count(totals, first), (BATCH_ROWS + 10) as i64);
+            let argv: String = connection
+                .query_row("SELECT argv FROM runs WHERE id = ?1", [second], |row| {
+                    row.get(0)
+                })
+                .unwrap();
+            assert_eq!(argv, "[\"timeln\"]");
+            drop(connection);
+            std::fs::remove_file(&path).unwrap();
+        }
+
+        #[test]
+        fn test_newer_schema_is_refused() {
+            let path =
+                std::env::temp_dir().join(format!("timeln-sqlite-newer-{}.db", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            let connection = Connection::open(&path).unwrap();
+            connection
+                .pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+                .unwrap();
+            drop(connection);
+            let err = SqliteStore::open(&path, vec![]).unwrap_err();
+            std::fs::remove_file(&path).unwrap();
+            assert!(err.to_string().contains("newer"), "{}", err);
+        }
+    }
+}
+
+/// Stand-in for builds without the `sqlite` feature, which cannot open a database.
+#[cfg(not(feature = "sqlite"))]
+mod store {
+    use std::convert::Infallible;
+    use std::io;
+    use std::path::Path;
+
+    use super::RunTotals;
+    use crate::timeln::TimeSnapshot;
+
+    /// A run being written to an SQLite database; never created without the `sqlite` feature.
+    #[derive(Debug)]
+    pub struct SqliteStore(Infallible);
+
+    impl SqliteStore {
+        /// Fails, as timeln was built without the `sqlite` feature.
+        pub fn open(_: impl AsRef<Path>, _: Vec<String>) -> io::Result<Self> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "timeln was built without the sqlite feature",
+            ))
+        }
+
+        pub fn run_id(&self) -> i64 {
+            match self.0 {}
+        }
+
+        pub fn push(&mut self, _: &TimeSnapshot, _: Option<&str>) -> io::Result<()> {
+            match self.0 {}
+        }
+
+        pub fn finish(&mut self, _: &RunTotals) -> io::Result<()> {
+            match self.0 {}
+        }
+    }
+}
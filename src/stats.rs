@@ -0,0 +1,721 @@
+//! This module contains the statistics computed over the collected time deltas, shared by the
+//! plots and the summaries.
+//!
+//! `RunningStats` is updated once per timed line and never retains the deltas themselves, so that
+//! summaries of unbounded streams use constant memory. Its quantiles are approximated by a
+//! `QuantileSketch`; `percentile` and `percentiles` compute exact values over retained data.
+//!
+//! # Example
+//!
+//! ```
+//! use timeln::stats::RunningStats;
+//!
+//! let mut stats = RunningStats::default();
+//! for delta in [0.1, 0.2, 0.3] {
+//!     stats.push(delta);
+//! }
+//! assert_eq!(stats.mean(), 0.2);
+//! ```
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Percentiles drawn as reference lines on the delta plot.
+pub const REFERENCE_PERCENTILES: [f64; 3] = [50.0, 95.0, 99.0];
+
+/// Returns the `p`-th percentile (0 to 100) of `sorted` using the nearest-rank method, or `None`
+/// if `sorted` is empty. `sorted` must be sorted in ascending order.
+pub fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (p.clamp(0.0, 100.0) / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.saturating_sub(1)])
+}
+
+/// Returns the percentiles `ps` of `values`, which need not be sorted. Returns an empty vector if
+/// `values` is empty.
+pub fn percentiles(values: &[f64], ps: &[f64]) -> Vec<f64> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    ps.iter().filter_map(|p| percentile(&sorted, *p)).collect()
+}
+
+/// Relative accuracy of the quantiles returned by `QuantileSketch`.
+pub const SKETCH_RELATIVE_ACCURACY: f64 = 0.01;
+
+/// An approximate quantile sketch over non-negative values with bounded relative error.
+///
+/// Values are counted in logarithmically sized buckets, so that any quantile is returned within
+/// `SKETCH_RELATIVE_ACCURACY` of a value of the right rank, and memory grows with the logarithm
+/// of the range of the values rather than with their number.
+#[derive(Debug, Clone)]
+pub struct QuantileSketch {
+    gamma: f64,
+    buckets: BTreeMap<i32, u64>,
+    zeros: u64,
+    count: u64,
+}
+
+impl Default for QuantileSketch {
+    fn default() -> Self {
+        Self {
+            gamma: (1.0 + SKETCH_RELATIVE_ACCURACY) / (1.0 - SKETCH_RELATIVE_ACCURACY),
+            buckets: BTreeMap::new(),
+            zeros: 0,
+            count: 0,
+        }
+    }
+}
+
+impl QuantileSketch {
+    /// Adds a value. Values that are not positive are counted as zero.
+    pub fn push(&mut self, value: f64) {
+        self.count += 1;
+        if value > 0.0 {
+            let key = value.ln() / self.gamma.ln();
+            *self.buckets.entry(key.ceil() as i32).or_insert(0) += 1;
+        } else {
+            self.zeros += 1;
+        }
+    }
+
+    /// Returns the approximate `q`-th quantile (0 to 1), or `None` if no values were added.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = (q.clamp(0.0, 1.0) * (self.count - 1) as f64) as u64;
+        if rank < self.zeros {
+            return Some(0.0);
+        }
+        let mut seen = self.zeros;
+        for (key, count) in &self.buckets {
+            seen += count;
+            if seen > rank {
+                return Some(2.0 * self.gamma.powi(*key) / (self.gamma + 1.0));
+            }
+        }
+        None
+    }
+
+    /// Returns the approximate fraction (0 to 1) of the values at most `value`, or `None` if no
+    /// values were added. Values in the bucket of `value` are counted, so that values up to
+    /// `SKETCH_RELATIVE_ACCURACY` above it may be too.
+    pub fn fraction_at_most(&self, value: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        if value < 0.0 {
+            return Some(0.0);
+        }
+        let mut at_most = self.zeros;
+        if value > 0.0 {
+            let key = (value.ln() / self.gamma.ln()).ceil() as i32;
+            at_most += self
+                .buckets
+                .range(..=key)
+                .map(|(_, count)| count)
+                .sum::<u64>();
+        }
+        Some(at_most as f64 / self.count as f64)
+    }
+}
+
+/// Largest number of thresholds chosen for the cumulative distribution of the deltas.
+pub const MAX_CDF_POINTS: usize = 5;
+
+/// Statistics of a stream of values, updated one value at a time.
+#[derive(Debug, Clone, Default)]
+pub struct RunningStats {
+    count: u64,
+    sum: f64,
+    mean: f64,
+    m2: f64,
+    min: Option<f64>,
+    max: Option<f64>,
+    sketch: QuantileSketch,
+}
+
+impl RunningStats {
+    /// Adds a value, updating the mean and variance with Welford's algorithm.
+    pub fn push(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+        self.min = Some(self.min.map_or(value, |min| min.min(value)));
+        self.max = Some(self.max.map_or(value, |max| max.max(value)));
+        self.sketch.push(value);
+    }
+
+    /// Number of values added.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Sum of the values added.
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// Mean of the values added, or 0 if there are none.
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Sample variance of the values added, or 0 if there are fewer than two.
+    pub fn variance(&self) -> f64 {
+        if self.count > 1 {
+            self.m2 / (self.count - 1) as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// Sample standard deviation of the values added.
+    pub fn std_dev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    /// Smallest value added.
+    pub fn min(&self) -> Option<f64> {
+        self.min
+    }
+
+    /// Largest value added.
+    pub fn max(&self) -> Option<f64> {
+        self.max
+    }
+
+    /// Approximate `p`-th percentile (0 to 100) of the values added.
+    pub fn percentile(&self, p: f64) -> Option<f64> {
+        self.sketch.quantile(p / 100.0)
+    }
+
+    /// Approximate fraction (0 to 1) of the values added that are at most `value`.
+    pub fn fraction_at_most(&self, value: f64) -> Option<f64> {
+        self.sketch.fraction_at_most(value)
+    }
+
+    /// Thresholds for the cumulative distribution of the values added: the powers of ten from the
+    /// first at or above the smallest value to the first at or above the largest, at most
+    /// `MAX_CDF_POINTS` of them, keeping the largest. There are none if no positive value was
+    /// added.
+    pub fn cdf_points(&self) -> Vec<f64> {
+        let max = match self.max {
+            Some(max) if max > 0.0 => max,
+            _ => return Vec::new(),
+        };
+        // The epsilon keeps exact powers of ten from rounding up to the next one.
+        let exponent = |value: f64| (value.log10() - 1e-9).ceil() as i32;
+        let last = exponent(max);
+        let first = self
+            .min
+            .filter(|min| *min > 0.0)
+            .map_or(i32::MIN, exponent)
+            .max(last - MAX_CDF_POINTS as i32 + 1);
+        (first..=last)
+            .map(|exponent| 10f64.powi(exponent))
+            .collect()
+    }
+}
+
+/// Weight of the latest delta in the moving average of the deltas, unless given.
+pub const DEFAULT_EWMA_ALPHA: f64 = 0.1;
+
+/// Exponentially weighted moving average of a stream of durations, which follows their trend
+/// while smoothing out the noise. Each new duration weighs `alpha` (0 to 1) in the average; the
+/// first one starts it.
+///
+/// The average is kept in nanoseconds, so that an `alpha` of 1 gives back each duration exactly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ewma {
+    alpha: f64,
+    nanos: Option<f64>,
+}
+
+impl Ewma {
+    pub fn new(alpha: f64) -> Self {
+        Self { alpha, nanos: None }
+    }
+
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    /// Adds `value` to the average, and returns the new average.
+    pub fn push(&mut self, value: Duration) -> Duration {
+        let value = value.as_nanos() as f64;
+        let nanos = match self.nanos {
+            Some(average) => self.alpha * value + (1.0 - self.alpha) * average,
+            None => value,
+        };
+        self.nanos = Some(nanos);
+        Duration::from_nanos(nanos.round() as u64)
+    }
+
+    /// The average of the durations added, or `None` if there are none.
+    pub fn value(&self) -> Option<Duration> {
+        self.nanos
+            .map(|nanos| Duration::from_nanos(nanos.round() as u64))
+    }
+}
+
+/// Relative difference from the moving average within which a delta is steady, unless given.
+pub const DEFAULT_TREND_TOLERANCE: f64 = 0.1;
+
+/// How a delta compares with the moving average of the deltas before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trend {
+    Slower,
+    Steady,
+    Faster,
+}
+
+/// Classifies `delta` against `average`: steady within `tolerance` (such as 0.1 for 10%) of it,
+/// bounds included, and otherwise slower or faster.
+pub fn trend(delta: Duration, average: Duration, tolerance: f64) -> Trend {
+    let delta = delta.as_secs_f64();
+    let average = average.as_secs_f64();
+    if delta > average * (1.0 + tolerance) {
+        Trend::Slower
+    } else if delta < average * (1.0 - tolerance) {
+        Trend::Faster
+    } else {
+        Trend::Steady
+    }
+}
+
+/// Length of the sliding window over which the recent rate of lines is measured, unless given.
+pub const DEFAULT_RATE_WINDOW: Duration = Duration::from_secs(10);
+
+/// Names a window of time by its length, as `10s` or `500ms`, such as in the `rate_10s` field of
+/// the events.
+pub fn window_label(window: &Duration) -> String {
+    if window.subsec_nanos() == 0 {
+        format!("{}s", window.as_secs())
+    } else {
+        format!("{}ms", window.as_millis())
+    }
+}
+
+/// Names a threshold of time by its length in the largest unit it is at least one of, as `1s`,
+/// `10ms`, `100us` or `2.5ms`, so that the thresholds of a fast run still tell apart.
+pub fn threshold_label(threshold: &Duration) -> String {
+    let secs = threshold.as_secs_f64();
+    let (value, unit) = if secs >= 1.0 {
+        (secs, "s")
+    } else if secs >= 1e-3 {
+        (secs * 1e3, "ms")
+    } else if secs >= 1e-6 {
+        (secs * 1e6, "us")
+    } else {
+        (secs * 1e9, "ns")
+    };
+    let value = format!("{:.3}", value);
+    format!(
+        "{}{}",
+        value.trim_end_matches('0').trim_end_matches('.'),
+        unit
+    )
+}
+
+/// Counts the lines that arrived within a sliding window of time ending now, for a rate that
+/// follows the bursts of a stream rather than averaging them out over the whole run.
+///
+/// The arrival of each line in the window is kept, oldest first, and dropped once it falls out of
+/// the window, so that each line costs constant amortized time. It can be shared between threads.
+#[derive(Debug)]
+pub struct SlidingWindow {
+    window: Duration,
+    arrivals: Mutex<VecDeque<Instant>>,
+}
+
+impl SlidingWindow {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            arrivals: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Adds a line arriving `at`, no earlier than the previous one, and returns the lines in the
+    /// window ending then, this one included.
+    pub fn push(&self, at: Instant) -> usize {
+        let Ok(mut arrivals) = self.arrivals.lock() else {
+            return 0;
+        };
+        arrivals.push_back(at);
+        self.evict(&mut arrivals, at)
+    }
+
+    /// The lines that arrived in the window ending `now`: less than its length before it.
+    pub fn count_at(&self, now: Instant) -> usize {
+        match self.arrivals.lock() {
+            Ok(mut arrivals) => self.evict(&mut arrivals, now),
+            Err(_) => 0,
+        }
+    }
+
+    /// Arrival of the oldest line still in the window, as of the latest line or count.
+    pub fn oldest(&self) -> Option<Instant> {
+        self.arrivals.lock().ok()?.front().copied()
+    }
+
+    /// Lines per second in the window ending `now`.
+    pub fn rate_at(&self, now: Instant) -> f64 {
+        self.count_at(now) as f64 / self.window.as_secs_f64()
+    }
+
+    /// Drops the arrivals that fell out of the window ending `now`, and returns those left.
+    fn evict(&self, arrivals: &mut VecDeque<Instant>, now: Instant) -> usize {
+        while arrivals
+            .front()
+            .is_some_and(|at| now.saturating_duration_since(*at) >= self.window)
+        {
+            arrivals.pop_front();
+        }
+        arrivals.len()
+    }
+}
+
+/// Lines timed before outliers are flagged, unless given.
+pub const DEFAULT_OUTLIER_WARMUP: u64 = 10;
+
+/// Whether `value` is an outlier of the values summarized by `stats`: more than `sigma` sample
+/// standard deviations above their mean. Nothing is flagged until `warmup` values, and at least
+/// two, have been added, so that the first values are not judged against too few others. When
+/// the values so far are all equal, any larger value is an outlier.
+pub fn is_outlier(stats: &RunningStats, value: f64, sigma: f64, warmup: u64) -> bool {
+    stats.count() >= warmup.max(2) && value > stats.mean() + sigma * stats.std_dev()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats_of(values: &[f64]) -> RunningStats {
+        let mut stats = RunningStats::default();
+        for value in values {
+            stats.push(*value);
+        }
+        stats
+    }
+
+    #[test]
+    fn test_ewma() {
+        let mut ewma = Ewma::new(0.5);
+        assert_eq!(ewma.value(), None);
+        assert_eq!(
+            ewma.push(Duration::from_millis(100)),
+            Duration::from_millis(100)
+        );
+        assert_eq!(
+            ewma.push(Duration::from_millis(200)),
+            Duration::from_millis(150)
+        );
+        assert_eq!(
+            ewma.push(Duration::from_millis(50)),
+            Duration::from_millis(100)
+        );
+        assert_eq!(ewma.value(), Some(Duration::from_millis(100)));
+
+        let mut ewma = Ewma::new(DEFAULT_EWMA_ALPHA);
+        ewma.push(Duration::from_secs(1));
+        // 0.1 * 2 s + 0.9 * 1 s
+        assert_eq!(
+            ewma.push(Duration::from_secs(2)),
+            Duration::from_millis(1100)
+        );
+    }
+
+    #[test]
+    fn test_ewma_alpha_one_is_raw_delta() {
+        let mut ewma = Ewma::new(1.0);
+        for nanos in [420_000_001, 7, 3_600_000_000_123, 0, 999_999_999] {
+            let delta = Duration::from_nanos(nanos);
+            assert_eq!(ewma.push(delta), delta);
+        }
+    }
+
+    #[test]
+    fn test_fraction_at_most() {
+        let mut stats = RunningStats::default();
+        assert_eq!(stats.fraction_at_most(1.0), None);
+        assert!(stats.cdf_points().is_empty());
+        let deltas = [(0.005, 620), (0.05, 290), (0.5, 83), (2.0, 7)];
+        for (delta, count) in deltas {
+            for _ in 0..count {
+                stats.push(delta);
+            }
+        }
+        assert_eq!(stats.cdf_points(), [0.01, 0.1, 1.0, 10.0]);
+        let fractions: Vec<f64> = stats
+            .cdf_points()
+            .iter()
+            .filter_map(|point| stats.fraction_at_most(*point))
+            .collect();
+        assert_eq!(fractions, [0.62, 0.91, 0.993, 1.0]);
+        // Values equal to the threshold are counted, and not those well below or above.
+        assert_eq!(stats.fraction_at_most(0.005), Some(0.62));
+        assert_eq!(stats.fraction_at_most(0.004), Some(0.0));
+        assert_eq!(stats.fraction_at_most(-1.0), Some(0.0));
+    }
+
+    #[test]
+    fn test_cdf_points_are_capped() {
+        let mut stats = RunningStats::default();
+        for delta in [0.0, 0.000_001, 100.0] {
+            stats.push(delta);
+        }
+        assert_eq!(
+            stats.cdf_points(),
+            [1.0, 10.0, 100.0, 1000.0, 10000.0].map(|p| p / 100.0)
+        );
+        let mut zeros = RunningStats::default();
+        zeros.push(0.0);
+        assert!(zeros.cdf_points().is_empty());
+        assert_eq!(zeros.fraction_at_most(0.0), Some(1.0));
+    }
+
+    #[test]
+    fn test_trend_around_tolerance() {
+        let average = Duration::from_millis(500);
+        let ms = Duration::from_millis;
+        assert_eq!(trend(ms(500), average, 0.1), Trend::Steady);
+        // The bounds of the tolerance are steady.
+        assert_eq!(trend(ms(550), average, 0.1), Trend::Steady);
+        assert_eq!(trend(ms(450), average, 0.1), Trend::Steady);
+        assert_eq!(trend(ms(551), average, 0.1), Trend::Slower);
+        assert_eq!(trend(ms(449), average, 0.1), Trend::Faster);
+        // Without tolerance only an equal delta is steady.
+        assert_eq!(trend(ms(500), average, 0.0), Trend::Steady);
+        assert_eq!(
+            trend(Duration::from_nanos(500_000_001), average, 0.0),
+            Trend::Slower
+        );
+        assert_eq!(
+            trend(Duration::from_nanos(499_999_999), average, 0.0),
+            Trend::Faster
+        );
+        // Against a zero average any delta is slower, and a zero delta steady.
+        assert_eq!(trend(ms(1), Duration::ZERO, 0.1), Trend::Slower);
+        assert_eq!(trend(Duration::ZERO, Duration::ZERO, 0.1), Trend::Steady);
+    }
+
+    #[test]
+    fn test_sliding_window_counts() {
+        let start = Instant::now();
+        let at = |millis: u64| start + Duration::from_millis(millis);
+        let window = SlidingWindow::new(Duration::from_secs(1));
+        assert_eq!(window.count_at(start), 0);
+        // A burst of 5 lines, 10 lines spread over 2 seconds, then a pause.
+        let mut arrivals: Vec<u64> = (0..5).map(|i| i * 10).collect();
+        arrivals.extend((0..10).map(|i| 1000 + i * 200));
+        let counts: Vec<usize> = arrivals
+            .iter()
+            .map(|&millis| window.push(at(millis)))
+            .collect();
+        // The line at 1 s still counts the burst but its first line, a second before it.
+        assert_eq!(counts, [1, 2, 3, 4, 5, 5, 2, 3, 4, 5, 5, 5, 5, 5, 5]);
+        // The last line arrived at 2.8 s; the lines after 1.8 s are within a second of it.
+        assert_eq!(window.count_at(at(2800)), 5);
+        assert_eq!(window.count_at(at(3200)), 3);
+        assert_eq!(window.rate_at(at(3200)), 3.0);
+        assert_eq!(window.count_at(at(3800)), 0);
+        assert_eq!(window.push(at(5000)), 1);
+    }
+
+    #[test]
+    fn test_sliding_window_evicts_whole_window() {
+        let start = Instant::now();
+        let window = SlidingWindow::new(Duration::from_millis(500));
+        for millis in 0..2000 {
+            window.push(start + Duration::from_millis(millis));
+        }
+        // Only the arrivals of the latest 500 ms are kept.
+        assert_eq!(window.arrivals.lock().unwrap().len(), 500);
+        assert_eq!(window.rate_at(start + Duration::from_millis(1999)), 1000.0);
+    }
+
+    #[test]
+    fn test_window_label() {
+        assert_eq!(window_label(&DEFAULT_RATE_WINDOW), "10s");
+        assert_eq!(window_label(&Duration::from_millis(500)), "500ms");
+        assert_eq!(window_label(&Duration::from_millis(1500)), "1500ms");
+    }
+
+    #[test]
+    fn test_threshold_label() {
+        assert_eq!(threshold_label(&Duration::from_secs(1)), "1s");
+        assert_eq!(threshold_label(&Duration::from_millis(2500)), "2.5s");
+        assert_eq!(threshold_label(&Duration::from_millis(10)), "10ms");
+        assert_eq!(threshold_label(&Duration::from_micros(100)), "100us");
+        assert_eq!(threshold_label(&Duration::from_nanos(50)), "50ns");
+        // The thresholds picked from the deltas are powers of ten, computed as floats.
+        assert_eq!(threshold_label(&Duration::from_secs_f64(1e-4)), "100us");
+    }
+
+    #[test]
+    fn test_outliers_above_sigma() {
+        // Mean 5, standard deviation about 2.14.
+        let stats = stats_of(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert!(!is_outlier(&stats, 9.0, 2.0, 0));
+        assert!(is_outlier(&stats, 9.5, 2.0, 0));
+        assert!(!is_outlier(&stats, 9.5, 3.0, 0));
+        // Values below the mean are never outliers, however far.
+        assert!(!is_outlier(&stats, -100.0, 2.0, 0));
+    }
+
+    #[test]
+    fn test_outliers_wait_for_warmup() {
+        let stats = stats_of(&[1.0, 1.1, 0.9]);
+        assert!(!is_outlier(&stats, 50.0, 3.0, 4));
+        assert!(is_outlier(&stats, 50.0, 3.0, 3));
+        // A single value has no spread to judge against.
+        assert!(!is_outlier(&stats_of(&[1.0]), 50.0, 3.0, 0));
+        assert!(!is_outlier(&RunningStats::default(), 50.0, 3.0, 0));
+    }
+
+    #[test]
+    fn test_outliers_of_constant_deltas() {
+        let stats = stats_of(&[0.5; 20]);
+        assert_eq!(stats.std_dev(), 0.0);
+        assert!(!is_outlier(&stats, 0.5, 3.0, 10));
+        assert!(!is_outlier(&stats, 0.4, 3.0, 10));
+        assert!(is_outlier(&stats, 0.6, 3.0, 10));
+    }
+
+    #[test]
+    fn test_percentile_nearest_rank() {
+        let sorted: Vec<f64> = (1..=100).map(f64::from).collect();
+        assert_eq!(percentile(&sorted, 50.0), Some(50.0));
+        assert_eq!(percentile(&sorted, 95.0), Some(95.0));
+        assert_eq!(percentile(&sorted, 99.0), Some(99.0));
+        assert_eq!(percentile(&sorted, 100.0), Some(100.0));
+        assert_eq!(percentile(&sorted, 0.0), Some(1.0));
+        assert_eq!(percentile(&[], 50.0), None);
+    }
+
+    #[test]
+    fn test_percentiles_unsorted() {
+        let values = vec![0.5, 0.1, 0.4, 0.2, 0.3];
+        assert_eq!(
+            percentiles(&values, &REFERENCE_PERCENTILES),
+            vec![0.3, 0.5, 0.5]
+        );
+        assert!(percentiles(&[], &REFERENCE_PERCENTILES).is_empty());
+    }
+
+    #[test]
+    fn test_running_stats_known_sequence() {
+        let mut stats = RunningStats::default();
+        for value in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            stats.push(value);
+        }
+        assert_eq!(stats.count(), 8);
+        assert_eq!(stats.sum(), 40.0);
+        assert_eq!(stats.mean(), 5.0);
+        assert!((stats.variance() - 32.0 / 7.0).abs() < 1e-12);
+        assert_eq!(stats.min(), Some(2.0));
+        assert_eq!(stats.max(), Some(9.0));
+    }
+
+    #[test]
+    fn test_running_stats_empty_and_single() {
+        let mut stats = RunningStats::default();
+        assert_eq!(stats.mean(), 0.0);
+        assert_eq!(stats.variance(), 0.0);
+        assert_eq!(stats.min(), None);
+        assert_eq!(stats.percentile(50.0), None);
+        stats.push(0.25);
+        assert_eq!(stats.mean(), 0.25);
+        assert_eq!(stats.variance(), 0.0);
+    }
+
+    #[test]
+    fn test_running_stats_large_offset() {
+        // Welford's update keeps the variance exact where the naive sum of squares would not.
+        let mut stats = RunningStats::default();
+        for value in [1e9 + 4.0, 1e9 + 7.0, 1e9 + 13.0, 1e9 + 16.0] {
+            stats.push(value);
+        }
+        assert_eq!(stats.mean(), 1e9 + 10.0);
+        assert!((stats.variance() - 30.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_quantile_sketch_tolerance() {
+        let values: Vec<f64> = (1..=10_000).map(|i| i as f64 * 1e-4).collect();
+        let mut sketch = QuantileSketch::default();
+        // Insert in a scrambled order; the sketch must not depend on it.
+        for i in 0..values.len() {
+            sketch.push(values[(i * 7919) % values.len()]);
+        }
+        for q in [0.01, 0.25, 0.5, 0.9, 0.95, 0.99, 1.0] {
+            let exact = values[(q * (values.len() - 1) as f64) as usize];
+            let approx = sketch.quantile(q).unwrap();
+            assert!(
+                (approx - exact).abs() <= exact * SKETCH_RELATIVE_ACCURACY,
+                "q={}: {} vs {}",
+                q,
+                approx,
+                exact
+            );
+        }
+    }
+
+    #[test]
+    fn test_quantile_sketch_random_samples() {
+        // Log-uniform deltas from 10 µs to 10 s, from a xorshift generator so the test is stable.
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut values: Vec<f64> = (0..100_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                let uniform = (state >> 11) as f64 / (1u64 << 53) as f64;
+                1e-5 * 1e6f64.powf(uniform)
+            })
+            .collect();
+        let mut stats = RunningStats::default();
+        for value in &values {
+            stats.push(*value);
+        }
+        values.sort_by(f64::total_cmp);
+        for p in [1.0, 25.0, 50.0, 90.0, 95.0, 99.0, 99.9] {
+            let exact = percentile(&values, p).unwrap();
+            let approx = stats.percentile(p).unwrap();
+            assert!(
+                (approx - exact).abs() <= exact * SKETCH_RELATIVE_ACCURACY * 1.01,
+                "p{}: {} vs {}",
+                p,
+                approx,
+                exact
+            );
+        }
+        // Six decades of values fit in a few KB, however many there are.
+        assert!(
+            stats.sketch.buckets.len() < 800,
+            "{}",
+            stats.sketch.buckets.len()
+        );
+    }
+
+    #[test]
+    fn test_quantile_sketch_zeros() {
+        let mut sketch = QuantileSketch::default();
+        for value in [0.0, 0.0, 0.0, 1.0] {
+            sketch.push(value);
+        }
+        assert_eq!(sketch.quantile(0.5), Some(0.0));
+        assert!((sketch.quantile(1.0).unwrap() - 1.0).abs() <= SKETCH_RELATIVE_ACCURACY);
+        assert_eq!(QuantileSketch::default().quantile(0.5), None);
+    }
+}
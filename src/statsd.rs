@@ -0,0 +1,212 @@
+//! This module emits the timings and counters of a run as StatsD metrics over UDP.
+//!
+//! Each timed line adds a `<prefix>.delta:<ms>|ms` timing, and every interval the counters are
+//! added as gauges: `<prefix>.lines`, `<prefix>.matches` (and `<prefix>.matches.<pattern>` per
+//! labelled pattern), `<prefix>.bytes` and `<prefix>.elapsed` in milliseconds.
+//!
+//! The metrics are batched, one per line, into datagrams of at most `MAX_DATAGRAM` bytes, so that
+//! a line costs at most one `send`. The socket is non-blocking and send errors are ignored: a
+//! missing or slow collector loses metrics rather than slowing the run.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use timeln::statsd::StatsdEmitter;
+//!
+//! let mut emitter = StatsdEmitter::connect("127.0.0.1:8125", "build")?;
+//! emitter.timing(Duration::from_millis(250));
+//! emitter.flush();
+//! # Ok::<(), std::io::Error>(())
+//! ```
+use std::io;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+use crate::metrics::Metrics;
+
+/// Prefix of the metric names, unless changed.
+pub const DEFAULT_PREFIX: &str = "timeln";
+
+/// Interval between two emissions of the gauges, unless changed.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Largest datagram sent, small enough not to be fragmented on any network.
+pub const MAX_DATAGRAM: usize = 512;
+
+/// Sends StatsD metrics to a collector, in batches.
+#[derive(Debug)]
+pub struct StatsdEmitter {
+    socket: UdpSocket,
+    prefix: String,
+    batch: String,
+}
+
+impl StatsdEmitter {
+    /// Creates an emitter sending to `addr` (`host:port`), with metric names starting with
+    /// `prefix`. Fails only if `addr` cannot be resolved or no socket can be opened.
+    pub fn connect(addr: &str, prefix: &str) -> io::Result<Self> {
+        let target = addr.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "the address resolved to nothing")
+        })?;
+        let local = if target.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(target)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            prefix: prefix.trim_end_matches('.').to_string(),
+            batch: String::new(),
+        })
+    }
+
+    /// Adds the timing of a line whose delta is `delta`.
+    pub fn timing(&mut self, delta: Duration) {
+        let millis = delta.as_secs_f64() * 1000.0;
+        self.push(&format!("{}.delta:{:.3}|ms", self.prefix, millis));
+    }
+
+    /// Adds the counters of `metrics` as gauges, then sends everything batched so far.
+    pub fn gauges(&mut self, metrics: &Metrics) {
+        let mut gauge = |name: &str, value: String| {
+            let metric = format!("{}.{}:{}|g", self.prefix, name, value);
+            self.push(&metric);
+        };
+        gauge("lines", metrics.lines.to_string());
+        gauge("matches", metrics.matches.to_string());
+        for (label, matches) in &metrics.pattern_matches {
+            gauge(&format!("matches.{}", sanitize(label)), matches.to_string());
+        }
+        gauge("bytes", metrics.bytes.to_string());
+        let elapsed = metrics.elapsed.as_secs_f64() * 1000.0;
+        gauge("elapsed", format!("{:.3}", elapsed));
+        self.flush();
+    }
+
+    /// Sends the metrics batched so far.
+    pub fn flush(&mut self) {
+        if !self.batch.is_empty() {
+            // Fire and forget: a full buffer or an absent collector only loses this batch.
+            let _ = self.socket.send(self.batch.as_bytes());
+            self.batch.clear();
+        }
+    }
+
+    /// Adds `metric` to the batch, first sending the batch if `metric` would not fit in it.
+    fn push(&mut self, metric: &str) {
+        if !self.batch.is_empty() && self.batch.len() + 1 + metric.len() > MAX_DATAGRAM {
+            self.flush();
+        }
+        if !self.batch.is_empty() {
+            self.batch.push('\n');
+        }
+        self.batch.push_str(metric);
+    }
+}
+
+/// Replaces the characters that would break a metric name, such as `:` and `|`, with `_`.
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "-_.".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A collector bound to a free local port, with an emitter sending to it.
+    fn collector(prefix: &str) -> (UdpSocket, StatsdEmitter) {
+        let collector = UdpSocket::bind("127.0.0.1:0").unwrap();
+        collector
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let addr = collector.local_addr().unwrap().to_string();
+        (collector, StatsdEmitter::connect(&addr, prefix).unwrap())
+    }
+
+    fn receive(collector: &UdpSocket) -> String {
+        let mut buffer = [0; 2 * MAX_DATAGRAM];
+        let len = collector.recv(&mut buffer).unwrap();
+        String::from_utf8(buffer[..len].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_timings_are_batched() {
+        let (collector, mut emitter) = collector("build.");
+        emitter.timing(Duration::from_millis(250));
+        emitter.timing(Duration::from_micros(1500));
+        emitter.flush();
+        assert_eq!(
+            receive(&collector),
+            "build.delta:250.000|ms\nbuild.delta:1.500|ms"
+        );
+    }
+
+    #[test]
+    fn test_full_batch_is_sent() {
+        let (collector, mut emitter) = collector(DEFAULT_PREFIX);
+        let metric = "timeln.delta:1.000|ms";
+        let per_datagram = (MAX_DATAGRAM + 1) / (metric.len() + 1);
+        for _ in 0..per_datagram + 1 {
+            emitter.timing(Duration::from_millis(1));
+        }
+        let datagram = receive(&collector);
+        assert!(datagram.len() <= MAX_DATAGRAM);
+        assert_eq!(datagram.lines().count(), per_datagram);
+        assert!(datagram.lines().all(|line| line == metric));
+        emitter.flush();
+        assert_eq!(receive(&collector), metric);
+    }
+
+    #[test]
+    fn test_gauges() {
+        let (collector, mut emitter) = collector(DEFAULT_PREFIX);
+        emitter.timing(Duration::from_millis(2));
+        emitter.gauges(&Metrics {
+            lines: 4,
+            matches: 3,
+            pattern_matches: vec![("err".to_string(), 2), ("a:b|c".to_string(), 1)],
+            bytes: 40,
+            last_delta: None,
+            elapsed: Duration::from_millis(1500),
+            recent_rate: None,
+            deltas: None,
+            progress: None,
+        });
+        let datagram = receive(&collector);
+        let metrics: Vec<&str> = datagram.lines().collect();
+        assert_eq!(
+            metrics,
+            [
+                "timeln.delta:2.000|ms",
+                "timeln.lines:4|g",
+                "timeln.matches:3|g",
+                "timeln.matches.err:2|g",
+                "timeln.matches.a_b_c:1|g",
+                "timeln.bytes:40|g",
+                "timeln.elapsed:1500.000|g",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_collector_does_not_fail() {
+        let (collector, mut emitter) = collector(DEFAULT_PREFIX);
+        drop(collector);
+        for _ in 0..100 {
+            emitter.timing(Duration::from_millis(1));
+            emitter.flush();
+        }
+    }
+}
@@ -0,0 +1,463 @@
+//! This module shows the progress of a run on the terminal, for runs whose output is redirected
+//! to a file:
+//!
+//! - a status line on stderr, like `pv`: the elapsed time, the lines read, the rate of lines over
+//!   the last seconds and the latest delta, redrawn in place every `STATUS_INTERVAL`;
+//! - the title of the terminal, such as `timeln: 4m12s, 1532 lines`, set at most every
+//!   `TITLE_INTERVAL`.
+//!
+//! Both are drawn by the same background thread, reading the counters of the run. When the run
+//! ends, before its summary, the status line is cleared and the title the terminal had is
+//! restored from the title stack of xterm. They are only meant for a terminal that understands
+//! the escape sequences: see `terminal_enabled`.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use timeln::status::{compact_duration, RateWindow};
+//!
+//! let mut window = RateWindow::new(Duration::from_secs(5));
+//! window.push(Duration::from_secs(1), 10);
+//! window.push(Duration::from_secs(3), 50);
+//! assert_eq!(window.rate(), Some(20.0));
+//! assert_eq!(compact_duration(&Duration::from_secs(252)), "4m12s");
+//! ```
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::formatter::TimeFormat;
+use crate::metrics::Metrics;
+use crate::stats::window_label;
+
+/// Time between two redraws of the status line.
+pub const STATUS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Time over which the rate of lines is measured.
+pub const RATE_WINDOW: Duration = Duration::from_secs(5);
+
+/// Least time between two changes of the terminal title.
+pub const TITLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Clears the line of the terminal the cursor is on.
+const CLEAR_LINE: &str = "\r\x1b[K";
+
+/// Saves the title of the terminal on the title stack of xterm.
+const PUSH_TITLE: &str = "\x1b[22;0t";
+
+/// Restores the title of the terminal saved by `PUSH_TITLE`.
+const POP_TITLE: &str = "\x1b[23;0t";
+
+/// Whether the progress can be drawn on stderr: only if it is a terminal, and one that
+/// understands escape sequences, given the value of `TERM`.
+pub fn terminal_enabled(is_terminal: bool, term: Option<&str>) -> bool {
+    is_terminal && term != Some("dumb")
+}
+
+/// What `StatusTicker` draws.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatusDisplay {
+    /// The status line on stderr.
+    pub line: bool,
+    /// The title of the terminal.
+    pub title: bool,
+}
+
+impl StatusDisplay {
+    pub fn is_empty(&self) -> bool {
+        !self.line && !self.title
+    }
+}
+
+/// The rate of lines over a rolling window of time, from samples of the line count.
+#[derive(Debug)]
+pub struct RateWindow {
+    window: Duration,
+    /// Times since the start and line counts, oldest first.
+    samples: VecDeque<(Duration, u64)>,
+}
+
+impl RateWindow {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Adds a sample of `lines` lines read `at` the given time since the start, no earlier than
+    /// the previous sample.
+    pub fn push(&mut self, at: Duration, lines: u64) {
+        self.samples.push_back((at, lines));
+        // The latest sample older than the window is kept, as the start of the window.
+        while self
+            .samples
+            .get(1)
+            .is_some_and(|(second, _)| at.saturating_sub(*second) >= self.window)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Lines per second over the window, or `None` until two samples are apart in time.
+    pub fn rate(&self) -> Option<f64> {
+        let (first_at, first_lines) = self.samples.front()?;
+        let (last_at, last_lines) = self.samples.back()?;
+        let span = last_at.saturating_sub(*first_at).as_secs_f64();
+        (span > 0.0).then(|| last_lines.saturating_sub(*first_lines) as f64 / span)
+    }
+}
+
+/// Renders the status line of a run, as `[Elapsed: 12.30 s, Lines: 1024, Rate: 83.2 lines/s,
+/// Last Delta: 0.01 s]`, followed by the approximate percentiles of the deltas once a line was timed,
+/// as `p50: 0.01 s, p95: 0.03 s, p99: 0.12 s`, and by the progress towards the total matches, if
+/// expected. The rate is that of the sliding window of the metrics, as `Rate 10s: ..`, if they
+/// have one, or else `rate`.
+pub fn status_line(metrics: &Metrics, rate: Option<f64>, time_format: &dyn TimeFormat) -> String {
+    let (label, rate) = match metrics.recent_rate {
+        Some((window, rate)) => (format!("Rate {}", window_label(&window)), Some(rate)),
+        None => ("Rate".to_string(), rate),
+    };
+    let rate = rate.map_or("-".to_string(), |rate| format!("{:.1} lines/s", rate));
+    let last_delta = metrics
+        .last_delta
+        .map_or("-".to_string(), |delta| time_format.format_duration(&delta));
+    let percentiles: String = metrics
+        .deltas
+        .iter()
+        .flat_map(|deltas| &deltas.quantiles)
+        .map(|(quantile, delta)| {
+            format!(
+                ", p{}: {}",
+                quantile * 100.0,
+                time_format.format_duration(delta)
+            )
+        })
+        .collect();
+    let progress = metrics.progress.map_or(String::new(), |progress| {
+        format!(", Matches: {}", progress.label(time_format))
+    });
+    format!(
+        "[Elapsed: {}, Lines: {}, {}: {}, Last Delta: {}{}{}]",
+        time_format.format_duration(&metrics.elapsed),
+        metrics.lines,
+        label,
+        rate,
+        last_delta,
+        percentiles,
+        progress
+    )
+}
+
+/// Formats `duration` compactly for the terminal title, as `12s`, `4m12s` or `1h02m`.
+pub fn compact_duration(duration: &Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m{:02}s", m, s),
+        (h, m, _) => format!("{}h{:02}m", h, m),
+    }
+}
+
+/// The terminal title showing the progress of a run, as `timeln: 4m12s, 1532 lines`.
+pub fn title_text(metrics: &Metrics) -> String {
+    format!(
+        "timeln: {}, {} lines",
+        compact_duration(&metrics.elapsed),
+        metrics.lines
+    )
+}
+
+/// Redraws the status line and the terminal title of a run from a background thread.
+#[derive(Debug, Default)]
+pub struct StatusTicker {
+    display: StatusDisplay,
+    stop: Arc<AtomicBool>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl StatusTicker {
+    pub fn new(display: StatusDisplay) -> Self {
+        Self {
+            display,
+            ..Self::default()
+        }
+    }
+
+    /// Draws on `out` every `interval` with the counters returned by `scrape`, until `clear` is
+    /// called or `scrape` returns `None`.
+    pub fn start(
+        &self,
+        mut out: Box<dyn Write + Send>,
+        interval: Duration,
+        time_format: Arc<Box<dyn TimeFormat>>,
+        scrape: impl Fn() -> Option<Metrics> + Send + 'static,
+    ) {
+        let stop = Arc::clone(&self.stop);
+        let display = self.display;
+        let handle = thread::spawn(move || {
+            let mut window = RateWindow::new(RATE_WINDOW);
+            let mut title: Option<(Instant, String)> = None;
+            // The status is drawn on stderr, which has nowhere to report its own failures, while
+            // the lines go on to stdout.
+            if display.title {
+                let _ = write!(out, "{}", PUSH_TITLE);
+            }
+            while !stop.load(Ordering::Acquire) {
+                let Some(metrics) = scrape() else {
+                    break;
+                };
+                if display.line {
+                    window.push(metrics.elapsed, metrics.lines);
+                    let line = status_line(&metrics, window.rate(), &**time_format);
+                    let _ = write!(out, "{}{}", CLEAR_LINE, line);
+                }
+                let due = title
+                    .as_ref()
+                    .is_none_or(|(at, _)| at.elapsed() >= TITLE_INTERVAL);
+                if display.title && due {
+                    let text = title_text(&metrics);
+                    if title.as_ref().is_none_or(|(_, last)| *last != text) {
+                        let _ = write!(out, "\x1b]0;{}\x07", text);
+                    }
+                    title = Some((Instant::now(), text));
+                }
+                let _ = out.flush();
+                thread::park_timeout(interval);
+            }
+            if display.line {
+                let _ = write!(out, "{}", CLEAR_LINE);
+            }
+            if display.title {
+                let _ = write!(out, "{}", POP_TITLE);
+            }
+            let _ = out.flush();
+        });
+        if let Ok(mut thread) = self.thread.lock() {
+            *thread = Some(handle);
+        }
+    }
+
+    /// Stops drawing, clearing the status line and restoring the title, and waits for the thread
+    /// drawing them.
+    pub fn clear(&self) {
+        self.stop.store(true, Ordering::Release);
+        let handle = match self.thread.lock() {
+            Ok(mut thread) => thread.take(),
+            Err(_) => None,
+        };
+        if let Some(handle) = handle {
+            handle.thread().unpark();
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatter::SecondsFormat;
+    use crate::metrics::DeltaSummary;
+    use crate::progress::Progress;
+
+    fn secs(secs: f64) -> Duration {
+        Duration::from_secs_f64(secs)
+    }
+
+    #[test]
+    fn test_rate_needs_two_samples() {
+        let mut window = RateWindow::new(RATE_WINDOW);
+        assert_eq!(window.rate(), None);
+        window.push(secs(1.0), 10);
+        assert_eq!(window.rate(), None);
+        window.push(secs(1.0), 12);
+        assert_eq!(window.rate(), None);
+        window.push(secs(1.5), 20);
+        assert_eq!(window.rate(), Some(20.0));
+    }
+
+    #[test]
+    fn test_rate_rolls_over_window() {
+        let mut window = RateWindow::new(secs(5.0));
+        // 100 lines per second for 10 seconds, then none for 3 seconds.
+        for tick in 0..=10 {
+            window.push(secs(tick as f64), tick * 100);
+        }
+        assert_eq!(window.rate(), Some(100.0));
+        for tick in 11..=13 {
+            window.push(secs(tick as f64), 1000);
+        }
+        // From 8 s to 13 s: 200 lines in 5 seconds.
+        assert_eq!(window.rate(), Some(40.0));
+        assert_eq!(window.samples.len(), 6);
+        window.push(secs(20.0), 1000);
+        assert_eq!(window.rate(), Some(0.0));
+    }
+
+    #[test]
+    fn test_status_line() {
+        let mut metrics = Metrics {
+            lines: 1024,
+            matches: 0,
+            pattern_matches: Vec::new(),
+            bytes: 0,
+            last_delta: None,
+            elapsed: secs(12.3),
+            recent_rate: None,
+            deltas: None,
+            progress: None,
+        };
+        assert_eq!(
+            status_line(&metrics, None, &SecondsFormat),
+            "[Elapsed: 12.30 s, Lines: 1024, Rate: -, Last Delta: -]"
+        );
+        metrics.last_delta = Some(secs(0.01));
+        assert_eq!(
+            status_line(&metrics, Some(83.25), &SecondsFormat),
+            "[Elapsed: 12.30 s, Lines: 1024, Rate: 83.2 lines/s, Last Delta: 0.01 s]"
+        );
+        metrics.recent_rate = Some((Duration::from_secs(10), 4.0));
+        assert_eq!(
+            status_line(&metrics, Some(83.25), &SecondsFormat),
+            "[Elapsed: 12.30 s, Lines: 1024, Rate 10s: 4.0 lines/s, Last Delta: 0.01 s]"
+        );
+        metrics.deltas = Some(DeltaSummary {
+            count: 1024,
+            sum: secs(12.0),
+            quantiles: vec![(0.5, secs(0.01)), (0.95, secs(0.03)), (0.99, secs(0.12))],
+        });
+        assert_eq!(
+            status_line(&metrics, None, &SecondsFormat),
+            "[Elapsed: 12.30 s, Lines: 1024, Rate 10s: 4.0 lines/s, Last Delta: 0.01 s, p50: 0.01 s, p95: 0.03 s, p99: 0.12 s]"
+        );
+        metrics.deltas = None;
+        metrics.progress = Some(Progress {
+            matches: 40,
+            total: 100,
+            eta: Some(secs(18.0)),
+        });
+        assert_eq!(
+            status_line(&metrics, None, &SecondsFormat),
+            "[Elapsed: 12.30 s, Lines: 1024, Rate 10s: 4.0 lines/s, Last Delta: 0.01 s, Matches: [########------------] 40/100, ETA: 18.00 s]"
+        );
+    }
+
+    /// A writer whose contents can be read while it is shared with the ticker.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_ticker_redraws_then_clears() {
+        let out = Shared::default();
+        let ticker = StatusTicker::new(StatusDisplay {
+            line: true,
+            title: false,
+        });
+        ticker.start(
+            Box::new(out.clone()),
+            Duration::from_millis(5),
+            Arc::new(Box::new(SecondsFormat)),
+            || {
+                Some(Metrics {
+                    lines: 3,
+                    matches: 0,
+                    pattern_matches: Vec::new(),
+                    bytes: 0,
+                    last_delta: None,
+                    elapsed: secs(1.0),
+                    recent_rate: None,
+                    deltas: None,
+                    progress: None,
+                })
+            },
+        );
+        thread::sleep(Duration::from_millis(30));
+        ticker.clear();
+        let written = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        assert!(
+            written.starts_with("\r\x1b[K[Elapsed: 1.00 s, Lines: 3,"),
+            "{:?}",
+            written
+        );
+        assert!(written.ends_with(CLEAR_LINE), "{:?}", written);
+        // Clearing again does nothing.
+        ticker.clear();
+        assert_eq!(out.0.lock().unwrap().len(), written.len());
+    }
+
+    #[test]
+    fn test_title_text() {
+        let metrics = |secs: u64, lines: u64| Metrics {
+            lines,
+            matches: 0,
+            pattern_matches: Vec::new(),
+            bytes: 0,
+            last_delta: None,
+            elapsed: Duration::from_secs(secs),
+            recent_rate: None,
+            deltas: None,
+            progress: None,
+        };
+        assert_eq!(title_text(&metrics(0, 0)), "timeln: 0s, 0 lines");
+        assert_eq!(title_text(&metrics(252, 1532)), "timeln: 4m12s, 1532 lines");
+        assert_eq!(title_text(&metrics(3725, 9)), "timeln: 1h02m, 9 lines");
+    }
+
+    #[test]
+    fn test_terminal_enabled() {
+        assert!(terminal_enabled(true, Some("xterm-256color")));
+        assert!(terminal_enabled(true, None));
+        assert!(!terminal_enabled(true, Some("dumb")));
+        assert!(!terminal_enabled(false, Some("xterm")));
+        assert!(StatusDisplay::default().is_empty());
+    }
+
+    #[test]
+    fn test_ticker_sets_then_restores_title() {
+        let out = Shared::default();
+        let ticker = StatusTicker::new(StatusDisplay {
+            line: false,
+            title: true,
+        });
+        ticker.start(
+            Box::new(out.clone()),
+            Duration::from_millis(5),
+            Arc::new(Box::new(SecondsFormat)),
+            || {
+                Some(Metrics {
+                    lines: 3,
+                    matches: 0,
+                    pattern_matches: Vec::new(),
+                    bytes: 0,
+                    last_delta: None,
+                    elapsed: secs(1.0),
+                    recent_rate: None,
+                    deltas: None,
+                    progress: None,
+                })
+            },
+        );
+        thread::sleep(Duration::from_millis(30));
+        ticker.clear();
+        let written = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        // The title is rate-limited, so it was only set once.
+        assert_eq!(
+            written,
+            format!("{}\x1b]0;timeln: 1s, 3 lines\x07{}", PUSH_TITLE, POP_TITLE)
+        );
+    }
+}
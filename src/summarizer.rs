@@ -1,7 +1,29 @@
-use crate::formatter::TimeFormat;
+use crate::activity::{bars, Activity};
+use crate::burst::{Burst, BurstThreshold};
+use crate::extract::ValueSeries;
+use crate::formatter::{RateFormat, TimeFormat};
+use crate::group::GroupStats;
+use crate::phase::Phase;
+use crate::progress::signed_error;
+use crate::report::SlowLine;
+use crate::stats::{threshold_label, window_label, RunningStats};
 use colored::Colorize;
 use std::time::Duration;
 
+/// A segment of a run between two lines matching the `--reset-on` marker, or between the last
+/// marker and the end of the run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lap {
+    /// Number of the lap, from 1.
+    pub number: usize,
+    /// Lines read during the lap, including the marker that started it.
+    pub lines: usize,
+    /// Lines of the lap that matched a regex pattern.
+    pub matches: usize,
+    /// Time from the marker that started the lap to the end of the lap.
+    pub duration: Duration,
+}
+
 /// A trait for objects that can summarize a process by providing a summary string
 /// based on total lines processed, total time taken, and a specified time format.
 pub trait Summarizer: Sync + Send {
@@ -10,19 +32,397 @@ pub trait Summarizer: Sync + Send {
     /// # Arguments
     ///
     /// * `total_lines` - The total number of lines processed.
+    /// * `total_matches` - The number of lines that matched a regex pattern.
+    /// * `total_bytes` - The number of bytes read, including line endings.
     /// * `total_time` - The total time taken to process lines.
+    /// * `overhead` - The part of `total_time` spent by timeln itself between reading a line and
+    ///   being ready for the next one, rather than waiting for input.
+    /// * `stats` - Statistics of the deltas of the timed lines.
     /// * `time_format` - The format to display time.
     ///
     /// # Returns
     ///
     /// A string containing the summary of the process.
+    #[allow(clippy::too_many_arguments)]
     fn summarize(
         &self,
         total_lines: usize,
         total_matches: usize,
+        total_bytes: u64,
         total_time: &Duration,
+        overhead: &Duration,
+        stats: &RunningStats,
         time_format: &dyn TimeFormat,
     ) -> String;
+
+    /// Summarizes a lap that just ended, printed before the marker of the next lap.
+    fn summarize_lap(&self, lap: &Lap, time_format: &dyn TimeFormat) -> String {
+        format!(
+            "[Lap {}: Lines: {}, Matches: {}, Time: {}]",
+            lap.number,
+            lap.lines,
+            lap.matches,
+            time_format.format_duration(&lap.duration)
+        )
+    }
+
+    /// Summarizes the section of the file `name` when several files are read, after the summary.
+    fn summarize_file(&self, name: &str, section: &Lap, time_format: &dyn TimeFormat) -> String {
+        format!(
+            "[File {}: Lines: {}, Matches: {}, Span: {}]",
+            name,
+            section.lines,
+            section.matches,
+            time_format.format_duration(&section.duration)
+        )
+    }
+
+    /// Summarizes the `count` laps of the run, which took `mean` on average, after the summary.
+    fn summarize_laps(
+        &self,
+        count: usize,
+        mean: &Duration,
+        time_format: &dyn TimeFormat,
+    ) -> String {
+        format!(
+            "[Laps: {}, Mean Lap Time: {}]",
+            count,
+            time_format.format_duration(mean)
+        )
+    }
+
+    /// Summarizes a run of `total_lines` lines that took `total_time`, for the desktop
+    /// notification sent when it finishes.
+    fn summarize_finished(
+        &self,
+        total_lines: usize,
+        total_time: &Duration,
+        time_format: &dyn TimeFormat,
+    ) -> String {
+        format!(
+            "timeln: finished, {} lines in {}",
+            total_lines,
+            time_format.format_duration(total_time)
+        )
+    }
+
+    /// Summarizes the first line matching `pattern`, which arrived `elapsed` into the run, for
+    /// the desktop notification sent for it.
+    fn summarize_first_match(
+        &self,
+        pattern: &str,
+        text: &str,
+        elapsed: &Duration,
+        time_format: &dyn TimeFormat,
+    ) -> String {
+        format!(
+            "timeln: '{}' matched after {}: {}",
+            pattern,
+            time_format.format_duration(elapsed),
+            text
+        )
+    }
+
+    /// Summarizes the number of lines that matched each regex pattern, given as `(label, count)`
+    /// pairs, after the summary.
+    fn summarize_patterns(&self, matches: &[(&str, usize)]) -> String {
+        let counts: Vec<String> = matches
+            .iter()
+            .map(|(label, count)| format!("{}: {}", label, count))
+            .collect();
+        format!("[Pattern Matches: {}]", counts.join(", "))
+    }
+
+    /// Summarizes one of the largest gaps of the run, the `index`-th from 1, with the lines before
+    /// and after it, after the detailed summary.
+    fn summarize_gap(&self, index: usize, gap: &SlowLine, time_format: &dyn TimeFormat) -> String {
+        format!(
+            "[Gap {}: Duration: {}, Ended At: {}, Line: {}, Before: \"{}\", After: \"{}\"]",
+            index,
+            time_format.format_duration(&gap.delta),
+            time_format.format_duration(&gap.elapsed),
+            gap.line_number,
+            gap.previous,
+            gap.text
+        )
+    }
+
+    /// Summarizes a phase of the run detected from the changes of pace of the deltas, after the
+    /// summary.
+    fn summarize_phase(&self, phase: &Phase, time_format: &dyn TimeFormat) -> String {
+        format!(
+            "[Phase {}: From Line: {}, Lines: {}, Start: {}, Duration: {}, Mean Delta: {}]",
+            phase.index,
+            phase.first_line,
+            phase.lines,
+            time_format.format_duration(&phase.start),
+            time_format.format_duration(&phase.duration),
+            time_format.format_duration(&phase.mean_delta)
+        )
+    }
+
+    /// Summarizes the final moving average of the deltas, each of which weighed `alpha`, after the
+    /// summary.
+    fn summarize_ewma(&self, ewma: &Duration, alpha: f64, time_format: &dyn TimeFormat) -> String {
+        format!(
+            "[EWMA Delta: {} (alpha {})]",
+            time_format.format_duration(ewma),
+            alpha
+        )
+    }
+
+    /// Summarizes the deltas as the rates they stand for, after the summary: the rate of the mean
+    /// delta, that of the longest delta as the lowest and that of the shortest as the highest.
+    fn summarize_rates(
+        &self,
+        mean_delta: &Duration,
+        min_delta: &Duration,
+        max_delta: &Duration,
+    ) -> String {
+        format!(
+            "[Rate: Mean: {}, Min: {}, Max: {}]",
+            RateFormat.format_duration(mean_delta),
+            RateFormat.format_duration(max_delta),
+            RateFormat.format_duration(min_delta)
+        )
+    }
+
+    /// Summarizes the cumulative distribution of the deltas, as the fraction (0 to 1) of them at
+    /// most each threshold of `points`, after the summary.
+    fn summarize_cdf(&self, points: &[(Duration, f64)]) -> String {
+        let points: Vec<String> = points
+            .iter()
+            .map(|(threshold, fraction)| {
+                format!(
+                    "≤{}: {}",
+                    threshold_label(threshold),
+                    format_percent(*fraction)
+                )
+            })
+            .collect();
+        format!("[Deltas Within: {}]", points.join(", "))
+    }
+
+    /// Summarizes the `count` bursts of more lines than `threshold` within its window, after the
+    /// summary.
+    fn summarize_bursts(&self, count: usize, threshold: &BurstThreshold) -> String {
+        format!(
+            "[Bursts: {} above {} lines/{}]",
+            count,
+            threshold.lines,
+            window_label(&threshold.window)
+        )
+    }
+
+    /// Summarizes a burst of lines, after the summary of the bursts.
+    fn summarize_burst(&self, burst: &Burst, time_format: &dyn TimeFormat) -> String {
+        format!(
+            "[Burst {}: Start: {}, Duration: {}, Lines: {}]",
+            burst.index,
+            time_format.format_duration(&burst.start),
+            time_format.format_duration(&burst.duration),
+            burst.lines
+        )
+    }
+
+    /// Summarizes the lines and matches of the run per bucket of time as rows of bars, in ASCII if
+    /// `ascii`, after the summary. The matches are left out if there are none.
+    fn summarize_activity(&self, activity: &Activity, ascii: bool) -> String {
+        let lines = activity.lines();
+        let matches = activity.matches();
+        let mut summary = format!(
+            "[Activity per {}, Lines (peak {}): {}",
+            window_label(&activity.width()),
+            lines.iter().max().unwrap_or(&0),
+            bars(&lines, ascii)
+        );
+        if let Some(peak) = matches.iter().max().filter(|peak| **peak > 0) {
+            summary.push_str(&format!(
+                ", Matches (peak {}): {}",
+                peak,
+                bars(&matches, ascii)
+            ));
+        }
+        summary.push(']');
+        summary
+    }
+
+    /// Summarizes the `matches` of the `total` expected with `--total-matches`, after the summary,
+    /// with the `last_estimate` of the total time of the run, if any, and how far it was from the
+    /// `total_time` it took.
+    fn summarize_progress(
+        &self,
+        matches: u64,
+        total: u64,
+        last_estimate: Option<&Duration>,
+        total_time: &Duration,
+        time_format: &dyn TimeFormat,
+    ) -> String {
+        match last_estimate {
+            Some(estimate) => format!(
+                "[Matches: {}/{}, Estimated: {}, Took: {}, Off by: {}]",
+                matches,
+                total,
+                time_format.format_duration(estimate),
+                time_format.format_duration(total_time),
+                signed_error(estimate, total_time, time_format)
+            ),
+            None => format!("[Matches: {}/{}]", matches, total),
+        }
+    }
+
+    /// Summarizes the `flagged` lines whose delta was more than `sigma` standard deviations above
+    /// the mean, after the summary.
+    fn summarize_outliers(&self, flagged: usize, sigma: f64) -> String {
+        format!("[Outliers: {} above {}σ]", flagged, sigma)
+    }
+
+    /// Summarizes the values extracted into `series`, after the summary.
+    fn summarize_values(&self, series: &ValueSeries) -> String {
+        let format = |value: Option<f64>| value.map_or("-".to_string(), format_value);
+        let stats = &series.stats;
+        format!(
+            "[{}: Min: {}, Max: {}, Mean: {}, Last: {}, Values: {}, Unparsed: {}]",
+            series.name,
+            format(stats.min()),
+            format(stats.max()),
+            format((stats.count() > 0).then(|| stats.mean())),
+            format(series.last),
+            stats.count(),
+            series.unparsed
+        )
+    }
+
+    /// Summarizes the lines grouped by a capture group as a table with a row per group, most
+    /// lines first, and a final `other` row for the values beyond the cap, after the summary.
+    fn summarize_groups(&self, groups: &GroupStats, time_format: &dyn TimeFormat) -> String {
+        let interval = |secs: Option<f64>| {
+            secs.map_or("-".to_string(), |secs| {
+                time_format.format_duration(&Duration::from_secs_f64(secs))
+            })
+        };
+        let sorted = groups.sorted();
+        let mut rows = vec![[
+            groups.name().to_string(),
+            "Count".to_string(),
+            "Mean Interval".to_string(),
+            "Max Interval".to_string(),
+        ]];
+        for (value, group) in &sorted {
+            let stats = &group.intervals;
+            rows.push([
+                value.to_string(),
+                group.count.to_string(),
+                interval((stats.count() > 0).then(|| stats.mean())),
+                interval(stats.max()),
+            ]);
+        }
+        if groups.other() > 0 {
+            rows.push([
+                "other".to_string(),
+                groups.other().to_string(),
+                "-".to_string(),
+                "-".to_string(),
+            ]);
+        }
+        let widths: Vec<usize> = (0..4)
+            .map(|column| {
+                rows.iter()
+                    .map(|row| row[column].chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let mut table = format!("[Groups by {}: {}]", groups.name(), sorted.len());
+        for row in rows {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            table.push('\n');
+            table.push_str(cells.join("  ").trim_end());
+        }
+        table
+    }
+}
+
+/// Formats a number of bytes as a human-readable size in powers of 1024, such as `3.3 MiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Formats an extracted value with six significant digits, without trailing zeros.
+fn format_value(value: f64) -> String {
+    if value == 0.0 {
+        return "0".to_string();
+    }
+    let decimals = (5 - value.abs().log10().floor() as i32).clamp(0, 15) as usize;
+    let formatted = format!("{:.*}", decimals, value);
+    if formatted.contains('.') {
+        formatted
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string()
+    } else {
+        formatted
+    }
+}
+
+/// Formats a fraction (0 to 1) as a percentage with at most one decimal, as `62%` or `99.3%`.
+pub fn format_percent(fraction: f64) -> String {
+    let tenths = (fraction * 1000.0).round() as u64;
+    if tenths.is_multiple_of(10) {
+        format!("{}%", tenths / 10)
+    } else {
+        format!("{}.{}%", tenths / 10, tenths % 10)
+    }
+}
+
+/// The kinds of summary that can be selected on the command line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SummaryKind {
+    #[default]
+    Simple,
+    Detailed,
+    Stats,
+}
+
+impl std::str::FromStr for SummaryKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "simple" => Ok(SummaryKind::Simple),
+            "detailed" => Ok(SummaryKind::Detailed),
+            "stats" => Ok(SummaryKind::Stats),
+            _ => Err(format!(
+                "invalid summary '{}', expected 'simple', 'detailed' or 'stats'",
+                s
+            )),
+        }
+    }
+}
+
+impl SummaryKind {
+    /// Creates the summarizer of this kind.
+    pub fn summarizer(self, color: bool) -> Box<dyn Summarizer> {
+        match self {
+            SummaryKind::Simple => Box::new(SimpleSummarizer { color }),
+            SummaryKind::Detailed => Box::new(DetailedSummarizer { color }),
+            SummaryKind::Stats => Box::new(StatsSummarizer { color }),
+        }
+    }
 }
 
 /// A simple implementation of the `Summarizer` trait.
@@ -35,7 +435,10 @@ impl Summarizer for SimpleSummarizer {
         &self,
         total_lines: usize,
         total_matches: usize,
+        _total_bytes: u64,
         total_time: &Duration,
+        _overhead: &Duration,
+        _stats: &RunningStats,
         time_format: &dyn TimeFormat,
     ) -> String {
         let time_str = time_format.format_duration(total_time);
@@ -55,7 +458,8 @@ impl Summarizer for SimpleSummarizer {
     }
 }
 
-/// A detailed implementation of the `Summarizer` trait that also provides an average time per line.
+/// A detailed implementation of the `Summarizer` trait that also provides an average time per line
+/// and the time timeln spent processing lines, to judge how much it disturbed the measurements.
 pub struct DetailedSummarizer {
     pub color: bool,
 }
@@ -65,7 +469,10 @@ impl Summarizer for DetailedSummarizer {
         &self,
         total_lines: usize,
         total_matches: usize,
+        total_bytes: u64,
         total_time: &Duration,
+        overhead: &Duration,
+        _stats: &RunningStats,
         time_format: &dyn TimeFormat,
     ) -> String {
         let time_str = time_format.format_duration(total_time);
@@ -77,22 +484,70 @@ impl Summarizer for DetailedSummarizer {
             Duration::default()
         };
         let avg_time_str = time_format.format_duration(&avg_time_per_line);
+        let overhead_str = time_format.format_duration(overhead);
+        let bytes_str = format!("{} bytes, {}", total_bytes, format_bytes(total_bytes));
         if self.color {
             format!(
-                "Processed {} lines in {} with {} matches. Average time per line: {}",
-                total_lines, time_str, total_matches, avg_time_str
+                "Processed {} lines ({}) in {} with {} matches. Average time per line: {}. timeln overhead: {}",
+                total_lines, bytes_str, time_str, total_matches, avg_time_str, overhead_str
             )
             .green()
             .to_string()
         } else {
             format!(
-                "Processed {} lines in {} with {} matches. Average time per line: {}",
-                total_lines, time_str, total_matches, avg_time_str
+                "Processed {} lines ({}) in {} with {} matches. Average time per line: {}. timeln overhead: {}",
+                total_lines, bytes_str, time_str, total_matches, avg_time_str, overhead_str
             )
         }
     }
 }
 
+/// An implementation of the `Summarizer` trait that describes the distribution of the deltas.
+///
+/// All figures come from streaming statistics, so that the summary of an unbounded stream does
+/// not require retaining its snapshots. Percentiles are approximate.
+pub struct StatsSummarizer {
+    pub color: bool,
+}
+
+impl Summarizer for StatsSummarizer {
+    fn summarize(
+        &self,
+        total_lines: usize,
+        total_matches: usize,
+        _total_bytes: u64,
+        total_time: &Duration,
+        _overhead: &Duration,
+        stats: &RunningStats,
+        time_format: &dyn TimeFormat,
+    ) -> String {
+        let format = |secs: Option<f64>| match secs {
+            Some(secs) => time_format.format_duration(&Duration::from_secs_f64(secs.max(0.0))),
+            None => "-".to_string(),
+        };
+        let timed = (stats.count() > 0).then_some(());
+        let summary = format!(
+            "[Processed Lines: {}, Matches: {}, Total Time: {}, Timed Lines: {}, Mean: {}, Std Dev: {}, Min: {}, p50: {}, p95: {}, p99: {}, Max: {}]",
+            total_lines,
+            total_matches,
+            time_format.format_duration(total_time),
+            stats.count(),
+            format(timed.map(|_| stats.mean())),
+            format(timed.map(|_| stats.std_dev())),
+            format(stats.min()),
+            format(stats.percentile(50.0)),
+            format(stats.percentile(95.0)),
+            format(stats.percentile(99.0)),
+            format(stats.max()),
+        );
+        if self.color {
+            summary.green().to_string()
+        } else {
+            summary
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -105,7 +560,15 @@ mod tests {
         let time_format: Box<dyn TimeFormat> = Box::new(SecondsFormat);
         let total_lines = 100;
         let total_time = Duration::new(30, 0); // 30 seconds
-        let summary = summarizer.summarize(total_lines, 0, &total_time, &*time_format);
+        let summary = summarizer.summarize(
+            total_lines,
+            0,
+            3_500_000,
+            &total_time,
+            &Duration::ZERO,
+            &RunningStats::default(),
+            &*time_format,
+        );
         assert_eq!(
             summary,
             "[Processed Lines: 100, Matches: 0, Total Time: 30.00 s]"
@@ -118,10 +581,297 @@ mod tests {
         let time_format: Box<dyn TimeFormat> = Box::new(SecondsFormat);
         let total_lines = 100;
         let total_time = Duration::new(100, 0); // 100 seconds
-        let summary = summarizer.summarize(total_lines, 0, &total_time, &*time_format);
+        let summary = summarizer.summarize(
+            total_lines,
+            0,
+            3_500_000,
+            &total_time,
+            &Duration::ZERO,
+            &RunningStats::default(),
+            &*time_format,
+        );
         assert_eq!(
             summary,
-            "Processed 100 lines in 100.00 s with 0 matches. Average time per line: 1.00 s"
+            "Processed 100 lines (3500000 bytes, 3.3 MiB) in 100.00 s with 0 matches. Average time per line: 1.00 s. timeln overhead: 0.00 s"
+        );
+    }
+
+    #[test]
+    fn test_stats_summarizer() {
+        let summarizer = SummaryKind::Stats.summarizer(false);
+        let mut stats = RunningStats::default();
+        for delta in [1.0, 2.0, 3.0] {
+            stats.push(delta);
+        }
+        let summary = summarizer.summarize(
+            3,
+            1,
+            0,
+            &Duration::new(6, 0),
+            &Duration::ZERO,
+            &stats,
+            &SecondsFormat,
+        );
+        assert!(summary.starts_with("[Processed Lines: 3, Matches: 1, Total Time: 6.00 s, Timed Lines: 3, Mean: 2.00 s, Std Dev: 1.00 s, Min: 1.00 s, p50: "));
+        assert!(summary.ends_with("Max: 3.00 s]"));
+
+        let empty = summarizer.summarize(
+            0,
+            0,
+            0,
+            &Duration::new(0, 0),
+            &Duration::ZERO,
+            &RunningStats::default(),
+            &SecondsFormat,
+        );
+        assert!(empty.contains("Mean: -, Std Dev: -, Min: -, p50: -"));
+    }
+
+    #[test]
+    fn test_default_lap_summaries() {
+        let summarizer = SummaryKind::Simple.summarizer(false);
+        let lap = Lap {
+            number: 2,
+            lines: 10,
+            matches: 3,
+            duration: Duration::from_millis(1500),
+        };
+        assert_eq!(
+            summarizer.summarize_lap(&lap, &SecondsFormat),
+            "[Lap 2: Lines: 10, Matches: 3, Time: 1.50 s]"
+        );
+        assert_eq!(
+            summarizer.summarize_laps(4, &Duration::from_secs(2), &SecondsFormat),
+            "[Laps: 4, Mean Lap Time: 2.00 s]"
+        );
+        assert_eq!(
+            summarizer.summarize_file("build1.log", &lap, &SecondsFormat),
+            "[File build1.log: Lines: 10, Matches: 3, Span: 1.50 s]"
+        );
+    }
+
+    #[test]
+    fn test_default_notification_messages() {
+        let summarizer = SummaryKind::Simple.summarizer(true);
+        assert_eq!(
+            summarizer.summarize_finished(1243, &Duration::from_secs(842), &SecondsFormat),
+            "timeln: finished, 1243 lines in 842.00 s"
+        );
+        assert_eq!(
+            summarizer.summarize_first_match(
+                "ERROR",
+                "ERROR disk full",
+                &Duration::from_millis(2500),
+                &SecondsFormat
+            ),
+            "timeln: 'ERROR' matched after 2.50 s: ERROR disk full"
+        );
+    }
+
+    #[test]
+    fn test_default_phase_summary() {
+        let summarizer = SummaryKind::Stats.summarizer(false);
+        let phase = Phase {
+            index: 2,
+            first_line: 51,
+            first_timed: 50,
+            start: Duration::from_millis(500),
+            duration: Duration::from_secs(10),
+            lines: 50,
+            mean_delta: Duration::from_millis(200),
+        };
+        assert_eq!(
+            summarizer.summarize_phase(&phase, &SecondsFormat),
+            "[Phase 2: From Line: 51, Lines: 50, Start: 0.50 s, Duration: 10.00 s, Mean Delta: 0.20 s]"
+        );
+    }
+
+    #[test]
+    fn test_default_gap_summary() {
+        let summarizer = SummaryKind::Detailed.summarizer(false);
+        let gap = SlowLine {
+            delta: Duration::from_millis(12300),
+            line_number: 120,
+            text: "Compiling foo".to_string(),
+            elapsed: Duration::from_secs(45),
+            previous: "fetching deps".to_string(),
+        };
+        assert_eq!(
+            summarizer.summarize_gap(1, &gap, &SecondsFormat),
+            "[Gap 1: Duration: 12.30 s, Ended At: 45.00 s, Line: 120, Before: \"fetching deps\", After: \"Compiling foo\"]"
+        );
+    }
+
+    #[test]
+    fn test_default_ewma_summary() {
+        let summarizer = SummaryKind::Simple.summarizer(false);
+        assert_eq!(
+            summarizer.summarize_ewma(&Duration::from_millis(510), 0.1, &SecondsFormat),
+            "[EWMA Delta: 0.51 s (alpha 0.1)]"
+        );
+    }
+
+    #[test]
+    fn test_default_rate_summary() {
+        let summarizer = SummaryKind::Simple.summarizer(false);
+        assert_eq!(
+            summarizer.summarize_rates(
+                &Duration::from_millis(250),
+                &Duration::ZERO,
+                &Duration::from_secs(2)
+            ),
+            "[Rate: Mean: 4.00 Hz, Min: 0.50 Hz, Max: 1000.00 kHz]"
+        );
+    }
+
+    #[test]
+    fn test_default_progress_summary() {
+        let summarizer = SummaryKind::Simple.summarizer(false);
+        assert_eq!(
+            summarizer.summarize_progress(40, 100, None, &Duration::from_secs(30), &SecondsFormat),
+            "[Matches: 40/100]"
         );
+        assert_eq!(
+            summarizer.summarize_progress(
+                100,
+                100,
+                Some(&Duration::from_secs(60)),
+                &Duration::from_millis(63_200),
+                &SecondsFormat
+            ),
+            "[Matches: 100/100, Estimated: 60.00 s, Took: 63.20 s, Off by: +3.20 s]"
+        );
+    }
+
+    #[test]
+    fn test_default_cdf_summary() {
+        let summarizer = SummaryKind::Simple.summarizer(false);
+        let points = [
+            (Duration::from_millis(10), 0.62),
+            (Duration::from_millis(100), 0.91),
+            (Duration::from_secs(1), 0.993),
+        ];
+        assert_eq!(
+            summarizer.summarize_cdf(&points),
+            "[Deltas Within: ≤10ms: 62%, ≤100ms: 91%, ≤1s: 99.3%]"
+        );
+        // The thresholds picked for a fast run are below what the time format shows.
+        let fast = [
+            (Duration::from_micros(10), 0.2),
+            (Duration::from_micros(100), 0.667),
+            (Duration::from_millis(1), 1.0),
+        ];
+        assert_eq!(
+            summarizer.summarize_cdf(&fast),
+            "[Deltas Within: ≤10us: 20%, ≤100us: 66.7%, ≤1ms: 100%]"
+        );
+        assert_eq!(format_percent(0.0), "0%");
+        assert_eq!(format_percent(0.99951), "100%");
+        assert_eq!(format_percent(0.0004), "0%");
+    }
+
+    #[test]
+    fn test_default_burst_summaries() {
+        let summarizer = SummaryKind::Simple.summarizer(false);
+        let threshold = BurstThreshold {
+            lines: 100,
+            window: Duration::from_secs(1),
+        };
+        assert_eq!(
+            summarizer.summarize_bursts(2, &threshold),
+            "[Bursts: 2 above 100 lines/1s]"
+        );
+        let burst = Burst {
+            index: 1,
+            start: Duration::from_millis(3200),
+            duration: Duration::from_millis(1400),
+            lines: 240,
+        };
+        assert_eq!(
+            summarizer.summarize_burst(&burst, &SecondsFormat),
+            "[Burst 1: Start: 3.20 s, Duration: 1.40 s, Lines: 240]"
+        );
+    }
+
+    #[test]
+    fn test_default_activity_summary() {
+        let summarizer = SummaryKind::Simple.summarizer(false);
+        let mut activity = Activity::new(Duration::from_secs(60), 60);
+        for (secs, matched) in [(0, false), (10, true), (130, false)] {
+            activity.push(Duration::from_secs(secs), matched);
+        }
+        assert_eq!(
+            summarizer.summarize_activity(&activity, false),
+            "[Activity per 60s, Lines (peak 2): █ ▄, Matches (peak 1): █  ]"
+        );
+        let mut idle = Activity::new(Duration::from_secs(1), 60);
+        idle.push(Duration::ZERO, false);
+        idle.extend_to(Duration::from_secs(1));
+        assert_eq!(
+            summarizer.summarize_activity(&idle, true),
+            "[Activity per 1s, Lines (peak 1): # ]"
+        );
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(5 << 30), "5.0 GiB");
+        assert_eq!(format_bytes(u64::MAX), "16777216.0 TiB");
+    }
+
+    #[test]
+    fn test_default_pattern_summary() {
+        let summarizer = SummaryKind::Simple.summarizer(false);
+        assert_eq!(
+            summarizer.summarize_patterns(&[("err", 2), ("p1", 0)]),
+            "[Pattern Matches: err: 2, p1: 0]"
+        );
+    }
+
+    #[test]
+    fn test_default_value_summary() {
+        let summarizer = SummaryKind::Simple.summarizer(false);
+        let mut series = ValueSeries::new("loss");
+        for text in ["0.5", "0.0123", "oops", "0.1"] {
+            series.push(text, Duration::ZERO);
+        }
+        assert_eq!(
+            summarizer.summarize_values(&series),
+            "[loss: Min: 0.0123, Max: 0.5, Mean: 0.2041, Last: 0.1, Values: 3, Unparsed: 1]"
+        );
+        assert_eq!(
+            summarizer.summarize_values(&ValueSeries::new("acc")),
+            "[acc: Min: -, Max: -, Mean: -, Last: -, Values: 0, Unparsed: 0]"
+        );
+        assert_eq!(format_value(1234567.0), "1234567");
+        assert_eq!(format_value(-0.000012345678), "-0.0000123457");
+    }
+
+    #[test]
+    fn test_default_group_summary() {
+        let summarizer = SummaryKind::Simple.summarizer(false);
+        let regex = regex::Regex::new(r"worker-(?P<id>\d+)").unwrap();
+        let mut groups = GroupStats::new("id", vec![regex]);
+        for (at, line) in [(0, "worker-7"), (1, "worker-12"), (3, "worker-7")] {
+            groups.line(line, Duration::from_secs(at));
+        }
+        assert_eq!(
+            summarizer.summarize_groups(&groups, &SecondsFormat),
+            "[Groups by id: 2]\n\
+             id  Count  Mean Interval  Max Interval\n\
+             7   2      3.00 s         3.00 s\n\
+             12  1      -              -"
+        );
+    }
+
+    #[test]
+    fn test_summary_kind_from_str() {
+        assert_eq!("simple".parse(), Ok(SummaryKind::Simple));
+        assert_eq!("detailed".parse(), Ok(SummaryKind::Detailed));
+        assert_eq!("stats".parse(), Ok(SummaryKind::Stats));
+        assert!("fancy".parse::<SummaryKind>().is_err());
     }
 }
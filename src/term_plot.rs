@@ -0,0 +1,160 @@
+//! This module renders rough character-based plots for terminals that cannot display SVG files.
+//!
+//! The `render_term_plot` function rasterizes a series of values onto a fixed-height grid of
+//! characters, labelling the y-axis with the minimum and maximum values. Series that are longer
+//! than the available width are downsampled by keeping the largest value in each column, so that
+//! spikes are never hidden.
+//!
+//! # Example
+//!
+//! ```
+//! use timeln::term_plot::render_term_plot;
+//!
+//! let deltas = vec![0.1, 0.2, 0.3, 0.4, 0.5];
+//! let plot = render_term_plot("Time delta", &deltas, 40, 15, false);
+//! println!("{}", plot);
+//! ```
+use colored::Colorize;
+
+use crate::plot::format_axis_seconds;
+
+/// Default number of rows used for terminal plots.
+pub const TERM_PLOT_HEIGHT: usize = 15;
+
+/// Returns the width of the terminal in columns, falling back to 80 if it cannot be determined.
+pub fn terminal_width() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|cols| cols.parse::<usize>().ok())
+        .filter(|&cols| cols > 0)
+        .unwrap_or(80)
+}
+
+/// Reduces `values` to at most `width` points by keeping the maximum of each bucket.
+fn downsample(values: &[f64], width: usize) -> Vec<f64> {
+    if values.len() <= width {
+        return values.to_vec();
+    }
+    (0..width)
+        .map(|col| {
+            let start = col * values.len() / width;
+            let end = ((col + 1) * values.len() / width).max(start + 1);
+            values[start..end]
+                .iter()
+                .cloned()
+                .fold(f64::NEG_INFINITY, f64::max)
+        })
+        .collect()
+}
+
+/// Renders `values` (in seconds) as a character plot `width` columns wide and `height` rows tall.
+///
+/// # Arguments
+///
+/// * `title` - A title printed above the plot.
+/// * `values` - The series to plot, one point per line.
+/// * `width` - The total width of the output, including the axis labels.
+/// * `height` - The number of rows in the plot area.
+/// * `color` - Whether to colorize the plotted points.
+///
+/// # Returns
+///
+/// A multi-line `String` containing the plot, without a trailing newline.
+pub fn render_term_plot(
+    title: &str,
+    values: &[f64],
+    width: usize,
+    height: usize,
+    color: bool,
+) -> String {
+    if values.is_empty() {
+        return format!("{}\n(no data)", title);
+    }
+
+    let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let max_label = format_axis_seconds(max);
+    let min_label = format_axis_seconds(min);
+    let label_width = max_label.chars().count().max(min_label.chars().count());
+
+    let height = height.max(2);
+    let plot_width = width.saturating_sub(label_width + 2).max(1);
+    let points = downsample(values, plot_width);
+
+    let mut grid = vec![vec![' '; points.len()]; height];
+    for (col, value) in points.iter().enumerate() {
+        let row = if max > min {
+            ((value - min) / (max - min) * (height - 1) as f64).round() as usize
+        } else {
+            0
+        };
+        grid[height - 1 - row][col] = '*';
+    }
+
+    let mut lines = vec![title.to_string()];
+    for (i, row) in grid.iter().enumerate() {
+        let label = if i == 0 {
+            max_label.as_str()
+        } else if i == height - 1 {
+            min_label.as_str()
+        } else {
+            ""
+        };
+        let row: String = row.iter().collect();
+        let row = if color { row.green().to_string() } else { row };
+        lines.push(format!("{:>width$} |{}", label, row, width = label_width));
+    }
+    lines.push(format!(
+        "{:>width$} +{}",
+        "",
+        "-".repeat(points.len()),
+        width = label_width
+    ));
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_term_plot_golden() {
+        let values = vec![0.0, 1.0, 2.0, 3.0];
+        let plot = render_term_plot("Time delta", &values, 9, 4, false);
+        let expected = [
+            "Time delta",
+            " 3s |   *",
+            "    |  * ",
+            "    | *  ",
+            "0ns |*   ",
+            "    +----",
+        ]
+        .join("\n");
+        assert_eq!(plot, expected);
+    }
+
+    #[test]
+    fn test_render_term_plot_downsamples_to_width() {
+        let values: Vec<f64> = (0..1000).map(|i| i as f64 * 0.001).collect();
+        let plot = render_term_plot("Time delta", &values, 40, 15, false);
+        for line in plot.lines().skip(1) {
+            assert!(line.chars().count() <= 40);
+        }
+        assert_eq!(plot.lines().count(), 1 + 15 + 1);
+    }
+
+    #[test]
+    fn test_render_term_plot_empty() {
+        assert_eq!(
+            render_term_plot("Time delta", &[], 40, 15, false),
+            "Time delta\n(no data)"
+        );
+    }
+
+    #[test]
+    fn test_downsample_keeps_spikes() {
+        let values = vec![0.1, 0.1, 5.0, 0.1, 0.1, 0.1];
+        assert_eq!(downsample(&values, 3), vec![0.1, 5.0, 0.1]);
+    }
+}
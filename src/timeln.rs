@@ -4,357 +4,6511 @@
 //!
 //! # Usage
 //!
-//! To use the timeln functionality, create a `TimelnContext` instance with the desired options using the `new` method. Then, call the `run` method to start the main loop of reading from stdin, annotating the lines, and sending the duration to the receiver. Finally, you can call the `summarize_and_plot` method to print a summary of the results and optionally plot the data.
+//! To use the timeln functionality, create a `TimelnContext` instance with the desired options using the `new` method, or with `TimelnContext::builder` to replace its input, output, time format, annotator or summarizer. Call `handle_signals` so that an interrupted or terminated run still prints its summary. Then, call the `run` method to start the main loop of reading from stdin, writing the annotated lines to stdout, and handing each snapshot to the snapshot sink. Finally, you can call the `summarize_and_plot` method to print a summary of the results and optionally plot the data.
 //!
 //! ## Example
 //!
-//! ```
-//! use crate::timeln::{TimelnContext, TimeSnapshot};
-//! use crate::argopt::TimelnOpt;
-//! use crate::reader::StdinReadData;
-//! use std::time::Duration;
+//! ```no_run
+//! use timeln::argopt::TimelnOpt;
+//! use timeln::TimelnContext;
 //!
 //! let opt = TimelnOpt {
 //!     color: false,
-//!     regex: None,
-//!     plot: false,
+//!     regex: vec![],
+//!     plot: true,
+//!     ..Default::default()
 //! };
 //!
 //! let mut context = TimelnContext::new(opt).unwrap();
 //!
-//! // Override the default stdin reader with a custom reader
-//! let custom_reader = StdinReadData { /* custom reader implementation */ };
-//! context.stdin = Box::new(custom_reader);
+//! // Print the summary even if the run is interrupted or terminated, then exit with status 130
+//! context.handle_signals(130).unwrap();
 //!
-//! // Run the timeln module
+//! // Time the lines of stdin until EOF
 //! context.run().unwrap();
 //!
-//! // Send a duration to the receiver
-//! let duration = Duration::from_secs(1);
-//! context.tx.send(TimeSnapshot { delta: duration, elapsed: duration }).unwrap();
-//!
-//! // Receive and process the duration
-//! let rx_lock = context.rx.lock().unwrap();
-//! let received_snapshot = rx_lock.try_recv().unwrap();
-//! // ...
-//!
 //! // Print a summary and plot the data
 //! context.summarize_and_plot().unwrap();
 //! ```
 //!
-//! # Testing
+//! To time lines from another source without printing summaries or plots, use `Pipeline`.
 //!
-//! The module includes unit tests for the `TimelnContext` struct and its methods. The tests cover the creation of a new context, sending and receiving durations, and running the main loop with test data. These tests ensure the correctness and functionality of the timeln module.
+//! # Snapshots
 //!
-//! Note: The `TimelnContext` struct and related types are intended for demonstration purposes and may require additional error handling and customization for your application's specific needs.
+//! The main loop hands the `TimeSnapshot` of each timed line to a `SnapshotSink`, chosen from the options when the context is built. The sink keeps a sample of the snapshots in memory for the plots and the report, streams them to the plot data file, or drops them when nothing reads them. It is shared with the finalizer behind a mutex rather than fed through a channel, so that a run finished by a signal handler still plots the snapshots so far.
 //!
-//! Note: The example and test code snippets assume the existence of certain types, such as `TimelnOpt`, `StdinReadData`, and `TimeSnapshot`. Please adjust the code according to your project structure and dependencies.
+//! # Testing
+//!
+//! The unit tests at the end of this module run contexts over in-memory readers and outputs, and check the annotated lines, the summaries and the files written at the end of the run.
 //!
 //! # Dependencies
 //!
 //! This module relies on several external dependencies:
 //! - `std::io::{self}`: Provides input/output functionality.
 //! - `std::time::{Instant, Duration}`: Enables time-related operations and measurements.
-//! - `colored::*`: Facilitates text coloring for line annotations.
-//! - `regex::Regex`: Supports regular expression matching for line filtering.
 //! - `std::sync::{Arc, Mutex}`: Provides synchronization primitives for multi-threaded environments.
-//! - `std::sync::mpsc::{self, Receiver, Sender}`: Implements message passing between threads.
-//! - `crate::annotator::{TimelnAnnotation, SimpleAnnotator}`: Provides line annotation functionality.
+//! - `std::sync::atomic::AtomicUsize`: Counts lines and matches without locking.
+//! - `crate::annotator::SimpleAnnotator`: Provides line annotation functionality.
+//! - `crate::pipeline::Pipeline`: Filters, times and annotates each line.
 //! - `crate::formatter::{SecondsFormat}`: Defines formatting options for time durations.
-//! - `crate::summarizer::{Summarizer, SimpleSummarizer}`: Implements result summarization.
+//! - `crate::summarizer::{Summarizer, SummaryKind}`: Implements result summarization.
+//! - `crate::stats::RunningStats`: Accumulates the statistics of the deltas as lines arrive.
 //! - `crate::plot::{plot_deltas, plot_times}`: Offers plotting capabilities for duration
-use colored::*;
-use regex::Regex;
-use std::io::{self};
-use std::time::{Duration, Instant};
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once, Weak};
+use std::thread;
 
-use std::sync::mpsc::{self, Receiver, Sender};
-use std::sync::{Arc, Mutex};
+use colored::{Color, Colorize};
+use regex::Regex;
 
-use crate::annotator::{SimpleAnnotator, TimelnAnnotation};
+use crate::activity::{Activity, DEFAULT_ACTIVITY_BUCKET, MAX_ACTIVITY_BUCKETS};
+use crate::annotator::{ColumnWidths, SimpleAnnotator, TimelnAnnotation, TsAnnotator, TsMode};
 use crate::argopt::TimelnOpt;
-use crate::error::TimelnError;
-use crate::formatter::SecondsFormat;
-use crate::plot::{plot_deltas, plot_times};
-use crate::reader::{ReadData, StdinReadData};
-use crate::summarizer::{SimpleSummarizer, Summarizer};
+use crate::burst::{BurstDetector, BurstThreshold};
+use crate::context::{ContextLines, CONTEXT_PREFIX, MATCH_PREFIX};
+use crate::csv::CsvColumns;
+use crate::dashboard::{self, Dashboard, DashboardStats, DASHBOARD_DELTAS};
+use crate::decompress::DecompressReadData;
+use crate::desktop::DesktopNotifier;
+use crate::error::{PatternError, TimelnError};
+use crate::events::{
+    lap_event, record_event, record_event_with_rate, EventWriter, DEFAULT_EVENT_BUFFER,
+};
+use crate::extract::Extractor;
+use crate::formatter::{SecondsFormat, TimeFormat};
+use crate::group::GroupStats;
+use crate::heat::{self, Heat};
+use crate::hook::{MatchHook, SlowHook, DEFAULT_HOOK_INTERVAL};
+use crate::journal::{journalctl_command, JournalPriority};
+use crate::jsonlog::{JsonFields, DEFAULT_TIME_FIELD};
+use crate::keys::{self, KeyCommand};
+use crate::live::{LiveServer, LiveStats, RecentDeltas, SPARKLINE_DELTAS};
+use crate::metrics::{self, DeltaSummary, Metrics, DEFAULT_TEXTFILE_INTERVAL};
+use crate::notify::{Notifier, RunEnd, DEFAULT_NOTIFY_INTERVAL};
+use crate::parquet::{ParquetWriter, DEFAULT_BATCH_ROWS};
+use crate::pause::PauseClock;
+use crate::phase::{PhaseDetector, DEFAULT_PHASE_RATIO, DEFAULT_PHASE_WINDOW};
+use crate::pipeline::{CaptureGroup, Pipeline};
+use crate::plot::{
+    plot_deltas, plot_times, plot_values, write_plot_data, PlotConfig, PlotData, PlotError,
+    PlotSample, PlotSize, PlotX, DEFAULT_PLOT_MAX_POINTS,
+};
+use crate::progress::MatchProgress;
+#[cfg(unix)]
+use crate::reader::SocketReadData;
+use crate::reader::{
+    ExecReadData, FileReadData, FilesReadData, FollowMode, JoinBoundary, JoinReadData,
+    JournalReadData, JsonReadData, LogfmtReadData, ParsedTimeReadData, ReadData, ReadOutcome,
+    ReplayPace, ReplayReadData, Stream, TcpReadData, ThreadedReadData, DEFAULT_CONNECT_TIMEOUT,
+    DEFAULT_FOLLOW_INTERVAL,
+};
+use crate::record::{Record, RecordWriter};
+use crate::report::{write_report, Report, SlowestLines, REPORT_SLOWEST_LINES};
+use crate::reported::{format_difference, SelfReported};
+use crate::screen::{ScreenStats, SummaryScreen, SCREEN_INTERVAL, SCREEN_SLOWEST};
+use crate::signal;
+use crate::sink::{SnapshotSink, DEFAULT_PLOT_MAX_SAMPLES};
+use crate::span::SpanTracker;
+use crate::sqlite::{RunTotals, SqliteStore};
+use crate::stats::{
+    is_outlier, window_label, RunningStats, SlidingWindow, DEFAULT_EWMA_ALPHA,
+    DEFAULT_OUTLIER_WARMUP, DEFAULT_RATE_WINDOW, DEFAULT_TREND_TOLERANCE,
+};
+use crate::statsd::{self, StatsdEmitter};
+use crate::status::{terminal_enabled, StatusDisplay, StatusTicker, STATUS_INTERVAL};
+use crate::summarizer::{Lap, Summarizer, SummaryKind};
+use crate::term_plot::{render_term_plot, terminal_width, TERM_PLOT_HEIGHT};
+use crate::timestamp::{TimestampCounts, TimestampParser, DEFAULT_TIME_LAYOUT};
 
 /// Information Collected at Each Line
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Default)]
 pub struct TimeSnapshot {
-    delta: Duration,
-    elapsed: Duration,
+    /// Time since the previous timed line.
+    pub delta: Duration,
+    /// Time since the start of the run.
+    pub elapsed: Duration,
+    /// Index of the regex pattern the line matched, if any.
+    pub pattern: Option<usize>,
+    /// Number of the line in the input, counting from 1.
+    pub line_no: usize,
+    /// Length of the line as read, line ending included, in bytes.
+    pub bytes: usize,
 }
 
-impl TimeSnapshot {
-    /// Create a defualt instance of TimeSnapshot.
-    pub fn default() -> Self {
-        Self {
-            delta: Duration::new(0, 0),
-            elapsed: Duration::new(0, 0),
+/// Converts the collected snapshots, each with its index among the `total` timed lines, into
+/// the series drawn by the plots.
+fn plot_data(durations: &[(usize, TimeSnapshot)], total: usize) -> PlotData {
+    PlotData {
+        times: durations
+            .iter()
+            .map(|(_, dur)| dur.elapsed.as_secs_f64())
+            .collect(),
+        deltas: durations
+            .iter()
+            .map(|(_, dur)| dur.delta.as_secs_f64())
+            .collect(),
+        patterns: durations.iter().map(|(_, dur)| dur.pattern).collect(),
+        sample: (total > durations.len()).then(|| PlotSample {
+            indices: durations.iter().map(|(index, _)| *index).collect(),
+            total,
+        }),
+    }
+}
+
+/// Writes `deltas.svg` and `times.svg` for the collected snapshots to `dir`, attempting both
+/// even if the first fails, and keeps the first failure in `failure`.
+fn write_plots(
+    data: &PlotData,
+    config: &PlotConfig,
+    dir: &Path,
+    failure: &mut Option<TimelnError>,
+) {
+    let deltas = dir.join("deltas.svg").display().to_string();
+    keep_first_error(
+        failure,
+        plot_deltas(data, &deltas, config).map_err(|err| TimelnError::plot(&deltas, err)),
+    );
+    let times = dir.join("times.svg").display().to_string();
+    keep_first_error(
+        failure,
+        plot_times(data, &times, config).map_err(|err| TimelnError::plot(&times, err)),
+    );
+}
+
+/// Plots each series of extracted values to its own SVG file in `dir`, named after the series,
+/// and keeps the first failure in `failure`.
+fn write_value_plots(
+    extractors: &[Extractor],
+    config: &PlotConfig,
+    dir: &Path,
+    failure: &mut Option<TimelnError>,
+) {
+    for series in extractors.iter().flat_map(Extractor::series) {
+        let filename = dir
+            .join(value_plot_filename(&series.name))
+            .display()
+            .to_string();
+        keep_first_error(
+            failure,
+            plot_values(series, &filename, config).map_err(|err| TimelnError::plot(&filename, err)),
+        );
+    }
+}
+
+/// Compiles every regex pattern of `opt`, so that all the invalid ones are reported together
+/// rather than one per run. A single invalid pattern is reported on its own.
+fn validate_patterns(opt: &TimelnOpt) -> Result<(), TimelnError> {
+    let mut patterns: Vec<(String, &String)> = Vec::new();
+    let no_patterns = Vec::new();
+    for (flag, given) in [
+        ("--regex", &opt.regex),
+        ("--between", &opt.between),
+        // With --logfmt, the values of --extract are keys rather than patterns.
+        (
+            "--extract",
+            if opt.logfmt {
+                &no_patterns
+            } else {
+                &opt.extract
+            },
+        ),
+    ] {
+        for (i, pattern) in given.iter().enumerate() {
+            // Number the patterns of an option given several, so that each can be found.
+            let flag = match given.len() {
+                1 => flag.to_string(),
+                _ => format!("{} #{}", flag, i + 1),
+            };
+            patterns.push((flag, pattern));
+        }
+    }
+    for (flag, given) in [
+        ("--reset-on", &opt.reset_on),
+        ("--self-reported", &opt.self_reported),
+        ("--join-until", &opt.join_until),
+    ] {
+        patterns.extend(given.iter().map(|pattern| (flag.to_string(), pattern)));
+    }
+    let mut errors: Vec<PatternError> = patterns
+        .into_iter()
+        .filter_map(|(flag, pattern)| {
+            let source = Regex::new(pattern).err()?;
+            Some(PatternError {
+                flag,
+                pattern: pattern.clone(),
+                source,
+            })
+        })
+        .collect();
+    match errors.len() {
+        0 => Ok(()),
+        1 => {
+            let error = errors.remove(0);
+            Err(TimelnError::regex(&error.pattern, error.source))
         }
+        _ => Err(TimelnError::MultiplePatternErrors(errors)),
     }
 }
 
-/// The main context struct for running the timeln module.
-/// It holds the state of the input and the options for processing the input.
-pub struct TimelnContext {
-    stdin: Box<dyn ReadData>,
-    annotator: SimpleAnnotator,
-    summarizer: Arc<Box<dyn Summarizer>>,
-    total_lines: Arc<Mutex<usize>>,
-    total_matches: Arc<Mutex<usize>>,
-    regex: Option<Regex>,
-    tx: Sender<TimeSnapshot>,
-    rx: Arc<Mutex<Receiver<TimeSnapshot>>>,
-    start_time: Instant,
-    plot: bool,
+/// Keeps the first error of writing the plots, plot data and report in `first`, and logs the
+/// later ones to stderr.
+fn keep_first_error(first: &mut Option<TimelnError>, result: Result<(), TimelnError>) {
+    match (result, &first) {
+        (Ok(()), _) => {}
+        (Err(err), None) => *first = Some(err),
+        (Err(err), Some(_)) => eprintln!("timeln: {}", err),
+    }
 }
 
-impl TimelnContext {
-    /// Creates a new instance of TimelnContext from a given set of options.
-    pub fn new(opt: TimelnOpt) -> Result<Self, TimelnError> {
-        let stdin = io::stdin();
-        let read_data: Box<dyn ReadData> = Box::new(StdinReadData {
-            stdin: stdin.lock(),
-        });
-        let start_time = Instant::now();
-        let time_format = SecondsFormat {};
-        let annotator = SimpleAnnotator {
-            color: opt.color,
-            time_format: Arc::new(Box::new(time_format.clone())),
-        };
+/// Name of the SVG file of the plot of the values named `name`, such as `values-loss.svg`, with
+/// any character that is unsafe in a file name replaced by an underscore.
+fn value_plot_filename(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("values-{}.svg", name)
+}
 
-        let regex = if let Some(r) = opt.regex {
-            Some(Regex::new(&r)?)
-        } else {
-            None
-        };
+/// Renders the deltas and elapsed times of the collected snapshots as terminal plots.
+fn render_term_plots(data: &PlotData, color: bool) -> String {
+    let width = terminal_width();
+    format!(
+        "{}\n{}",
+        render_term_plot("Time delta", &data.deltas, width, TERM_PLOT_HEIGHT, color),
+        render_term_plot("Time elapsed", &data.times, width, TERM_PLOT_HEIGHT, color)
+    )
+}
 
-        let summarizer: Arc<Box<dyn Summarizer>> =
-            Arc::new(Box::new(SimpleSummarizer { color: opt.color }));
+/// Writes the HTML report for the collected snapshots to `path`.
+#[allow(clippy::too_many_arguments)]
+fn write_report_file(
+    path: &str,
+    data: &PlotData,
+    stats: &RunningStats,
+    slowest: &SlowestLines,
+    total_lines: usize,
+    total_matches: usize,
+    total_bytes: u64,
+    total_time: Duration,
+    plot_config: &PlotConfig,
+    time_format: &dyn TimeFormat,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let slowest = slowest.to_sorted_vec();
+    let report = Report {
+        total_lines,
+        total_matches,
+        total_bytes,
+        total_time,
+        data,
+        stats,
+        slowest: &slowest,
+        plot_config,
+    };
+    write_report(path, &report, time_format)
+}
 
-        let total_lines = Arc::new(Mutex::new(0));
-        let total_matches = Arc::new(Mutex::new(0));
+/// Treats a write to a closed pipe, such as the output of `timeln | head`, as successful, so that
+/// the files written after it are still written.
+fn ignore_broken_pipe(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
 
-        let (tx, rx) = mpsc::channel::<TimeSnapshot>();
-        let rx = Arc::new(Mutex::new(rx));
+/// A run of consecutive lines faster than `--collapse-faster-than`, printed as a single
+/// placeholder once a slower line arrives or the run ends.
+#[derive(Debug, Default)]
+struct CollapsedRun {
+    lines: usize,
+    /// Sum of the deltas of the lines.
+    duration: Duration,
+}
 
-        Ok(Self {
-            stdin: read_data,
-            annotator,
-            summarizer,
-            total_lines,
-            total_matches,
-            regex,
-            tx,
-            rx,
-            start_time,
-            plot: opt.plot,
-        })
+impl CollapsedRun {
+    fn placeholder(&self, time_format: &dyn TimeFormat) -> String {
+        format!(
+            "[… {} {} in {}]",
+            self.lines,
+            if self.lines == 1 { "line" } else { "lines" },
+            time_format.format_duration(&self.duration)
+        )
     }
+}
 
-    /// Runs the main loop of reading from stdin, annotating the lines and sending the duration to the receiver.
-    pub fn run(&mut self) -> Result<(), TimelnError> {
-        let mut last_time = Instant::now();
-        let mut buffer = String::new();
+/// Marker of the lines flagged as outliers with `--flag-outliers`.
+const OUTLIER_MARKER: &str = "⚠";
 
-        let total_lines_ctrlc = self.total_lines.clone();
-        let total_matches_ctrlc = self.total_matches.clone();
-        let summarizer_ctrlc = self.summarizer.clone();
-        let start_time_ctrlc = self.start_time;
-        let rx_ctrlc = Arc::clone(&self.rx);
-        let time_format_ctrlc = self.annotator.time_format.clone();
-
-        ctrlc::set_handler(move || {
-            let total_lines = total_lines_ctrlc.lock().unwrap();
-            let total_matches = total_matches_ctrlc.lock().unwrap();
-            println!(
-                "{}",
-                summarizer_ctrlc.summarize(
-                    *total_lines,
-                    *total_matches,
-                    &Instant::now().duration_since(start_time_ctrlc),
-                    &**time_format_ctrlc
-                )
-            );
+/// Flags the lines whose delta is an outlier of the deltas before them, with `--flag-outliers`.
+#[derive(Debug)]
+struct Outliers {
+    sigma: f64,
+    warmup: u64,
+    flagged: AtomicUsize,
+}
 
-            let rx_lock = rx_ctrlc.lock().unwrap();
-            let durations: Vec<_> = rx_lock.try_iter().collect();
-            let deltas: Vec<f64> = durations
-                .iter()
-                .map(|&dur| dur.delta.as_secs_f64())
-                .collect();
-            let times: Vec<f64> = durations
-                .iter()
-                .map(|&dur| dur.elapsed.as_secs_f64())
-                .collect();
-            plot_deltas(&deltas, "deltas.svg").unwrap();
-            plot_times(&times, "times.svg").unwrap();
-            std::process::exit(0);
+/// Accumulates the laps of a run with `--reset-on`. Times are measured from the start of the run.
+#[derive(Debug, Default)]
+struct Laps {
+    /// Start of the current lap, and its lines and matches so far.
+    current: Option<(Duration, usize, usize)>,
+    count: usize,
+    total: Duration,
+}
+
+impl Laps {
+    /// Starts a new lap at `at`, ending the current one, which is returned.
+    fn start(&mut self, at: Duration) -> Option<Lap> {
+        let ended = self.finish(at);
+        self.current = Some((at, 0, 0));
+        ended
+    }
+
+    /// Counts a line of the current lap, if any.
+    fn line(&mut self, matched: bool) {
+        if let Some((_, lines, matches)) = &mut self.current {
+            *lines += 1;
+            *matches += usize::from(matched);
+        }
+    }
+
+    /// Ends the current lap at `at`, returning it.
+    fn finish(&mut self, at: Duration) -> Option<Lap> {
+        let (start, lines, matches) = self.current.take()?;
+        self.count += 1;
+        let duration = at.saturating_sub(start);
+        self.total += duration;
+        Some(Lap {
+            number: self.count,
+            lines,
+            matches,
+            duration,
         })
-        .expect("Error setting Ctrl-C handler");
+    }
 
-        loop {
-            buffer.clear();
-            let bytes_read = self.stdin.read_line(&mut buffer)?;
-            if bytes_read == 0 {
-                // EOF
-                break;
-            }
-            let mut total_lines_guard = self.total_lines.lock()?;
-            *total_lines_guard += 1;
-
-            let now = Instant::now();
-
-            if let Some(re) = &self.regex {
-                match re.captures_iter(&buffer).next() {
-                    Some(cap) => {
-                        let delta = now.duration_since(last_time);
-                        last_time = now;
-
-                        self.tx.send(TimeSnapshot {
-                            delta: delta,
-                            elapsed: now.duration_since(self.start_time),
-                        })?;
-
-                        let mut total_matches_guard = self.total_matches.lock().unwrap();
-                        *total_matches_guard += 1;
-
-                        let line = String::from(
-                            buffer
-                                .trim()
-                                .replace(&cap[0], &format!("{}", &cap[0].red())),
-                        );
-                        let output = self.annotator.format_line(
-                            &line,
-                            &now.duration_since(self.start_time),
-                            &delta,
-                        );
-                        println!("{}", output);
-                    }
-                    None => {}
-                }
-            } else {
-                let delta = now.duration_since(last_time);
-                last_time = now;
+    /// Mean duration of the ended laps.
+    fn mean(&self) -> Option<Duration> {
+        (self.count > 0).then(|| self.total / self.count as u32)
+    }
+}
 
-                self.tx.send(TimeSnapshot {
-                    delta: delta,
-                    elapsed: now.duration_since(self.start_time),
-                })?;
+/// The sections of a run reading several files, one per file, each timed as a lap.
+#[derive(Debug, Default)]
+struct FileSections {
+    /// The names of the files, by index.
+    names: Vec<String>,
+    /// Index of the file of the latest line.
+    current: Option<usize>,
+    laps: Laps,
+    /// The ended sections, with the index of their file.
+    ended: Vec<(usize, Lap)>,
+}
 
-                let line = String::from(buffer.trim());
-                let output =
-                    self.annotator
-                        .format_line(&line, &now.duration_since(self.start_time), &delta);
-                println!("{}", output);
-            }
+impl FileSections {
+    fn new(names: Vec<String>) -> Self {
+        Self {
+            names,
+            ..Self::default()
         }
+    }
 
-        Ok(())
+    /// A line read from the file at `index` arrived at `at`: returns the name of the file if the
+    /// line starts its section, ending that of the previous file.
+    fn line_from(&mut self, index: usize, at: Duration) -> Option<&str> {
+        if self.current == Some(index) {
+            return None;
+        }
+        if let (Some(previous), Some(lap)) = (self.current, self.laps.start(at)) {
+            self.ended.push((previous, lap));
+        }
+        self.current = Some(index);
+        self.names.get(index).map(String::as_str)
     }
 
-    /// Prints a summary of the results and optionally plots the data.
-    pub fn summarize_and_plot(&self) -> Result<(), TimelnError> {
-        let now = Instant::now();
-        let total_lines_final = self.total_lines.lock()?;
-        let total_matches_final = self.total_matches.lock()?;
-        println!(
-            "{}",
-            self.summarizer.summarize(
-                *total_lines_final,
-                *total_matches_final,
-                &now.duration_since(self.start_time),
-                &**self.annotator.time_format
-            )
+    /// Ends the section of the latest file at `at`, returning all the sections with the names of
+    /// their files, in order.
+    fn finish(&mut self, at: Duration) -> Vec<(&str, Lap)> {
+        if let (Some(current), Some(lap)) = (self.current.take(), self.laps.finish(at)) {
+            self.ended.push((current, lap));
+        }
+        self.ended
+            .iter()
+            .map(|(index, lap)| (self.names[*index].as_str(), *lap))
+            .collect()
+    }
+}
+
+/// Starts writing events to the file descriptor `fd` given with `--event-fd`, which must be open.
+#[cfg(unix)]
+fn open_events(
+    fd: i32,
+    capacity: usize,
+    drop_oldest: bool,
+) -> Result<Arc<EventWriter>, TimelnError> {
+    EventWriter::open_fd(fd, capacity, drop_oldest).map_err(|err| {
+        TimelnError::Usage(format!("cannot write events to --event-fd {}: {}", fd, err))
+    })
+}
+
+#[cfg(not(unix))]
+fn open_events(_: i32, _: usize, _: bool) -> Result<Arc<EventWriter>, TimelnError> {
+    Err(TimelnError::Usage(
+        "--event-fd is only supported on Unix".to_string(),
+    ))
+}
+
+/// Reads the socket or named pipe `path` given with `--listen`, returning the socket created, to be
+/// removed at the end of the run.
+#[cfg(unix)]
+fn listen(path: &str, keep: bool) -> Result<(Box<dyn ReadData>, Option<PathBuf>), TimelnError> {
+    let reader = SocketReadData::listen(path, keep)?;
+    let socket = reader.socket_path().map(Path::to_path_buf);
+    Ok((Box::new(reader), socket))
+}
+
+#[cfg(not(unix))]
+fn listen(_: &str, _: bool) -> Result<(Box<dyn ReadData>, Option<PathBuf>), TimelnError> {
+    Err(TimelnError::Usage(
+        "--listen is only supported on Unix".to_string(),
+    ))
+}
+
+/// Tag of the lines output while timing is paused.
+const PAUSED_TAG: &str = "[paused]";
+
+/// Writes to the raw tee file with `write`, if teeing. A failed write is reported once and stops
+/// the teeing, rather than the run.
+fn write_tee(
+    tee: &mut Option<BufWriter<File>>,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) {
+    let Some(writer) = tee else {
+        return;
+    };
+    if let Err(err) = write(writer) {
+        eprintln!(
+            "timeln: could not write the raw input, no longer saving it: {}",
+            err
         );
+        *tee = None;
+    }
+}
 
-        if self.plot {
-            let rx_lock = self.rx.lock()?;
-            let durations: Vec<_> = rx_lock.try_iter().collect();
-            let deltas: Vec<f64> = durations
-                .iter()
-                .map(|&dur| dur.delta.as_secs_f64())
-                .collect();
-            let times: Vec<f64> = durations
-                .iter()
-                .map(|&dur| dur.elapsed.as_secs_f64())
-                .collect();
-            plot_deltas(&deltas, "deltas.svg")?;
-            plot_times(&times, "times.svg")?;
+/// Compiles a regex pattern of the options.
+fn compile(pattern: &str) -> Result<Regex, TimelnError> {
+    Regex::new(pattern).map_err(|err| TimelnError::regex(pattern, err))
+}
+
+/// Counters and statistics of the run, updated by the main loop and read by the finalizer and the
+/// exporters.
+#[derive(Clone, Default)]
+struct Counters {
+    total_lines: Arc<AtomicUsize>,
+    total_matches: Arc<AtomicUsize>,
+    /// Number of bytes read, including line endings, before any trimming.
+    total_bytes: Arc<AtomicU64>,
+    /// Nanoseconds spent between reading a line and being ready to read the next one.
+    overhead: Arc<AtomicU64>,
+    /// Lines read from the stdout and stderr of a child process, indexed by `Stream`.
+    stream_lines: Arc<[AtomicUsize; 2]>,
+    /// Number of lines that matched each regex pattern.
+    pattern_matches: Arc<Vec<AtomicUsize>>,
+    /// Nanoseconds from the start to the arrival of the latest line.
+    last_arrival: Arc<AtomicU64>,
+    /// Nanoseconds between the latest timed line and the one before it.
+    last_delta: Arc<AtomicU64>,
+    stats: Arc<Mutex<RunningStats>>,
+    /// Time during which timing was paused, left out of the elapsed times and deltas.
+    pause: Arc<PauseClock>,
+}
+
+impl Counters {
+    /// Counters for a run with `patterns` regex patterns.
+    fn new(patterns: usize) -> Self {
+        Self {
+            pattern_matches: Arc::new((0..patterns).map(|_| AtomicUsize::new(0)).collect()),
+            ..Self::default()
         }
+    }
 
-        Ok(())
+    /// The totals counted so far, for a run that lasted `total_time`.
+    fn totals(&self, total_time: Duration) -> RunTotals {
+        RunTotals {
+            lines: self.total_lines.load(Ordering::Acquire) as u64,
+            matches: self.total_matches.load(Ordering::Acquire) as u64,
+            bytes: self.total_bytes.load(Ordering::Acquire),
+            total_time,
+        }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::{argopt::TimelnOpt, reader::TestReadData};
+/// The state of the features that follow the lines, to summarize them at the end of the run.
+#[derive(Clone)]
+struct Trackers {
+    laps: Arc<Mutex<Laps>>,
+    /// The sections of the files, if several are read.
+    files: Arc<Mutex<Option<FileSections>>>,
+    collapsed: Arc<Mutex<Option<CollapsedRun>>>,
+    spans: Arc<Mutex<Option<SpanTracker>>>,
+    phases: Arc<Mutex<Option<PhaseDetector>>>,
+    bursts: Arc<Mutex<Option<BurstDetector>>>,
+    activity: Arc<Mutex<Option<Activity>>>,
+    extractors: Arc<Mutex<Vec<Extractor>>>,
+    groups: Arc<Mutex<Option<GroupStats>>>,
+    reported: Arc<Mutex<Option<SelfReported>>>,
+    slowest: Arc<Mutex<SlowestLines>>,
+}
 
-    #[test]
-    fn test_timeln_context_new() {
-        let opt = TimelnOpt {
-            color: false,
-            regex: None,
-            plot: false,
+impl Trackers {
+    /// Creates the trackers enabled in `opt`, for a run started at `start_time` that keeps its
+    /// `slowest` lines.
+    fn new(opt: &TimelnOpt, slowest: usize, start_time: Instant) -> Result<Self, TimelnError> {
+        let spans = match opt.between.as_slice() {
+            [start, end] => {
+                compile(start)?;
+                compile(end)?;
+                Some(SpanTracker::new(start, end).map_err(|err| TimelnError::regex(start, err))?)
+            }
+            _ => None,
         };
-        let context = TimelnContext::new(opt);
-        assert!(context.is_ok());
+        let extractors = if opt.logfmt {
+            opt.extract.iter().map(|key| Extractor::key(key)).collect()
+        } else {
+            opt.extract
+                .iter()
+                .map(|pattern| {
+                    Extractor::new(pattern).map_err(|err| TimelnError::regex(pattern, err))
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        let groups = match &opt.group_by {
+            Some(name) if opt.logfmt => Some(GroupStats::by_key(name)),
+            Some(name) => {
+                let patterns = opt
+                    .regex
+                    .iter()
+                    .map(|pattern| compile(pattern))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let groups = GroupStats::new(name, patterns);
+                if !groups.has_patterns() {
+                    let err = format!("no regex pattern has a capture group named '{}'", name);
+                    return Err(TimelnError::Usage(err));
+                }
+                Some(groups)
+            }
+            None => None,
+        };
+        let reported = opt
+            .self_reported
+            .as_deref()
+            .map(|pattern| {
+                SelfReported::new(pattern).map_err(|err| TimelnError::regex(pattern, err))
+            })
+            .transpose()?;
+        Ok(Self {
+            laps: Arc::new(Mutex::new(Laps::default())),
+            files: Arc::new(Mutex::new(
+                (opt.file.len() > 1).then(|| FileSections::new(opt.file.clone())),
+            )),
+            collapsed: Arc::new(Mutex::new(None)),
+            spans: Arc::new(Mutex::new(spans)),
+            phases: Arc::new(Mutex::new(opt.detect_phases.then(|| {
+                PhaseDetector::new(
+                    opt.phase_window.unwrap_or(DEFAULT_PHASE_WINDOW),
+                    opt.phase_ratio.unwrap_or(DEFAULT_PHASE_RATIO),
+                )
+            }))),
+            bursts: Arc::new(Mutex::new(
+                opt.detect_bursts
+                    .map(|threshold| BurstDetector::new(threshold, start_time)),
+            )),
+            activity: Arc::new(Mutex::new(opt.activity.then(|| {
+                Activity::new(
+                    opt.activity_bucket.unwrap_or(DEFAULT_ACTIVITY_BUCKET),
+                    MAX_ACTIVITY_BUCKETS,
+                )
+            }))),
+            extractors: Arc::new(Mutex::new(extractors)),
+            groups: Arc::new(Mutex::new(groups)),
+            reported: Arc::new(Mutex::new(reported)),
+            slowest: Arc::new(Mutex::new(SlowestLines::new(slowest))),
+        })
     }
+}
 
-    #[test]
-    fn test_send_duration() {
-        let opt = TimelnOpt {
-            color: false,
-            regex: None,
-            plot: false,
+/// The regex patterns and the pipeline that times and annotates the lines.
+struct Matching {
+    pipeline: Pipeline,
+    /// Label of each pattern, in the summary and the plots.
+    labels: Vec<String>,
+    /// Marker starting a new lap.
+    reset_on: Option<Regex>,
+    /// Lines printed around the matches, if any.
+    context: Option<ContextLines>,
+}
+
+impl Matching {
+    /// Compiles the patterns of `opt` into a pipeline annotating the lines with `annotator`, that
+    /// keeps the moving average of the deltas with `ewma_alpha` and counts the lines of
+    /// `rate_window`.
+    fn new(
+        opt: &TimelnOpt,
+        annotator: Box<dyn TimelnAnnotation>,
+        ewma_alpha: Option<f64>,
+        rate_window: Option<Arc<SlidingWindow>>,
+    ) -> Result<Self, TimelnError> {
+        validate_patterns(opt)?;
+        if !opt.label.is_empty() && opt.label.len() != opt.regex.len() {
+            let err = format!(
+                "got {} labels for {} regex patterns; give one --label per --regex",
+                opt.label.len(),
+                opt.regex.len()
+            );
+            return Err(TimelnError::Usage(err));
+        }
+        let labels: Vec<String> = if opt.label.is_empty() {
+            (0..opt.regex.len()).map(|i| format!("p{}", i)).collect()
+        } else {
+            opt.label.clone()
         };
-        let context = TimelnContext::new(opt).unwrap();
-        let duration = Duration::from_secs(1);
-        assert!(context
-            .tx
-            .send(TimeSnapshot {
-                delta: duration,
-                elapsed: duration
-            })
-            .is_ok());
+        let mut pipeline = Pipeline::new(annotator)
+            .ewma(ewma_alpha)
+            .trend(
+                opt.trend
+                    .then(|| opt.trend_tolerance.unwrap_or(DEFAULT_TREND_TOLERANCE)),
+                opt.ascii,
+            )
+            .annotate_rate(rate_window.filter(|_| opt.annotate_rate))
+            .heat(opt.heat.then(|| {
+                let colorterm = std::env::var("COLORTERM").ok();
+                Heat::new(heat::truecolor_supported(colorterm.as_deref()))
+            }))
+            .as_rate(opt.as_rate)
+            .show_avg(opt.show_avg)
+            .skip_first_delta(opt.skip_first_delta)
+            .passthrough(opt.passthrough)
+            .trim(opt.trim)
+            .no_line(opt.no_line)
+            .color(opt.color)
+            .match_colors(opt.match_colors.clone());
+        if pattern_summary(opt) {
+            pipeline = pipeline.labels(labels.clone());
+        }
+        for pattern in &opt.regex {
+            pipeline = pipeline.regex(pattern)?;
+        }
+        if opt.only_matching {
+            let group = opt.group.clone().unwrap_or(CaptureGroup::Index(0));
+            pipeline = pipeline.only_matching(Some(group))?;
+        }
+        Ok(Self {
+            pipeline,
+            labels,
+            reset_on: opt.reset_on.as_deref().map(compile).transpose()?,
+            context: (opt.before_context.is_some() || opt.after_context.is_some()).then(|| {
+                ContextLines::new(
+                    opt.before_context.unwrap_or(0),
+                    opt.after_context.unwrap_or(0),
+                )
+            }),
+        })
     }
+}
 
-    #[test]
-    fn test_receive_duration() {
-        let opt = TimelnOpt {
-            color: false,
-            regex: None,
-            plot: false,
+/// Whether the matches of each pattern are summarized, because the patterns of `opt` are labelled
+/// or there are several of them.
+fn pattern_summary(opt: &TimelnOpt) -> bool {
+    !opt.label.is_empty() || opt.regex.len() > 1
+}
+
+/// What the finalizer reports about the input.
+struct InputState {
+    /// Whether the lines arrive at times read from the input rather than when they are read, so
+    /// that the run lasts until the latest of them.
+    input_times: bool,
+    timestamps: Option<Arc<TimestampCounts>>,
+    /// Lines of a structured log that were not JSON objects with a valid time.
+    json_failures: Option<Arc<AtomicUsize>>,
+    /// Lines of a logfmt log without a valid time.
+    logfmt_untimed: Option<Arc<AtomicUsize>>,
+    /// Socket created for `--listen`, removed at the end of the run.
+    listen_socket: Option<PathBuf>,
+}
+
+/// Opens the input of `opt`, unless a `reader` replaces it, and wraps it in the readers that parse
+/// its times, structure or multi-line records. The times of the lines are measured from
+/// `start_time`, and a replay waits between its lines unless `instant_replay`.
+fn open_input(
+    opt: &TimelnOpt,
+    reader: Option<Box<dyn ReadData>>,
+    instant_replay: bool,
+    start_time: Instant,
+) -> Result<(Box<dyn ReadData>, InputState), TimelnError> {
+    if opt.time_field.is_some() && !opt.json_input && !opt.logfmt {
+        let err = "--time-field names the time of a structured log; give --json-input or --logfmt";
+        return Err(TimelnError::Usage(err.to_string()));
+    }
+    if opt.csv_columns.is_some() && opt.replay.is_none() {
+        let err =
+            "--csv-columns maps the columns of a replayed CSV file; give --replay".to_string();
+        return Err(TimelnError::Usage(err));
+    }
+    // Commands and sockets stop waiting for a line on their own, and TCP connections too, but
+    // waiting for a client only on Unix.
+    let times_out = reader.is_none()
+        && (opt.journal
+            || opt.listen.is_some()
+            || opt.connect.is_some()
+            || (opt.listen_tcp.is_some() && cfg!(unix))
+            || !opt.command.is_empty())
+        && opt.replay.is_none();
+    let mut listen_socket = None;
+    let read_data: Box<dyn ReadData> = match reader {
+        Some(reader) => reader,
+        None if opt.replay.is_some() => {
+            let path = opt.replay.as_deref().unwrap_or_default();
+            let pace = (!instant_replay).then_some(ReplayPace {
+                speed: opt.speed.unwrap_or(1.0),
+                clamp: opt.replay_clamp,
+            });
+            let csv = opt.csv_columns.is_some()
+                || Path::new(path)
+                    .extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+            if csv {
+                let columns = opt.csv_columns.unwrap_or_default();
+                Box::new(ReplayReadData::open_csv(path, columns, start_time, pace)?)
+            } else {
+                Box::new(ReplayReadData::open(path, start_time, pace)?)
+            }
+        }
+        None if opt.journal => {
+            if !cfg!(all(feature = "journald", target_os = "linux")) {
+                let err = "--journal needs timeln to be built with the journald feature, on Linux";
+                return Err(TimelnError::Usage(err.to_string()));
+            }
+            let journalctl = journalctl_command(&opt.unit, opt.priority);
+            Box::new(ExecReadData::spawn(&journalctl)?)
+        }
+        None if opt.listen.is_some() => {
+            let (reader, socket) =
+                listen(opt.listen.as_deref().unwrap_or_default(), opt.listen_keep)?;
+            listen_socket = socket;
+            reader
+        }
+        None if opt.connect.is_some() => Box::new(TcpReadData::connect(
+            opt.connect.as_deref().unwrap_or_default(),
+            opt.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT),
+            opt.reconnect,
+        )?),
+        None if opt.listen_tcp.is_some() => Box::new(TcpReadData::listen(
+            opt.listen_tcp.as_deref().unwrap_or_default(),
+            opt.reconnect,
+        )?),
+        None if !opt.command.is_empty() => Box::new(ExecReadData::spawn(&opt.command)?),
+        None if opt.file.len() > 1 && opt.follow.is_some() => {
+            let err = "--follow reads a single --file";
+            return Err(TimelnError::Usage(err.to_string()));
+        }
+        // A followed file grows as it is read, so it is never decompressed.
+        None if opt.follow.is_some() => Box::new(FileReadData::open(
+            &opt.file[0],
+            opt.follow,
+            opt.follow_interval.unwrap_or(DEFAULT_FOLLOW_INTERVAL),
+        )?),
+        None if opt.file.len() > 1 => Box::new(FilesReadData::open(&opt.file)?),
+        None if !opt.file.is_empty() => Box::new(DecompressReadData::open(&opt.file[0])?),
+        None => Box::new(DecompressReadData::stdin()),
+    };
+    let read_data = match opt.exit_idle {
+        // The other readers are read on a helper thread, so that --exit-idle can end the run.
+        Some(_) if !times_out => Box::new(ThreadedReadData::spawn(read_data)),
+        _ => read_data,
+    };
+    let read_data: Box<dyn ReadData> = if opt.journal {
+        Box::new(JournalReadData::new(
+            read_data,
+            start_time,
+            SystemTime::now(),
+        ))
+    } else {
+        read_data
+    };
+    let (read_data, timestamps): (Box<dyn ReadData>, _) = match &opt.parse_time {
+        Some(pattern) => {
+            let layout = opt.time_layout.as_deref().unwrap_or(DEFAULT_TIME_LAYOUT);
+            let parser = TimestampParser::new(pattern, layout)?;
+            let counts = Arc::new(TimestampCounts::default());
+            let parsing =
+                ParsedTimeReadData::new(read_data, parser, Arc::clone(&counts), start_time);
+            (Box::new(parsing), Some(counts))
+        }
+        None => (read_data, None),
+    };
+    let (read_data, json_failures): (Box<dyn ReadData>, _) = if opt.json_input {
+        let time_field = opt.time_field.as_deref().unwrap_or(DEFAULT_TIME_FIELD);
+        let fields = JsonFields::new(time_field, opt.line_field.clone());
+        let failures = Arc::new(AtomicUsize::new(0));
+        let parsing = JsonReadData::new(read_data, fields, Arc::clone(&failures), start_time);
+        (Box::new(parsing), Some(failures))
+    } else {
+        (read_data, None)
+    };
+    let boundary = match &opt.join_until {
+        Some(pattern) => Some(JoinBoundary::Until(compile(pattern)?)),
+        None if opt.paragraph => Some(JoinBoundary::Paragraph),
+        None => None,
+    };
+    let read_data: Box<dyn ReadData> = match boundary {
+        Some(boundary) => Box::new(JoinReadData::new(read_data, boundary)),
+        None => read_data,
+    };
+    // Last, so that the pairs of the lines are those of the joined lines.
+    let (read_data, logfmt_untimed): (Box<dyn ReadData>, _) = if opt.logfmt {
+        let time_key = opt.time_field.as_deref().unwrap_or(DEFAULT_TIME_FIELD);
+        let untimed = Arc::new(AtomicUsize::new(0));
+        let parsing = LogfmtReadData::new(
+            read_data,
+            time_key,
+            opt.fields.clone(),
+            Arc::clone(&untimed),
+            start_time,
+        );
+        (Box::new(parsing), Some(untimed))
+    } else {
+        (read_data, None)
+    };
+    let input = InputState {
+        input_times: opt.replay.is_some()
+            || opt.journal
+            || timestamps.is_some()
+            || json_failures.is_some()
+            || logfmt_untimed.is_some(),
+        timestamps,
+        json_failures,
+        logfmt_untimed,
+        listen_socket,
+    };
+    Ok((read_data, input))
+}
+
+/// What is summarized at the end of the run besides the deltas, with the state it is drawn from.
+struct SummaryConfig {
+    /// Whether the matches of each pattern are summarized.
+    pattern_summary: bool,
+    /// Colors of the matches of each pattern, cycled through by pattern index, if any.
+    match_colors: Vec<Color>,
+    lap_summary: bool,
+    /// Whether the activity is drawn in ASCII.
+    ascii: bool,
+    /// Number of the largest gaps listed after the detailed summary.
+    gaps: usize,
+    outliers: Option<Outliers>,
+    /// Thresholds of the cumulative distribution of the deltas summarized, if any; chosen from the
+    /// deltas if empty.
+    cdf_points: Option<Vec<Duration>>,
+    /// Weight of each delta in their moving average, if it is computed.
+    ewma_alpha: Option<f64>,
+    /// The moving average of the deltas so far.
+    ewma: Mutex<Option<Duration>>,
+    /// Whether the rates of the deltas are summarized.
+    as_rate: bool,
+    /// Progress towards the matches expected with `--total-matches`, if given.
+    progress: Option<Mutex<MatchProgress>>,
+}
+
+impl SummaryConfig {
+    /// The summaries requested by `opt`.
+    fn new(opt: &TimelnOpt) -> Result<Self, TimelnError> {
+        if opt.gaps.is_some() && opt.summary != SummaryKind::Detailed {
+            let err =
+                "--gaps lists the largest gaps in the detailed summary; give --summary detailed";
+            return Err(TimelnError::Usage(err.to_string()));
+        }
+        let ewma_alpha = opt
+            .ewma
+            .then(|| opt.ewma_alpha.unwrap_or(DEFAULT_EWMA_ALPHA));
+        Ok(Self {
+            pattern_summary: pattern_summary(opt),
+            match_colors: opt.match_colors.clone(),
+            lap_summary: opt.lap_summary,
+            ascii: opt.ascii,
+            gaps: opt.gaps.unwrap_or(0),
+            outliers: opt.flag_outliers.map(|sigma| Outliers {
+                sigma,
+                warmup: opt.outlier_warmup.unwrap_or(DEFAULT_OUTLIER_WARMUP),
+                flagged: AtomicUsize::new(0),
+            }),
+            cdf_points: opt.cdf.then(|| opt.cdf_points.clone()),
+            ewma_alpha,
+            ewma: Mutex::new(None),
+            as_rate: opt.as_rate,
+            progress: opt
+                .total_matches
+                .map(|total| Mutex::new(MatchProgress::new(total, ewma_alpha))),
+        })
+    }
+}
+
+/// Files and services the lines are written to as they arrive, finished at the end of the run.
+#[derive(Clone)]
+struct Exporters {
+    recorder: Arc<Mutex<Option<RecordWriter>>>,
+    /// Raw copy of the input, flushed by the finalizer.
+    tee: Arc<Mutex<Option<BufWriter<File>>>>,
+    sink: Arc<Mutex<SnapshotSink>>,
+    /// File the plot data is written to, or streamed to by the sink.
+    plot_data: Option<String>,
+    statsd: Arc<Mutex<Option<StatsdEmitter>>>,
+    events: Option<Arc<EventWriter>>,
+    sqlite: Arc<Mutex<Option<SqliteStore>>>,
+    sqlite_path: Option<String>,
+    parquet: Arc<Mutex<Option<ParquetWriter>>>,
+    parquet_path: Option<String>,
+}
+
+impl Exporters {
+    /// Opens the exporters enabled in `opt`. The sink keeps the snapshots for the plots and the
+    /// report, with the text of their lines if `keep_text`.
+    fn open(opt: &TimelnOpt, keep_text: bool) -> Result<Self, TimelnError> {
+        let recorder = match &opt.record {
+            Some(path) => Some(
+                RecordWriter::create(path, std::env::args().collect(), opt.record_sync)
+                    .map_err(|err| TimelnError::output(path, err))?,
+            ),
+            None => None,
         };
-        let context = TimelnContext::new(opt).unwrap();
-        let duration = Duration::from_secs(1);
-        context
-            .tx
-            .send(TimeSnapshot {
-                delta: duration,
-                elapsed: duration,
-            })
-            .unwrap();
-        let rx_lock = context.rx.lock().unwrap();
-        assert_eq!(rx_lock.try_recv().unwrap().delta, duration);
+        let sqlite = match &opt.sqlite {
+            Some(_) if !cfg!(feature = "sqlite") => {
+                let err = "--sqlite needs timeln to be built with the sqlite feature";
+                return Err(TimelnError::Usage(err.to_string()));
+            }
+            Some(path) => Some(
+                SqliteStore::open(path, std::env::args().collect())
+                    .map_err(|err| TimelnError::output(path, err))?,
+            ),
+            None => None,
+        };
+        let parquet = match &opt.parquet {
+            Some(_) if !cfg!(feature = "parquet") => {
+                let err = "--parquet needs timeln to be built with the parquet feature";
+                return Err(TimelnError::Usage(err.to_string()));
+            }
+            Some(path) => Some(
+                ParquetWriter::create(
+                    path,
+                    std::env::args().collect(),
+                    opt.parquet_batch.unwrap_or(DEFAULT_BATCH_ROWS),
+                )
+                .map_err(|err| TimelnError::output(path, err))?,
+            ),
+            None => None,
+        };
+        let events = match opt.event_fd {
+            Some(fd) => Some(open_events(
+                fd,
+                opt.event_buffer.unwrap_or(DEFAULT_EVENT_BUFFER),
+                opt.event_drop_oldest,
+            )?),
+            None => None,
+        };
+        if let Some(events) = &events {
+            events.send(record_event(&Record::header(std::env::args().collect())));
+        }
+        let tee = match &opt.tee_raw {
+            Some(path) => Some(BufWriter::new(
+                File::create(path).map_err(|err| TimelnError::output(path, err))?,
+            )),
+            None => None,
+        };
+        let sink = if opt.plot || opt.plot_term || opt.report.is_some() {
+            SnapshotSink::in_memory(
+                Some(opt.plot_max_samples.unwrap_or(DEFAULT_PLOT_MAX_SAMPLES))
+                    .filter(|max| *max > 0)
+                    .unwrap_or(usize::MAX),
+            )
+            .max_memory(opt.max_memory)
+            .keep_text(keep_text)
+        } else if let Some(path) = &opt.plot_data {
+            SnapshotSink::streaming(path, opt.plot_x)
+                .map_err(|err| TimelnError::plot_data(path, err))?
+        } else {
+            SnapshotSink::Null
+        };
+        let statsd = match &opt.statsd {
+            Some(addr) => {
+                let prefix = opt
+                    .statsd_prefix
+                    .as_deref()
+                    .unwrap_or(statsd::DEFAULT_PREFIX);
+                Some(
+                    StatsdEmitter::connect(addr, prefix)
+                        .map_err(|err| TimelnError::output(addr, err))?,
+                )
+            }
+            None => None,
+        };
+        Ok(Self {
+            recorder: Arc::new(Mutex::new(recorder)),
+            tee: Arc::new(Mutex::new(tee)),
+            sink: Arc::new(Mutex::new(sink)),
+            plot_data: opt.plot_data.clone(),
+            statsd: Arc::new(Mutex::new(statsd)),
+            events,
+            sqlite: Arc::new(Mutex::new(sqlite)),
+            sqlite_path: opt.sqlite.clone(),
+            parquet: Arc::new(Mutex::new(parquet)),
+            parquet_path: opt.parquet.clone(),
+        })
     }
+}
 
-    #[test]
-    fn test_run() {
-        let opt = TimelnOpt {
-            color: false,
-            regex: None,
-            plot: false,
+/// Commands run for the lines, and notifications sent for the lines and the end of the run.
+struct Hooks {
+    /// Command run for the slow lines, if any.
+    slow: Option<SlowHook>,
+    /// Command run for the matches, if any.
+    matched: Option<MatchHook>,
+    notifier: Option<Notifier>,
+    desktop: Option<DesktopNotifier>,
+}
+
+impl Hooks {
+    /// The hooks and notifications enabled in `opt`.
+    fn new(opt: &TimelnOpt) -> Result<Self, TimelnError> {
+        let desktop = match &opt.notify_on_match {
+            _ if !opt.notify => None,
+            _ if !cfg!(feature = "desktop") => {
+                let err = "--notify needs timeln to be built with the desktop feature";
+                return Err(TimelnError::Usage(err.to_string()));
+            }
+            Some(pattern) => Some(DesktopNotifier::new(Some(compile(pattern)?))),
+            None => Some(DesktopNotifier::new(None)),
         };
-        let mut context = TimelnContext::new(opt).unwrap();
-        let test_data = TestReadData {
-            data: std::io::Cursor::new("test\n".to_string()),
+        let notifier = match &opt.notify_url {
+            Some(_) if !cfg!(feature = "notify") => {
+                let err = "--notify-url needs timeln to be built with the notify feature";
+                return Err(TimelnError::Usage(err.to_string()));
+            }
+            Some(url) => Some(Notifier::new(
+                url,
+                opt.notify_label.clone(),
+                opt.notify_slower_than,
+                opt.notify_interval.unwrap_or(DEFAULT_NOTIFY_INTERVAL),
+            )),
+            None => None,
         };
-        context.stdin = Box::new(test_data);
-        assert!(context.run().is_ok());
+        Ok(Self {
+            slow: opt
+                .on_slow
+                .zip(opt.on_slow_cmd.as_deref())
+                .map(|(threshold, command)| {
+                    SlowHook::new(
+                        threshold,
+                        command,
+                        opt.on_slow_interval.unwrap_or(DEFAULT_HOOK_INTERVAL),
+                    )
+                }),
+            matched: opt
+                .on_match_cmd
+                .as_deref()
+                .map(|command| MatchHook::new(command, opt.on_match_limit)),
+            notifier,
+            desktop,
+        })
+    }
+}
+
+/// What is drawn on the terminal during the run, and the live stats page, all stopped by
+/// `write_outputs`.
+struct Displays {
+    /// Rows and columns of the terminal the lines are printed to, if the dashboard or the summary
+    /// screen is drawn on it.
+    terminal_size: Option<(usize, usize)>,
+    /// Status line and terminal title redrawn on stderr.
+    status: Option<StatusTicker>,
+    /// Panel at the bottom of the terminal.
+    dashboard: Option<Dashboard>,
+    /// Summary redrawn on the whole terminal.
+    screen: Option<SummaryScreen>,
+    /// Latest deltas, for the sparklines of the live stats page and the summary screen, and the
+    /// dashboard.
+    recent_deltas: Option<RecentDeltas>,
+    /// Lines that arrived lately, for the rate of the status line, the dashboard, the events and
+    /// the annotations.
+    rate_window: Option<Arc<SlidingWindow>>,
+    /// Server of the live stats page.
+    live: Option<LiveServer>,
+    /// Whether single-key commands are read from the terminal.
+    keys: bool,
+    /// Output held back while the display is paused with a key, written when it resumes.
+    held: Mutex<Option<Vec<u8>>>,
+}
+
+impl Displays {
+    /// The displays enabled in `opt`. The dashboard and the summary screen are only drawn on the
+    /// terminal the lines are printed to, if they are printed to stdout, which `own_output` tells.
+    fn new(opt: &TimelnOpt, own_output: bool) -> Result<Self, TimelnError> {
+        if opt.rate_window == Some(Duration::ZERO) {
+            let err = "--rate-window must be longer than zero".to_string();
+            return Err(TimelnError::Usage(err));
+        }
+        let term = std::env::var("TERM").ok();
+        let dashboard_size = if opt.dashboard && own_output {
+            dashboard::terminal_size()
+        } else {
+            None
+        };
+        // So is the summary screen, or else the lines are printed as usual.
+        let screen_size = if opt.watch_summary && own_output {
+            dashboard::terminal_size().filter(|_| terminal_enabled(true, term.as_deref()))
+        } else {
+            None
+        };
+        // The progress is only drawn on a terminal, and never goes to a redirected stderr.
+        let display = if terminal_enabled(io::stderr().is_terminal(), term.as_deref()) {
+            StatusDisplay {
+                line: opt.status,
+                title: opt.title,
+            }
+        } else {
+            StatusDisplay::default()
+        };
+        let live = match &opt.serve {
+            Some(addr) => {
+                Some(LiveServer::bind(addr).map_err(|err| TimelnError::output(addr, err))?)
+            }
+            None => None,
+        };
+        Ok(Self {
+            terminal_size: dashboard_size.or(screen_size),
+            status: (!display.is_empty()).then(|| StatusTicker::new(display)),
+            dashboard: dashboard_size
+                .filter(|_| terminal_enabled(true, term.as_deref()))
+                .and_then(|(width, height)| Dashboard::new(width, height)),
+            screen: screen_size.map(|_| SummaryScreen::default()),
+            recent_deltas: (live.is_some() || dashboard_size.is_some() || screen_size.is_some())
+                .then(|| RecentDeltas::new(SPARKLINE_DELTAS)),
+            rate_window: (opt.status
+                || opt.dashboard
+                || opt.event_fd.is_some()
+                || opt.annotate_rate)
+                .then(|| {
+                    Arc::new(SlidingWindow::new(
+                        opt.rate_window.unwrap_or(DEFAULT_RATE_WINDOW),
+                    ))
+                }),
+            live,
+            keys: opt.keys,
+            held: Mutex::new(None),
+        })
+    }
+}
+
+/// An output written after the summary at the end of the run.
+#[derive(Debug, Clone, PartialEq)]
+enum FinalOutput {
+    /// The Prometheus textfile, written a last time with the final counters.
+    Textfile(PathBuf),
+    /// The totals of the run in the SQLite database.
+    Sqlite(String),
+    /// The last row group and the footer of the Parquet file.
+    Parquet(String),
+    /// The webhook notification of the end of the run.
+    Webhook,
+    Desktop,
+    TermPlots,
+    /// The SVG plots of the deltas and times, and of the extracted values.
+    Plots,
+    PlotData(String),
+    Report(String),
+}
+
+impl FinalOutput {
+    /// The outputs requested by `opt`, in the order they are written.
+    fn requested(opt: &TimelnOpt) -> Vec<Self> {
+        [
+            opt.prom_textfile
+                .as_ref()
+                .map(|path| Self::Textfile(PathBuf::from(path))),
+            opt.sqlite.clone().map(Self::Sqlite),
+            opt.parquet.clone().map(Self::Parquet),
+            opt.notify_url.as_ref().map(|_| Self::Webhook),
+            opt.notify.then_some(Self::Desktop),
+            opt.plot_term.then_some(Self::TermPlots),
+            opt.plot.then_some(Self::Plots),
+            opt.plot_data.clone().map(Self::PlotData),
+            opt.report.clone().map(Self::Report),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// Name of the output, in the message telling that there was no time left to write it.
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Textfile(_) => "Prometheus textfile",
+            Self::Sqlite(_) => "SQLite totals",
+            Self::Parquet(_) => "Parquet file",
+            Self::Webhook => "webhook notification",
+            Self::Desktop => "desktop notification",
+            Self::TermPlots => "terminal plots",
+            Self::Plots => "plots",
+            Self::PlotData(_) => "plot data",
+            Self::Report(_) => "report",
+        }
+    }
+
+    /// Whether the output is drawn from the snapshots of the sink.
+    fn plotted(&self) -> bool {
+        matches!(
+            self,
+            Self::TermPlots | Self::Plots | Self::PlotData(_) | Self::Report(_)
+        )
+    }
+}
+
+/// The totals of a finished run, as summarized and written to the outputs.
+struct FinishedRun {
+    totals: RunTotals,
+    /// Time during which timing was paused.
+    paused: Duration,
+}
+
+/// The lines of one feature in the summary of a finished run, colored.
+type SummarySection = fn(&Finalizer, &FinishedRun) -> Result<Vec<String>, TimelnError>;
+
+/// Prints the summary and writes the plots and report at the end of a run.
+///
+/// It is shared between the main loop and the signal handler, which may both try to finish the
+/// run at the same time; `finalize` makes sure only one of them produces any output.
+struct Finalizer {
+    once: Once,
+    summarizer: Box<dyn Summarizer>,
+    time_format: Arc<Box<dyn TimeFormat>>,
+    color: bool,
+    start_time: Instant,
+    counters: Counters,
+    trackers: Trackers,
+    input: InputState,
+    summary: SummaryConfig,
+    exporters: Exporters,
+    hooks: Hooks,
+    display: Displays,
+    /// Directory of the SVG plots.
+    plot_dir: PathBuf,
+    plot_config: PlotConfig,
+    /// Whether the phase boundaries are drawn on the delta plot.
+    plot_phases: bool,
+    /// The outputs written after the summary, in order.
+    outputs: Vec<FinalOutput>,
+    /// Why `run` stopped, for the notification; a signal is told by the finalizing deadline.
+    end: Mutex<RunEnd>,
+}
+
+impl Finalizer {
+    /// Writes the summary to `out`, then the other requested outputs.
+    ///
+    /// Only the first call does anything; concurrent calls block until it has finished, so that
+    /// callers may exit the process as soon as this returns. One output that cannot be written
+    /// does not prevent the others: the first failure is returned once they have all been
+    /// attempted, and the later ones are logged to stderr.
+    fn finalize(&self, out: &mut dyn Write) -> Result<(), TimelnError> {
+        let mut result = Ok(());
+        self.once
+            .call_once(|| result = self.write_outputs(out, None));
+        result
+    }
+
+    /// Like `finalize`, but for when the process is about to be killed: the summary is always
+    /// written, then each of the outputs after it only if it can be started within `budget` of
+    /// now, so that the slow ones are skipped rather than cut off halfway. The exporters are
+    /// budgeted too: a skipped SQLite database lacks the totals of the run, and a skipped Parquet
+    /// file its footer.
+    fn finalize_within(&self, out: &mut dyn Write, budget: Duration) -> Result<(), TimelnError> {
+        let mut result = Ok(());
+        let deadline = Instant::now() + budget;
+        self.once
+            .call_once(|| result = self.write_outputs(out, Some(deadline)));
+        result
+    }
+
+    /// Time since the start of the run at `now`, excluding paused time, or up to the latest line
+    /// when replaying or parsing the times of the lines.
+    fn total_time(&self, now: Instant) -> Duration {
+        // Replayed lines arrive at their recorded times, however fast they are replayed.
+        if self.input.input_times {
+            Duration::from_nanos(self.counters.last_arrival.load(Ordering::Acquire))
+        } else {
+            now.duration_since(self.start_time)
+                .saturating_sub(self.counters.pause.paused_at(now))
+        }
+    }
+
+    /// The counters of the run so far, as exported to Prometheus.
+    fn metrics(&self) -> Result<Metrics, TimelnError> {
+        let pattern_matches = if self.summary.pattern_summary {
+            self.plot_config
+                .pattern_labels
+                .iter()
+                .zip(self.counters.pattern_matches.iter())
+                .map(|(label, count)| (label.clone(), count.load(Ordering::Acquire) as u64))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let deltas = DeltaSummary::of(&*self.counters.stats.lock()?);
+        let elapsed = self.total_time(Instant::now());
+        let progress = match &self.summary.progress {
+            Some(progress) => Some(progress.lock()?.snapshot(elapsed)),
+            None => None,
+        };
+        Ok(Metrics {
+            lines: self.counters.total_lines.load(Ordering::Acquire) as u64,
+            matches: self.counters.total_matches.load(Ordering::Acquire) as u64,
+            pattern_matches,
+            bytes: self.counters.total_bytes.load(Ordering::Acquire),
+            last_delta: deltas
+                .as_ref()
+                .map(|_| Duration::from_nanos(self.counters.last_delta.load(Ordering::Acquire))),
+            elapsed,
+            recent_rate: self
+                .display
+                .rate_window
+                .as_ref()
+                .map(|window| (window.window(), window.rate_at(Instant::now()))),
+            deltas,
+            progress,
+        })
+    }
+
+    /// The summary of the run so far, from its `metrics`.
+    fn interim_summary(&self, metrics: &Metrics) -> Result<String, TimelnError> {
+        let overhead = Duration::from_nanos(self.counters.overhead.load(Ordering::Acquire));
+        Ok(self.summarizer.summarize(
+            metrics.lines as usize,
+            metrics.matches as usize,
+            metrics.bytes,
+            &metrics.elapsed,
+            &overhead,
+            &*self.counters.stats.lock()?,
+            &**self.time_format,
+        ))
+    }
+
+    /// The progress of the run so far, as shown by the dashboard.
+    fn dashboard_stats(&self) -> Result<DashboardStats, TimelnError> {
+        let metrics = self.metrics()?;
+        let mut recent_deltas = self
+            .display
+            .recent_deltas
+            .as_ref()
+            .map(RecentDeltas::deltas)
+            .unwrap_or_default();
+        recent_deltas.reverse();
+        recent_deltas.truncate(DASHBOARD_DELTAS);
+        Ok(DashboardStats {
+            elapsed: metrics.elapsed,
+            rate: metrics.recent_rate,
+            recent_deltas,
+            matches: metrics.matches,
+            progress: metrics.progress,
+        })
+    }
+
+    /// The summary of the run so far, as shown by the summary screen.
+    fn screen_stats(&self) -> Result<ScreenStats, TimelnError> {
+        let metrics = self.metrics()?;
+        let summary = self.interim_summary(&metrics)?;
+        let stats = self.counters.stats.lock()?;
+        let mut below = 0.0;
+        let histogram = stats
+            .cdf_points()
+            .into_iter()
+            .filter_map(|point| {
+                let fraction = stats.fraction_at_most(point)?;
+                let bucket = (fraction - below).max(0.0);
+                below = fraction;
+                Some((Duration::from_secs_f64(point), bucket))
+            })
+            .collect();
+        drop(stats);
+        let slowest = self
+            .trackers
+            .slowest
+            .lock()?
+            .to_sorted_vec()
+            .iter()
+            .take(SCREEN_SLOWEST)
+            .enumerate()
+            .map(|(i, gap)| {
+                self.summarizer
+                    .summarize_gap(i + 1, gap, &**self.time_format)
+            })
+            .collect();
+        Ok(ScreenStats {
+            summary,
+            histogram,
+            slowest,
+            recent_deltas: self
+                .display
+                .recent_deltas
+                .as_ref()
+                .map(RecentDeltas::deltas)
+                .unwrap_or_default(),
+        })
+    }
+
+    /// The progress of the run so far, as served on the live stats page.
+    fn live_stats(&self) -> Result<LiveStats, TimelnError> {
+        let metrics = self.metrics()?;
+        let summary = self.interim_summary(&metrics)?;
+        Ok(LiveStats {
+            elapsed: metrics.elapsed,
+            lines: metrics.lines,
+            matches: metrics.matches,
+            bytes: metrics.bytes,
+            last_delta: metrics.last_delta,
+            recent_deltas: self
+                .display
+                .recent_deltas
+                .as_ref()
+                .map(RecentDeltas::deltas)
+                .unwrap_or_default(),
+            summary,
+        })
+    }
+
+    /// Sends the counters of the run so far to StatsD, with the timings batched since the last
+    /// time.
+    fn emit_gauges(&self) -> Result<(), TimelnError> {
+        let metrics = self.metrics()?;
+        if let Some(statsd) = &mut *self.exporters.statsd.lock()? {
+            statsd.gauges(&metrics);
+        }
+        Ok(())
+    }
+
+    /// Starts exporting the metrics of `opt` on background threads, which stop once the context
+    /// and its signal handler are gone. Returns the address the Prometheus metrics are served on.
+    fn start_exporters(
+        self: &Arc<Self>,
+        opt: &TimelnOpt,
+    ) -> Result<Option<SocketAddr>, TimelnError> {
+        let scrape = |finalizer: Weak<Finalizer>| {
+            move || {
+                let finalizer = finalizer.upgrade()?;
+                match finalizer.metrics() {
+                    Ok(metrics) => Some(metrics.render()),
+                    Err(err) => {
+                        eprintln!("timeln: could not collect the metrics: {}", err);
+                        None
+                    }
+                }
+            }
+        };
+        if let Some(path) = &opt.prom_textfile {
+            metrics::export_textfile(
+                PathBuf::from(path),
+                opt.prom_interval.unwrap_or(DEFAULT_TEXTFILE_INTERVAL),
+                scrape(Arc::downgrade(self)),
+            );
+        }
+        if self.exporters.statsd.lock()?.is_some() {
+            let interval = opt.statsd_interval.unwrap_or(statsd::DEFAULT_INTERVAL);
+            let finalizer = Arc::downgrade(self);
+            thread::spawn(move || loop {
+                thread::sleep(interval);
+                let Some(finalizer) = finalizer.upgrade() else {
+                    return;
+                };
+                if let Err(err) = finalizer.emit_gauges() {
+                    eprintln!("timeln: could not collect the metrics: {}", err);
+                    return;
+                }
+            });
+        }
+        match &opt.prom_listen {
+            Some(addr) => Ok(Some(
+                metrics::serve(addr, scrape(Arc::downgrade(self)))
+                    .map_err(|err| TimelnError::output(addr, err))?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Starts drawing the displays on background threads, the dashboard and the summary screen on
+    /// `out`. They stop once the context and its signal handler are gone.
+    fn start_displays(self: &Arc<Self>, out: &Arc<Mutex<Box<dyn Write + Send>>>) {
+        let display = &self.display;
+        if let Some(status) = &display.status {
+            let weak = Arc::downgrade(self);
+            status.start(
+                Box::new(io::stderr()),
+                STATUS_INTERVAL,
+                Arc::clone(&self.time_format),
+                move || weak.upgrade()?.metrics().ok(),
+            );
+        }
+        if let (Some(dashboard), Some((width, _))) = (&display.dashboard, display.terminal_size) {
+            let weak = Arc::downgrade(self);
+            dashboard.start(
+                Arc::clone(out),
+                width,
+                STATUS_INTERVAL,
+                Arc::clone(&self.time_format),
+                move || weak.upgrade()?.dashboard_stats().ok(),
+            );
+        }
+        if let (Some(screen), Some(size)) = (&display.screen, display.terminal_size) {
+            let weak = Arc::downgrade(self);
+            screen.start(
+                Arc::clone(out),
+                size,
+                SCREEN_INTERVAL,
+                Arc::clone(&self.time_format),
+                move || weak.upgrade()?.screen_stats().ok(),
+            );
+        }
+        if let Some(live) = &display.live {
+            let finalizer = Arc::downgrade(self);
+            live.start(move || match finalizer.upgrade()?.live_stats() {
+                Ok(stats) => Some(stats),
+                Err(err) => {
+                    eprintln!("timeln: could not collect the live stats: {}", err);
+                    None
+                }
+            });
+        }
+    }
+
+    /// Writes the placeholder of a run of collapsed lines, muted if color is enabled.
+    fn write_collapsed(&self, out: &mut dyn Write, run: &CollapsedRun) -> io::Result<()> {
+        let placeholder = run.placeholder(&**self.time_format);
+        if self.color {
+            writeln!(out, "{}", placeholder.dimmed())
+        } else {
+            writeln!(out, "{}", placeholder)
+        }
+    }
+
+    /// Colors a line printed along with the summary, if color is enabled.
+    fn paint(&self, line: String) -> String {
+        if self.color {
+            line.green().to_string()
+        } else {
+            line
+        }
+    }
+
+    /// Stops the displays, finishes the input and writes the summary to `out`, then each of the
+    /// `outputs` that can be started before the `deadline`, if any.
+    fn write_outputs(
+        &self,
+        out: &mut dyn Write,
+        deadline: Option<Instant>,
+    ) -> Result<(), TimelnError> {
+        self.stop_displays(out)?;
+        let now = Instant::now();
+        let run = FinishedRun {
+            totals: self.counters.totals(self.total_time(now)),
+            paused: self.counters.pause.paused_at(now),
+        };
+        let footer = Record::Footer {
+            lines: run.totals.lines,
+            total: run.totals.total_time,
+        };
+        self.finish_input(out, &footer)?;
+        let overhead = Duration::from_nanos(self.counters.overhead.load(Ordering::Acquire));
+        ignore_broken_pipe(writeln!(
+            out,
+            "{}",
+            self.summarizer.summarize(
+                run.totals.lines as usize,
+                run.totals.matches as usize,
+                run.totals.bytes,
+                &run.totals.total_time,
+                &overhead,
+                &*self.counters.stats.lock()?,
+                &**self.time_format
+            )
+        ))?;
+        let sections: &[SummarySection] = &[
+            Self::gap_lines,
+            Self::input_lines,
+            Self::pattern_lines,
+            Self::average_lines,
+            Self::distribution_lines,
+            Self::burst_lines,
+            Self::activity_lines,
+            Self::lap_lines,
+            Self::span_lines,
+            Self::phase_lines,
+            Self::value_lines,
+            Self::reported_lines,
+            Self::group_lines,
+            Self::paused_lines,
+        ];
+        for section in sections {
+            for line in section(self, &run)? {
+                ignore_broken_pipe(writeln!(out, "{}", line))?;
+            }
+        }
+        if let Some(events) = &self.exporters.events {
+            events.send(record_event(&footer));
+            let dropped = events.finish();
+            if dropped > 0 {
+                eprintln!("timeln: dropped {} events for a slow reader", dropped);
+            }
+        }
+        self.emit_gauges()?;
+        if let Some(live) = &self.display.live {
+            live.shutdown();
+        }
+
+        let (durations, total) = match self.outputs.iter().any(FinalOutput::plotted) {
+            true => self.exporters.sink.lock()?.take(),
+            false => (Vec::new(), 0),
+        };
+        let data = plot_data(&durations, total);
+        let end = match deadline {
+            Some(_) => RunEnd::Interrupted,
+            None => *self.end.lock()?,
+        };
+        let mut failure = None;
+        for output in &self.outputs {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                eprintln!("timeln: no time left to write the {}", output.name());
+                continue;
+            }
+            let result = self.write_output(output, &run, end, &data, out);
+            keep_first_error(&mut failure, result);
+        }
+        failure.map_or(Ok(()), Err)
+    }
+
+    /// Clears what is drawn on the terminal, and writes the output held back by a pause to `out`.
+    fn stop_displays(&self, out: &mut dyn Write) -> Result<(), TimelnError> {
+        if let Some(status) = &self.display.status {
+            status.clear();
+        }
+        if let Some(dashboard) = &self.display.dashboard {
+            dashboard.clear(out);
+        }
+        if let Some(screen) = &self.display.screen {
+            screen.clear(out);
+        }
+        if self.display.keys {
+            keys::restore_terminal();
+        }
+        if let Some(held) = self.display.held.lock()?.take() {
+            ignore_broken_pipe(out.write_all(&held))?;
+        }
+        Ok(())
+    }
+
+    /// Writes the lines still collapsed to `out`, flushes the raw copy of the input, removes the
+    /// listening socket and ends the record file with `footer`.
+    fn finish_input(&self, out: &mut dyn Write, footer: &Record) -> Result<(), TimelnError> {
+        if let Some(run) = self.trackers.collapsed.lock()?.take() {
+            ignore_broken_pipe(self.write_collapsed(out, &run))?;
+        }
+        write_tee(&mut *self.exporters.tee.lock()?, |tee| tee.flush());
+        if let Some(path) = &self.input.listen_socket {
+            if let Err(err) = std::fs::remove_file(path) {
+                eprintln!("timeln: could not remove {}: {}", path.display(), err);
+            }
+        }
+        if let Some(recorder) = &mut *self.exporters.recorder.lock()? {
+            if let Err(err) = recorder.write(footer) {
+                eprintln!("timeln: could not finish the record file: {}", err);
+            }
+        }
+        Ok(())
+    }
+
+    /// The largest gaps, after the detailed summary.
+    fn gap_lines(&self, _: &FinishedRun) -> Result<Vec<String>, TimelnError> {
+        let slowest = self.trackers.slowest.lock()?.to_sorted_vec();
+        Ok(slowest
+            .iter()
+            .take(self.summary.gaps)
+            .enumerate()
+            .map(|(i, gap)| {
+                let summary = self
+                    .summarizer
+                    .summarize_gap(i + 1, gap, &**self.time_format);
+                self.paint(summary)
+            })
+            .collect())
+    }
+
+    /// The lines of each stream of a command, and those whose time or structure could not be
+    /// parsed.
+    fn input_lines(&self, _: &FinishedRun) -> Result<Vec<String>, TimelnError> {
+        let mut lines = Vec::new();
+        let stream_lines = &self.counters.stream_lines;
+        let stdout_lines = stream_lines[Stream::Stdout as usize].load(Ordering::Acquire);
+        let stderr_lines = stream_lines[Stream::Stderr as usize].load(Ordering::Acquire);
+        if stdout_lines + stderr_lines > 0 {
+            lines.push(format!(
+                "[Stdout Lines: {}, Stderr Lines: {}]",
+                stdout_lines, stderr_lines
+            ));
+        }
+        if let Some(timestamps) = &self.input.timestamps {
+            lines.push(timestamps.summary());
+        }
+        if let Some(failures) = &self.input.json_failures {
+            lines.push(format!(
+                "[JSON Parse Failures: {}]",
+                failures.load(Ordering::Acquire)
+            ));
+        }
+        if let Some(untimed) = &self.input.logfmt_untimed {
+            lines.push(format!(
+                "[Logfmt Untimed Lines: {}]",
+                untimed.load(Ordering::Acquire)
+            ));
+        }
+        Ok(lines.into_iter().map(|line| self.paint(line)).collect())
+    }
+
+    /// The matches of each pattern.
+    fn pattern_lines(&self, _: &FinishedRun) -> Result<Vec<String>, TimelnError> {
+        if !self.summary.pattern_summary {
+            return Ok(Vec::new());
+        }
+        // With match colors, each pattern is named in its color rather than the summary's.
+        let match_colors = &self.summary.match_colors;
+        let colored = self.color && !match_colors.is_empty();
+        let labels: Vec<String> = self
+            .plot_config
+            .pattern_labels
+            .iter()
+            .enumerate()
+            .map(|(i, label)| match colored {
+                true => label
+                    .color(match_colors[i % match_colors.len()])
+                    .to_string(),
+                false => label.clone(),
+            })
+            .collect();
+        let matches: Vec<(&str, usize)> = labels
+            .iter()
+            .zip(self.counters.pattern_matches.iter())
+            .map(|(label, count)| (label.as_str(), count.load(Ordering::Acquire)))
+            .collect();
+        let summary = self.summarizer.summarize_patterns(&matches);
+        Ok(vec![if colored {
+            summary
+        } else {
+            self.paint(summary)
+        }])
+    }
+
+    /// The moving average and the rates of the deltas, and the progress towards the expected
+    /// matches.
+    fn average_lines(&self, run: &FinishedRun) -> Result<Vec<String>, TimelnError> {
+        let mut lines = Vec::new();
+        if let (Some(alpha), Some(ewma)) = (self.summary.ewma_alpha, *self.summary.ewma.lock()?) {
+            lines.push(
+                self.summarizer
+                    .summarize_ewma(&ewma, alpha, &**self.time_format),
+            );
+        }
+        if self.summary.as_rate {
+            let stats = self.counters.stats.lock()?;
+            let rates = stats.min().zip(stats.max()).map(|(min, max)| {
+                self.summarizer.summarize_rates(
+                    &Duration::from_secs_f64(stats.mean()),
+                    &Duration::from_secs_f64(min),
+                    &Duration::from_secs_f64(max),
+                )
+            });
+            lines.extend(rates);
+        }
+        if let Some(progress) = &self.summary.progress {
+            let progress = progress.lock()?;
+            let total_time = run.totals.total_time;
+            let snapshot = progress.snapshot(total_time);
+            lines.push(self.summarizer.summarize_progress(
+                snapshot.matches,
+                snapshot.total,
+                progress.last_estimate().as_ref(),
+                &total_time,
+                &**self.time_format,
+            ));
+        }
+        Ok(lines.into_iter().map(|line| self.paint(line)).collect())
+    }
+
+    /// The outliers flagged, and the cumulative distribution of the deltas.
+    fn distribution_lines(&self, _: &FinishedRun) -> Result<Vec<String>, TimelnError> {
+        let mut lines = Vec::new();
+        if let Some(outliers) = &self.summary.outliers {
+            lines.push(
+                self.summarizer
+                    .summarize_outliers(outliers.flagged.load(Ordering::Acquire), outliers.sigma),
+            );
+        }
+        if let Some(points) = &self.summary.cdf_points {
+            let stats = self.counters.stats.lock()?;
+            let points: Vec<Duration> = if points.is_empty() {
+                stats
+                    .cdf_points()
+                    .into_iter()
+                    .map(Duration::from_secs_f64)
+                    .collect()
+            } else {
+                points.clone()
+            };
+            let cdf: Vec<(Duration, f64)> = points
+                .into_iter()
+                .filter_map(|point| {
+                    let fraction = stats.fraction_at_most(point.as_secs_f64())?;
+                    Some((point, fraction))
+                })
+                .collect();
+            if !cdf.is_empty() {
+                lines.push(self.summarizer.summarize_cdf(&cdf));
+            }
+        }
+        Ok(lines.into_iter().map(|line| self.paint(line)).collect())
+    }
+
+    /// The bursts detected, each on its own line after their count.
+    fn burst_lines(&self, _: &FinishedRun) -> Result<Vec<String>, TimelnError> {
+        let Some(detector) = &*self.trackers.bursts.lock()? else {
+            return Ok(Vec::new());
+        };
+        let bursts = detector.bursts();
+        let mut lines = vec![self
+            .summarizer
+            .summarize_bursts(bursts.len(), &detector.threshold())];
+        for burst in bursts {
+            lines.push(self.summarizer.summarize_burst(&burst, &**self.time_format));
+        }
+        Ok(lines.into_iter().map(|line| self.paint(line)).collect())
+    }
+
+    /// The activity over the whole run.
+    fn activity_lines(&self, run: &FinishedRun) -> Result<Vec<String>, TimelnError> {
+        let Some(activity) = &mut *self.trackers.activity.lock()? else {
+            return Ok(Vec::new());
+        };
+        activity.extend_to(run.totals.total_time);
+        let summary = self
+            .summarizer
+            .summarize_activity(activity, self.summary.ascii);
+        Ok(vec![self.paint(summary)])
+    }
+
+    /// The last lap, which the end of the run finishes, and the laps of the run and of each file.
+    fn lap_lines(&self, run: &FinishedRun) -> Result<Vec<String>, TimelnError> {
+        let total_time = run.totals.total_time;
+        let mut lines = Vec::new();
+        let mut laps = self.trackers.laps.lock()?;
+        if let Some(lap) = laps.finish(total_time) {
+            if let Some(events) = &self.exporters.events {
+                events.send(lap_event(&lap));
+            }
+            if self.summary.lap_summary {
+                lines.push(self.summarizer.summarize_lap(&lap, &**self.time_format));
+            }
+        }
+        if let Some(mean) = laps.mean() {
+            lines.push(
+                self.summarizer
+                    .summarize_laps(laps.count, &mean, &**self.time_format),
+            );
+        }
+        drop(laps);
+        if let Some(files) = &mut *self.trackers.files.lock()? {
+            for (name, lap) in files.finish(total_time) {
+                lines.push(
+                    self.summarizer
+                        .summarize_file(name, &lap, &**self.time_format),
+                );
+            }
+        }
+        Ok(lines.into_iter().map(|line| self.paint(line)).collect())
+    }
+
+    /// The spans timed, and those left open.
+    fn span_lines(&self, _: &FinishedRun) -> Result<Vec<String>, TimelnError> {
+        let Some(spans) = &*self.trackers.spans.lock()? else {
+            return Ok(Vec::new());
+        };
+        let stats = spans.stats();
+        let format = |secs: Option<f64>| match secs {
+            Some(secs) => self
+                .time_format
+                .format_duration(&Duration::from_secs_f64(secs)),
+            None => "-".to_string(),
+        };
+        let mut lines = vec![format!(
+            "[Spans: {}, Mean: {}, Max: {}, Incomplete: {}]",
+            stats.count(),
+            format((stats.count() > 0).then(|| stats.mean())),
+            format(stats.max()),
+            spans.open().len()
+        )];
+        for open in spans.open() {
+            lines.push(format!(
+                "[incomplete span, started at {}] {}",
+                self.time_format.format_duration(&open.started),
+                open.start_text
+            ));
+        }
+        Ok(lines.into_iter().map(|line| self.paint(line)).collect())
+    }
+
+    /// The phases detected.
+    fn phase_lines(&self, run: &FinishedRun) -> Result<Vec<String>, TimelnError> {
+        let Some(phases) = &*self.trackers.phases.lock()? else {
+            return Ok(Vec::new());
+        };
+        Ok(phases
+            .phases(run.totals.total_time)
+            .iter()
+            .map(|phase| {
+                let summary = self.summarizer.summarize_phase(phase, &**self.time_format);
+                self.paint(summary)
+            })
+            .collect())
+    }
+
+    /// The values extracted from the lines, one series per line.
+    fn value_lines(&self, _: &FinishedRun) -> Result<Vec<String>, TimelnError> {
+        Ok(self
+            .trackers
+            .extractors
+            .lock()?
+            .iter()
+            .flat_map(Extractor::series)
+            .map(|series| self.paint(self.summarizer.summarize_values(series)))
+            .collect())
+    }
+
+    /// The differences between the measured and self-reported durations.
+    fn reported_lines(&self, _: &FinishedRun) -> Result<Vec<String>, TimelnError> {
+        let Some(reported) = &*self.trackers.reported.lock()? else {
+            return Ok(Vec::new());
+        };
+        let differences = reported.differences();
+        let format = |difference: Option<f64>| {
+            difference.map_or("-".to_string(), |difference| {
+                format_difference(difference, &**self.time_format)
+            })
+        };
+        let summary = format!(
+            "[Self-Reported Lines: {}, Mean Difference: {}, Max Difference: {}, Malformed: {}]",
+            differences.count(),
+            format((differences.count() > 0).then(|| differences.mean())),
+            format(reported.max_abs_difference()).trim_start_matches('+'),
+            reported.malformed()
+        );
+        Ok(vec![self.paint(summary)])
+    }
+
+    /// The table of the deltas of each group.
+    fn group_lines(&self, _: &FinishedRun) -> Result<Vec<String>, TimelnError> {
+        let Some(groups) = &*self.trackers.groups.lock()? else {
+            return Ok(Vec::new());
+        };
+        let table = self
+            .summarizer
+            .summarize_groups(groups, &**self.time_format);
+        Ok(vec![self.paint(table)])
+    }
+
+    /// The time during which timing was paused, if it was.
+    fn paused_lines(&self, run: &FinishedRun) -> Result<Vec<String>, TimelnError> {
+        if run.paused.is_zero() {
+            return Ok(Vec::new());
+        }
+        let paused = format!(
+            "[Paused: {}]",
+            self.time_format.format_duration(&run.paused)
+        );
+        Ok(vec![self.paint(paused)])
+    }
+
+    /// Writes `output` for the finished `run`, which ended with `end`, drawing the plots from
+    /// `data`. The terminal plots are written to `out`.
+    fn write_output(
+        &self,
+        output: &FinalOutput,
+        run: &FinishedRun,
+        end: RunEnd,
+        data: &PlotData,
+        out: &mut dyn Write,
+    ) -> Result<(), TimelnError> {
+        let totals = &run.totals;
+        match output {
+            FinalOutput::Textfile(path) => metrics::write_textfile(path, &self.metrics()?.render())
+                .map_err(|err| TimelnError::output(path, err)),
+            FinalOutput::Sqlite(path) => match &mut *self.exporters.sqlite.lock()? {
+                Some(store) => store
+                    .finish(totals)
+                    .map_err(|err| TimelnError::output(path, err)),
+                None => Ok(()),
+            },
+            FinalOutput::Parquet(path) => match self.exporters.parquet.lock()?.take() {
+                Some(writer) => writer
+                    .finish()
+                    .map_err(|err| TimelnError::output(path, err)),
+                None => Ok(()),
+            },
+            FinalOutput::Webhook => {
+                if let Some(notifier) = &self.hooks.notifier {
+                    notifier.finished(totals, end);
+                }
+                Ok(())
+            }
+            FinalOutput::Desktop => {
+                if let Some(desktop) = &self.hooks.desktop {
+                    let message = self.summarizer.summarize_finished(
+                        totals.lines as usize,
+                        &totals.total_time,
+                        &**self.time_format,
+                    );
+                    desktop.finished(&message);
+                }
+                Ok(())
+            }
+            FinalOutput::TermPlots => {
+                ignore_broken_pipe(writeln!(out, "{}", render_term_plots(data, self.color)))?;
+                Ok(())
+            }
+            FinalOutput::Plots => {
+                let mut failure = None;
+                let mut plot_config = self.plot_config.clone();
+                let phases = self.trackers.phases.lock()?;
+                if let Some(phases) = phases.as_ref().filter(|_| self.plot_phases) {
+                    plot_config.phase_boundaries = phases
+                        .phases(totals.total_time)
+                        .iter()
+                        .skip(1)
+                        .map(|phase| (phase.first_timed, phase.start.as_secs_f64()))
+                        .collect();
+                }
+                drop(phases);
+                write_plots(data, &plot_config, &self.plot_dir, &mut failure);
+                write_value_plots(
+                    &self.trackers.extractors.lock()?,
+                    &self.plot_config,
+                    &self.plot_dir,
+                    &mut failure,
+                );
+                failure.map_or(Ok(()), Err)
+            }
+            FinalOutput::PlotData(path) => {
+                let mut sink = self.exporters.sink.lock()?;
+                let result = match &mut *sink {
+                    SnapshotSink::Streaming { .. } => sink.finish().map_err(PlotError::from),
+                    _ => write_plot_data(data, path, &self.plot_config),
+                };
+                result.map_err(|err| TimelnError::plot_data(path, err))
+            }
+            FinalOutput::Report(path) => write_report_file(
+                path,
+                data,
+                &*self.counters.stats.lock()?,
+                &*self.trackers.slowest.lock()?,
+                totals.lines as usize,
+                totals.matches as usize,
+                totals.bytes,
+                totals.total_time,
+                &self.plot_config,
+                &**self.time_format,
+            )
+            .map_err(|err| TimelnError::report(path, err)),
+        }
+    }
+}
+
+/// Finalizes the run to `out`, within `budget` if the process is about to be killed, and exits the
+/// process with `status`.
+fn exit_after_finalizing(
+    finalizer: &Finalizer,
+    out: &Mutex<Box<dyn Write + Send>>,
+    budget: Option<Duration>,
+    status: i32,
+) -> ! {
+    // A poisoned output is still usable; the summary matters more than the panic.
+    let mut out = out.lock().unwrap_or_else(|err| err.into_inner());
+    let result = match budget {
+        Some(budget) => finalizer.finalize_within(&mut *out, budget),
+        None => finalizer.finalize(&mut *out),
+    };
+    if let Err(err) = result.and_then(|_| Ok(out.flush()?)) {
+        eprintln!("timeln: {}", err);
+    }
+    std::process::exit(status);
+}
+
+/// Applies a `command` given with a key during the run, writing to `out`. Quitting exits the
+/// process with `status`.
+fn apply_key(
+    finalizer: &Finalizer,
+    out: &Mutex<Box<dyn Write + Send>>,
+    command: KeyCommand,
+    status: i32,
+) -> Result<(), TimelnError> {
+    match command {
+        KeyCommand::Summary => {
+            let summary = finalizer.interim_summary(&finalizer.metrics()?)?;
+            let mut out = out.lock()?;
+            ignore_broken_pipe(writeln!(out, "{}", finalizer.paint(summary)))?;
+            ignore_broken_pipe(out.flush())?;
+        }
+        KeyCommand::TogglePause => {
+            let mut out = out.lock()?;
+            let mut held = finalizer.display.held.lock()?;
+            match held.take() {
+                Some(lines) => {
+                    ignore_broken_pipe(out.write_all(&lines))?;
+                    ignore_broken_pipe(out.flush())?;
+                }
+                None => *held = Some(Vec::new()),
+            }
+        }
+        KeyCommand::Quit => exit_after_finalizing(finalizer, out, None, status),
+        KeyCommand::ToggleColor => {
+            let colored = colored::control::SHOULD_COLORIZE.should_colorize();
+            colored::control::set_override(!colored);
+        }
+    }
+    Ok(())
+}
+
+/// Builds a `TimelnContext`, replacing any of its default parts.
+///
+/// By default the context reads stdin, writes to stdout, formats times in seconds, annotates lines
+/// with a `SimpleAnnotator` and prints the summary selected by `summary` (a `SimpleSummarizer`
+/// unless changed).
+///
+/// # Example
+///
+/// ```
+/// use timeln::timeln::TimelnContextBuilder;
+/// use timeln::{IterReadData, MillisecondsFormat};
+///
+/// let mut context = TimelnContextBuilder::new()
+///     .reader(Box::new(IterReadData::new(["compiling", "done"])))
+///     .output(Box::new(std::io::sink()))
+///     .time_format(Box::new(MillisecondsFormat))
+///     .regex("done")
+///     .build()?;
+/// context.run()?;
+/// # Ok::<(), timeln::TimelnError>(())
+/// ```
+#[derive(Default)]
+pub struct TimelnContextBuilder {
+    opt: TimelnOpt,
+    reader: Option<Box<dyn ReadData>>,
+    output: Option<Box<dyn Write + Send>>,
+    annotator: Option<Box<dyn TimelnAnnotation>>,
+    summarizer: Option<Box<dyn Summarizer>>,
+    time_format: Option<Arc<Box<dyn TimeFormat>>>,
+    instant_replay: bool,
+    quiet: bool,
+    keep_line_text: bool,
+    plot_dir: Option<PathBuf>,
+}
+
+impl From<TimelnOpt> for TimelnContextBuilder {
+    /// Creates a builder with the default parts and the given options.
+    fn from(opt: TimelnOpt) -> Self {
+        Self {
+            opt,
+            ..Self::default()
+        }
+    }
+}
+
+impl TimelnContextBuilder {
+    /// Creates a builder with the default parts and options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the lines from `reader` instead of stdin.
+    pub fn reader(mut self, reader: Box<dyn ReadData>) -> Self {
+        self.reader = Some(reader);
+        self
+    }
+
+    /// Writes the annotated lines and the summary to `output` instead of stdout.
+    pub fn output(mut self, output: Box<dyn Write + Send>) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    /// Annotates the lines with `annotator` instead of a `SimpleAnnotator`.
+    pub fn annotator(mut self, annotator: Box<dyn TimelnAnnotation>) -> Self {
+        self.annotator = Some(annotator);
+        self
+    }
+
+    /// Summarizes the run with `summarizer`, ignoring `summary`.
+    pub fn summarizer(mut self, summarizer: Box<dyn Summarizer>) -> Self {
+        self.summarizer = Some(summarizer);
+        self
+    }
+
+    /// Stamps the lines like moreutils `ts` in `mode`, with the strftime `format` or the default
+    /// format of `ts`, instead of with a `SimpleAnnotator`.
+    pub fn ts(mut self, mode: TsMode, format: Option<String>) -> Self {
+        self.opt.ts = Some(format);
+        self.opt.ts_incremental = mode == TsMode::Incremental;
+        self.opt.ts_since_start = mode == TsMode::SinceStart;
+        self
+    }
+
+    /// Pads the times in the annotations of the default annotator to the widest of each column so
+    /// far, so that the columns line up and only ever grow.
+    pub fn align(mut self, align: bool) -> Self {
+        self.opt.align = align;
+        self
+    }
+
+    /// Formats times with `time_format` instead of `SecondsFormat`, in the default annotator,
+    /// the summary and the report.
+    pub fn time_format(mut self, time_format: Box<dyn TimeFormat>) -> Self {
+        self.time_format = Some(Arc::new(time_format));
+        self
+    }
+
+    /// Runs `command` and times the lines of its stdout and stderr instead of reading stdin,
+    /// unless a reader is given. The first element is the program and the rest its arguments.
+    pub fn command(mut self, command: Vec<String>) -> Self {
+        self.opt.command = command;
+        self
+    }
+
+    /// Reads the lines of the file at `path` instead of stdin, unless a reader or command is given.
+    pub fn file(mut self, path: Option<String>) -> Self {
+        self.opt.file = path.into_iter().collect();
+        self
+    }
+
+    /// Reads the lines of the files at `paths`, one after the other, instead of stdin, unless a
+    /// reader or command is given. With several files, each starts with a header naming it and has
+    /// a row in the summary.
+    pub fn files(mut self, paths: Vec<String>) -> Self {
+        self.opt.file = paths;
+        self
+    }
+
+    /// Sets whether the elapsed times and deltas are measured from the first line of each file
+    /// rather than from the start of the run and the last line of the previous file.
+    pub fn reset_per_file(mut self, reset_per_file: bool) -> Self {
+        self.opt.reset_per_file = reset_per_file;
+        self
+    }
+
+    /// Keeps reading the file as it grows, reopening it when it is rotated in `FollowMode::Name`.
+    pub fn follow(mut self, follow: Option<FollowMode>) -> Self {
+        self.opt.follow = follow;
+        self
+    }
+
+    /// Sets how often a followed file is checked for new lines.
+    pub fn follow_interval(mut self, interval: Option<Duration>) -> Self {
+        self.opt.follow_interval = interval;
+        self
+    }
+
+    /// Replaces each run of consecutive timed lines whose deltas are below `threshold` with a
+    /// single placeholder giving their count and total time. The lines still count in the summary
+    /// and plots.
+    pub fn collapse_faster_than(mut self, threshold: Option<Duration>) -> Self {
+        self.opt.collapse_faster_than = threshold;
+        self
+    }
+
+    /// Joins the input lines up to and including each line matching `pattern` into one line, timed
+    /// and matched as a whole. The pattern is compiled by `build`.
+    pub fn join_until(mut self, pattern: Option<&str>) -> Self {
+        self.opt.join_until = pattern.map(str::to_string);
+        self
+    }
+
+    /// Sets whether the input lines are joined into paragraphs separated by blank lines, unless
+    /// `join_until` is set.
+    pub fn paragraph(mut self, paragraph: bool) -> Self {
+        self.opt.paragraph = paragraph;
+        self
+    }
+
+    /// Ends the input, as if it were closed, once no line has arrived for `timeout`.
+    pub fn exit_idle(mut self, timeout: Option<Duration>) -> Self {
+        self.opt.exit_idle = timeout;
+        self
+    }
+
+    /// Replays the record file at `path` instead of reading stdin, unless a reader is given.
+    pub fn replay(mut self, path: Option<String>) -> Self {
+        self.opt.replay = path;
+        self
+    }
+
+    /// Replays the record file at `speed` times its recorded pace.
+    pub fn speed(mut self, speed: Option<f64>) -> Self {
+        self.opt.speed = speed;
+        self
+    }
+
+    /// Waits at most `clamp` between two replayed lines.
+    pub fn replay_clamp(mut self, clamp: Option<Duration>) -> Self {
+        self.opt.replay_clamp = clamp;
+        self
+    }
+
+    /// Replays the CSV file given to `replay`, with the times in `columns`. Any replayed file
+    /// whose name ends in `.csv` is read as CSV, with the default columns unless given.
+    pub fn csv_columns(mut self, columns: Option<CsvColumns>) -> Self {
+        self.opt.csv_columns = columns;
+        self
+    }
+
+    /// Times the lines by the timestamps that the regex `pattern` finds in them, rather than by
+    /// when they are read.
+    pub fn parse_time(mut self, pattern: Option<String>) -> Self {
+        self.opt.parse_time = pattern;
+        self
+    }
+
+    /// Parses the timestamps found by `parse_time` with the chrono `layout`, such as
+    /// `%b %e %H:%M:%S`, instead of `DEFAULT_TIME_LAYOUT`.
+    pub fn time_layout(mut self, layout: Option<String>) -> Self {
+        self.opt.time_layout = layout;
+        self
+    }
+
+    /// Reads the lines as JSON objects, timed by their time field and displayed as their line
+    /// field.
+    pub fn json_input(mut self, json: bool) -> Self {
+        self.opt.json_input = json;
+        self
+    }
+
+    /// Reads the time of each JSON line from the field `name` instead of `DEFAULT_TIME_FIELD`.
+    pub fn time_field(mut self, name: Option<String>) -> Self {
+        self.opt.time_field = name;
+        self
+    }
+
+    /// Displays each JSON line as its field `name`, if it has one, rather than as a whole.
+    pub fn line_field(mut self, name: Option<String>) -> Self {
+        self.opt.line_field = name;
+        self
+    }
+
+    /// Reads the lines as logfmt, timed by their time key, `time_field` or else
+    /// `DEFAULT_TIME_FIELD`, with `group_by` and `extract` naming keys.
+    pub fn logfmt(mut self, logfmt: bool) -> Self {
+        self.opt.logfmt = logfmt;
+        self
+    }
+
+    /// Displays the key `key` of each logfmt line, rather than all of its keys. May be called
+    /// multiple times, to display the keys in that order.
+    pub fn field(mut self, key: &str) -> Self {
+        self.opt.fields.push(key.to_string());
+        self
+    }
+
+    /// Reads the entries of the systemd journal, which needs the `journald` feature on Linux, or
+    /// the journal entries written by `journalctl --output=json` to the reader.
+    pub fn journal(mut self, journal: bool) -> Self {
+        self.opt.journal = journal;
+        self
+    }
+
+    /// Reads the journal entries of the systemd `unit` only; may be called for several units.
+    pub fn unit(mut self, unit: &str) -> Self {
+        self.opt.unit.push(unit.to_string());
+        self
+    }
+
+    /// Reads the journal entries of `priority` only.
+    pub fn priority(mut self, priority: Option<JournalPriority>) -> Self {
+        self.opt.priority = priority;
+        self
+    }
+
+    /// Reads the lines written to the Unix domain socket created at `path`, or to the named pipe
+    /// already there, instead of stdin.
+    pub fn listen(mut self, path: Option<String>) -> Self {
+        self.opt.listen = path;
+        self
+    }
+
+    /// Sets whether the connections after the first one to the socket of `listen` are read as
+    /// part of the same run.
+    pub fn listen_keep(mut self, keep: bool) -> Self {
+        self.opt.listen_keep = keep;
+        self
+    }
+
+    /// Reads the lines of a TCP connection made to `addr`, such as `localhost:9000`, instead of
+    /// stdin.
+    pub fn connect(mut self, addr: Option<String>) -> Self {
+        self.opt.connect = addr;
+        self
+    }
+
+    /// Reads the lines of the first TCP connection accepted on `addr`, such as `0.0.0.0:9000`,
+    /// instead of stdin.
+    pub fn listen_tcp(mut self, addr: Option<String>) -> Self {
+        self.opt.listen_tcp = addr;
+        self
+    }
+
+    /// Sets whether a TCP connection that closes or drops is made again, or the next one
+    /// accepted, instead of ending the input.
+    pub fn reconnect(mut self, reconnect: bool) -> Self {
+        self.opt.reconnect = reconnect;
+        self
+    }
+
+    /// Waits at most `timeout` for the TCP connection of `connect` to be made, instead of
+    /// `DEFAULT_CONNECT_TIMEOUT`.
+    pub fn connect_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.opt.connect_timeout = timeout;
+        self
+    }
+
+    /// Replays the record file as fast as possible instead of at its recorded pace.
+    pub fn instant_replay(mut self, instant: bool) -> Self {
+        self.instant_replay = instant;
+        self
+    }
+
+    /// Copies every input line, with its line ending, to the file `path`. Invalid UTF-8 is
+    /// copied as read, that is replaced with U+FFFD.
+    pub fn tee_raw(mut self, path: Option<String>) -> Self {
+        self.opt.tee_raw = path;
+        self
+    }
+
+    /// Records every input line with its arrival time to the record file `path`.
+    pub fn record(mut self, path: Option<String>) -> Self {
+        self.opt.record = path;
+        self
+    }
+
+    /// Sets whether each record is synced to disk before the next line is processed.
+    pub fn record_sync(mut self, sync: bool) -> Self {
+        self.opt.record_sync = sync;
+        self
+    }
+
+    /// Outputs only the summary and terminal plots, not the annotated lines.
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Keeps the text of the lines sampled for the plots and report, along with their snapshots.
+    pub fn keep_line_text(mut self, keep: bool) -> Self {
+        self.keep_line_text = keep;
+        self
+    }
+
+    /// Writes the SVG plots to `dir` instead of the current directory.
+    pub fn plot_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.plot_dir = Some(dir.into());
+        self
+    }
+
+    /// Colors the default annotations, stderr lines and summary.
+    pub fn color(mut self, color: bool) -> Self {
+        self.opt.color = color;
+        self
+    }
+
+    /// Adds a regex pattern; only the matching lines are timed, unless passthrough is enabled.
+    /// The pattern is compiled by `build`.
+    pub fn regex(mut self, pattern: &str) -> Self {
+        self.opt.regex.push(pattern.to_string());
+        self
+    }
+
+    /// Labels the next regex pattern without a label, pairing labels with patterns in order. Each
+    /// matching line is then prefixed with the label of its pattern, which also names the pattern
+    /// in the summary, the plot legend and the report. `build` fails unless every pattern gets a
+    /// label.
+    pub fn label(mut self, label: &str) -> Self {
+        self.opt.label.push(label.to_string());
+        self
+    }
+
+    /// Highlights the matches of each regex pattern, and its label, in the color at its index in
+    /// `colors`, cycling through them if there are more patterns. The counts of the patterns in
+    /// the summary take their colors as well.
+    pub fn match_colors(mut self, colors: Vec<Color>) -> Self {
+        self.opt.match_colors = colors;
+        self
+    }
+
+    /// Starts a new lap, measuring elapsed times from the line, whenever a line matches
+    /// `pattern`, whether or not it is timed. The pattern is compiled by `build`.
+    pub fn reset_on(mut self, pattern: Option<&str>) -> Self {
+        self.opt.reset_on = pattern.map(str::to_string);
+        self
+    }
+
+    /// Compares the duration that each timed line reports about itself, captured by the first
+    /// group of `pattern` (such as `took (\S+)`), with its measured delta, annotating the line with
+    /// both and summarizing the differences. The pattern is compiled by `build`.
+    pub fn self_reported(mut self, pattern: Option<&str>) -> Self {
+        self.opt.self_reported = pattern.map(str::to_string);
+        self
+    }
+
+    /// Keeps separate interval statistics for each value of the capture group `name` of the regex
+    /// patterns, or of the key `name` with `logfmt`, printed as a table after the summary. `build`
+    /// fails if no pattern has the group.
+    pub fn group_by(mut self, name: Option<&str>) -> Self {
+        self.opt.group_by = name.map(str::to_string);
+        self
+    }
+
+    /// Extracts the values captured by the named groups of `pattern` from each line matching it,
+    /// summarizing them and plotting them with `plot`. May be called multiple times. The pattern
+    /// is compiled by `build`. With `logfmt`, `pattern` is a key whose values are extracted.
+    pub fn extract(mut self, pattern: &str) -> Self {
+        self.opt.extract.push(pattern.to_string());
+        self
+    }
+
+    /// Measures the spans from each line matching `start` to the next line matching `end`,
+    /// printing each span and summarizing them. Nested spans are paired like brackets. The
+    /// patterns are compiled by `build`.
+    pub fn between(mut self, start: &str, end: &str) -> Self {
+        self.opt.between = vec![start.to_string(), end.to_string()];
+        self
+    }
+
+    /// Detects the phases of the run from the changes of pace of the deltas, printing a notice when
+    /// one starts and summarizing them. A phase starts when the mean of the latest `window` deltas
+    /// (20 if not given) is more than `ratio` (3 if not given) times faster or slower than that of
+    /// the current phase.
+    pub fn detect_phases(
+        mut self,
+        detect: bool,
+        window: Option<usize>,
+        ratio: Option<f64>,
+    ) -> Self {
+        self.opt.detect_phases = detect;
+        self.opt.phase_window = window;
+        self.opt.phase_ratio = ratio;
+        self
+    }
+
+    /// Sets whether the detected phase boundaries are drawn on the delta plot.
+    pub fn plot_phases(mut self, plot_phases: bool) -> Self {
+        self.opt.plot_phases = plot_phases;
+        self
+    }
+
+    /// Sets whether a summary of each lap is printed when it ends.
+    pub fn lap_summary(mut self, lap_summary: bool) -> Self {
+        self.opt.lap_summary = lap_summary;
+        self
+    }
+
+    /// Sets whether leading and trailing whitespace is trimmed from the lines; by default only the
+    /// line ending is removed.
+    pub fn trim(mut self, trim: bool) -> Self {
+        self.opt.trim = trim;
+        self
+    }
+
+    /// Sets whether only the annotation of each timed line is output, without the line itself.
+    pub fn no_line(mut self, no_line: bool) -> Self {
+        self.opt.no_line = no_line;
+        self
+    }
+
+    /// Outputs only the matches of the regex patterns, each on a line of its own with the times of
+    /// its line, like `grep -o`, or the capture `group` of them if given. `build` fails if no
+    /// pattern has the group.
+    pub fn only_matching(mut self, only_matching: bool, group: Option<CaptureGroup>) -> Self {
+        self.opt.only_matching = only_matching;
+        self.opt.group = group;
+        self
+    }
+
+    /// Prints `before` lines before each match of the regex patterns and `after` lines after it,
+    /// like `grep -B` and `-A`, annotated with their own arrival times but not timed. The context
+    /// lines are prefixed with `-`, and dimmed if color is enabled, and the matches with `:`.
+    pub fn context(mut self, before: Option<usize>, after: Option<usize>) -> Self {
+        self.opt.before_context = before;
+        self.opt.after_context = after;
+        self
+    }
+
+    /// Sets whether lines that match no pattern are still timed and output.
+    pub fn passthrough(mut self, passthrough: bool) -> Self {
+        self.opt.passthrough = passthrough;
+        self
+    }
+
+    /// Selects the summary printed at the end of the run, unless a summarizer is given.
+    pub fn summary(mut self, summary: SummaryKind) -> Self {
+        self.opt.summary = summary;
+        self
+    }
+
+    /// Lists the `gaps` largest deltas after the detailed summary, with the lines before and after
+    /// each of them.
+    pub fn gaps(mut self, gaps: Option<usize>) -> Self {
+        self.opt.gaps = gaps;
+        self
+    }
+
+    /// Writes `deltas.svg` and `times.svg` at the end of the run.
+    pub fn plot(mut self, plot: bool) -> Self {
+        self.opt.plot = plot;
+        self
+    }
+
+    /// Prints terminal plots after the summary.
+    pub fn plot_term(mut self, plot_term: bool) -> Self {
+        self.opt.plot_term = plot_term;
+        self
+    }
+
+    /// Writes the plotted points to the data file `path`.
+    pub fn plot_data(mut self, path: Option<String>) -> Self {
+        self.opt.plot_data = path;
+        self
+    }
+
+    /// Writes an HTML report to `path`.
+    pub fn report(mut self, path: Option<String>) -> Self {
+        self.opt.report = path;
+        self
+    }
+
+    /// Exports the counters as Prometheus metrics to the textfile `path`, rewritten every
+    /// `prom_interval` during the run and once more at its end.
+    pub fn prom_textfile(mut self, path: Option<String>) -> Self {
+        self.opt.prom_textfile = path;
+        self
+    }
+
+    /// Sets the interval between two writes of the Prometheus textfile.
+    pub fn prom_interval(mut self, interval: Option<Duration>) -> Self {
+        self.opt.prom_interval = interval;
+        self
+    }
+
+    /// Serves the counters as Prometheus metrics at `/metrics` on `addr`, such as `127.0.0.1:9100`.
+    pub fn prom_listen(mut self, addr: Option<String>) -> Self {
+        self.opt.prom_listen = addr;
+        self
+    }
+
+    /// Serves a live stats page at `/`, and its stats as JSON at `/stats`, on `addr`, until the
+    /// end of the run.
+    pub fn serve(mut self, addr: Option<String>) -> Self {
+        self.opt.serve = addr;
+        self
+    }
+
+    /// Shows a live status line on stderr during the run, like `pv`, if stderr is a terminal.
+    pub fn status(mut self, status: bool) -> Self {
+        self.opt.status = status;
+        self
+    }
+
+    /// Shows the progress in the title of the terminal during the run, if stderr is a terminal.
+    pub fn title(mut self, title: bool) -> Self {
+        self.opt.title = title;
+        self
+    }
+
+    /// Reads single-key commands from the terminal during the run, once `handle_keys` is called.
+    pub fn keys(mut self, keys: bool) -> Self {
+        self.opt.keys = keys;
+        self
+    }
+
+    /// Shows the progress towards `total` matches of the regex patterns, with an estimate of the
+    /// time left, in the status line and the dashboard, and how far off it was in the summary.
+    pub fn total_matches(mut self, total: Option<u64>) -> Self {
+        self.opt.total_matches = total;
+        self
+    }
+
+    /// Draws a live panel at the bottom of the terminal, below the lines, if the output is the
+    /// terminal and it is large enough.
+    pub fn dashboard(mut self, dashboard: bool) -> Self {
+        self.opt.dashboard = dashboard;
+        self
+    }
+
+    /// Redraws the summary, the histogram of the deltas, the slowest lines and a sparkline of the
+    /// latest deltas on the whole terminal every second instead of printing the lines, if the
+    /// output is the terminal.
+    pub fn watch_summary(mut self, watch_summary: bool) -> Self {
+        self.opt.watch_summary = watch_summary;
+        self
+    }
+
+    /// Shows the exponentially weighted moving average of the deltas in the annotations and the
+    /// summary, each new delta weighing `alpha` (0.1 if not given).
+    pub fn ewma(mut self, ewma: bool, alpha: Option<f64>) -> Self {
+        self.opt.ewma = ewma;
+        self.opt.ewma_alpha = alpha;
+        self
+    }
+
+    /// Shows whether each delta is slower, about the same (within `tolerance` of it, 0.1 if not
+    /// given) or faster than the moving average of the deltas before it, with an ASCII glyph if
+    /// `ascii`. Needs `ewma`.
+    pub fn trend(mut self, trend: bool, tolerance: Option<f64>, ascii: bool) -> Self {
+        self.opt.trend = trend;
+        self.opt.trend_tolerance = tolerance;
+        self.opt.ascii = ascii;
+        self
+    }
+
+    /// Measures the rate of lines over a sliding `window` (10 s if not given) rather than the whole
+    /// run, for the status line and the events, and shows it in the annotations if `annotate`.
+    pub fn rate_window(mut self, window: Option<Duration>, annotate: bool) -> Self {
+        self.opt.rate_window = window;
+        self.opt.annotate_rate = annotate;
+        self
+    }
+
+    /// Colors the delta of each line from blue for the fastest to red for the slowest, against the
+    /// deltas so far, in 24-bit color if `COLORTERM` declares it or else in three levels.
+    pub fn heat(mut self, heat: bool) -> Self {
+        self.opt.heat = heat;
+        self
+    }
+
+    /// Shows the mean of the deltas so far in the annotations, the same mean as in the summary of
+    /// `SummaryKind::Stats`.
+    pub fn show_avg(mut self, show_avg: bool) -> Self {
+        self.opt.show_avg = show_avg;
+        self
+    }
+
+    /// Leaves the delta of the first timed line, the time from the start to that line, out of the
+    /// statistics of the deltas and the mean of `show_avg`.
+    pub fn skip_first_delta(mut self, skip: bool) -> Self {
+        self.opt.skip_first_delta = skip;
+        self
+    }
+
+    /// Shows the delta of each line and its moving average as the rates they stand for, `1/delta`
+    /// in Hz, in the annotations, and summarizes the mean, lowest and highest rates.
+    pub fn as_rate(mut self, as_rate: bool) -> Self {
+        self.opt.as_rate = as_rate;
+        self
+    }
+
+    /// Flags the lines whose delta is more than `sigma` standard deviations above the mean delta,
+    /// once `warmup` lines (10 if not given) have been timed, and counts them in the summary.
+    pub fn flag_outliers(mut self, sigma: Option<f64>, warmup: Option<u64>) -> Self {
+        self.opt.flag_outliers = sigma;
+        self.opt.outlier_warmup = warmup;
+        self
+    }
+
+    /// Detects the bursts of more lines than `threshold` within its window, printing a notice when
+    /// one starts and summarizing them. A burst ends once at most half as many lines are left in
+    /// the window.
+    pub fn detect_bursts(mut self, threshold: Option<BurstThreshold>) -> Self {
+        self.opt.detect_bursts = threshold;
+        self
+    }
+
+    /// Summarizes the lines and matches per bucket of `width` (a minute if not given) of the run as
+    /// rows of bars, doubling the width of the buckets as the run grows past 60 of them.
+    pub fn activity(mut self, activity: bool, width: Option<Duration>) -> Self {
+        self.opt.activity = activity;
+        self.opt.activity_bucket = width;
+        self
+    }
+
+    /// Summarizes the fraction of the deltas at most each threshold of `points`, or of a few
+    /// powers of ten spanning the deltas if `points` is empty.
+    pub fn cdf(mut self, cdf: bool, points: Vec<Duration>) -> Self {
+        self.opt.cdf = cdf;
+        self.opt.cdf_points = points;
+        self
+    }
+
+    /// Emits the timings and counters as StatsD metrics to `addr` (`host:port`) over UDP.
+    pub fn statsd(mut self, addr: Option<String>) -> Self {
+        self.opt.statsd = addr;
+        self
+    }
+
+    /// Sets the prefix of the StatsD metric names.
+    pub fn statsd_prefix(mut self, prefix: Option<String>) -> Self {
+        self.opt.statsd_prefix = prefix;
+        self
+    }
+
+    /// Sets the interval between two emissions of the StatsD gauges.
+    pub fn statsd_interval(mut self, interval: Option<Duration>) -> Self {
+        self.opt.statsd_interval = interval;
+        self
+    }
+
+    /// Writes the events of the run as newline-delimited JSON to the open file descriptor `fd`.
+    pub fn event_fd(mut self, fd: Option<i32>) -> Self {
+        self.opt.event_fd = fd;
+        self
+    }
+
+    /// Sets the number of events queued for a slow reader of the event file descriptor.
+    pub fn event_buffer(mut self, events: Option<usize>) -> Self {
+        self.opt.event_buffer = events;
+        self
+    }
+
+    /// Sets whether the oldest queued events are dropped when the reader of the event file
+    /// descriptor falls behind, rather than waiting for it.
+    pub fn event_drop_oldest(mut self, drop_oldest: bool) -> Self {
+        self.opt.event_drop_oldest = drop_oldest;
+        self
+    }
+
+    /// Stores the run and its snapshots in the SQLite database `path`, which needs the `sqlite`
+    /// feature.
+    pub fn sqlite(mut self, path: Option<String>) -> Self {
+        self.opt.sqlite = path;
+        self
+    }
+
+    /// Shows a desktop notification when the run finishes, which needs the `desktop` feature.
+    pub fn notify(mut self, notify: bool) -> Self {
+        self.opt.notify = notify;
+        self
+    }
+
+    /// Also shows a desktop notification for the first line matching `pattern`.
+    pub fn notify_on_match(mut self, pattern: Option<String>) -> Self {
+        self.opt.notify_on_match = pattern;
+        self
+    }
+
+    /// POSTs a JSON notification to the webhook `url` when the run ends, which needs the `notify`
+    /// feature.
+    pub fn notify_url(mut self, url: Option<String>) -> Self {
+        self.opt.notify_url = url;
+        self
+    }
+
+    /// Sets the label of the run in its notifications.
+    pub fn notify_label(mut self, label: Option<String>) -> Self {
+        self.opt.notify_label = label;
+        self
+    }
+
+    /// Also notifies of the lines slower than `threshold`, at most once per `interval`, or once a
+    /// minute if it is `None`.
+    pub fn notify_slower_than(
+        mut self,
+        threshold: Option<Duration>,
+        interval: Option<Duration>,
+    ) -> Self {
+        self.opt.notify_slower_than = threshold;
+        self.opt.notify_interval = interval;
+        self
+    }
+
+    /// Runs `command` in the shell for the lines slower than `threshold`, at most once per
+    /// `interval`, or once a minute if it is `None`.
+    pub fn on_slow(
+        mut self,
+        threshold: Option<Duration>,
+        command: Option<String>,
+        interval: Option<Duration>,
+    ) -> Self {
+        self.opt.on_slow = threshold;
+        self.opt.on_slow_cmd = command;
+        self.opt.on_slow_interval = interval;
+        self
+    }
+
+    /// Runs `command` in the shell for each match of the regex patterns, at most `limit` times if
+    /// given.
+    pub fn on_match(mut self, command: Option<String>, limit: Option<usize>) -> Self {
+        self.opt.on_match_cmd = command;
+        self.opt.on_match_limit = limit;
+        self
+    }
+
+    /// Writes the snapshots to the Parquet file `path`, which needs the `parquet` feature.
+    pub fn parquet(mut self, path: Option<String>) -> Self {
+        self.opt.parquet = path;
+        self
+    }
+
+    /// Sets the number of rows of each record batch of the Parquet file.
+    pub fn parquet_batch(mut self, rows: Option<usize>) -> Self {
+        self.opt.parquet_batch = rows;
+        self
+    }
+
+    /// Sets the size of the SVG plots.
+    pub fn plot_size(mut self, size: PlotSize) -> Self {
+        self.opt.plot_size = size;
+        self
+    }
+
+    /// Sets the title of the SVG plots.
+    pub fn plot_title(mut self, title: Option<String>) -> Self {
+        self.opt.plot_title = title;
+        self
+    }
+
+    /// Sets what the x-axis of the plots represents.
+    pub fn plot_x(mut self, x: PlotX) -> Self {
+        self.opt.plot_x = x;
+        self
+    }
+
+    /// Sets whether the delta plot uses a logarithmic y-axis.
+    pub fn plot_log_y(mut self, log_y: bool) -> Self {
+        self.opt.plot_log_y = log_y;
+        self
+    }
+
+    /// Sets the largest number of points drawn per plot; 0 draws every point.
+    pub fn plot_max_points(mut self, max_points: Option<usize>) -> Self {
+        self.opt.plot_max_points = max_points;
+        self
+    }
+
+    /// Sets the largest number of snapshots kept for the plots; 0 keeps every snapshot.
+    pub fn plot_max_samples(mut self, max_samples: Option<usize>) -> Self {
+        self.opt.plot_max_samples = max_samples;
+        self
+    }
+
+    /// Caps the memory retained for the plots and report at about `max_bytes`; beyond it fewer
+    /// snapshots are kept and the text of the slowest lines is dropped.
+    pub fn max_memory(mut self, max_bytes: Option<usize>) -> Self {
+        self.opt.max_memory = max_bytes;
+        self
+    }
+
+    /// Sets whether percentile lines are drawn on the delta plot.
+    pub fn plot_percentiles(mut self, percentiles: bool) -> Self {
+        self.opt.plot_percentiles = percentiles;
+        self
+    }
+
+    /// Sets whether the delta plot shows the rate of each delta, `1/delta` in Hz, instead of the
+    /// delta.
+    pub fn plot_rate(mut self, rate: bool) -> Self {
+        self.opt.plot_rate = rate;
+        self
+    }
+
+    /// Builds the context, compiling the regex patterns and creating the plot data and record
+    /// files. The clock starts now.
+    pub fn build(self) -> Result<TimelnContext, TimelnError> {
+        let opt = self.opt;
+        let time_format = self
+            .time_format
+            .unwrap_or_else(|| Arc::new(Box::new(SecondsFormat {})));
+        let annotator = match self.annotator {
+            Some(annotator) => annotator,
+            None => default_annotator(&opt, &time_format)?,
+        };
+        let summary = SummaryConfig::new(&opt)?;
+        // The panel and the summary screen are only drawn on the terminal the lines are printed to.
+        let display = Displays::new(&opt, self.output.is_none())?;
+        let matching = Matching::new(
+            &opt,
+            annotator,
+            summary.ewma_alpha,
+            display.rate_window.clone(),
+        )?;
+        let start_time = matching.pipeline.start_time();
+        let report_lines = if opt.report.is_some() {
+            REPORT_SLOWEST_LINES
+        } else {
+            0
+        };
+        let screen_lines = if display.screen.is_some() {
+            SCREEN_SLOWEST
+        } else {
+            0
+        };
+        let slowest = report_lines.max(screen_lines).max(summary.gaps);
+        let trackers = Trackers::new(&opt, slowest, start_time)?;
+        let (stdin, input) = open_input(&opt, self.reader, self.instant_replay, start_time)?;
+        let exporters = Exporters::open(&opt, self.keep_line_text)?;
+        let hooks = Hooks::new(&opt)?;
+        let counters = Counters::new(opt.regex.len());
+        let out = self.output.unwrap_or_else(|| Box::new(io::stdout()));
+        let out = Arc::new(Mutex::new(out));
+
+        let finalizer = Arc::new(Finalizer {
+            once: Once::new(),
+            summarizer: self
+                .summarizer
+                .unwrap_or_else(|| opt.summary.summarizer(opt.color)),
+            time_format,
+            color: opt.color,
+            start_time,
+            counters: counters.clone(),
+            trackers: trackers.clone(),
+            input,
+            summary,
+            exporters: exporters.clone(),
+            hooks,
+            display,
+            plot_dir: self.plot_dir.unwrap_or_default(),
+            plot_config: PlotConfig {
+                size: opt.plot_size,
+                title: opt.plot_title.clone(),
+                x: opt.plot_x,
+                log_y: opt.plot_log_y,
+                mark_matches: !opt.regex.is_empty() && opt.passthrough,
+                pattern_labels: matching.labels,
+                max_points: Some(opt.plot_max_points.unwrap_or(DEFAULT_PLOT_MAX_POINTS))
+                    .filter(|max| *max > 0),
+                percentiles: opt.plot_percentiles,
+                phase_boundaries: Vec::new(),
+                rate: opt.plot_rate,
+            },
+            plot_phases: opt.plot_phases,
+            outputs: FinalOutput::requested(&opt),
+            end: Mutex::new(RunEnd::default()),
+        });
+        let metrics_addr = finalizer.start_exporters(&opt)?;
+        finalizer.start_displays(&out);
+
+        Ok(TimelnContext {
+            stdin,
+            read_timeout: opt.exit_idle,
+            out,
+            output_closed: false,
+            // The summary screen takes the place of the lines.
+            quiet: self.quiet || finalizer.display.screen.is_some(),
+            pipeline: matching.pipeline,
+            counters,
+            trackers,
+            exporters,
+            reset_on: matching.reset_on,
+            collapse_faster_than: opt.collapse_faster_than,
+            context: matching.context,
+            skip_delta: opt.skip_first_delta,
+            reset_per_file: opt.reset_per_file,
+            finalizer,
+            metrics_addr,
+        })
+    }
+}
+
+/// The annotator of `opt`: the timestamps of `--ts`, or else the times in `time_format`.
+fn default_annotator(
+    opt: &TimelnOpt,
+    time_format: &Arc<Box<dyn TimeFormat>>,
+) -> Result<Box<dyn TimelnAnnotation>, TimelnError> {
+    let Some(format) = &opt.ts else {
+        return Ok(Box::new(SimpleAnnotator {
+            color: opt.color,
+            time_format: Arc::clone(time_format),
+            widths: opt.align.then(ColumnWidths::default),
+        }));
+    };
+    let mode = if opt.ts_incremental {
+        TsMode::Incremental
+    } else if opt.ts_since_start {
+        TsMode::SinceStart
+    } else {
+        TsMode::Absolute
+    };
+    Ok(Box::new(TsAnnotator::new(
+        mode,
+        format.as_deref(),
+        SystemTime::now(),
+        opt.color,
+    )?))
+}
+
+/// The main context struct for running the timeln module.
+/// It holds the state of the input and the options for processing the input.
+pub struct TimelnContext {
+    stdin: Box<dyn ReadData>,
+    /// Longest wait for a line, after which the input is taken to have ended, with `exit_idle`.
+    read_timeout: Option<Duration>,
+    out: Arc<Mutex<Box<dyn Write + Send>>>,
+    output_closed: bool,
+    quiet: bool,
+    pipeline: Pipeline,
+    counters: Counters,
+    trackers: Trackers,
+    exporters: Exporters,
+    /// Marker starting a new lap.
+    reset_on: Option<Regex>,
+    collapse_faster_than: Option<Duration>,
+    /// Lines printed around the matches, if any.
+    context: Option<ContextLines>,
+    /// Whether the delta of the next timed line is left out of the statistics of the deltas: that
+    /// of the first line, since the start, until it is timed.
+    skip_delta: bool,
+    /// Whether the elapsed times and deltas are measured from the first line of each file.
+    reset_per_file: bool,
+    finalizer: Arc<Finalizer>,
+    /// Address the Prometheus metrics are served on.
+    metrics_addr: Option<SocketAddr>,
+}
+
+impl TimelnContext {
+    /// Creates a new instance of TimelnContext reading stdin, or the command, file or record file
+    /// in the options, from a given set of options.
+    pub fn new(opt: TimelnOpt) -> Result<Self, TimelnError> {
+        TimelnContextBuilder::from(opt).build()
+    }
+
+    /// Returns a builder for a context with custom input, output, annotation or summary.
+    pub fn builder() -> TimelnContextBuilder {
+        TimelnContextBuilder::new()
+    }
+
+    /// Installs a handler for Ctrl-C and, on Unix, SIGTERM and SIGHUP, or on Windows, Ctrl-Break
+    /// and closing the console, that prints the summary and writes the plots of the run so far,
+    /// then exits the process with `status`. On Unix, SIGUSR1 and SIGUSR2 also pause and resume
+    /// timing. The handlers can only be installed once per process.
+    pub fn handle_signals(&self, status: i32) -> Result<(), TimelnError> {
+        let finalizer = Arc::clone(&self.finalizer);
+        let out = Arc::clone(&self.out);
+        signal::install(move |budget| exit_after_finalizing(&finalizer, &out, budget, status))?;
+        #[cfg(unix)]
+        {
+            let pause = Arc::clone(&self.counters.pause);
+            signal::install_pause(move |paused| {
+                let now = Instant::now();
+                if paused {
+                    pause.pause(now);
+                } else {
+                    pause.resume(now);
+                }
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Reads single-key commands from the terminal on a background thread, if `keys` was set: `s`
+    /// prints the summary so far, `p` pauses and resumes the display of the lines, `q` finishes
+    /// the run and exits the process with `status`, and `c` turns the colors off and on. Does
+    /// nothing if stdin is a terminal, as its keys are then the input, or if there is no
+    /// controlling terminal. The terminal is restored when the run is finalized.
+    pub fn handle_keys(&self, status: i32) -> Result<(), TimelnError> {
+        if !self.finalizer.display.keys || io::stdin().is_terminal() {
+            return Ok(());
+        }
+        let Some(terminal) = keys::open_terminal()? else {
+            return Ok(());
+        };
+        let commands = keys::spawn(terminal);
+        let finalizer = Arc::clone(&self.finalizer);
+        let out = Arc::clone(&self.out);
+        thread::spawn(move || {
+            for command in commands {
+                if let Err(err) = apply_key(&finalizer, &out, command, status) {
+                    eprintln!("timeln: {}", err);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Runs the main loop of reading the input, writing the annotated lines to the output and handing each snapshot to the sink.
+    ///
+    /// Stops early, without an error, if the output is a pipe whose reader has gone away.
+    pub fn run(&mut self) -> Result<(), TimelnError> {
+        let mut buffer = String::new();
+
+        loop {
+            buffer.clear();
+            let bytes_read = match self.read_timeout {
+                Some(timeout) => match self.stdin.read_line_timeout(&mut buffer, timeout)? {
+                    ReadOutcome::Line(bytes) => bytes,
+                    ReadOutcome::TimedOut => {
+                        *self.finalizer.end.lock()? = RunEnd::IdleTimeout;
+                        break;
+                    }
+                    ReadOutcome::Eof => break,
+                },
+                None => self.stdin.read_line(&mut buffer)?,
+            };
+            if bytes_read == 0 {
+                // EOF
+                break;
+            }
+            // Timestamp the line before any of our own work, so that its delta is the difference
+            // between arrival times rather than including the processing of the previous line.
+            let started = Instant::now();
+            self.counters
+                .total_bytes
+                .fetch_add(bytes_read as u64, Ordering::Relaxed);
+            let arrived = self.stdin.arrived().unwrap_or(started);
+            let stream = self.stdin.stream();
+            write_tee(&mut *self.exporters.tee.lock()?, |tee| {
+                tee.write_all(buffer.as_bytes())
+            });
+            let result = self.process_line(&buffer, arrived, stream);
+            self.counters
+                .overhead
+                .fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+            match result {
+                Err(TimelnError::Io(err)) if err.kind() == io::ErrorKind::BrokenPipe => {
+                    self.output_closed = true;
+                    *self.finalizer.end.lock()? = RunEnd::OutputClosed;
+                    return Ok(());
+                }
+                result => result?,
+            }
+        }
+
+        ignore_broken_pipe(self.out.lock()?.flush())?;
+        Ok(())
+    }
+
+    /// Writes a line to the output, after the placeholder of any lines collapsed before it.
+    /// The line is held back instead while the display is paused.
+    fn emit(&self, line: &str) -> Result<(), TimelnError> {
+        let mut out = self.out.lock()?;
+        let mut held = self.finalizer.display.held.lock()?;
+        let out: &mut dyn Write = match &mut *held {
+            Some(held) => held,
+            None => &mut **out,
+        };
+        if let Some(run) = self.trackers.collapsed.lock()?.take() {
+            self.finalizer.write_collapsed(out, &run)?;
+        }
+        writeln!(out, "{}", line)?;
+        Ok(())
+    }
+
+    /// Times, records and outputs a line that was read at `arrived`, from `stream` if the input
+    /// merges the streams of a child process. A notice from the reader is output first.
+    fn process_line(
+        &mut self,
+        line: &str,
+        arrived: Instant,
+        stream: Option<Stream>,
+    ) -> Result<(), TimelnError> {
+        let paused = self.counters.pause.is_paused_at(arrived);
+        let timed_at = self.counters.pause.unpaused(arrived);
+        if let Some(notice) = self.stdin.take_notice() {
+            let notice = self.pipeline.notice(&notice, timed_at);
+            if !self.quiet {
+                self.emit(&notice)?;
+            }
+        }
+        let elapsed = arrived.saturating_duration_since(self.finalizer.start_time);
+        let rate = self.finalizer.display.rate_window.as_ref().map(|window| {
+            let lines = window.push(arrived);
+            (
+                window.window(),
+                lines as f64 / window.window().as_secs_f64(),
+            )
+        });
+        self.counters
+            .last_arrival
+            .fetch_max(elapsed.as_nanos() as u64, Ordering::Relaxed);
+        let mut recorder = self.exporters.recorder.lock()?;
+        if recorder.is_some() || self.exporters.events.is_some() {
+            let record = Record::Line {
+                elapsed,
+                stream,
+                text: line.trim_end_matches(['\n', '\r']).to_string(),
+            };
+            if let Some(recorder) = &mut *recorder {
+                recorder
+                    .write(&record)
+                    .map_err(|err| TimelnError::output(recorder.path(), err))?;
+            }
+            if let Some(events) = &self.exporters.events {
+                events.send(match rate {
+                    Some((window, rate)) => record_event_with_rate(&record, &window, rate),
+                    None => record_event(&record),
+                });
+            }
+        }
+        drop(recorder);
+        let lines = self.stdin.lines();
+        let line_number = self
+            .counters
+            .total_lines
+            .fetch_add(lines, Ordering::Relaxed)
+            + 1;
+        if let Some(stream) = stream {
+            self.counters.stream_lines[stream as usize].fetch_add(lines, Ordering::Relaxed);
+        }
+        if let Some(desktop) = &self.finalizer.hooks.desktop {
+            let text = line.trim_end_matches(['\n', '\r']);
+            if desktop.first_match(text) {
+                let message = self.finalizer.summarizer.summarize_first_match(
+                    desktop.pattern().unwrap_or_default(),
+                    text,
+                    &elapsed,
+                    &**self.finalizer.time_format,
+                );
+                desktop.matched(message);
+            }
+        }
+
+        if let (Some(index), Some(files)) = (self.stdin.file(), &mut *self.trackers.files.lock()?) {
+            let first = files.current.is_none();
+            let since_start = timed_at.saturating_duration_since(self.finalizer.start_time);
+            if let Some(name) = files.line_from(index, since_start) {
+                if self.reset_per_file && !first {
+                    self.pipeline.reset(timed_at);
+                }
+                if !self.quiet {
+                    if !first {
+                        self.emit("")?;
+                    }
+                    self.emit(&format!("==> {} <==", name))?;
+                }
+            }
+        }
+
+        let mut laps = self.trackers.laps.lock()?;
+        if self.reset_on.as_ref().is_some_and(|re| re.is_match(line)) {
+            let ended = laps.start(timed_at.saturating_duration_since(self.finalizer.start_time));
+            self.pipeline.reset_elapsed(timed_at);
+            if let (Some(events), Some(lap)) = (&self.exporters.events, &ended) {
+                events.send(lap_event(lap));
+            }
+            if let Some(lap) = ended.filter(|_| self.finalizer.summary.lap_summary && !self.quiet) {
+                let summary = self
+                    .finalizer
+                    .summarizer
+                    .summarize_lap(&lap, &**self.finalizer.time_format);
+                self.emit(&self.finalizer.paint(summary))?;
+            }
+        }
+        drop(laps);
+
+        let matched = self.time_line(line, timed_at, stream, paused, line_number)?;
+        self.trackers.laps.lock()?.line(matched);
+        if let Some(files) = &mut *self.trackers.files.lock()? {
+            files.laps.line(matched);
+        }
+
+        let since_start = timed_at.saturating_duration_since(self.finalizer.start_time);
+        if let Some(activity) = &mut *self.trackers.activity.lock()? {
+            activity.push(since_start, matched);
+        }
+        let burst = match &mut *self.trackers.bursts.lock()? {
+            Some(bursts) => bursts
+                .push(arrived)
+                .map(|burst| (burst, bursts.threshold())),
+            None => None,
+        };
+        if let Some((burst, threshold)) = burst.filter(|_| !self.quiet) {
+            let notice = format!(
+                "[burst of {} lines within {}, started at {}]",
+                burst.lines,
+                window_label(&threshold.window),
+                self.finalizer.time_format.format_duration(&burst.start)
+            );
+            self.emit(&self.finalizer.paint(notice))?;
+        }
+        let pairs = self.stdin.pairs();
+        for extractor in self.trackers.extractors.lock()?.iter_mut() {
+            extractor.line(line, since_start);
+            extractor.pairs(pairs, since_start);
+        }
+        if let Some(groups) = &mut *self.trackers.groups.lock()? {
+            groups.line(line, since_start);
+            groups.pairs(pairs, since_start);
+        }
+        let span = match &mut *self.trackers.spans.lock()? {
+            Some(spans) => spans.line(line, since_start),
+            None => None,
+        };
+        if let Some(span) = span.filter(|_| !self.quiet) {
+            let span = format!(
+                "[span: {}] {}",
+                self.finalizer.time_format.format_duration(&span.duration),
+                span.start_text
+            );
+            self.emit(&self.finalizer.paint(span))?;
+        }
+        Ok(())
+    }
+
+    /// Times and outputs a line that arrived at `timed_at`, excluding paused time, unless timing
+    /// was paused. Returns whether the line matched a regex pattern.
+    fn time_line(
+        &mut self,
+        line: &str,
+        timed_at: Instant,
+        stream: Option<Stream>,
+        paused: bool,
+        line_number: usize,
+    ) -> Result<bool, TimelnError> {
+        if paused {
+            if !self.quiet {
+                let text = self.pipeline.line_text(line);
+                self.emit(&format!("{} {}", PAUSED_TAG, text))?;
+            }
+            return Ok(false);
+        }
+
+        let Some(mut timed) = self.pipeline.time_line_from(line, timed_at, stream) else {
+            self.context_line(line, timed_at)?;
+            return Ok(false);
+        };
+        // The pipeline does not see the paused lines, nor each of the lines joined into one.
+        timed.snapshot.line_no = line_number;
+        let snapshot = timed.snapshot;
+        let comparison = match &mut *self.trackers.reported.lock()? {
+            Some(reported) => reported.compare(line, snapshot.delta),
+            None => None,
+        };
+        if let Some(comparison) = comparison {
+            let time_format = &**self.finalizer.time_format;
+            timed.output = format!(
+                "{} [reported: {}, difference: {}]",
+                timed.output,
+                time_format.format_duration(&comparison.reported),
+                format_difference(comparison.difference, time_format)
+            );
+        }
+
+        self.counters
+            .last_delta
+            .store(snapshot.delta.as_nanos() as u64, Ordering::Release);
+        if timed.ewma.is_some() {
+            *self.finalizer.summary.ewma.lock()? = timed.ewma;
+        }
+        let mut stats = self.counters.stats.lock()?;
+        let delta = snapshot.delta.as_secs_f64();
+        if let Some(outliers) = &self.finalizer.summary.outliers {
+            if is_outlier(&stats, delta, outliers.sigma, outliers.warmup) {
+                outliers.flagged.fetch_add(1, Ordering::Relaxed);
+                let marker = if self.finalizer.color {
+                    OUTLIER_MARKER.red().to_string()
+                } else {
+                    OUTLIER_MARKER.to_string()
+                };
+                timed.output = format!("{} {}", marker, timed.output);
+            }
+        }
+        // Only the first timed line can have its delta skipped.
+        if !std::mem::take(&mut self.skip_delta) {
+            stats.push(delta);
+        }
+        drop(stats);
+        if let Some(recent) = &self.finalizer.display.recent_deltas {
+            recent.push(snapshot.delta);
+        }
+        if let Some(statsd) = &mut *self.exporters.statsd.lock()? {
+            statsd.timing(snapshot.delta);
+        }
+        let pattern = snapshot
+            .pattern
+            .map(|pattern| self.finalizer.plot_config.pattern_labels[pattern].as_str());
+        if let Some(store) = &mut *self.exporters.sqlite.lock()? {
+            store.push(&snapshot, pattern).map_err(|err| {
+                TimelnError::output(
+                    self.exporters.sqlite_path.as_deref().unwrap_or_default(),
+                    err,
+                )
+            })?;
+        }
+        if let Some(writer) = &mut *self.exporters.parquet.lock()? {
+            writer.push(&snapshot, pattern).map_err(|err| {
+                TimelnError::output(
+                    self.exporters.parquet_path.as_deref().unwrap_or_default(),
+                    err,
+                )
+            })?;
+        }
+        let limits_memory = {
+            let mut sink = self.exporters.sink.lock()?;
+            sink.push_line(snapshot, self.pipeline.line_text(line))
+                .map_err(|err| {
+                    // Only a sink streaming to the plot data file can fail.
+                    TimelnError::plot_data(
+                        self.exporters.plot_data.as_deref().unwrap_or_default(),
+                        err,
+                    )
+                })?;
+            sink.limits_memory()
+        };
+
+        if let Some(pattern) = snapshot.pattern {
+            self.counters.total_matches.fetch_add(1, Ordering::Relaxed);
+            if let Some(progress) = &self.finalizer.summary.progress {
+                progress.lock()?.record(snapshot.elapsed);
+            }
+            if let Some(hook) = &self.finalizer.hooks.matched {
+                if let Some(captures) = self.pipeline.captures(line, pattern) {
+                    let text = self.pipeline.line_text(line);
+                    hook.matched(&captures, snapshot.delta, snapshot.elapsed, text);
+                }
+            }
+            self.counters.pattern_matches[pattern].fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some(notifier) = &self.finalizer.hooks.notifier {
+            if notifier.is_slow(snapshot.delta) {
+                let now = Instant::now();
+                let totals = RunTotals {
+                    lines: self.counters.total_lines.load(Ordering::Acquire) as u64,
+                    matches: self.counters.total_matches.load(Ordering::Acquire) as u64,
+                    bytes: self.counters.total_bytes.load(Ordering::Acquire),
+                    total_time: self.finalizer.total_time(now),
+                };
+                let text = self.pipeline.line_text(line);
+                notifier.threshold(&totals, snapshot.delta, text, now);
+            }
+        }
+        if let Some(hook) = &self.finalizer.hooks.slow {
+            let text = self.pipeline.line_text(line);
+            hook.line(snapshot.delta, snapshot.elapsed, text, Instant::now());
+        }
+
+        // None are kept unless the report, the gaps or the summary screen list them.
+        let since_start = timed_at.saturating_duration_since(self.finalizer.start_time);
+        let text_bytes = {
+            let mut slowest = self.trackers.slowest.lock()?;
+            slowest.record(
+                line_number,
+                snapshot.delta,
+                since_start,
+                self.pipeline.line_text(line),
+            );
+            limits_memory.then(|| slowest.text_bytes())
+        };
+        // The slowest lines are locked again only in the one line that exceeds the cap.
+        if let Some(text_bytes) = text_bytes {
+            if self.exporters.sink.lock()?.check_memory(text_bytes) {
+                self.trackers.slowest.lock()?.drop_text();
+            }
+        }
+
+        if let Some(context) = self.context.as_mut().filter(|_| snapshot.pattern.is_some()) {
+            let before = context.matched();
+            if !self.quiet {
+                for line in before {
+                    self.emit(&line)?;
+                }
+            }
+            timed.output = format!("{} {}", MATCH_PREFIX, timed.output);
+        }
+        match self.collapse_faster_than {
+            _ if self.quiet => {}
+            Some(threshold) if snapshot.delta < threshold => {
+                let mut collapsed = self.trackers.collapsed.lock()?;
+                let run = collapsed.get_or_insert_with(CollapsedRun::default);
+                run.lines += 1;
+                run.duration += snapshot.delta;
+            }
+            _ => self.emit(&timed.output)?,
+        }
+
+        let since_start = timed_at.saturating_duration_since(self.finalizer.start_time);
+        let phase = match &mut *self.trackers.phases.lock()? {
+            Some(phases) => phases.push(snapshot.line_no, since_start, snapshot.delta),
+            None => None,
+        };
+        if let Some(phase) = phase.filter(|_| !self.quiet) {
+            let time_format = &**self.finalizer.time_format;
+            let notice = format!(
+                "[phase {} from line {}, started at {}, mean delta {}]",
+                phase.index,
+                phase.first_line,
+                time_format.format_duration(&phase.start),
+                time_format.format_duration(&phase.mean_delta)
+            );
+            self.emit(&self.finalizer.paint(notice))?;
+        }
+        Ok(snapshot.pattern.is_some())
+    }
+
+    /// Outputs `line`, which arrived at `timed_at` and matched no pattern, if it follows a match
+    /// closely enough to be in its context, or else keeps it in case a match follows.
+    fn context_line(&mut self, line: &str, timed_at: Instant) -> Result<(), TimelnError> {
+        let Some(context) = &mut self.context else {
+            return Ok(());
+        };
+        let pipeline = &self.pipeline;
+        let color = self.finalizer.color;
+        let printed = context.other(|| {
+            let output = format!(
+                "{} {}",
+                CONTEXT_PREFIX,
+                pipeline.context_line(line, timed_at)
+            );
+            if color {
+                output.dimmed().to_string()
+            } else {
+                output
+            }
+        });
+        match printed {
+            Some(output) if !self.quiet => self.emit(&output),
+            _ => Ok(()),
+        }
+    }
+
+    /// Number of lines that matched a regex pattern so far.
+    pub fn total_matches(&self) -> usize {
+        self.counters.total_matches.load(Ordering::Acquire)
+    }
+
+    /// The totals of the run so far, as summarized at the end of the run.
+    pub fn totals(&self) -> RunTotals {
+        RunTotals {
+            lines: self.counters.total_lines.load(Ordering::Acquire) as u64,
+            matches: self.counters.total_matches.load(Ordering::Acquire) as u64,
+            bytes: self.counters.total_bytes.load(Ordering::Acquire),
+            total_time: self.finalizer.total_time(Instant::now()),
+        }
+    }
+
+    /// The format of the times of the run.
+    pub fn time_format(&self) -> &dyn TimeFormat {
+        &**self.finalizer.time_format
+    }
+
+    /// Address the Prometheus metrics are served on, if `prom_listen` was set.
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.metrics_addr
+    }
+
+    /// Address the live stats page is served on, if `serve` was set.
+    pub fn live_addr(&self) -> Option<SocketAddr> {
+        self.finalizer.display.live.as_ref().map(LiveServer::addr)
+    }
+
+    /// Whether `run` ended because no line arrived within the `exit_idle` timeout.
+    pub fn idle_timed_out(&self) -> bool {
+        self.finalizer
+            .end
+            .lock()
+            .is_ok_and(|end| *end == RunEnd::IdleTimeout)
+    }
+
+    /// Prints a summary of the results and optionally plots the data.
+    ///
+    /// Does nothing if the summary was already printed by the signal handler. If `run` stopped
+    /// because the output was closed, the summary is printed to stderr instead.
+    pub fn summarize_and_plot(&self) -> Result<(), TimelnError> {
+        if self.output_closed {
+            return self.finalizer.finalize(&mut io::stderr());
+        }
+        let mut out = self.out.lock()?;
+        self.finalizer.finalize(&mut *out)?;
+        ignore_broken_pipe(out.flush())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotator::UnicodeAnnotator;
+    use crate::formatter::MillisecondsFormat;
+    use crate::progress::Progress;
+    use crate::{argopt::TimelnOpt, reader::IterReadData, reader::TestReadData};
+    use colored::Colorize;
+    use std::path::Path;
+
+    /// An output that can still be read after it is handed to the context.
+    #[derive(Clone, Default)]
+    struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl SharedOutput {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Formats every duration the same, so that annotated lines can be compared exactly.
+    struct FixedFormat;
+
+    impl TimeFormat for FixedFormat {
+        fn format_duration(&self, _duration: &Duration) -> String {
+            "t".to_string()
+        }
+    }
+
+    struct FixedSummarizer;
+
+    impl Summarizer for FixedSummarizer {
+        fn summarize(
+            &self,
+            total_lines: usize,
+            _total_matches: usize,
+            _total_bytes: u64,
+            _total_time: &Duration,
+            _overhead: &Duration,
+            _stats: &RunningStats,
+            _time_format: &dyn TimeFormat,
+        ) -> String {
+            format!("{} lines", total_lines)
+        }
+    }
+
+    /// A builder reading `lines` and writing to a returned `SharedOutput`.
+    fn builder(lines: &[&str]) -> (TimelnContextBuilder, SharedOutput) {
+        let out = SharedOutput::default();
+        let builder = TimelnContext::builder()
+            .reader(Box::new(IterReadData::new(
+                lines
+                    .iter()
+                    .map(|line| line.to_string())
+                    .collect::<Vec<_>>(),
+            )))
+            .output(Box::new(out.clone()));
+        (builder, out)
+    }
+
+    #[test]
+    fn test_timeln_context_new() {
+        let opt = TimelnOpt::default();
+        let context = TimelnContext::new(opt);
+        assert!(context.is_ok());
+    }
+
+    #[test]
+    fn test_every_invalid_pattern_is_reported() {
+        let opt = TimelnOpt {
+            regex: vec!["ok".to_string(), "(".to_string()],
+            reset_on: Some("[a-".to_string()),
+            extract: vec!["loss=(\\S+)".to_string()],
+            ..Default::default()
+        };
+        let err = match TimelnContext::new(opt) {
+            Err(err @ TimelnError::MultiplePatternErrors(_)) => err,
+            other => panic!("expected every bad pattern, got {:?}", other.err()),
+        };
+        let message = err.to_string();
+        assert!(
+            message.starts_with("2 invalid regex patterns:\n"),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains("\n  --regex #2 '(': regex parse error:"),
+            "{}",
+            message
+        );
+        assert!(message.contains("unclosed group"), "{}", message);
+        assert!(message.contains("\n  --reset-on '[a-': "), "{}", message);
+        assert!(!message.contains("--extract"), "{}", message);
+    }
+
+    #[test]
+    fn test_timeln_context_new_invalid_regex() {
+        let opt = TimelnOpt {
+            regex: vec!["(".to_string()],
+            ..Default::default()
+        };
+        assert!(matches!(
+            TimelnContext::new(opt),
+            Err(TimelnError::RegexCompile { .. })
+        ));
+    }
+
+    #[test]
+    fn test_push_snapshot() {
+        let context = TimelnContext::builder().build().unwrap();
+        let duration = Duration::from_secs(1);
+        assert!(context
+            .exporters
+            .sink
+            .lock()
+            .unwrap()
+            .push(TimeSnapshot {
+                delta: duration,
+                elapsed: duration,
+                ..TimeSnapshot::default()
+            })
+            .is_ok());
+    }
+
+    #[test]
+    fn test_retain_snapshot_when_plotting() {
+        let context = TimelnContext::builder().plot(true).build().unwrap();
+        let duration = Duration::from_secs(1);
+        let mut sink = context.exporters.sink.lock().unwrap();
+        sink.push(TimeSnapshot {
+            delta: duration,
+            elapsed: duration,
+            ..TimeSnapshot::default()
+        })
+        .unwrap();
+        assert_eq!(sink.take().0[0].1.delta, duration);
+    }
+
+    #[test]
+    fn test_run_without_consumer_retains_nothing() {
+        let mut context = TimelnContext::builder()
+            .reader(Box::new(TestReadData::cursor("line\n".repeat(10_000))))
+            .output(Box::new(io::sink()))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        assert_eq!(context.counters.total_lines.load(Ordering::Relaxed), 10_000);
+        assert_eq!(context.exporters.sink.lock().unwrap().len(), 0);
+        assert_eq!(context.counters.stats.lock().unwrap().count(), 10_000);
+    }
+
+    #[test]
+    fn test_run() {
+        let (builder, out) = builder(&["test", "  second line  "]);
+        let mut context = builder.time_format(Box::new(FixedFormat)).build().unwrap();
+        assert!(context.run().is_ok());
+        assert_eq!(
+            out.contents(),
+            "[time: t, delta: t] test\n[time: t, delta: t]   second line  \n"
+        );
+    }
+
+    #[test]
+    fn test_run_trim() {
+        let (builder, out) = builder(&["  second line  \r\n", "last\r"]);
+        let mut context = builder
+            .time_format(Box::new(FixedFormat))
+            .trim(true)
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        assert_eq!(
+            out.contents(),
+            "[time: t, delta: t] second line\n[time: t, delta: t] last\n"
+        );
+    }
+
+    #[test]
+    fn test_no_line_prints_only_annotations() {
+        let (builder, out) = builder(&["compile secret.rs\n", "link\n", "test secret.rs\n"]);
+        let mut context = builder
+            .time_format(Box::new(FixedFormat))
+            .regex("secret")
+            .label("step")
+            .no_line(true)
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        let out = out.contents();
+        assert_eq!(out, "[time: t, delta: t]\n[time: t, delta: t]\n");
+        for text in ["compile", "secret", "link", "step"] {
+            assert!(!out.contains(text), "{:?}", out);
+        }
+    }
+
+    #[test]
+    fn test_context_lines_around_matches() {
+        let (builder, out) = builder(&[
+            "1 ERROR\n",
+            "2\n",
+            "3\n",
+            "4\n",
+            "5\n",
+            "6 ERROR\n",
+            "7\n",
+            "8 ERROR\n",
+            "9\n",
+            "10\n",
+        ]);
+        let mut context = builder
+            .time_format(Box::new(FixedFormat))
+            .regex("ERROR")
+            .context(Some(2), Some(1))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        let lines: Vec<String> = out.contents().lines().map(str::to_string).collect();
+        let annotated =
+            |prefix: &str, text: &str| format!("{} [time: t, delta: t] {}", prefix, text);
+        // The match on the first line has nothing before it, and the context of 6 and 8 overlaps.
+        assert_eq!(
+            lines,
+            [
+                annotated(":", &format!("1 {}", "ERROR".red())),
+                annotated("-", "2"),
+                annotated("-", "4"),
+                annotated("-", "5"),
+                annotated(":", &format!("6 {}", "ERROR".red())),
+                annotated("-", "7"),
+                annotated(":", &format!("8 {}", "ERROR".red())),
+                annotated("-", "9"),
+            ]
+        );
+        assert_eq!(context.total_matches(), 3);
+    }
+
+    #[test]
+    fn test_run_crlf_leaves_no_carriage_returns() {
+        let (builder, out) = builder(&["build ok\r\n", "test ok  \r\n"]);
+        let mut context = builder.color(true).regex("ok").build().unwrap();
+        context.run().unwrap();
+        let out = out.contents();
+        assert!(!out.contains('\r'), "{:?}", out);
+        assert!(out.ends_with(&format!("test {}  \n", "ok".red())));
+    }
+
+    #[test]
+    fn test_match_colors_highlight_each_pattern() {
+        let (builder, out) = builder(&["ERROR: disk full", "disk ok"]);
+        let mut context = builder
+            .color(true)
+            .regex("ERROR")
+            .regex("disk")
+            .match_colors(vec![Color::Red, Color::Yellow])
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+        let red = |text: &str| text.color(Color::Red).to_string();
+        let yellow = |text: &str| text.color(Color::Yellow).to_string();
+        let out = out.contents();
+        // The line matching both patterns gets both colors, not only the first pattern's.
+        assert!(
+            out.contains(&format!("{}: {} full\n", red("ERROR"), yellow("disk"))),
+            "{:?}",
+            out
+        );
+        assert!(out.contains(&format!("{} ok\n", yellow("disk"))));
+        assert!(out.contains(&format!(
+            "[Pattern Matches: {}: 1, {}: 1]\n",
+            red("p0"),
+            yellow("p1")
+        )));
+    }
+
+    #[test]
+    fn test_run_snapshots_carry_line_details() {
+        let (keeping, _) = builder(&["a", "x é", "b", "x"]);
+        let mut context = keeping
+            .regex("^x")
+            .passthrough(true)
+            .plot_term(true)
+            .keep_line_text(true)
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        let mut sink = context.exporters.sink.lock().unwrap();
+        let text = sink.take_text();
+        let details: Vec<(usize, Option<usize>, usize, &str)> = sink
+            .take()
+            .0
+            .iter()
+            .map(|(_, snapshot)| {
+                (
+                    snapshot.line_no,
+                    snapshot.pattern,
+                    snapshot.bytes,
+                    text[&snapshot.line_no].as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            details,
+            vec![
+                (1, None, 2, "a"),
+                (2, Some(0), 5, "x é"),
+                (3, None, 2, "b"),
+                (4, Some(0), 2, "x")
+            ]
+        );
+
+        let (dropping, _) = builder(&["a"]);
+        let mut context = dropping.plot_term(true).build().unwrap();
+        context.run().unwrap();
+        assert!(context
+            .exporters
+            .sink
+            .lock()
+            .unwrap()
+            .take_text()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_run_counts_raw_bytes() {
+        // "héllo\n" is 7 bytes and "日本 \r\n" is 9, whatever is trimmed from them.
+        let (builder, out) = builder(&["héllo", "日本 \r\n"]);
+        let mut context = builder
+            .summary(SummaryKind::Detailed)
+            .trim(true)
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        assert_eq!(context.counters.total_bytes.load(Ordering::Relaxed), 16);
+        context.summarize_and_plot().unwrap();
+        assert!(out
+            .contents()
+            .contains("Processed 2 lines (16 bytes, 16 B)"));
+    }
+
+    #[test]
+    fn test_run_invalid_utf8() {
+        let out = SharedOutput::default();
+        let mut context = TimelnContext::builder()
+            .reader(Box::new(TestReadData::cursor(
+                b"before\nmid\xFF\xFEline\nafter\n".to_vec(),
+            )))
+            .output(Box::new(out.clone()))
+            .time_format(Box::new(FixedFormat))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        assert_eq!(context.counters.total_lines.load(Ordering::Relaxed), 3);
+        assert_eq!(
+            out.contents().lines().nth(1),
+            Some("[time: t, delta: t] mid\u{FFFD}\u{FFFD}line")
+        );
+    }
+
+    /// An output whose reader has gone away.
+    struct ClosedOutput;
+
+    impl Write for ClosedOutput {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+    }
+
+    #[test]
+    fn test_run_stops_on_broken_pipe() {
+        let mut context = TimelnContext::builder()
+            .reader(Box::new(IterReadData::new(["a", "b", "c"])))
+            .output(Box::new(ClosedOutput))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        assert!(context.output_closed);
+        assert_eq!(context.counters.total_lines.load(Ordering::Relaxed), 1);
+    }
+
+    /// Hands out a line every `interval`, telling the time it was due as its arrival, so that the
+    /// deltas do not depend on how late the test was scheduled.
+    struct ScheduledReadData {
+        remaining: usize,
+        interval: Duration,
+        line: String,
+        arrived: Option<Instant>,
+    }
+
+    impl ScheduledReadData {
+        fn new(remaining: usize, interval: Duration, line: &str) -> Self {
+            ScheduledReadData {
+                remaining,
+                interval,
+                line: line.to_string(),
+                arrived: None,
+            }
+        }
+    }
+
+    impl ReadData for ScheduledReadData {
+        fn read_line(&mut self, buf: &mut String) -> Result<usize, TimelnError> {
+            if self.remaining == 0 {
+                return Ok(0);
+            }
+            self.remaining -= 1;
+            let due = self
+                .arrived
+                .map_or_else(Instant::now, |last| last + self.interval);
+            std::thread::sleep(due.saturating_duration_since(Instant::now()));
+            self.arrived = Some(due);
+            buf.push_str(&self.line);
+            Ok(self.line.len())
+        }
+
+        fn arrived(&self) -> Option<Instant> {
+            self.arrived
+        }
+    }
+
+    /// An output that takes `delay` to write anything, standing for a slow terminal.
+    struct SlowOutput {
+        delay: Duration,
+    }
+
+    impl Write for SlowOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            std::thread::sleep(self.delay);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_deltas_exclude_processing_time() {
+        let interval = Duration::from_millis(50);
+        let reader = ScheduledReadData::new(5, interval, "step\n");
+        // Printing each line takes a fifth of the interval between lines.
+        let delay = interval / 5;
+        let mut context = TimelnContext::builder()
+            .reader(Box::new(reader))
+            .output(Box::new(SlowOutput { delay }))
+            .plot(true)
+            .build()
+            .unwrap();
+        context.run().unwrap();
+
+        let (snapshots, _) = context.exporters.sink.lock().unwrap().take();
+        assert_eq!(snapshots.len(), 5);
+        let overhead = Duration::from_nanos(context.counters.overhead.load(Ordering::Relaxed));
+        assert!(overhead >= delay * 5, "{:?}", overhead);
+        for (line, snapshot) in snapshots.iter().enumerate().skip(1) {
+            assert_eq!(snapshot.1.delta, interval, "line {}", line);
+        }
+    }
+
+    #[test]
+    fn test_run_command_tags_streams() {
+        let out = SharedOutput::default();
+        let mut context = TimelnContext::builder()
+            .command(
+                ["sh", "-c", "echo built; echo warning >&2; echo done"]
+                    .map(String::from)
+                    .to_vec(),
+            )
+            .output(Box::new(out.clone()))
+            .time_format(Box::new(FixedFormat))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+
+        let out = out.contents();
+        assert!(out.contains("[time: t, delta: t] [out] built\n"));
+        assert!(out.contains("[time: t, delta: t] [err] warning\n"));
+        assert!(out.contains("[time: t, delta: t] [out] done\n"));
+        assert!(out.ends_with("[Stdout Lines: 2, Stderr Lines: 1]\n"));
+    }
+
+    /// Reports a rotation before its second line.
+    struct RotatingReadData {
+        lines: Vec<&'static str>,
+        notice: Option<String>,
+    }
+
+    impl ReadData for RotatingReadData {
+        fn read_line(&mut self, buf: &mut String) -> Result<usize, TimelnError> {
+            if self.lines.is_empty() {
+                return Ok(0);
+            }
+            let line = self.lines.remove(0);
+            if line == "new" {
+                self.notice = Some("[log rotated]".to_string());
+            }
+            buf.push_str(line);
+            Ok(line.len())
+        }
+
+        fn take_notice(&mut self) -> Option<String> {
+            self.notice.take()
+        }
+    }
+
+    #[test]
+    fn test_run_outputs_reader_notice() {
+        let out = SharedOutput::default();
+        let mut context = TimelnContext::builder()
+            .reader(Box::new(RotatingReadData {
+                lines: vec!["old", "new"],
+                notice: None,
+            }))
+            .output(Box::new(out.clone()))
+            .time_format(Box::new(FixedFormat))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        assert_eq!(
+            out.contents(),
+            "[time: t, delta: t] old\n[time: t, delta: t] [log rotated]\n[time: t, delta: t] new\n"
+        );
+        assert_eq!(context.counters.total_lines.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_run_file() {
+        let path = std::env::temp_dir().join(format!("timeln-run-{}", std::process::id()));
+        std::fs::write(&path, "one\ntwo\n").unwrap();
+        let mut context = TimelnContext::builder()
+            .file(Some(path.to_string_lossy().into_owned()))
+            .output(Box::new(io::sink()))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(context.counters.total_lines.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_run_connect() {
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"starting\nbuilding\ndone\n").unwrap();
+        });
+        let out = SharedOutput::default();
+        let mut context = TimelnContext::builder()
+            .connect(Some(addr))
+            .connect_timeout(Some(Duration::from_secs(5)))
+            .output(Box::new(out.clone()))
+            .time_format(Box::new(FixedFormat))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        server.join().unwrap();
+        assert_eq!(
+            out.contents(),
+            "[time: t, delta: t] starting\n[time: t, delta: t] building\n[time: t, delta: t] done\n"
+        );
+        assert_eq!(context.counters.total_lines.load(Ordering::Relaxed), 3);
+
+        // Nothing listens on the port once the listener is dropped.
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = closed.local_addr().unwrap().to_string();
+        drop(closed);
+        let err = TimelnContext::builder()
+            .connect(Some(addr.clone()))
+            .build()
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(
+            err.starts_with(&format!("could not read {}: ", addr)),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_run_exit_idle() {
+        struct StalledReadData(bool);
+
+        impl ReadData for StalledReadData {
+            fn read_line(&mut self, buf: &mut String) -> Result<usize, TimelnError> {
+                if std::mem::replace(&mut self.0, true) {
+                    loop {
+                        std::thread::park();
+                    }
+                }
+                buf.push_str("started\n");
+                Ok(8)
+            }
+        }
+
+        let out = SharedOutput::default();
+        let mut context = TimelnContext::builder()
+            .reader(Box::new(StalledReadData(false)))
+            .output(Box::new(out.clone()))
+            .exit_idle(Some(Duration::from_millis(50)))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        assert!(context.idle_timed_out());
+        context.summarize_and_plot().unwrap();
+        assert!(out.contents().contains("[Processed Lines: 1"));
+
+        let mut finished = TimelnContext::builder()
+            .reader(Box::new(IterReadData::new(["a"])))
+            .output(Box::new(io::sink()))
+            .exit_idle(Some(Duration::from_secs(60)))
+            .build()
+            .unwrap();
+        finished.run().unwrap();
+        assert!(!finished.idle_timed_out());
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let path = std::env::temp_dir().join(format!("timeln-record-run-{}", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let reader = ScheduledReadData::new(3, Duration::from_millis(20), "step\tdone\n");
+        let mut recorded = TimelnContext::builder()
+            .reader(Box::new(reader))
+            .output(Box::new(io::sink()))
+            .record(Some(path.clone()))
+            .plot_term(true)
+            .build()
+            .unwrap();
+        recorded.run().unwrap();
+
+        // Every line is on disk before the run finishes, but not the footer.
+        let read_records = || -> Vec<Record> {
+            let file = io::BufReader::new(std::fs::File::open(&path).unwrap());
+            crate::record::RecordReader::new(file)
+                .collect::<io::Result<_>>()
+                .unwrap()
+        };
+        let records = read_records();
+        assert!(matches!(records[0], Record::Header { .. }));
+        assert_eq!(records.len(), 4);
+        assert!(matches!(&records[3], Record::Line { text, .. } if text == "step\tdone"));
+
+        // A crashed run can be replayed, reproducing the recorded deltas.
+        let out = SharedOutput::default();
+        let mut replayed = TimelnContext::builder()
+            .replay(Some(path.clone()))
+            .instant_replay(true)
+            .quiet(true)
+            .output(Box::new(out.clone()))
+            .summarizer(Box::new(FixedSummarizer))
+            .plot_term(true)
+            .build()
+            .unwrap();
+        replayed.run().unwrap();
+        let (original, _) = recorded.exporters.sink.lock().unwrap().take();
+        let (replay, _) = replayed.exporters.sink.lock().unwrap().take();
+        let deltas = |snapshots: &[(usize, TimeSnapshot)]| -> Vec<Duration> {
+            snapshots
+                .iter()
+                .map(|(_, snapshot)| snapshot.delta)
+                .collect()
+        };
+        assert_eq!(deltas(&replay), deltas(&original));
+        assert!(out.contents().is_empty());
+
+        recorded.summarize_and_plot().unwrap();
+        let records = read_records();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            records[4],
+            Record::Footer { lines: 3, total } if total >= Duration::from_millis(40)
+        ));
+    }
+
+    #[test]
+    fn test_replay_csv() {
+        let path = std::env::temp_dir().join(format!("timeln-replay-{}.csv", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let replay = |data: &str, columns: Option<CsvColumns>| -> String {
+            std::fs::write(&path, data).unwrap();
+            let out = SharedOutput::default();
+            let mut context = TimelnContext::builder()
+                .replay(Some(path.clone()))
+                .csv_columns(columns)
+                .instant_replay(true)
+                .output(Box::new(out.clone()))
+                .build()
+                .unwrap();
+            context.run().unwrap();
+            out.contents()
+        };
+
+        // Read as CSV for its extension, with the default columns and a header.
+        let out = replay("elapsed,delta\n1.5,1.5\n4,2.5\n", None);
+        assert_eq!(
+            out,
+            "[time: 1.50 s, delta: 1.50 s] 1.5,1.5\n[time: 4.00 s, delta: 2.50 s] 4,2.5\n"
+        );
+        // Without a header, the deltas in the mapped column add up to the times.
+        let out = replay("build,2\ntest,0.5\n", "delta=2".parse().ok());
+        assert_eq!(
+            out,
+            "[time: 2.00 s, delta: 2.00 s] build,2\n[time: 2.50 s, delta: 0.50 s] test,0.5\n"
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_total_matches_estimates_time_left() {
+        let path = std::env::temp_dir().join(format!("timeln-progress-{}.csv", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        // Epochs 2 s apart, then a slower last one.
+        std::fs::write(&path, "elapsed,delta\n1,1\n2,1\n4,2\n6,2\n9,3\n").unwrap();
+        let out = SharedOutput::default();
+        let mut context = TimelnContext::builder()
+            .replay(Some(path.clone()))
+            .instant_replay(true)
+            .regex("^[2469],")
+            .total_matches(Some(4))
+            .output(Box::new(out.clone()))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // After the third match at 6 s, matches came every 2 s: the last was expected at 8 s.
+        let metrics = context.finalizer.metrics().unwrap();
+        assert_eq!(
+            metrics.progress,
+            Some(Progress {
+                matches: 4,
+                total: 4,
+                eta: None
+            })
+        );
+        context.summarize_and_plot().unwrap();
+        assert!(out
+            .contents()
+            .contains("[Matches: 4/4, Estimated: 8.00 s, Took: 9.00 s, Off by: +1.00 s]\n"));
+    }
+
+    #[test]
+    fn test_journal_entries() {
+        let out = SharedOutput::default();
+        let mut context = TimelnContext::builder()
+            .reader(Box::new(IterReadData::new([
+                r#"{"__REALTIME_TIMESTAMP": "1709553600000000", "MESSAGE": "Starting app..."}"#,
+                r#"{"__REALTIME_TIMESTAMP": "1709553602500000", "MESSAGE": "Started app."}"#,
+                r#"{"__REALTIME_TIMESTAMP": "1709553603000000", "MESSAGE": [111, 107]}"#,
+            ])))
+            .journal(true)
+            .output(Box::new(out.clone()))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+        let out = out.contents();
+        for line in [
+            "delta: 0.00 s] Starting app...\n",
+            "[time: 2.50 s, delta: 2.50 s] Started app.\n",
+            "[time: 3.00 s, delta: 0.50 s] ok\n",
+            "Total Time: 3.00 s",
+        ] {
+            assert!(out.contains(line), "{:?} not in {}", line, out);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_listen_counts_across_connections() {
+        let path = std::env::temp_dir().join(format!("timeln-{}-listen", std::process::id()));
+        let out = SharedOutput::default();
+        let mut context = TimelnContext::builder()
+            .listen(Some(path.display().to_string()))
+            .listen_keep(true)
+            .exit_idle(Some(Duration::from_millis(300)))
+            .output(Box::new(out.clone()))
+            .build()
+            .unwrap();
+        let writer = {
+            let path = path.clone();
+            thread::spawn(move || {
+                for lines in ["one\ntwo\n", "three\n"] {
+                    let mut stream = std::os::unix::net::UnixStream::connect(&path).unwrap();
+                    stream.write_all(lines.as_bytes()).unwrap();
+                }
+            })
+        };
+        context.run().unwrap();
+        writer.join().unwrap();
+        assert!(context.idle_timed_out());
+        assert!(path.exists());
+        context.summarize_and_plot().unwrap();
+        assert!(!path.exists());
+
+        let output = out.contents();
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[2].contains("[connection 2]"), "{}", output);
+        assert!(lines[3].ends_with("three"), "{}", output);
+        assert_eq!(context.totals().lines, 3);
+    }
+
+    #[cfg(not(all(feature = "journald", target_os = "linux")))]
+    #[test]
+    fn test_journal_needs_feature() {
+        match TimelnContext::builder().journal(true).build() {
+            Err(TimelnError::Usage(message)) => assert!(message.contains("journald")),
+            other => panic!("expected a usage error, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let parse = |lines: &[&'static str], pattern: &str, layout: Option<&str>| -> String {
+            let out = SharedOutput::default();
+            let mut context = TimelnContext::builder()
+                .reader(Box::new(IterReadData::new(lines.to_vec())))
+                .parse_time(Some(pattern.to_string()))
+                .time_layout(layout.map(str::to_string))
+                .output(Box::new(out.clone()))
+                .build()
+                .unwrap();
+            context.run().unwrap();
+            context.summarize_and_plot().unwrap();
+            out.contents()
+        };
+
+        let out = parse(
+            &[
+                "Mar  4 12:00:00 host app: start",
+                "Mar  4 12:00:02 host app: loaded",
+                "    continued",
+                "Mar  4 12:00:01 host app: late",
+                "Mar  4 12:00:05 host app: done",
+            ],
+            r"^\w{3} +\d+ [\d:]+",
+            Some("%b %e %H:%M:%S"),
+        );
+        for line in [
+            "[time: 0.00 s, delta: 0.00 s] Mar  4 12:00:00 host app: start\n",
+            "[time: 2.00 s, delta: 2.00 s] Mar  4 12:00:02 host app: loaded\n",
+            "[time: 2.00 s, delta: 0.00 s]     continued\n",
+            "[time: 2.00 s, delta: 0.00 s] Mar  4 12:00:01 host app: late\n",
+            "[time: 5.00 s, delta: 3.00 s] Mar  4 12:00:05 host app: done\n",
+            "Total Time: 5.00 s",
+            "[Untimed Lines: 1, Out-of-order Timestamps: 1]",
+        ] {
+            assert!(out.contains(line), "{:?} not in {}", line, out);
+        }
+
+        let out = parse(
+            &[
+                "2024-03-04T23:59:59.500 INFO start",
+                "2024-03-05T00:00:01.250 INFO done",
+            ],
+            r"^\S+",
+            None,
+        );
+        assert!(
+            out.contains("[time: 1.75 s, delta: 1.75 s] 2024-03-05T00:00:01.250 INFO done\n"),
+            "{}",
+            out
+        );
+        assert!(out.contains("[Untimed Lines: 0, Out-of-order Timestamps: 0]"));
+    }
+
+    #[test]
+    fn test_json_input() {
+        let (reading, out) = builder(&[]);
+        let mut context = reading
+            .regex("^done$")
+            .reader(Box::new(IterReadData::new([
+                r#"{"ts": 1709553600000, "msg": "start"}"#,
+                r#"{"ts": 1709553600750, "msg": "done", "level": "info"}"#,
+                r#"{"ts": 1709553601, "msg": "cut off"#,
+                r#"{"ts": 1709553602500, "msg": "done"}"#,
+            ])))
+            .json_input(true)
+            .line_field(Some("msg".to_string()))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+        // The regex patterns match the displayed field, not the JSON object.
+        assert_eq!(context.total_matches(), 2);
+        let out = out.contents();
+        for line in [
+            "[time: 0.75 s, delta: 0.75 s] done\n",
+            "[time: 2.50 s, delta: 1.75 s] done\n",
+            "[JSON Parse Failures: 1]",
+        ] {
+            assert!(out.contains(line), "{:?} not in {}", line, out);
+        }
+
+        let (reading, out) = builder(&[]);
+        let mut context = reading
+            .reader(Box::new(IterReadData::new([
+                "starting up",
+                r#"{"time": "2024-03-04T12:00:00Z", "msg": "a"}"#,
+                r#"{"time": "2024-03-04T13:00:01.5+01:00", "msg": "b"}"#,
+            ])))
+            .json_input(true)
+            .time_field(Some("time".to_string()))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+        let out = out.contents();
+        // Without a line field, the lines are displayed whole.
+        assert!(out.contains("delta: 0.00 s] starting up\n"), "{}", out);
+        assert!(
+            out.contains(
+                "[time: 1.50 s, delta: 1.50 s] {\"time\": \"2024-03-04T13:00:01.5+01:00\", \"msg\": \"b\"}\n"
+            ),
+            "{}",
+            out
+        );
+        assert!(out.contains("[JSON Parse Failures: 1]"), "{}", out);
+    }
+
+    #[test]
+    fn test_logfmt_input() {
+        let (reading, out) = builder(&[
+            r#"ts=1709553600 worker=a msg="job started" loss=0.5"#,
+            r#"ts=1709553601.5 worker=b msg=start"#,
+            r#"worker=b msg="no time""#,
+            r#"ts=1709553603 worker=a msg="job done" loss=0.25 cached"#,
+        ]);
+        let mut context = reading
+            .logfmt(true)
+            .group_by(Some("worker"))
+            .extract("loss")
+            .field("msg")
+            .field("worker")
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+
+        let out = out.contents();
+        for line in [
+            "[time: 0.00 s, delta: 0.00 s] msg=\"job started\" worker=a\n",
+            "[time: 1.50 s, delta: 1.50 s] msg=start worker=b\n",
+            "msg=\"no time\" worker=b\n",
+            "[time: 3.00 s, delta: 3.00 s] msg=\"job done\" worker=a\n",
+            "[Logfmt Untimed Lines: 1]",
+            "[loss: Min: 0.25, Max: 0.5, Mean: 0.375, Last: 0.25, Values: 2, Unparsed: 0]\n",
+            "[Groups by worker: 2]\n",
+            "a       2      3.00 s         3.00 s\n",
+        ] {
+            assert!(out.contains(line), "{:?} not in {}", line, out);
+        }
+
+        // Without --fields, the whole line is shown, normalized.
+        let (reading, out) = builder(&[r#"ts=2  msg="a b"  cached"#]);
+        let mut context = reading.logfmt(true).build().unwrap();
+        context.run().unwrap();
+        assert!(out.contents().ends_with("] ts=2 msg=\"a b\" cached\n"));
+    }
+
+    #[test]
+    fn test_time_field_needs_structured_input() {
+        let (reading, _) = builder(&[]);
+        let built = reading.time_field(Some("ts".to_string())).build();
+        assert!(matches!(built, Err(TimelnError::Usage(_))));
+    }
+
+    #[test]
+    fn test_csv_columns_without_replay_is_usage_error() {
+        let (mapping, _) = builder(&[]);
+        let built = mapping.csv_columns(Some(CsvColumns::default())).build();
+        assert!(matches!(built, Err(TimelnError::Usage(_))));
+    }
+
+    #[test]
+    fn test_replay_shows_recorded_deltas_at_any_speed() {
+        let path = std::env::temp_dir().join(format!("timeln-replay-speed-{}", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let mut writer = RecordWriter::create(&path, vec![], false).unwrap();
+        for (seconds, text) in [(0.0, "start"), (2.0, "stalled"), (2.5, "done")] {
+            let elapsed = Duration::from_secs_f64(seconds);
+            let text = text.to_string();
+            writer
+                .write(&Record::Line {
+                    elapsed,
+                    stream: None,
+                    text,
+                })
+                .unwrap();
+        }
+
+        let out = SharedOutput::default();
+        let mut context = TimelnContext::builder()
+            .replay(Some(path.clone()))
+            .speed(Some(100.0))
+            .replay_clamp(Some(Duration::from_millis(10)))
+            .output(Box::new(out.clone()))
+            .build()
+            .unwrap();
+        let started = Instant::now();
+        context.run().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        context.summarize_and_plot().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let out = out.contents();
+        assert!(
+            out.contains("[time: 2.00 s, delta: 2.00 s] stalled\n"),
+            "{}",
+            out
+        );
+        assert!(
+            out.contains("[time: 2.50 s, delta: 0.50 s] done\n"),
+            "{}",
+            out
+        );
+        assert!(out.contains("Total Time: 2.50 s"), "{}", out);
+    }
+
+    #[test]
+    fn test_tee_raw_copies_input_bytes() {
+        let input = "one\r\n  indented\t \n\nlast without newline";
+        let path = std::env::temp_dir().join(format!("timeln-tee-{}", std::process::id()));
+        let mut context = TimelnContext::builder()
+            .reader(Box::new(TestReadData::cursor(input.to_string())))
+            .output(Box::new(io::sink()))
+            .tee_raw(Some(path.to_string_lossy().into_owned()))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+        let teed = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(teed, input.as_bytes());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_tee_raw_write_error_stops_teeing() {
+        let line = "x".repeat(10_000);
+        let (builder, out) = builder(&[&line, "next"]);
+        let mut context = builder
+            .tee_raw(Some("/dev/full".to_string()))
+            .summarizer(Box::new(FixedSummarizer))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        assert!(context.exporters.tee.lock().unwrap().is_none());
+        context.summarize_and_plot().unwrap();
+        assert!(out.contents().ends_with("next\n2 lines\n"));
+    }
+
+    #[test]
+    fn test_prometheus_metrics_export() {
+        let dir = std::env::temp_dir().join(format!("timeln-prom-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("timeln.prom");
+        let (exporting, _) = builder(&["ERROR one", "ok", "WARN two", "ERROR three"]);
+        let mut context = exporting
+            .regex("ERROR")
+            .label("err")
+            .regex("WARN")
+            .label("warn")
+            .prom_textfile(Some(path.display().to_string()))
+            .prom_listen(Some("127.0.0.1:0".to_string()))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+
+        let response = crate::metrics::tests::get(context.metrics_addr().unwrap(), "/metrics");
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        let scraped = crate::metrics::tests::parse_exposition(body);
+        assert_eq!(scraped["timeln_lines_total"], 4.0);
+        assert_eq!(scraped["timeln_matches_total{pattern=\"err\"}"], 2.0);
+        assert_eq!(scraped["timeln_matches_total{pattern=\"warn\"}"], 1.0);
+        assert_eq!(scraped["timeln_bytes_total"], 34.0);
+        assert!(scraped["timeln_last_delta_seconds"] >= 0.0);
+
+        context.summarize_and_plot().unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let written = crate::metrics::tests::parse_exposition(&written);
+        assert_eq!(written["timeln_lines_total"], 4.0);
+        assert!(written["timeln_elapsed_seconds"] > 0.0);
+    }
+
+    #[test]
+    fn test_serve_live_stats_during_run() {
+        let (serving, _) = builder(&["ERROR one", "ok", "ERROR two"]);
+        let mut context = serving
+            .regex("ERROR")
+            .serve(Some("127.0.0.1:0".to_string()))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+
+        let addr = context.live_addr().unwrap();
+        let response = crate::metrics::tests::get(addr, "/stats");
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{}", head);
+        let stats: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(stats["lines"], 3);
+        assert_eq!(stats["matches"], 2);
+        assert_eq!(stats["bytes"], 23);
+        assert_eq!(stats["recent_deltas_ns"].as_array().unwrap().len(), 2);
+        assert!(stats["last_delta_ns"].is_u64());
+        assert!(
+            stats["summary"]
+                .as_str()
+                .unwrap()
+                .contains("Processed Lines: 3"),
+            "{}",
+            stats["summary"]
+        );
+
+        context.summarize_and_plot().unwrap();
+        let stopped = (0..100).any(|_| {
+            thread::sleep(Duration::from_millis(10));
+            std::net::TcpStream::connect(addr).is_err()
+        });
+        assert!(stopped);
+    }
+
+    #[test]
+    fn test_statsd_timings_and_gauges() {
+        let collector = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        collector
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let (emitting, _) = builder(&["start", "done", "other"]);
+        let mut context = emitting
+            .regex("done|other")
+            .statsd(Some(collector.local_addr().unwrap().to_string()))
+            .statsd_prefix(Some("ci".to_string()))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+
+        let mut buffer = [0; statsd::MAX_DATAGRAM];
+        let len = collector.recv(&mut buffer).unwrap();
+        let datagram = String::from_utf8_lossy(&buffer[..len]);
+        let metrics: Vec<&str> = datagram.lines().collect();
+        assert_eq!(metrics.len(), 6, "{}", datagram);
+        assert!(metrics[..2]
+            .iter()
+            .all(|metric| metric.starts_with("ci.delta:") && metric.ends_with("|ms")));
+        assert_eq!(
+            metrics[2..5],
+            ["ci.lines:3|g", "ci.matches:2|g", "ci.bytes:17|g"]
+        );
+        assert!(metrics[5].starts_with("ci.elapsed:"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_events_written_to_fd() {
+        use std::io::Read;
+        use std::os::unix::io::{AsRawFd, FromRawFd};
+
+        let mut fds = [0; 2];
+        // SAFETY: pipe writes two file descriptors to the array, which are owned by the files.
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let (mut reader, writer) =
+            unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        let (emitting, _) = builder(&["begin", "step", "begin", "step"]);
+        let mut context = emitting
+            .reset_on(Some("begin"))
+            .event_fd(Some(writer.as_raw_fd()))
+            .build()
+            .unwrap();
+        drop(writer);
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+        drop(context);
+
+        let mut written = String::new();
+        reader.read_to_string(&mut written).unwrap();
+        let events: Vec<serde_json::Value> = written
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let kinds: Vec<&str> = events
+            .iter()
+            .map(|event| event["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "started",
+                "line",
+                "line",
+                // The marker starting the second lap ends the first one.
+                "line",
+                "interim-summary",
+                "line",
+                "interim-summary",
+                "finished"
+            ]
+        );
+        assert_eq!(events[2]["text"], "step");
+        // The lines arrive at once, all within the default window of 10 s.
+        assert_eq!(events[2]["rate_10s"], 0.2);
+        assert_eq!(events[5]["rate_10s"], 0.4);
+        assert_eq!(events[4]["lines"], 2);
+        assert_eq!(events[7]["lines"], 4);
+    }
+
+    #[test]
+    fn test_closed_event_fd_is_usage_error() {
+        let (emitting, _) = builder(&[]);
+        match emitting.event_fd(Some(9999)).build() {
+            Err(TimelnError::Usage(message)) => assert!(message.contains("9999"), "{}", message),
+            other => panic!("expected a usage error, got {:?}", other.err()),
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_stores_each_run() {
+        let path = std::env::temp_dir().join(format!("timeln-runs-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        for lines in [&["a", "ok b", "c"][..], &["ok d", "ok e"]] {
+            let (storing, _) = builder(lines);
+            let mut context = storing
+                .regex("ok")
+                .label("ok")
+                .passthrough(true)
+                .sqlite(Some(path.display().to_string()))
+                .build()
+                .unwrap();
+            context.run().unwrap();
+            context.summarize_and_plot().unwrap();
+        }
+
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        let query = |sql: &str| -> Vec<(i64, i64)> {
+            let mut statement = connection.prepare(sql).unwrap();
+            let rows = statement
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .unwrap();
+            rows.map(Result::unwrap).collect()
+        };
+        let runs = query("SELECT total_lines, total_matches FROM runs ORDER BY id");
+        let snapshots =
+            query("SELECT COUNT(*), COUNT(pattern) FROM snapshots GROUP BY run_id ORDER BY run_id");
+        drop(connection);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(runs, [(3, 1), (2, 2)]);
+        assert_eq!(snapshots, [(3, 1), (2, 2)]);
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    #[test]
+    fn test_sqlite_needs_feature() {
+        let (storing, _) = builder(&[]);
+        match storing.sqlite(Some("runs.db".to_string())).build() {
+            Err(TimelnError::Usage(message)) => assert!(message.contains("feature")),
+            other => panic!("expected a usage error, got {:?}", other.err()),
+        }
+    }
+
+    #[cfg(feature = "notify")]
+    #[test]
+    fn test_notify_on_eof_and_slow_line() {
+        let (url, bodies) = crate::notify::tests::webhook(200);
+        let (notifying, _) = builder(&["a", "b"]);
+        let mut context = notifying
+            .notify_url(Some(url))
+            .notify_label(Some("nightly".to_string()))
+            .notify_slower_than(Some(Duration::ZERO), None)
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+
+        let mut payloads: Vec<String> = (0..2)
+            .map(|_| bodies.recv_timeout(Duration::from_secs(5)).unwrap())
+            .collect();
+        payloads.sort();
+        let finished: serde_json::Value = serde_json::from_str(&payloads[0]).unwrap();
+        assert_eq!(finished["event"], "finished");
+        assert_eq!(finished["label"], "nightly");
+        assert_eq!(finished["reason"], "eof");
+        assert_eq!(finished["lines"], 2);
+        // The second slow line is within the interval of the first.
+        let threshold: serde_json::Value = serde_json::from_str(&payloads[1]).unwrap();
+        assert_eq!(threshold["event"], "threshold");
+        assert_eq!(threshold["text"], "a");
+        assert!(bodies.recv_timeout(Duration::from_millis(200)).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_on_slow_runs_command_once() {
+        let path = std::env::temp_dir().join(format!("timeln-on-slow-{}", std::process::id()));
+        let (hooked, _) = builder(&["a", "b"]);
+        let mut context = hooked
+            .on_slow(
+                Some(Duration::ZERO),
+                Some(format!("echo \"$TIMELN_LINE\" >> {}", path.display())),
+                None,
+            )
+            .build()
+            .unwrap();
+        context.run().unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !path.exists() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        // The second slow line is within the interval of the first.
+        thread::sleep(Duration::from_millis(200));
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, "a\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_on_match_runs_command_for_each_match() {
+        let path = std::env::temp_dir().join(format!("timeln-on-match-{}", std::process::id()));
+        let (hooked, _) = builder(&["epoch 1 loss 0.5", "skip", "epoch 2 loss 0.25", "epoch 3"]);
+        let mut context = hooked
+            .regex(r"epoch (\d+)(?: loss (\S+))?")
+            .on_match(
+                Some(format!(
+                    "echo \"$TIMELN_GROUP_1 $TIMELN_GROUP_2 $TIMELN_MATCH\" >> {}",
+                    path.display()
+                )),
+                Some(2),
+            )
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        assert_eq!(context.total_matches(), 3);
+
+        // The commands run in the background, in any order.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut written = String::new();
+        while written.lines().count() < 2 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+            written = std::fs::read_to_string(&path).unwrap_or_default();
+        }
+        // The third match is over the limit.
+        thread::sleep(Duration::from_millis(200));
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut lines: Vec<&str> = written.lines().collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            ["1 0.5 epoch 1 loss 0.5", "2 0.25 epoch 2 loss 0.25"]
+        );
+    }
+
+    #[cfg(not(feature = "notify"))]
+    #[test]
+    fn test_notify_needs_feature() {
+        let (notifying, _) = builder(&[]);
+        match notifying
+            .notify_url(Some("http://localhost/".to_string()))
+            .build()
+        {
+            Err(TimelnError::Usage(message)) => assert!(message.contains("feature")),
+            other => panic!("expected a usage error, got {:?}", other.err()),
+        }
+    }
+
+    #[cfg(not(feature = "desktop"))]
+    #[test]
+    fn test_desktop_notify_needs_feature() {
+        let (notifying, _) = builder(&[]);
+        match notifying.notify(true).build() {
+            Err(TimelnError::Usage(message)) => assert!(message.contains("feature")),
+            other => panic!("expected a usage error, got {:?}", other.err()),
+        }
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parquet_has_every_snapshot() {
+        use arrow_array::cast::AsArray;
+        use arrow_array::types::UInt64Type;
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let path = std::env::temp_dir().join(format!("timeln-run-{}.parquet", std::process::id()));
+        let (writing, _) = builder(&["a", "ok b", "c", "ok d", "e"]);
+        let mut context = writing
+            .regex("ok")
+            .passthrough(true)
+            .parquet(Some(path.display().to_string()))
+            .parquet_batch(Some(2))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<_> = reader.map(Result::unwrap).collect();
+        std::fs::remove_file(&path).unwrap();
+        let line_numbers: Vec<u64> = batches
+            .iter()
+            .flat_map(|batch| {
+                batch
+                    .column(0)
+                    .as_primitive::<UInt64Type>()
+                    .values()
+                    .to_vec()
+            })
+            .collect();
+        assert_eq!(line_numbers, [1, 2, 3, 4, 5]);
+        let matched: usize = batches
+            .iter()
+            .map(|batch| batch.column(3).as_boolean().true_count())
+            .sum();
+        assert_eq!(matched, 2);
+    }
+
+    #[cfg(not(feature = "parquet"))]
+    #[test]
+    fn test_parquet_needs_feature() {
+        let (writing, _) = builder(&[]);
+        match writing.parquet(Some("run.parquet".to_string())).build() {
+            Err(TimelnError::Usage(message)) => assert!(message.contains("feature")),
+            other => panic!("expected a usage error, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn test_failed_plot_keeps_summary_and_other_plot() {
+        let dir = std::env::temp_dir().join(format!("timeln-plot-dir-{}", std::process::id()));
+        // A directory where the delta plot should be makes it unwritable.
+        std::fs::create_dir_all(dir.join("deltas.svg")).unwrap();
+        let (plotting, out) = builder(&["a", "b"]);
+        let mut context = plotting
+            .summarizer(Box::new(FixedSummarizer))
+            .plot(true)
+            .plot_dir(&dir)
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        let result = context.summarize_and_plot();
+        let times_written = dir.join("times.svg").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+        match result {
+            Err(TimelnError::PlotRender { path, .. }) => assert!(path.ends_with("deltas.svg")),
+            other => panic!("expected a plot error, got {:?}", other),
+        }
+        assert!(times_written);
+        assert!(out.contents().ends_with("2 lines\n"));
+    }
+
+    #[test]
+    fn test_unwritable_report_names_path_and_writes_other_outputs() {
+        let dir = std::env::temp_dir().join(format!("timeln-missing-{}", std::process::id()));
+        let report = dir.join("report.html").display().to_string();
+        let plot_data = std::env::temp_dir()
+            .join(format!("timeln-plot-data-{}.csv", std::process::id()))
+            .display()
+            .to_string();
+        let (builder, _) = builder(&["a", "b"]);
+        let mut context = builder
+            .report(Some(report.clone()))
+            .plot_data(Some(plot_data.clone()))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        let result = context.summarize_and_plot();
+        let written = std::fs::remove_file(&plot_data).is_ok();
+        match result {
+            Err(TimelnError::Report { path, .. }) => assert_eq!(path, report),
+            other => panic!("expected a report error, got {:?}", other),
+        }
+        assert!(written);
+    }
+
+    #[test]
+    fn test_run_degrades_over_max_memory() {
+        let lines: Vec<String> = (0..1000)
+            .map(|i| format!("{} {}", i, "x".repeat(200)))
+            .collect();
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        let (builder, _) = builder(&lines);
+        let mut context = builder
+            .report(Some("unused.html".to_string()))
+            .max_memory(Some(1000))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        assert_eq!(context.trackers.slowest.lock().unwrap().text_bytes(), 0);
+        let sink = context.exporters.sink.lock().unwrap();
+        assert!(sink.len() * crate::sink::SNAPSHOT_BYTES <= 1000);
+        assert!(!sink.is_empty());
+        assert_eq!(context.counters.stats.lock().unwrap().count(), 1000);
+    }
+
+    /// Pausing or resuming timing at a time in milliseconds.
+    enum Toggle {
+        Pause(u64),
+        Resume(u64),
+    }
+
+    /// Returns lines with scripted arrival times, pausing or resuming timing before some of them.
+    struct PausingReadData {
+        start: Instant,
+        pause: Arc<PauseClock>,
+        /// Arrival time in milliseconds, line, and pause or resume before it.
+        lines: Vec<(u64, &'static str, Option<Toggle>)>,
+        arrived: Option<Instant>,
+    }
+
+    impl ReadData for PausingReadData {
+        fn read_line(&mut self, buf: &mut String) -> Result<usize, TimelnError> {
+            if self.lines.is_empty() {
+                return Ok(0);
+            }
+            let (arrived, line, action) = self.lines.remove(0);
+            let at = |millis| self.start + Duration::from_millis(millis);
+            match action {
+                Some(Toggle::Pause(millis)) => self.pause.pause(at(millis)),
+                Some(Toggle::Resume(millis)) => self.pause.resume(at(millis)),
+                None => {}
+            }
+            self.arrived = Some(at(arrived));
+            buf.push_str(line);
+            Ok(line.len())
+        }
+
+        fn arrived(&self) -> Option<Instant> {
+            self.arrived
+        }
+    }
+
+    #[test]
+    fn test_run_leaves_out_paused_time() {
+        let out = SharedOutput::default();
+        let mut context = TimelnContext::builder()
+            .output(Box::new(out.clone()))
+            .plot_term(true)
+            .build()
+            .unwrap();
+        context.stdin = Box::new(PausingReadData {
+            start: context.pipeline.start_time(),
+            pause: Arc::clone(&context.counters.pause),
+            lines: vec![
+                (1000, "before", None),
+                (2000, "while attached", Some(Toggle::Pause(1500))),
+                (5000, "after", Some(Toggle::Resume(4000))),
+            ],
+            arrived: None,
+        });
+        context.run().unwrap();
+
+        let (snapshots, _) = context.exporters.sink.lock().unwrap().take();
+        let snapshots: Vec<TimeSnapshot> = snapshots.into_iter().map(|(_, s)| s).collect();
+        assert_eq!(snapshots.len(), 2);
+        // The 2.5s pause is left out of both the delta and the elapsed time.
+        assert_eq!(snapshots[1].delta, Duration::from_millis(1500));
+        assert_eq!(snapshots[1].elapsed, Duration::from_millis(2500));
+        assert_eq!(context.counters.stats.lock().unwrap().count(), 2);
+        assert!(out.contents().contains("\n[paused] while attached\n"));
+        assert_eq!(
+            context
+                .counters
+                .pause
+                .paused_at(context.pipeline.start_time() + Duration::from_secs(9)),
+            Duration::from_millis(2500)
+        );
+
+        // The summary reports the paused time.
+        context
+            .counters
+            .pause
+            .pause(Instant::now() - Duration::from_secs(3));
+        context.summarize_and_plot().unwrap();
+        assert!(
+            out.contents().contains("[Paused: 5.50 s]"),
+            "{}",
+            out.contents()
+        );
+    }
+
+    /// Reads the lines of a `PausingReadData` as if each was read from the file at the same index
+    /// in `files`.
+    struct FilesPausingReadData {
+        lines: PausingReadData,
+        files: Vec<usize>,
+        file: Option<usize>,
+    }
+
+    impl ReadData for FilesPausingReadData {
+        fn read_line(&mut self, buf: &mut String) -> Result<usize, TimelnError> {
+            self.file = (!self.files.is_empty()).then(|| self.files.remove(0));
+            self.lines.read_line(buf)
+        }
+
+        fn arrived(&self) -> Option<Instant> {
+            self.lines.arrived()
+        }
+
+        fn file(&self) -> Option<usize> {
+            self.file
+        }
+    }
+
+    /// Runs a context reading two files, resetting the times per file if `reset`, and returns its
+    /// output and the elapsed times and deltas of the lines, in milliseconds.
+    fn run_files(reset: bool) -> (String, Vec<(u128, u128)>) {
+        let out = SharedOutput::default();
+        let mut context = TimelnContext::builder()
+            .output(Box::new(out.clone()))
+            .files(vec!["build1.log".to_string(), "build2.log".to_string()])
+            .reset_per_file(reset)
+            // Replaced below, once the start of the run is known.
+            .reader(Box::new(IterReadData::new(Vec::<String>::new())))
+            .regex("step")
+            .passthrough(true)
+            .plot_term(true)
+            .build()
+            .unwrap();
+        context.stdin = Box::new(FilesPausingReadData {
+            lines: PausingReadData {
+                start: context.pipeline.start_time(),
+                pause: Arc::clone(&context.counters.pause),
+                lines: vec![
+                    (0, "setup", None),
+                    (500, "step 1", None),
+                    (2000, "setup", None),
+                    (2300, "step 1", None),
+                    (2400, "step 2", None),
+                ],
+                arrived: None,
+            },
+            files: vec![0, 0, 1, 1, 1],
+            file: None,
+        });
+        context.run().unwrap();
+
+        let (snapshots, _) = context.exporters.sink.lock().unwrap().take();
+        let times = snapshots
+            .iter()
+            .map(|(_, snapshot)| (snapshot.elapsed.as_millis(), snapshot.delta.as_millis()))
+            .collect();
+        context.summarize_and_plot().unwrap();
+        (out.contents(), times)
+    }
+
+    #[test]
+    fn test_run_files_have_sections() {
+        let (out, times) = run_files(false);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "==> build1.log <==");
+        assert!(lines[1].ends_with("setup") && lines[2].ends_with("step 1"));
+        assert_eq!(lines[3..5], ["", "==> build2.log <=="]);
+        assert!(lines[5].ends_with("setup"), "{}", out);
+
+        // The elapsed times run on across the files.
+        assert_eq!(
+            times,
+            [(0, 0), (500, 500), (2000, 1500), (2300, 300), (2400, 100)]
+        );
+
+        let files: Vec<&str> = lines
+            .iter()
+            .copied()
+            .filter(|line| line.starts_with("[File"))
+            .collect();
+        assert_eq!(files.len(), 2, "{}", out);
+        assert_eq!(
+            files[0],
+            "[File build1.log: Lines: 2, Matches: 1, Span: 2.00 s]"
+        );
+        assert!(files[1].starts_with("[File build2.log: Lines: 3, Matches: 2, Span: "));
+    }
+
+    #[test]
+    fn test_run_reset_per_file() {
+        let (out, times) = run_files(true);
+        assert!(out.contains("==> build2.log <=="), "{}", out);
+        assert_eq!(times, [(0, 0), (500, 500), (0, 0), (300, 300), (400, 100)]);
+    }
+
+    #[test]
+    fn test_run_reset_on_counts_laps() {
+        let out = SharedOutput::default();
+        let mut context = TimelnContext::builder()
+            .output(Box::new(out.clone()))
+            .reset_on(Some(r"^Epoch \d+ start"))
+            .lap_summary(true)
+            .regex("step")
+            .passthrough(true)
+            .plot_term(true)
+            .build()
+            .unwrap();
+        let lines = [
+            (0, "setup"),
+            (1000, "Epoch 1 start"),
+            (1500, "step"),
+            (3000, "Epoch 2 start"),
+            (3500, "step"),
+            (4000, "step"),
+            (7000, "Epoch 3 start"),
+            (7200, "step"),
+        ];
+        context.stdin = Box::new(PausingReadData {
+            start: context.pipeline.start_time(),
+            pause: Arc::clone(&context.counters.pause),
+            lines: lines
+                .into_iter()
+                .map(|(at, line)| (at, line, None))
+                .collect(),
+            arrived: None,
+        });
+        context.run().unwrap();
+
+        let (snapshots, _) = context.exporters.sink.lock().unwrap().take();
+        let elapsed: Vec<u128> = snapshots
+            .iter()
+            .map(|(_, snapshot)| snapshot.elapsed.as_millis())
+            .collect();
+        assert_eq!(elapsed, vec![0, 0, 500, 0, 500, 1000, 0, 200]);
+        assert_eq!(snapshots[3].1.delta, Duration::from_millis(1500));
+
+        context.summarize_and_plot().unwrap();
+        let out = out.contents();
+        let lap_lines: Vec<&str> = out.lines().filter(|l| l.starts_with("[Lap")).collect();
+        assert_eq!(lap_lines.len(), 4, "{}", out);
+        assert_eq!(lap_lines[0], "[Lap 1: Lines: 2, Matches: 1, Time: 2.00 s]");
+        assert_eq!(lap_lines[1], "[Lap 2: Lines: 3, Matches: 2, Time: 4.00 s]");
+        assert!(lap_lines[2].starts_with("[Lap 3: Lines: 2, Matches: 1, Time: "));
+        assert!(lap_lines[3].starts_with("[Laps: 3, Mean Lap Time: "));
+        assert!(out.contains("[Lap 1: Lines: 2, Matches: 1, Time: 2.00 s]\n[time: 0.00 s, delta: 1.50 s] Epoch 2 start\n"));
+    }
+
+    #[test]
+    fn test_run_between_times_spans() {
+        let out = SharedOutput::default();
+        let mut context = TimelnContext::builder()
+            .output(Box::new(out.clone()))
+            .between("^Compiling", "^Finished")
+            .build()
+            .unwrap();
+        let lines = [
+            (0, "Compiling a"),
+            (500, "Compiling b"),
+            (1500, "Finished"),
+            (2000, "Finished"),
+            (2500, "Finished"),
+            (3000, "Compiling c"),
+        ];
+        context.stdin = Box::new(PausingReadData {
+            start: context.pipeline.start_time(),
+            pause: Arc::clone(&context.counters.pause),
+            lines: lines
+                .into_iter()
+                .map(|(at, line)| (at, line, None))
+                .collect(),
+            arrived: None,
+        });
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+
+        let out = out.contents();
+        let span_lines: Vec<&str> = out.lines().filter(|l| l.starts_with("[span")).collect();
+        assert_eq!(
+            span_lines,
+            vec!["[span: 1.00 s] Compiling b", "[span: 2.00 s] Compiling a"],
+            "{}",
+            out
+        );
+        assert!(out.contains("Finished\n[span: 1.00 s] Compiling b\n"));
+        assert!(out.contains("[Spans: 2, Mean: 1.50 s, Max: 2.00 s, Incomplete: 1]\n"));
+        assert!(out.contains("[incomplete span, started at 3.00 s] Compiling c\n"));
+    }
+
+    #[test]
+    fn test_run_extract_summarizes_values() {
+        let (builder, out) = builder(&[
+            "epoch 1 loss=0.5 acc=0.6",
+            "epoch 2 loss=0.25",
+            "epoch 3 loss=n/a",
+            "took 12ms",
+            "epoch 4 loss=0.125 acc=0.9",
+        ]);
+        let mut context = builder
+            .extract(r"loss=(?P<loss>\S+)(?: acc=(?P<acc>\S+))?")
+            .extract(r"took (\d+)ms")
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+
+        let out = out.contents();
+        assert!(
+            out.contains(
+                "[loss: Min: 0.125, Max: 0.5, Mean: 0.291667, Last: 0.125, Values: 3, Unparsed: 1]\n"
+            ),
+            "{}",
+            out
+        );
+        assert!(out.contains(
+            "[acc: Min: 0.6, Max: 0.9, Mean: 0.75, Last: 0.9, Values: 2, Unparsed: 0]\n"
+        ));
+        assert!(out.contains(
+            "[took (\\d+)ms: Min: 12, Max: 12, Mean: 12, Last: 12, Values: 1, Unparsed: 0]\n"
+        ));
+    }
+
+    #[test]
+    fn test_run_group_by_times_groups_independently() {
+        let out = SharedOutput::default();
+        let mut context = TimelnContext::builder()
+            .output(Box::new(out.clone()))
+            .regex(r"worker-(?P<id>\d+)")
+            .group_by(Some("id"))
+            .build()
+            .unwrap();
+        let lines = [
+            (0, "worker-1 start"),
+            (200, "worker-2 start"),
+            (500, "worker-1 step"),
+            (700, "setup"),
+            (1200, "worker-2 step"),
+            (1500, "worker-1 done"),
+        ];
+        context.stdin = Box::new(PausingReadData {
+            start: context.pipeline.start_time(),
+            pause: Arc::clone(&context.counters.pause),
+            lines: lines
+                .into_iter()
+                .map(|(at, line)| (at, line, None))
+                .collect(),
+            arrived: None,
+        });
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+
+        let out = out.contents();
+        assert!(
+            out.contains(
+                "[Groups by id: 2]\n\
+                 id  Count  Mean Interval  Max Interval\n\
+                 1   3      0.75 s         1.00 s\n\
+                 2   2      1.00 s         1.00 s\n"
+            ),
+            "{}",
+            out
+        );
+    }
+
+    #[test]
+    fn test_group_by_needs_the_capture_group() {
+        let result = TimelnContext::builder()
+            .output(Box::new(std::io::sink()))
+            .regex(r"worker-(\d+)")
+            .group_by(Some("id"))
+            .build();
+        let err = result.err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "no regex pattern has a capture group named 'id'"
+        );
+    }
+
+    #[test]
+    fn test_run_prefixes_labels() {
+        let (builder, out) = builder(&["ERROR io", "INFO ok", "WARN disk", "ERROR net"]);
+        let mut context = builder
+            .time_format(Box::new(FixedFormat))
+            .regex("ERROR")
+            .label("err")
+            .regex("WARN")
+            .label("warn")
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+        let red = |text: &str| format!("{}", text.red());
+        assert_eq!(
+            out.contents(),
+            format!(
+                "[time: t, delta: t] [err] {} io\n\
+                 [time: t, delta: t] [warn] {} disk\n\
+                 [time: t, delta: t] [err] {} net\n\
+                 [Processed Lines: 4, Matches: 3, Total Time: t]\n\
+                 [Pattern Matches: err: 2, warn: 1]\n",
+                red("ERROR"),
+                red("WARN"),
+                red("ERROR")
+            )
+        );
+    }
+
+    #[test]
+    fn test_run_unlabeled_patterns_fall_back_to_indices() {
+        let (multiple, out) = builder(&["ERROR io", "WARN disk"]);
+        let mut context = multiple.regex("ERROR").regex("WARN").build().unwrap();
+        assert_eq!(
+            context.finalizer.plot_config.pattern_labels,
+            vec!["p0", "p1"]
+        );
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+        let out = out.contents();
+        assert!(out.contains("] [p0] "), "{}", out);
+        assert!(out.contains("] [p1] "), "{}", out);
+        assert!(out.ends_with("[Pattern Matches: p0: 1, p1: 1]\n"));
+
+        // A single unlabeled pattern keeps the plain output.
+        let (single, out) = builder(&["ERROR io"]);
+        let mut context = single.regex("ERROR").build().unwrap();
+        context.run().unwrap();
+        assert!(!out.contents().contains("[p0]"));
+    }
+
+    #[test]
+    fn test_labels_must_pair_with_patterns() {
+        let result = TimelnContext::builder()
+            .regex("ERROR")
+            .regex("WARN")
+            .label("err")
+            .build();
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "got 1 labels for 2 regex patterns; give one --label per --regex"
+        );
+    }
+
+    #[test]
+    fn test_detect_phases() {
+        let out = SharedOutput::default();
+        let mut context = TimelnContext::builder()
+            .output(Box::new(out.clone()))
+            .detect_phases(true, Some(5), None)
+            .build()
+            .unwrap();
+        // 30 lines 10 ms apart, then 30 lines 200 ms apart.
+        let lines: Vec<(u64, &str, Option<Toggle>)> = (1..=60)
+            .map(|i| {
+                (
+                    if i <= 30 {
+                        i * 10
+                    } else {
+                        300 + (i - 30) * 200
+                    },
+                    "x",
+                    None,
+                )
+            })
+            .collect();
+        context.stdin = Box::new(PausingReadData {
+            start: context.pipeline.start_time(),
+            pause: Arc::clone(&context.counters.pause),
+            lines,
+            arrived: None,
+        });
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+        let output = out.contents();
+        let notices: Vec<&str> = output
+            .lines()
+            .filter(|line| line.starts_with("[phase"))
+            .collect();
+        assert_eq!(
+            notices,
+            ["[phase 2 from line 31, started at 0.30 s, mean delta 0.20 s]"]
+        );
+        // The notice follows the line that revealed the phase.
+        assert!(output.contains("] x\n[phase 2"), "{}", output);
+        let phases: Vec<&str> = output
+            .lines()
+            .filter(|line| line.starts_with("[Phase"))
+            .collect();
+        assert_eq!(phases.len(), 2, "{}", output);
+        assert!(phases[0].starts_with("[Phase 1: From Line: 1, Lines: 30, Start: 0.00 s, Duration: 0.30 s, Mean Delta: 0.01 s]"));
+        assert!(
+            phases[1].starts_with("[Phase 2: From Line: 31, Lines: 30, Start: 0.30 s, Duration: ")
+        );
+        assert!(phases[1].ends_with("Mean Delta: 0.20 s]"), "{}", phases[1]);
+    }
+
+    /// Runs a context that collapses lines faster than 100ms over `lines`, arriving at the given
+    /// milliseconds, and returns its output and snapshots.
+    fn run_collapsing(lines: &[(u64, &'static str)]) -> (String, usize) {
+        let out = SharedOutput::default();
+        let mut context = TimelnContext::builder()
+            .output(Box::new(out.clone()))
+            .collapse_faster_than(Some(Duration::from_millis(100)))
+            .plot_term(true)
+            .build()
+            .unwrap();
+        context.stdin = Box::new(PausingReadData {
+            start: context.pipeline.start_time(),
+            pause: Arc::clone(&context.counters.pause),
+            lines: lines.iter().map(|(at, line)| (*at, *line, None)).collect(),
+            arrived: None,
+        });
+        context.run().unwrap();
+        let snapshots = context.exporters.sink.lock().unwrap().take().0.len();
+        context
+            .finalizer
+            .finalize(&mut *context.out.lock().unwrap())
+            .unwrap();
+        let out = out.contents();
+        let end = out.find("[Processed").unwrap();
+        (out[..end].to_string(), snapshots)
+    }
+
+    #[test]
+    fn test_run_collapses_alternating_fast_and_slow_lines() {
+        let (out, snapshots) = run_collapsing(&[
+            (0, "a"),
+            (10, "b"),
+            (20, "c"),
+            (1000, "slow"),
+            (1010, "d"),
+            (2000, "slower"),
+        ]);
+        assert_eq!(
+            out,
+            "[… 3 lines in 0.02 s]\n\
+             [time: 1.00 s, delta: 0.98 s] slow\n\
+             [… 1 line in 0.01 s]\n\
+             [time: 2.00 s, delta: 0.99 s] slower\n"
+        );
+        assert_eq!(snapshots, 6);
+    }
+
+    #[test]
+    fn test_run_collapsed_lines_flush_at_eof() {
+        let (out, snapshots) = run_collapsing(&[(500, "slow"), (520, "x"), (560, "y")]);
+        assert_eq!(
+            out,
+            "[time: 0.50 s, delta: 0.50 s] slow\n\
+             [… 2 lines in 0.06 s]\n"
+        );
+        assert_eq!(snapshots, 3);
+    }
+
+    #[test]
+    fn test_run_compares_self_reported_durations() {
+        let out = SharedOutput::default();
+        let mut context = TimelnContext::builder()
+            .output(Box::new(out.clone()))
+            .self_reported(Some(r"took (\S+)"))
+            .build()
+            .unwrap();
+        let lines = [
+            (600, "build took 500ms"),
+            (800, "setup"),
+            (2000, "test took 1.5s"),
+            (2500, "lint took ages"),
+        ];
+        context.stdin = Box::new(PausingReadData {
+            start: context.pipeline.start_time(),
+            pause: Arc::clone(&context.counters.pause),
+            lines: lines
+                .into_iter()
+                .map(|(at, line)| (at, line, None))
+                .collect(),
+            arrived: None,
+        });
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+
+        let out = out.contents();
+        assert!(out.starts_with(
+            "[time: 0.60 s, delta: 0.60 s] build took 500ms \
+             [reported: 0.50 s, difference: +0.10 s]\n\
+             [time: 0.80 s, delta: 0.20 s] setup\n\
+             [time: 2.00 s, delta: 1.20 s] test took 1.5s \
+             [reported: 1.50 s, difference: -0.30 s]\n\
+             [time: 2.50 s, delta: 0.50 s] lint took ages\n"
+        ));
+        assert!(
+            out.contains(
+                "[Self-Reported Lines: 2, Mean Difference: -0.10 s, Max Difference: 0.30 s, \
+                 Malformed: 1]\n"
+            ),
+            "{}",
+            out
+        );
+    }
+
+    #[test]
+    fn test_run_joins_traceback_into_one_line() {
+        let (builder, out) = builder(&[
+            "starting",
+            "Traceback (most recent call last):",
+            "  File \"app.py\", line 9, in <module>",
+            "    main()",
+            "  File \"app.py\", line 5, in main",
+            "ValueError: bad input",
+            "done",
+        ]);
+        let mut context = builder
+            .time_format(Box::new(FixedFormat))
+            .join_until(Some(r"^(starting|done|\w*Error\b)"))
+            .regex("ValueError")
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+        assert_eq!(
+            out.contents(),
+            format!(
+                "[time: t, delta: t] Traceback (most recent call last):\n\
+                 \x20 File \"app.py\", line 9, in <module>\n\
+                 \x20   main()\n\
+                 \x20 File \"app.py\", line 5, in main\n\
+                 {}: bad input\n\
+                 [Processed Lines: 7, Matches: 1, Total Time: t]\n",
+                "ValueError".red()
+            )
+        );
+    }
+
+    #[test]
+    fn test_value_plot_filename() {
+        assert_eq!(value_plot_filename("loss"), "values-loss.svg");
+        assert_eq!(
+            value_plot_filename("took (\\d+)ms"),
+            "values-took___d__ms.svg"
+        );
+    }
+
+    #[test]
+    fn test_run_highlights_match() {
+        let (builder, out) = builder(&["compile ok", "skipped", "test ok"]);
+        let mut context = builder
+            .time_format(Box::new(FixedFormat))
+            .regex("ok")
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        let ok = format!("{}", "ok".red());
+        assert_eq!(
+            out.contents(),
+            format!(
+                "[time: t, delta: t] compile {}\n[time: t, delta: t] test {}\n",
+                ok, ok
+            )
+        );
+    }
+
+    #[test]
+    fn test_builder_reader_and_output() {
+        let (builder, out) = builder(&["first", "second"]);
+        let mut context = builder.build().unwrap();
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+
+        let out = out.contents();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("[time: ") && lines[0].ends_with("] first"));
+        assert!(lines[1].ends_with("] second"));
+        assert!(lines[2].starts_with("[Processed Lines: 2"));
+    }
+
+    #[test]
+    fn test_builder_time_format() {
+        let (builder, out) = builder(&["line"]);
+        let mut context = builder
+            .time_format(Box::new(MillisecondsFormat))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+        let out = out.contents();
+        assert!(out.lines().next().unwrap().contains(" ms, delta: "));
+        assert!(out.lines().last().unwrap().contains(" ms"));
+    }
+
+    #[test]
+    fn test_annotate_rate() {
+        let (rated, out) = builder(&["a", "b"]);
+        let mut context = rated
+            .rate_window(Some(Duration::from_secs(2)), true)
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        let output = out.contents();
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[0].ends_with(", rate_2s: 0.5/s] a"), "{}", output);
+        assert!(lines[1].ends_with(", rate_2s: 1.0/s] b"), "{}", output);
+
+        let (zero, _) = builder(&[]);
+        assert!(matches!(
+            zero.rate_window(Some(Duration::ZERO), false).build(),
+            Err(TimelnError::Usage(_))
+        ));
+    }
+
+    #[test]
+    fn test_ewma_annotations_and_summary() {
+        let (smoothed, out) = builder(&["a", "b", "c"]);
+        let mut context = smoothed.ewma(true, Some(1.0)).build().unwrap();
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+        let output = out.contents();
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(
+            lines[0].starts_with("[time: ") && lines[0].contains(", ~"),
+            "{}",
+            output
+        );
+        assert!(
+            lines.last().unwrap().starts_with("[EWMA Delta: ")
+                && lines.last().unwrap().ends_with(" (alpha 1)]"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn test_as_rate_annotations_and_summary() {
+        let (rated, out) = builder(&[]);
+        let mut context = rated.as_rate(true).build().unwrap();
+        context.stdin = Box::new(PausingReadData {
+            start: context.pipeline.start_time(),
+            pause: Arc::clone(&context.counters.pause),
+            lines: vec![(500, "a", None), (750, "b", None)],
+            arrived: None,
+        });
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+        let output = out.contents();
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[0].ends_with("delta: 2.00 Hz] a"), "{}", output);
+        assert!(lines[1].ends_with("delta: 4.00 Hz] b"), "{}", output);
+        assert_eq!(
+            *lines.last().unwrap(),
+            "[Rate: Mean: 2.67 Hz, Min: 2.00 Hz, Max: 4.00 Hz]",
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn test_skip_first_delta() {
+        for (skip, expected) in [(false, "avg: 0.40 s"), (true, "avg: 0.10 s")] {
+            let (averaged, out) = builder(&[]);
+            let mut context = averaged
+                .summary(SummaryKind::Stats)
+                .time_format(Box::new(SecondsFormat))
+                .show_avg(true)
+                .skip_first_delta(skip)
+                .build()
+                .unwrap();
+            context.stdin = Box::new(PausingReadData {
+                start: context.pipeline.start_time(),
+                pause: Arc::clone(&context.counters.pause),
+                lines: vec![(1000, "a", None), (1100, "b", None), (1200, "c", None)],
+                arrived: None,
+            });
+            context.run().unwrap();
+            assert_eq!(
+                context.counters.stats.lock().unwrap().count(),
+                if skip { 2 } else { 3 }
+            );
+            let output = out.contents();
+            let lines: Vec<&str> = output.lines().collect();
+            assert!(
+                lines[2].ends_with(&format!("{}] c", expected)),
+                "{}",
+                output
+            );
+            // The annotation shows the mean of the summary.
+            let mean = context.counters.stats.lock().unwrap().mean();
+            assert_eq!(format!("avg: {:.2} s", mean), expected);
+        }
+    }
+
+    #[test]
+    fn test_gaps_in_detailed_summary() {
+        let lines = ["start", "fetching", "fetched", "building", "done"];
+        let (gaps, out) = builder(&lines);
+        let mut context = gaps
+            .summary(SummaryKind::Detailed)
+            .gaps(Some(2))
+            .build()
+            .unwrap();
+        context.stdin = Box::new(PausingReadData {
+            start: context.pipeline.start_time(),
+            pause: Arc::clone(&context.counters.pause),
+            lines: vec![
+                (10, "start", None),
+                (20, "fetching", None),
+                (920, "fetched", None),
+                (930, "building", None),
+                (1430, "done", None),
+            ],
+            arrived: None,
+        });
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+        let output = out.contents();
+        let gaps: Vec<&str> = output
+            .lines()
+            .filter(|line| line.starts_with("[Gap "))
+            .collect();
+        assert_eq!(
+            gaps,
+            [
+                "[Gap 1: Duration: 0.90 s, Ended At: 0.92 s, Line: 3, Before: \"fetching\", After: \"fetched\"]",
+                "[Gap 2: Duration: 0.50 s, Ended At: 1.43 s, Line: 5, Before: \"building\", After: \"done\"]",
+            ],
+            "{}",
+            output
+        );
+
+        let (simple, _) = builder(&lines);
+        assert!(matches!(
+            simple.gaps(Some(2)).build(),
+            Err(TimelnError::Usage(_))
+        ));
+    }
+
+    #[test]
+    fn test_watch_summary_without_terminal() {
+        let lines = ["start", "fetching", "fetched", "building", "done"];
+        let (watching, out) = builder(&lines);
+        let mut context = watching
+            .summary(SummaryKind::Detailed)
+            .gaps(Some(1))
+            .watch_summary(true)
+            .build()
+            .unwrap();
+        // The output is not the terminal: the lines are printed as usual.
+        assert!(context.finalizer.display.screen.is_none());
+        context.stdin = Box::new(PausingReadData {
+            start: context.pipeline.start_time(),
+            pause: Arc::clone(&context.counters.pause),
+            lines: vec![
+                (10, "start", None),
+                (20, "fetching", None),
+                (920, "fetched", None),
+                (930, "building", None),
+                (1430, "done", None),
+            ],
+            arrived: None,
+        });
+        context.run().unwrap();
+        assert_eq!(out.contents().lines().count(), lines.len());
+
+        // What the screen would show.
+        let stats = context.finalizer.screen_stats().unwrap();
+        let histogram: Vec<(String, String)> = stats
+            .histogram
+            .iter()
+            .map(|(threshold, fraction)| {
+                (
+                    SecondsFormat.format_duration(threshold),
+                    crate::summarizer::format_percent(*fraction),
+                )
+            })
+            .collect();
+        assert_eq!(
+            histogram,
+            [("0.01 s", "60%"), ("0.10 s", "0%"), ("1.00 s", "40%")]
+                .map(|(threshold, fraction)| (threshold.to_string(), fraction.to_string()))
+        );
+        assert_eq!(stats.slowest.len(), 1);
+        assert!(stats.slowest[0].starts_with("[Gap 1: Duration: 0.90 s"));
+    }
+
+    #[test]
+    fn test_detect_bursts() {
+        let (bursty, out) = builder(&["a", "b", "c", "d"]);
+        let mut context = bursty
+            .detect_bursts(Some(BurstThreshold {
+                lines: 2,
+                window: Duration::from_secs(1),
+            }))
+            .build()
+            .unwrap();
+        context.stdin = Box::new(PausingReadData {
+            start: context.pipeline.start_time(),
+            pause: Arc::clone(&context.counters.pause),
+            lines: vec![
+                (10, "a", None),
+                (20, "b", None),
+                (30, "c", None),
+                (2000, "d", None),
+            ],
+            arrived: None,
+        });
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+        let output = out.contents();
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[2].ends_with("] c"), "{}", output);
+        assert_eq!(
+            lines[3], "[burst of 3 lines within 1s, started at 0.01 s]",
+            "{}",
+            output
+        );
+        assert!(output.contains("[Bursts: 1 above 2 lines/1s]\n[Burst 1: Start: 0.01 s, Duration: 0.02 s, Lines: 3]"), "{}", output);
+    }
+
+    #[test]
+    fn test_activity_summary() {
+        let (active, out) = builder(&["a", "b", "c", "d"]);
+        let mut context = active
+            .regex("c")
+            .activity(true, Some(Duration::from_millis(100)))
+            .build()
+            .unwrap();
+        context.stdin = Box::new(PausingReadData {
+            start: context.pipeline.start_time(),
+            pause: Arc::clone(&context.counters.pause),
+            lines: vec![
+                (10, "a", None),
+                (20, "b", None),
+                (150, "c", None),
+                (350, "d", None),
+            ],
+            arrived: None,
+        });
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+        let output = out.contents();
+        assert!(
+            output.contains("[Activity per 100ms, Lines (peak 2): █▄ ▄"),
+            "{}",
+            output
+        );
+        assert!(output.contains(", Matches (peak 1):  █"), "{}", output);
+    }
+
+    #[test]
+    fn test_cdf_summary() {
+        let (cdf, out) = builder(&["a", "b", "c", "d"]);
+        let mut context = cdf
+            .cdf(
+                true,
+                vec![Duration::from_millis(150), Duration::from_secs(1)],
+            )
+            .build()
+            .unwrap();
+        context.stdin = Box::new(PausingReadData {
+            start: context.pipeline.start_time(),
+            pause: Arc::clone(&context.counters.pause),
+            lines: vec![
+                (100, "a", None),
+                (200, "b", None),
+                (300, "c", None),
+                (800, "d", None),
+            ],
+            arrived: None,
+        });
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+        let output = out.contents();
+        assert!(
+            output.contains("[Deltas Within: ≤150ms: 75%, ≤1s: 100%]"),
+            "{}",
+            output
+        );
+
+        let (auto, out) = builder(&["a", "b"]);
+        let mut context = auto.cdf(true, Vec::new()).build().unwrap();
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+        let output = out.contents();
+        let cdf = output
+            .lines()
+            .find(|line| line.starts_with("[Deltas Within: ≤"))
+            .unwrap_or_else(|| panic!("{}", output));
+        // The deltas of lines read at once are far below a millisecond, and so are the
+        // thresholds, which still read apart.
+        let labels: Vec<&str> = cdf
+            .split(", ")
+            .map(|point| point.rsplit_once(": ").unwrap().0)
+            .collect();
+        let mut distinct = labels.clone();
+        distinct.dedup();
+        assert_eq!(labels, distinct, "{}", cdf);
+        assert!(!cdf.contains("0.00 s"), "{}", cdf);
+    }
+
+    #[test]
+    fn test_trend_glyphs() {
+        let (trending, out) = builder(&["a", "b", "c"]);
+        let mut context = trending
+            .ewma(true, None)
+            .trend(true, Some(0.1), true)
+            .build()
+            .unwrap();
+        context.stdin = Box::new(PausingReadData {
+            start: context.pipeline.start_time(),
+            pause: Arc::clone(&context.counters.pause),
+            lines: vec![(100, "a", None), (200, "b", None), (500, "c", None)],
+            arrived: None,
+        });
+        context.run().unwrap();
+        let output = out.contents();
+        let texts: Vec<&str> = output
+            .lines()
+            .map(|line| line.split("] ").nth(1).unwrap())
+            .collect();
+        assert_eq!(texts, ["a", "= b", "+ c"], "{}", output);
+    }
+
+    #[test]
+    fn test_flag_outliers() {
+        /// Returns `line N` lines arriving the given milliseconds after the one before.
+        struct SpacedReadData {
+            at: Instant,
+            deltas: std::vec::IntoIter<u64>,
+            count: usize,
+        }
+
+        impl ReadData for SpacedReadData {
+            fn read_line(&mut self, buf: &mut String) -> Result<usize, TimelnError> {
+                let Some(delta) = self.deltas.next() else {
+                    return Ok(0);
+                };
+                self.at += Duration::from_millis(delta);
+                let line = format!("line {}\n", self.count);
+                self.count += 1;
+                buf.push_str(&line);
+                Ok(line.len())
+            }
+
+            fn arrived(&self) -> Option<Instant> {
+                Some(self.at)
+            }
+        }
+
+        let out = SharedOutput::default();
+        let mut context = TimelnContext::builder()
+            .time_format(Box::new(MillisecondsFormat))
+            .flag_outliers(Some(3.0), Some(4))
+            .output(Box::new(out.clone()))
+            .build()
+            .unwrap();
+        context.stdin = Box::new(SpacedReadData {
+            at: context.pipeline.start_time(),
+            deltas: vec![10, 10, 12, 9, 11, 10, 200, 10].into_iter(),
+            count: 0,
+        });
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+        let output = out.contents();
+        let flagged: Vec<&str> = output
+            .lines()
+            .filter(|line| line.starts_with(OUTLIER_MARKER))
+            .collect();
+        assert_eq!(flagged.len(), 1, "{}", output);
+        assert!(flagged[0].ends_with("line 6"), "{}", output);
+        assert!(output.ends_with("[Outliers: 1 above 3σ]\n"), "{}", output);
+    }
+
+    #[test]
+    fn test_ts_compatibility() {
+        let out = SharedOutput::default();
+        let mut context = TimelnContext::builder()
+            .reader(Box::new(IterReadData::new(["one", "two"])))
+            .ts(TsMode::SinceStart, None)
+            .output(Box::new(out.clone()))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        assert_eq!(out.contents(), "00:00:00 one\n00:00:00 two\n");
+
+        let invalid = TimelnContext::builder().ts(TsMode::Absolute, Some("%Q".to_string()));
+        assert!(matches!(invalid.build(), Err(TimelnError::Usage(_))));
+    }
+
+    #[test]
+    fn test_builder_annotator() {
+        let (builder, out) = builder(&["line"]);
+        let mut context = builder
+            .annotator(Box::new(UnicodeAnnotator {
+                color: false,
+                time_format: Arc::new(Box::new(SecondsFormat)),
+                widths: None,
+            }))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        assert!(out.contents().starts_with("[Τ: "));
+    }
+
+    #[test]
+    fn test_builder_summarizer() {
+        let (builder, out) = builder(&["a", "b", "c"]);
+        let mut context = builder
+            .summarizer(Box::new(FixedSummarizer))
+            .build()
+            .unwrap();
+        context.run().unwrap();
+        context.summarize_and_plot().unwrap();
+        assert_eq!(out.contents().lines().last(), Some("3 lines"));
+    }
+
+    #[test]
+    fn test_builder_regex() {
+        let (builder, out) = builder(&["skip", "match 1", "skip", "match 2"]);
+        let mut context = builder.regex("^match").build().unwrap();
+        context.run().unwrap();
+        assert_eq!(context.counters.total_lines.load(Ordering::Relaxed), 4);
+        assert_eq!(context.counters.total_matches.load(Ordering::Relaxed), 2);
+        assert_eq!(out.contents().lines().count(), 2);
+
+        assert!(matches!(
+            TimelnContext::builder().regex("(").build(),
+            Err(TimelnError::RegexCompile { .. })
+        ));
+    }
+
+    #[test]
+    fn test_builder_plot() {
+        let (builder, _) = builder(&["a", "b"]);
+        let mut context = builder.plot(true).build().unwrap();
+        context.run().unwrap();
+        assert_eq!(context.exporters.sink.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_keys_pause_display_and_print_summary() -> Result<(), TimelnError> {
+        let (builder, out) = builder(&["a", "b"]);
+        let mut context = builder
+            .annotator(Box::new(SimpleAnnotator {
+                color: false,
+                time_format: Arc::new(Box::new(FixedFormat)),
+                widths: None,
+            }))
+            .summarizer(Box::new(FixedSummarizer))
+            .keys(true)
+            .build()?;
+        apply_key(&context.finalizer, &context.out, KeyCommand::TogglePause, 0)?;
+        context.run()?;
+        // The lines are timed, but held back until the display resumes.
+        assert_eq!(out.contents(), "");
+        apply_key(&context.finalizer, &context.out, KeyCommand::Summary, 0)?;
+        assert_eq!(out.contents(), "2 lines\n");
+        apply_key(&context.finalizer, &context.out, KeyCommand::TogglePause, 0)?;
+        assert_eq!(
+            out.contents(),
+            "2 lines\n[time: t, delta: t] a\n[time: t, delta: t] b\n"
+        );
+
+        // Lines still held when the run ends are written before the summary.
+        apply_key(&context.finalizer, &context.out, KeyCommand::TogglePause, 0)?;
+        context.emit("c")?;
+        context.summarize_and_plot()?;
+        assert!(out.contents().ends_with("b\nc\n2 lines\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_finalize_runs_once() {
+        let context = TimelnContext::builder().build().unwrap();
+        context.counters.total_lines.store(3, Ordering::Relaxed);
+
+        // The signal handler and the EOF path racing to finish the run.
+        let finalizer = Arc::clone(&context.finalizer);
+        let handler = std::thread::spawn(move || {
+            let mut out = Vec::new();
+            finalizer.finalize(&mut out).unwrap();
+            out
+        });
+        let mut out = Vec::new();
+        context.finalizer.finalize(&mut out).unwrap();
+        out.extend(handler.join().unwrap());
+
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.matches("[Processed Lines: 3").count(), 1);
+
+        let mut again = Vec::new();
+        context.finalizer.finalize(&mut again).unwrap();
+        assert!(again.is_empty());
+    }
+
+    #[test]
+    fn test_finalize_within_skips_outputs_past_deadline() {
+        let context = TimelnContext::builder().plot_term(true).build().unwrap();
+        context.counters.total_lines.store(2, Ordering::Relaxed);
+        let mut out = Vec::new();
+        context
+            .finalizer
+            .finalize_within(&mut out, Duration::ZERO)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().count(), 1);
+        assert!(out.starts_with("[Processed Lines: 2"));
+
+        let context = TimelnContext::builder().plot_term(true).build().unwrap();
+        let mut out = Vec::new();
+        context
+            .finalizer
+            .finalize_within(&mut out, Duration::from_secs(60))
+            .unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("Time delta"));
+    }
+
+    #[test]
+    fn test_finalize_within_skips_exporters_past_deadline() {
+        let dir = std::env::temp_dir().join(format!("timeln-budget-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("timeln.prom");
+        let build = || {
+            TimelnContext::builder()
+                .output(Box::new(std::io::sink()))
+                .prom_textfile(Some(path.display().to_string()))
+                .build()
+                .unwrap()
+        };
+
+        let context = build();
+        let mut out = Vec::new();
+        context
+            .finalizer
+            .finalize_within(&mut out, Duration::ZERO)
+            .unwrap();
+        assert!(!path.exists());
+
+        let context = build();
+        context
+            .finalizer
+            .finalize_within(&mut out, Duration::from_secs(60))
+            .unwrap();
+        let written = path.exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(written);
+    }
+
+    #[test]
+    fn test_finalize_without_plot_writes_no_files() {
+        let context = TimelnContext::builder().plot(false).build().unwrap();
+        let duration = Duration::from_millis(10);
+        context
+            .exporters
+            .sink
+            .lock()
+            .unwrap()
+            .push(TimeSnapshot {
+                delta: duration,
+                elapsed: duration,
+                ..TimeSnapshot::default()
+            })
+            .unwrap();
+        assert!(!Path::new("deltas.svg").exists() && !Path::new("times.svg").exists());
+
+        let mut out = Vec::new();
+        context.finalizer.finalize(&mut out).unwrap();
+        assert!(!out.is_empty());
+        assert!(!Path::new("deltas.svg").exists());
+        assert!(!Path::new("times.svg").exists());
     }
 }
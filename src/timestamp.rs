@@ -0,0 +1,222 @@
+//! This module parses the timestamps written in the lines of a log, so that an already-written
+//! log can be timed by when its lines were logged rather than by when timeln read them.
+//!
+//! `TimestampParser` finds the timestamp of a line with a regex, taking its first capture group
+//! or else the whole match, and parses it with a chrono layout such as `%Y-%m-%dT%H:%M:%S%.f` or
+//! `%b %e %H:%M:%S`. A layout without a year, like that of syslog, is taken to be in 1970, and one
+//! without a date on 1 January; a layout without an offset is taken to be in UTC. Only the
+//! differences between the timestamps matter, so these defaults do not change the deltas.
+//!
+//! # Example
+//!
+//! ```
+//! use timeln::timestamp::TimestampParser;
+//!
+//! let parser = TimestampParser::new(r"^(\w{3} +\d+ [\d:]+)", "%b %e %H:%M:%S")?;
+//! let first = parser.parse("Mar  4 12:00:00 host cron[42]: started").unwrap();
+//! let second = parser.parse("Mar  4 12:00:03 host cron[42]: done").unwrap();
+//! assert_eq!(second - first, 3_000_000_000);
+//! assert_eq!(parser.parse("no timestamp here"), None);
+//! # Ok::<(), timeln::error::TimelnError>(())
+//! ```
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use chrono::format::{parse, Item, Parsed, StrftimeItems};
+use chrono::DateTime;
+use regex::Regex;
+
+use crate::error::TimelnError;
+
+/// Layout of the timestamps, unless given: ISO 8601 without an offset, such as
+/// `2024-03-04T12:00:00.250`.
+pub const DEFAULT_TIME_LAYOUT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+/// Epoch times from this value on are in milliseconds rather than seconds.
+const EPOCH_MILLIS_FROM: f64 = 1e11;
+
+/// Parses a timestamp of a structured log, in nanoseconds since the Unix epoch: seconds or
+/// milliseconds since the epoch, or RFC 3339 such as `2024-03-04T12:00:00.250Z`. Epoch times from
+/// `1e11` on are taken to be milliseconds, as that many seconds would be more than 3000 years away.
+pub fn parse_timestamp(text: &str) -> Option<i64> {
+    let text = text.trim();
+    // Whole epoch times are converted exactly, as a float cannot hold every nanosecond.
+    if let Ok(epoch) = text.parse::<i64>() {
+        return if epoch.unsigned_abs() >= EPOCH_MILLIS_FROM as u64 {
+            epoch.checked_mul(1_000_000)
+        } else {
+            epoch.checked_mul(1_000_000_000)
+        };
+    }
+    let Ok(epoch) = text.parse::<f64>() else {
+        let time = DateTime::parse_from_rfc3339(text).ok()?;
+        return time.timestamp_nanos_opt();
+    };
+    let seconds = if epoch.abs() >= EPOCH_MILLIS_FROM {
+        epoch / 1000.0
+    } else {
+        epoch
+    };
+    let nanos = (seconds * 1e9).round();
+    (nanos.is_finite() && nanos.abs() < i64::MAX as f64).then_some(nanos as i64)
+}
+
+/// Finds and parses the timestamps of lines.
+#[derive(Debug)]
+pub struct TimestampParser {
+    regex: Regex,
+    layout: String,
+}
+
+impl TimestampParser {
+    /// Creates a parser finding timestamps with the regex `pattern` and parsing them with the
+    /// chrono `layout`. Fails if either is invalid.
+    pub fn new(pattern: &str, layout: &str) -> Result<Self, TimelnError> {
+        let regex = Regex::new(pattern).map_err(|err| TimelnError::regex(pattern, err))?;
+        if StrftimeItems::new(layout).any(|item| item == Item::Error) {
+            return Err(TimelnError::Usage(format!(
+                "invalid time layout '{}', expected e.g. %Y-%m-%dT%H:%M:%S",
+                layout
+            )));
+        }
+        Ok(Self {
+            regex,
+            layout: layout.to_string(),
+        })
+    }
+
+    /// Returns the timestamp of `line`, in nanoseconds since the Unix epoch, or `None` if it has
+    /// none that the layout parses.
+    pub fn parse(&self, line: &str) -> Option<i64> {
+        let captures = self.regex.captures(line)?;
+        let capture = captures.get(1).or_else(|| captures.get(0))?;
+        let mut parsed = Parsed::new();
+        parse(
+            &mut parsed,
+            capture.as_str().trim(),
+            StrftimeItems::new(&self.layout),
+        )
+        .ok()?;
+        if parsed.timestamp().is_none() {
+            if parsed.year().is_none() && parsed.year_mod_100().is_none() {
+                parsed.set_year(1970).ok()?;
+            }
+            if parsed.month().is_none() && parsed.day().is_none() && parsed.ordinal().is_none() {
+                parsed.set_month(1).ok()?;
+                parsed.set_day(1).ok()?;
+            }
+        }
+        match parsed.offset() {
+            Some(_) => parsed.to_datetime().ok()?.timestamp_nanos_opt(),
+            None => parsed
+                .to_naive_datetime_with_offset(0)
+                .ok()?
+                .and_utc()
+                .timestamp_nanos_opt(),
+        }
+    }
+}
+
+/// Counts of the lines whose timestamp could not be used, for the summary.
+#[derive(Debug, Default)]
+pub struct TimestampCounts {
+    untimed: AtomicUsize,
+    out_of_order: AtomicUsize,
+}
+
+impl TimestampCounts {
+    /// Counts a line without a timestamp, which took the time of the line before it.
+    pub fn untimed(&self) {
+        self.untimed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a line whose timestamp was earlier than that of a line before it.
+    pub fn out_of_order(&self) {
+        self.out_of_order.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Summarizes the counts, as `[Untimed Lines: 2, Out-of-order Timestamps: 1]`.
+    pub fn summary(&self) -> String {
+        format!(
+            "[Untimed Lines: {}, Out-of-order Timestamps: {}]",
+            self.untimed.load(Ordering::Acquire),
+            self.out_of_order.load(Ordering::Acquire)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: i64 = 1_000_000_000;
+
+    #[test]
+    fn test_iso_timestamps() {
+        let parser = TimestampParser::new(r"^\S+", DEFAULT_TIME_LAYOUT).unwrap();
+        let first = parser.parse("2024-03-04T23:59:59.750 start").unwrap();
+        let second = parser.parse("2024-03-05T00:00:01 next day").unwrap();
+        assert_eq!(second - first, 1_250_000_000);
+
+        let parser = TimestampParser::new(r"^\[([^\]]+)\]", "%Y-%m-%dT%H:%M:%S%:z").unwrap();
+        let utc = parser.parse("[2024-03-04T12:00:00+00:00] a").unwrap();
+        let ahead = parser.parse("[2024-03-04T13:00:05+01:00] b").unwrap();
+        assert_eq!(ahead - utc, 5 * SECOND);
+    }
+
+    #[test]
+    fn test_syslog_timestamps() {
+        let parser = TimestampParser::new(r"^\w{3} +\d+ [\d:]+", "%b %e %H:%M:%S").unwrap();
+        let first = parser.parse("Dec 31 23:59:58 host app: a").unwrap();
+        let second = parser.parse("Dec 31 23:59:59 host app: b").unwrap();
+        assert_eq!(second - first, SECOND);
+        assert_eq!(parser.parse("Dec 32 23:59:59 host app: c"), None);
+        assert_eq!(parser.parse("    continued"), None);
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("2"), Some(2 * SECOND));
+        assert_eq!(
+            parse_timestamp("1709553600250"),
+            Some(1_709_553_600_250_000_000)
+        );
+        assert_eq!(parse_timestamp(" 1.5 "), Some(1_500_000_000));
+        assert_eq!(
+            parse_timestamp("1970-01-01T00:00:03+00:00"),
+            Some(3 * SECOND)
+        );
+        assert_eq!(parse_timestamp("soon"), None);
+    }
+
+    #[test]
+    fn test_time_only_layout() {
+        let parser = TimestampParser::new(r"at (\S+)", "%H:%M:%S%.3f").unwrap();
+        let first = parser.parse("at 10:00:00.100").unwrap();
+        let second = parser.parse("done at 10:00:02.600").unwrap();
+        assert_eq!(second - first, 2_500_000_000);
+    }
+
+    #[test]
+    fn test_invalid_layout_or_regex() {
+        assert!(matches!(
+            TimestampParser::new(r"\S+", "%Y-%Q"),
+            Err(TimelnError::Usage(_))
+        ));
+        assert!(matches!(
+            TimestampParser::new("(", DEFAULT_TIME_LAYOUT),
+            Err(TimelnError::RegexCompile { .. })
+        ));
+    }
+
+    #[test]
+    fn test_counts_summary() {
+        let counts = TimestampCounts::default();
+        counts.untimed();
+        counts.untimed();
+        counts.out_of_order();
+        assert_eq!(
+            counts.summary(),
+            "[Untimed Lines: 2, Out-of-order Timestamps: 1]"
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! This module backs `timeln watch`, which runs a command again and again, on an interval or when
+//! watched files change, and compares the total time of each run with the previous and the best
+//! runs.
+//!
+//! Changes are found by polling the modification times of the watched paths every
+//! `WATCH_POLL_INTERVAL`, so that no platform-specific file notification is needed. A watched
+//! directory changes when an entry is added, removed or renamed, but not when a file in it is
+//! written.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use timeln::formatter::SecondsFormat;
+//! use timeln::watch::WatchHistory;
+//!
+//! let mut history = WatchHistory::default();
+//! history.push(10, 0, Duration::from_millis(1500));
+//! let second = history.push(10, 0, Duration::from_millis(1200));
+//! assert_eq!(
+//!     history.row(&second, &SecondsFormat),
+//!     "[Run 2: Lines: 10, Matches: 0, Time: 1.20 s, 0.30 s faster than run 1, Best: run 2]"
+//! );
+//! ```
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::formatter::TimeFormat;
+
+/// Time between the end of a run and the start of the next, unless paths are watched or it is
+/// given.
+pub const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often the watched paths are checked for changes.
+pub const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The totals of a run of the watched command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Iteration {
+    /// Number of the run, from 1.
+    pub number: usize,
+    pub lines: u64,
+    pub matches: u64,
+    pub total_time: Duration,
+}
+
+/// The runs of the watched command so far.
+#[derive(Debug, Default)]
+pub struct WatchHistory {
+    iterations: Vec<Iteration>,
+}
+
+impl WatchHistory {
+    /// Adds a run of `lines` lines, `matches` of which matched a regex pattern, that took
+    /// `total_time`, and returns it.
+    pub fn push(&mut self, lines: u64, matches: u64, total_time: Duration) -> Iteration {
+        let iteration = Iteration {
+            number: self.iterations.len() + 1,
+            lines,
+            matches,
+            total_time,
+        };
+        self.iterations.push(iteration);
+        iteration
+    }
+
+    pub fn iterations(&self) -> &[Iteration] {
+        &self.iterations
+    }
+
+    /// The fastest run so far, the earliest of them on a tie.
+    pub fn best(&self) -> Option<&Iteration> {
+        self.iterations
+            .iter()
+            .min_by_key(|iteration| (iteration.total_time, iteration.number))
+    }
+
+    /// How `iteration` compares with the run before it, as `0.30 s faster than run 1`, or `None`
+    /// for the first run.
+    fn change(&self, iteration: &Iteration, time_format: &dyn TimeFormat) -> Option<String> {
+        let previous = self.iterations.get(iteration.number.checked_sub(2)?)?;
+        let (difference, comparison) = match iteration.total_time.cmp(&previous.total_time) {
+            std::cmp::Ordering::Less => (previous.total_time - iteration.total_time, "faster"),
+            std::cmp::Ordering::Greater => (iteration.total_time - previous.total_time, "slower"),
+            std::cmp::Ordering::Equal => return Some(format!("same as run {}", previous.number)),
+        };
+        Some(format!(
+            "{} {} than run {}",
+            time_format.format_duration(&difference),
+            comparison,
+            previous.number
+        ))
+    }
+
+    /// Summarizes `iteration` on one line, with how it compares with the run before it and the
+    /// best run so far.
+    pub fn row(&self, iteration: &Iteration, time_format: &dyn TimeFormat) -> String {
+        let mut row = format!(
+            "[Run {}: Lines: {}, Matches: {}, Time: {}",
+            iteration.number,
+            iteration.lines,
+            iteration.matches,
+            time_format.format_duration(&iteration.total_time)
+        );
+        if let Some(change) = self.change(iteration, time_format) {
+            row.push_str(", ");
+            row.push_str(&change);
+        }
+        if let Some(best) = self.best() {
+            row.push_str(&format!(", Best: run {}]", best.number));
+        }
+        row
+    }
+
+    /// Tabulates every run so far, with a row per run.
+    pub fn table(&self, time_format: &dyn TimeFormat) -> String {
+        let mut rows = vec![[
+            "Run".to_string(),
+            "Lines".to_string(),
+            "Matches".to_string(),
+            "Time".to_string(),
+            "Change".to_string(),
+        ]];
+        for iteration in &self.iterations {
+            rows.push([
+                iteration.number.to_string(),
+                iteration.lines.to_string(),
+                iteration.matches.to_string(),
+                time_format.format_duration(&iteration.total_time),
+                self.change(iteration, time_format)
+                    .unwrap_or_else(|| "-".to_string()),
+            ]);
+        }
+        let widths: Vec<usize> = (0..5)
+            .map(|column| {
+                rows.iter()
+                    .map(|row| row[column].chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let mut table = match self.best() {
+            Some(best) => format!(
+                "[Runs: {}, Best: run {} in {}]",
+                self.iterations.len(),
+                best.number,
+                time_format.format_duration(&best.total_time)
+            ),
+            None => "[Runs: 0]".to_string(),
+        };
+        for row in rows {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            table.push('\n');
+            table.push_str(cells.join("  ").trim_end());
+        }
+        table
+    }
+}
+
+/// Waits for the next run of the watched command: for an interval, or for a watched path to
+/// change.
+#[derive(Debug)]
+pub struct Watcher {
+    paths: Vec<PathBuf>,
+    interval: Duration,
+    /// Modification times of the paths when the last run started, `None` for a missing path.
+    seen: Vec<Option<SystemTime>>,
+}
+
+impl Watcher {
+    /// Waits for `interval` between runs, or if `paths` are given, for one of them to change.
+    pub fn new(paths: Vec<PathBuf>, interval: Duration) -> Self {
+        Self {
+            paths,
+            interval,
+            seen: Vec::new(),
+        }
+    }
+
+    fn modified(&self) -> Vec<Option<SystemTime>> {
+        self.paths
+            .iter()
+            .map(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+            .collect()
+    }
+
+    /// Notes the modification times of the paths as a run starts, so that a change during the
+    /// run starts the next one.
+    pub fn mark(&mut self) {
+        self.seen = self.modified();
+    }
+
+    /// Blocks until the next run is due.
+    pub fn wait(&mut self) {
+        if self.paths.is_empty() {
+            thread::sleep(self.interval);
+            return;
+        }
+        while self.modified() == self.seen {
+            thread::sleep(WATCH_POLL_INTERVAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatter::SecondsFormat;
+
+    fn history(times: &[u64]) -> WatchHistory {
+        let mut history = WatchHistory::default();
+        for millis in times {
+            history.push(4, 1, Duration::from_millis(*millis));
+        }
+        history
+    }
+
+    #[test]
+    fn test_rows_compare_with_previous_and_best() {
+        let history = history(&[1500, 1000, 1250, 1250]);
+        let rows: Vec<String> = history
+            .iterations()
+            .iter()
+            .map(|iteration| history.row(iteration, &SecondsFormat))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                "[Run 1: Lines: 4, Matches: 1, Time: 1.50 s, Best: run 2]",
+                "[Run 2: Lines: 4, Matches: 1, Time: 1.00 s, 0.50 s faster than run 1, Best: run 2]",
+                "[Run 3: Lines: 4, Matches: 1, Time: 1.25 s, 0.25 s slower than run 2, Best: run 2]",
+                "[Run 4: Lines: 4, Matches: 1, Time: 1.25 s, same as run 3, Best: run 2]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_best_prefers_earliest_tie() {
+        assert_eq!(history(&[]).best(), None);
+        assert_eq!(history(&[900, 700, 700]).best().unwrap().number, 2);
+    }
+
+    #[test]
+    fn test_table() {
+        assert_eq!(
+            history(&[]).table(&SecondsFormat),
+            "[Runs: 0]\nRun  Lines  Matches  Time  Change"
+        );
+        assert_eq!(
+            history(&[1500, 1000]).table(&SecondsFormat),
+            "[Runs: 2, Best: run 2 in 1.00 s]\n\
+             Run  Lines  Matches  Time    Change\n\
+             1    4      1        1.50 s  -\n\
+             2    4      1        1.00 s  0.50 s faster than run 1"
+        );
+    }
+
+    #[test]
+    fn test_watcher_waits_for_change() {
+        let path = std::env::temp_dir().join(format!("timeln-watch-{}", std::process::id()));
+        fs::write(&path, "a").unwrap();
+        let mut watcher = Watcher::new(vec![path.clone()], DEFAULT_WATCH_INTERVAL);
+        watcher.mark();
+        let writer = {
+            let path = path.clone();
+            thread::spawn(move || {
+                thread::sleep(WATCH_POLL_INTERVAL);
+                // Some file systems only keep whole seconds.
+                let later = SystemTime::now() + Duration::from_secs(2);
+                fs::File::options()
+                    .write(true)
+                    .open(&path)
+                    .unwrap()
+                    .set_modified(later)
+                    .unwrap();
+            })
+        };
+        watcher.wait();
+        writer.join().unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+}
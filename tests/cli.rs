@@ -0,0 +1,500 @@
+//! End-to-end tests running the `timeln` binary.
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+#[test]
+fn test_exits_cleanly_when_output_is_closed() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_timeln"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = thread::spawn(move || {
+        // timeln stops reading once its output is closed, so later writes may fail.
+        for i in 0..100_000 {
+            if writeln!(stdin, "line {}", i).is_err() {
+                break;
+            }
+        }
+    });
+
+    // Read a single line, then close the pipe like `timeln | head -1` would.
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut first = String::new();
+    stdout.read_line(&mut first).unwrap();
+    assert!(first.ends_with("line 0\n"));
+    drop(stdout);
+
+    writer.join().unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+    assert!(stderr.contains("[Processed Lines: "), "{}", stderr);
+}
+
+#[test]
+fn test_unwritable_report_exits_with_output_status() {
+    let report = std::env::temp_dir()
+        .join(format!("timeln-cli-missing-{}", std::process::id()))
+        .join("report.html");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_timeln"))
+        .arg("--report")
+        .arg(&report)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    writeln!(child.stdin.take().unwrap(), "line").unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(4), "{}", stderr);
+    assert!(stderr.contains(&report.display().to_string()), "{}", stderr);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("[Processed Lines: 1"));
+}
+
+/// Runs timeln with `args` on `input`, and returns its exit status and stderr.
+fn run_status(args: &[&str], input: &str) -> (Option<i32>, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_timeln"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // timeln may exit before reading its input.
+    let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    (output.status.code(), stderr)
+}
+
+#[test]
+fn test_status_line_only_on_terminal() {
+    let output = Command::new(env!("CARGO_BIN_EXE_timeln"))
+        .arg("--status")
+        .arg("--")
+        .args(["sh", "-c", "echo one; sleep 0.5; echo two"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(
+        stdout.contains("one\n") && stdout.contains("two\n"),
+        "{}",
+        stdout
+    );
+    // Neither the redirected output nor the redirected stderr gets the status line.
+    assert!(
+        !stdout.contains("Rate:") && !stdout.contains('\r'),
+        "{:?}",
+        stdout
+    );
+    assert!(!stderr.contains("Rate:"), "{:?}", stderr);
+}
+
+#[test]
+fn test_success_exits_with_zero() {
+    let (status, stderr) = run_status(&["-r", "done"], "start\ndone\n");
+    assert_eq!(status, Some(0), "{}", stderr);
+}
+
+#[test]
+fn test_exit_code_without_matches_exits_with_one() {
+    let (status, stderr) = run_status(&["-r", "done", "--exit-code"], "start\nfailed\n");
+    assert_eq!(status, Some(1), "{}", stderr);
+    let (status, stderr) = run_status(&["-r", "done", "--exit-code"], "start\ndone\n");
+    assert_eq!(status, Some(0), "{}", stderr);
+    let (status, stderr) = run_status(&["-r", "done"], "start\nfailed\n");
+    assert_eq!(status, Some(0), "{}", stderr);
+}
+
+#[test]
+fn test_usage_errors_exit_with_two() {
+    let (status, stderr) = run_status(&["-r", "("], "");
+    assert_eq!(status, Some(2), "{}", stderr);
+    assert!(stderr.contains("'('"), "{}", stderr);
+    let (status, stderr) = run_status(&["--no-such-option"], "");
+    assert_eq!(status, Some(2), "{}", stderr);
+    let (status, stderr) = run_status(&["--exit-code"], "");
+    assert_eq!(status, Some(2), "{}", stderr);
+}
+
+#[test]
+fn test_help_exits_with_zero() {
+    let (status, _) = run_status(&["--help"], "");
+    assert_eq!(status, Some(0));
+}
+
+#[test]
+fn test_version_describes_build() {
+    for flag in ["--version", "--bugreport"] {
+        let (status, stdout) = run_output(&[flag], &[("TIMELN_SUMMARY", "stats")], "");
+        assert_eq!(status, Some(0), "{}", stdout);
+        assert!(stdout.starts_with(&format!("timeln {}\n", env!("CARGO_PKG_VERSION"))));
+        for field in ["commit", "build date", "target"] {
+            let value = stdout
+                .lines()
+                .find_map(|line| line.strip_prefix(&format!("{}: ", field)));
+            assert!(value.is_some_and(|value| !value.is_empty()), "{}", stdout);
+        }
+        assert!(stdout.contains("\nfeatures: "), "{}", stdout);
+    }
+    let (_, stdout) = run_output(&["--bugreport"], &[("TIMELN_SUMMARY", "stats")], "");
+    assert!(stdout.contains("\nos: "), "{}", stdout);
+    assert!(stdout.contains("\nTIMELN_SUMMARY=stats\n"), "{}", stdout);
+}
+
+#[test]
+fn test_prom_textfile_has_final_counters() {
+    let dir = std::env::temp_dir().join(format!("timeln-cli-prom-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("timeln.prom");
+    let args = ["-r", "done", "--prom-textfile", path.to_str().unwrap()];
+    let (status, stderr) = run_status(&args, "start\ndone\n");
+    let metrics = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(status, Some(0), "{}", stderr);
+    assert!(metrics.contains("# TYPE timeln_lines_total counter\n"));
+    assert!(metrics.contains("\ntimeln_lines_total 2\n"), "{}", metrics);
+    assert!(
+        metrics.contains("\ntimeln_matches_total 1\n"),
+        "{}",
+        metrics
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_events_on_fd() {
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::unix::io::FromRawFd;
+    use std::os::unix::process::CommandExt;
+
+    let mut fds = [0; 2];
+    // SAFETY: pipe writes two file descriptors to the array, which are owned by the files.
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    let (mut events, writer) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    let write_fd = fds[1];
+    let mut command = Command::new(env!("CARGO_BIN_EXE_timeln"));
+    command
+        .args(["--event-fd", "3"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    // SAFETY: dup2 is async-signal-safe, and the closure allocates nothing.
+    unsafe {
+        command.pre_exec(move || match libc::dup2(write_fd, 3) {
+            -1 => Err(std::io::Error::last_os_error()),
+            _ => Ok(()),
+        });
+    }
+    let mut child = command.spawn().unwrap();
+    drop(writer);
+    writeln!(child.stdin.take().unwrap(), "one\ntwo").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("[Processed Lines: 2"));
+
+    let mut written = String::new();
+    events.read_to_string(&mut written).unwrap();
+    let events: Vec<serde_json::Value> = written
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(events.len(), 4, "{}", written);
+    assert_eq!(events[0]["event"], "started");
+    assert_eq!(events[0]["argv"][1], "--event-fd");
+    assert_eq!(events[2]["text"], "two");
+    assert_eq!(events[3]["event"], "finished");
+    assert_eq!(events[3]["lines"], 2);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_closed_event_fd_exits_with_two() {
+    let (status, stderr) = run_status(&["--event-fd", "9999"], "");
+    assert_eq!(status, Some(2), "{}", stderr);
+    assert!(stderr.contains("--event-fd 9999"), "{}", stderr);
+}
+
+#[test]
+fn test_missing_input_exits_with_three() {
+    let missing = std::env::temp_dir()
+        .join(format!("timeln-cli-missing-{}", std::process::id()))
+        .join("input.log");
+    let missing = missing.to_str().unwrap();
+    let (status, stderr) = run_status(&["--file", missing], "");
+    assert_eq!(status, Some(3), "{}", stderr);
+    assert!(stderr.contains(missing), "{}", stderr);
+}
+
+#[test]
+fn test_plot_csv_names_malformed_row() {
+    let path = std::env::temp_dir().join(format!("timeln-cli-{}.csv", std::process::id()));
+    std::fs::write(&path, "elapsed,delta\n1,1\n2,1\nsoon,1\n").unwrap();
+    let (status, stderr) = run_status(&["plot", path.to_str().unwrap(), "--plot-term"], "");
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(status, Some(3), "{}", stderr);
+    assert!(stderr.contains("line 4"), "{}", stderr);
+}
+
+#[test]
+fn test_watch_compares_runs() {
+    // timeln itself is the watched command, as it is available on every platform.
+    let output = Command::new(env!("CARGO_BIN_EXE_timeln"))
+        .args(["watch", "--runs", "2", "--interval", "10ms", "--"])
+        .args([env!("CARGO_BIN_EXE_timeln"), "-V"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "{}", stdout);
+    let rows: Vec<&str> = stdout.lines().collect();
+    assert!(
+        rows[0].starts_with("[Run 1: Lines: 1, Matches: 0,"),
+        "{}",
+        stdout
+    );
+    assert!(rows[1].starts_with("[Run 2: Lines: 1,"), "{}", stdout);
+    assert!(rows[1].contains("than run 1, Best: run "), "{}", stdout);
+    assert!(rows[2].starts_with("[Runs: 2, Best: run "), "{}", stdout);
+    assert_eq!(rows.len(), 6, "{}", stdout);
+}
+
+#[test]
+fn test_watch_needs_command() {
+    let (status, stderr) = run_status(&["watch", "--runs", "1"], "");
+    assert_eq!(status, Some(2), "{}", stderr);
+    assert!(stderr.contains("needs a command"), "{}", stderr);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_sigint_tabulates_watched_runs() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_timeln"))
+        .args(["watch", "--interval", "1h", "--"])
+        .args([env!("CARGO_BIN_EXE_timeln"), "-V"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut first = String::new();
+    stdout.read_line(&mut first).unwrap();
+    assert!(first.starts_with("[Run 1: "), "{}", first);
+
+    // SAFETY: kill has no memory safety preconditions.
+    assert_eq!(
+        unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) },
+        0
+    );
+    assert_eq!(child.wait().unwrap().code(), Some(130));
+    let mut table = String::new();
+    std::io::Read::read_to_string(&mut stdout, &mut table).unwrap();
+    assert!(table.starts_with("[Runs: 1, Best: run 1 in "), "{}", table);
+    assert!(table.contains("\n1    1      0"), "{}", table);
+}
+
+/// Starts timeln on a pipe that stays open, waits for it to print one line, then sends `signal`.
+#[cfg(unix)]
+fn stop_with_signal(signal: libc::c_int) -> std::process::Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_timeln"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = child.stdin.take().unwrap();
+    writeln!(stdin, "working").unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut first = String::new();
+    stdout.read_line(&mut first).unwrap();
+    assert!(first.ends_with("working\n"));
+
+    // SAFETY: kill has no memory safety preconditions.
+    assert_eq!(unsafe { libc::kill(child.id() as libc::pid_t, signal) }, 0);
+    let output = child.wait_with_output().unwrap();
+    drop(stdin);
+
+    let mut rest = String::new();
+    std::io::Read::read_to_string(&mut stdout, &mut rest).unwrap();
+    std::process::Output {
+        stdout: rest.into_bytes(),
+        ..output
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_sigterm_prints_summary() {
+    let output = stop_with_signal(libc::SIGTERM);
+    assert_eq!(output.status.code(), Some(130));
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("[Processed Lines: 1,"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_sigint_exits_with_130() {
+    let output = stop_with_signal(libc::SIGINT);
+    assert_eq!(output.status.code(), Some(130));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("[Processed Lines: 1"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_sighup_prints_summary() {
+    let output = stop_with_signal(libc::SIGHUP);
+    assert_eq!(output.status.code(), Some(130));
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("[Processed Lines: 1,"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_sigusr1_pauses_and_sigusr2_resumes_timing() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_timeln"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut send = |line: &str, signal: libc::c_int| {
+        writeln!(stdin, "{}", line).unwrap();
+        let mut echoed = String::new();
+        stdout.read_line(&mut echoed).unwrap();
+        // SAFETY: kill has no memory safety preconditions.
+        assert_eq!(unsafe { libc::kill(child.id() as libc::pid_t, signal) }, 0);
+        thread::sleep(std::time::Duration::from_millis(200));
+        echoed
+    };
+    assert!(send("first", libc::SIGUSR1).ends_with("] first\n"));
+    assert_eq!(send("second", libc::SIGUSR2), "[paused] second\n");
+    assert!(send("third", 0).ends_with("] third\n"));
+    drop(stdin);
+
+    let mut rest = String::new();
+    std::io::Read::read_to_string(&mut stdout, &mut rest).unwrap();
+    assert!(child.wait().unwrap().success());
+    assert!(rest.contains("[Processed Lines: 3,"), "{}", rest);
+    assert!(rest.contains("[Paused: 0."), "{}", rest);
+}
+
+/// Runs timeln with `args` and the environment variables `envs` on `input`, and returns its exit
+/// status and stdout.
+fn run_output(args: &[&str], envs: &[(&str, &str)], input: &str) -> (Option<i32>, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_timeln"))
+        .args(args)
+        .envs(envs.iter().copied())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    (output.status.code(), stdout)
+}
+
+#[test]
+fn test_short_and_repeated_options() {
+    let args = [
+        "-r", "start", "--label", "s", "-r", "done", "--label", "d", "--ts", "-i",
+    ];
+    let (status, stdout) = run_output(&args, &[], "start\nnoise\ndone\n");
+    assert_eq!(status, Some(0), "{}", stdout);
+    assert!(
+        stdout.contains("[s] start") && stdout.contains("[d] done"),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("noise"), "{}", stdout);
+
+    let args = [
+        "--between",
+        "^begin",
+        "^end",
+        "--cdf",
+        "--cdf-points",
+        "10ms,1s",
+    ];
+    let (status, stdout) = run_output(&args, &[], "begin\nend\n");
+    assert_eq!(status, Some(0), "{}", stdout);
+    assert!(stdout.contains("[Spans: 1,"), "{}", stdout);
+    assert!(stdout.contains("[Deltas Within: ≤10ms: "), "{}", stdout);
+
+    let args = [
+        "-c",
+        "--summary",
+        "detailed",
+        "--gaps",
+        "1",
+        "--",
+        "sh",
+        "-c",
+        "echo one",
+    ];
+    // Colors are only kept off a terminal when forced.
+    let (status, stdout) = run_output(&args, &[("CLICOLOR_FORCE", "1")], "");
+    assert_eq!(status, Some(0), "{}", stdout);
+    assert!(stdout.contains("\u{1b}["), "{}", stdout);
+    assert!(stdout.contains("Processed 1 lines"), "{}", stdout);
+    assert!(stdout.contains("[Gap 1: "), "{}", stdout);
+}
+
+#[test]
+fn test_invalid_values_and_conflicts_exit_with_two() {
+    for args in [
+        &["--summary", "fancy"][..],
+        &["--plot-x", "line"],
+        &["--rate-window", "soon"],
+        &["--detect-bursts", "100"],
+        &["--file", "a.log", "--replay", "a.rec"],
+        &["--journal", "--listen", "a.sock"],
+        &["--json-input", "--logfmt"],
+        &["--replay", "a.rec", "--parse-time", "^\\S+"],
+        &["--ts", "-i", "-s"],
+        &["--between", "^begin"],
+    ] {
+        let (status, stderr) = run_status(args, "");
+        assert_eq!(status, Some(2), "{:?}: {}", args, stderr);
+        assert!(stderr.contains("try '--help'"), "{:?}: {}", args, stderr);
+    }
+}
+
+#[test]
+fn test_environment_fallbacks() {
+    let (status, stdout) = run_output(&[], &[("TIMELN_SUMMARY", "detailed")], "line\n");
+    assert_eq!(status, Some(0), "{}", stdout);
+    assert!(stdout.contains("Processed 1 lines"), "{}", stdout);
+    // The command line takes precedence.
+    let (_, stdout) = run_output(
+        &["--summary", "simple"],
+        &[("TIMELN_SUMMARY", "detailed")],
+        "line\n",
+    );
+    assert!(stdout.contains("[Processed Lines: 1"), "{}", stdout);
+
+    let (_, stdout) = run_output(
+        &[],
+        &[("TIMELN_COLOR", "1"), ("CLICOLOR_FORCE", "1")],
+        "line\n",
+    );
+    assert!(stdout.contains("\u{1b}["), "{}", stdout);
+    let (_, stdout) = run_output(
+        &[],
+        &[("TIMELN_COLOR", "0"), ("CLICOLOR_FORCE", "1")],
+        "line\n",
+    );
+    assert!(!stdout.contains("\u{1b}["), "{}", stdout);
+
+    let (status, _) = run_output(&[], &[("TIMELN_SUMMARY", "fancy")], "");
+    assert_eq!(status, Some(2));
+}